@@ -0,0 +1,40 @@
+//! Download all media of a single account without a config file.
+//!
+//! ```text
+//! RXD_AUTH_TOKEN=... RXD_CT0=... cargo run --example download_user -- <screen_name>
+//! ```
+
+use std::env;
+use std::path::Path;
+use std::sync::Arc;
+
+use rxd::Task;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let screen_name = env::args()
+        .nth(1)
+        .ok_or("usage: download_user <screen_name>")?;
+    let auth_token = env::var("RXD_AUTH_TOKEN")?;
+    let ct0 = env::var("RXD_CT0")?;
+
+    let db = rxd::db::init_db(Path::new("rxd.db")).await?;
+    let task = Task::builder()
+        .screen_name(&screen_name)
+        .credentials(auth_token, ct0)
+        .db(db)
+        .build()
+        .await?;
+    println!(
+        "downloading @{} ({} media tweets)",
+        task.user().screen_name,
+        task.user().media_count
+    );
+
+    let totals = Arc::new(task).execute().await?;
+    println!(
+        "{} downloaded, {} skipped, {} failed",
+        totals.downloaded, totals.skipped, totals.failed
+    );
+    Ok(())
+}
@@ -0,0 +1,1048 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::header::{AUTHORIZATION, COOKIE, ORIGIN, REFERER, RETRY_AFTER, USER_AGENT};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Certificate, Client, ClientBuilder, RequestBuilder, StatusCode};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use tokio::sync::Semaphore;
+use tracing::{error, instrument, trace, warn};
+
+use crate::deleted::{TweetStatus, parse_tweet_result_response};
+use crate::error::{Error, Result};
+use crate::following::{AccountPage, parse_following_response};
+use crate::graphql::{Response, UserByScreenNameData, UserData, UserResult};
+use crate::list::{ListId, parse_list_members_response};
+use crate::profile::Profile;
+use crate::query_ids::{self, QueryIds};
+use crate::rotation::{AccountPool, Credentials};
+use crate::screen_name;
+use crate::syndication::{SyndicatedTweet, Syndication};
+use crate::task::{ExtractOptions, MediaPage, User, parse_user_media_response};
+
+const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/114.0.0.0 Safari/537.36";
+const DEFAULT_AUTHORIZATION: &str = "Bearer AAAAAAAAAAAAAAAAAAAAANRILgAAAAAAnNwIzUejRCOuH5E6I8xnZz4puTs%3D1Zv7ttfk8LF81IUq16cHjhLTvJu4FA33AGWWjCpTnA";
+
+/// Host of the web client, used for API requests and the Referer/Origin headers
+pub const DEFAULT_BASE_URL: &str = "https://x.com";
+
+/// Timeline entries requested per page
+const PAGE_SIZE: u32 = 100;
+/// Timeline entries requested per page by guests, larger pages are refused
+pub const GUEST_PAGE_SIZE: u32 = 20;
+
+/// Timeline entries requested per page instead of the default, 1 to 100
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "u32")]
+pub struct PageSize(u32);
+
+impl PageSize {
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl TryFrom<u32> for PageSize {
+    type Error = Error;
+
+    fn try_from(size: u32) -> Result<Self> {
+        if (1..=PAGE_SIZE).contains(&size) {
+            Ok(Self(size))
+        } else {
+            Err(Error::Config(format!(
+                "page_size must be between 1 and {PAGE_SIZE}, got {size}"
+            )))
+        }
+    }
+}
+
+/// How often a rate-limited request is retried before giving up
+pub(crate) const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+/// Upper bound on a single rate-limit wait
+pub(crate) const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(15 * 60);
+
+/// Which certificates the HTTP client trusts
+#[derive(Debug, Clone)]
+pub struct TlsOptions {
+    /// PEM file with extra root certificates, e.g. of a TLS-intercepting proxy
+    pub ca_cert: Option<PathBuf>,
+    /// Trust the operating system's certificate store
+    pub os_roots: bool,
+}
+
+impl Default for TlsOptions {
+    fn default() -> Self {
+        Self {
+            ca_cert: None,
+            os_roots: true,
+        }
+    }
+}
+
+impl TlsOptions {
+    /// Client builder trusting the configured certificates
+    ///
+    /// Fails if the CA certificate cannot be read or parsed.
+    pub fn client_builder(&self) -> Result<ClientBuilder> {
+        let certs = match &self.ca_cert {
+            Some(path) => load_certificates(path)?,
+            None => Vec::new(),
+        };
+        let builder = Client::builder();
+        if self.os_roots {
+            return Ok(builder.tls_certs_merge(certs));
+        }
+        if certs.is_empty() {
+            return Err(Error::Config(
+                "ca_cert is required when the OS certificate store is disabled".to_string(),
+            ));
+        }
+        Ok(builder.tls_certs_only(certs))
+    }
+}
+
+fn load_certificates(path: &PathBuf) -> Result<Vec<Certificate>> {
+    let pem = std::fs::read(path).map_err(|e| {
+        Error::Config(format!(
+            "failed to read CA certificate {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    let certs = Certificate::from_pem_bundle(&pem)
+        .map_err(|e| Error::Config(format!("invalid CA certificate {}: {}", path.display(), e)))?;
+    if certs.is_empty() {
+        return Err(Error::Config(format!(
+            "no certificates found in {}",
+            path.display()
+        )));
+    }
+    Ok(certs)
+}
+
+/// Authenticated client for the GraphQL API
+#[derive(Debug, Clone)]
+pub struct Api {
+    client: Client,
+    base_url: String,
+    /// Sends a guest token instead of account cookies
+    guest: bool,
+    /// Accounts GraphQL requests rotate between, the one of `client` first
+    pool: Option<Arc<AccountPool>>,
+    page_size: Option<PageSize>,
+    query_ids: QueryIds,
+    /// Feature flags the API demanded that are missing from the built-in sets,
+    /// shared by clones so they are learned once per run
+    extra_features: Arc<Mutex<Map<String, Value>>>,
+    /// Limits GraphQL requests in flight across clones, unlimited if `None`
+    api_permits: Option<Arc<Semaphore>>,
+    usage: ApiUsage,
+    /// Cookie-less fallback for single tweets
+    syndication: Syndication,
+}
+
+/// GraphQL requests sent and the rate limit left, shared by an [`Api`] and
+/// its clones
+#[derive(Debug, Clone, Default)]
+pub struct ApiUsage(Arc<UsageCounters>);
+
+#[derive(Debug, Default)]
+struct UsageCounters {
+    requests: AtomicU64,
+    rate_limit_remaining: Mutex<Option<u64>>,
+}
+
+impl ApiUsage {
+    /// GraphQL requests answered so far
+    pub fn requests(&self) -> u64 {
+        self.0.requests.load(Ordering::Relaxed)
+    }
+
+    /// `x-rate-limit-remaining` of the latest response that had it
+    pub fn rate_limit_remaining(&self) -> Option<u64> {
+        *self
+            .0
+            .rate_limit_remaining
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn record(&self, response: &reqwest::Response) {
+        self.0.requests.fetch_add(1, Ordering::Relaxed);
+        if let Some(remaining) = RateLimit::from_headers(response.headers()).remaining {
+            *self
+                .0
+                .rate_limit_remaining
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()) = Some(remaining);
+        }
+    }
+}
+
+impl Api {
+    /// Client for the default API host
+    pub fn new(auth_token: &str, ct0: &str) -> Result<Self> {
+        Self::with_base_url(auth_token, ct0, DEFAULT_BASE_URL)
+    }
+
+    /// Client for a custom API host, e.g. a mock server in tests
+    pub fn with_base_url(auth_token: &str, ct0: &str, base_url: &str) -> Result<Self> {
+        Self::with_tls(auth_token, ct0, base_url, &TlsOptions::default())
+    }
+
+    /// Client for a custom API host with custom certificate trust
+    pub fn with_tls(auth_token: &str, ct0: &str, base_url: &str, tls: &TlsOptions) -> Result<Self> {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        Ok(Self {
+            client: build_client(auth_token, ct0, &base_url, tls)?,
+            base_url,
+            guest: false,
+            pool: None,
+            page_size: None,
+            query_ids: QueryIds::default(),
+            extra_features: Arc::default(),
+            api_permits: None,
+            usage: ApiUsage::default(),
+            syndication: Syndication::new(tls)?,
+        })
+    }
+
+    /// Client without an account, for public accounts only
+    ///
+    /// Fetches a guest token from `activate.json`. Guests see no sensitive
+    /// media, get smaller pages and stricter rate limits, and are refused
+    /// with [`Error::GuestUnsupported`] where an account is needed.
+    #[instrument(skip_all)]
+    pub async fn guest(base_url: &str, tls: &TlsOptions) -> Result<Self> {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        let activation = tls
+            .client_builder()?
+            .default_headers(base_headers(&base_url)?)
+            .build()?;
+        let guest_token = activate_guest(&activation, &base_url).await?;
+        Ok(Self {
+            client: build_guest_client(&guest_token, &base_url, tls)?,
+            base_url,
+            guest: true,
+            pool: None,
+            page_size: None,
+            query_ids: QueryIds::default(),
+            extra_features: Arc::default(),
+            api_permits: None,
+            usage: ApiUsage::default(),
+            syndication: Syndication::new(tls)?,
+        })
+    }
+
+    /// Whether requests are sent as a guest
+    pub fn is_guest(&self) -> bool {
+        self.guest
+    }
+
+    /// Request pages of `size` entries, guests still get at most
+    /// [`GUEST_PAGE_SIZE`]
+    pub fn with_page_size(mut self, size: PageSize) -> Self {
+        self.page_size = Some(size);
+        self
+    }
+
+    /// Timeline entries requested per page
+    pub fn page_size(&self) -> u32 {
+        match (self.page_size, self.guest) {
+            (Some(size), true) => size.get().min(GUEST_PAGE_SIZE),
+            (Some(size), false) => size.get(),
+            (None, true) => GUEST_PAGE_SIZE,
+            (None, false) => PAGE_SIZE,
+        }
+    }
+
+    /// Error for a rejected request of `operation`, telling guests that
+    /// they need an account where that is the likely reason
+    fn rejected(&self, operation: &str, status: StatusCode, body: String) -> Error {
+        match api_error(status, body) {
+            Error::Auth(_) if self.guest => Error::GuestUnsupported(operation.to_string()),
+            Error::Api { status, .. } if self.guest && status == StatusCode::FORBIDDEN => {
+                Error::GuestUnsupported(operation.to_string())
+            }
+            e => e,
+        }
+    }
+
+    /// Rotate GraphQL requests between this client's account and
+    /// `accounts`, see [`rotation`](crate::rotation)
+    ///
+    /// Media downloads keep using this client's account.
+    pub fn with_rotation(mut self, accounts: &[Credentials], tls: &TlsOptions) -> Result<Self> {
+        let mut clients = vec![("default account".to_string(), self.client.clone())];
+        for (i, account) in accounts.iter().enumerate() {
+            let name = account
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("account {}", i + 1));
+            let client = build_client(&account.auth_token, &account.ct0, &self.base_url, tls)?;
+            clients.push((name, client));
+        }
+        self.pool = Some(Arc::new(AccountPool::new(clients)));
+        Ok(self)
+    }
+
+    /// Send at most `limit` GraphQL requests at once, across this client
+    /// and its clones
+    ///
+    /// A request waiting out a rate limit keeps its place, so the others
+    /// wait as well.
+    /// Media downloads are not limited.
+    pub fn with_api_concurrency(mut self, limit: usize) -> Self {
+        self.api_permits = Some(Arc::new(Semaphore::new(limit.max(1))));
+        self
+    }
+
+    /// Look up single tweets the GraphQL API refuses with this syndication
+    /// client, e.g. one for a mock server in tests
+    pub fn with_syndication(mut self, syndication: Syndication) -> Self {
+        self.syndication = syndication;
+        self
+    }
+
+    /// Use these query IDs instead of the compiled-in ones
+    pub fn with_query_ids(mut self, query_ids: QueryIds) -> Self {
+        self.query_ids = query_ids;
+        self
+    }
+
+    /// Query IDs used for requests
+    pub fn query_ids(&self) -> &QueryIds {
+        &self.query_ids
+    }
+
+    /// Host requests are sent to, without a trailing slash
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Requests sent by this client and its clones
+    pub fn usage(&self) -> &ApiUsage {
+        &self.usage
+    }
+
+    /// Underlying HTTP client, with auth headers set
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    fn graphql_url(&self, query_id: &str, operation: &str) -> String {
+        format!("{}/i/api/graphql/{}/{}", self.base_url, query_id, operation)
+    }
+
+    /// Send a request, waiting out rate limits until `x-rate-limit-reset`
+    ///
+    /// Responses with `Retry-After` are returned as they are, media hosts
+    /// send it and downloads honour it, see [`crate::pushback`].
+    pub(crate) async fn send(&self, request: RequestBuilder) -> Result<reqwest::Response> {
+        let mut attempt = 0;
+        loop {
+            let this_request = request
+                .try_clone()
+                .ok_or_else(|| Error::Parse("request body is not cloneable".to_string()))?;
+            let response = this_request.send().await?;
+            if response.status() != StatusCode::TOO_MANY_REQUESTS
+                || response.headers().contains_key(RETRY_AFTER)
+                || attempt >= MAX_RATE_LIMIT_RETRIES
+            {
+                return Ok(response);
+            }
+            attempt += 1;
+
+            let wait = rate_limit_wait(&response);
+            warn!(
+                "rate limited, waiting {}s before retrying ({}/{})",
+                wait.as_secs(),
+                attempt,
+                MAX_RATE_LIMIT_RETRIES
+            );
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Send a GraphQL query, returning the response if it was successful
+    ///
+    /// When the API rejects the request because feature flags are missing they
+    /// are added and the request is retried once.
+    async fn graphql_get(
+        &self,
+        query_id: &str,
+        operation: &str,
+        referer: String,
+        params: &[(&str, &Value)],
+        features: &Value,
+    ) -> Result<reqwest::Response> {
+        let mut repaired = false;
+        loop {
+            let mut features = features.clone();
+            if let Some(features) = features.as_object_mut() {
+                let extra = self
+                    .extra_features
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner());
+                features.extend(extra.iter().map(|(k, v)| (k.clone(), v.clone())));
+            }
+
+            let mut query = params
+                .iter()
+                .map(|(name, value)| Ok((*name, serde_json::to_string(value)?)))
+                .collect::<Result<Vec<_>>>()?;
+            query.push(("features", serde_json::to_string(&features)?));
+
+            let url = self.graphql_url(query_id, operation);
+            let request =
+                |client: &Client| client.get(&url).header(REFERER, &referer).query(&query);
+            let permit = match &self.api_permits {
+                Some(permits) => permits.acquire().await.ok(),
+                None => None,
+            };
+            let response = match &self.pool {
+                Some(pool) => pool.send(operation, request).await?,
+                None => self.send(request(&self.client)).await?,
+            };
+            drop(permit);
+            self.usage.record(&response);
+
+            let status = response.status();
+            trace!("{} response status: {}", operation, status);
+            if status.is_success() {
+                return Ok(response);
+            }
+
+            let body = response.text().await?;
+            let missing = parse_missing_features(&body);
+            if repaired || missing.is_empty() {
+                error!("{} API error: {}", operation, body);
+                return Err(self.rejected(operation, status, body));
+            }
+
+            let names: Vec<&str> = missing.iter().map(|(name, _)| name.as_str()).collect();
+            warn!(
+                "API requires new feature flags, retrying with: {}",
+                names.join(", ")
+            );
+            self.extra_features
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .extend(missing.into_iter().map(|(k, v)| (k, Value::Bool(v))));
+            repaired = true;
+        }
+    }
+
+    /// Look up an account by screen name, `@handle` or profile URL
+    #[instrument(skip_all, fields(user = screen_name))]
+    pub async fn user_by_screen_name(&self, screen_name: &str) -> Result<User> {
+        let screen_name = &screen_name::normalize(screen_name)?;
+        let body = self.user_by_screen_name_body(screen_name).await?;
+        self.parse_user(screen_name, &body)
+    }
+
+    /// Raw response of [`Api::user_by_screen_name`], for archiving before
+    /// it is parsed with [`Api::parse_user`]
+    pub(crate) async fn user_by_screen_name_body(&self, screen_name: &str) -> Result<String> {
+        let (variables, field_toggles, features) = user_by_screen_name_query(screen_name);
+        let response = self
+            .graphql_get(
+                &self.query_ids.user_by_screen_name,
+                query_ids::USER_BY_SCREEN_NAME,
+                format!("{}/{}", self.base_url, screen_name),
+                &[("variables", &variables), ("fieldToggles", &field_toggles)],
+                &features,
+            )
+            .await?;
+        Ok(response.text().await?)
+    }
+
+    /// Look up the public details of an account by screen name, `@handle`
+    /// or profile URL
+    #[instrument(skip_all, fields(user = screen_name))]
+    pub async fn user_profile(&self, screen_name: &str) -> Result<Profile> {
+        let screen_name = &screen_name::normalize(screen_name)?;
+        let body = self.user_by_screen_name_body(screen_name).await?;
+        let user = self.user_data(screen_name, &body)?;
+        Ok(Profile::new(screen_name, user))
+    }
+
+    /// Account of `screen_name` in a UserByScreenName response
+    pub(crate) fn parse_user(&self, screen_name: &str, body: &str) -> Result<User> {
+        let user = self.user_data(screen_name, body)?;
+        Ok(User {
+            screen_name: screen_name.to_string(),
+            name: user.legacy.name,
+            rest_id: user.rest_id,
+            media_count: user.legacy.media_count,
+        })
+    }
+
+    /// The account in a UserByScreenName response, if it is available
+    fn user_data(&self, screen_name: &str, body: &str) -> Result<UserData> {
+        let raw: Response<UserByScreenNameData> = serde_json::from_str(body)?;
+
+        let result = raw
+            .data
+            .and_then(|d| d.user)
+            .and_then(|u| u.result)
+            .ok_or_else(|| Error::Parse("failed to find user result".to_string()))?;
+        let user = match result {
+            UserResult::User(user) if self.guest && user.legacy.protected => {
+                return Err(Error::GuestUnsupported(format!(
+                    "protected account @{screen_name}"
+                )));
+            }
+            UserResult::User(user) => user,
+            UserResult::UserUnavailable { reason } => {
+                return Err(Error::UserUnavailable {
+                    screen_name: screen_name.to_string(),
+                    reason: reason.unwrap_or_else(|| "unknown reason".to_string()),
+                });
+            }
+            UserResult::Unknown => {
+                return Err(Error::Parse("unexpected user result type".to_string()));
+            }
+        };
+        Ok(user)
+    }
+
+    /// Look up an account once, without waiting out rate limits, to check
+    /// that the credentials are accepted
+    ///
+    /// A rate-limited lookup counts as accepted.
+    #[instrument(skip_all, fields(user = screen_name))]
+    pub async fn probe(&self, screen_name: &str) -> Result<RateLimit> {
+        let (variables, field_toggles, features) = user_by_screen_name_query(screen_name);
+        let response = self
+            .client
+            .get(self.graphql_url(
+                &self.query_ids.user_by_screen_name,
+                query_ids::USER_BY_SCREEN_NAME,
+            ))
+            .header(REFERER, format!("{}/{}", self.base_url, screen_name))
+            .query(&[
+                ("variables", serde_json::to_string(&variables)?),
+                ("fieldToggles", serde_json::to_string(&field_toggles)?),
+                ("features", serde_json::to_string(&features)?),
+            ])
+            .send()
+            .await?;
+
+        let status = response.status();
+        let rate_limit = RateLimit::from_headers(response.headers());
+        if status.is_success() || status == StatusCode::TOO_MANY_REQUESTS {
+            return Ok(rate_limit);
+        }
+        Err(api_error(status, response.text().await?))
+    }
+
+    /// Screen name of the account the credentials belong to
+    ///
+    /// One request without waiting out rate limits, meant as a quick check
+    /// before a long run.
+    #[instrument(skip_all)]
+    pub async fn verify_credentials(&self) -> Result<String> {
+        if self.guest {
+            return Err(Error::GuestUnsupported(
+                "the account of the credentials".to_string(),
+            ));
+        }
+        let response = self
+            .client
+            .get(format!("{}/i/api/1.1/account/settings.json", self.base_url))
+            .header(REFERER, format!("{}/settings/account", self.base_url))
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(api_error(status, response.text().await?));
+        }
+        let settings: AccountSettings = response.json().await?;
+        Ok(settings.screen_name)
+    }
+
+    /// Fetch one page of an account's media timeline
+    #[instrument(skip_all, fields(user = %user.screen_name))]
+    pub async fn user_media(
+        &self,
+        user: &User,
+        cursor: Option<&str>,
+        options: &ExtractOptions,
+    ) -> Result<MediaPage> {
+        let body = self.user_media_body(user, cursor).await?;
+        let raw: Value = serde_json::from_str(&body)?;
+
+        parse_user_media_response(&raw, options)
+    }
+
+    /// Raw response of [`Api::user_media`], for archiving before it is
+    /// parsed
+    pub(crate) async fn user_media_body(
+        &self,
+        user: &User,
+        cursor: Option<&str>,
+    ) -> Result<String> {
+        let variables = if let Some(c) = cursor {
+            json!({
+                "userId": user.rest_id,
+                "count": self.page_size(),
+                "cursor": c,
+                "includePromotedContent": false,
+                "withClientEventToken": false,
+                "withBirdwatchNotes": false,
+                "withVoice": true,
+                "withV2Timeline": true
+            })
+        } else {
+            json!({
+                "userId": user.rest_id,
+                "count": self.page_size(),
+                "includePromotedContent": false,
+                "withClientEventToken": false,
+                "withBirdwatchNotes": false,
+                "withVoice": true,
+                "withV2Timeline": true
+            })
+        };
+
+        let features = json!({
+            "responsive_web_graphql_exclude_directive_enabled": true,
+            "verified_phone_label_enabled": false,
+            "creator_subscriptions_tweet_preview_api_enabled": true,
+            "responsive_web_graphql_timeline_navigation_enabled": true,
+            "responsive_web_graphql_skip_user_profile_image_extensions_enabled": false,
+            "tweetypie_unmention_optimization_enabled": true,
+            "responsive_web_edit_tweet_api_enabled": true,
+            "graphql_is_translatable_rweb_tweet_is_translatable_enabled": true,
+            "view_counts_everywhere_api_enabled": true,
+            "longform_notetweets_consumption_enabled": true,
+            "responsive_web_twitter_article_tweet_consumption_enabled": false,
+            "tweet_awards_web_tipping_enabled": false,
+            "freedom_of_speech_not_reach_fetch_enabled": true,
+            "standardized_nudges_misinfo": true,
+            "tweet_with_visibility_results_prefer_gql_limited_actions_policy_enabled": true,
+            "longform_notetweets_rich_text_read_enabled": true,
+            "longform_notetweets_inline_media_enabled": true,
+            "responsive_web_media_download_video_enabled": false,
+            "responsive_web_enhance_cards_enabled": false
+        });
+
+        let response = self
+            .graphql_get(
+                &self.query_ids.user_media,
+                query_ids::USER_MEDIA,
+                format!("{}/{}", self.base_url, user.screen_name),
+                &[("variables", &variables)],
+                &features,
+            )
+            .await?;
+        Ok(response.text().await?)
+    }
+
+    /// Fetch one page of the accounts `user` follows
+    #[instrument(skip_all, fields(user = %user.screen_name))]
+    pub async fn following(&self, user: &User, cursor: Option<&str>) -> Result<AccountPage> {
+        let mut variables = json!({
+            "userId": user.rest_id,
+            "count": self.page_size(),
+            "includePromotedContent": false
+        });
+        if let Some(c) = cursor {
+            variables["cursor"] = json!(c);
+        }
+
+        let features = json!({
+            "responsive_web_graphql_exclude_directive_enabled": true,
+            "verified_phone_label_enabled": false,
+            "creator_subscriptions_tweet_preview_api_enabled": true,
+            "responsive_web_graphql_timeline_navigation_enabled": true,
+            "responsive_web_graphql_skip_user_profile_image_extensions_enabled": false,
+            "longform_notetweets_consumption_enabled": true,
+            "responsive_web_enhance_cards_enabled": false
+        });
+
+        let response = self
+            .graphql_get(
+                &self.query_ids.following,
+                query_ids::FOLLOWING,
+                format!("{}/{}/following", self.base_url, user.screen_name),
+                &[("variables", &variables)],
+                &features,
+            )
+            .await?;
+
+        let raw: Value = response.json().await?;
+        parse_following_response(&raw)
+    }
+
+    /// Look up whether a tweet still exists
+    #[instrument(skip_all, fields(tweet = tweet_id))]
+    pub async fn tweet_status(&self, tweet_id: &str) -> Result<TweetStatus> {
+        let variables = json!({
+            "tweetId": tweet_id,
+            "withCommunity": false,
+            "includePromotedContent": false,
+            "withVoice": false
+        });
+
+        let features = json!({
+            "responsive_web_graphql_exclude_directive_enabled": true,
+            "verified_phone_label_enabled": false,
+            "creator_subscriptions_tweet_preview_api_enabled": true,
+            "responsive_web_graphql_timeline_navigation_enabled": true,
+            "responsive_web_graphql_skip_user_profile_image_extensions_enabled": false,
+            "longform_notetweets_consumption_enabled": true,
+            "responsive_web_enhance_cards_enabled": false
+        });
+
+        let response = match self
+            .graphql_get(
+                &self.query_ids.tweet_result_by_rest_id,
+                query_ids::TWEET_RESULT_BY_REST_ID,
+                format!("{}/i/web/status/{}", self.base_url, tweet_id),
+                &[("variables", &variables)],
+                &features,
+            )
+            .await
+        {
+            Ok(response) => response,
+            Err(e) if is_refused_request(&e) => return self.syndicated_status(tweet_id, e).await,
+            Err(e) => return Err(e),
+        };
+
+        let raw: Value = response.json().await?;
+        parse_tweet_result_response(&raw)
+    }
+
+    /// Status of a tweet the GraphQL API refused to look up, from the
+    /// syndication API
+    ///
+    /// Only finding the tweet settles it. Deleted tweets and those of
+    /// protected accounts look the same there, so otherwise the GraphQL
+    /// error is returned.
+    async fn syndicated_status(&self, tweet_id: &str, refused: Error) -> Result<TweetStatus> {
+        warn!("{}, trying the syndication API", refused);
+        match self
+            .syndicated_tweet(tweet_id, &ExtractOptions::default())
+            .await
+        {
+            Ok(_) => Ok(TweetStatus::Live),
+            Err(e) => {
+                warn!("syndication API failed too: {}", e);
+                Err(refused)
+            }
+        }
+    }
+
+    /// Fetch a public tweet and its media from the syndication API, without
+    /// cookies, see [`syndication`](crate::syndication)
+    pub async fn syndicated_tweet(
+        &self,
+        tweet_id: &str,
+        options: &ExtractOptions,
+    ) -> Result<SyndicatedTweet> {
+        self.syndication.tweet(tweet_id, options).await
+    }
+
+    /// Fetch one page of the members of a list
+    #[instrument(skip_all, fields(list = %list))]
+    pub async fn list_members(&self, list: &ListId, cursor: Option<&str>) -> Result<AccountPage> {
+        let mut variables = json!({
+            "listId": list.as_str(),
+            "count": self.page_size(),
+            "withSafetyModeUserFields": true
+        });
+        if let Some(c) = cursor {
+            variables["cursor"] = json!(c);
+        }
+
+        let features = json!({
+            "responsive_web_graphql_exclude_directive_enabled": true,
+            "verified_phone_label_enabled": false,
+            "creator_subscriptions_tweet_preview_api_enabled": true,
+            "responsive_web_graphql_timeline_navigation_enabled": true,
+            "responsive_web_graphql_skip_user_profile_image_extensions_enabled": false,
+            "longform_notetweets_consumption_enabled": true,
+            "responsive_web_enhance_cards_enabled": false
+        });
+
+        let response = self
+            .graphql_get(
+                &self.query_ids.list_members,
+                query_ids::LIST_MEMBERS,
+                format!("{}/i/lists/{}/members", self.base_url, list),
+                &[("variables", &variables)],
+                &features,
+            )
+            .await?;
+
+        let raw: Value = response.json().await?;
+        parse_list_members_response(&raw)
+    }
+}
+
+/// Feature flags named in a "features cannot be null" API error
+///
+/// Flags are listed after the colon of the error message. They default to
+/// `false`, unless the message says they must be true.
+pub fn parse_missing_features(body: &str) -> Vec<(String, bool)> {
+    const MARKERS: [(&str, bool); 2] = [
+        ("features cannot be null:", false),
+        ("features must be true:", true),
+    ];
+
+    let Ok(response) = serde_json::from_str::<Value>(body) else {
+        return Vec::new();
+    };
+    let messages = response
+        .get("errors")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.get("message").and_then(Value::as_str));
+
+    let mut missing = Vec::new();
+    for message in messages {
+        for (marker, value) in MARKERS {
+            let Some(start) = message.find(marker) else {
+                continue;
+            };
+            missing.extend(
+                message[start + marker.len()..]
+                    .split(',')
+                    .map(|name| name.trim().trim_end_matches('.'))
+                    .filter(|name| !name.is_empty())
+                    .map(|name| (name.to_string(), value)),
+            );
+        }
+    }
+    missing
+}
+
+/// Why the API rejected the credentials
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthFailure {
+    /// `auth_token` is wrong, expired or was logged out
+    InvalidToken,
+    /// `ct0` does not belong to `auth_token`
+    CsrfMismatch,
+    /// The account has to be unlocked in a browser
+    AccountLocked,
+    Suspended,
+}
+
+impl AuthFailure {
+    /// Classify a rejected response by its error codes, `None` if it is not
+    /// about the credentials
+    pub fn classify(status: StatusCode, body: &str) -> Option<Self> {
+        #[derive(Deserialize)]
+        struct Errors {
+            errors: Vec<ErrorCode>,
+        }
+        #[derive(Deserialize)]
+        struct ErrorCode {
+            code: Option<u32>,
+        }
+
+        let codes = serde_json::from_str::<Errors>(body)
+            .map(|e| e.errors.into_iter().filter_map(|e| e.code).collect())
+            .unwrap_or_else(|_| Vec::new());
+        for code in codes {
+            match code {
+                32 | 89 | 215 => return Some(AuthFailure::InvalidToken),
+                353 => return Some(AuthFailure::CsrfMismatch),
+                326 => return Some(AuthFailure::AccountLocked),
+                64 => return Some(AuthFailure::Suspended),
+                _ => {}
+            }
+        }
+        (status == StatusCode::UNAUTHORIZED).then_some(AuthFailure::InvalidToken)
+    }
+
+    /// What to do about it, in one line
+    pub fn fix(&self) -> &'static str {
+        match self {
+            AuthFailure::InvalidToken => {
+                "log in again in the browser and copy the auth_token cookie"
+            }
+            AuthFailure::CsrfMismatch => {
+                "copy the ct0 cookie again, from the same browser session as auth_token"
+            }
+            AuthFailure::AccountLocked => "unlock the account at x.com in a browser",
+            AuthFailure::Suspended => "use the cookies of another account",
+        }
+    }
+}
+
+impl std::fmt::Display for AuthFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AuthFailure::InvalidToken => "auth_token is invalid or expired",
+            AuthFailure::CsrfMismatch => "ct0 does not match auth_token",
+            AuthFailure::AccountLocked => "the account is locked",
+            AuthFailure::Suspended => "the account is suspended",
+        })
+    }
+}
+
+/// Whether a GraphQL request was refused as a whole, not answered for the tweet
+fn is_refused_request(e: &Error) -> bool {
+    match e {
+        Error::Auth(_) | Error::GuestUnsupported(_) => true,
+        Error::Api { status, .. } => matches!(
+            *status,
+            StatusCode::BAD_REQUEST | StatusCode::FORBIDDEN | StatusCode::NOT_FOUND
+        ),
+        _ => false,
+    }
+}
+
+/// Error for a rejected API request, telling credential problems apart
+fn api_error(status: StatusCode, body: String) -> Error {
+    match AuthFailure::classify(status, &body) {
+        Some(failure) => Error::Auth(failure),
+        None => Error::Api { status, body },
+    }
+}
+
+/// The part of `account/settings.json` rxd needs
+#[derive(Deserialize)]
+struct AccountSettings {
+    screen_name: String,
+}
+
+/// Variables, field toggles and features of a UserByScreenName query
+fn user_by_screen_name_query(screen_name: &str) -> (Value, Value, Value) {
+    let variables = json!({
+        "screen_name": screen_name,
+        "withSafetyModeUserFields": false,
+    });
+
+    let features = json!({
+        "hidden_profile_likes_enabled": false,
+        "hidden_profile_subscriptions_enabled": false,
+        "responsive_web_graphql_exclude_directive_enabled": true,
+        "verified_phone_label_enabled": false,
+        "subscriptions_verification_info_verified_since_enabled": true,
+        "highlights_tweets_tab_ui_enabled": true,
+        "creator_subscriptions_tweet_preview_api_enabled": true,
+        "responsive_web_graphql_skip_user_profile_image_extensions_enabled": false,
+        "responsive_web_graphql_timeline_navigation_enabled": true,
+    });
+
+    let field_toggles = json!({
+        "withAuxiliaryUserLabels": false,
+    });
+
+    (variables, field_toggles, features)
+}
+
+/// Rate limit window of an endpoint, from the `x-rate-limit-*` headers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimit {
+    pub limit: Option<u64>,
+    pub remaining: Option<u64>,
+    /// Unix timestamp at which the window resets
+    pub reset: Option<u64>,
+}
+
+impl RateLimit {
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok())
+        };
+        Self {
+            limit: get("x-rate-limit-limit"),
+            remaining: get("x-rate-limit-remaining"),
+            reset: get("x-rate-limit-reset"),
+        }
+    }
+}
+
+/// Time until the rate limit resets, from the `x-rate-limit-reset` epoch header
+fn rate_limit_wait(response: &reqwest::Response) -> Duration {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let reset = response
+        .headers()
+        .get("x-rate-limit-reset")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    match reset {
+        Some(reset) => {
+            Duration::from_secs(reset.saturating_sub(now).max(1)).min(MAX_RATE_LIMIT_WAIT)
+        }
+        None => Duration::from_secs(60),
+    }
+}
+
+#[instrument(skip_all)]
+fn build_client(auth_token: &str, ct0: &str, base_url: &str, tls: &TlsOptions) -> Result<Client> {
+    let mut headers = base_headers(base_url)?;
+    headers.insert(
+        COOKIE,
+        HeaderValue::from_str(&format!("auth_token={auth_token}; ct0={ct0};"))?,
+    );
+    headers.insert(
+        HeaderName::from_str("x-csrf-token")?,
+        HeaderValue::from_str(ct0)?,
+    );
+
+    let client = tls.client_builder()?.default_headers(headers).build()?;
+    Ok(client)
+}
+
+/// [`build_client`] sending a guest token instead of account cookies
+fn build_guest_client(guest_token: &str, base_url: &str, tls: &TlsOptions) -> Result<Client> {
+    let mut headers = base_headers(base_url)?;
+    headers.insert(
+        HeaderName::from_str("x-guest-token")?,
+        HeaderValue::from_str(guest_token)?,
+    );
+
+    let client = tls.client_builder()?.default_headers(headers).build()?;
+    Ok(client)
+}
+
+/// Headers of the web client sent with and without an account
+fn base_headers(base_url: &str) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    headers.insert(USER_AGENT, HeaderValue::from_static(DEFAULT_USER_AGENT));
+    headers.insert(
+        AUTHORIZATION,
+        HeaderValue::from_static(DEFAULT_AUTHORIZATION),
+    );
+    // Requests must look like they come from the web client on the same host
+    headers.insert(ORIGIN, HeaderValue::from_str(base_url)?);
+    headers.insert(REFERER, HeaderValue::from_str(&format!("{base_url}/"))?);
+    Ok(headers)
+}
+
+/// Fetch a guest token, valid for a few hours
+async fn activate_guest(client: &Client, base_url: &str) -> Result<String> {
+    #[derive(Deserialize)]
+    struct Activation {
+        guest_token: String,
+    }
+
+    let response = client
+        .post(format!("{base_url}/i/api/1.1/guest/activate.json"))
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(api_error(status, response.text().await?));
+    }
+    Ok(response.json::<Activation>().await?.guest_token)
+}
@@ -0,0 +1,273 @@
+//! Raw GraphQL responses kept next to an account's media, for fields the
+//! parser does not extract yet.
+//!
+//! Every response is written to `<save_path>/_raw` before it is parsed, as
+//! `<time>-<operation>[-page<N>].json`, gzipped with the `gzip` command if
+//! [`ResponseArchive::compress`] is set. [`ArchivedResponses`] reads them
+//! back for `rxd replay`.
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, SystemTime};
+
+use chrono::Utc;
+use serde_json::Value;
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::{debug, info};
+
+use crate::error::{Error, Result};
+use crate::query_ids;
+use crate::task::{ExtractOptions, MediaPage, parse_user_media_response};
+
+/// Folder of the raw responses inside an account's save path
+pub const RAW_DIR: &str = "_raw";
+
+/// How raw responses are archived
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ResponseArchive {
+    /// Gzip every response, which needs the `gzip` command
+    pub compress: bool,
+    /// Delete archived responses older than this many days, keep them all
+    /// if `None`
+    pub max_age_days: Option<u32>,
+}
+
+impl ResponseArchive {
+    /// Gzip every response, which needs the `gzip` command
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Delete archived responses older than `days`
+    pub fn max_age_days(mut self, days: Option<u32>) -> Self {
+        self.max_age_days = days;
+        self
+    }
+
+    /// Write `body` of an `operation` response to the archive in
+    /// `save_path`, numbered with `page` for timelines
+    pub async fn write(
+        &self,
+        save_path: &Path,
+        operation: &str,
+        page: Option<u32>,
+        body: &str,
+    ) -> Result<PathBuf> {
+        let dir = save_path.join(RAW_DIR);
+        fs::create_dir_all(&dir).await?;
+        let stem = match page {
+            Some(page) => format!("{}-{operation}-page{page}", timestamp()),
+            None => format!("{}-{operation}", timestamp()),
+        };
+        let extension = if self.compress { "json.gz" } else { "json" };
+        let (path, file) = create_unique(&dir, &stem, extension).await?;
+        let written = if self.compress {
+            gzip(body, file.into_std().await).await
+        } else {
+            write_all(file, body).await
+        };
+        if let Err(e) = written {
+            let _ = fs::remove_file(&path).await;
+            return Err(e);
+        }
+        debug!("archived {} response to {}", operation, path.display());
+        Ok(path)
+    }
+
+    /// Delete responses in the archive of `save_path` older than
+    /// [`Self::max_age_days`], returning how many were deleted
+    pub async fn prune(&self, save_path: &Path) -> Result<usize> {
+        let Some(days) = self.max_age_days else {
+            return Ok(0);
+        };
+        let max_age = Duration::from_secs(u64::from(days) * 24 * 60 * 60);
+        let mut entries = match fs::read_dir(save_path.join(RAW_DIR)).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let now = SystemTime::now();
+        let mut pruned = 0;
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            let age = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| now.duration_since(modified).ok());
+            if metadata.is_file() && age.is_some_and(|age| age > max_age) {
+                fs::remove_file(entry.path()).await?;
+                pruned += 1;
+            }
+        }
+        if pruned > 0 {
+            info!(
+                "pruned {} archived responses older than {} days",
+                pruned, days
+            );
+        }
+        Ok(pruned)
+    }
+}
+
+/// Responses archived in one folder, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct ArchivedResponses {
+    lookups: Vec<PathBuf>,
+    pages: Vec<PathBuf>,
+}
+
+impl ArchivedResponses {
+    /// Archived responses in `dir`, other files are ignored
+    pub async fn scan(dir: &Path) -> Result<Self> {
+        let mut responses = Self::default();
+        let mut entries = fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            let Some(stem) = name.to_str().and_then(|name| {
+                name.strip_suffix(".json.gz")
+                    .or_else(|| name.strip_suffix(".json"))
+            }) else {
+                continue;
+            };
+            if stem.contains(&format!("-{}-page", query_ids::USER_MEDIA)) {
+                responses.pages.push(entry.path());
+            } else if stem.contains(&format!("-{}", query_ids::USER_BY_SCREEN_NAME)) {
+                responses.lookups.push(entry.path());
+            }
+        }
+        // Names start with the time they were written
+        responses.pages.sort();
+        responses.lookups.sort();
+        Ok(responses)
+    }
+
+    /// Archived timeline pages
+    pub fn pages(&self) -> &[PathBuf] {
+        &self.pages
+    }
+
+    /// Latest archived account lookup
+    pub fn lookup(&self) -> Option<&Path> {
+        self.lookups.last().map(PathBuf::as_path)
+    }
+
+    /// Screen name in the latest archived account lookup
+    pub async fn screen_name(&self) -> Result<Option<String>> {
+        let Some(path) = self.lookup() else {
+            return Ok(None);
+        };
+        let raw: Value = serde_json::from_str(&read(path).await?)?;
+        Ok(raw
+            .pointer("/data/user/result/legacy/screen_name")
+            .and_then(Value::as_str)
+            .map(str::to_string))
+    }
+}
+
+/// Body of an archived response, decompressed with the `gzip` command if
+/// it is gzipped
+pub async fn read(path: &Path) -> Result<String> {
+    if path.extension().is_none_or(|e| e != "gz") {
+        return Ok(fs::read_to_string(path).await?);
+    }
+    let output = match Command::new("gzip")
+        .arg("-dc")
+        .arg(path)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(Error::Config(
+                "reading gzipped responses needs gzip, which is not installed".to_string(),
+            ));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "gzip failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .into());
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|_| Error::Parse(format!("{} is not UTF-8", path.display())))
+}
+
+/// Archived timeline page at `path`, parsed like a fetched one
+pub async fn read_page(path: &Path, options: &ExtractOptions) -> Result<MediaPage> {
+    let raw: Value = serde_json::from_str(&read(path).await?)?;
+    parse_user_media_response(&raw, options)
+}
+
+/// Current time down to milliseconds, sorting in the order of writing
+fn timestamp() -> String {
+    Utc::now().format("%Y%m%dT%H%M%S%.3fZ").to_string()
+}
+
+/// New file `<stem>.<extension>` in `dir`, with a counter if the name is taken
+async fn create_unique(dir: &Path, stem: &str, extension: &str) -> Result<(PathBuf, fs::File)> {
+    let mut counter = 0;
+    loop {
+        let path = match counter {
+            0 => dir.join(format!("{stem}.{extension}")),
+            n => dir.join(format!("{stem}-{n}.{extension}")),
+        };
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await
+        {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => counter += 1,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+async fn write_all(mut file: fs::File, body: &str) -> Result<()> {
+    file.write_all(body.as_bytes()).await?;
+    file.flush().await?;
+    Ok(())
+}
+
+/// Compress `body` into `file` with the `gzip` command
+async fn gzip(body: &str, file: std::fs::File) -> Result<()> {
+    let mut child = match Command::new("gzip")
+        .args(["-c", "-n"])
+        .stdin(Stdio::piped())
+        .stdout(file)
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(Error::Config(
+                "archive_compress needs gzip, which is not installed".to_string(),
+            ));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(body.as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "gzip failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,257 @@
+//! `SHA256SUMS` files in the format of `sha256sum`, for checking a copy of
+//! the downloads with coreutils alone.
+//!
+//! Each line is `<hash>  <path>` with the path relative to the file and
+//! separated by `/`, sorted by path. Recorded SHA-256 hashes are used where
+//! they exist, other files are hashed. A file modified after the previous
+//! `SHA256SUMS` was written is hashed again, and files gone from disk are
+//! dropped.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use futures::StreamExt;
+use sqlx::SqlitePool;
+use tokio::fs;
+use tracing::{debug, instrument, warn};
+
+use crate::db::{self, AccountFile};
+use crate::error::Result;
+use crate::hash::HashAlgorithm;
+
+/// Name of the checksum file
+pub const SHA256SUMS: &str = "SHA256SUMS";
+
+/// Counts of a run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChecksumSummary {
+    /// `SHA256SUMS` files written
+    pub written: u64,
+    /// Lines in them
+    pub entries: u64,
+    /// Files hashed for them, the other hashes were known
+    pub hashed: u64,
+    /// Recorded files not on disk
+    pub missing: u64,
+    /// Files left out, outside of the root or unreadable
+    pub skipped: u64,
+}
+
+/// A file to list, with the hash known for it
+struct Entry {
+    path: PathBuf,
+    relative: String,
+    recorded: Option<String>,
+}
+
+/// Write `SHA256SUMS` for the files of `screen_name`, or of every account
+///
+/// Without `root` each account gets one in the folder `folder` returns for
+/// it, with `root` a single one there lists every file below it. Up to
+/// `threads` files are hashed at once.
+#[instrument(skip_all)]
+pub async fn write(
+    pool: &SqlitePool,
+    screen_name: Option<&str>,
+    folder: impl Fn(&str) -> PathBuf,
+    root: Option<&Path>,
+    threads: usize,
+) -> Result<ChecksumSummary> {
+    let files = db::account_files(pool, screen_name).await?;
+    let mut summary = ChecksumSummary::default();
+
+    // Files by the folder of their SHA256SUMS
+    let mut manifests: BTreeMap<PathBuf, Vec<Entry>> = BTreeMap::new();
+    let root = match root {
+        Some(root) => Some(fs::canonicalize(root).await?),
+        None => None,
+    };
+    let mut folders = HashMap::new();
+    for file in files {
+        let account_folder = folders
+            .entry(file.screen_name.to_lowercase())
+            .or_insert_with(|| folder(&file.screen_name))
+            .clone();
+        let path = account_folder.join(&file.filename);
+        let recorded = sha256(&file);
+        let (dir, relative) = match &root {
+            None => (account_folder, relative(Path::new(&file.filename))),
+            Some(root) => {
+                // The folder, not the file, which may link to another one
+                let absolute = match fs::canonicalize(&account_folder).await {
+                    Ok(absolute) => absolute.join(&file.filename),
+                    Err(e) if e.kind() == ErrorKind::NotFound => {
+                        summary.missing += 1;
+                        continue;
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+                match absolute.strip_prefix(root) {
+                    Ok(inside) => (root.clone(), relative(inside)),
+                    Err(_) => {
+                        warn!("not below {}, left out: {}", root.display(), path.display());
+                        summary.skipped += 1;
+                        continue;
+                    }
+                }
+            }
+        };
+        let Some(relative) = relative else {
+            warn!(
+                "name not representable in {}: {}",
+                SHA256SUMS,
+                path.display()
+            );
+            summary.skipped += 1;
+            continue;
+        };
+        manifests.entry(dir).or_default().push(Entry {
+            path,
+            relative,
+            recorded,
+        });
+    }
+
+    for (dir, entries) in manifests {
+        if !fs::try_exists(&dir).await? {
+            debug!("no folder {}", dir.display());
+            summary.missing += entries.len() as u64;
+            continue;
+        }
+        write_one(&dir, entries, threads, &mut summary).await?;
+    }
+    Ok(summary)
+}
+
+/// Update the `SHA256SUMS` in `dir` to list `entries`
+async fn write_one(
+    dir: &Path,
+    entries: Vec<Entry>,
+    threads: usize,
+    summary: &mut ChecksumSummary,
+) -> Result<()> {
+    let manifest = dir.join(SHA256SUMS);
+    let (previous, written_at) = read_previous(&manifest).await?;
+
+    let mut lines = BTreeMap::new();
+    let mut to_hash = Vec::new();
+    for entry in entries {
+        let modified = match fs::metadata(&entry.path).await {
+            Ok(metadata) => metadata.modified().ok(),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                summary.missing += 1;
+                continue;
+            }
+            Err(e) => {
+                warn!("failed to read {}: {}", entry.path.display(), e);
+                summary.skipped += 1;
+                continue;
+            }
+        };
+        let unchanged = match (written_at, modified) {
+            (None, _) => true,
+            (Some(written_at), Some(modified)) => modified <= written_at,
+            (Some(_), None) => false,
+        };
+        let known = previous
+            .get(&entry.relative)
+            .cloned()
+            .or(entry.recorded)
+            .filter(|_| unchanged);
+        match known {
+            Some(hash) => {
+                lines.insert(entry.relative, hash);
+            }
+            None => to_hash.push((entry.path, entry.relative)),
+        }
+    }
+
+    let mut hashed = futures::stream::iter(to_hash)
+        .map(|(path, relative)| async move {
+            let result = tokio::task::spawn_blocking({
+                let path = path.clone();
+                move || HashAlgorithm::Sha256.hash_file(&path)
+            })
+            .await
+            .map_err(std::io::Error::other)
+            .and_then(|result| result);
+            (path, relative, result)
+        })
+        .buffer_unordered(threads.max(1));
+    while let Some((path, relative, result)) = hashed.next().await {
+        match result {
+            Ok(hash) => {
+                summary.hashed += 1;
+                lines.insert(relative, hash);
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => summary.missing += 1,
+            Err(e) => {
+                warn!("failed to read {}: {}", path.display(), e);
+                summary.skipped += 1;
+            }
+        }
+    }
+
+    if lines.is_empty() {
+        // sha256sum rejects a file without lines
+        match fs::remove_file(&manifest).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => return Ok(()),
+        }
+    }
+    let content: String = lines
+        .iter()
+        .map(|(path, hash)| format!("{hash}  {path}\n"))
+        .collect();
+    let partial = dir.join(format!("{SHA256SUMS}.part"));
+    fs::write(&partial, content).await?;
+    fs::rename(&partial, &manifest).await?;
+    debug!("wrote {} lines to {}", lines.len(), manifest.display());
+    summary.written += 1;
+    summary.entries += lines.len() as u64;
+    Ok(())
+}
+
+/// Lines of an existing `SHA256SUMS` by path and when it was written
+async fn read_previous(manifest: &Path) -> Result<(HashMap<String, String>, Option<SystemTime>)> {
+    let content = match fs::read_to_string(manifest).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok((HashMap::new(), None)),
+        Err(e) => return Err(e.into()),
+    };
+    let written_at = fs::metadata(manifest).await?.modified().ok();
+    let lines = content
+        .lines()
+        .filter_map(|line| {
+            let (hash, path) = line.split_once("  ")?;
+            Some((path.to_string(), hash.to_string()))
+        })
+        .collect();
+    Ok((lines, written_at))
+}
+
+/// Recorded hash of `file` if it is a SHA-256 one
+fn sha256(file: &AccountFile) -> Option<String> {
+    match file.hash_algorithm {
+        Some(HashAlgorithm::Sha256) => file.file_hash.clone(),
+        _ => None,
+    }
+}
+
+/// `path` joined with `/`, `None` if it leaves its folder or has a name
+/// `sha256sum` would have to escape
+fn relative(path: &Path) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    let relative = parts.join("/");
+    let escaped = relative.contains(['\n', '\r', '\\']);
+    (!relative.is_empty() && !escaped).then_some(relative)
+}
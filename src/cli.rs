@@ -0,0 +1,221 @@
+//! Subcommands of the `rxd` binary.
+//!
+//! `main.rs` parses the command line and calls the function of the
+//! subcommand. These print their results and progress bars for a terminal;
+//! embedders call the modules they are built on instead.
+
+pub mod download;
+pub mod export;
+pub mod import;
+pub mod logging;
+pub mod maintenance;
+pub mod query;
+pub mod setup;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use sqlx::SqlitePool;
+use tracing::warn;
+
+use crate::api::{self, Api, TlsOptions};
+use crate::config::{self, Config};
+use crate::db;
+use crate::error::{Error, Result};
+use crate::hash::HashAlgorithm;
+use crate::lock::{self, RunLock};
+
+/// Exit code when another run holds the lock of the database, `EX_TEMPFAIL`
+pub const EXIT_LOCKED: u8 = 75;
+
+/// Options that apply to every subcommand
+#[derive(Debug, Clone, Default)]
+pub struct Globals {
+    /// Config file, searched for in standard locations if `None`
+    pub config: Option<PathBuf>,
+    /// Wait for another run holding the lock of the database instead of
+    /// failing with [`Error::Locked`]
+    pub wait: bool,
+}
+
+impl Globals {
+    /// `db_path`, or the database of the config file
+    pub fn db_path(&self, db_path: Option<&Path>) -> Result<PathBuf> {
+        if let Some(db_path) = db_path {
+            return Ok(db_path.to_path_buf());
+        }
+        let config_path = config::resolve_path(self.config.as_deref())?;
+        let config_dir = config::config_dir(&config_path);
+        Ok(if config_path.exists() {
+            Config::load(&config_path)?.database_path(&config_dir)
+        } else {
+            config_dir.join("rxd.db")
+        })
+    }
+
+    /// The config file, `None` without a readable one
+    pub fn optional_config(&self) -> Option<Config> {
+        config::resolve_path(self.config.as_deref())
+            .ok()
+            .filter(|path| path.exists())
+            .and_then(|path| Config::load(&path).ok())
+    }
+
+    /// `hash_algorithm` of the config file, the default without a readable one
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.optional_config()
+            .map(|config| config.hash_algorithm)
+            .unwrap_or_default()
+    }
+
+    /// Database at `db_path`, or the one of the config file
+    pub async fn open_existing_db(&self, db_path: Option<&Path>) -> Result<SqlitePool> {
+        let db_path = self.db_path(db_path)?;
+        // Opening would create an empty database
+        if !db_path.exists() {
+            return Err(no_database(&db_path));
+        }
+        db::open(&db_path).await
+    }
+
+    /// [`Globals::open_existing_db`] for a command that writes, holding the
+    /// run lock until the returned lock is dropped
+    pub async fn lock_existing_db(
+        &self,
+        db_path: Option<&Path>,
+    ) -> Result<(SqlitePool, Option<RunLock>)> {
+        let db_path = self.db_path(db_path)?;
+        if !db_path.exists() {
+            return Err(no_database(&db_path));
+        }
+        let lock = self.run_lock(&db_path).await?;
+        Ok((db::open(&db_path).await?, lock))
+    }
+
+    /// Database at `db_path` or the one of the config file, created if
+    /// missing, holding the run lock until the returned lock is dropped
+    pub async fn lock_db(&self, db_path: Option<&Path>) -> Result<(SqlitePool, Option<RunLock>)> {
+        let db_path = self.db_path(db_path)?;
+        let lock = self.run_lock(&db_path).await?;
+        Ok((db::open(&db_path).await?, lock))
+    }
+
+    /// Lock of the database at `db` for a run, `None` for an in-memory
+    /// database or where files cannot be locked
+    ///
+    /// Fails with [`Error::Locked`] if another run holds it, unless
+    /// [`Globals::wait`] is set.
+    pub async fn run_lock(&self, db: &Path) -> Result<Option<RunLock>> {
+        if db.as_os_str() == db::MEMORY {
+            return Ok(None);
+        }
+        let path = lock::lock_path(db);
+        let result = if self.wait {
+            RunLock::acquire(&path).await
+        } else {
+            RunLock::try_acquire(&path)
+        };
+        match result {
+            Ok(lock) => Ok(Some(lock)),
+            Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::Unsupported => {
+                warn!(
+                    "cannot lock {}, running without a lock: {}",
+                    path.display(),
+                    e
+                );
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn no_database(db_path: &Path) -> Error {
+    Error::Config(format!("no database at {}", db_path.display()))
+}
+
+/// API client with the credentials and TLS settings of `config`
+pub fn config_api(config: &Config, config_dir: &Path) -> Result<Api> {
+    let tls = TlsOptions {
+        ca_cert: config.ca_cert.as_ref().map(|p| config_dir.join(p)),
+        os_roots: config.tls_os_roots,
+    };
+    Api::with_tls(
+        &config.auth_token,
+        &config.ct0,
+        config
+            .api_base_url
+            .as_deref()
+            .unwrap_or(api::DEFAULT_BASE_URL),
+        &tls,
+    )
+}
+
+/// Folder of an account's files, `dir` if given, otherwise its
+/// `save_path` in `config` or `downloads/<screen_name>`
+pub fn account_folders(
+    config: Option<&Config>,
+    dir: Option<&Path>,
+) -> impl Fn(&str) -> PathBuf + Send + Sync + use<> {
+    // Accounts are matched ignoring case, like the database does
+    let save_paths: HashMap<String, PathBuf> = config
+        .iter()
+        .flat_map(|config| &config.tasks)
+        .filter_map(|task| {
+            let save_path = task.save_path.as_ref()?;
+            Some((task.screen_name.to_lowercase(), PathBuf::from(save_path)))
+        })
+        .collect();
+    let dir = dir.map(|dir| dir.to_path_buf());
+    move |screen_name: &str| match &dir {
+        Some(dir) => dir.clone(),
+        None => save_paths
+            .get(&screen_name.to_lowercase())
+            .cloned()
+            .unwrap_or_else(|| PathBuf::from("downloads").join(screen_name)),
+    }
+}
+
+/// Parse a duration such as `90s`, `30m`, `2h` or `1d`, for clap
+pub fn parse_duration(s: &str) -> std::result::Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("invalid duration: {s}"))?;
+    let unit_secs = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        _ => return Err(format!("invalid duration unit: {unit}")),
+    };
+    let secs = value
+        .checked_mul(unit_secs)
+        .ok_or_else(|| "duration too large".to_string())?;
+    if secs == 0 {
+        return Err("duration must be greater than zero".to_string());
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+/// `threads`, or the number of CPUs
+fn threads_or_cpus(threads: Option<usize>) -> usize {
+    threads.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    })
+}
+
+/// Progress bar with `label` in front, its length set by the first update
+fn progress_bar(label: &str) -> indicatif::ProgressBar {
+    indicatif::ProgressBar::new(0).with_style(
+        indicatif::ProgressStyle::with_template(&format!(
+            "{label} {{wide_bar}} {{pos}}/{{len}} ({{eta}})"
+        ))
+        .expect("valid template"),
+    )
+}
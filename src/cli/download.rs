@@ -0,0 +1,955 @@
+//! `rxd download` and `rxd replay`: runs of every task in the config, once
+//! or on a schedule.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use chrono::Local;
+use croner::Cron;
+use indicatif::HumanDuration;
+use sqlx::SqlitePool;
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, debug, error, info, info_span, warn};
+use tracing_subscriber::filter::LevelFilter;
+
+use super::{Globals, account_folders, logging};
+use crate::archive::{ArchivedResponses, ResponseArchive};
+use crate::config::{self, Config, LogFormat, TaskConfig};
+use crate::error::{Error, Result};
+use crate::events::{self, EventFormat, EventSender};
+use crate::following::{self, FollowingConfig};
+use crate::hash::HashAlgorithm;
+use crate::list::{self, ListId};
+use crate::listing::ListFormat;
+use crate::metrics::{self, Metrics};
+use crate::network::OutagePolicy;
+use crate::pushback::PushbackPolicy;
+use crate::reload::LoadedConfig;
+use crate::task::{Task, TaskBuilder};
+use crate::thumbs::FrameExtractor;
+use crate::{Api, api, convert, db, filename, hook, notify, query_ids, report, sidecar, summary};
+
+/// Time between runs in watch mode without --interval or `schedule`
+pub const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Arguments of `rxd download` and `rxd replay`
+#[derive(Debug, Clone, Default)]
+pub struct DownloadArgs {
+    /// Config file given to the subcommand, [`Globals::config`] if `None`
+    pub config_path: Option<PathBuf>,
+    pub events: Option<EventFormat>,
+    /// Keep running, at `interval` or `cron`, the `schedule` of the config
+    /// or [`DEFAULT_WATCH_INTERVAL`]
+    pub watch: bool,
+    pub interval: Option<Duration>,
+    /// Runs on this schedule even without `watch`
+    pub cron: Option<Cron>,
+    pub refresh_query_ids: bool,
+    /// Report file instead of the `report_path` of the config
+    pub report: Option<PathBuf>,
+    pub keep_reports: bool,
+    /// Desktop notification even without `desktop_notify` in the config
+    pub notify: bool,
+    pub overwrite: bool,
+    pub full_scan: bool,
+    pub recheck_gone: bool,
+    /// Accounts to download, all of them if empty
+    pub users: Vec<String>,
+    /// Also download followed accounts without a `[following]` section
+    pub following: bool,
+    pub limit_accounts: Option<usize>,
+    pub guest: bool,
+    pub dry_run: bool,
+    /// Print the media in this format instead of downloading them
+    pub list_only: Option<ListFormat>,
+    /// Leave out the summary table at the end of a run
+    pub quiet: bool,
+    /// Download from archived responses instead of the API
+    pub replay: Option<ReplayArgs>,
+}
+
+/// Arguments only `rxd replay` has
+#[derive(Debug, Clone, Default)]
+pub struct ReplayArgs {
+    /// Folder of archived responses
+    pub raw_dir: PathBuf,
+    pub save_path: Option<PathBuf>,
+    /// Account of the responses, the one of the archived lookup if `None`
+    pub user: Option<String>,
+}
+
+/// Logging given on the command line, the settings of the config apply
+/// where these are `None`
+#[derive(Debug, Clone)]
+pub struct LogArgs {
+    pub console_level: LevelFilter,
+    pub console_format: Option<LogFormat>,
+    pub log_file: Option<PathBuf>,
+    pub log_file_level: Option<LevelFilter>,
+    pub log_file_format: Option<LogFormat>,
+}
+
+impl DownloadArgs {
+    /// How to repeat runs, `None` to run once
+    fn schedule(&self, config: &Config) -> Result<Option<Schedule>> {
+        if let Some(cron) = &self.cron {
+            return Ok(Some(Schedule::Cron(Box::new(cron.clone()))));
+        }
+        if !self.watch {
+            return Ok(None);
+        }
+        match (self.interval, &config.schedule) {
+            (Some(interval), _) => Ok(Some(Schedule::Interval(interval))),
+            (None, Some(expr)) => {
+                let cron = Cron::from_str(expr).map_err(|e| {
+                    Error::Config(format!("invalid cron expression \"{expr}\": {e}"))
+                })?;
+                Ok(Some(Schedule::Cron(Box::new(cron))))
+            }
+            (None, None) => Ok(Some(Schedule::Interval(DEFAULT_WATCH_INTERVAL))),
+        }
+    }
+
+    /// Where to write run reports and whether to keep earlier ones
+    fn report(&self, config: &Config, config_dir: &Path) -> Option<ReportTarget> {
+        if self.replay.is_some() {
+            return None;
+        }
+        let path = self
+            .report
+            .clone()
+            .or_else(|| config.report_path(config_dir))?;
+        Some(ReportTarget {
+            path,
+            keep_existing: self.keep_reports || config.keep_reports,
+        })
+    }
+
+    /// Followed accounts to download, from `[following]` or --following
+    fn following(&self, config: &Config) -> Option<FollowingConfig> {
+        let mut following = match (&config.following, self.following) {
+            (Some(section), _) => section.clone(),
+            (None, true) => FollowingConfig::default(),
+            (None, false) => return None,
+        };
+        if let Some(limit) = self.limit_accounts {
+            following.limit_accounts = Some(limit);
+        }
+        Some(following)
+    }
+
+    /// Whether the task for `screen_name` runs, ignoring case in --user
+    fn selects(&self, screen_name: &str) -> bool {
+        self.users.is_empty()
+            || self
+                .users
+                .iter()
+                .any(|user| user.eq_ignore_ascii_case(screen_name))
+    }
+}
+
+/// When to repeat runs in watch mode
+enum Schedule {
+    Interval(Duration),
+    Cron(Box<Cron>),
+}
+
+/// State shared by every run
+struct RunContext {
+    api: Api,
+    /// Client for requests to other hosts, such as notification webhooks
+    http: reqwest::Client,
+    /// Discovered GraphQL query IDs are cached here between runs
+    query_ids_cache: PathBuf,
+    db: SqlitePool,
+    /// Shared across tasks so the concurrency cap applies to the whole run
+    post_download_hook: Option<Arc<hook::PostDownloadHook>>,
+    /// Shared across tasks like the hook, `None` without ffmpeg
+    frame_extractor: Option<Arc<FrameExtractor>>,
+    /// `None` without ffmpeg
+    post_process: Option<convert::PostProcess>,
+    events: Option<EventSender>,
+    /// Watch mode with `metrics_listen` only
+    metrics: Option<Arc<Metrics>>,
+    /// Where run reports go, with the digest of the config they record
+    report: Option<(ReportTarget, String)>,
+    desktop_notify: bool,
+    cancel: CancellationToken,
+}
+
+/// File the report of every run is written to
+struct ReportTarget {
+    path: PathBuf,
+    /// Write next to an existing report instead of overwriting it
+    keep_existing: bool,
+}
+
+/// Run the tasks of the config once or on a schedule, or replay archived
+/// responses, holding the lock of the database until done
+pub async fn download(globals: &Globals, args: &DownloadArgs, log: &LogArgs) -> Result<()> {
+    let config_path =
+        config::resolve_path(args.config_path.as_deref().or(globals.config.as_deref()))?;
+    let config_dir = config::config_dir(&config_path);
+    let config = Config::load(&config_path)?;
+    // Media hosts need no account
+    config.require_credentials(args.guest || args.replay.is_some())?;
+
+    let log_file_level = match (log.log_file_level, config.log_file_level.as_deref()) {
+        (Some(level), _) => level,
+        (None, Some(level)) => level
+            .parse()
+            .map_err(|e| Error::Config(format!("invalid log_file_level \"{level}\": {e}")))?,
+        (None, None) => LevelFilter::DEBUG,
+    };
+    // A log file from the config is relative to the config directory
+    let log_file = log
+        .log_file
+        .clone()
+        .or_else(|| config.log_file.as_ref().map(|p| config_dir.join(p)));
+    let task_log_dir = config.per_task_logs.then(|| config_dir.join("logs"));
+    logging::init(&logging::LogOptions {
+        console_level: log.console_level,
+        console_format: log.console_format.or(config.log_format).unwrap_or_default(),
+        stdout_reserved: args.events.is_some() || args.list_only.is_some(),
+        log_file: log_file.as_deref(),
+        log_file_level,
+        log_file_format: log
+            .log_file_format
+            .or(config.log_file_format)
+            .unwrap_or_default(),
+        task_log_dir: task_log_dir.as_deref(),
+    })?;
+    info!("tracing initialized");
+    info!("using config file {}", config_path.display());
+
+    // Listing records nothing, the database on disk is left alone
+    let db_file = config.database_path(&config_dir);
+    let (db, _lock) = match args.list_only {
+        Some(_) => (db::init_memory_db().await?, None),
+        None => {
+            // Held until the run ends
+            let lock = globals.run_lock(&db_file).await?;
+            (db::open(&db_file).await?, lock)
+        }
+    };
+
+    let post_download_hook = match &config.post_download_hook {
+        Some(template) => Some(Arc::new(hook::PostDownloadHook::new(
+            template,
+            Duration::from_secs(config.hook_timeout_secs),
+            config.hook_concurrency,
+        )?)),
+        None => None,
+    };
+
+    let frame_extractor = if config.extract_video_frames {
+        let extractor = FrameExtractor::new(
+            "ffmpeg",
+            Duration::from_secs(config.ffmpeg_timeout_secs),
+            config.ffmpeg_concurrency,
+        );
+        if extractor.is_available().await {
+            Some(Arc::new(extractor))
+        } else {
+            warn!(
+                "extract_video_frames is set but ffmpeg is not installed, saving video posters instead"
+            );
+            None
+        }
+    } else {
+        None
+    };
+    let post_process = match config.post_process.clone() {
+        Some(post_process) => {
+            if crate::thumbs::ffmpeg_available(&post_process.ffmpeg).await {
+                Some(post_process)
+            } else {
+                warn!(
+                    "post_process is set but ffmpeg is not installed, keeping images as downloaded"
+                );
+                None
+            }
+        }
+        None => None,
+    };
+
+    // Events go through a channel so the writer never blocks a download
+    let (events, events_writer) = match args.events {
+        Some(EventFormat::Ndjson) => {
+            let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+            (Some(tx), Some(tokio::spawn(events::write_ndjson(rx))))
+        }
+        None => (None, None),
+    };
+
+    // Tasks stop at the next page or download once interrupted
+    let cancel = CancellationToken::new();
+    tokio::spawn({
+        let cancel = cancel.clone();
+        async move {
+            shutdown_signal().await;
+            info!("interrupted, finishing in-flight downloads");
+            cancel.cancel();
+        }
+    });
+
+    // Fails here rather than on the first request if the CA file is bad
+    let tls = api::TlsOptions {
+        ca_cert: config.ca_cert.as_ref().map(|p| config_dir.join(p)),
+        os_roots: config.tls_os_roots,
+    };
+    let base_url = config
+        .api_base_url
+        .as_deref()
+        .unwrap_or(api::DEFAULT_BASE_URL);
+    let api = if (args.guest || config.guest) && args.replay.is_none() {
+        Api::guest(base_url, &tls).await?
+    } else {
+        Api::with_tls(&config.auth_token, &config.ct0, base_url, &tls)?
+    };
+    let api = match config.page_size {
+        Some(size) => api.with_page_size(size),
+        None => api,
+    }
+    .with_api_concurrency(config.api_concurrency);
+    info!(
+        "up to {} API requests and {} media downloads at once",
+        config.api_concurrency.max(1),
+        config.concurrent_downloads
+    );
+    if api.is_guest() {
+        warn!(
+            "guest mode: public accounts only, no sensitive media, {} tweets per page and stricter rate limits",
+            api.page_size()
+        );
+    }
+    let api = match (config.rotate_accounts, config.accounts.is_empty()) {
+        _ if api.is_guest() => api,
+        (true, false) => {
+            info!(
+                "rotating requests between {} accounts",
+                config.accounts.len() + 1
+            );
+            api.with_rotation(&config.accounts, &tls)?
+        }
+        (true, true) => {
+            warn!("rotate_accounts is set without [[accounts]], using one account");
+            api
+        }
+        (false, _) => api,
+    };
+    // Without the auth headers of the API client
+    let http = tls.client_builder()?.build()?;
+
+    // Only watch mode serves metrics, nothing listens otherwise
+    let schedule = args.schedule(&config)?;
+    let metrics = match (config.metrics_listen, &schedule) {
+        (Some(addr), Some(_)) => {
+            let metrics = Arc::new(Metrics::new(api.usage().clone()));
+            metrics::serve(addr, Arc::clone(&metrics), cancel.clone()).await?;
+            Some(metrics)
+        }
+        (Some(_), None) => {
+            debug!("metrics_listen is only served in watch mode");
+            None
+        }
+        (None, _) => None,
+    };
+    // Counted from the events, whether or not they are also written out
+    let (events, metrics_collector) = match &metrics {
+        Some(metrics) => {
+            let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+            let collector = tokio::spawn(Arc::clone(metrics).collect(rx, events));
+            (Some(tx), Some(collector))
+        }
+        None => (events, None),
+    };
+
+    let desktop_notify = args.notify || config.desktop_notify;
+    if desktop_notify && !cfg!(feature = "desktop-notify") {
+        warn!("built without desktop notifications, not showing any");
+    }
+    #[cfg(feature = "desktop-notify")]
+    if desktop_notify && notify::desktop::find_notifier().is_none() {
+        warn!(
+            "desktop notifications need {}, which is not installed",
+            notify::desktop::NOTIFIER
+        );
+    }
+
+    let report = match args.report(&config, &config_dir) {
+        Some(target) => Some((target, config_digest(&config_path)?)),
+        None => None,
+    };
+
+    let ctx = RunContext {
+        api,
+        http,
+        query_ids_cache: config_dir.join("query_ids.json"),
+        db,
+        post_download_hook,
+        frame_extractor,
+        post_process,
+        events,
+        metrics,
+        report,
+        desktop_notify,
+        cancel,
+    };
+    let result = match (&args.replay, schedule) {
+        (Some(replay_args), _) => replay(args, replay_args, &config, &ctx).await,
+        (None, None) => run(args, &config, &ctx).await,
+        (None, Some(schedule)) => watch(args, &config_path, &ctx, schedule).await,
+    };
+
+    // Dropping the last sender lets the writer drain the channel and exit
+    drop(ctx);
+    if let Some(collector) = metrics_collector {
+        let _ = collector.await;
+    }
+    if let Some(writer) = events_writer {
+        let _ = writer.await;
+    }
+
+    result
+}
+
+/// SHA-256 of the config file, so a report shows which config it ran with
+fn config_digest(config_path: &Path) -> std::io::Result<String> {
+    Ok(HashAlgorithm::Sha256.hash(&std::fs::read(config_path)?))
+}
+
+/// Show how a run went on the desktop
+///
+/// Headless sessions have no notifier, which must not fail the run.
+#[cfg(feature = "desktop-notify")]
+async fn show_desktop_notification(run_summary: &summary::RunSummary) {
+    let notification = notify::desktop::DesktopNotification::for_run(run_summary);
+    if let Err(e) = notification.show().await {
+        debug!("failed to show desktop notification: {}", e);
+    }
+}
+
+/// Warned about at startup
+#[cfg(not(feature = "desktop-notify"))]
+async fn show_desktop_notification(_: &summary::RunSummary) {}
+
+/// Run every task on a schedule until interrupted, reading the config at
+/// `config_path` again before every run
+async fn watch(
+    args: &DownloadArgs,
+    config_path: &Path,
+    ctx: &RunContext,
+    schedule: Schedule,
+) -> Result<()> {
+    let mut current = LoadedConfig::load(config_path)?;
+    let mut first = true;
+    match &schedule {
+        Schedule::Interval(interval) => {
+            info!("watch mode, checking every {}", HumanDuration(*interval))
+        }
+        Schedule::Cron(cron) => info!("watch mode, schedule \"{}\"", cron.pattern),
+    }
+    loop {
+        // Cron runs wait for their slot, interval runs start right away
+        let slot = match &schedule {
+            Schedule::Cron(cron) => {
+                let next = cron
+                    .find_next_occurrence(&Local::now(), false)
+                    .map_err(|e| {
+                        Error::Config(format!("no next run of \"{}\": {}", cron.pattern, e))
+                    })?;
+                info!("next run scheduled at {}", next.format("%Y-%m-%d %H:%M:%S"));
+                let wait = (next - Local::now()).to_std().unwrap_or_default();
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => {}
+                    _ = ctx.cancel.cancelled() => {
+                        info!("interrupted, stopping watch mode");
+                        break;
+                    }
+                }
+                Some((cron, next))
+            }
+            Schedule::Interval(_) => None,
+        };
+
+        // A broken edit must not end watch mode either
+        if !std::mem::take(&mut first) {
+            match LoadedConfig::load(config_path) {
+                Ok(reloaded) => {
+                    for change in current.changes(&reloaded) {
+                        info!("config reloaded: {}", change);
+                    }
+                    current = reloaded;
+                }
+                Err(e) => error!("keeping the previous config, {}", e),
+            }
+        }
+
+        let result = run(args, &current.config, ctx).await;
+        if ctx.cancel.is_cancelled() {
+            info!("interrupted, stopping watch mode");
+            break;
+        }
+        // A failed iteration must not end watch mode
+        if let Err(e) = result {
+            error!("run failed: {}", e);
+        }
+
+        match (&schedule, slot) {
+            (Schedule::Interval(interval), _) => {
+                let wait = *interval + jitter(*interval);
+                info!("next run in {}", HumanDuration(wait));
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => {}
+                    _ = ctx.cancel.cancelled() => {
+                        info!("interrupted, stopping watch mode");
+                        break;
+                    }
+                }
+            }
+            // Runs never overlap, slots that passed while running are skipped
+            (Schedule::Cron(_), Some((cron, slot))) => {
+                if let Ok(following) = cron.find_next_occurrence(&slot, false)
+                    && following <= Local::now()
+                {
+                    warn!(
+                        "run overran the slot at {}, skipping missed runs",
+                        following.format("%Y-%m-%d %H:%M:%S")
+                    );
+                }
+            }
+            (Schedule::Cron(_), None) => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Download or list the media of the responses archived in `raw_dir`
+async fn replay(
+    args: &DownloadArgs,
+    replay_args: &ReplayArgs,
+    config: &Config,
+    ctx: &RunContext,
+) -> Result<()> {
+    let raw_dir = &replay_args.raw_dir;
+    let responses = ArchivedResponses::scan(raw_dir).await?;
+    if responses.pages().is_empty() {
+        return Err(Error::Config(format!(
+            "no archived timeline pages in {}",
+            raw_dir.display()
+        )));
+    }
+    let screen_name = match &replay_args.user {
+        Some(user) => user.clone(),
+        None => responses.screen_name().await?.ok_or_else(|| {
+            Error::Config(format!(
+                "no account lookup archived in {}, pass --user",
+                raw_dir.display()
+            ))
+        })?,
+    };
+    let task_config = TaskConfig {
+        screen_name,
+        ..Default::default()
+    };
+
+    let totals = async {
+        let mut builder =
+            task_builder(args, config, &task_config, &ctx.api, ctx)?.replay(responses);
+        if let Some(save_path) = &replay_args.save_path {
+            builder = builder.save_path(save_path);
+        }
+        Arc::new(builder.build().await?).execute().await
+    }
+    .instrument(info_span!(
+        logging::TASK_SPAN,
+        screen_name = %task_config.screen_name
+    ))
+    .await?;
+
+    if args.list_only.is_none() {
+        info!(
+            "replay finished: {} media items, {} downloaded, {} skipped, {} failed",
+            totals.fetched, totals.downloaded, totals.skipped, totals.failed
+        );
+    }
+    Ok(())
+}
+
+/// Builder for the task of `task_config` with the settings of the config
+/// and command line
+fn task_builder(
+    args: &DownloadArgs,
+    config: &Config,
+    task_config: &TaskConfig,
+    api: &Api,
+    ctx: &RunContext,
+) -> Result<TaskBuilder> {
+    let mut builder = Task::builder()
+        .screen_name(&task_config.screen_name)
+        .api(api.clone())
+        .concurrency(config.concurrent_downloads)
+        .image_size(task_config.image_size.unwrap_or(config.image_size))
+        .timezone(config.timezone)
+        .exclude_retweets(
+            task_config
+                .exclude_retweets
+                .unwrap_or(config.exclude_retweets),
+        )
+        .text_include(task_config.text_include.clone())
+        .text_exclude(task_config.text_exclude.clone())
+        .min_favorites(task_config.min_favorites)
+        .min_retweets(task_config.min_retweets)
+        .db(ctx.db.clone())
+        .cancellation(ctx.cancel.clone());
+    if let Some(save_path) = &task_config.save_path {
+        builder = builder.save_path(save_path);
+    }
+    if let Some(max_file_size) = config.max_file_size {
+        builder = builder.max_file_size(max_file_size.0);
+    }
+    if let Some(max_video_bitrate) = config.max_video_bitrate {
+        builder = builder.max_video_bitrate(max_video_bitrate);
+    }
+    builder = builder
+        .hash_algorithm(config.hash_algorithm)
+        .overwrite(args.overwrite)
+        .syndication_rescue(config.syndication_rescue)
+        .full_scan(args.full_scan)
+        .dedupe(config.dedupe, account_folders(Some(config), None))
+        .embed_metadata(config.embed_metadata)
+        .video_thumbnails(config.save_video_thumbnails || config.extract_video_frames)
+        .outage_policy(
+            OutagePolicy::default().max_outage(Duration::from_secs(config.max_outage_secs)),
+        )
+        .pushback_policy(
+            PushbackPolicy::default().max_wait(Duration::from_secs(config.max_retry_after_secs)),
+        );
+    if config.archive_responses {
+        builder = builder.archive_responses(
+            ResponseArchive::default()
+                .compress(config.archive_compress)
+                .max_age_days(config.archive_max_age_days),
+        );
+    }
+    if let Some(pages) = config.stop_after_known_pages {
+        builder = builder.stop_after_known_pages(pages);
+    }
+    if args.recheck_gone {
+        builder = builder.recheck_gone_after(Duration::ZERO);
+    } else if let Some(days) = config.recheck_gone_after_days {
+        builder = builder.recheck_gone_after(Duration::from_secs(u64::from(days) * 24 * 60 * 60));
+    }
+    if config.write_text_sidecars {
+        builder = builder.text_sidecars(sidecar::TextSidecars {
+            per_tweet: config.text_sidecars_per_tweet,
+            permalink: config.text_sidecar_permalink,
+        });
+    }
+    if let Some(template) = task_config
+        .filename_template
+        .as_ref()
+        .or(config.filename_template.as_ref())
+    {
+        builder = builder.filename_template(
+            filename::Template::parse(template)?.text_length(config.filename_text_length),
+        );
+    }
+    if let Some(frames) = &ctx.frame_extractor {
+        builder = builder.frame_extractor(Arc::clone(frames));
+    }
+    if let Some(post_process) = &ctx.post_process {
+        builder = builder.post_process(post_process.clone());
+    }
+    if let Some(hook) = &ctx.post_download_hook {
+        builder = builder.post_download_hook(Arc::clone(hook));
+    }
+    if let Some(format) = args.list_only.clone() {
+        let screen_name = task_config.screen_name.clone();
+        let image_size = task_config.image_size.unwrap_or(config.image_size);
+        builder = builder.list_only(move |item| {
+            // A closed pipe, e.g. into head, is not an error
+            let _ = writeln!(
+                std::io::stdout(),
+                "{}",
+                format.line(item, &screen_name, image_size)
+            );
+        });
+    }
+    Ok(builder)
+}
+
+/// Run every task in the config once
+async fn run(args: &DownloadArgs, config: &Config, ctx: &RunContext) -> Result<()> {
+    let run_started = Instant::now();
+    let started_at = Local::now();
+    let mut run_summary = summary::RunSummary {
+        overwrite: args.overwrite,
+        full_scan: args.full_scan,
+        ..Default::default()
+    };
+    if run_summary.overwrite {
+        warn!("overwrite mode, downloading every item again");
+    }
+    if run_summary.full_scan {
+        info!("full scan, paging every timeline to the end");
+    }
+
+    // Checked every run so long-running watch mode picks up rotated IDs
+    let query_ids = query_ids::resolve(
+        ctx.api.client(),
+        ctx.api.base_url(),
+        &ctx.query_ids_cache,
+        args.refresh_query_ids,
+    )
+    .await;
+    let api = ctx.api.clone().with_query_ids(query_ids);
+
+    // Listed every run so watch mode picks up new follows and members
+    let (discovered, discovery_error) = discovered_tasks(args, config, &api).await;
+    let mut run_error = discovery_error;
+    let configured = config.tasks.iter().map(|t| (t, &Origin::Config));
+    let tasks: Vec<_> = configured
+        .chain(discovered.iter().map(|(t, origin)| (t, origin)))
+        .filter(|(t, _)| args.selects(&t.screen_name))
+        .collect();
+    for user in &args.users {
+        if !tasks
+            .iter()
+            .any(|(t, _)| t.screen_name.eq_ignore_ascii_case(user))
+        {
+            warn!("no task for --user @{}", user);
+        }
+    }
+    if args.dry_run {
+        for (task_config, origin) in &tasks {
+            println!("@{}\t{}", task_config.screen_name, origin);
+        }
+        return match run_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        };
+    }
+    let mut skipped_accounts = 0;
+
+    for (task_config, origin) in tasks {
+        let task_started = Instant::now();
+        // Items are only kept for a report, events still reach the stream
+        let (task_events, item_log) = match &ctx.report {
+            Some(_) => {
+                let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+                let log = tokio::spawn(summary::ItemLog::collect(rx, ctx.events.clone()));
+                (Some(tx), Some(log))
+            }
+            None => (ctx.events.clone(), None),
+        };
+        let result = async {
+            let mut builder = task_builder(args, config, task_config, &api, ctx)?;
+            if let Some(events) = task_events {
+                builder = builder.events(events);
+            }
+            Arc::new(builder.build().await?).execute().await
+        }
+        .instrument(info_span!(
+            logging::TASK_SPAN,
+            screen_name = %task_config.screen_name
+        ))
+        .await;
+
+        // A task that failed part way keeps what it got through
+        let (totals, error) = match result {
+            Ok(totals) => (totals, None),
+            Err(e) => (e.totals().unwrap_or_default(), Some(e)),
+        };
+        // The task and its sender are gone, so the log is complete
+        let items = match item_log {
+            Some(log) => log.await.unwrap_or_default(),
+            None => Default::default(),
+        };
+        run_summary.tasks.push(summary::TaskSummary {
+            screen_name: task_config.screen_name.clone(),
+            totals,
+            elapsed: task_started.elapsed(),
+            error: error.as_ref().map(|e| e.to_string()),
+            items,
+        });
+        if let Some(e) = error {
+            // Only this account's timeline broke off, it counts as a failure
+            if matches!(e, Error::FetchFailed { .. }) {
+                error!("task for @{} failed: {}", task_config.screen_name, e);
+                continue;
+            }
+            // e.g. protected or suspended since they were listed
+            // Later accounts would not get through either
+            if *origin != Origin::Config
+                && !matches!(e, Error::Cancelled { .. } | Error::NetworkDown { .. })
+            {
+                warn!("skipping @{} ({}): {}", task_config.screen_name, origin, e);
+                skipped_accounts += 1;
+                continue;
+            }
+            error!("task for @{} failed: {}", task_config.screen_name, e);
+            run_error = Some(e);
+            break;
+        }
+    }
+    run_summary.elapsed = run_started.elapsed();
+    if let Some(metrics) = &ctx.metrics {
+        metrics.record_run(started_at, run_summary.elapsed);
+    }
+    if skipped_accounts > 0 {
+        warn!(
+            "{} of {} followed accounts and list members skipped",
+            skipped_accounts,
+            discovered.len()
+        );
+    }
+    // Nothing was downloaded to summarize or report
+    if args.list_only.is_some() {
+        return match run_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        };
+    }
+
+    if !args.quiet {
+        eprint!("\n{}", run_summary.to_table());
+    }
+
+    if let Some((target, config_digest)) = &ctx.report {
+        let report = report::Report::new(
+            &run_summary,
+            started_at,
+            Local::now(),
+            config_digest.clone(),
+        );
+        match report.write(&target.path, target.keep_existing) {
+            Ok(path) => info!("report written to {}", path.display()),
+            Err(e) => warn!("failed to write report {}: {}", target.path.display(), e),
+        }
+    }
+
+    // Delivery failures must not change the exit code
+    if let Some(notifications) = &config.notifications
+        && let Err(e) = notify::send(&ctx.http, notifications, &run_summary).await
+    {
+        warn!("failed to send notification: {}", e);
+    }
+    if ctx.desktop_notify {
+        show_desktop_notification(&run_summary).await;
+    }
+
+    match run_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Where an account to download comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Origin {
+    /// A task in the config, which keeps its own settings
+    Config,
+    /// Followed by the `[following]` account
+    Following,
+    /// Member of the `list_members` list
+    List(ListId),
+}
+
+impl std::fmt::Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Origin::Config => f.write_str("config"),
+            Origin::Following => f.write_str("following"),
+            Origin::List(list) => write!(f, "list {list}"),
+        }
+    }
+}
+
+/// Tasks with the global settings for followed accounts and list members
+/// that are not configured, each account once
+///
+/// An account list that cannot be fetched is left out and its error
+/// returned, the other accounts are still downloaded.
+async fn discovered_tasks(
+    args: &DownloadArgs,
+    config: &Config,
+    api: &Api,
+) -> (Vec<(TaskConfig, Origin)>, Option<Error>) {
+    let mut accounts = Vec::new();
+    let mut error = None;
+    if let Some(following) = args.following(config) {
+        match following::followed_accounts(api, &following).await {
+            Ok(followed) => accounts.extend(followed.into_iter().map(|a| (a, Origin::Following))),
+            Err(e) => {
+                error!("failed to list followed accounts: {}", e);
+                error = Some(e);
+            }
+        }
+    }
+    if let Some(list) = &config.list_members {
+        match list::list_members(api, list).await {
+            Ok(members) => {
+                accounts.extend(members.into_iter().map(|a| (a, Origin::List(list.clone()))))
+            }
+            Err(e) => {
+                error!("failed to list members of list {}: {}", list, e);
+                error = Some(e);
+            }
+        }
+    }
+
+    let mut tasks: Vec<(TaskConfig, Origin)> = Vec::new();
+    for (account, origin) in accounts {
+        let known = config
+            .tasks
+            .iter()
+            .chain(tasks.iter().map(|(t, _)| t))
+            .any(|t| t.screen_name.eq_ignore_ascii_case(&account.screen_name));
+        if !known {
+            let task = TaskConfig {
+                screen_name: account.screen_name,
+                ..Default::default()
+            };
+            tasks.push((task, origin));
+        }
+    }
+    (tasks, error)
+}
+
+/// Resolves on Ctrl-C, or SIGTERM on unix
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = sigterm.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Up to 10% of the interval, so multiple instances don't hit the API in lockstep
+fn jitter(interval: Duration) -> Duration {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    interval.mul_f64(f64::from(nanos % 1000) / 10_000.0)
+}
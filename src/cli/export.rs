@@ -0,0 +1,71 @@
+//! `rxd pack` and `rxd gallery`.
+
+use std::path::Path;
+
+use chrono::NaiveDate;
+use indicatif::HumanBytes;
+
+use super::{Globals, account_folders};
+use crate::error::Result;
+use crate::gallery::{self, GalleryOptions};
+use crate::pack::{self, PackOptions};
+
+/// Arguments of `rxd pack`
+pub struct PackArgs<'a> {
+    pub screen_name: &'a str,
+    pub out: &'a Path,
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+    pub allow_mismatch: bool,
+    pub db: Option<&'a Path>,
+    pub dir: Option<&'a Path>,
+}
+
+/// Pack an account's files and print what went into the archive
+pub async fn pack(globals: &Globals, args: &PackArgs<'_>) -> Result<()> {
+    let pool = globals.open_existing_db(args.db).await?;
+    let folder = account_folders(globals.optional_config().as_ref(), args.dir)(args.screen_name);
+    let options = PackOptions::default()
+        .since(args.since)
+        .until(args.until)
+        .allow_mismatch(args.allow_mismatch);
+    let summary = pack::pack(&pool, args.screen_name, &folder, args.out, &options).await?;
+    println!(
+        "{} files and {} captions or posters packed into {} ({}), {} mismatched, {} unverified, {} missing",
+        summary.files,
+        summary.sidecars,
+        args.out.display(),
+        HumanBytes(summary.bytes),
+        summary.mismatched,
+        summary.unverified,
+        summary.missing
+    );
+    Ok(())
+}
+
+/// Arguments of `rxd gallery`
+pub struct GalleryArgs<'a> {
+    pub screen_name: &'a str,
+    pub out: &'a Path,
+    pub page_size: usize,
+    pub db: Option<&'a Path>,
+    pub dir: Option<&'a Path>,
+}
+
+/// Write an account's gallery and print where it is
+pub async fn gallery(globals: &Globals, args: &GalleryArgs<'_>) -> Result<()> {
+    let pool = globals.open_existing_db(args.db).await?;
+    let folder = account_folders(globals.optional_config().as_ref(), args.dir)(args.screen_name);
+    let options = GalleryOptions::default().page_size(args.page_size);
+    let summary = gallery::generate(&pool, args.screen_name, &folder, args.out, &options).await?;
+    println!(
+        "{} files on {} pages in {}, {} with a poster, {} missing, {} pages updated",
+        summary.items,
+        summary.pages,
+        args.out.join(gallery::INDEX).display(),
+        summary.posters,
+        summary.missing,
+        summary.written
+    );
+    Ok(())
+}
@@ -0,0 +1,151 @@
+//! `rxd import-folder` and `rxd import`.
+
+use std::path::Path;
+
+use super::{Globals, account_folders, progress_bar, threads_or_cpus};
+use crate::config::{self, Config};
+use crate::error::{Error, Result};
+use crate::filename::Template;
+use crate::import::twitter_archive::{self, ImportOptions, TwitterArchive};
+use crate::{Api, import};
+
+/// Index the files in `dir` with a progress bar and print how many were new
+pub async fn import_folder(
+    globals: &Globals,
+    db: Option<&Path>,
+    dir: &Path,
+    screen_name: &str,
+    threads: Option<usize>,
+) -> Result<()> {
+    let (pool, _lock) = globals.lock_db(db).await?;
+    if !dir.is_dir() {
+        return Err(Error::Config(format!(
+            "{} is not a directory",
+            dir.display()
+        )));
+    }
+    let bar = progress_bar("hashing");
+    let summary = import::import_folder(
+        &pool,
+        dir,
+        screen_name,
+        globals.hash_algorithm(),
+        threads_or_cpus(threads),
+        |done, total| {
+            bar.set_length(total);
+            bar.set_position(done);
+        },
+    )
+    .await;
+    bar.finish_and_clear();
+    let summary = summary?;
+
+    println!(
+        "{} new files indexed, {} already recorded, {} unreadable, {} found",
+        summary.indexed, summary.known, summary.failed, summary.found
+    );
+    Ok(())
+}
+
+/// Record the entries of a gallery-dl archive and print the counts
+pub async fn import_gallery_dl(
+    globals: &Globals,
+    db: Option<&Path>,
+    archive: &Path,
+    metadata_dir: Option<&Path>,
+    screen_name: Option<&str>,
+) -> Result<()> {
+    let (pool, _lock) = globals.lock_db(db).await?;
+    let summary = import::gallery_dl::import(&pool, archive, metadata_dir, screen_name).await?;
+    println!(
+        "{} entries imported, {} already recorded, {} of other sites ignored",
+        summary.imported, summary.known, summary.ignored
+    );
+    if summary.no_account > 0 {
+        println!(
+            "{} entries left out without a metadata file, pass --metadata-dir or --user",
+            summary.no_account
+        );
+    }
+    Ok(())
+}
+
+/// Import a Twitter data export with a progress bar and print its counts
+pub async fn import_twitter_archive(
+    globals: &Globals,
+    db: Option<&Path>,
+    path: &Path,
+    user: Option<&str>,
+    no_download: bool,
+) -> Result<()> {
+    let (pool, _lock) = globals.lock_db(db).await?;
+    let archive = TwitterArchive::open(path).await?;
+    let screen_name = match user {
+        Some(user) => user.to_string(),
+        None => archive.screen_name().await?.ok_or_else(|| {
+            Error::Config("the export does not name its account, pass --user".to_string())
+        })?,
+    };
+    let config = globals.optional_config();
+    let defaults = Config::default();
+    let settings = config.as_ref().unwrap_or(&defaults);
+    let template = settings
+        .tasks
+        .iter()
+        .find(|task| task.screen_name.eq_ignore_ascii_case(&screen_name))
+        .and_then(|task| task.filename_template.as_ref())
+        .or(settings.filename_template.as_ref())
+        .map(|template| {
+            Template::parse(template).map(|t| t.text_length(settings.filename_text_length))
+        })
+        .transpose()?;
+    let options = ImportOptions {
+        folder: account_folders(config.as_ref(), None)(&screen_name),
+        screen_name,
+        filename_template: template,
+        timezone: settings.timezone,
+        image_size: settings.image_size,
+        hash_algorithm: settings.hash_algorithm,
+        max_file_size: settings.max_file_size.map(|size| size.0),
+        concurrency: settings.concurrent_downloads,
+    };
+    // Media hosts need no account
+    let api = if no_download {
+        None
+    } else {
+        Some(match &config {
+            Some(config) => {
+                let config_dir =
+                    config::config_dir(&config::resolve_path(globals.config.as_deref())?);
+                super::config_api(config, &config_dir)?
+            }
+            None => Api::new("", "")?,
+        })
+    };
+
+    let bar = progress_bar("importing");
+    let summary =
+        twitter_archive::import(&pool, &archive, api.as_ref(), &options, |done, total| {
+            bar.set_length(total);
+            bar.set_position(done);
+        })
+        .await;
+    bar.finish_and_clear();
+    let summary = summary?;
+
+    println!(
+        "{} of {} tweets imported, {} files copied, {} downloads queued, {} skipped",
+        summary.imported, summary.tweets, summary.copied, summary.queued, summary.skipped
+    );
+    if summary.queued > 0 {
+        if no_download {
+            println!("{} left for later runs to download", summary.queued);
+        } else {
+            println!(
+                "{} downloaded, {} failed",
+                summary.downloaded, summary.failed
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,258 @@
+//! Console, log file and per-task log output of the command line.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Write as _};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::config::LogFormat;
+use crate::error::{Error, Result};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Subscriber};
+use tracing_indicatif::IndicatifLayer;
+use tracing_subscriber::filter::{FilterExt, LevelFilter, Targets, filter_fn};
+use tracing_subscriber::fmt::time::LocalTime;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+/// Name of the span wrapping everything one task does, with a
+/// `screen_name` field
+pub const TASK_SPAN: &str = "task";
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Where and how much to log
+pub struct LogOptions<'a> {
+    pub console_level: LevelFilter,
+    pub console_format: LogFormat,
+    /// Stdout is taken by the event stream, keep all logs on stderr
+    pub stdout_reserved: bool,
+    pub log_file: Option<&'a Path>,
+    pub log_file_level: LevelFilter,
+    pub log_file_format: LogFormat,
+    /// Also write each task's logs to `<dir>/<screen_name>.log`
+    pub task_log_dir: Option<&'a Path>,
+}
+
+/// Install the global tracing subscriber
+pub fn init(options: &LogOptions) -> Result<()> {
+    let mut layers: Vec<BoxedLayer> = Vec::new();
+
+    // RUST_LOG takes precedence over -v / -q when set
+    let console_filter = EnvFilter::builder()
+        .with_default_directive(options.console_level.into())
+        .from_env_lossy()
+        // Only there to route events to per-task logs
+        .and(filter_fn(|metadata| metadata.name() != TASK_SPAN));
+
+    match options.console_format {
+        LogFormat::Text => {
+            // Progress bars are filtered separately so they keep working at any verbosity
+            let indicatif_layer = IndicatifLayer::new();
+            layers.push(
+                tracing_subscriber::fmt::layer()
+                    .with_writer(indicatif_layer.get_stderr_writer())
+                    .with_timer(LocalTime::new(time::macros::format_description!(
+                        "[hour]:[minute]:[second]"
+                    )))
+                    .with_filter(console_filter)
+                    .boxed(),
+            );
+            layers.push(indicatif_layer.with_filter(LevelFilter::INFO).boxed());
+        }
+        LogFormat::Json => {
+            // Progress bars make no sense in piped output
+            let layer = tracing_subscriber::fmt::layer()
+                .json()
+                .with_timer(LocalTime::rfc_3339());
+            let layer = if options.stdout_reserved {
+                layer
+                    .with_writer(io::stderr)
+                    .with_filter(console_filter)
+                    .boxed()
+            } else {
+                layer
+                    .with_writer(io::stdout)
+                    .with_filter(console_filter)
+                    .boxed()
+            };
+            layers.push(layer);
+        }
+    }
+
+    // Open the log file up front so a bad path fails loudly instead of logging nowhere
+    if let Some(path) = options.log_file {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| {
+                Error::Config(format!("failed to open log file {}: {}", path.display(), e))
+            })?;
+        let writer = Arc::new(file);
+        let layer = match options.log_file_format {
+            LogFormat::Text => tracing_subscriber::fmt::layer()
+                .with_writer(writer)
+                .with_ansi(false)
+                .with_timer(LocalTime::rfc_3339())
+                .with_filter(options.log_file_level)
+                .boxed(),
+            LogFormat::Json => tracing_subscriber::fmt::layer()
+                .json()
+                .with_writer(writer)
+                .with_timer(LocalTime::rfc_3339())
+                .with_filter(options.log_file_level)
+                .boxed(),
+        };
+        layers.push(layer);
+    }
+
+    if let Some(dir) = options.task_log_dir {
+        std::fs::create_dir_all(dir).map_err(|e| {
+            Error::Config(format!(
+                "failed to create task log directory {}: {}",
+                dir.display(),
+                e
+            ))
+        })?;
+        layers.push(
+            TaskLogLayer {
+                dir: dir.to_path_buf(),
+                files: Mutex::default(),
+            }
+            // Debug output of HTTP libraries would drown the task's own
+            .with_filter(
+                Targets::new()
+                    .with_default(LevelFilter::INFO)
+                    .with_target("rxd", LevelFilter::DEBUG),
+            )
+            .boxed(),
+        );
+    }
+
+    tracing_subscriber::registry().with(layers).init();
+
+    Ok(())
+}
+
+/// Appends events inside a [`TASK_SPAN`] to a plain-text file per account
+struct TaskLogLayer {
+    dir: PathBuf,
+    /// Files opened in this run, by screen name
+    files: Mutex<HashMap<String, Arc<Mutex<File>>>>,
+}
+
+/// Log file of the task a span belongs to
+struct TaskLog(Arc<Mutex<File>>);
+
+impl TaskLogLayer {
+    /// File for `screen_name`, opened with a delimiter line on first use
+    fn file(&self, screen_name: &str) -> io::Result<Arc<Mutex<File>>> {
+        let mut files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(file) = files.get(screen_name) {
+            return Ok(Arc::clone(file));
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(format!("{screen_name}.log")))?;
+        writeln!(
+            file,
+            "===== run started {} =====",
+            chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false)
+        )?;
+        let file = Arc::new(Mutex::new(file));
+        files.insert(screen_name.to_string(), Arc::clone(&file));
+        Ok(file)
+    }
+}
+
+impl<S> Layer<S> for TaskLogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if attrs.metadata().name() != TASK_SPAN {
+            return;
+        }
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        let Some(screen_name) = fields.screen_name else {
+            return;
+        };
+        // Logging about a log file would end up in this layer again
+        match self.file(&screen_name) {
+            Ok(file) => {
+                if let Some(span) = ctx.span(id) {
+                    span.extensions_mut().insert(TaskLog(file));
+                }
+            }
+            Err(e) => eprintln!("failed to open task log for @{screen_name}: {e}"),
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let Some(scope) = ctx.event_scope(event) else {
+            return;
+        };
+        let Some(file) = scope.into_iter().find_map(|span| {
+            span.extensions()
+                .get::<TaskLog>()
+                .map(|log| Arc::clone(&log.0))
+        }) else {
+            return;
+        };
+
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let metadata = event.metadata();
+        let line = format!(
+            "{} {:>5} {}: {}{}\n",
+            chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+            metadata.level(),
+            metadata.target(),
+            fields.message,
+            fields.rest
+        );
+        let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = file.write_all(line.as_bytes());
+    }
+}
+
+/// Fields of an event or span rendered as text
+#[derive(Default)]
+struct Fields {
+    message: String,
+    /// ` key=value` pairs of other fields
+    rest: String,
+    screen_name: Option<String>,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "screen_name" => self.screen_name = Some(value.to_string()),
+            "message" => self.message.push_str(value),
+            name => {
+                let _ = write!(self.rest, " {name}={value}");
+            }
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        match field.name() {
+            "screen_name" => self.screen_name = Some(format!("{value:?}")),
+            "message" => {
+                let _ = write!(self.message, "{value:?}");
+            }
+            name => {
+                let _ = write!(self.rest, " {name}={value:?}");
+            }
+        }
+    }
+}
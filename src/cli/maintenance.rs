@@ -0,0 +1,294 @@
+//! Subcommands that keep the downloads in order: `rxd hash`, `verify`,
+//! `manifest`, `thumbs` and `check-deleted`.
+
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use super::{Globals, account_folders, progress_bar, threads_or_cpus};
+use crate::config::{self, Config};
+use crate::error::Result;
+use crate::thumbs::{ThumbFormat, ThumbOptions};
+use crate::verify::{self, Damage};
+use crate::{checksums, deleted, hash, thumbs};
+
+/// Hash the files recorded without a hash with a progress bar and print
+/// the totals
+pub async fn backfill_hashes(
+    globals: &Globals,
+    db: Option<&Path>,
+    screen_name: Option<&str>,
+    dir: Option<&Path>,
+    threads: Option<usize>,
+) -> Result<()> {
+    let (pool, _lock) = globals.lock_existing_db(db).await?;
+    let config = globals.optional_config();
+    let algorithm = config
+        .as_ref()
+        .map(|config| config.hash_algorithm)
+        .unwrap_or_default();
+
+    let bar = progress_bar("hashing");
+    let summary = hash::backfill::backfill(
+        &pool,
+        screen_name,
+        account_folders(config.as_ref(), dir),
+        algorithm,
+        threads_or_cpus(threads),
+        |done, total| {
+            bar.set_length(total);
+            bar.set_position(done);
+        },
+    )
+    .await;
+    bar.finish_and_clear();
+    let summary = summary?;
+
+    println!(
+        "{} files hashed, {} missing, {} unreadable, {} hashed before",
+        summary.hashed, summary.missing, summary.failed, summary.already_hashed
+    );
+    Ok(())
+}
+
+/// Arguments of `rxd verify`
+pub struct VerifyArgs<'a> {
+    pub screen_name: Option<&'a str>,
+    pub db: Option<&'a Path>,
+    pub dir: Option<&'a Path>,
+    pub threads: Option<usize>,
+    pub repair: bool,
+}
+
+/// Check the recorded files with a progress bar, print the damaged ones
+/// and the totals, and download them again with `repair`
+///
+/// Fails if any damaged files are left.
+pub async fn verify_files(globals: &Globals, args: &VerifyArgs<'_>) -> Result<ExitCode> {
+    // Only repairs write, checking can run alongside a download
+    let (pool, _lock) = if args.repair {
+        globals.lock_existing_db(args.db).await?
+    } else {
+        (globals.open_existing_db(args.db).await?, None)
+    };
+    // Repairs need the credentials, checks only the save paths
+    let config = if args.repair {
+        Some(Config::load(&config::resolve_path(
+            globals.config.as_deref(),
+        )?)?)
+    } else {
+        globals.optional_config()
+    };
+
+    let bar = progress_bar("verifying");
+    let report = verify::verify(
+        &pool,
+        args.screen_name,
+        account_folders(config.as_ref(), args.dir),
+        threads_or_cpus(args.threads),
+        |done, total| {
+            bar.set_length(total);
+            bar.set_position(done);
+        },
+    )
+    .await;
+    bar.finish_and_clear();
+    let report = report?;
+
+    for damaged in &report.damaged {
+        let kind = match damaged.damage {
+            Damage::Missing => "missing",
+            Damage::Mismatch => "corrupt",
+        };
+        println!("{kind}\t{}", damaged.path.display());
+    }
+    let missing = report
+        .damaged
+        .iter()
+        .filter(|d| d.damage == Damage::Missing)
+        .count();
+    println!(
+        "{} intact, {} corrupt, {} missing, {} unreadable, {} with an unknown hash",
+        report.intact,
+        report.damaged.len() - missing,
+        missing,
+        report.unreadable,
+        report.unchecked
+    );
+
+    let mut left = report.damaged.len() as u64;
+    if let Some(config) = config.filter(|_| args.repair)
+        && !report.damaged.is_empty()
+    {
+        let config_dir = config::config_dir(&config::resolve_path(globals.config.as_deref())?);
+        let api = super::config_api(&config, &config_dir)?;
+        let repair_options = verify::RepairOptions {
+            concurrency: config.concurrent_downloads,
+            image_size: config.image_size,
+            hash_algorithm: config.hash_algorithm,
+            max_file_size: config.max_file_size.map(|size| size.0),
+        };
+        let bar = progress_bar("repairing");
+        let summary = verify::repair(
+            &pool,
+            &api,
+            report.damaged,
+            &repair_options,
+            |done, total| {
+                bar.set_length(total);
+                bar.set_position(done);
+            },
+        )
+        .await;
+        bar.finish_and_clear();
+        let summary = summary?;
+
+        println!(
+            "{} repaired, {} unrepairable, {} failed, {} untouched",
+            summary.repaired, summary.unrepairable, summary.failed, report.intact
+        );
+        left -= summary.repaired;
+    }
+    Ok(match left {
+        0 => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    })
+}
+
+/// Arguments of `rxd manifest`
+pub struct ManifestArgs<'a> {
+    pub screen_name: Option<&'a str>,
+    pub root: Option<&'a Path>,
+    pub db: Option<&'a Path>,
+    pub dir: Option<&'a Path>,
+    pub threads: Option<usize>,
+}
+
+/// Write the SHA256SUMS files and print how many lines they have
+pub async fn write_manifests(globals: &Globals, args: &ManifestArgs<'_>) -> Result<()> {
+    let (pool, _lock) = globals.lock_existing_db(args.db).await?;
+    let summary = checksums::write(
+        &pool,
+        args.screen_name,
+        account_folders(globals.optional_config().as_ref(), args.dir),
+        args.root,
+        threads_or_cpus(args.threads),
+    )
+    .await?;
+    println!(
+        "{} lines in {} {} files, {} files hashed, {} missing, {} left out",
+        summary.entries,
+        summary.written,
+        checksums::SHA256SUMS,
+        summary.hashed,
+        summary.missing,
+        summary.skipped
+    );
+    Ok(())
+}
+
+/// Arguments of `rxd thumbs`
+pub struct ThumbArgs<'a> {
+    pub screen_name: Option<&'a str>,
+    pub size: u32,
+    pub format: ThumbFormat,
+    pub videos: bool,
+    pub db: Option<&'a Path>,
+    pub dir: Option<&'a Path>,
+    pub threads: Option<usize>,
+}
+
+/// Make thumbnails with a progress bar, print the images that could not be
+/// read and the totals
+pub async fn make_thumbs(globals: &Globals, args: &ThumbArgs<'_>) -> Result<()> {
+    let (pool, _lock) = globals.lock_existing_db(args.db).await?;
+    let mut options = ThumbOptions::default()
+        .size(args.size)
+        .format(args.format)
+        .videos(args.videos);
+    if let Some(threads) = args.threads {
+        options = options.concurrency(threads);
+    }
+    let bar = progress_bar("thumbnails");
+    let summary = thumbs::make_thumbs(
+        &pool,
+        args.screen_name,
+        account_folders(globals.optional_config().as_ref(), args.dir),
+        &options,
+        |done, total| {
+            bar.set_length(total);
+            bar.set_position(done);
+        },
+    )
+    .await;
+    bar.finish_and_clear();
+    let summary = summary?;
+    for failed in &summary.failed {
+        println!("unreadable\t{}\t{}", failed.path.display(), failed.reason);
+    }
+    println!(
+        "{} thumbnails made, {} up to date, {} unreadable, {} missing",
+        summary.made,
+        summary.up_to_date,
+        summary.failed.len(),
+        summary.missing
+    );
+    Ok(())
+}
+
+/// Look up archived tweets with a progress bar and print what was found
+pub async fn check_deleted(
+    globals: &Globals,
+    db: Option<&Path>,
+    screen_name: Option<&str>,
+    older_than: Option<Duration>,
+    list: bool,
+) -> Result<()> {
+    let (pool, _lock) = globals.lock_existing_db(db).await?;
+    let config_path = config::resolve_path(globals.config.as_deref())?;
+    let config = Config::load(&config_path)?;
+    let api = super::config_api(&config, &config::config_dir(&config_path))?
+        .with_api_concurrency(config.api_concurrency);
+
+    let bar = progress_bar("checking");
+    let summary = deleted::check(
+        &pool,
+        &api,
+        screen_name,
+        older_than,
+        config.api_concurrency,
+        |done, total| {
+            bar.set_length(total);
+            bar.set_position(done);
+        },
+    )
+    .await;
+    bar.finish_and_clear();
+    let summary = summary?;
+
+    if list {
+        for tweet in &summary.newly_deleted {
+            println!(
+                "{}\t@{}\t{}\t{}",
+                tweet.tweet_id,
+                tweet.screen_name,
+                tweet.reason,
+                tweet.filenames.join(" ")
+            );
+        }
+    }
+    println!(
+        "{} live, {} newly deleted, {} withheld, {} failed",
+        summary.live,
+        summary.newly_deleted.len(),
+        summary.withheld,
+        summary.failed
+    );
+    if summary.remaining > 0 {
+        println!(
+            "stopped by the rate limit with {} tweets left, run again later to continue",
+            summary.remaining
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,434 @@
+//! Subcommands that read the database: `rxd info`, `list-users`, `stats`,
+//! `search-text`, `duplicates` and `near-duplicates`.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use indicatif::HumanBytes;
+use tracing::info;
+
+use super::{Globals, account_folders, progress_bar};
+use crate::config::{self, Config};
+use crate::db::{self, UserOrder};
+use crate::error::{Error, Result};
+use crate::profile::Profile;
+use crate::stats::{Stats, StatsOptions};
+use crate::{Api, dedupe, phash, task};
+
+/// Characters of text shown on each side of a search match
+const SNIPPET_CONTEXT: usize = 30;
+
+/// Print the details of an account and what is archived of it, failing if
+/// it is unavailable
+pub async fn user_info(
+    globals: &Globals,
+    screen_name: &str,
+    cookies: Option<(&str, &str)>,
+    json: bool,
+    db: Option<&Path>,
+) -> Result<ExitCode> {
+    let api = match cookies {
+        Some((auth_token, ct0)) => Api::new(auth_token, ct0)?,
+        None => {
+            let config_path = config::resolve_path(globals.config.as_deref())?;
+            let config = Config::load(&config_path)?;
+            super::config_api(&config, &config::config_dir(&config_path))?
+        }
+    };
+    let profile = match api.user_profile(screen_name).await {
+        Ok(profile) => profile,
+        Err(e @ Error::UserUnavailable { .. }) => {
+            eprintln!("{e}");
+            return Ok(ExitCode::FAILURE);
+        }
+        Err(e) => return Err(e),
+    };
+
+    // Only a database given with --db has to exist
+    let pool = match db {
+        Some(_) => Some(globals.open_existing_db(db).await?),
+        None => globals.open_existing_db(None).await.ok(),
+    };
+    let archived = match &pool {
+        Some(pool) => db::user_stats(pool, screen_name).await?,
+        None => None,
+    };
+
+    if json {
+        #[derive(serde::Serialize)]
+        struct Info<'a> {
+            #[serde(flatten)]
+            profile: &'a Profile,
+            archived: Option<db::UserStats>,
+        }
+        let info = Info {
+            profile: &profile,
+            archived,
+        };
+        println!("{}", serde_json::to_string_pretty(&info)?);
+        return Ok(ExitCode::SUCCESS);
+    }
+    println!("@{} ({})", profile.screen_name, profile.name);
+    println!("id         {}", profile.rest_id);
+    println!(
+        "created    {}",
+        profile.created_at.as_deref().unwrap_or("-")
+    );
+    println!("tweets     {}", profile.statuses_count);
+    println!("media      {}", profile.media_count);
+    println!("followers  {}", profile.followers_count);
+    println!(
+        "protected  {}",
+        if profile.protected { "yes" } else { "no" }
+    );
+    println!(
+        "avatar     {}",
+        profile.avatar_url.as_deref().unwrap_or("-")
+    );
+    println!(
+        "banner     {}",
+        profile.banner_url.as_deref().unwrap_or("-")
+    );
+    match (&pool, archived) {
+        (Some(_), Some(stats)) => println!(
+            "archived   {} media, {} hashed, newest tweet {}",
+            stats.media,
+            stats.hashed,
+            stats.newest_tweet.as_deref().unwrap_or("-")
+        ),
+        (Some(_), None) => println!("archived   nothing"),
+        (None, _) => {}
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Print the accounts in the database as a table or JSON
+pub async fn list_users(
+    globals: &Globals,
+    db: Option<&Path>,
+    order: UserOrder,
+    json: bool,
+) -> Result<()> {
+    let pool = globals.open_existing_db(db).await?;
+    let users = db::list_users(&pool, order).await?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&users)?);
+        return Ok(());
+    }
+    let name_width = users
+        .iter()
+        .map(|u| u.screen_name.len() + 1)
+        .chain(["USER".len()])
+        .max()
+        .unwrap_or(0);
+    println!(
+        "{:<name_width$}  {:>7}  {:>7}  NEWEST TWEET",
+        "USER", "MEDIA", "HASHED"
+    );
+    for user in &users {
+        println!(
+            "{:<name_width$}  {:>7}  {:>7}  {}",
+            format!("@{}", user.screen_name),
+            user.media,
+            user.hashed,
+            user.newest_tweet.as_deref().unwrap_or("-")
+        );
+    }
+    Ok(())
+}
+
+/// Arguments of `rxd stats`
+pub struct StatsArgs<'a> {
+    pub screen_name: Option<&'a str>,
+    pub detailed: bool,
+    pub top: usize,
+    pub json: bool,
+    pub db: Option<&'a Path>,
+    pub dir: Option<&'a Path>,
+}
+
+/// Print statistics as tables or JSON, and to stderr how many sizes had to
+/// be read from disk
+pub async fn stats(globals: &Globals, args: &StatsArgs<'_>) -> Result<()> {
+    let pool = globals.open_existing_db(args.db).await?;
+    let options = StatsOptions::default()
+        .detailed(args.detailed)
+        .top(args.top);
+    let stats = crate::stats::collect(
+        &pool,
+        args.screen_name,
+        account_folders(globals.optional_config().as_ref(), args.dir),
+        &options,
+    )
+    .await?;
+    print_stats(&stats, args.json)
+}
+
+fn print_stats(stats: &Stats, json: bool) -> Result<()> {
+    if stats.sized_from_disk > 0 {
+        eprintln!(
+            "{} file sizes were not recorded and were read from disk, later runs will be faster",
+            stats.sized_from_disk
+        );
+    }
+    if stats.unknown_size > 0 {
+        eprintln!(
+            "{} files are not on disk and counted with 0 bytes",
+            stats.unknown_size
+        );
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(stats)?);
+        return Ok(());
+    }
+
+    let name_width = stats
+        .accounts
+        .iter()
+        .map(|a| a.screen_name.len() + 1)
+        .chain(["USER".len()])
+        .max()
+        .unwrap_or(0);
+    println!(
+        "{:<name_width$}  {:>7}  {:>7}  {:>10}",
+        "USER", "TWEETS", "FILES", "SIZE"
+    );
+    for account in &stats.accounts {
+        println!(
+            "{:<name_width$}  {:>7}  {:>7}  {:>10}",
+            format!("@{}", account.screen_name),
+            account.tweets,
+            account.files,
+            HumanBytes(account.bytes).to_string()
+        );
+    }
+
+    let Some(detail) = &stats.detail else {
+        return Ok(());
+    };
+    for (heading, totals) in [("MONTH", &detail.months), ("TYPE", &detail.media_types)] {
+        println!();
+        println!(
+            "{:<7}  {:>7}  {:>7}  {:>10}",
+            heading, "TWEETS", "FILES", "SIZE"
+        );
+        for total in totals {
+            println!(
+                "{:<7}  {:>7}  {:>7}  {:>10}",
+                total.key,
+                total.tweets,
+                total.files,
+                HumanBytes(total.bytes).to_string()
+            );
+        }
+    }
+    if !detail.largest.is_empty() {
+        let id_width = detail
+            .largest
+            .iter()
+            .map(|f| f.tweet_id.len())
+            .chain(["TWEET".len()])
+            .max()
+            .unwrap_or(0);
+        println!();
+        println!("{:>10}  {:<id_width$}  FILE", "SIZE", "TWEET");
+        for file in &detail.largest {
+            println!(
+                "{:>10}  {:<id_width$}  {}",
+                HumanBytes(file.bytes).to_string(),
+                file.tweet_id,
+                file.filename
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Print tweets matching `query` as tab-separated lines
+pub async fn search_text(
+    globals: &Globals,
+    db: Option<&Path>,
+    query: &str,
+    screen_name: Option<&str>,
+) -> Result<()> {
+    let pool = globals.open_existing_db(db).await?;
+    for found in db::search_text(&pool, query, screen_name).await? {
+        println!(
+            "{}\t{}\t@{}\t{}\t{}",
+            found.tweet_id,
+            found.tweet_time,
+            found.screen_name,
+            snippet(&found.full_text, query, SNIPPET_CONTEXT),
+            found.filenames.join(" ")
+        );
+    }
+    Ok(())
+}
+
+/// Part of `text` around the first match of `query`, on a single line
+fn snippet(text: &str, query: &str, context: usize) -> String {
+    let chars: Vec<char> = text
+        .chars()
+        .map(|c| if c.is_whitespace() { ' ' } else { c })
+        .collect();
+    let lower: Vec<char> = chars.iter().flat_map(|c| c.to_lowercase()).collect();
+    let needle: Vec<char> = query
+        .trim()
+        .chars()
+        .flat_map(|c| c.to_lowercase())
+        .collect();
+    // Lowercasing can change the length, positions are only exact without that
+    let start = if lower.len() == chars.len() && !needle.is_empty() {
+        lower
+            .windows(needle.len())
+            .position(|w| w == needle.as_slice())
+            .unwrap_or(0)
+    } else {
+        0
+    };
+    let from = start.saturating_sub(context);
+    let to = (start + needle.len() + context).min(chars.len());
+    let mut snippet: String = chars[from..to].iter().collect();
+    if from > 0 {
+        snippet.insert(0, '…');
+    }
+    if to < chars.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+/// Print the groups of identical files and what linking them saves
+pub async fn list_duplicates(globals: &Globals, db: Option<&Path>) -> Result<()> {
+    let pool = globals.open_existing_db(db).await?;
+    let report = dedupe::DuplicateReport::new(db::duplicate_media(&pool).await?);
+    for media in report.groups.iter().flatten() {
+        println!(
+            "{}\t@{}\t{}\t{}",
+            media.file_hash,
+            media.screen_name,
+            media.filename,
+            media.link.map_or("file", |kind| kind.as_str())
+        );
+    }
+    println!(
+        "{} groups of identical files, {} hardlinks, {} symlinks, {} saved",
+        report.groups.len(),
+        report.hardlinks,
+        report.symlinks,
+        HumanBytes(report.bytes_saved)
+    );
+    Ok(())
+}
+
+/// Arguments of `rxd near-duplicates`
+pub struct NearDuplicateArgs<'a> {
+    pub screen_name: Option<&'a str>,
+    pub max_distance: u32,
+    pub json: bool,
+    pub db: Option<&'a Path>,
+    pub dir: Option<&'a Path>,
+    pub threads: Option<usize>,
+}
+
+/// Hash new images with a progress bar and print the clusters of
+/// near-duplicates
+pub async fn near_duplicates(globals: &Globals, args: &NearDuplicateArgs<'_>) -> Result<()> {
+    let (pool, _lock) = globals.lock_existing_db(args.db).await?;
+    let folder = account_folders(globals.optional_config().as_ref(), args.dir);
+    let mut phash_options = phash::PhashOptions::default();
+    if let Some(threads) = args.threads {
+        phash_options = phash_options.concurrency(threads);
+    }
+    let bar = progress_bar("hashing");
+    let summary = phash::hash_images(
+        &pool,
+        args.screen_name,
+        &folder,
+        &phash_options,
+        |done, total| {
+            bar.set_length(total);
+            bar.set_position(done);
+        },
+    )
+    .await;
+    bar.finish_and_clear();
+    let summary = summary?;
+    for (path, reason) in &summary.failed {
+        eprintln!("unreadable\t{}\t{}", path.display(), reason);
+    }
+    if summary.hashed > 0 || summary.missing > 0 {
+        info!(
+            "hashed {} new images, {} missing",
+            summary.hashed, summary.missing
+        );
+    }
+
+    let clusters = phash::near_duplicates(&pool, args.screen_name, args.max_distance).await?;
+
+    #[derive(serde::Serialize)]
+    struct JsonFile<'a> {
+        path: PathBuf,
+        size: Option<u64>,
+        screen_name: &'a str,
+        tweet_id: &'a str,
+        permalink: String,
+        media_url: &'a str,
+        /// Hex, too large for the numbers of many JSON readers
+        phash: String,
+        /// Bits it differs in from the first of the cluster
+        distance: u32,
+    }
+    let mut json_clusters = Vec::new();
+    for (number, cluster) in clusters.iter().enumerate() {
+        let first = cluster.media[0].phash;
+        let files: Vec<JsonFile> = cluster
+            .media
+            .iter()
+            .map(|media| {
+                let path = folder(&media.screen_name).join(&media.filename);
+                let size = media
+                    .file_size
+                    .or_else(|| std::fs::metadata(&path).ok().map(|m| m.len()));
+                JsonFile {
+                    path,
+                    size,
+                    screen_name: &media.screen_name,
+                    tweet_id: &media.tweet_id,
+                    permalink: task::permalink(&media.screen_name, &media.tweet_id),
+                    media_url: &media.media_url,
+                    phash: format!("{:016x}", media.phash),
+                    distance: phash::distance(first, media.phash),
+                }
+            })
+            .collect();
+        if args.json {
+            json_clusters.push(files);
+            continue;
+        }
+        println!(
+            "cluster {}: {} images, up to {} bits apart",
+            number + 1,
+            files.len(),
+            cluster.max_distance
+        );
+        for file in &files {
+            let size = file
+                .size
+                .map(|size| HumanBytes(size).to_string())
+                .unwrap_or_else(|| "?".to_string());
+            println!("  {}\t{}\t{}", file.path.display(), size, file.permalink);
+        }
+    }
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&json_clusters)?);
+    } else {
+        println!(
+            "{} clusters of {} images",
+            clusters.len(),
+            clusters.iter().map(|c| c.media.len()).sum::<usize>()
+        );
+    }
+    Ok(())
+}
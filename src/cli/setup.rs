@@ -0,0 +1,52 @@
+//! `rxd doctor` and `rxd check-auth`.
+
+use std::path::Path;
+use std::process::ExitCode;
+
+use crate::api::Api;
+use crate::config::{self, Config};
+use crate::doctor;
+use crate::error::{Error, Result};
+
+/// Print the setup checks for the config at `config_path`, failing if any
+/// check fails
+pub async fn doctor(config_path: Option<&Path>) -> Result<ExitCode> {
+    let config_path = config::resolve_path(config_path)?;
+    let report = doctor::run(&config_path, doctor::MEDIA_HOST).await;
+    print!("{}", report.to_text());
+    Ok(if report.has_failures() {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Print whose credentials these are, or why they are rejected
+///
+/// Cookies given on the command line are checked against the default host
+/// without reading a config.
+pub async fn check_auth(
+    config_path: Option<&Path>,
+    cookies: Option<(&str, &str)>,
+) -> Result<ExitCode> {
+    let api = match cookies {
+        Some((auth_token, ct0)) => Api::new(auth_token, ct0)?,
+        None => {
+            let config_path = config::resolve_path(config_path)?;
+            let config = Config::load(&config_path)?;
+            super::config_api(&config, &config::config_dir(&config_path))?
+        }
+    };
+
+    match api.verify_credentials().await {
+        Ok(screen_name) => {
+            println!("authenticated as @{screen_name}");
+            Ok(ExitCode::SUCCESS)
+        }
+        Err(Error::Auth(failure)) => {
+            eprintln!("credentials rejected: {failure}\nfix: {}", failure.fix());
+            Ok(ExitCode::FAILURE)
+        }
+        Err(e) => Err(e),
+    }
+}
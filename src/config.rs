@@ -0,0 +1,409 @@
+use std::env;
+use std::net::SocketAddr;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::api::PageSize;
+use crate::convert::PostProcess;
+use crate::dedupe::Dedupe;
+use crate::error::{Error, Result};
+use crate::filename::Timezone;
+use crate::filter::{FileSize, TextPattern};
+use crate::following::FollowingConfig;
+use crate::hash::HashAlgorithm;
+use crate::list::ListId;
+use crate::notify::NotificationConfig;
+use crate::rotation::Credentials;
+use crate::screen_name;
+use crate::task::ImageSize;
+
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Database used when `database` is not set, next to the config file
+const DATABASE_FILE_NAME: &str = "rxd.db";
+
+/// Top-level config file contents
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    /// Required unless `guest` is set
+    #[serde(default)]
+    pub auth_token: String,
+    #[serde(default)]
+    pub ct0: String,
+    /// Download public media without an account, see
+    /// [`Api::guest`](crate::api::Api::guest)
+    #[serde(default)]
+    pub guest: bool,
+    /// Send GraphQL requests from whichever of the accounts has the most
+    /// left of its rate limit, see [`rotation`](crate::rotation)
+    #[serde(default)]
+    pub rotate_accounts: bool,
+    /// Accounts rotated between next to `auth_token` and `ct0`, only used
+    /// with `rotate_accounts`
+    #[serde(default)]
+    pub accounts: Vec<Credentials>,
+    #[serde(default = "default_concurrent_downloads")]
+    pub concurrent_downloads: usize,
+    /// Size of downloaded images, can be overridden per task
+    #[serde(default)]
+    pub image_size: ImageSize,
+    /// Template for file names, see [`Template`](crate::filename::Template),
+    /// can be overridden per task
+    #[serde(default)]
+    pub filename_template: Option<String>,
+    /// Leave out retweets, can be overridden per task
+    #[serde(default = "default_exclude_retweets")]
+    pub exclude_retweets: bool,
+    /// Timezone of dates in file names
+    #[serde(default)]
+    pub timezone: Timezone,
+    /// Skip files larger than this, e.g. "50MB"
+    #[serde(default)]
+    pub max_file_size: Option<FileSize>,
+    /// Highest video bitrate to download, in bits per second
+    #[serde(default)]
+    pub max_video_bitrate: Option<u64>,
+    /// Retry videos whose URL fails with the variant the syndication API
+    /// offers, for public tweets
+    #[serde(default)]
+    pub syndication_rescue: bool,
+    /// Hash of new downloads, files keep verifying with the one they were
+    /// recorded with
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+    /// Maximum length of the `{text}` placeholder, in characters
+    #[serde(default = "default_filename_text_length")]
+    pub filename_text_length: usize,
+    /// Host of the web client, defaults to [`DEFAULT_BASE_URL`](crate::api::DEFAULT_BASE_URL)
+    #[serde(default)]
+    pub api_base_url: Option<String>,
+    /// PEM file with extra root certificates, relative to the config file
+    #[serde(default)]
+    pub ca_cert: Option<PathBuf>,
+    /// Trust the operating system's certificate store
+    #[serde(default = "default_tls_os_roots")]
+    pub tls_os_roots: bool,
+    /// Database file, relative to the config file, see [`Config::database_path`]
+    #[serde(default)]
+    pub database: Option<String>,
+    /// Console log format
+    #[serde(default)]
+    pub log_format: Option<LogFormat>,
+    /// Also write logs to this file
+    #[serde(default)]
+    pub log_file: Option<PathBuf>,
+    /// Log level for `log_file`, e.g. "debug"
+    #[serde(default)]
+    pub log_file_level: Option<String>,
+    /// Log format for `log_file`
+    #[serde(default)]
+    pub log_file_format: Option<LogFormat>,
+    /// Also write each task's debug logs to `logs/<screen_name>.log` next
+    /// to the config file
+    #[serde(default)]
+    pub per_task_logs: bool,
+    /// Command run after every new download, see [`PostDownloadHook`](crate::hook::PostDownloadHook)
+    #[serde(default)]
+    pub post_download_hook: Option<String>,
+    #[serde(default = "default_hook_timeout_secs")]
+    pub hook_timeout_secs: u64,
+    /// Maximum number of hooks running at once
+    #[serde(default = "default_hook_concurrency")]
+    pub hook_concurrency: usize,
+    /// Cron expression used by watch mode instead of an interval
+    #[serde(default)]
+    pub schedule: Option<String>,
+    #[serde(default)]
+    pub notifications: Option<NotificationConfig>,
+    /// Show a desktop notification when a run finishes
+    #[serde(default)]
+    pub desktop_notify: bool,
+    /// What to do with downloads identical to a recorded file
+    #[serde(default)]
+    pub dedupe: Dedupe,
+    /// Write the tweet text of every download to a `.txt` caption file
+    #[serde(default)]
+    pub write_text_sidecars: bool,
+    /// One caption file per tweet, `<tweet_id>.txt`, instead of one per file
+    #[serde(default)]
+    pub text_sidecars_per_tweet: bool,
+    /// Add the tweet's permalink to caption files
+    #[serde(default)]
+    pub text_sidecar_permalink: bool,
+    /// Embed the tweet's date, author, text and link in downloaded images
+    #[serde(default)]
+    pub embed_metadata: bool,
+    /// Save the poster image of every video next to it
+    #[serde(default)]
+    pub save_video_thumbnails: bool,
+    /// Save a frame about a second into every video as its thumbnail with
+    /// ffmpeg, the poster when ffmpeg is missing or fails
+    #[serde(default)]
+    pub extract_video_frames: bool,
+    #[serde(default = "default_ffmpeg_timeout_secs")]
+    pub ffmpeg_timeout_secs: u64,
+    /// Maximum number of ffmpeg processes extracting frames at once
+    #[serde(default = "default_ffmpeg_concurrency")]
+    pub ffmpeg_concurrency: usize,
+    /// Convert downloaded images with ffmpeg, e.g.
+    /// `{ convert = "webp", quality = 90, only = ["png"] }`
+    #[serde(default)]
+    pub post_process: Option<PostProcess>,
+    /// Keep every raw API response in `<save_path>/_raw`
+    #[serde(default)]
+    pub archive_responses: bool,
+    /// Gzip archived responses, needs the `gzip` command
+    #[serde(default)]
+    pub archive_compress: bool,
+    /// Delete archived responses older than this many days
+    #[serde(default)]
+    pub archive_max_age_days: Option<u32>,
+    /// GraphQL requests in flight at once across all tasks, separate from
+    /// `concurrent_downloads`
+    #[serde(default = "default_api_concurrency")]
+    pub api_concurrency: usize,
+    /// Timeline entries requested per page, 1 to 100
+    #[serde(default)]
+    pub page_size: Option<PageSize>,
+    /// Stop a task after this many timeline pages in a row downloaded
+    /// nothing new, ignored with `--force`
+    #[serde(default)]
+    pub stop_after_known_pages: Option<NonZeroU32>,
+    /// Try media found gone (404 or 410) this many days ago or longer
+    /// again, skipped until then
+    #[serde(default)]
+    pub recheck_gone_after_days: Option<u32>,
+    /// How long a task waits for a lost network connection to come back
+    /// before failing
+    #[serde(default = "default_max_outage_secs")]
+    pub max_outage_secs: u64,
+    /// Longest wait for a media host that answers 429 or 503 with
+    /// `Retry-After` before retrying the download
+    #[serde(default = "default_max_retry_after_secs")]
+    pub max_retry_after_secs: u64,
+    /// JSON report written after every run, see [`Config::report_path`]
+    #[serde(default)]
+    pub report: Option<String>,
+    /// Keep earlier reports instead of overwriting them
+    #[serde(default)]
+    pub keep_reports: bool,
+    /// Also download every account followed, see [`FollowingConfig`]
+    #[serde(default)]
+    pub following: Option<FollowingConfig>,
+    /// Also download every member of this list, by ID or link
+    #[serde(default)]
+    pub list_members: Option<ListId>,
+    /// Address watch mode serves Prometheus metrics on, e.g.
+    /// "127.0.0.1:9184"
+    #[serde(default)]
+    pub metrics_listen: Option<SocketAddr>,
+    #[serde(default)]
+    pub tasks: Vec<TaskConfig>,
+}
+
+fn default_concurrent_downloads() -> usize {
+    4
+}
+
+fn default_api_concurrency() -> usize {
+    2
+}
+
+fn default_filename_text_length() -> usize {
+    crate::filename::DEFAULT_TEXT_LENGTH
+}
+
+fn default_exclude_retweets() -> bool {
+    true
+}
+
+fn default_tls_os_roots() -> bool {
+    true
+}
+
+fn default_hook_timeout_secs() -> u64 {
+    60
+}
+
+fn default_hook_concurrency() -> usize {
+    2
+}
+
+fn default_ffmpeg_timeout_secs() -> u64 {
+    30
+}
+
+fn default_ffmpeg_concurrency() -> usize {
+    2
+}
+
+fn default_max_outage_secs() -> u64 {
+    30 * 60
+}
+
+fn default_max_retry_after_secs() -> u64 {
+    5 * 60
+}
+
+/// A single account to download
+#[derive(Debug, Default, Deserialize)]
+pub struct TaskConfig {
+    /// Normalized from `@handle` or a profile URL, see [`screen_name`]
+    #[serde(deserialize_with = "screen_name::deserialize")]
+    pub screen_name: String,
+    #[serde(default)]
+    pub save_path: Option<String>,
+    /// Overrides the global `image_size`
+    #[serde(default)]
+    pub image_size: Option<ImageSize>,
+    /// Overrides the global `filename_template`
+    #[serde(default)]
+    pub filename_template: Option<String>,
+    /// Overrides the global `exclude_retweets`
+    #[serde(default)]
+    pub exclude_retweets: Option<bool>,
+    /// Only download tweets whose text matches one of these patterns
+    #[serde(default)]
+    pub text_include: Vec<TextPattern>,
+    /// Skip tweets whose text matches one of these patterns, takes
+    /// precedence over `text_include`
+    #[serde(default)]
+    pub text_exclude: Vec<TextPattern>,
+    /// Skip tweets with fewer likes at the time they are fetched
+    #[serde(default)]
+    pub min_favorites: u64,
+    /// Skip tweets with fewer retweets at the time they are fetched
+    #[serde(default)]
+    pub min_retweets: u64,
+}
+
+/// Output format of a log destination
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, including spans
+    Json,
+}
+
+/// Settings of an empty config file
+impl Default for Config {
+    fn default() -> Self {
+        toml::from_str("").expect("every setting has a default")
+    }
+}
+
+impl Config {
+    /// Read and parse a config file
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path)?;
+        toml::from_str(&raw).map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Database file of a config in `config_dir`
+    ///
+    /// `~` at the start of `database` is the home directory and
+    /// [`db::MEMORY`](crate::db::MEMORY) keeps the database in memory.
+    /// Without `database` it is `rxd.db` next to the config file.
+    pub fn database_path(&self, config_dir: &Path) -> PathBuf {
+        match self.database.as_deref() {
+            None => config_dir.join(DATABASE_FILE_NAME),
+            Some(crate::db::MEMORY) => PathBuf::from(crate::db::MEMORY),
+            Some(path) => config_dir.join(expand_home(path)),
+        }
+    }
+
+    /// Fail unless there are credentials or `guest` mode is on
+    pub fn require_credentials(&self, guest: bool) -> Result<()> {
+        if guest || self.guest || (!self.auth_token.is_empty() && !self.ct0.is_empty()) {
+            return Ok(());
+        }
+        Err(Error::Config(
+            "auth_token and ct0 are required, or set guest = true for public accounts".to_string(),
+        ))
+    }
+
+    /// Run report file of a config in `config_dir`, relative to it with `~`
+    /// as the home directory
+    pub fn report_path(&self, config_dir: &Path) -> Option<PathBuf> {
+        self.report
+            .as_deref()
+            .map(|path| config_dir.join(expand_home(path)))
+    }
+}
+
+/// Directory of a config file, relative paths in it are resolved against
+pub fn config_dir(config_path: &Path) -> PathBuf {
+    config_path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .map(|p| p.to_path_buf())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// `path` with a leading `~` replaced by the home directory
+pub fn expand_home(path: &str) -> PathBuf {
+    let rest = match path.strip_prefix('~') {
+        Some("") => "",
+        Some(rest) if rest.starts_with(['/', '\\']) => &rest[1..],
+        _ => return PathBuf::from(path),
+    };
+    let home = env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .filter(|h| !h.is_empty());
+    match home {
+        Some(home) => PathBuf::from(home).join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+/// Platform config directory for rxd, e.g. `~/.config/rxd` on Linux
+fn platform_config_dir() -> Option<PathBuf> {
+    let base = if cfg!(target_os = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|h| PathBuf::from(h).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+    };
+    base.map(|b| b.join("rxd"))
+}
+
+/// Locations searched for a config file when none is given, in priority order
+pub fn candidate_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Some(dir) = platform_config_dir() {
+        paths.push(dir.join(CONFIG_FILE_NAME));
+    }
+    paths.push(PathBuf::from(CONFIG_FILE_NAME));
+    paths
+}
+
+/// Resolve the config file path, preferring an explicit path over discovery
+pub fn resolve_path(explicit: Option<&Path>) -> Result<PathBuf> {
+    if let Some(path) = explicit {
+        return Ok(path.to_path_buf());
+    }
+
+    let candidates = candidate_paths();
+    if let Some(found) = candidates.iter().find(|p| p.is_file()) {
+        return Ok(found.clone());
+    }
+
+    let checked = candidates
+        .iter()
+        .map(|p| format!("  {}", p.display()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Err(Error::Config(format!(
+        "no config file found, checked:\n{checked}"
+    )))
+}
@@ -0,0 +1,236 @@
+//! Conversion of downloaded images to a smaller format, the `post_process`
+//! step of the config.
+//!
+//! Matching images are converted with `ffmpeg` right after they are
+//! downloaded. The converted file is written next to the original, read
+//! back to check it decodes, and only then moved into place and the
+//! original removed; the download is recorded under the new name, hash and
+//! size. Images that cannot be converted are kept as downloaded.
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::process::Command;
+use tracing::debug;
+
+use crate::error::{Error, Result};
+use crate::hash::HashAlgorithm;
+
+/// Longest an `ffmpeg` run of a conversion may take
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Format images are converted to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConvertFormat {
+    #[default]
+    Webp,
+}
+
+impl ConvertFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ConvertFormat::Webp => "webp",
+        }
+    }
+
+    /// `ffmpeg` encoder arguments, lossless at quality 100
+    fn codec(self, quality: u8) -> Vec<String> {
+        match self {
+            ConvertFormat::Webp if quality >= 100 => ["-c:v", "libwebp", "-lossless", "1"]
+                .map(String::from)
+                .to_vec(),
+            ConvertFormat::Webp => vec![
+                "-c:v".to_string(),
+                "libwebp".to_string(),
+                "-quality".to_string(),
+                quality.to_string(),
+            ],
+        }
+    }
+}
+
+/// `post_process` of the config: which downloaded images are converted
+/// to what
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PostProcess {
+    pub convert: ConvertFormat,
+    /// 0 to 100, 100 is lossless
+    #[serde(default = "default_quality")]
+    pub quality: u8,
+    /// Extensions of the images converted, ignoring case
+    #[serde(default = "default_only")]
+    pub only: Vec<String>,
+    /// The `ffmpeg` program to run
+    #[serde(skip, default = "default_ffmpeg")]
+    pub ffmpeg: PathBuf,
+}
+
+fn default_quality() -> u8 {
+    90
+}
+
+fn default_only() -> Vec<String> {
+    vec!["png".to_string()]
+}
+
+fn default_ffmpeg() -> PathBuf {
+    PathBuf::from("ffmpeg")
+}
+
+impl PostProcess {
+    pub fn new(convert: ConvertFormat) -> Self {
+        Self {
+            convert,
+            quality: default_quality(),
+            only: default_only(),
+            ffmpeg: default_ffmpeg(),
+        }
+    }
+
+    /// 0 to 100, 100 is lossless
+    pub fn quality(mut self, quality: u8) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    /// Extensions of the images converted, ignoring case
+    pub fn only(mut self, only: Vec<String>) -> Self {
+        self.only = only;
+        self
+    }
+
+    /// The `ffmpeg` program to run
+    pub fn ffmpeg(mut self, ffmpeg: impl Into<PathBuf>) -> Self {
+        self.ffmpeg = ffmpeg.into();
+        self
+    }
+
+    /// Whether the image at `path` is converted
+    pub fn matches(&self, path: &Path) -> bool {
+        let Some(extension) = path.extension().and_then(|e| e.to_str()) else {
+            return false;
+        };
+        !extension.eq_ignore_ascii_case(self.convert.extension())
+            && self
+                .only
+                .iter()
+                .any(|only| only.trim_start_matches('.').eq_ignore_ascii_case(extension))
+    }
+
+    /// Convert the image at `source` with `ffmpeg` and remove it, returning
+    /// the converted file with its hash and size
+    ///
+    /// A file already at the converted name is only replaced with
+    /// `replace`. `source` is left as it was when anything fails.
+    pub async fn convert(
+        &self,
+        source: &Path,
+        replace: bool,
+        algorithm: HashAlgorithm,
+    ) -> Result<Converted> {
+        let target = source.with_extension(self.convert.extension());
+        if !replace && tokio::fs::try_exists(&target).await? {
+            return Err(Error::Conversion(format!(
+                "{} already exists",
+                target.display()
+            )));
+        }
+        let mut partial = target.as_os_str().to_owned();
+        partial.push(".part");
+        let partial = PathBuf::from(partial);
+
+        let result = self.write(source, &partial, algorithm).await;
+        let (hash, size) = match result {
+            Ok(written) => written,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&partial).await;
+                return Err(e);
+            }
+        };
+        tokio::fs::rename(&partial, &target).await?;
+        tokio::fs::remove_file(source).await?;
+        debug!("converted {} to {}", source.display(), target.display());
+        Ok(Converted {
+            path: target,
+            hash,
+            size,
+        })
+    }
+
+    /// Write the converted `source` to `partial` and check it decodes
+    async fn write(
+        &self,
+        source: &Path,
+        partial: &Path,
+        algorithm: HashAlgorithm,
+    ) -> Result<(String, u64)> {
+        let ffmpeg = &self.ffmpeg;
+        let mut encode = Command::new(ffmpeg);
+        encode
+            .args(["-nostdin", "-loglevel", "error", "-y", "-i"])
+            .arg(source)
+            .args(self.convert.codec(self.quality))
+            .args(["-frames:v", "1", "-update", "1", "-f", "image2"])
+            .arg(partial);
+        run(ffmpeg, encode).await?;
+
+        let mut decode = Command::new(ffmpeg);
+        decode
+            .args(["-nostdin", "-loglevel", "error", "-i"])
+            .arg(partial)
+            .args(["-f", "null", "-"]);
+        run(ffmpeg, decode)
+            .await
+            .map_err(|e| Error::Conversion(format!("converted file does not decode: {e}")))?;
+
+        let size = tokio::fs::metadata(partial).await?.len();
+        if size == 0 {
+            return Err(Error::Conversion("ffmpeg wrote an empty file".to_string()));
+        }
+        let path = partial.to_path_buf();
+        let hash = tokio::task::spawn_blocking(move || algorithm.hash_file(&path))
+            .await
+            .map_err(std::io::Error::other)??;
+        Ok((hash, size))
+    }
+}
+
+/// An image converted by [`PostProcess::convert`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Converted {
+    pub path: PathBuf,
+    pub hash: String,
+    pub size: u64,
+}
+
+/// Run an `ffmpeg` command, failing with what it printed
+async fn run(ffmpeg: &Path, mut command: Command) -> Result<()> {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    let output = match tokio::time::timeout(TIMEOUT, command.output()).await {
+        Err(_) => return Err(Error::Timeout(TIMEOUT)),
+        Ok(Err(e)) if e.kind() == ErrorKind::NotFound => {
+            return Err(Error::Config(format!(
+                "post_process needs {}, which is not installed",
+                ffmpeg.display()
+            )));
+        }
+        Ok(output) => output?,
+    };
+    if !output.status.success() {
+        let reason = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(Error::Conversion(if reason.is_empty() {
+            format!("ffmpeg failed with {}", output.status)
+        } else {
+            reason
+        }));
+    }
+    Ok(())
+}
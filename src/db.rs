@@ -1,16 +1,61 @@
+use std::collections::HashSet;
 use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
 
-use sha2::{Digest, Sha256};
+use clap::ValueEnum;
+use serde::Serialize;
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
 use sqlx::{Row, SqlitePool};
 use tokio::fs;
-use tracing::{info, instrument};
+use tracing::{debug, info, instrument};
+
+use crate::dedupe::{Link, LinkKind};
+use crate::error::{Error, Result};
+use crate::hash::HashAlgorithm;
+use crate::task::Engagement;
+
+/// Version of the schema [`init_db`] migrates to, stored as `user_version`
+///
+/// Bump it whenever a migration is added.
+pub const SCHEMA_VERSION: i64 = 15;
+
+/// Path of a database that is kept in memory, e.g. for tests
+pub const MEMORY: &str = ":memory:";
+
+/// Open the database at `path`, creating it and its parent directories
+///
+/// [`MEMORY`] opens a database that lives as long as the pool. Fails with
+/// [`Error::DatabaseOpen`] if the file cannot be created or written.
+#[instrument(skip_all)]
+pub async fn open(path: &Path) -> Result<SqlitePool> {
+    if path.as_os_str() == MEMORY {
+        return init_memory_db().await;
+    }
+    let unopenable = |reason: String| Error::DatabaseOpen {
+        path: path.to_path_buf(),
+        reason,
+    };
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| unopenable(e.to_string()))?;
+    }
+    // SQLite would open it read-only and only fail on the first write
+    if let Ok(metadata) = fs::metadata(path).await
+        && metadata.permissions().readonly()
+    {
+        return Err(unopenable("the file is read-only".to_string()));
+    }
+    init_db(path).await.map_err(|e| match e {
+        Error::Database(e) => unopenable(e.to_string()),
+        e => e,
+    })
+}
 
 /// Initialize database connection pool and create tables
 #[instrument(skip_all)]
-pub async fn init_db(
-    db_path: &Path,
-) -> Result<SqlitePool, Box<dyn std::error::Error + Send + Sync>> {
+pub async fn init_db(db_path: &Path) -> Result<SqlitePool> {
     let options = SqliteConnectOptions::new()
         .filename(db_path)
         .create_if_missing(true);
@@ -19,7 +64,26 @@ pub async fn init_db(
         .max_connections(5)
         .connect_with(options)
         .await?;
+    migrate(&pool).await?;
+
+    info!("database initialized at {}", db_path.display());
+    Ok(pool)
+}
 
+/// Database that lives as long as the pool, e.g. for tests
+#[instrument(skip_all)]
+pub async fn init_memory_db() -> Result<SqlitePool> {
+    // Every connection would get a database of its own
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(SqliteConnectOptions::from_str("sqlite::memory:")?)
+        .await?;
+    migrate(&pool).await?;
+    Ok(pool)
+}
+
+/// Create tables and bring older databases up to [`SCHEMA_VERSION`]
+async fn migrate(pool: &SqlitePool) -> Result<()> {
     // Create tweets table
     sqlx::query(
         r#"
@@ -33,7 +97,7 @@ pub async fn init_db(
         )
         "#,
     )
-    .execute(&pool)
+    .execute(pool)
     .await?;
 
     // Create media table
@@ -50,19 +114,184 @@ pub async fn init_db(
         )
         "#,
     )
-    .execute(&pool)
+    .execute(pool)
+    .await?;
+
+    // Columns added after the first release
+    add_column_if_missing(pool, "media", "image_size", "TEXT").await?;
+    add_column_if_missing(pool, "tweets", "favorite_count", "INTEGER").await?;
+    add_column_if_missing(pool, "tweets", "retweet_count", "INTEGER").await?;
+    add_column_if_missing(pool, "media", "imported", "INTEGER NOT NULL DEFAULT 0").await?;
+    add_column_if_missing(pool, "tweets", "deleted_at", "TEXT").await?;
+    add_column_if_missing(pool, "tweets", "deleted_reason", "TEXT").await?;
+    add_column_if_missing(pool, "media", "etag", "TEXT").await?;
+    add_column_if_missing(pool, "media", "last_modified", "TEXT").await?;
+    // NULL for hashes made before it was recorded, all SHA-256
+    add_column_if_missing(pool, "media", "hash_algorithm", "TEXT").await?;
+    // Exact URL fetched, `media_url` stays the canonical key
+    add_column_if_missing(pool, "media", "download_url", "TEXT").await?;
+    // Set when the file is a link to an identical one, see `dedupe`
+    add_column_if_missing(pool, "media", "linked_to", "TEXT").await?;
+    add_column_if_missing(pool, "media", "link_kind", "TEXT").await?;
+    add_column_if_missing(pool, "media", "link_size", "INTEGER").await?;
+    // Poster image saved next to a video
+    add_column_if_missing(pool, "media", "thumbnail", "TEXT").await?;
+    // Of videos and GIFs, NULL for photos
+    add_column_if_missing(pool, "media", "duration_ms", "INTEGER").await?;
+    add_column_if_missing(pool, "media", "width", "INTEGER").await?;
+    add_column_if_missing(pool, "media", "height", "INTEGER").await?;
+    add_column_if_missing(pool, "tweets", "reply_count", "INTEGER").await?;
+    add_column_if_missing(pool, "tweets", "quote_count", "INTEGER").await?;
+    add_column_if_missing(pool, "tweets", "view_count", "INTEGER").await?;
+    // Bytes on disk, NULL for files downloaded before it was recorded
+    add_column_if_missing(pool, "media", "file_size", "INTEGER").await?;
+    // Set when the server answered 404 or 410 for the media
+    add_column_if_missing(pool, "media", "gone_at", "TEXT").await?;
+    // Last `rxd check-deleted` of the tweet and what it found
+    add_column_if_missing(pool, "tweets", "checked_at", "TEXT").await?;
+    add_column_if_missing(pool, "tweets", "check_status", "TEXT").await?;
+    // dHash of an image's pixels for `rxd near-duplicates`, the 64 bits as
+    // a signed integer
+    add_column_if_missing(pool, "media", "phash", "INTEGER").await?;
+
+    // Tweets found gone before they were archived
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS tombstones (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            entry_id TEXT NOT NULL,
+            tweet_id TEXT,
+            screen_name TEXT NOT NULL,
+            reason TEXT NOT NULL,
+            first_seen TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (screen_name, entry_id)
+        )
+        "#,
+    )
+    .execute(pool)
     .await?;
 
     // Create indexes
     sqlx::query("CREATE INDEX IF NOT EXISTS idx_tweets_screen_name ON tweets(screen_name)")
-        .execute(&pool)
+        .execute(pool)
         .await?;
     sqlx::query("CREATE INDEX IF NOT EXISTS idx_media_tweet_id ON media(tweet_id)")
-        .execute(&pool)
+        .execute(pool)
         .await?;
 
-    info!("database initialized at {}", db_path.display());
-    Ok(pool)
+    // Searches fall back to LIKE without it
+    if let Err(e) = create_text_index(pool).await {
+        debug!("full-text index unavailable: {}", e);
+    }
+
+    sqlx::query(&format!("PRAGMA user_version = {SCHEMA_VERSION}"))
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Schema version of an existing database, opened read-only so nothing is
+/// migrated
+#[instrument(skip_all)]
+pub async fn schema_version(db_path: &Path) -> Result<i64> {
+    let options = SqliteConnectOptions::new()
+        .filename(db_path)
+        .read_only(true);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await?;
+    let version = sqlx::query_scalar("PRAGMA user_version")
+        .fetch_one(&pool)
+        .await?;
+    pool.close().await;
+    Ok(version)
+}
+
+/// FTS5 index over `tweets.full_text`, kept in sync by triggers
+///
+/// The trigram tokenizer matches substrings in any script, texts without
+/// spaces between words included.
+async fn create_text_index(pool: &SqlitePool) -> Result<()> {
+    if has_text_index(pool).await? {
+        return Ok(());
+    }
+    sqlx::query(
+        r#"
+        CREATE VIRTUAL TABLE tweets_fts USING fts5(
+            full_text,
+            content = 'tweets',
+            content_rowid = 'id',
+            tokenize = 'trigram'
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS tweets_fts_insert AFTER INSERT ON tweets BEGIN
+            INSERT INTO tweets_fts (rowid, full_text) VALUES (new.id, new.full_text);
+        END
+        "#,
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS tweets_fts_delete AFTER DELETE ON tweets BEGIN
+            INSERT INTO tweets_fts (tweets_fts, rowid, full_text)
+            VALUES ('delete', old.id, old.full_text);
+        END
+        "#,
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS tweets_fts_update AFTER UPDATE OF full_text ON tweets BEGIN
+            INSERT INTO tweets_fts (tweets_fts, rowid, full_text)
+            VALUES ('delete', old.id, old.full_text);
+            INSERT INTO tweets_fts (rowid, full_text) VALUES (new.id, new.full_text);
+        END
+        "#,
+    )
+    .execute(pool)
+    .await?;
+    // Index tweets recorded before the index existed
+    sqlx::query("INSERT INTO tweets_fts (tweets_fts) VALUES ('rebuild')")
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+async fn has_text_index(pool: &SqlitePool) -> Result<bool> {
+    let row =
+        sqlx::query("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'tweets_fts'")
+            .fetch_optional(pool)
+            .await?;
+    Ok(row.is_some())
+}
+
+/// Add a column to a table created by an older version
+async fn add_column_if_missing(
+    pool: &SqlitePool,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<()> {
+    let columns = sqlx::query(&format!("PRAGMA table_info({table})"))
+        .fetch_all(pool)
+        .await?;
+    if columns.iter().any(|c| c.get::<String, _>("name") == column) {
+        return Ok(());
+    }
+    sqlx::query(&format!(
+        "ALTER TABLE {table} ADD COLUMN {column} {definition}"
+    ))
+    .execute(pool)
+    .await?;
+    Ok(())
 }
 
 /// Insert or update a tweet record
@@ -73,12 +302,13 @@ pub async fn upsert_tweet(
     screen_name: &str,
     tweet_time: &str,
     full_text: Option<&str>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+) -> Result<()> {
     sqlx::query(
         r#"
         INSERT INTO tweets (tweet_id, screen_name, tweet_time, full_text)
         VALUES (?, ?, ?, ?)
         ON CONFLICT(tweet_id) DO UPDATE SET
+            tweet_time = excluded.tweet_time,
             full_text = excluded.full_text
         "#,
     )
@@ -92,24 +322,110 @@ pub async fn upsert_tweet(
     Ok(())
 }
 
-/// Insert or update a media record
+/// Record a timeline entry of a tweet that cannot be shown
+///
+/// An archived tweet is marked as deleted, keeping the time and reason it
+/// was first found gone, and `true` is returned. Other entries go to the
+/// `tombstones` table once.
+#[instrument(skip_all)]
+pub async fn record_tombstone(
+    pool: &SqlitePool,
+    screen_name: &str,
+    entry_id: &str,
+    tweet_id: Option<&str>,
+    reason: &str,
+) -> Result<bool> {
+    if let Some(tweet_id) = tweet_id {
+        let marked = sqlx::query(
+            r#"
+            UPDATE tweets SET
+                deleted_at = COALESCE(deleted_at, CURRENT_TIMESTAMP),
+                deleted_reason = COALESCE(deleted_reason, ?)
+            WHERE tweet_id = ?
+            "#,
+        )
+        .bind(reason)
+        .bind(tweet_id)
+        .execute(pool)
+        .await?
+        .rows_affected();
+        if marked > 0 {
+            return Ok(true);
+        }
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO tombstones (entry_id, tweet_id, screen_name, reason)
+        VALUES (?, ?, ?, ?)
+        ON CONFLICT(screen_name, entry_id) DO NOTHING
+        "#,
+    )
+    .bind(entry_id)
+    .bind(tweet_id)
+    .bind(screen_name)
+    .bind(reason)
+    .execute(pool)
+    .await?;
+    Ok(false)
+}
+
+/// Record the counts of a tweet as of now, unknown counts keep the recorded
+/// ones
+#[instrument(skip_all)]
+pub async fn update_engagement(
+    pool: &SqlitePool,
+    tweet_id: &str,
+    engagement: &Engagement,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE tweets SET
+            favorite_count = COALESCE(?, favorite_count),
+            retweet_count = COALESCE(?, retweet_count),
+            reply_count = COALESCE(?, reply_count),
+            quote_count = COALESCE(?, quote_count),
+            view_count = COALESCE(?, view_count)
+        WHERE tweet_id = ?
+        "#,
+    )
+    .bind(engagement.favorite_count.map(|n| n as i64))
+    .bind(engagement.retweet_count.map(|n| n as i64))
+    .bind(engagement.reply_count.map(|n| n as i64))
+    .bind(engagement.quote_count.map(|n| n as i64))
+    .bind(engagement.view_count.map(|n| n as i64))
+    .bind(tweet_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Insert or update a media record, a `None` filename or download URL
+/// keeps the recorded one
+///
+/// `media_url` is the canonical URL, `download_url` the one the file was
+/// fetched from, e.g. with `?name=orig` or of a video variant.
 #[instrument(skip_all)]
 pub async fn upsert_media(
     pool: &SqlitePool,
     tweet_id: &str,
     media_url: &str,
+    download_url: Option<&str>,
     filename: Option<&str>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+) -> Result<()> {
     sqlx::query(
         r#"
-        INSERT INTO media (tweet_id, media_url, filename)
-        VALUES (?, ?, ?)
+        INSERT INTO media (tweet_id, media_url, download_url, filename)
+        VALUES (?, ?, ?, ?)
         ON CONFLICT(media_url) DO UPDATE SET
-            filename = excluded.filename
+            download_url = COALESCE(excluded.download_url, media.download_url),
+            filename = COALESCE(excluded.filename, media.filename)
         "#,
     )
     .bind(tweet_id)
     .bind(media_url)
+    .bind(download_url)
     .bind(filename)
     .execute(pool)
     .await?;
@@ -117,15 +433,153 @@ pub async fn upsert_media(
     Ok(())
 }
 
+/// Move a media recorded under `download_url` to its canonical `media_url`
+///
+/// Videos were keyed by the URL of their variant before canonical URLs were
+/// recorded. Does nothing if `media_url` is recorded already, returns
+/// whether a row was moved.
+#[instrument(skip_all)]
+pub async fn rekey_media(pool: &SqlitePool, download_url: &str, media_url: &str) -> Result<bool> {
+    let result = sqlx::query(
+        r#"
+        UPDATE media SET media_url = ?1, download_url = COALESCE(download_url, ?2)
+        WHERE media_url = ?2
+            AND NOT EXISTS (SELECT 1 FROM media WHERE media_url = ?1)
+        "#,
+    )
+    .bind(media_url)
+    .bind(download_url)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
 /// Update file hash after download
 #[instrument(skip_all)]
 pub async fn update_hash(
     pool: &SqlitePool,
     media_url: &str,
     file_hash: &str,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    sqlx::query("UPDATE media SET file_hash = ? WHERE media_url = ?")
-        .bind(file_hash)
+    algorithm: HashAlgorithm,
+) -> Result<()> {
+    // A different file needs its perceptual hash taken again
+    sqlx::query(
+        r#"
+        UPDATE media SET phash = CASE WHEN file_hash = ?1 THEN phash END,
+            file_hash = ?1, hash_algorithm = ?2
+        WHERE media_url = ?3
+        "#,
+    )
+    .bind(file_hash)
+    .bind(algorithm.as_str())
+    .bind(media_url)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Record the size of the downloaded file
+#[instrument(skip_all)]
+pub async fn update_file_size(pool: &SqlitePool, media_url: &str, file_size: u64) -> Result<()> {
+    sqlx::query("UPDATE media SET file_size = ? WHERE media_url = ?")
+        .bind(file_size as i64)
+        .bind(media_url)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Record the media at `media_url` as gone from the server, keeping the
+/// time it was first found gone
+#[instrument(skip_all)]
+pub async fn mark_gone(pool: &SqlitePool, media_url: &str) -> Result<()> {
+    sqlx::query(
+        "UPDATE media SET gone_at = COALESCE(gone_at, CURRENT_TIMESTAMP) WHERE media_url = ?",
+    )
+    .bind(media_url)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Clear the gone mark of media that was downloaded after all
+#[instrument(skip_all)]
+pub async fn clear_gone(pool: &SqlitePool, media_url: &str) -> Result<()> {
+    sqlx::query("UPDATE media SET gone_at = NULL WHERE media_url = ? AND gone_at IS NOT NULL")
+        .bind(media_url)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Whether the media at `media_url` is recorded as gone, and was found gone
+/// less than `recheck_after` ago if given
+#[instrument(skip_all)]
+pub async fn is_gone(
+    pool: &SqlitePool,
+    media_url: &str,
+    recheck_after: Option<Duration>,
+) -> Result<bool> {
+    let modifier = recheck_after.map(|age| format!("-{} seconds", age.as_secs()));
+    let row = sqlx::query(
+        r#"
+        SELECT 1 FROM media
+        WHERE media_url = ?1 AND gone_at IS NOT NULL
+            AND (?2 IS NULL OR gone_at > datetime('now', ?2))
+        "#,
+    )
+    .bind(media_url)
+    .bind(modifier)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.is_some())
+}
+
+/// Record which `name=` size of an image was downloaded
+#[instrument(skip_all)]
+pub async fn update_image_size(pool: &SqlitePool, media_url: &str, image_size: &str) -> Result<()> {
+    sqlx::query("UPDATE media SET image_size = ? WHERE media_url = ?")
+        .bind(image_size)
+        .bind(media_url)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Record the URL a media file was fetched from
+#[instrument(skip_all)]
+pub async fn update_download_url(
+    pool: &SqlitePool,
+    media_url: &str,
+    download_url: &str,
+) -> Result<()> {
+    sqlx::query("UPDATE media SET download_url = ? WHERE media_url = ?")
+        .bind(download_url)
+        .bind(media_url)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Record the `ETag` and `Last-Modified` headers a media file was served with
+#[instrument(skip_all)]
+pub async fn update_validators(
+    pool: &SqlitePool,
+    media_url: &str,
+    etag: Option<&str>,
+    last_modified: Option<&str>,
+) -> Result<()> {
+    sqlx::query("UPDATE media SET etag = ?, last_modified = ? WHERE media_url = ?")
+        .bind(etag)
+        .bind(last_modified)
         .bind(media_url)
         .execute(pool)
         .await?;
@@ -138,48 +592,984 @@ pub async fn update_hash(
 pub struct MediaRecord {
     pub filename: Option<String>,
     pub file_hash: Option<String>,
+    /// Size of a downloaded image, e.g. `orig` or `large`
+    pub image_size: Option<String>,
+    /// Algorithm of `file_hash`, `None` if it is unknown to this version
+    pub hash_algorithm: Option<HashAlgorithm>,
+    /// `ETag` the file was served with
+    pub etag: Option<String>,
+    /// `Last-Modified` the file was served with
+    pub last_modified: Option<String>,
+    /// URL the file was fetched from
+    pub download_url: Option<String>,
+    /// Name of the poster image saved next to a video
+    pub thumbnail: Option<String>,
 }
 
 /// Get media record by URL
 #[instrument(skip_all)]
-pub async fn get_media_by_url(
-    pool: &SqlitePool,
-    media_url: &str,
-) -> Result<Option<MediaRecord>, Box<dyn std::error::Error + Send + Sync>> {
-    let row = sqlx::query("SELECT filename, file_hash FROM media WHERE media_url = ?")
-        .bind(media_url)
-        .fetch_optional(pool)
-        .await?;
+pub async fn get_media_by_url(pool: &SqlitePool, media_url: &str) -> Result<Option<MediaRecord>> {
+    let row = sqlx::query(
+        r#"
+        SELECT filename, file_hash, image_size, hash_algorithm, etag, last_modified,
+            download_url, thumbnail
+        FROM media WHERE media_url = ?
+        "#,
+    )
+    .bind(media_url)
+    .fetch_optional(pool)
+    .await?;
 
     Ok(row.map(|r| MediaRecord {
         filename: r.get("filename"),
         file_hash: r.get("file_hash"),
+        image_size: r.get("image_size"),
+        hash_algorithm: r
+            .get::<Option<String>, _>("hash_algorithm")
+            .map_or(Some(HashAlgorithm::Sha256), |a| a.parse().ok()),
+        etag: r.get("etag"),
+        last_modified: r.get("last_modified"),
+        download_url: r.get("download_url"),
+        thumbnail: r.get("thumbnail"),
     }))
 }
 
-/// Calculate SHA-256 hash of file content
-pub fn calculate_hash(data: &[u8]) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(data);
-    format!("{:x}", hasher.finalize())
+/// URL of the media of `screen_name` a file was saved as
+///
+/// Other accounts save to their own folders, so their files of the same
+/// name do not count.
+#[instrument(skip_all)]
+pub async fn get_media_url_by_filename(
+    pool: &SqlitePool,
+    screen_name: &str,
+    filename: &str,
+) -> Result<Option<String>> {
+    let row = sqlx::query(
+        r#"
+        SELECT m.media_url
+        FROM media m
+        JOIN tweets t ON t.tweet_id = m.tweet_id
+        WHERE m.filename = ? AND t.screen_name = ? COLLATE NOCASE
+        "#,
+    )
+    .bind(filename)
+    .bind(screen_name)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| r.get("media_url")))
+}
+
+/// A file that was on disk before rxd recorded it, see [`insert_imported`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedMedia {
+    /// ID of the tweet, or a placeholder when the file name does not tell
+    pub tweet_id: String,
+    pub screen_name: String,
+    /// Date from the file name at midnight, or the modification time
+    pub tweet_time: String,
+    pub media_url: String,
+    /// Path relative to the imported directory, unknown for files of other
+    /// downloaders without metadata
+    pub filename: Option<String>,
+    /// Unknown for files that are not on disk
+    pub file_hash: Option<String>,
+    pub hash_algorithm: HashAlgorithm,
+    pub full_text: Option<String>,
+}
+
+/// Record an imported file, with a placeholder tweet unless the tweet is
+/// already known
+///
+/// Returns whether the file was new, media already recorded is left alone.
+#[instrument(skip_all)]
+pub async fn insert_imported(pool: &SqlitePool, media: &ImportedMedia) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        r#"
+        INSERT INTO tweets (tweet_id, screen_name, tweet_time, full_text)
+        VALUES (?, ?, ?, ?)
+        ON CONFLICT(tweet_id) DO NOTHING
+        "#,
+    )
+    .bind(&media.tweet_id)
+    .bind(&media.screen_name)
+    .bind(&media.tweet_time)
+    .bind(&media.full_text)
+    .execute(&mut *tx)
+    .await?;
+    let inserted = sqlx::query(
+        r#"
+        INSERT INTO media (tweet_id, media_url, filename, file_hash, hash_algorithm, imported)
+        VALUES (?, ?, ?, ?, ?, 1)
+        ON CONFLICT(media_url) DO NOTHING
+        "#,
+    )
+    .bind(&media.tweet_id)
+    .bind(&media.media_url)
+    .bind(&media.filename)
+    .bind(&media.file_hash)
+    .bind(
+        media
+            .file_hash
+            .as_ref()
+            .map(|_| media.hash_algorithm.as_str()),
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected()
+        > 0;
+    tx.commit().await?;
+    Ok(inserted)
 }
 
-/// Verify if file exists and has matching hash
+/// Whether media was recorded by an import from another downloader, which
+/// keeps the files elsewhere
+///
+/// `placeholder_url` is the URL recorded when the real one is unknown.
 #[instrument(skip_all)]
-pub async fn verify_file(
+pub async fn is_imported_elsewhere(
     pool: &SqlitePool,
     media_url: &str,
-    save_path: &Path,
-) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    placeholder_url: &str,
+) -> Result<bool> {
+    let row = sqlx::query(
+        r#"
+        SELECT 1 FROM media
+        WHERE media_url IN (?, ?) AND imported = 1 AND file_hash IS NULL
+        "#,
+    )
+    .bind(media_url)
+    .bind(placeholder_url)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.is_some())
+}
+
+/// File names recorded for the media of an account
+#[instrument(skip_all)]
+pub async fn known_filenames(pool: &SqlitePool, screen_name: &str) -> Result<HashSet<String>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT m.filename
+        FROM media m
+        JOIN tweets t ON t.tweet_id = m.tweet_id
+        WHERE t.screen_name = ? COLLATE NOCASE AND m.filename IS NOT NULL
+        "#,
+    )
+    .bind(screen_name)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.iter().map(|r| r.get("filename")).collect())
+}
+
+/// Downloaded media without a hash, e.g. from before files were hashed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnhashedMedia {
+    pub media_url: String,
+    pub screen_name: String,
+    pub filename: String,
+}
+
+/// Media with a file name but no hash, of `screen_name` or every account
+///
+/// Media imported from other downloaders are left out, their files are not
+/// in the account's folder.
+#[instrument(skip_all)]
+pub async fn unhashed_media(
+    pool: &SqlitePool,
+    screen_name: Option<&str>,
+) -> Result<Vec<UnhashedMedia>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT m.media_url, t.screen_name, m.filename
+        FROM media m
+        JOIN tweets t ON t.tweet_id = m.tweet_id
+        WHERE m.filename IS NOT NULL AND m.file_hash IS NULL AND m.imported = 0
+            AND (?1 IS NULL OR t.screen_name = ?1 COLLATE NOCASE)
+        ORDER BY m.id
+        "#,
+    )
+    .bind(screen_name)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|r| UnhashedMedia {
+            media_url: r.get("media_url"),
+            screen_name: r.get("screen_name"),
+            filename: r.get("filename"),
+        })
+        .collect())
+}
+
+/// Downloaded media with the hash their file should have
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashedMedia {
+    pub media_url: String,
+    pub download_url: Option<String>,
+    pub screen_name: String,
+    pub filename: String,
+    pub file_hash: String,
+    /// `None` if it is unknown to this version
+    pub hash_algorithm: Option<HashAlgorithm>,
+    /// The server answered 404 or 410 for it
+    pub gone: bool,
+}
+
+/// Media with a file name and a hash, of `screen_name` or every account
+#[instrument(skip_all)]
+pub async fn hashed_media(
+    pool: &SqlitePool,
+    screen_name: Option<&str>,
+) -> Result<Vec<HashedMedia>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT m.media_url, m.download_url, t.screen_name, m.filename, m.file_hash,
+            m.hash_algorithm, m.gone_at IS NOT NULL AS gone
+        FROM media m
+        JOIN tweets t ON t.tweet_id = m.tweet_id
+        WHERE m.filename IS NOT NULL AND m.file_hash IS NOT NULL
+            AND (?1 IS NULL OR t.screen_name = ?1 COLLATE NOCASE)
+        ORDER BY m.id
+        "#,
+    )
+    .bind(screen_name)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|r| HashedMedia {
+            media_url: r.get("media_url"),
+            download_url: r.get("download_url"),
+            screen_name: r.get("screen_name"),
+            filename: r.get("filename"),
+            file_hash: r.get("file_hash"),
+            hash_algorithm: r
+                .get::<Option<String>, _>("hash_algorithm")
+                .map_or(Some(HashAlgorithm::Sha256), |a| a.parse().ok()),
+            gone: r.get("gone"),
+        })
+        .collect())
+}
+
+/// A file in an account's folder, with its hash if one is recorded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountFile {
+    pub screen_name: String,
+    pub media_url: String,
+    pub filename: String,
+    pub file_hash: Option<String>,
+    /// `None` without a hash or if it is unknown to this version
+    pub hash_algorithm: Option<HashAlgorithm>,
+}
+
+/// Downloaded files of `screen_name` or every account
+///
+/// Media imported from other downloaders are left out, their files are not
+/// in the account's folder.
+#[instrument(skip_all)]
+pub async fn account_files(
+    pool: &SqlitePool,
+    screen_name: Option<&str>,
+) -> Result<Vec<AccountFile>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT t.screen_name, m.media_url, m.filename, m.file_hash, m.hash_algorithm
+        FROM media m
+        JOIN tweets t ON t.tweet_id = m.tweet_id
+        WHERE m.filename IS NOT NULL AND m.imported = 0
+            AND (?1 IS NULL OR t.screen_name = ?1 COLLATE NOCASE)
+        ORDER BY m.id
+        "#,
+    )
+    .bind(screen_name)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|r| {
+            let file_hash: Option<String> = r.get("file_hash");
+            AccountFile {
+                screen_name: r.get("screen_name"),
+                media_url: r.get("media_url"),
+                filename: r.get("filename"),
+                hash_algorithm: file_hash.as_ref().and_then(|_| {
+                    r.get::<Option<String>, _>("hash_algorithm")
+                        .map_or(Some(HashAlgorithm::Sha256), |a| a.parse().ok())
+                }),
+                file_hash,
+            }
+        })
+        .collect())
+}
+
+/// A downloaded file with the tweet it belongs to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadedMedia {
+    pub tweet_id: String,
+    /// Local time the tweet was posted, `YYYY-MM-DD HH:MM:SS`
+    pub tweet_time: String,
+    pub full_text: Option<String>,
+    pub engagement: Engagement,
+    pub media_url: String,
+    pub filename: String,
+    pub file_hash: Option<String>,
+    /// `None` without a hash or if it is unknown to this version
+    pub hash_algorithm: Option<HashAlgorithm>,
+}
+
+/// Downloaded files of `screen_name` from tweets posted at or after
+/// `since` and before `until`, both `YYYY-MM-DD`, oldest first
+#[instrument(skip_all)]
+pub async fn downloaded_media(
+    pool: &SqlitePool,
+    screen_name: &str,
+    since: Option<&str>,
+    until: Option<&str>,
+) -> Result<Vec<DownloadedMedia>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT t.tweet_id, t.tweet_time, t.full_text, t.favorite_count, t.retweet_count,
+            t.reply_count, t.quote_count, t.view_count, m.media_url, m.filename,
+            m.file_hash, m.hash_algorithm
+        FROM media m
+        JOIN tweets t ON t.tweet_id = m.tweet_id
+        WHERE m.filename IS NOT NULL
+            AND t.screen_name = ?1 COLLATE NOCASE
+            AND (?2 IS NULL OR t.tweet_time >= ?2)
+            AND (?3 IS NULL OR t.tweet_time < ?3)
+        ORDER BY t.tweet_time, m.id
+        "#,
+    )
+    .bind(screen_name)
+    .bind(since)
+    .bind(until)
+    .fetch_all(pool)
+    .await?;
+
+    let count = |r: &sqlx::sqlite::SqliteRow, column: &str| {
+        r.get::<Option<i64>, _>(column).map(|n| n as u64)
+    };
+    Ok(rows
+        .iter()
+        .map(|r| {
+            let file_hash: Option<String> = r.get("file_hash");
+            DownloadedMedia {
+                tweet_id: r.get("tweet_id"),
+                tweet_time: r.get("tweet_time"),
+                full_text: r.get("full_text"),
+                engagement: Engagement {
+                    favorite_count: count(r, "favorite_count"),
+                    retweet_count: count(r, "retweet_count"),
+                    reply_count: count(r, "reply_count"),
+                    quote_count: count(r, "quote_count"),
+                    view_count: count(r, "view_count"),
+                },
+                media_url: r.get("media_url"),
+                filename: r.get("filename"),
+                hash_algorithm: file_hash.as_ref().and_then(|_| {
+                    r.get::<Option<String>, _>("hash_algorithm")
+                        .map_or(Some(HashAlgorithm::Sha256), |a| a.parse().ok())
+                }),
+                file_hash,
+            }
+        })
+        .collect())
+}
+
+/// Other media whose file has `file_hash`, files that are no links first
+#[instrument(skip_all)]
+pub async fn find_by_hash(
+    pool: &SqlitePool,
+    file_hash: &str,
+    algorithm: HashAlgorithm,
+    except_media_url: &str,
+) -> Result<Vec<HashedMedia>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT m.media_url, m.download_url, t.screen_name, m.filename, m.file_hash,
+            m.hash_algorithm, m.gone_at IS NOT NULL AS gone
+        FROM media m
+        JOIN tweets t ON t.tweet_id = m.tweet_id
+        WHERE m.file_hash = ? AND COALESCE(m.hash_algorithm, 'sha256') = ?
+            AND m.filename IS NOT NULL AND m.media_url != ?
+        ORDER BY m.linked_to IS NOT NULL, m.id
+        "#,
+    )
+    .bind(file_hash)
+    .bind(algorithm.as_str())
+    .bind(except_media_url)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|r| HashedMedia {
+            media_url: r.get("media_url"),
+            download_url: r.get("download_url"),
+            screen_name: r.get("screen_name"),
+            filename: r.get("filename"),
+            file_hash: r.get("file_hash"),
+            hash_algorithm: Some(algorithm),
+            gone: r.get("gone"),
+        })
+        .collect())
+}
+
+/// Record that the file of `media_url` is `link`, or a file of its own
+#[instrument(skip_all)]
+pub async fn update_link(pool: &SqlitePool, media_url: &str, link: Option<&Link>) -> Result<()> {
+    sqlx::query("UPDATE media SET linked_to = ?, link_kind = ?, link_size = ? WHERE media_url = ?")
+        .bind(link.map(|l| l.to.as_str()))
+        .bind(link.map(|l| l.kind.as_str()))
+        .bind(link.map(|l| l.bytes as i64))
+        .bind(media_url)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Record the poster image saved next to the video of `media_url`
+#[instrument(skip_all)]
+pub async fn update_thumbnail(
+    pool: &SqlitePool,
+    media_url: &str,
+    filename: Option<&str>,
+) -> Result<()> {
+    sqlx::query("UPDATE media SET thumbnail = ? WHERE media_url = ?")
+        .bind(filename)
+        .bind(media_url)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Record the length and resolution of a video, unknown values keep the
+/// recorded ones
+#[instrument(skip_all)]
+pub async fn update_video_details(
+    pool: &SqlitePool,
+    media_url: &str,
+    duration_ms: Option<u64>,
+    resolution: Option<(u32, u32)>,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE media SET
+            duration_ms = COALESCE(?, duration_ms),
+            width = COALESCE(?, width),
+            height = COALESCE(?, height)
+        WHERE media_url = ?
+        "#,
+    )
+    .bind(duration_ms.map(|ms| ms as i64))
+    .bind(resolution.map(|(width, _)| width))
+    .bind(resolution.map(|(_, height)| height))
+    .bind(media_url)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// A recorded file with the same content as another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateMedia {
+    pub media_url: String,
+    pub screen_name: String,
+    pub filename: String,
+    pub file_hash: String,
+    pub hash_algorithm: String,
+    /// How the file is linked, `None` for a file of its own
+    pub link: Option<LinkKind>,
+    /// Bytes saved by the link
+    pub link_size: Option<u64>,
+}
+
+impl DuplicateMedia {
+    /// Whether both files have the same content
+    pub fn same_content(&self, other: &DuplicateMedia) -> bool {
+        self.file_hash == other.file_hash && self.hash_algorithm == other.hash_algorithm
+    }
+}
+
+/// Files whose hash is recorded more than once, grouped by hash
+#[instrument(skip_all)]
+pub async fn duplicate_media(pool: &SqlitePool) -> Result<Vec<DuplicateMedia>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT m.media_url, t.screen_name, m.filename, m.file_hash,
+            COALESCE(m.hash_algorithm, 'sha256') AS hash_algorithm, m.link_kind, m.link_size
+        FROM media m
+        JOIN tweets t ON t.tweet_id = m.tweet_id
+        WHERE m.filename IS NOT NULL AND m.file_hash IS NOT NULL
+            AND (m.file_hash, COALESCE(m.hash_algorithm, 'sha256')) IN (
+                SELECT file_hash, COALESCE(hash_algorithm, 'sha256')
+                FROM media
+                WHERE filename IS NOT NULL AND file_hash IS NOT NULL
+                GROUP BY 1, 2
+                HAVING COUNT(*) > 1
+            )
+        ORDER BY m.file_hash, 5, m.id
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|r| DuplicateMedia {
+            media_url: r.get("media_url"),
+            screen_name: r.get("screen_name"),
+            filename: r.get("filename"),
+            file_hash: r.get("file_hash"),
+            hash_algorithm: r.get("hash_algorithm"),
+            link: r
+                .get::<Option<String>, _>("link_kind")
+                .and_then(|k| k.parse().ok()),
+            link_size: r.get::<Option<i64>, _>("link_size").map(|s| s as u64),
+        })
+        .collect())
+}
+
+/// Number of media with a hash, of `screen_name` or every account
+#[instrument(skip_all)]
+pub async fn count_hashed(pool: &SqlitePool, screen_name: Option<&str>) -> Result<u64> {
+    let count: i64 = sqlx::query_scalar(
+        r#"
+        SELECT COUNT(*)
+        FROM media m
+        JOIN tweets t ON t.tweet_id = m.tweet_id
+        WHERE m.file_hash IS NOT NULL
+            AND (?1 IS NULL OR t.screen_name = ?1 COLLATE NOCASE)
+        "#,
+    )
+    .bind(screen_name)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count as u64)
+}
+
+/// What the archive holds for one account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserStats {
+    pub screen_name: String,
+    /// Media recorded for the account's tweets
+    pub media: i64,
+    /// Media downloaded and verified by hash
+    pub hashed: i64,
+    /// `tweet_time` of the newest recorded tweet
+    pub newest_tweet: Option<String>,
+}
+
+/// A downloaded file without a perceptual hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnphashedMedia {
+    pub media_url: String,
+    pub screen_name: String,
+    pub filename: String,
+}
+
+/// Downloaded files of `screen_name` or every account without a perceptual
+/// hash
+///
+/// Files that are links to identical ones and imported files are left out.
+#[instrument(skip_all)]
+pub async fn unphashed_media(
+    pool: &SqlitePool,
+    screen_name: Option<&str>,
+) -> Result<Vec<UnphashedMedia>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT m.media_url, t.screen_name, m.filename
+        FROM media m
+        JOIN tweets t ON t.tweet_id = m.tweet_id
+        WHERE m.filename IS NOT NULL AND m.phash IS NULL
+            AND m.imported = 0 AND m.linked_to IS NULL
+            AND (?1 IS NULL OR t.screen_name = ?1 COLLATE NOCASE)
+        ORDER BY m.id
+        "#,
+    )
+    .bind(screen_name)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|r| UnphashedMedia {
+            media_url: r.get("media_url"),
+            screen_name: r.get("screen_name"),
+            filename: r.get("filename"),
+        })
+        .collect())
+}
+
+/// Record the perceptual hash of the file of `media_url`
+#[instrument(skip_all)]
+pub async fn update_phash(pool: &SqlitePool, media_url: &str, phash: u64) -> Result<()> {
+    sqlx::query("UPDATE media SET phash = ? WHERE media_url = ?")
+        .bind(phash as i64)
+        .bind(media_url)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// A downloaded file with its perceptual hash
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PhashedMedia {
+    pub media_url: String,
+    pub tweet_id: String,
+    pub screen_name: String,
+    pub filename: String,
+    /// Bytes on disk, `None` if not recorded
+    pub file_size: Option<u64>,
+    pub phash: u64,
+}
+
+/// Downloaded files of `screen_name` or every account with a perceptual
+/// hash
+#[instrument(skip_all)]
+pub async fn phashed_media(
+    pool: &SqlitePool,
+    screen_name: Option<&str>,
+) -> Result<Vec<PhashedMedia>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT m.media_url, m.tweet_id, t.screen_name, m.filename, m.file_size, m.phash
+        FROM media m
+        JOIN tweets t ON t.tweet_id = m.tweet_id
+        WHERE m.filename IS NOT NULL AND m.phash IS NOT NULL
+            AND (?1 IS NULL OR t.screen_name = ?1 COLLATE NOCASE)
+        ORDER BY m.id
+        "#,
+    )
+    .bind(screen_name)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|r| PhashedMedia {
+            media_url: r.get("media_url"),
+            tweet_id: r.get("tweet_id"),
+            screen_name: r.get("screen_name"),
+            filename: r.get("filename"),
+            file_size: r.get::<Option<i64>, _>("file_size").map(|n| n as u64),
+            phash: r.get::<i64, _>("phash") as u64,
+        })
+        .collect())
+}
+
+/// Order of [`list_users`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum UserOrder {
+    /// Alphabetically by screen name
+    #[default]
+    Name,
+    /// Newest archived tweet first
+    Recent,
+}
+
+/// Every account with tweets in the database
+#[instrument(skip_all)]
+pub async fn list_users(pool: &SqlitePool, order: UserOrder) -> Result<Vec<UserStats>> {
+    let order_by = match order {
+        UserOrder::Name => "screen_name COLLATE NOCASE",
+        UserOrder::Recent => "newest_tweet DESC, screen_name COLLATE NOCASE",
+    };
+    let rows = sqlx::query(&format!(
+        r#"
+        SELECT tweets.screen_name AS screen_name,
+            COUNT(media.id) AS media,
+            COUNT(media.file_hash) AS hashed,
+            MAX(tweets.tweet_time) AS newest_tweet
+        FROM tweets
+        LEFT JOIN media ON media.tweet_id = tweets.tweet_id
+        GROUP BY tweets.screen_name
+        ORDER BY {order_by}
+        "#
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|r| UserStats {
+            screen_name: r.get("screen_name"),
+            media: r.get("media"),
+            hashed: r.get("hashed"),
+            newest_tweet: r.get("newest_tweet"),
+        })
+        .collect())
+}
+
+/// What the archive holds for `screen_name`, ignoring case, `None` without
+/// any of its tweets
+#[instrument(skip_all)]
+pub async fn user_stats(pool: &SqlitePool, screen_name: &str) -> Result<Option<UserStats>> {
+    let row = sqlx::query(
+        r#"
+        SELECT tweets.screen_name AS screen_name,
+            COUNT(media.id) AS media,
+            COUNT(media.file_hash) AS hashed,
+            MAX(tweets.tweet_time) AS newest_tweet
+        FROM tweets
+        LEFT JOIN media ON media.tweet_id = tweets.tweet_id
+        WHERE tweets.screen_name = ? COLLATE NOCASE
+        GROUP BY tweets.screen_name COLLATE NOCASE
+        "#,
+    )
+    .bind(screen_name)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| UserStats {
+        screen_name: r.get("screen_name"),
+        media: r.get("media"),
+        hashed: r.get("hashed"),
+        newest_tweet: r.get("newest_tweet"),
+    }))
+}
+
+/// A downloaded file with its tweet and recorded size
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizedMedia {
+    pub media_url: String,
+    pub tweet_id: String,
+    pub screen_name: String,
+    /// Local time the tweet was posted, `YYYY-MM-DD HH:MM:SS`
+    pub tweet_time: String,
+    pub filename: String,
+    /// `None` for files downloaded before sizes were recorded
+    pub file_size: Option<u64>,
+    /// Saved by another downloader, not in the account's folder
+    pub imported: bool,
+}
+
+/// Downloaded files of `screen_name` or every account
+#[instrument(skip_all)]
+pub async fn sized_media(pool: &SqlitePool, screen_name: Option<&str>) -> Result<Vec<SizedMedia>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT m.media_url, t.tweet_id, t.screen_name, t.tweet_time, m.filename,
+            m.file_size, m.imported
+        FROM media m
+        JOIN tweets t ON t.tweet_id = m.tweet_id
+        WHERE m.filename IS NOT NULL
+            AND (?1 IS NULL OR t.screen_name = ?1 COLLATE NOCASE)
+        ORDER BY t.tweet_time, m.id
+        "#,
+    )
+    .bind(screen_name)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|r| SizedMedia {
+            media_url: r.get("media_url"),
+            tweet_id: r.get("tweet_id"),
+            screen_name: r.get("screen_name"),
+            tweet_time: r.get("tweet_time"),
+            filename: r.get("filename"),
+            file_size: r.get::<Option<i64>, _>("file_size").map(|n| n as u64),
+            imported: r.get::<i64, _>("imported") != 0,
+        })
+        .collect())
+}
+
+/// An archived tweet to look up upstream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UncheckedTweet {
+    pub tweet_id: String,
+    pub screen_name: String,
+}
+
+/// Tweets of `screen_name`, or of every account, not known to be deleted
+/// and never checked, or last checked at least `recheck_after` ago
+///
+/// Never checked tweets come first, then the ones checked longest ago.
+#[instrument(skip_all)]
+pub async fn tweets_to_check(
+    pool: &SqlitePool,
+    screen_name: Option<&str>,
+    recheck_after: Option<Duration>,
+) -> Result<Vec<UncheckedTweet>> {
+    let modifier = recheck_after.map(|age| format!("-{} seconds", age.as_secs()));
+    let rows = sqlx::query(
+        r#"
+        SELECT tweet_id, screen_name FROM tweets
+        WHERE deleted_at IS NULL
+            AND (?1 IS NULL OR screen_name = ?1 COLLATE NOCASE)
+            AND (checked_at IS NULL OR (?2 IS NOT NULL AND checked_at <= datetime('now', ?2)))
+        ORDER BY checked_at IS NOT NULL, checked_at, id
+        "#,
+    )
+    .bind(screen_name)
+    .bind(modifier)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|r| UncheckedTweet {
+            tweet_id: r.get("tweet_id"),
+            screen_name: r.get("screen_name"),
+        })
+        .collect())
+}
+
+/// Record what a check of a tweet found, `status` being `live`, `deleted`
+/// or `withheld`
+///
+/// With a `deleted_reason` the tweet is marked as deleted like a tombstone
+/// does, keeping the time and reason it was first found gone.
+#[instrument(skip_all)]
+pub async fn record_check(
+    pool: &SqlitePool,
+    tweet_id: &str,
+    status: &str,
+    deleted_reason: Option<&str>,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE tweets SET
+            checked_at = CURRENT_TIMESTAMP,
+            check_status = ?1,
+            deleted_at = CASE WHEN ?2 IS NULL THEN deleted_at
+                ELSE COALESCE(deleted_at, CURRENT_TIMESTAMP) END,
+            deleted_reason = COALESCE(deleted_reason, ?2)
+        WHERE tweet_id = ?3
+        "#,
+    )
+    .bind(status)
+    .bind(deleted_reason)
+    .bind(tweet_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Files the media of a tweet were saved as
+#[instrument(skip_all)]
+pub async fn tweet_filenames(pool: &SqlitePool, tweet_id: &str) -> Result<Vec<String>> {
+    Ok(sqlx::query_scalar(
+        "SELECT filename FROM media WHERE tweet_id = ? AND filename IS NOT NULL ORDER BY filename",
+    )
+    .bind(tweet_id)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// A tweet whose text matched a search
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TextMatch {
+    pub tweet_id: String,
+    pub screen_name: String,
+    pub tweet_time: String,
+    pub full_text: String,
+    /// Files its media were saved as
+    pub filenames: Vec<String>,
+}
+
+/// Tweets whose text contains `query`, ignoring case, newest first
+///
+/// Uses the full-text index for queries of three or more characters, the
+/// shortest the trigram tokenizer can match. Shorter queries, and databases
+/// without FTS5, scan with `LIKE`, which only ignores the case of ASCII
+/// letters.
+#[instrument(skip_all)]
+pub async fn search_text(
+    pool: &SqlitePool,
+    query: &str,
+    screen_name: Option<&str>,
+) -> Result<Vec<TextMatch>> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let indexed = query.chars().count() >= 3 && has_text_index(pool).await?;
+    let (from, condition, pattern) = if indexed {
+        // A quoted phrase, so operators in the query are taken literally
+        (
+            "tweets_fts JOIN tweets ON tweets.id = tweets_fts.rowid",
+            "tweets_fts MATCH ?",
+            format!("\"{}\"", query.replace('"', "\"\"")),
+        )
+    } else {
+        (
+            "tweets",
+            "tweets.full_text LIKE ? ESCAPE '\\'",
+            format!(
+                "%{}%",
+                query
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_")
+            ),
+        )
+    };
+    let rows = sqlx::query(&format!(
+        r#"
+        SELECT tweets.tweet_id AS tweet_id, tweets.screen_name AS screen_name,
+            tweets.tweet_time AS tweet_time, tweets.full_text AS full_text
+        FROM {from}
+        WHERE {condition}
+            AND (? IS NULL OR tweets.screen_name = ? COLLATE NOCASE)
+        ORDER BY tweets.tweet_time DESC, tweets.tweet_id DESC
+        "#
+    ))
+    .bind(pattern)
+    .bind(screen_name)
+    .bind(screen_name)
+    .fetch_all(pool)
+    .await?;
+
+    let mut matches = Vec::with_capacity(rows.len());
+    for row in rows {
+        let tweet_id: String = row.get("tweet_id");
+        let filenames = tweet_filenames(pool, &tweet_id).await?;
+        matches.push(TextMatch {
+            tweet_id,
+            screen_name: row.get("screen_name"),
+            tweet_time: row.get("tweet_time"),
+            full_text: row.get("full_text"),
+            filenames,
+        });
+    }
+    Ok(matches)
+}
+
+/// Calculate hash of file content
+pub fn calculate_hash(data: &[u8], algorithm: HashAlgorithm) -> String {
+    algorithm.hash(data)
+}
+
+/// Verify if file exists and has matching hash, made with the algorithm
+/// recorded for it
+#[instrument(skip_all)]
+pub async fn verify_file(pool: &SqlitePool, media_url: &str, save_path: &Path) -> Result<bool> {
     let record = match get_media_by_url(pool, media_url).await? {
         Some(r) => r,
         None => return Ok(false),
     };
 
-    let (filename, expected_hash) = match (record.filename, record.file_hash) {
-        (Some(f), Some(h)) => (f, h),
-        _ => return Ok(false),
-    };
+    let (filename, expected_hash, algorithm) =
+        match (record.filename, record.file_hash, record.hash_algorithm) {
+            (Some(f), Some(h), Some(a)) => (f, h, a),
+            _ => return Ok(false),
+        };
 
     let filepath = save_path.join(&filename);
     if !filepath.exists() {
@@ -187,7 +1577,7 @@ pub async fn verify_file(
     }
 
     let content = fs::read(&filepath).await?;
-    let actual_hash = calculate_hash(&content);
+    let actual_hash = calculate_hash(&content, algorithm);
 
     Ok(actual_hash == expected_hash)
 }
@@ -0,0 +1,155 @@
+//! Deduplication of downloads by linking them to identical files already in
+//! the archive.
+//!
+//! A download is still written in full, it is swapped for a link once its
+//! hash matches a recorded file. Files are only ever replaced by rename, so
+//! downloading one of the names again never changes the other.
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::db::DuplicateMedia;
+use crate::error::{Error, Result};
+
+/// What to do with a download identical to a recorded file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Dedupe {
+    /// Keep every download as its own file
+    #[default]
+    Off,
+    /// Link the download to the recorded file, a symlink where hardlinks
+    /// are unsupported and a copy across devices
+    Hardlink,
+}
+
+/// How a file is linked to the identical file it stands for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    Hardlink,
+    Symlink,
+}
+
+impl LinkKind {
+    /// Name stored in the database
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LinkKind::Hardlink => "hardlink",
+            LinkKind::Symlink => "symlink",
+        }
+    }
+}
+
+impl fmt::Display for LinkKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for LinkKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "hardlink" => Ok(LinkKind::Hardlink),
+            "symlink" => Ok(LinkKind::Symlink),
+            _ => Err(Error::Config(format!("unknown link kind: {s}"))),
+        }
+    }
+}
+
+/// A download linked to an identical recorded file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    /// Media URL of the recorded file
+    pub to: String,
+    pub kind: LinkKind,
+    /// Size of the file, saved on disk by the link
+    pub bytes: u64,
+}
+
+/// Replace `path` with a link to `original`, which has the same content
+///
+/// Tries a hardlink, then a symlink where hardlinks are unsupported. Fails
+/// when neither can be made, e.g. across devices, and `path` is left as it
+/// was.
+pub fn link(original: &Path, path: &Path) -> io::Result<LinkKind> {
+    let mut tmp = path.as_os_str().to_os_string();
+    tmp.push(".link.part");
+    let tmp = Path::new(&tmp);
+    let _ = std::fs::remove_file(tmp);
+
+    let kind = match std::fs::hard_link(original, tmp) {
+        Ok(()) => LinkKind::Hardlink,
+        // A symlink would work, but breaks when the other drive is gone
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => return Err(e),
+        Err(e) => {
+            debug!("no hardlink to {}: {}", original.display(), e);
+            symlink(&std::path::absolute(original)?, tmp)?;
+            LinkKind::Symlink
+        }
+    };
+    if let Err(e) = std::fs::rename(tmp, path) {
+        let _ = std::fs::remove_file(tmp);
+        return Err(e);
+    }
+    Ok(kind)
+}
+
+#[cfg(unix)]
+fn symlink(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+/// Needs Developer Mode or an elevated prompt, without them files are kept
+/// as copies
+#[cfg(windows)]
+fn symlink(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(original, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_: &Path, _: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Recorded files that share their content, with what linking them saved
+#[derive(Debug, Clone, Default)]
+pub struct DuplicateReport {
+    /// Files of the same content, each group in the order they were recorded
+    pub groups: Vec<Vec<DuplicateMedia>>,
+    pub hardlinks: u64,
+    pub symlinks: u64,
+    /// Bytes the links take up less than copies would
+    pub bytes_saved: u64,
+}
+
+impl DuplicateReport {
+    /// Group `media`, ordered by hash as [`db::duplicate_media`](crate::db::duplicate_media)
+    /// returns them
+    pub fn new(media: Vec<DuplicateMedia>) -> Self {
+        let mut report = Self::default();
+        for media in media {
+            match media.link {
+                Some(LinkKind::Hardlink) => report.hardlinks += 1,
+                Some(LinkKind::Symlink) => report.symlinks += 1,
+                None => {}
+            }
+            if media.link.is_some() {
+                report.bytes_saved = report
+                    .bytes_saved
+                    .saturating_add(media.link_size.unwrap_or(0));
+            }
+            match report.groups.last_mut() {
+                Some(group) if group[0].same_content(&media) => group.push(media),
+                _ => report.groups.push(vec![media]),
+            }
+        }
+        report
+    }
+}
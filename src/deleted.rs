@@ -0,0 +1,170 @@
+//! `rxd check-deleted`: which archived tweets no longer exist upstream.
+//!
+//! Every tweet is looked up with TweetResultByRestId and the result is
+//! recorded with the time of the check right away, so an interrupted run
+//! continues with the tweets it has not reached yet. Rate limits are
+//! waited out like for any other request; a run that is still limited
+//! after that stops and reports how many tweets are left.
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::SqlitePool;
+use tracing::{info, instrument, warn};
+
+use crate::api::Api;
+use crate::db;
+use crate::error::{Error, Result};
+use crate::graphql::{Response, TweetResult, TweetResultByRestIdData};
+
+/// What a lookup found of a tweet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TweetStatus {
+    Live,
+    /// Gone, with the notice shown instead or why it is unavailable
+    Deleted(String),
+    /// Still there but hidden, e.g. withheld in a country or of a
+    /// suspended or protected account
+    Withheld(String),
+}
+
+impl TweetStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TweetStatus::Live => "live",
+            TweetStatus::Deleted(_) => "deleted",
+            TweetStatus::Withheld(_) => "withheld",
+        }
+    }
+}
+
+/// Parse a TweetResultByRestId response into the status of the tweet
+#[instrument(skip_all)]
+pub fn parse_tweet_result_response(raw: &Value) -> Result<TweetStatus> {
+    let response = Response::<TweetResultByRestIdData>::deserialize(raw)
+        .map_err(|e| Error::Parse(format!("invalid TweetResultByRestId response: {e}")))?;
+    let data = response
+        .data
+        .ok_or_else(|| Error::Parse("missing data".to_string()))?;
+
+    // Tweets that do not exist (any more) get an empty `tweetResult`
+    let Some(result) = data.tweet_result.and_then(|t| t.result) else {
+        return Ok(TweetStatus::Deleted("not found".to_string()));
+    };
+    match result {
+        TweetResult::Tweet(_) | TweetResult::TweetWithVisibilityResults { .. } => {
+            Ok(TweetStatus::Live)
+        }
+        TweetResult::TweetTombstone { tombstone } => {
+            let notice = tombstone
+                .and_then(|t| t.text)
+                .map(|t| t.text.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .unwrap_or_else(|| "unavailable".to_string());
+            if notice.to_lowercase().contains("withheld") {
+                Ok(TweetStatus::Withheld(notice))
+            } else {
+                Ok(TweetStatus::Deleted(notice))
+            }
+        }
+        TweetResult::TweetUnavailable { reason } => Ok(TweetStatus::Withheld(
+            reason.unwrap_or_else(|| "unavailable".to_string()),
+        )),
+        TweetResult::Unknown => Err(Error::Parse("unknown tweet result type".to_string())),
+    }
+}
+
+/// A tweet found deleted by this check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeletedTweet {
+    pub tweet_id: String,
+    pub screen_name: String,
+    pub reason: String,
+    /// Files its media were saved as
+    pub filenames: Vec<String>,
+}
+
+/// Outcome of a check
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheckSummary {
+    pub live: u64,
+    pub withheld: u64,
+    pub newly_deleted: Vec<DeletedTweet>,
+    /// Lookups that failed, the tweets are checked again next time
+    pub failed: u64,
+    /// Tweets not checked because the rate limit stopped the run
+    pub remaining: u64,
+}
+
+/// Look up the archived tweets of `screen_name`, or of every account, that
+/// were never checked or last checked at least `recheck_after` ago
+///
+/// Up to `concurrency` tweets are looked up at once and `progress` is
+/// called with the number of checked tweets and the total. Tweets already
+/// known to be deleted are not looked up again.
+#[instrument(skip_all)]
+pub async fn check(
+    pool: &SqlitePool,
+    api: &Api,
+    screen_name: Option<&str>,
+    recheck_after: Option<Duration>,
+    concurrency: usize,
+    mut progress: impl FnMut(u64, u64),
+) -> Result<CheckSummary> {
+    let tweets = db::tweets_to_check(pool, screen_name, recheck_after).await?;
+    let mut summary = CheckSummary::default();
+
+    let total = tweets.len() as u64;
+    let mut done = 0;
+    progress(0, total);
+    let mut lookups = futures::stream::iter(tweets)
+        .map(|tweet| async move {
+            let result = api.tweet_status(&tweet.tweet_id).await;
+            (tweet, result)
+        })
+        .buffer_unordered(concurrency.max(1));
+
+    while let Some((tweet, result)) = lookups.next().await {
+        let status = match result {
+            Ok(status) => status,
+            Err(Error::Api { status, .. }) if status == StatusCode::TOO_MANY_REQUESTS => {
+                info!("still rate limited, stopping; run again later to continue");
+                // Lookups in flight are dropped and checked next time
+                summary.remaining = total - done;
+                break;
+            }
+            Err(e @ (Error::Auth(_) | Error::GuestUnsupported(_))) => return Err(e),
+            Err(e) => {
+                warn!("failed to check tweet {}: {}", tweet.tweet_id, e);
+                summary.failed += 1;
+                done += 1;
+                progress(done, total);
+                continue;
+            }
+        };
+        let deleted_reason = match &status {
+            TweetStatus::Deleted(reason) => Some(reason.as_str()),
+            _ => None,
+        };
+        db::record_check(pool, &tweet.tweet_id, status.as_str(), deleted_reason).await?;
+        match status {
+            TweetStatus::Live => summary.live += 1,
+            TweetStatus::Withheld(_) => summary.withheld += 1,
+            TweetStatus::Deleted(reason) => {
+                let filenames = db::tweet_filenames(pool, &tweet.tweet_id).await?;
+                summary.newly_deleted.push(DeletedTweet {
+                    tweet_id: tweet.tweet_id,
+                    screen_name: tweet.screen_name,
+                    reason,
+                    filenames,
+                });
+            }
+        }
+        done += 1;
+        progress(done, total);
+    }
+    Ok(summary)
+}
@@ -0,0 +1,447 @@
+//! Setup checks behind `rxd doctor`.
+//!
+//! Every check results in a line of the report, failures and warnings come
+//! with a suggested fix. Later checks are skipped when the config cannot be
+//! loaded.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use indicatif::{HumanBytes, HumanDuration};
+use reqwest::{Client, StatusCode};
+
+use crate::api::{self, Api, TlsOptions};
+use crate::config::Config;
+use crate::db;
+use crate::error::Error;
+use crate::filename::Template;
+use crate::query_ids;
+
+/// Host serving images, checked next to the API host
+pub const MEDIA_HOST: &str = "https://pbs.twimg.com";
+
+/// Free space below which a save path gets a warning
+const LOW_DISK_SPACE: u64 = 1 << 30;
+
+/// Account looked up to check credentials when the config has no tasks
+const PROBE_SCREEN_NAME: &str = "X";
+
+/// Outcome of a check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    /// Works, but may cause trouble
+    Warn,
+    /// Downloads will not work
+    Fail,
+}
+
+/// One line of the report
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    pub detail: String,
+    /// Suggested fix for warnings and failures
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn pass(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: Status::Pass,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn warn(name: impl Into<String>, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: Status::Warn,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn fail(name: impl Into<String>, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: Status::Fail,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Results of all checks, in the order they ran
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// Whether any check failed
+    pub fn has_failures(&self) -> bool {
+        self.checks.iter().any(|c| c.status == Status::Fail)
+    }
+
+    /// Render the report as one line per check
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for check in &self.checks {
+            let label = match check.status {
+                Status::Pass => "ok",
+                Status::Warn => "WARN",
+                Status::Fail => "FAIL",
+            };
+            let _ = writeln!(out, "[{:>4}] {}: {}", label, check.name, check.detail);
+            if let Some(fix) = &check.fix {
+                let _ = writeln!(out, "       fix: {fix}");
+            }
+        }
+        out
+    }
+}
+
+/// Run every check for the config at `config_path`
+///
+/// `media_host` is checked for connectivity next to the API host, normally
+/// [`MEDIA_HOST`].
+pub async fn run(config_path: &Path, media_host: &str) -> Report {
+    let mut report = Report::default();
+
+    let config = match Config::load(config_path)
+        .and_then(|config| config.require_credentials(false).map(|_| config))
+    {
+        Ok(config) => config,
+        Err(e) => {
+            report.checks.push(Check::fail(
+                "config",
+                e.to_string(),
+                "compare the file with example.toml",
+            ));
+            return report;
+        }
+    };
+    report.checks.push(Check::pass(
+        "config",
+        format!(
+            "{} loaded with {} tasks",
+            config_path.display(),
+            config.tasks.len()
+        ),
+    ));
+    report.checks.extend(check_templates(&config));
+
+    let config_dir = &crate::config::config_dir(config_path);
+    let tls = TlsOptions {
+        ca_cert: config.ca_cert.as_ref().map(|p| config_dir.join(p)),
+        os_roots: config.tls_os_roots,
+    };
+    let base_url = config
+        .api_base_url
+        .as_deref()
+        .unwrap_or(api::DEFAULT_BASE_URL);
+
+    match tls.client_builder().and_then(|b| Ok(b.build()?)) {
+        Ok(client) => {
+            for host in [base_url, media_host] {
+                report.checks.push(check_host(&client, host).await);
+            }
+        }
+        Err(e) => report.checks.push(Check::fail(
+            "tls",
+            e.to_string(),
+            "fix ca_cert or enable tls_os_roots",
+        )),
+    }
+
+    if config.guest {
+        report.checks.push(match Api::guest(base_url, &tls).await {
+            Ok(_) => Check::pass("credentials", "guest mode, guest token issued"),
+            Err(e) => Check::fail(
+                "credentials",
+                e.to_string(),
+                "try again later, or set auth_token and ct0 instead of guest mode",
+            ),
+        });
+    } else {
+        match Api::with_tls(&config.auth_token, &config.ct0, base_url, &tls) {
+            Ok(api) => {
+                // Same IDs as a download would use, discovered ones are cached
+                let cache = config_dir.join("query_ids.json");
+                let ids = query_ids::resolve(api.client(), api.base_url(), &cache, false).await;
+                let api = api.with_query_ids(ids);
+                report.checks.extend(check_credentials(&api, &config).await);
+            }
+            Err(e) => report.checks.push(Check::fail(
+                "credentials",
+                e.to_string(),
+                "copy auth_token and ct0 again from the browser cookies",
+            )),
+        }
+    }
+
+    for task in &config.tasks {
+        let save_path = task.save_path.as_ref().map_or_else(
+            || PathBuf::from("downloads").join(&task.screen_name),
+            PathBuf::from,
+        );
+        report.checks.push(check_save_path(&save_path));
+    }
+
+    report
+        .checks
+        .push(check_database(&config.database_path(config_dir)).await);
+    if config.desktop_notify {
+        report.checks.push(check_notifier());
+    }
+    report
+}
+
+fn check_templates(config: &Config) -> Vec<Check> {
+    let templates = config.filename_template.iter().chain(
+        config
+            .tasks
+            .iter()
+            .filter_map(|t| t.filename_template.as_ref()),
+    );
+    templates
+        .filter_map(|template| Template::parse(template).err())
+        .map(|e| {
+            Check::fail(
+                "filename_template",
+                e.to_string(),
+                "use placeholders like {date}-{media_id}",
+            )
+        })
+        .collect()
+}
+
+/// Whether the program showing desktop notifications is installed
+#[cfg(feature = "desktop-notify")]
+fn check_notifier() -> Check {
+    use crate::notify::desktop::{self, NOTIFIER};
+
+    match desktop::find_notifier() {
+        Some(path) => Check::pass("notifications", format!("{NOTIFIER} at {}", path.display())),
+        None => Check::warn(
+            "notifications",
+            format!("{NOTIFIER} is not installed, no notification will be shown"),
+            match NOTIFIER {
+                "notify-send" => "install notify-send (libnotify) or turn off desktop_notify",
+                _ => "add it to PATH or turn off desktop_notify",
+            },
+        ),
+    }
+}
+
+#[cfg(not(feature = "desktop-notify"))]
+fn check_notifier() -> Check {
+    Check::warn(
+        "notifications",
+        "built without the desktop-notify feature, no notification will be shown",
+        "build with default features or turn off desktop_notify",
+    )
+}
+
+/// Whether DNS, TLS and HTTP work for `host`, any response counts
+async fn check_host(client: &Client, host: &str) -> Check {
+    let name = format!("connectivity {host}");
+    match client.head(host).send().await {
+        Ok(response) => Check::pass(name, format!("HTTP {}", response.status().as_u16())),
+        Err(e) => Check::fail(
+            name,
+            error_chain(&e),
+            "check DNS, firewall and proxy settings, set ca_cert behind a TLS-intercepting proxy",
+        ),
+    }
+}
+
+async fn check_credentials(api: &Api, config: &Config) -> Vec<Check> {
+    let screen_name = config
+        .tasks
+        .first()
+        .map_or(PROBE_SCREEN_NAME, |t| t.screen_name.as_str());
+    let rate_limit = match api.probe(screen_name).await {
+        Ok(rate_limit) => rate_limit,
+        Err(Error::Auth(failure)) => {
+            return vec![Check::fail(
+                "credentials",
+                failure.to_string(),
+                failure.fix(),
+            )];
+        }
+        Err(Error::Api { status, .. }) => {
+            let fix = match status {
+                StatusCode::FORBIDDEN => {
+                    "copy auth_token and ct0 again from the same browser session"
+                }
+                StatusCode::NOT_FOUND => "query IDs are outdated, run with --refresh-query-ids",
+                _ => "try again later",
+            };
+            return vec![Check::fail(
+                "credentials",
+                format!("lookup of @{screen_name} returned HTTP {}", status.as_u16()),
+                fix,
+            )];
+        }
+        Err(e) => {
+            let detail = match e {
+                Error::Http(e) => error_chain(&e.without_url()),
+                e => e.to_string(),
+            };
+            return vec![Check::fail("credentials", detail, "fix connectivity first")];
+        }
+    };
+
+    let mut checks = vec![Check::pass(
+        "credentials",
+        format!("lookup of @{screen_name} accepted"),
+    )];
+    checks.push(match (rate_limit.remaining, rate_limit.limit) {
+        (Some(0), _) => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let wait = rate_limit.reset.unwrap_or(now).saturating_sub(now);
+            Check::warn(
+                "rate limit",
+                "no requests left in this window",
+                format!(
+                    "wait {} before running",
+                    HumanDuration(std::time::Duration::from_secs(wait))
+                ),
+            )
+        }
+        (Some(remaining), Some(limit)) => Check::pass(
+            "rate limit",
+            format!("{remaining} of {limit} requests left"),
+        ),
+        _ => Check::pass("rate limit", "not reported"),
+    });
+    checks
+}
+
+/// Whether files can be created in `save_path` and there is room for them
+///
+/// A missing directory is checked through its closest existing parent,
+/// nothing is created but a short-lived probe file.
+fn check_save_path(save_path: &Path) -> Check {
+    let name = format!("save path {}", save_path.display());
+    // Relative paths end in an empty ancestor, the working directory
+    let existing = save_path
+        .ancestors()
+        .find(|p| p.as_os_str().is_empty() || p.is_dir());
+    let Some(existing) = existing else {
+        return Check::fail(
+            name,
+            "no existing parent directory",
+            "set save_path to a directory on a mounted filesystem",
+        );
+    };
+    let existing = if existing.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        existing
+    };
+
+    let probe = existing.join(format!(".rxd-doctor-{}", std::process::id()));
+    if let Err(e) = std::fs::write(&probe, b"") {
+        return Check::fail(
+            name,
+            format!("cannot write to {}: {}", existing.display(), e),
+            "fix the directory permissions or choose another save_path",
+        );
+    }
+    let _ = std::fs::remove_file(&probe);
+
+    match free_space(existing) {
+        Some(free) if free < LOW_DISK_SPACE => Check::warn(
+            name,
+            format!("writable, only {} free", HumanBytes(free)),
+            "free up space or set max_file_size",
+        ),
+        Some(free) => Check::pass(name, format!("writable, {} free", HumanBytes(free))),
+        None => Check::pass(name, "writable"),
+    }
+}
+
+/// Bytes available to unprivileged users on the filesystem of `path`
+#[cfg(unix)]
+fn free_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` is only read after success
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stat.assume_init()
+    };
+    #[allow(clippy::unnecessary_cast)]
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+async fn check_database(db_path: &Path) -> Check {
+    let name = format!("database {}", db_path.display());
+    if db_path.as_os_str() == db::MEMORY {
+        return Check::pass(name, "kept in memory, nothing is saved");
+    }
+    if !db_path.exists() {
+        return Check::warn(
+            name,
+            "does not exist yet",
+            "nothing to do, it is created on the first download",
+        );
+    }
+    match db::schema_version(db_path).await {
+        Ok(version) if version == db::SCHEMA_VERSION => {
+            Check::pass(name, format!("schema version {version}"))
+        }
+        Ok(version) if version < db::SCHEMA_VERSION => Check::warn(
+            name,
+            format!(
+                "schema version {version}, current is {}",
+                db::SCHEMA_VERSION
+            ),
+            "nothing to do, it is migrated on the next download",
+        ),
+        Ok(version) => Check::fail(
+            name,
+            format!(
+                "schema version {version} is newer than {} of this rxd",
+                db::SCHEMA_VERSION
+            ),
+            "upgrade rxd",
+        ),
+        Err(e) => Check::fail(
+            name,
+            e.to_string(),
+            "restore it from a backup or move it away to start over",
+        ),
+    }
+}
+
+/// Error with its sources, reqwest hides the interesting part in them
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut text = error.to_string();
+    let mut source = error.source();
+    while let Some(e) = source {
+        let _ = write!(text, ": {e}");
+        source = e.source();
+    }
+    text
+}
@@ -0,0 +1,324 @@
+//! Tweet metadata embedded in downloaded images for photo managers.
+//!
+//! JPEGs get an Exif segment with the date, author and text and an XMP
+//! segment that also holds the permalink, PNGs get the XMP packet in an
+//! `iTXt` chunk. Only metadata segments are added or replaced, the image
+//! data is copied as it is. Other formats and files that do not parse are
+//! left alone.
+
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+
+use crate::sidecar::decode_entities;
+
+/// Longest tweet text embedded, in bytes, so both segments stay within the
+/// 64 KiB a JPEG segment can hold
+const MAX_TEXT_LEN: usize = 8000;
+
+const JPEG_SOI: [u8; 2] = [0xFF, 0xD8];
+const JPEG_SOS: u8 = 0xDA;
+const JPEG_EOI: u8 = 0xD9;
+const JPEG_APP0: u8 = 0xE0;
+const JPEG_APP1: u8 = 0xE1;
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp\0";
+
+const TAG_IMAGE_DESCRIPTION: u16 = 0x010E;
+const TAG_ARTIST: u16 = 0x013B;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+
+/// What is embedded about a tweet
+#[derive(Debug, Clone)]
+pub struct ImageMetadata<'a> {
+    /// When the tweet was posted, in the offset it is written with
+    pub date: DateTime<FixedOffset>,
+    /// Screen name of the account, without `@`
+    pub author: &'a str,
+    /// Tweet text as the API returns it, with HTML entities
+    pub text: Option<&'a str>,
+    pub permalink: &'a str,
+}
+
+/// `image` with `metadata` embedded, `None` if it is not a JPEG or PNG or
+/// does not parse
+pub fn embed(image: &[u8], metadata: &ImageMetadata) -> Option<Vec<u8>> {
+    let text = metadata.text.map(|text| {
+        let text = decode_entities(text.trim());
+        let mut end = text.len().min(MAX_TEXT_LEN);
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text[..end].to_string()
+    });
+    let text = text.as_deref().filter(|t| !t.is_empty());
+    let xmp = xmp_packet(metadata, text);
+
+    if image.starts_with(&JPEG_SOI) {
+        embed_jpeg(image, &exif(metadata, text), &xmp)
+    } else if image.starts_with(&PNG_SIGNATURE) {
+        embed_png(image, &xmp)
+    } else {
+        None
+    }
+}
+
+/// Copy the segments of a JPEG up to its image data, putting `exif` and
+/// `xmp` after any `APP0` in place of earlier ones
+fn embed_jpeg(image: &[u8], exif: &[u8], xmp: &[u8]) -> Option<Vec<u8>> {
+    let segments = [
+        app1_segment(EXIF_HEADER, exif)?,
+        app1_segment(XMP_HEADER, xmp)?,
+    ];
+    let mut out = Vec::with_capacity(image.len() + exif.len() + xmp.len() + 64);
+    out.extend_from_slice(&JPEG_SOI);
+    let mut inserted = false;
+    let mut pos = JPEG_SOI.len();
+    loop {
+        if image.get(pos) != Some(&0xFF) {
+            return None;
+        }
+        let marker = *image.get(pos + 1)?;
+        // Fill bytes before a marker
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        if marker != JPEG_APP0 && !inserted {
+            segments.iter().for_each(|s| out.extend_from_slice(s));
+            inserted = true;
+        }
+        if marker == JPEG_SOS {
+            out.extend_from_slice(&image[pos..]);
+            return Some(out);
+        }
+        if marker == JPEG_EOI {
+            return None;
+        }
+        let len = u16::from_be_bytes([*image.get(pos + 2)?, *image.get(pos + 3)?]) as usize;
+        let end = pos + 2 + len;
+        if len < 2 || end > image.len() {
+            return None;
+        }
+        let payload = &image[pos + 4..end];
+        let replaced = marker == JPEG_APP1
+            && (payload.starts_with(EXIF_HEADER) || payload.starts_with(XMP_HEADER));
+        if !replaced {
+            out.extend_from_slice(&image[pos..end]);
+        }
+        pos = end;
+    }
+}
+
+/// `APP1` segment of `header` and `payload`, `None` if it is too long
+fn app1_segment(header: &[u8], payload: &[u8]) -> Option<Vec<u8>> {
+    let len = u16::try_from(2 + header.len() + payload.len()).ok()?;
+    let mut segment = vec![0xFF, JPEG_APP1];
+    segment.extend_from_slice(&len.to_be_bytes());
+    segment.extend_from_slice(header);
+    segment.extend_from_slice(payload);
+    Some(segment)
+}
+
+/// Copy the chunks of a PNG, putting `xmp` after `IHDR` in place of an
+/// earlier XMP chunk
+fn embed_png(image: &[u8], xmp: &[u8]) -> Option<Vec<u8>> {
+    let mut data = PNG_XMP_KEYWORD.to_vec();
+    // Uncompressed, no language tag or translated keyword
+    data.extend_from_slice(&[0, 0, 0, 0]);
+    data.extend_from_slice(xmp);
+    let itxt = png_chunk(b"iTXt", &data);
+
+    let mut out = Vec::with_capacity(image.len() + itxt.len());
+    out.extend_from_slice(&PNG_SIGNATURE);
+    let mut pos = PNG_SIGNATURE.len();
+    let mut first = true;
+    loop {
+        let header = image.get(pos..pos + 8)?;
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let kind = &header[4..8];
+        let end = pos.checked_add(12)?.checked_add(len)?;
+        let chunk = image.get(pos..end)?;
+        let crc = u32::from_be_bytes([
+            chunk[len + 8],
+            chunk[len + 9],
+            chunk[len + 10],
+            chunk[len + 11],
+        ]);
+        if crc32(&chunk[4..len + 8]) != crc || (first && kind != b"IHDR") {
+            return None;
+        }
+        if !(kind == b"iTXt" && chunk[8..].starts_with(PNG_XMP_KEYWORD)) {
+            out.extend_from_slice(chunk);
+        }
+        if first {
+            out.extend_from_slice(&itxt);
+            first = false;
+        }
+        pos = end;
+        if kind == b"IEND" {
+            out.extend_from_slice(&image[pos..]);
+            return Some(out);
+        }
+    }
+}
+
+fn png_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(data.len() + 12);
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    let crc = crc32(&chunk[4..]);
+    chunk.extend_from_slice(&crc.to_be_bytes());
+    chunk
+}
+
+/// CRC-32 of PNG chunks
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xEDB8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+/// Value of a TIFF field
+enum Field {
+    /// NUL-terminated text
+    Ascii(Vec<u8>),
+    Long(u32),
+}
+
+impl Field {
+    fn ascii(text: &str) -> Self {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.push(0);
+        Field::Ascii(bytes)
+    }
+}
+
+/// Big-endian TIFF structure of the Exif segment
+fn exif(metadata: &ImageMetadata, text: Option<&str>) -> Vec<u8> {
+    let mut ifd0 = Vec::new();
+    if let Some(text) = text {
+        ifd0.push((TAG_IMAGE_DESCRIPTION, Field::ascii(text)));
+    }
+    ifd0.push((TAG_ARTIST, Field::ascii(&format!("@{}", metadata.author))));
+    // Pointed at once the Exif IFD's position is known
+    ifd0.push((TAG_EXIF_IFD, Field::Long(0)));
+    let exif_ifd = [
+        (
+            TAG_DATE_TIME_ORIGINAL,
+            Field::ascii(&metadata.date.format("%Y:%m:%d %H:%M:%S").to_string()),
+        ),
+        (
+            TAG_OFFSET_TIME_ORIGINAL,
+            Field::ascii(&metadata.date.format("%:z").to_string()),
+        ),
+    ];
+
+    let mut tiff = b"MM\0\x2A\0\0\0\x08".to_vec();
+    let pointer = write_ifd(&mut tiff, &ifd0) + 2 + 12 * (ifd0.len() - 1) + 8;
+    let exif_offset = tiff.len() as u32;
+    tiff[pointer..pointer + 4].copy_from_slice(&exif_offset.to_be_bytes());
+    write_ifd(&mut tiff, &exif_ifd);
+    tiff
+}
+
+/// Append an IFD of `entries`, sorted by tag, followed by the values that
+/// do not fit in an entry, and return where it starts
+fn write_ifd(tiff: &mut Vec<u8>, entries: &[(u16, Field)]) -> usize {
+    let start = tiff.len();
+    let data_start = start + 2 + 12 * entries.len() + 4;
+    let mut data = Vec::new();
+    tiff.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+    for (tag, field) in entries {
+        tiff.extend_from_slice(&tag.to_be_bytes());
+        match field {
+            Field::Ascii(bytes) => {
+                tiff.extend_from_slice(&2u16.to_be_bytes());
+                tiff.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+                if bytes.len() <= 4 {
+                    let mut inline = [0u8; 4];
+                    inline[..bytes.len()].copy_from_slice(bytes);
+                    tiff.extend_from_slice(&inline);
+                } else {
+                    tiff.extend_from_slice(&((data_start + data.len()) as u32).to_be_bytes());
+                    data.extend_from_slice(bytes);
+                    // Values start on a word boundary
+                    if bytes.len() % 2 == 1 {
+                        data.push(0);
+                    }
+                }
+            }
+            Field::Long(value) => {
+                tiff.extend_from_slice(&4u16.to_be_bytes());
+                tiff.extend_from_slice(&1u32.to_be_bytes());
+                tiff.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+    }
+    // No next IFD
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+    tiff.extend_from_slice(&data);
+    start
+}
+
+/// XMP packet with the date, author, text and permalink
+fn xmp_packet(metadata: &ImageMetadata, text: Option<&str>) -> Vec<u8> {
+    let date = metadata.date.to_rfc3339_opts(SecondsFormat::Secs, false);
+    let description = text
+        .map(|text| {
+            format!(
+                "\n   <dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>",
+                escape_xml(text)
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>
+<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">
+ <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">
+  <rdf:Description rdf:about=\"\"
+    xmlns:dc=\"http://purl.org/dc/elements/1.1/\"
+    xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"
+    xmlns:photoshop=\"http://ns.adobe.com/photoshop/1.0/\">
+   <xmp:CreateDate>{date}</xmp:CreateDate>
+   <photoshop:DateCreated>{date}</photoshop:DateCreated>
+   <dc:creator><rdf:Seq><rdf:li>@{author}</rdf:li></rdf:Seq></dc:creator>{description}
+   <dc:source>{permalink}</dc:source>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end=\"w\"?>",
+        author = escape_xml(metadata.author),
+        permalink = escape_xml(metadata.permalink),
+    )
+    .into_bytes()
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // Not allowed in XML
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => {}
+            _ => out.push(c),
+        }
+    }
+    out
+}
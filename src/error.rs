@@ -0,0 +1,179 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+use crate::api::AuthFailure;
+use crate::events::Totals;
+use crate::lock::LockHolder;
+
+/// Errors returned by rxd
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// Transport-level HTTP failure
+    #[error("http error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The API answered with a non-success status
+    #[error("API error: {status}")]
+    Api { status: StatusCode, body: String },
+
+    /// The API rejected the `auth_token` and `ct0` cookies
+    #[error("credentials rejected: {0}")]
+    Auth(AuthFailure),
+
+    /// Guest mode hit something only an account can see, e.g. a protected
+    /// account
+    #[error("{0} is not available to guests, set auth_token and ct0 instead of using guest mode")]
+    GuestUnsupported(String),
+
+    /// A media download answered with a non-success status
+    #[error("download failed: {0}")]
+    Download(StatusCode),
+
+    /// A media host answered 429 or 503 and asked to come back after
+    /// `retry_after`
+    #[error("download pushed back: {status}, retry after {}s", retry_after.as_secs())]
+    PushedBack {
+        status: StatusCode,
+        retry_after: Duration,
+    },
+
+    /// The syndication API does not show the tweet, e.g. as it is of a
+    /// protected account or has sensitive media
+    #[error("syndication API cannot serve tweet {tweet_id}: {reason}")]
+    SyndicationUnavailable { tweet_id: String, reason: String },
+
+    /// Neither a screen name nor a profile URL, as given
+    #[error("invalid screen name {0:?}, expected a handle such as \"nasa\" or a profile URL")]
+    InvalidScreenName(String),
+
+    /// The account is suspended, deactivated or otherwise unavailable
+    #[error("user @{screen_name} is unavailable: {reason}")]
+    UserUnavailable { screen_name: String, reason: String },
+
+    /// A response did not have the expected shape
+    #[error("unexpected response: {0}")]
+    Parse(String),
+
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    /// The database file cannot be created, opened or written
+    #[error("cannot open database {}: {reason}", path.display())]
+    DatabaseOpen { path: PathBuf, reason: String },
+
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("invalid header: {0}")]
+    Header(String),
+
+    /// Invalid or missing configuration
+    #[error("config error: {0}")]
+    Config(String),
+
+    /// No file name for the media fits the filesystem limits
+    #[error("file name too long for {0}")]
+    FilenameTooLong(String),
+
+    /// A download is larger than the configured maximum, `size` is what
+    /// was announced or received when it was stopped
+    #[error("{size} bytes exceed the limit of {limit} bytes")]
+    TooLarge { size: u64, limit: u64 },
+
+    /// A conditional request found the recorded file still current
+    #[error("not modified")]
+    NotModified,
+
+    /// An HLS video that cannot be downloaded, e.g. without ffmpeg
+    #[error("HLS unsupported: {0}")]
+    HlsUnsupported(String),
+
+    /// A file read for an archive differs from its recorded hash
+    #[error("{} does not match its recorded hash", .0.display())]
+    HashMismatch(PathBuf),
+
+    /// A thumbnail could not be made
+    #[error("thumbnail error: {0}")]
+    Thumbnail(String),
+
+    /// An image whose perceptual hash could not be taken
+    #[error("perceptual hash error: {0}")]
+    PerceptualHash(String),
+
+    /// Another process holds the lock of the database
+    #[error(
+        "{} is held by another rxd run{}",
+        .path.display(),
+        .holder.as_ref().map(|h| format!(" ({h})")).unwrap_or_default()
+    )]
+    Locked {
+        path: PathBuf,
+        holder: Option<LockHolder>,
+    },
+
+    /// A downloaded image could not be converted by `post_process`
+    #[error("conversion error: {0}")]
+    Conversion(String),
+
+    /// The post-download hook could not be run or failed
+    #[error("hook error: {0}")]
+    Hook(String),
+
+    /// The desktop notification could not be shown
+    #[error("notification error: {0}")]
+    Notification(String),
+
+    #[error("timed out after {}s", .0.as_secs())]
+    Timeout(Duration),
+
+    /// The network stayed down longer than the task waits for it, `totals`
+    /// is what it got through before
+    #[error("network down for over {}s after {} downloads", .outage.as_secs(), .totals.downloaded)]
+    NetworkDown { outage: Duration, totals: Totals },
+
+    /// A page of the timeline could not be fetched, `totals` is what the
+    /// pages before it downloaded
+    #[error("failed to fetch page {page} after {} downloads: {source}", .totals.downloaded)]
+    FetchFailed {
+        page: u32,
+        source: Box<Error>,
+        totals: Totals,
+    },
+
+    /// The task was cancelled, `totals` is what it got through before stopping
+    #[error("cancelled after {} downloads", .totals.downloaded)]
+    Cancelled { totals: Totals },
+}
+
+impl Error {
+    /// What a task got through before it failed with this error
+    pub fn totals(&self) -> Option<Totals> {
+        match self {
+            Error::NetworkDown { totals, .. }
+            | Error::FetchFailed { totals, .. }
+            | Error::Cancelled { totals } => Some(*totals),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::header::InvalidHeaderValue> for Error {
+    fn from(e: reqwest::header::InvalidHeaderValue) -> Self {
+        Error::Header(e.to_string())
+    }
+}
+
+impl From<reqwest::header::InvalidHeaderName> for Error {
+    fn from(e: reqwest::header::InvalidHeaderName) -> Self {
+        Error::Header(e.to_string())
+    }
+}
+
+/// Result type used throughout rxd
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,131 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+/// Sender half of an event channel attached to a task
+pub type EventSender = mpsc::UnboundedSender<Event>;
+
+/// Progress event emitted while a task runs
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    TaskStarted {
+        screen_name: String,
+    },
+    PageFetched {
+        screen_name: String,
+        page: u32,
+        count: usize,
+        cursor: Option<String>,
+    },
+    /// A media request is about to be sent
+    DownloadStarted {
+        screen_name: String,
+        tweet_id: String,
+        url: String,
+    },
+    /// Bytes received so far for a download, `total` if the server sent a length
+    DownloadProgress {
+        screen_name: String,
+        url: String,
+        bytes: u64,
+        total: Option<u64>,
+    },
+    ItemDownloaded {
+        screen_name: String,
+        tweet_id: String,
+        url: String,
+        path: PathBuf,
+        bytes: u64,
+    },
+    ItemSkipped {
+        screen_name: String,
+        tweet_id: String,
+        url: String,
+        reason: SkipReason,
+    },
+    ItemFailed {
+        screen_name: String,
+        tweet_id: String,
+        url: String,
+        error: String,
+    },
+    TaskFinished {
+        screen_name: String,
+        totals: Totals,
+    },
+}
+
+/// Why an item was not downloaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// File on disk matches the hash recorded in the database
+    Verified,
+    /// File already exists on disk
+    Exists,
+    /// Left out by the task's filters, e.g. a retweet
+    Filtered,
+    /// Larger than the maximum file size
+    TooLarge,
+    /// Downloaded by another downloader, recorded by `rxd import`
+    Imported,
+    /// Recorded file is still current according to the server
+    NotModified,
+    /// The server answered 404 or 410 for it in an earlier run
+    Gone,
+}
+
+/// Per-task counters
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Totals {
+    pub fetched: usize,
+    pub downloaded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// Items left out by the task's filters
+    pub filtered: usize,
+    /// Downloads stopped for exceeding the maximum file size
+    pub too_large: usize,
+    /// Bytes written by new downloads
+    pub bytes: u64,
+    /// New downloads whose post-download hook failed
+    pub hook_failures: usize,
+    /// Downloads that replaced a file on disk in overwrite mode
+    pub replaced: usize,
+    /// Items skipped as gone from the server in an earlier run
+    pub gone: usize,
+    /// Images converted by `post_process`
+    pub converted: usize,
+    /// Images `post_process` failed to convert, kept as downloaded
+    pub unconverted: usize,
+}
+
+/// Machine-readable event stream format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EventFormat {
+    /// One JSON object per line on stdout
+    Ndjson,
+}
+
+/// Write events from a channel to stdout as newline-delimited JSON
+///
+/// Returns once every sender has been dropped. Download progress events are
+/// left out, they are too frequent to be useful in a line-based stream.
+pub async fn write_ndjson(mut rx: mpsc::UnboundedReceiver<Event>) {
+    while let Some(event) = rx.recv().await {
+        if matches!(event, Event::DownloadProgress { .. }) {
+            continue;
+        }
+        let Ok(line) = serde_json::to_string(&event) else {
+            continue;
+        };
+        let mut stdout = io::stdout().lock();
+        let _ = writeln!(stdout, "{line}");
+        let _ = stdout.flush();
+    }
+}
@@ -0,0 +1,319 @@
+//! File name templates and fitting names within filesystem limits.
+//!
+//! Names are measured in bytes, the unit ext4 and most other filesystems
+//! limit them in. UTF-16 based filesystems count at most as many units as
+//! there are UTF-8 bytes, so the same limit is safe there.
+
+use std::borrow::Cow;
+use std::path::Path;
+use std::str::FromStr;
+
+use chrono::{DateTime, FixedOffset, Local, Utc};
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
+
+/// Characters separating placeholders, dropped next to empty values
+const SEPARATORS: [char; 4] = ['-', '_', ' ', '.'];
+
+/// Default maximum length of the `{text}` slug, in characters
+pub const DEFAULT_TEXT_LENGTH: usize = 60;
+
+/// Longest file name ext4, NTFS and APFS accept
+pub const MAX_FILENAME_BYTES: usize = 255;
+
+/// Longest path Windows accepts without long path support
+#[cfg(windows)]
+const MAX_PATH_BYTES: usize = 260;
+
+/// Room after the stem for a collision counter (`-999`), the extension
+/// (`.webp`) and the suffix of partial downloads (`.part`)
+const RESERVED_BYTES: usize = 4 + 5 + 5;
+
+/// A piece of a file name stem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment<'a> {
+    text: Cow<'a, str>,
+    shortenable: bool,
+}
+
+impl<'a> Segment<'a> {
+    /// Kept as is, e.g. IDs that make the name unique
+    pub fn fixed(text: impl Into<Cow<'a, str>>) -> Self {
+        Self {
+            text: text.into(),
+            shortenable: false,
+        }
+    }
+
+    /// May be shortened to make the name fit, a leading separator can be
+    /// part of it
+    pub fn variable(text: impl Into<Cow<'a, str>>) -> Self {
+        Self {
+            text: text.into(),
+            shortenable: true,
+        }
+    }
+}
+
+/// Longest stem in bytes for files created in `dir`
+pub fn max_stem_len(dir: &Path) -> usize {
+    #[cfg(windows)]
+    let max = MAX_FILENAME_BYTES.min(MAX_PATH_BYTES.saturating_sub(dir.as_os_str().len() + 1));
+    #[cfg(not(windows))]
+    let max = {
+        let _ = dir;
+        MAX_FILENAME_BYTES
+    };
+    max.saturating_sub(RESERVED_BYTES)
+}
+
+/// Join segments into a stem of at most `max_bytes`
+///
+/// Variable segments are shortened longest first, on character boundaries,
+/// and lose separators left dangling at their end. Returns `None` when the
+/// fixed segments alone are too long.
+pub fn fit(segments: &[Segment], max_bytes: usize) -> Option<String> {
+    let mut texts: Vec<&str> = segments.iter().map(|s| s.text.as_ref()).collect();
+    let mut len: usize = texts.iter().map(|t| t.len()).sum();
+
+    while len > max_bytes {
+        let longest = segments
+            .iter()
+            .zip(&texts)
+            .enumerate()
+            .filter(|(_, (segment, text))| segment.shortenable && !text.is_empty())
+            .max_by_key(|(_, (_, text))| text.len())
+            .map(|(i, _)| i)?;
+        let text = texts[longest];
+        let cut = text.char_indices().next_back().map_or(0, |(i, _)| i);
+        let shortened = text[..cut].trim_end_matches(SEPARATORS);
+        len -= text.len() - shortened.len();
+        texts[longest] = shortened;
+    }
+
+    Some(texts.concat())
+}
+
+/// Timezone that `{date}` and `{time}` are rendered in
+///
+/// Changing it renames files of tweets posted near midnight, which are then
+/// downloaded again unless the database already records them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Timezone {
+    /// Timezone of the machine rxd runs on
+    #[default]
+    Local,
+    Utc,
+    /// Fixed offset like `+09:00`
+    Fixed(FixedOffset),
+}
+
+impl Timezone {
+    /// `timestamp` in this timezone
+    pub fn convert(&self, timestamp: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        match self {
+            Timezone::Local => timestamp.with_timezone(&Local).fixed_offset(),
+            Timezone::Utc => timestamp.with_timezone(&Utc).fixed_offset(),
+            Timezone::Fixed(offset) => timestamp.with_timezone(offset),
+        }
+    }
+}
+
+impl FromStr for Timezone {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            _ if s.eq_ignore_ascii_case("local") => Ok(Timezone::Local),
+            _ if s.eq_ignore_ascii_case("utc") || s == "Z" => Ok(Timezone::Utc),
+            _ => s.parse::<FixedOffset>().map(Timezone::Fixed).map_err(|_| {
+                Error::Config(format!(
+                    "invalid timezone {s}, expected \"local\", \"UTC\" or an offset like \"+09:00\""
+                ))
+            }),
+        }
+    }
+}
+
+impl TryFrom<String> for Timezone {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// Template for the stem of media file names
+///
+/// Supported placeholders: `{date}`, `{time}` (`HHMMSS`), `{tweet_id}`,
+/// `{media_id}`, `{index}`
+/// (`01`, `02`, … for tweets with several media, empty otherwise),
+/// `{screen_name}` and `{text}` (a slug of the tweet text). Separators next
+/// to a placeholder that renders empty are dropped.
+#[derive(Debug, Clone)]
+pub struct Template {
+    parts: Vec<Part>,
+    text_length: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Date,
+    Time,
+    TweetId,
+    MediaId,
+    Index,
+    ScreenName,
+    Text,
+}
+
+/// Values substituted into a [`Template`]
+pub struct Fields<'a> {
+    pub date: &'a str,
+    pub time: &'a str,
+    pub tweet_id: &'a str,
+    pub media_id: &'a str,
+    pub index: Option<usize>,
+    pub screen_name: &'a str,
+    pub text: Option<&'a str>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..].find('}').ok_or_else(|| {
+                Error::Config(format!(
+                    "unclosed placeholder in filename_template: {template}"
+                ))
+            })?;
+            parts.push(match &rest[start + 1..start + end] {
+                "date" => Part::Date,
+                "time" => Part::Time,
+                "tweet_id" => Part::TweetId,
+                "media_id" => Part::MediaId,
+                "index" => Part::Index,
+                "screen_name" => Part::ScreenName,
+                "text" => Part::Text,
+                other => {
+                    return Err(Error::Config(format!(
+                        "unknown placeholder {{{other}}} in filename_template"
+                    )));
+                }
+            });
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        if parts.iter().all(|p| matches!(p, Part::Literal(_))) {
+            return Err(Error::Config(
+                "filename_template has no placeholders".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            parts,
+            text_length: DEFAULT_TEXT_LENGTH,
+        })
+    }
+
+    /// Maximum length of the `{text}` slug, in characters
+    pub fn text_length(mut self, text_length: usize) -> Self {
+        self.text_length = text_length;
+        self
+    }
+
+    /// Segments of the stem for one media, to be joined with [`fit`]
+    pub fn render(&self, fields: &Fields) -> Vec<Segment<'static>> {
+        let mut segments: Vec<Segment<'static>> = Vec::new();
+        // Separators at the end of the last literal, they belong to the next
+        // value and go away together with it
+        let mut separator = String::new();
+
+        for part in &self.parts {
+            let (value, shortenable) = match part {
+                Part::Literal(literal) => {
+                    // Nothing before it, e.g. after an empty value at the start
+                    let literal = if segments.is_empty() && separator.is_empty() {
+                        literal.trim_start_matches(SEPARATORS)
+                    } else {
+                        literal
+                    };
+                    let kept = literal.trim_end_matches(SEPARATORS);
+                    if !kept.is_empty() {
+                        segments.push(Segment::fixed(format!("{separator}{kept}")));
+                        separator.clear();
+                    }
+                    separator.push_str(&literal[kept.len()..]);
+                    continue;
+                }
+                Part::Date => (fields.date.to_string(), false),
+                Part::Time => (fields.time.to_string(), false),
+                Part::TweetId => (fields.tweet_id.to_string(), false),
+                Part::MediaId => (fields.media_id.to_string(), false),
+                Part::Index => (
+                    fields.index.map(|i| format!("{i:02}")).unwrap_or_default(),
+                    false,
+                ),
+                Part::ScreenName => (fields.screen_name.to_string(), true),
+                Part::Text => (
+                    fields
+                        .text
+                        .map(|text| slug(text, self.text_length))
+                        .unwrap_or_default(),
+                    true,
+                ),
+            };
+
+            let separator = std::mem::take(&mut separator);
+            if value.is_empty() {
+                continue;
+            }
+            let text = if segments.is_empty() {
+                value
+            } else {
+                format!("{separator}{value}")
+            };
+            segments.push(if shortenable {
+                Segment::variable(text)
+            } else {
+                Segment::fixed(text)
+            });
+        }
+        segments
+    }
+}
+
+/// Filesystem-safe slug of a tweet text, at most `max_chars` characters
+///
+/// Links and mentions are dropped, letters and digits of any script are
+/// kept in lowercase and everything else, including emoji, becomes a dash.
+pub fn slug(text: &str, max_chars: usize) -> String {
+    let mut slug = String::new();
+    let words = text.split_whitespace().filter(|word| {
+        !(word.starts_with('@') || word.starts_with("http://") || word.starts_with("https://"))
+    });
+    for word in words {
+        for c in word.chars() {
+            if c.is_alphanumeric() {
+                slug.extend(c.to_lowercase());
+            } else if !slug.is_empty() && !slug.ends_with('-') {
+                slug.push('-');
+            }
+        }
+        if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    let slug: String = slug.chars().take(max_chars).collect();
+    slug.trim_matches('-').to_string()
+}
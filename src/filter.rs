@@ -0,0 +1,91 @@
+//! Filters deciding which tweets a task downloads.
+
+use std::str::FromStr;
+
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
+
+/// Regular expression matched case-insensitively anywhere in a tweet's text
+///
+/// A plain word like `giveaway` matches as a substring.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TextPattern(Regex);
+
+impl TextPattern {
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl FromStr for TextPattern {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        RegexBuilder::new(s)
+            .case_insensitive(true)
+            .build()
+            .map(TextPattern)
+            .map_err(|e| Error::Config(format!("invalid text pattern {s:?}: {e}")))
+    }
+}
+
+impl TryFrom<String> for TextPattern {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// Whether `text` matches any of `patterns`
+pub fn matches_any(patterns: &[TextPattern], text: &str) -> bool {
+    patterns.iter().any(|p| p.is_match(text))
+}
+
+/// Size limit like `50MB` or `1.5GiB`, in bytes
+///
+/// `KB`, `MB` and `GB` are powers of 1000, `KiB`, `MiB` and `GiB` powers of
+/// 1024. A plain number is bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct FileSize(pub u64);
+
+impl FromStr for FileSize {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::Config(format!("invalid file size {s:?}, expected e.g. \"50MB\""));
+        let s = s.trim();
+        let split = s
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let number: f64 = number.parse().map_err(|_| invalid())?;
+        let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "k" | "kb" => 1000,
+            "m" | "mb" => 1000 * 1000,
+            "g" | "gb" => 1000 * 1000 * 1000,
+            "kib" => 1 << 10,
+            "mib" => 1 << 20,
+            "gib" => 1 << 30,
+            _ => return Err(invalid()),
+        };
+        Ok(FileSize((number * multiplier as f64) as u64))
+    }
+}
+
+impl TryFrom<String> for FileSize {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
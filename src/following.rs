@@ -0,0 +1,151 @@
+//! Accounts followed by an account, downloaded like configured tasks.
+
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{debug, info, instrument};
+
+use crate::api::Api;
+use crate::error::{Error, Result};
+use crate::graphql::{FollowingData, Instruction, Response, UserResult};
+use crate::task::User;
+
+/// `[following]` section of the config
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FollowingConfig {
+    /// Account whose follows are downloaded, the one of the credentials if
+    /// not set
+    #[serde(default)]
+    pub screen_name: Option<String>,
+    /// Accounts left out, ignoring case and a leading `@`
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Download at most this many accounts, the most recently followed first
+    #[serde(default)]
+    pub limit_accounts: Option<usize>,
+}
+
+impl FollowingConfig {
+    /// Whether `screen_name` is on the exclude list
+    pub fn excludes(&self, screen_name: &str) -> bool {
+        self.exclude.iter().any(|excluded| {
+            excluded
+                .trim_start_matches('@')
+                .eq_ignore_ascii_case(screen_name)
+        })
+    }
+}
+
+/// One page of a timeline of accounts, e.g. Following or ListMembers
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct AccountPage {
+    pub accounts: Vec<User>,
+    /// Suspended or deactivated accounts listed without their details
+    pub unavailable: usize,
+    /// Cursor of the next page, `None` on the last one
+    pub next_cursor: Option<String>,
+}
+
+/// Parse a Following response into accounts and the cursor of the next page
+#[instrument(skip_all)]
+pub fn parse_following_response(raw: &Value) -> Result<AccountPage> {
+    let response = Response::<FollowingData>::deserialize(raw)
+        .map_err(|e| Error::Parse(format!("invalid Following response: {e}")))?;
+
+    let instructions = response
+        .data
+        .and_then(|d| d.user)
+        .and_then(|u| u.result)
+        .and_then(|r| r.timeline_v2)
+        .and_then(|t| t.timeline)
+        .map(|t| t.instructions)
+        .ok_or_else(|| Error::Parse("failed to find instructions".to_string()))?;
+
+    Ok(account_page(&instructions))
+}
+
+/// Accounts and the cursor of the next page in the instructions of a
+/// timeline of accounts
+pub(crate) fn account_page(instructions: &[Instruction]) -> AccountPage {
+    let mut page = AccountPage::default();
+    for entry in instructions.iter().flat_map(|i| &i.entries) {
+        if entry.entry_id.starts_with("cursor-bottom")
+            && let Some(cursor_value) = &entry.content.value
+        {
+            page.next_cursor = Some(cursor_value.clone());
+        }
+        let result = entry
+            .content
+            .item_content
+            .as_ref()
+            .and_then(|c| c.user_results.as_ref())
+            .and_then(|u| u.result.as_ref());
+        match result {
+            Some(UserResult::User(user)) => match &user.legacy.screen_name {
+                Some(screen_name) => page.accounts.push(User {
+                    screen_name: screen_name.clone(),
+                    name: user.legacy.name.clone(),
+                    rest_id: user.rest_id.clone(),
+                    media_count: user.legacy.media_count,
+                }),
+                None => debug!("account {} listed without a screen name", user.rest_id),
+            },
+            Some(UserResult::UserUnavailable { .. }) => page.unavailable += 1,
+            Some(UserResult::Unknown) | None => {}
+        }
+    }
+    page
+}
+
+/// Accounts followed by the configured account, without excluded ones and
+/// up to the limit, in the order they are listed
+#[instrument(skip_all)]
+pub async fn followed_accounts(api: &Api, config: &FollowingConfig) -> Result<Vec<User>> {
+    let screen_name = match &config.screen_name {
+        Some(screen_name) => screen_name.trim_start_matches('@').to_string(),
+        None => api.verify_credentials().await?,
+    };
+    let user = api.user_by_screen_name(&screen_name).await?;
+
+    let mut accounts = Vec::new();
+    let (mut excluded, mut unavailable) = (0, 0);
+    let mut cursor = None;
+    loop {
+        let page = api.following(&user, cursor.as_deref()).await?;
+        unavailable += page.unavailable;
+        let listed = page.accounts.len() + page.unavailable;
+        for account in page.accounts {
+            if config.excludes(&account.screen_name) {
+                excluded += 1;
+            } else if !accounts
+                .iter()
+                .any(|known: &User| known.rest_id == account.rest_id)
+            {
+                accounts.push(account);
+            }
+        }
+        if config
+            .limit_accounts
+            .is_some_and(|limit| accounts.len() >= limit)
+        {
+            break;
+        }
+        // The last page repeats its cursor without any accounts
+        match page.next_cursor {
+            Some(next) if listed > 0 && cursor.as_ref() != Some(&next) => cursor = Some(next),
+            _ => break,
+        }
+    }
+    if let Some(limit) = config.limit_accounts {
+        accounts.truncate(limit);
+    }
+
+    info!(
+        "@{} follows {} accounts to download, {} excluded, {} unavailable",
+        screen_name,
+        accounts.len(),
+        excluded,
+        unavailable
+    );
+    Ok(accounts)
+}
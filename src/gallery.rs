@@ -0,0 +1,400 @@
+//! `rxd gallery`: a static HTML gallery of an account's downloaded files.
+//!
+//! The gallery is an `index.html` with the newest files first, grouped by
+//! month and split into `page-<n>.html` pages of
+//! [`GalleryOptions::page_size`] files, plus a `gallery.css` next to them.
+//! Thumbnails are those made by `rxd thumbs`, the files themselves without
+//! one, or the poster saved next to a video, and link to the files where they are; nothing is loaded from the
+//! network. Pages do not change unless the archive does, so generating
+//! into the same folder again only rewrites what changed and removes pages
+//! left over from a larger gallery.
+
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+
+use chrono::NaiveDate;
+use sqlx::SqlitePool;
+use tracing::{debug, info, instrument, warn};
+
+use crate::db::{self, DownloadedMedia};
+use crate::error::Result;
+use crate::task::{permalink, thumbnail_path};
+use crate::thumbs::{ThumbFormat, thumb_path};
+use crate::verify::VIDEO_EXTENSIONS;
+
+/// Name of the first page
+pub const INDEX: &str = "index.html";
+
+/// Name of the stylesheet shared by the pages
+pub const STYLESHEET: &str = "gallery.css";
+
+const CSS: &str = "\
+body { margin: 0 auto; max-width: 1400px; padding: 1em; font-family: sans-serif; background: #111; color: #ddd; }
+a { color: #8cf; }
+h2 { border-bottom: 1px solid #333; padding-bottom: .2em; }
+nav { margin: 1em 0; line-height: 2; }
+nav a, nav span { padding: .2em .5em; }
+nav span { border: 1px solid #555; }
+.grid { display: grid; grid-template-columns: repeat(auto-fill, minmax(200px, 1fr)); gap: 1em; }
+figure { margin: 0; background: #1b1b1b; }
+figure img, figure .placeholder { display: block; width: 100%; aspect-ratio: 1; object-fit: cover; background: #222; }
+figure .placeholder { display: flex; align-items: center; justify-content: center; font-size: 3em; color: #666; }
+figure a.media { position: relative; display: block; }
+figure a.video::after { content: \"\\25B6\"; position: absolute; right: .4em; bottom: .3em; color: #fff; text-shadow: 0 0 4px #000; }
+figcaption { padding: .4em; font-size: .85em; }
+figcaption p { margin: .3em 0; overflow: hidden; display: -webkit-box; -webkit-line-clamp: 4; -webkit-box-orient: vertical; white-space: pre-wrap; }
+";
+
+/// How the gallery is laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct GalleryOptions {
+    /// Files per page
+    pub page_size: usize,
+}
+
+impl Default for GalleryOptions {
+    fn default() -> Self {
+        Self { page_size: 500 }
+    }
+}
+
+impl GalleryOptions {
+    /// Files per page
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size;
+        self
+    }
+}
+
+/// What went into a gallery
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GallerySummary {
+    /// Files shown
+    pub items: u64,
+    pub pages: u64,
+    /// Pages and stylesheet written, the others were up to date
+    pub written: u64,
+    /// Videos shown with their poster
+    pub posters: u64,
+    /// Recorded files not on disk, left out
+    pub missing: u64,
+}
+
+/// Generate the gallery of the downloaded files of `screen_name` in
+/// `folder` into the folder `out`
+#[instrument(skip_all, fields(user = screen_name))]
+pub async fn generate(
+    pool: &SqlitePool,
+    screen_name: &str,
+    folder: &Path,
+    out: &Path,
+    options: &GalleryOptions,
+) -> Result<GallerySummary> {
+    let media = db::downloaded_media(pool, screen_name, None, None).await?;
+    info!(
+        "generating a gallery of {} files of @{}",
+        media.len(),
+        screen_name
+    );
+
+    let job = GalleryJob {
+        screen_name: screen_name.to_string(),
+        folder: folder.to_path_buf(),
+        page_size: options.page_size.max(1),
+        media,
+    };
+    let out = out.to_path_buf();
+    tokio::task::spawn_blocking(move || job.write(&out))
+        .await
+        .map_err(io::Error::other)?
+}
+
+/// Everything a gallery needs, moved to a blocking thread
+struct GalleryJob {
+    screen_name: String,
+    folder: PathBuf,
+    page_size: usize,
+    media: Vec<DownloadedMedia>,
+}
+
+/// A file shown in the gallery
+struct Item<'a> {
+    media: &'a DownloadedMedia,
+    /// Link to the file
+    href: String,
+    /// Link to the thumbnail, `None` for videos without a poster
+    thumbnail: Option<String>,
+    is_video: bool,
+}
+
+impl GalleryJob {
+    fn write(&self, out: &Path) -> Result<GallerySummary> {
+        fs::create_dir_all(out)?;
+        let out = absolute(out)?;
+        let folder = absolute(&self.folder)?;
+        let mut summary = GallerySummary::default();
+
+        let mut items = Vec::new();
+        for media in self.media.iter().rev() {
+            let path = folder.join(&media.filename);
+            if !path.is_file() {
+                debug!("not on disk, leaving out: {}", path.display());
+                summary.missing += 1;
+                continue;
+            }
+            let is_video = is_video(&path);
+            let thumbnail = if is_video {
+                Some(thumbnail_path(&path)).filter(|poster| poster.is_file())
+            } else {
+                Some(image_thumb(&folder, &media.filename).unwrap_or_else(|| path.clone()))
+            };
+            if is_video && thumbnail.is_some() {
+                summary.posters += 1;
+            }
+            items.push(Item {
+                media,
+                href: href(&out, &path),
+                thumbnail: thumbnail.map(|thumbnail| href(&out, &thumbnail)),
+                is_video,
+            });
+        }
+        if summary.missing > 0 {
+            warn!("{} recorded files are not on disk", summary.missing);
+        }
+        summary.items = items.len() as u64;
+
+        let pages: Vec<&[Item]> = if items.is_empty() {
+            vec![&[]]
+        } else {
+            items.chunks(self.page_size).collect()
+        };
+        summary.pages = pages.len() as u64;
+        if write_if_changed(&out.join(STYLESHEET), CSS)? {
+            summary.written += 1;
+        }
+        for (index, page) in pages.iter().enumerate() {
+            let html = self.render_page(page, index + 1, pages.len(), items.len());
+            if write_if_changed(&out.join(page_name(index + 1)), &html)? {
+                summary.written += 1;
+            }
+        }
+        remove_stale_pages(&out, pages.len())?;
+        Ok(summary)
+    }
+
+    fn render_page(&self, items: &[Item], number: usize, pages: usize, total: usize) -> String {
+        let title = match pages {
+            1 => format!("@{}", self.screen_name),
+            _ => format!("@{} - page {number} of {pages}", self.screen_name),
+        };
+        let nav = navigation(number, pages);
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+             <title>{}</title>\n<link rel=\"stylesheet\" href=\"{STYLESHEET}\">\n</head>\n<body>\n\
+             <header>\n<h1>@{}</h1>\n<p>{total} files</p>\n</header>\n{nav}",
+            escape(&title),
+            escape(&self.screen_name),
+        );
+
+        let mut month = None;
+        for item in items {
+            let media = item.media;
+            let item_month = media.tweet_time.get(..7).unwrap_or(&media.tweet_time);
+            if month != Some(item_month) {
+                if month.is_some() {
+                    html.push_str("</div>\n</section>\n");
+                }
+                month = Some(item_month);
+                html.push_str(&format!(
+                    "<section>\n<h2 id=\"{}\">{}</h2>\n<div class=\"grid\">\n",
+                    escape(item_month),
+                    escape(&month_label(item_month))
+                ));
+            }
+            let thumbnail = match &item.thumbnail {
+                Some(src) => {
+                    format!("<img src=\"{src}\" loading=\"lazy\" decoding=\"async\" alt=\"\">")
+                }
+                None => "<span class=\"placeholder\">&#9654;</span>".to_string(),
+            };
+            let class = if item.is_video {
+                "media video"
+            } else {
+                "media"
+            };
+            let text = media
+                .full_text
+                .as_deref()
+                .filter(|text| !text.trim().is_empty())
+                .map(|text| format!("<p>{}</p>", escape(text)))
+                .unwrap_or_default();
+            html.push_str(&format!(
+                "<figure>\n<a class=\"{class}\" href=\"{}\">{thumbnail}</a>\n\
+                 <figcaption><time>{}</time> <a href=\"{}\">tweet</a>{text}</figcaption>\n</figure>\n",
+                item.href,
+                escape(media.tweet_time.get(..16).unwrap_or(&media.tweet_time)),
+                escape(&permalink(&self.screen_name, &media.tweet_id)),
+            ));
+        }
+        if month.is_some() {
+            html.push_str("</div>\n</section>\n");
+        }
+        html.push_str(&nav);
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+/// File name of page `number`, counting from 1
+fn page_name(number: usize) -> String {
+    match number {
+        1 => INDEX.to_string(),
+        _ => format!("page-{number}.html"),
+    }
+}
+
+/// Links to the other pages, empty for a single page
+fn navigation(number: usize, pages: usize) -> String {
+    if pages < 2 {
+        return String::new();
+    }
+    let mut nav = String::from("<nav>");
+    if number > 1 {
+        nav.push_str(&format!("<a href=\"{}\">newer</a> ", page_name(number - 1)));
+    }
+    for page in 1..=pages {
+        if page == number {
+            nav.push_str(&format!("<span>{page}</span> "));
+        } else {
+            nav.push_str(&format!("<a href=\"{}\">{page}</a> ", page_name(page)));
+        }
+    }
+    if number < pages {
+        nav.push_str(&format!("<a href=\"{}\">older</a>", page_name(number + 1)));
+    }
+    nav.push_str("</nav>\n");
+    nav
+}
+
+/// `March 2025` for `2025-03`
+fn month_label(month: &str) -> String {
+    NaiveDate::parse_from_str(&format!("{month}-01"), "%Y-%m-%d")
+        .map(|date| date.format("%B %Y").to_string())
+        .unwrap_or_else(|_| month.to_string())
+}
+
+/// Thumbnail `rxd thumbs` made of an image, if any
+fn image_thumb(folder: &Path, filename: &str) -> Option<PathBuf> {
+    [ThumbFormat::Jpeg, ThumbFormat::Webp]
+        .into_iter()
+        .map(|format| thumb_path(folder, filename, format))
+        .find(|thumb| thumb.is_file())
+}
+
+fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| VIDEO_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// `path` made absolute, with symlinks resolved if it exists
+fn absolute(path: &Path) -> io::Result<PathBuf> {
+    match path.canonicalize() {
+        Ok(path) => Ok(path),
+        Err(e) if e.kind() == ErrorKind::NotFound => std::path::absolute(path),
+        Err(e) => Err(e),
+    }
+}
+
+/// Link from a page in `out` to `path`, relative if both are on the same
+/// drive
+fn href(out: &Path, path: &Path) -> String {
+    let base: Vec<Component> = out.components().collect();
+    let target: Vec<Component> = path.components().collect();
+    let common = base.iter().zip(&target).take_while(|(a, b)| a == b).count();
+    // Nothing in common across Windows drives
+    if common == 0 {
+        let segments = target.iter().filter_map(|c| match c {
+            Component::Normal(name) => Some(encode(&name.to_string_lossy())),
+            Component::Prefix(prefix) => Some(prefix.as_os_str().to_string_lossy().into_owned()),
+            _ => None,
+        });
+        return format!("file:///{}", segments.collect::<Vec<_>>().join("/"));
+    }
+    let up = std::iter::repeat_n("..".to_string(), base.len() - common);
+    let down = target[common..]
+        .iter()
+        .map(|c| encode(&c.as_os_str().to_string_lossy()));
+    up.chain(down).collect::<Vec<_>>().join("/")
+}
+
+/// Percent-encode a path segment for a URL
+fn encode(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+/// Escape text for HTML content and attributes
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Write `contents` to `path` unless it already holds them, `true` if
+/// written
+fn write_if_changed(path: &Path, contents: &str) -> io::Result<bool> {
+    match fs::read(path) {
+        Ok(existing) if existing == contents.as_bytes() => return Ok(false),
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+    fs::write(&partial, contents)?;
+    fs::rename(&partial, path)?;
+    Ok(true)
+}
+
+/// Remove `page-<n>.html` files beyond the last of `pages` pages
+fn remove_stale_pages(out: &Path, pages: usize) -> io::Result<()> {
+    for entry in fs::read_dir(out)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(number) = name
+            .to_str()
+            .and_then(|n| n.strip_prefix("page-"))
+            .and_then(|n| n.strip_suffix(".html"))
+            .and_then(|n| n.parse::<usize>().ok())
+        else {
+            continue;
+        };
+        if number > pages {
+            debug!("removing stale page {}", entry.path().display());
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,417 @@
+//! Typed views of the GraphQL responses rxd consumes.
+//!
+//! Only the fields rxd reads are modelled, unknown fields are ignored so
+//! additions to the API don't break deserialization.
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+/// Top-level GraphQL envelope
+#[derive(Debug, Deserialize)]
+pub struct Response<T> {
+    pub data: Option<T>,
+}
+
+/// `data` of a TweetResultByRestId response
+#[derive(Debug, Deserialize)]
+pub struct TweetResultByRestIdData {
+    /// Empty for tweets that do not exist
+    #[serde(rename = "tweetResult")]
+    pub tweet_result: Option<TweetResults>,
+}
+
+/// `data` of a UserByScreenName response
+#[derive(Debug, Deserialize)]
+pub struct UserByScreenNameData {
+    pub user: Option<UserResults>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserResults {
+    pub result: Option<UserResult>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "__typename")]
+pub enum UserResult {
+    User(UserData),
+    /// Suspended or otherwise unavailable account
+    UserUnavailable {
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserData {
+    pub rest_id: String,
+    pub legacy: UserLegacy,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserLegacy {
+    pub name: String,
+    /// Only read from lists of accounts, a lookup knows the name it asked for
+    #[serde(default)]
+    pub screen_name: Option<String>,
+    #[serde(default)]
+    pub media_count: u64,
+    /// Tweets are only visible to approved followers
+    #[serde(default)]
+    pub protected: bool,
+    #[serde(default)]
+    pub statuses_count: u64,
+    #[serde(default)]
+    pub followers_count: u64,
+    /// e.g. `Wed Mar 12 18:47:51 +0000 2009`
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub profile_image_url_https: Option<String>,
+    #[serde(default)]
+    pub profile_banner_url: Option<String>,
+}
+
+/// `data` of a Following response, a timeline of accounts shaped like
+/// [`UserMediaData`]
+pub type FollowingData = UserMediaData;
+
+/// `data` of a ListMembers response
+#[derive(Debug, Deserialize)]
+pub struct ListMembersData {
+    pub list: Option<ListMembersList>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListMembersList {
+    pub members_timeline: Option<TimelineV2>,
+}
+
+/// `data` of a UserMedia response
+#[derive(Debug, Deserialize)]
+pub struct UserMediaData {
+    pub user: Option<UserMediaUser>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserMediaUser {
+    pub result: Option<UserMediaResult>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserMediaResult {
+    #[serde(alias = "timeline")]
+    pub timeline_v2: Option<TimelineV2>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimelineV2 {
+    pub timeline: Option<Timeline>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Timeline {
+    #[serde(default)]
+    pub instructions: Vec<Instruction>,
+}
+
+/// A timeline instruction, e.g. `TimelineAddEntries` or `TimelineAddToModule`
+#[derive(Debug, Deserialize)]
+pub struct Instruction {
+    /// e.g. `TimelineAddEntries`, `TimelinePinEntry`
+    #[serde(default, rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub entries: Vec<Entry>,
+    /// The pinned tweet of a `TimelinePinEntry` instruction
+    #[serde(default)]
+    pub entry: Option<Entry>,
+    /// Items appended to the media grid module on later pages
+    #[serde(default, rename = "moduleItems")]
+    pub module_items: Vec<ModuleItem>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Entry {
+    #[serde(default, rename = "entryId")]
+    pub entry_id: String,
+    #[serde(default)]
+    pub content: EntryContent,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct EntryContent {
+    /// Cursor value for cursor entries
+    #[serde(default)]
+    pub value: Option<String>,
+    /// Grid items of a module entry
+    #[serde(default)]
+    pub items: Vec<ModuleItem>,
+    /// Single item of an entry, e.g. an account of a Following timeline
+    #[serde(default, rename = "itemContent")]
+    pub item_content: Option<ItemContent>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModuleItem {
+    /// e.g. `profile-grid-0-tweet-1900000000000000001`
+    #[serde(default, rename = "entryId")]
+    pub entry_id: String,
+    pub item: Option<ItemWrapper>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ItemWrapper {
+    #[serde(rename = "itemContent")]
+    pub item_content: Option<ItemContent>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ItemContent {
+    pub tweet_results: Option<TweetResults>,
+    pub user_results: Option<UserResults>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TweetResults {
+    pub result: Option<TweetResult>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "__typename")]
+pub enum TweetResult {
+    Tweet(Tweet),
+    /// Tweet with limited visibility, the actual tweet is nested
+    TweetWithVisibilityResults {
+        tweet: Tweet,
+    },
+    /// Deleted, withheld or age-restricted tweet
+    TweetTombstone {
+        #[serde(default)]
+        tombstone: Option<Tombstone>,
+    },
+    /// Tweet hidden from the account, e.g. of a suspended or protected one
+    TweetUnavailable {
+        /// e.g. `Suspended`, `Protected`
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+impl TweetResult {
+    /// The tweet itself, if this result carries one
+    pub fn tweet(&self) -> Option<&Tweet> {
+        match self {
+            TweetResult::Tweet(tweet) => Some(tweet),
+            TweetResult::TweetWithVisibilityResults { tweet } => Some(tweet),
+            _ => None,
+        }
+    }
+}
+
+/// Notice shown in place of a tweet
+#[derive(Debug, Deserialize)]
+pub struct Tombstone {
+    #[serde(default)]
+    pub text: Option<TombstoneText>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TombstoneText {
+    /// e.g. `This Post was deleted by the Post author. Learn more`
+    #[serde(default)]
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Tweet {
+    #[serde(default)]
+    pub rest_id: Option<String>,
+    pub legacy: Option<TweetLegacy>,
+    /// Link preview or player card, some videos are only attached here
+    #[serde(default)]
+    pub card: Option<Card>,
+    /// Full content of long-form tweets, `legacy` only has a truncated copy
+    #[serde(default)]
+    pub note_tweet: Option<NoteTweet>,
+    #[serde(default)]
+    pub views: Option<Views>,
+}
+
+/// View counter of a tweet, missing on tweets older than it
+#[derive(Debug, Deserialize)]
+pub struct Views {
+    /// e.g. `"1000"`
+    #[serde(default)]
+    pub count: Option<String>,
+}
+
+impl Tweet {
+    /// The long-form note of this tweet, if it is one
+    pub fn note(&self) -> Option<&NoteTweetResult> {
+        self.note_tweet
+            .as_ref()
+            .and_then(|n| n.note_tweet_results.as_ref())
+            .and_then(|r| r.result.as_ref())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TweetLegacy {
+    #[serde(default)]
+    pub full_text: Option<String>,
+    /// e.g. `Wed Mar 12 18:47:51 +0000 2025`
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub extended_entities: Option<ExtendedEntities>,
+    /// Likes at the time of the request
+    #[serde(default)]
+    pub favorite_count: Option<u64>,
+    #[serde(default)]
+    pub retweet_count: Option<u64>,
+    #[serde(default)]
+    pub reply_count: Option<u64>,
+    #[serde(default)]
+    pub quote_count: Option<u64>,
+    /// Set on retweets, the original tweet is only read for its presence
+    #[serde(default)]
+    pub retweeted_status_result: Option<serde::de::IgnoredAny>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExtendedEntities {
+    #[serde(default)]
+    pub media: Vec<Media>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Media {
+    #[serde(default, rename = "type")]
+    pub kind: MediaKind,
+    #[serde(default)]
+    pub id_str: Option<String>,
+    #[serde(default)]
+    pub media_url_https: Option<String>,
+    #[serde(default)]
+    pub video_info: Option<VideoInfo>,
+    #[serde(default)]
+    pub original_info: Option<OriginalInfo>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaKind {
+    #[default]
+    Photo,
+    Video,
+    AnimatedGif,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NoteTweet {
+    pub note_tweet_results: Option<NoteTweetResults>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NoteTweetResults {
+    pub result: Option<NoteTweetResult>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NoteTweetResult {
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub entity_set: Option<ExtendedEntities>,
+    #[serde(default)]
+    pub media: Option<NoteMedia>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NoteMedia {
+    /// Media embedded in the text, in reading order
+    #[serde(default)]
+    pub inline_media: Vec<InlineMedia>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InlineMedia {
+    pub media_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Card {
+    pub legacy: Option<CardLegacy>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CardLegacy {
+    /// e.g. `summary_large_image`, `player` or `unified_card`
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub binding_values: Vec<BindingValue>,
+}
+
+impl CardLegacy {
+    /// String value of a binding
+    pub fn string_value(&self, key: &str) -> Option<&str> {
+        self.binding_values
+            .iter()
+            .find(|b| b.key == key)
+            .and_then(|b| b.value.string_value.as_deref())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BindingValue {
+    pub key: String,
+    pub value: BindingValueData,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BindingValueData {
+    #[serde(default)]
+    pub string_value: Option<String>,
+}
+
+/// JSON payload in the `unified_card` binding value
+#[derive(Debug, Deserialize)]
+pub struct UnifiedCard {
+    /// Media ID → media, in the same shape as `extended_entities`
+    #[serde(default)]
+    pub media_entities: BTreeMap<String, Media>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VideoInfo {
+    /// Not set for GIFs
+    #[serde(default)]
+    pub duration_millis: Option<u64>,
+    #[serde(default)]
+    pub variants: Vec<VideoVariant>,
+}
+
+/// Size of the uploaded media, of the poster for videos
+#[derive(Debug, Deserialize)]
+pub struct OriginalInfo {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VideoVariant {
+    #[serde(default)]
+    pub content_type: Option<String>,
+    pub url: String,
+    #[serde(default)]
+    pub bitrate: Option<u64>,
+}
@@ -0,0 +1,113 @@
+//! Content hashes recorded for downloaded files.
+//!
+//! Every hash is stored with the algorithm it was made with, so files keep
+//! verifying after `hash_algorithm` is changed.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, Result};
+
+pub mod backfill;
+
+/// Algorithm of a recorded hash
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    /// Used for every hash before the algorithm was recorded
+    #[default]
+    Sha256,
+    /// Faster on CPUs without SHA extensions
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// Name as recorded in the database and config
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// Hex digest of `data`
+    pub fn hash(self, data: &[u8]) -> String {
+        let mut hasher = Hasher::new(self);
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    /// Hex digest of a file, read in pieces
+    pub fn hash_file(self, path: &Path) -> io::Result<String> {
+        let mut hasher = Hasher::new(self);
+        io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+        Ok(hasher.finalize())
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            _ => Err(Error::Config(format!("unknown hash algorithm: {s}"))),
+        }
+    }
+}
+
+/// Incremental hasher of either algorithm
+pub enum Hasher {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::default()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    /// Hex digest of everything written so far
+    pub fn finalize(self) -> String {
+        let digest = match self {
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            Hasher::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+        };
+        digest.iter().map(|b| format!("{b:02x}")).collect()
+    }
+}
+
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+//! Hashing of downloaded files recorded without a hash.
+//!
+//! Only rows without a hash are read, so an interrupted run picks up where
+//! it stopped.
+
+use std::path::PathBuf;
+
+use futures::StreamExt;
+use sqlx::SqlitePool;
+use tracing::{instrument, warn};
+
+use super::HashAlgorithm;
+use crate::db;
+use crate::error::Result;
+
+/// Counts of a backfill
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackfillSummary {
+    /// Files hashed by this run
+    pub hashed: u64,
+    /// Recorded files that are not on disk
+    pub missing: u64,
+    /// Files that could not be read
+    pub failed: u64,
+    /// Media that had a hash before
+    pub already_hashed: u64,
+}
+
+/// Hash the recorded files of `screen_name`, or of every account, that
+/// have no hash yet
+///
+/// Files are looked up in the folder `folder` returns for their account.
+/// Up to `threads` files are hashed at once with `algorithm` and `progress`
+/// is called with the number of hashed files and the total.
+#[instrument(skip_all)]
+pub async fn backfill(
+    pool: &SqlitePool,
+    screen_name: Option<&str>,
+    folder: impl Fn(&str) -> PathBuf,
+    algorithm: HashAlgorithm,
+    threads: usize,
+    mut progress: impl FnMut(u64, u64),
+) -> Result<BackfillSummary> {
+    let pending = db::unhashed_media(pool, screen_name).await?;
+    let mut summary = BackfillSummary {
+        already_hashed: db::count_hashed(pool, screen_name).await?,
+        ..Default::default()
+    };
+
+    let total = pending.len() as u64;
+    let mut done = 0;
+    progress(0, total);
+    let mut hashed = futures::stream::iter(pending)
+        .map(|media| {
+            let path = folder(&media.screen_name).join(&media.filename);
+            async move {
+                let result = hash_file(path.clone(), algorithm).await;
+                (media, path, result)
+            }
+        })
+        .buffer_unordered(threads.max(1));
+
+    while let Some((media, path, result)) = hashed.next().await {
+        done += 1;
+        progress(done, total);
+        match result {
+            Ok(file_hash) => {
+                db::update_hash(pool, &media.media_url, &file_hash, algorithm).await?;
+                summary.hashed += 1;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                warn!("missing file of {}: {}", media.media_url, path.display());
+                summary.missing += 1;
+            }
+            Err(e) => {
+                warn!("failed to hash {}: {}", path.display(), e);
+                summary.failed += 1;
+            }
+        }
+    }
+    Ok(summary)
+}
+
+async fn hash_file(path: PathBuf, algorithm: HashAlgorithm) -> std::io::Result<String> {
+    tokio::task::spawn_blocking(move || algorithm.hash_file(&path))
+        .await
+        .map_err(std::io::Error::other)?
+}
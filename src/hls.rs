@@ -0,0 +1,259 @@
+//! Download of videos that are only available as HLS playlists.
+//!
+//! Segments of the best rendition are fetched and concatenated into a single
+//! file. Renditions with a separate audio track need muxing, which is left to
+//! `ffmpeg` when it is installed.
+
+use std::path::Path;
+use std::process::Stdio;
+
+use reqwest::{Client, Url};
+use tokio::process::Command;
+use tracing::{debug, instrument};
+
+use crate::error::{Error, Result};
+
+/// Content type of HLS playlists in video variants
+pub const PLAYLIST_CONTENT_TYPE: &str = "application/x-mpegURL";
+
+/// Whether a media URL points to an HLS playlist
+pub fn is_playlist(url: &str) -> bool {
+    url.split(['?', '#'])
+        .next()
+        .is_some_and(|path| path.ends_with(".m3u8"))
+}
+
+/// A rendition listed in a master playlist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant {
+    pub uri: String,
+    pub bandwidth: u64,
+    /// Playlist of a separate audio track, if the rendition has one
+    pub audio_uri: Option<String>,
+}
+
+/// Segments of a media playlist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaPlaylist {
+    /// fMP4 initialization section from `#EXT-X-MAP`
+    pub init: Option<String>,
+    pub segments: Vec<String>,
+}
+
+impl MediaPlaylist {
+    /// Extension of the concatenated segments
+    pub fn extension(&self) -> &'static str {
+        if self.init.is_some() { "mp4" } else { "ts" }
+    }
+}
+
+/// A downloaded video
+#[derive(Debug)]
+pub struct Video {
+    pub bytes: Vec<u8>,
+    pub extension: &'static str,
+}
+
+/// Renditions of a master playlist, empty if it is a media playlist
+pub fn parse_master(playlist: &str) -> Vec<Variant> {
+    // GROUP-ID -> URI of EXT-X-MEDIA audio renditions
+    let mut audio_groups = Vec::new();
+    for line in playlist.lines() {
+        if let Some(attrs) = line.strip_prefix("#EXT-X-MEDIA:")
+            && attribute(attrs, "TYPE") == Some("AUDIO")
+            && let (Some(group), Some(uri)) =
+                (attribute(attrs, "GROUP-ID"), attribute(attrs, "URI"))
+        {
+            audio_groups.push((group.to_string(), uri.to_string()));
+        }
+    }
+
+    let mut variants = Vec::new();
+    let mut lines = playlist.lines().map(str::trim);
+    while let Some(line) = lines.next() {
+        let Some(attrs) = line.strip_prefix("#EXT-X-STREAM-INF:") else {
+            continue;
+        };
+        let Some(uri) = lines
+            .by_ref()
+            .find(|l| !l.is_empty() && !l.starts_with('#'))
+        else {
+            break;
+        };
+        let audio_uri = attribute(attrs, "AUDIO").and_then(|group| {
+            audio_groups
+                .iter()
+                .find(|(g, _)| g == group)
+                .map(|(_, uri)| uri.clone())
+        });
+        variants.push(Variant {
+            uri: uri.to_string(),
+            bandwidth: attribute(attrs, "BANDWIDTH")
+                .and_then(|b| b.parse().ok())
+                .unwrap_or(0),
+            audio_uri,
+        });
+    }
+    variants
+}
+
+/// Segments of a media playlist
+pub fn parse_media(playlist: &str) -> MediaPlaylist {
+    let mut init = None;
+    let mut segments = Vec::new();
+    for line in playlist.lines().map(str::trim) {
+        if let Some(attrs) = line.strip_prefix("#EXT-X-MAP:") {
+            init = attribute(attrs, "URI").map(String::from);
+        } else if !line.is_empty() && !line.starts_with('#') {
+            segments.push(line.to_string());
+        }
+    }
+    MediaPlaylist { init, segments }
+}
+
+/// Value of an attribute in an `#EXT-X-...:` attribute list, without quotes
+fn attribute<'a>(attrs: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = attrs;
+    while !rest.is_empty() {
+        let (key, after) = rest.split_once('=')?;
+        let (value, next) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"')?;
+                (&quoted[..end], quoted[end + 1..].trim_start_matches(','))
+            }
+            None => match after.split_once(',') {
+                Some((value, next)) => (value, next),
+                None => (after, ""),
+            },
+        };
+        if key.trim() == name {
+            return Some(value);
+        }
+        rest = next;
+    }
+    None
+}
+
+/// Download the best rendition of a playlist
+///
+/// `on_progress` is called with the number of bytes received so far.
+/// `scratch` is used as the output file when `ffmpeg` has to mux audio.
+/// Fails with [`Error::TooLarge`] once the video grows past `max_bytes`.
+#[instrument(skip_all, fields(url = url))]
+pub async fn download(
+    client: &Client,
+    url: &str,
+    scratch: &Path,
+    max_bytes: Option<u64>,
+    mut on_progress: impl FnMut(u64),
+) -> Result<Video> {
+    let url = Url::parse(url).map_err(|e| Error::Parse(format!("invalid playlist URL: {e}")))?;
+    let playlist = fetch_text(client, &url).await?;
+
+    let variants = parse_master(&playlist);
+    let (media_url, media) = match variants.iter().max_by_key(|v| v.bandwidth) {
+        Some(variant) => {
+            let media_url = join(&url, &variant.uri)?;
+            if let Some(audio_uri) = &variant.audio_uri {
+                let audio_url = join(&url, audio_uri)?;
+                return mux_with_ffmpeg(&media_url, &audio_url, scratch, max_bytes).await;
+            }
+            let media = parse_media(&fetch_text(client, &media_url).await?);
+            (media_url, media)
+        }
+        None => {
+            let media = parse_media(&playlist);
+            (url, media)
+        }
+    };
+    if media.segments.is_empty() {
+        return Err(Error::HlsUnsupported(
+            "playlist has no segments".to_string(),
+        ));
+    }
+    debug!(
+        "fetching {} segments from {}",
+        media.segments.len(),
+        media_url
+    );
+
+    let mut bytes = Vec::new();
+    for uri in media.init.iter().chain(&media.segments) {
+        let response = client.get(join(&media_url, uri)?).send().await?;
+        if !response.status().is_success() {
+            return Err(Error::Download(response.status()));
+        }
+        bytes.extend_from_slice(&response.bytes().await?);
+        on_progress(bytes.len() as u64);
+        check_size(bytes.len() as u64, max_bytes)?;
+    }
+
+    Ok(Video {
+        bytes,
+        extension: media.extension(),
+    })
+}
+
+async fn fetch_text(client: &Client, url: &Url) -> Result<String> {
+    let response = client.get(url.clone()).send().await?;
+    if !response.status().is_success() {
+        return Err(Error::Download(response.status()));
+    }
+    Ok(response.text().await?)
+}
+
+fn join(base: &Url, uri: &str) -> Result<Url> {
+    base.join(uri)
+        .map_err(|e| Error::Parse(format!("invalid playlist entry {uri}: {e}")))
+}
+
+/// Combine separate video and audio playlists into an MP4 with `ffmpeg`
+async fn mux_with_ffmpeg(
+    video: &Url,
+    audio: &Url,
+    scratch: &Path,
+    max_bytes: Option<u64>,
+) -> Result<Video> {
+    debug!("muxing {} and {} with ffmpeg", video, audio);
+    let output = Command::new("ffmpeg")
+        .args(["-nostdin", "-loglevel", "error", "-y", "-i"])
+        .arg(video.as_str())
+        .arg("-i")
+        .arg(audio.as_str())
+        .args(["-c", "copy", "-f", "mp4"])
+        .arg(scratch)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output()
+        .await;
+
+    let result = match output {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(Error::HlsUnsupported(
+            "separate audio track needs ffmpeg, which is not installed".to_string(),
+        )),
+        Err(e) => Err(e.into()),
+        Ok(output) if !output.status.success() => Err(Error::HlsUnsupported(format!(
+            "ffmpeg failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))),
+        Ok(_) => tokio::fs::read(scratch).await.map_err(Error::from),
+    };
+    let _ = tokio::fs::remove_file(scratch).await;
+
+    let bytes = result?;
+    check_size(bytes.len() as u64, max_bytes)?;
+    Ok(Video {
+        bytes,
+        extension: "mp4",
+    })
+}
+
+/// Whether `size` is within `max_bytes`
+fn check_size(size: u64, max_bytes: Option<u64>) -> Result<()> {
+    match max_bytes {
+        Some(limit) if size > limit => Err(Error::TooLarge { size, limit }),
+        _ => Ok(()),
+    }
+}
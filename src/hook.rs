@@ -0,0 +1,93 @@
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+use tracing::{debug, instrument};
+
+use crate::error::{Error, Result};
+
+/// Command run after every new download
+///
+/// The template is split on whitespace and each argument has its placeholders
+/// substituted, so paths with spaces are passed as a single argument and no
+/// shell is involved. Supported placeholders: `{path}`, `{tweet_id}`,
+/// `{screen_name}` and `{media_type}`. The tweet is also provided as JSON in
+/// the `RXD_TWEET_JSON` environment variable.
+#[derive(Debug)]
+pub struct PostDownloadHook {
+    argv: Vec<String>,
+    timeout: Duration,
+    permits: Arc<Semaphore>,
+}
+
+/// Values substituted into the hook command
+pub struct HookContext<'a> {
+    pub path: &'a Path,
+    pub tweet_id: &'a str,
+    pub screen_name: &'a str,
+    pub media_type: &'a str,
+    pub tweet_json: String,
+}
+
+impl PostDownloadHook {
+    pub fn new(template: &str, timeout: Duration, concurrency: usize) -> Result<Self> {
+        let argv: Vec<String> = template.split_whitespace().map(String::from).collect();
+        if argv.is_empty() {
+            return Err(Error::Hook("post_download_hook is empty".to_string()));
+        }
+        Ok(Self {
+            argv,
+            timeout,
+            permits: Arc::new(Semaphore::new(concurrency.max(1))),
+        })
+    }
+
+    /// Run the hook, waiting for a free slot first
+    #[instrument(skip_all, fields(tweet_id = ctx.tweet_id))]
+    pub async fn run(&self, ctx: &HookContext<'_>) -> Result<()> {
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .map_err(|e| Error::Hook(e.to_string()))?;
+
+        let mut args = self.argv.iter().map(|arg| substitute(arg, ctx));
+        let program = args.next().unwrap_or_default();
+        let mut command = Command::new(&program);
+        command
+            .args(args)
+            .env("RXD_TWEET_JSON", &ctx.tweet_json)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let output = tokio::time::timeout(self.timeout, command.output())
+            .await
+            .map_err(|_| Error::Timeout(self.timeout))??;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !stdout.trim().is_empty() {
+            debug!("hook stdout: {}", stdout.trim());
+        }
+        if !stderr.trim().is_empty() {
+            debug!("hook stderr: {}", stderr.trim());
+        }
+
+        if !output.status.success() {
+            return Err(Error::Hook(format!("exited with {}", output.status)));
+        }
+        Ok(())
+    }
+}
+
+fn substitute(arg: &str, ctx: &HookContext) -> String {
+    arg.replace("{path}", &ctx.path.to_string_lossy())
+        .replace("{tweet_id}", ctx.tweet_id)
+        .replace("{screen_name}", ctx.screen_name)
+        .replace("{media_type}", ctx.media_type)
+}
@@ -0,0 +1,239 @@
+//! Indexing of folders downloaded before the database existed, behind
+//! `rxd import-folder`.
+//!
+//! Files are hashed and recorded as media of the account so that later runs
+//! recognize them. Dates, tweet and media IDs are read from names in the
+//! default `{date}-{media_id}` and `{date}-{tweet_id}-{index}-{media_id}`
+//! formats. Images named by their media ID get their real URL, which lets
+//! downloads skip them; other files are recorded under a placeholder URL.
+
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use futures::StreamExt;
+use regex::Regex;
+use tracing::{instrument, warn};
+
+use crate::db::{self, ImportedMedia};
+use crate::error::Result;
+use crate::hash::HashAlgorithm;
+
+pub mod gallery_dl;
+pub mod twitter_archive;
+
+/// Extensions of files rxd downloads
+const MEDIA_EXTENSIONS: [&str; 7] = ["jpg", "jpeg", "png", "webp", "gif", "mp4", "mov"];
+
+/// Extensions images are served with, the URL can be rebuilt for these
+const IMAGE_URL_EXTENSIONS: [&str; 2] = ["jpg", "png"];
+
+/// Prefix of placeholder URLs and tweet IDs of imported files
+pub const PLACEHOLDER_PREFIX: &str = "import:";
+
+/// Milliseconds since the Unix epoch of the first tweet ID timestamp
+const SNOWFLAKE_EPOCH_MS: i64 = 1_288_834_974_657;
+
+static GROUPED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\d{4}-\d{2}-\d{2})-(\d+)-\d{2}-([A-Za-z0-9_-]+)$").expect("valid regex")
+});
+static SINGLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\d{4}-\d{2}-\d{2})-([A-Za-z0-9_-]+)$").expect("valid regex"));
+
+/// What a file name tells about its media
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Inferred {
+    pub date: Option<NaiveDate>,
+    pub tweet_id: Option<String>,
+    pub media_id: Option<String>,
+}
+
+/// Read date, tweet and media ID from a file name stem in a default format
+pub fn infer(stem: &str) -> Inferred {
+    let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok();
+    if let Some(c) = GROUPED.captures(stem) {
+        return Inferred {
+            date: date(&c[1]),
+            tweet_id: Some(c[2].to_string()),
+            media_id: Some(c[3].to_string()),
+        };
+    }
+    if let Some(c) = SINGLE.captures(stem) {
+        return Inferred {
+            date: date(&c[1]),
+            tweet_id: None,
+            media_id: Some(c[2].to_string()),
+        };
+    }
+    Inferred::default()
+}
+
+/// Counts of an import
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    /// Media files in the folder
+    pub found: u64,
+    /// Files recorded before, not hashed again
+    pub known: u64,
+    /// Files recorded by this import
+    pub indexed: u64,
+    /// Files that could not be read
+    pub failed: u64,
+}
+
+/// Record the media files under `dir` as downloads of `screen_name`
+///
+/// Files already recorded are skipped, so an interrupted import picks up
+/// where it stopped. Up to `threads` files are hashed at once with
+/// `algorithm` and `progress` is called with the number of hashed files and
+/// the total.
+#[instrument(skip_all, fields(user = screen_name))]
+pub async fn import_folder(
+    pool: &sqlx::SqlitePool,
+    dir: &Path,
+    screen_name: &str,
+    algorithm: HashAlgorithm,
+    threads: usize,
+    mut progress: impl FnMut(u64, u64),
+) -> Result<ImportSummary> {
+    let root = dir.to_path_buf();
+    let files = tokio::task::spawn_blocking(move || files_under(&root, is_media))
+        .await
+        .map_err(std::io::Error::other)??;
+
+    let known = db::known_filenames(pool, screen_name).await?;
+    let mut summary = ImportSummary {
+        found: files.len() as u64,
+        ..Default::default()
+    };
+    let pending: Vec<String> = files.into_iter().filter(|f| !known.contains(f)).collect();
+    summary.known = summary.found - pending.len() as u64;
+
+    let total = pending.len() as u64;
+    let mut done = 0;
+    progress(0, total);
+    let mut hashed = futures::stream::iter(pending)
+        .map(|filename| {
+            let path = dir.join(&filename);
+            async move {
+                let result = tokio::task::spawn_blocking(move || {
+                    let hash = algorithm.hash_file(&path)?;
+                    let modified = std::fs::metadata(&path)?.modified()?;
+                    Ok::<_, std::io::Error>((hash, modified))
+                })
+                .await
+                .map_err(std::io::Error::other)
+                .and_then(|r| r);
+                (filename, result)
+            }
+        })
+        .buffer_unordered(threads.max(1));
+
+    while let Some((filename, result)) = hashed.next().await {
+        done += 1;
+        progress(done, total);
+        let (file_hash, modified) = match result {
+            Ok(hashed) => hashed,
+            Err(e) => {
+                warn!("failed to hash {}: {}", filename, e);
+                summary.failed += 1;
+                continue;
+            }
+        };
+        let media = ImportedMedia {
+            hash_algorithm: algorithm,
+            ..imported_media(screen_name, filename, file_hash, modified.into())
+        };
+        if db::insert_imported(pool, &media).await? {
+            summary.indexed += 1;
+        }
+    }
+    Ok(summary)
+}
+
+/// Database record of a file, from what its name tells
+fn imported_media(
+    screen_name: &str,
+    filename: String,
+    file_hash: String,
+    modified: DateTime<Local>,
+) -> ImportedMedia {
+    let path = Path::new(&filename);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let ext = path
+        .extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let inferred = infer(stem);
+    let placeholder = format!("{PLACEHOLDER_PREFIX}{screen_name}/{filename}");
+
+    let media_url = match &inferred.media_id {
+        Some(media_id) if IMAGE_URL_EXTENSIONS.contains(&ext.as_str()) => {
+            format!("https://pbs.twimg.com/media/{media_id}.{ext}")
+        }
+        _ => placeholder.clone(),
+    };
+    let tweet_time = match inferred.date {
+        Some(date) => format!("{} 00:00:00", date.format("%Y-%m-%d")),
+        None => modified.format("%Y-%m-%d %H:%M:%S").to_string(),
+    };
+    ImportedMedia {
+        tweet_id: inferred.tweet_id.unwrap_or(placeholder),
+        screen_name: screen_name.to_string(),
+        tweet_time,
+        media_url,
+        filename: Some(filename),
+        file_hash: Some(file_hash),
+        hash_algorithm: HashAlgorithm::default(),
+        full_text: None,
+    }
+}
+
+/// Whether a file name has the extension of a file rxd downloads, partial
+/// downloads do not
+fn is_media(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| MEDIA_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// Files under `root` whose name passes `keep`, as `/`-separated relative
+/// paths, sorted
+///
+/// Hidden files and directories are left out.
+fn files_under(root: &Path, keep: fn(&str) -> bool) -> std::io::Result<Vec<String>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(relative) = dirs.pop() {
+        for entry in std::fs::read_dir(root.join(&relative))? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                warn!("skipping {}: name is not UTF-8", entry.path().display());
+                continue;
+            };
+            if name.starts_with('.') {
+                continue;
+            }
+            let path = relative.join(name);
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            if keep(name) {
+                let parts: Vec<_> = path.iter().filter_map(|p| p.to_str()).collect();
+                files.push(parts.join("/"));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Time a tweet was posted, encoded in its ID since late 2010
+fn snowflake_time(tweet_id: &str) -> Option<DateTime<Utc>> {
+    let id = tweet_id.parse::<i64>().ok()?;
+    DateTime::from_timestamp_millis((id >> 22) + SNOWFLAKE_EPOCH_MS)
+}
@@ -0,0 +1,263 @@
+//! Import of gallery-dl download archives.
+//!
+//! gallery-dl records every download as an entry key in the `archive` table
+//! of its SQLite archive. Twitter keys are `twitter` followed by
+//! `{tweet_id}_{retweet_id}_{num}`, `num` being the 1-based position of the
+//! media in the tweet. Keys of other sites and formats are ignored.
+//!
+//! The archive holds no account names, URLs or file names. They are taken
+//! from the JSON files gallery-dl writes next to downloads with
+//! `--write-metadata`, named after the media file with `.json` appended.
+//! Without a metadata file an entry is recorded for the account given on the
+//! command line, if any, and its time is derived from the tweet ID.
+//!
+//! Images get their `pbs.twimg.com/media` URL back from the metadata file
+//! name and extension. Other media are recorded under a placeholder URL of
+//! tweet ID and position, which downloads check next to the real URL.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use tracing::{debug, instrument, warn};
+
+use super::IMAGE_URL_EXTENSIONS;
+use crate::db::{self, ImportedMedia};
+use crate::error::Result;
+use crate::hash::HashAlgorithm;
+
+/// Prefix gallery-dl puts before the keys of its Twitter extractor
+const CATEGORY: &str = "twitter";
+
+/// Key of a downloaded media file in the archive
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Entry {
+    pub tweet_id: String,
+    /// 1-based position among the media of the tweet
+    pub num: usize,
+}
+
+/// Read an archive key, `None` for other sites and unknown formats
+pub fn parse_entry(key: &str) -> Option<Entry> {
+    let mut parts = key.strip_prefix(CATEGORY)?.split('_');
+    let (tweet_id, retweet_id, num) = (parts.next()?, parts.next()?, parts.next()?);
+    let is_id = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if parts.next().is_some() || !is_id(tweet_id) || !is_id(retweet_id) {
+        return None;
+    }
+    Some(Entry {
+        tweet_id: tweet_id.to_string(),
+        num: num.parse().ok().filter(|&n| n > 0)?,
+    })
+}
+
+/// Fields of a gallery-dl metadata file that rxd records
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub entry: Entry,
+    pub screen_name: String,
+    /// `date` of the tweet, in UTC
+    pub date: Option<NaiveDateTime>,
+    pub content: Option<String>,
+    /// Base name of the media URL, the media ID for images
+    pub filename: Option<String>,
+    pub extension: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawMetadata {
+    category: String,
+    tweet_id: u64,
+    num: usize,
+    date: Option<String>,
+    author: RawAuthor,
+    content: Option<String>,
+    filename: Option<String>,
+    extension: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawAuthor {
+    name: String,
+}
+
+/// Read a metadata file, `None` for other sites and unknown formats
+pub fn parse_metadata(json: &str) -> Option<Metadata> {
+    let raw: RawMetadata = serde_json::from_str(json).ok()?;
+    if raw.category != CATEGORY {
+        return None;
+    }
+    Some(Metadata {
+        entry: Entry {
+            tweet_id: raw.tweet_id.to_string(),
+            num: raw.num,
+        },
+        screen_name: raw.author.name,
+        date: raw
+            .date
+            .and_then(|d| NaiveDateTime::parse_from_str(&d, "%Y-%m-%d %H:%M:%S").ok()),
+        content: raw.content,
+        filename: raw.filename,
+        extension: raw.extension,
+    })
+}
+
+/// URL recorded for media whose real URL is unknown
+pub fn placeholder_url(tweet_id: &str, num: usize) -> String {
+    format!("gallery-dl:{tweet_id}_{num}")
+}
+
+/// Counts of an import
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    /// Entries in the archive
+    pub entries: u64,
+    /// Entries recorded by this import
+    pub imported: u64,
+    /// Entries recorded before
+    pub known: u64,
+    /// Entries of other sites or in unknown formats
+    pub ignored: u64,
+    /// Twitter entries left out for lack of an account name
+    pub no_account: u64,
+}
+
+/// Record the entries of the archive at `archive` as downloaded
+///
+/// Metadata files under `metadata_dir` fill in accounts, texts, URLs and
+/// file names. Entries without one are recorded for `screen_name`, or left
+/// out when it is `None`.
+#[instrument(skip_all)]
+pub async fn import(
+    pool: &SqlitePool,
+    archive: &Path,
+    metadata_dir: Option<&Path>,
+    screen_name: Option<&str>,
+) -> Result<ImportSummary> {
+    let keys = read_archive(archive).await?;
+    let metadata = match metadata_dir {
+        Some(dir) => read_metadata(dir).await?,
+        None => HashMap::new(),
+    };
+
+    let mut summary = ImportSummary {
+        entries: keys.len() as u64,
+        ..Default::default()
+    };
+    for key in keys {
+        let Some(entry) = parse_entry(&key) else {
+            debug!("ignoring archive entry {}", key);
+            summary.ignored += 1;
+            continue;
+        };
+        let media = match metadata.get(&entry) {
+            Some((filename, metadata)) => from_metadata(metadata, filename),
+            None => match screen_name {
+                Some(screen_name) => without_metadata(&entry, screen_name),
+                None => {
+                    summary.no_account += 1;
+                    continue;
+                }
+            },
+        };
+        if db::insert_imported(pool, &media).await? {
+            summary.imported += 1;
+        } else {
+            summary.known += 1;
+        }
+    }
+    Ok(summary)
+}
+
+/// Keys in the `archive` table, opened read-only
+async fn read_archive(path: &Path) -> Result<Vec<String>> {
+    let options = SqliteConnectOptions::new().filename(path).read_only(true);
+    let archive = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await?;
+    let keys = sqlx::query_scalar("SELECT entry FROM archive")
+        .fetch_all(&archive)
+        .await;
+    archive.close().await;
+    Ok(keys?)
+}
+
+/// Twitter metadata files under `dir` by entry, with the name of their
+/// media file
+async fn read_metadata(dir: &Path) -> Result<HashMap<Entry, (String, Metadata)>> {
+    let dir = dir.to_path_buf();
+    let found = tokio::task::spawn_blocking(move || {
+        let mut found = HashMap::new();
+        for path in super::files_under(&dir, |name| name.ends_with(".json"))? {
+            let json = match std::fs::read_to_string(dir.join(&path)) {
+                Ok(json) => json,
+                Err(e) => {
+                    warn!("failed to read {}: {}", path, e);
+                    continue;
+                }
+            };
+            if let Some(metadata) = parse_metadata(&json) {
+                let name = path.rsplit('/').next().unwrap_or(&path);
+                let filename = name.trim_end_matches(".json").to_string();
+                found.insert(metadata.entry.clone(), (filename, metadata));
+            }
+        }
+        Ok::<_, std::io::Error>(found)
+    })
+    .await
+    .map_err(std::io::Error::other)??;
+    Ok(found)
+}
+
+fn from_metadata(metadata: &Metadata, filename: &str) -> ImportedMedia {
+    let Entry { tweet_id, num } = &metadata.entry;
+    let media_url = match (&metadata.filename, metadata.extension.as_deref()) {
+        (Some(media_id), Some(ext)) if IMAGE_URL_EXTENSIONS.contains(&ext) => {
+            format!("https://pbs.twimg.com/media/{media_id}.{ext}")
+        }
+        _ => placeholder_url(tweet_id, *num),
+    };
+    let tweet_time = match metadata.date {
+        Some(date) => local_time(date.and_utc()),
+        None => snowflake_time(tweet_id),
+    };
+    ImportedMedia {
+        tweet_id: tweet_id.clone(),
+        screen_name: metadata.screen_name.clone(),
+        tweet_time,
+        media_url,
+        filename: Some(filename.to_string()),
+        file_hash: None,
+        hash_algorithm: HashAlgorithm::default(),
+        full_text: metadata.content.clone(),
+    }
+}
+
+fn without_metadata(entry: &Entry, screen_name: &str) -> ImportedMedia {
+    ImportedMedia {
+        tweet_id: entry.tweet_id.clone(),
+        screen_name: screen_name.to_string(),
+        tweet_time: snowflake_time(&entry.tweet_id),
+        media_url: placeholder_url(&entry.tweet_id, entry.num),
+        filename: None,
+        file_hash: None,
+        hash_algorithm: HashAlgorithm::default(),
+        full_text: None,
+    }
+}
+
+/// Time a tweet was posted, from its ID
+fn snowflake_time(tweet_id: &str) -> String {
+    local_time(super::snowflake_time(tweet_id).unwrap_or_default())
+}
+
+/// `tweet_time` as rxd records it
+fn local_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
@@ -0,0 +1,811 @@
+//! Import of the data export Twitter offers of one's own account, behind
+//! `rxd import --from twitter-archive`.
+//!
+//! The export is a ZIP file, or the folder it unpacks to, with the tweets
+//! in `data/tweets.js` and their media in `data/tweets_media/`, named
+//! `<tweet_id>-<name of the media URL>`. Older exports call them
+//! `data/tweet.js` and `data/tweet_media/`, split large accounts into
+//! `tweets-part1.js`, … and wrap each tweet in `{"tweet": …}` or not, and
+//! the oldest keep one file per month in `data/js/tweets/` and bundle no
+//! media. IDs and counts are strings in some generations and numbers in
+//! others.
+//!
+//! Tweets and media are recorded as downloads of the account. Bundled files
+//! are copied, or hard-linked out of an unpacked folder, into the account's
+//! folder under the names downloads give them, and media the export only
+//! links is downloaded from its URL. Media of retweets belongs to other
+//! accounts and is left out. ZIP files are read with the `unzip` command.
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::LazyLock;
+
+use chrono::{DateTime, FixedOffset, Local};
+use futures::StreamExt;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::SqlitePool;
+use tokio::process::Command;
+use tracing::{debug, instrument, warn};
+
+use crate::api::Api;
+use crate::db;
+use crate::error::{Error, Result};
+use crate::filename::{self, Template, Timezone};
+use crate::hash::HashAlgorithm;
+use crate::pushback;
+use crate::task::{self, Body, Engagement, ImageSize, MediaItem, MediaType};
+use crate::verify;
+
+/// Tweet files relative to `data/`, of every generation of the export
+static TWEET_FILES: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:tweets?(?:-part\d+)?\.js|js/tweets/\d{4}_\d{2}\.js)$").expect("valid regex")
+});
+
+/// Folders of bundled media relative to `data/`, newest first
+const MEDIA_FOLDERS: [&str; 2] = ["tweets_media/", "tweet_media/"];
+
+/// Files naming the account relative to `data/`, newest first
+const ACCOUNT_FILES: [&str; 2] = ["account.js", "js/user_details.js"];
+
+/// Where an export is read from
+#[derive(Debug, Clone)]
+enum Source {
+    Folder(PathBuf),
+    Zip(PathBuf),
+}
+
+/// A Twitter data export
+#[derive(Debug)]
+pub struct TwitterArchive {
+    source: Source,
+    /// Paths of the export's files by their path relative to `data/`
+    files: HashMap<String, String>,
+}
+
+impl TwitterArchive {
+    /// Open the export at `path`, a ZIP file or the folder it unpacks to
+    ///
+    /// Fails if it has no tweet file.
+    pub async fn open(path: &Path) -> Result<Self> {
+        let source = if tokio::fs::metadata(path).await?.is_dir() {
+            Source::Folder(path.to_path_buf())
+        } else {
+            Source::Zip(path.to_path_buf())
+        };
+        let entries = match &source {
+            Source::Folder(root) => {
+                let root = root.clone();
+                tokio::task::spawn_blocking(move || super::files_under(&root, |_| true))
+                    .await
+                    .map_err(std::io::Error::other)??
+            }
+            Source::Zip(zip) => {
+                let listing = unzip("-Z1", zip, None, Stdio::piped()).await?;
+                String::from_utf8_lossy(&listing)
+                    .lines()
+                    .filter(|entry| !entry.ends_with('/'))
+                    .map(str::to_string)
+                    .collect()
+            }
+        };
+        let files: HashMap<String, String> = entries
+            .into_iter()
+            .filter_map(|entry| Some((data_path(&entry)?.to_string(), entry)))
+            .collect();
+        if !files.keys().any(|name| TWEET_FILES.is_match(name)) {
+            return Err(Error::Parse(format!(
+                "{} has no data/tweets.js, it is not a Twitter data export",
+                path.display()
+            )));
+        }
+        Ok(Self { source, files })
+    }
+
+    /// Screen name of the account the export is of, if it names it
+    pub async fn screen_name(&self) -> Result<Option<String>> {
+        match ACCOUNT_FILES.iter().find_map(|name| self.files.get(*name)) {
+            Some(entry) => Ok(parse_account(&self.read(entry).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Tweets of all tweet files
+    pub async fn tweets(&self) -> Result<ParsedTweets> {
+        let mut names: Vec<&String> = self
+            .files
+            .keys()
+            .filter(|name| TWEET_FILES.is_match(name))
+            .collect();
+        names.sort();
+        let mut parsed = ParsedTweets::default();
+        for name in names {
+            let file = parse_tweets(&self.read(&self.files[name]).await?)?;
+            debug!("{} tweets in {}", file.tweets.len(), name);
+            parsed.tweets.extend(file.tweets);
+            parsed.unreadable += file.unreadable;
+        }
+        Ok(parsed)
+    }
+
+    /// Path of the bundled media file named `name`
+    fn bundled(&self, name: &str) -> Option<&str> {
+        MEDIA_FOLDERS
+            .iter()
+            .find_map(|folder| self.files.get(&format!("{folder}{name}")))
+            .map(String::as_str)
+    }
+
+    async fn read(&self, entry: &str) -> Result<String> {
+        let bytes = match &self.source {
+            Source::Folder(root) => tokio::fs::read(root.join(entry)).await?,
+            Source::Zip(zip) => unzip("-p", zip, Some(entry), Stdio::piped()).await?,
+        };
+        String::from_utf8(bytes).map_err(|_| Error::Parse(format!("{entry} is not UTF-8")))
+    }
+
+    /// Put the file `entry` at `path`, under a temporary name until it is
+    /// complete
+    async fn extract(&self, entry: &str, path: &Path) -> Result<()> {
+        let mut part = path.as_os_str().to_owned();
+        part.push(".part");
+        let part = PathBuf::from(part);
+        let _ = tokio::fs::remove_file(&part).await;
+        let extracted = match &self.source {
+            Source::Folder(root) => {
+                let from = root.join(entry);
+                match tokio::fs::hard_link(&from, &part).await {
+                    Ok(()) => Ok(()),
+                    Err(_) => tokio::fs::copy(&from, &part).await.map(|_| ()),
+                }
+                .map_err(Error::from)
+            }
+            Source::Zip(zip) => match std::fs::File::create(&part) {
+                Ok(file) => unzip("-p", zip, Some(entry), file.into()).await.map(|_| ()),
+                Err(e) => Err(e.into()),
+            },
+        };
+        match extracted {
+            Ok(()) => Ok(tokio::fs::rename(&part, path).await?),
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&part).await;
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Path of an export's file relative to its `data/` folder, whatever folder
+/// the export was packed or unpacked into
+fn data_path(entry: &str) -> Option<&str> {
+    entry
+        .strip_prefix("data/")
+        .or_else(|| entry.find("/data/").map(|i| &entry[i + "/data/".len()..]))
+}
+
+/// Standard output of `unzip <option> <zip> [entry]`, or nothing when it
+/// goes to a file
+async fn unzip(option: &str, zip: &Path, entry: Option<&str>, stdout: Stdio) -> Result<Vec<u8>> {
+    let mut command = Command::new("unzip");
+    command.arg(option).arg(zip);
+    if let Some(entry) = entry {
+        // Taken as a wildcard pattern otherwise
+        let escaped: String = entry
+            .chars()
+            .map(|c| match c {
+                '*' | '?' | '[' => format!("[{c}]"),
+                c => c.to_string(),
+            })
+            .collect();
+        command.arg(escaped);
+    }
+    // `output` would capture standard output even when it goes to a file
+    let child = command
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn();
+    let output = match child {
+        Ok(child) => child.wait_with_output().await?,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(Error::Config(
+                "reading ZIP files needs unzip, which is not installed, unpack the export and pass its folder".to_string(),
+            ));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "unzip failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .into());
+    }
+    Ok(output.stdout)
+}
+
+/// Tweets of tweet files
+#[derive(Debug, Default)]
+pub struct ParsedTweets {
+    pub tweets: Vec<ArchiveTweet>,
+    /// Entries that are not tweets in a known format
+    pub unreadable: u64,
+}
+
+/// A tweet of an export
+#[derive(Debug, Clone)]
+pub struct ArchiveTweet {
+    pub tweet_id: String,
+    pub timestamp: DateTime<FixedOffset>,
+    pub full_text: Option<String>,
+    /// Retweets list the media of the retweeted tweet
+    pub is_retweet: bool,
+    pub engagement: Engagement,
+    pub media: Vec<ArchiveMedia>,
+}
+
+/// A media of a tweet of an export
+#[derive(Debug, Clone)]
+pub struct ArchiveMedia {
+    /// Canonical URL, as downloads record it
+    pub url: String,
+    pub media_type: MediaType,
+    /// URLs the file is offered at, best first: the photo, or the mp4
+    /// variants of a video
+    pub variants: Vec<String>,
+    /// Length of a video
+    pub duration_ms: Option<u64>,
+}
+
+/// Read a tweet file, `window.YTD.tweets.part0 = [...]` or an older form
+///
+/// Fails only if the file is not a list, entries in unknown formats are
+/// counted as unreadable.
+pub fn parse_tweets(js: &str) -> Result<ParsedTweets> {
+    let entries: Vec<Value> = serde_json::from_str(script_json(js))?;
+    let mut parsed = ParsedTweets::default();
+    for entry in entries {
+        let tweet = serde_json::from_value::<RawEntry>(entry)
+            .ok()
+            .and_then(|entry| match entry {
+                RawEntry::Wrapped { tweet } => tweet.into_tweet(),
+                RawEntry::Bare(tweet) => tweet.into_tweet(),
+            });
+        match tweet {
+            Some(tweet) => parsed.tweets.push(tweet),
+            None => parsed.unreadable += 1,
+        }
+    }
+    Ok(parsed)
+}
+
+/// Screen name in an account file, `window.YTD.account.part0 = [...]` or
+/// the older `var user_details = {...}`
+pub fn parse_account(js: &str) -> Option<String> {
+    let value: Value = serde_json::from_str(script_json(js)).ok()?;
+    let account = match &value {
+        Value::Array(entries) => entries.first()?.get("account")?,
+        other => other,
+    };
+    account
+        .get("username")
+        .or_else(|| account.get("screen_name"))?
+        .as_str()
+        .map(str::to_string)
+}
+
+/// JSON assigned to a variable by a script of the export
+fn script_json(js: &str) -> &str {
+    let start = js.find(['[', '{']).unwrap_or(0);
+    js[start..].trim_end().trim_end_matches(';')
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawEntry {
+    Wrapped { tweet: RawTweet },
+    Bare(RawTweet),
+}
+
+/// An ID or count, a string in some generations of the export
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Number {
+    Int(u64),
+    Text(String),
+}
+
+impl Number {
+    fn value(&self) -> Option<u64> {
+        match self {
+            Number::Int(n) => Some(*n),
+            Number::Text(s) => s.parse().ok(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RawTweet {
+    #[serde(default)]
+    id_str: Option<String>,
+    #[serde(default)]
+    id: Option<Number>,
+    #[serde(default)]
+    created_at: Option<String>,
+    #[serde(default)]
+    full_text: Option<String>,
+    /// Text of the oldest exports
+    #[serde(default)]
+    text: Option<String>,
+    #[serde(default)]
+    favorite_count: Option<Number>,
+    #[serde(default)]
+    retweet_count: Option<Number>,
+    /// Retweeted tweet of the oldest exports
+    #[serde(default)]
+    retweeted_status: Option<Value>,
+    #[serde(default)]
+    entities: Option<RawEntities>,
+    #[serde(default)]
+    extended_entities: Option<RawEntities>,
+}
+
+#[derive(Deserialize)]
+struct RawEntities {
+    #[serde(default)]
+    media: Vec<RawMedia>,
+}
+
+#[derive(Deserialize)]
+struct RawMedia {
+    #[serde(default, rename = "type")]
+    kind: Option<String>,
+    #[serde(default)]
+    media_url_https: Option<String>,
+    #[serde(default)]
+    media_url: Option<String>,
+    #[serde(default)]
+    video_info: Option<RawVideoInfo>,
+}
+
+#[derive(Deserialize)]
+struct RawVideoInfo {
+    #[serde(default)]
+    duration_millis: Option<Number>,
+    #[serde(default)]
+    variants: Vec<RawVariant>,
+}
+
+#[derive(Deserialize)]
+struct RawVariant {
+    #[serde(default)]
+    bitrate: Option<Number>,
+    #[serde(default)]
+    content_type: Option<String>,
+    url: String,
+}
+
+impl RawTweet {
+    fn into_tweet(self) -> Option<ArchiveTweet> {
+        let tweet_id = match (self.id_str, &self.id) {
+            (Some(id), _) => id,
+            (None, Some(id)) => id.value()?.to_string(),
+            (None, None) => return None,
+        };
+        if tweet_id.is_empty() || !tweet_id.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let timestamp = self
+            .created_at
+            .as_deref()
+            .and_then(parse_time)
+            .or_else(|| super::snowflake_time(&tweet_id).map(|t| t.fixed_offset()))?;
+        let full_text = self.full_text.or(self.text);
+        let is_retweet = self.retweeted_status.is_some()
+            || full_text.as_deref().is_some_and(|t| t.starts_with("RT @"));
+
+        // Entities of older tweets list only the first media
+        let mut media: Vec<ArchiveMedia> = Vec::new();
+        for found in self
+            .extended_entities
+            .or(self.entities)
+            .into_iter()
+            .flat_map(|entities| entities.media)
+            .filter_map(|media| media.into_media(&tweet_id))
+        {
+            if !media.iter().any(|known| known.url == found.url) {
+                media.push(found);
+            }
+        }
+        Some(ArchiveTweet {
+            tweet_id,
+            timestamp,
+            full_text,
+            is_retweet,
+            engagement: Engagement {
+                favorite_count: self.favorite_count.as_ref().and_then(Number::value),
+                retweet_count: self.retweet_count.as_ref().and_then(Number::value),
+                ..Default::default()
+            },
+            media,
+        })
+    }
+}
+
+impl RawMedia {
+    fn into_media(self, tweet_id: &str) -> Option<ArchiveMedia> {
+        let url = self.media_url_https.or_else(|| {
+            self.media_url
+                .map(|url| url.replacen("http://", "https://", 1))
+        })?;
+        match self.kind.as_deref() {
+            Some("video" | "animated_gif") => {
+                let info = self.video_info?;
+                let mut mp4: Vec<RawVariant> = info
+                    .variants
+                    .into_iter()
+                    .filter(|v| v.content_type.as_deref().is_some_and(|t| t.contains("mp4")))
+                    .collect();
+                mp4.sort_by_key(|v| std::cmp::Reverse(v.bitrate.as_ref().and_then(Number::value)));
+                if mp4.is_empty() {
+                    warn!("no mp4 variant for video in tweet {}", tweet_id);
+                    return None;
+                }
+                Some(ArchiveMedia {
+                    url,
+                    media_type: MediaType::Video,
+                    variants: mp4.into_iter().map(|v| v.url).collect(),
+                    duration_ms: info.duration_millis.as_ref().and_then(Number::value),
+                })
+            }
+            // The oldest exports leave the type out of some photos
+            Some("photo") | None => Some(ArchiveMedia {
+                variants: vec![url.clone()],
+                url,
+                media_type: MediaType::Image,
+                duration_ms: None,
+            }),
+            Some(other) => {
+                debug!("ignoring {} media of tweet {}", other, tweet_id);
+                None
+            }
+        }
+    }
+}
+
+/// `created_at` of a tweet, `Wed Mar 12 18:47:51 +0000 2025` or
+/// `2012-03-12 18:47:51 +0000` in the oldest exports
+fn parse_time(s: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_str(s, "%a %b %d %H:%M:%S %z %Y")
+        .or_else(|_| DateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S %z"))
+        .ok()
+}
+
+/// Name the export bundles the file of `url` under
+fn bundled_name(tweet_id: &str, url: &str) -> Option<String> {
+    let name = url.split(['?', '#']).next()?.rsplit('/').next()?;
+    (!name.is_empty()).then(|| format!("{tweet_id}-{name}"))
+}
+
+/// Where and how an export is imported
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// Account the tweets are recorded for
+    pub screen_name: String,
+    /// Folder of the account's files
+    pub folder: PathBuf,
+    /// Names of the files, the default names of downloads if `None`
+    pub filename_template: Option<Template>,
+    pub timezone: Timezone,
+    /// Size missing images are downloaded in
+    pub image_size: ImageSize,
+    pub hash_algorithm: HashAlgorithm,
+    pub max_file_size: Option<u64>,
+    /// Files copied or downloaded at once
+    pub concurrency: usize,
+}
+
+/// Counts of an import
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    /// Entries of the tweet files
+    pub tweets: u64,
+    /// Tweets with media recorded by this import
+    pub imported: u64,
+    /// Bundled files copied into the account's folder
+    pub copied: u64,
+    /// Media the export does not bundle, to be downloaded
+    pub queued: u64,
+    /// Queued media downloaded
+    pub downloaded: u64,
+    /// Media that could not be copied or downloaded, tried again by the
+    /// next import
+    pub failed: u64,
+    /// Media recorded with its file before, media of retweets and entries
+    /// that could not be read
+    pub skipped: u64,
+}
+
+/// Media to put into the account's folder
+struct Pending {
+    item: MediaItem,
+    /// Path of the file in the export, `None` to download it
+    bundled: Option<String>,
+}
+
+/// What became of a pending media
+enum Outcome {
+    Copied,
+    Downloaded,
+    /// Answered with 404 or 410
+    Gone,
+    /// Queued without a client to download it
+    Left,
+}
+
+/// Record the tweets of `archive` as tweets of the account and put their
+/// media into its folder
+///
+/// Bundled files are copied and, with `api`, media the export leaves out is
+/// downloaded; without it, that media is recorded for later runs to
+/// download. Media recorded with its file before is skipped, so an import
+/// can be repeated, e.g. to retry failed downloads. `progress` is called
+/// with the number of handled media and the total.
+#[instrument(skip_all, fields(user = options.screen_name))]
+pub async fn import(
+    pool: &SqlitePool,
+    archive: &TwitterArchive,
+    api: Option<&Api>,
+    options: &ImportOptions,
+    mut progress: impl FnMut(u64, u64),
+) -> Result<ImportSummary> {
+    let parsed = archive.tweets().await?;
+    let mut summary = ImportSummary {
+        tweets: parsed.tweets.len() as u64 + parsed.unreadable,
+        skipped: parsed.unreadable,
+        ..Default::default()
+    };
+    tokio::fs::create_dir_all(&options.folder).await?;
+
+    let mut pending = Vec::new();
+    for tweet in &parsed.tweets {
+        if tweet.is_retweet {
+            summary.skipped += tweet.media.len() as u64;
+            continue;
+        }
+        let multiple = tweet.media.len() > 1;
+        let mut recorded = false;
+        for (i, media) in tweet.media.iter().enumerate() {
+            if db::verify_file(pool, &media.url, &options.folder).await? {
+                summary.skipped += 1;
+                continue;
+            }
+            let bundled = media.variants.iter().find_map(|url| {
+                let entry = archive.bundled(&bundled_name(&tweet.tweet_id, url)?)?;
+                Some((url, entry.to_string()))
+            });
+            // Named after the variant the file is of, like downloads
+            let download_url = bundled.as_ref().map_or(&media.variants[0], |(url, _)| url);
+            let item = media_item(tweet, media, download_url, multiple.then_some(i + 1));
+            record_item(pool, &options.screen_name, &item).await?;
+            recorded = true;
+            if bundled.is_none() {
+                summary.queued += 1;
+            }
+            pending.push(Pending {
+                item,
+                bundled: bundled.map(|(_, entry)| entry),
+            });
+        }
+        summary.imported += u64::from(recorded);
+    }
+
+    let max_stem_len = filename::max_stem_len(&options.folder);
+    let total = pending.len() as u64;
+    let mut done = 0;
+    progress(0, total);
+    let mut handled = futures::stream::iter(pending)
+        .map(|pending| async move {
+            let result = match (&pending.bundled, api) {
+                (Some(entry), _) => {
+                    copy_bundled(pool, archive, entry, &pending.item, options, max_stem_len).await
+                }
+                (None, Some(api)) => {
+                    download(pool, api, &pending.item, options, max_stem_len).await
+                }
+                (None, None) => Ok(Outcome::Left),
+            };
+            (pending.item, result)
+        })
+        .buffer_unordered(options.concurrency.max(1));
+
+    while let Some((item, result)) = handled.next().await {
+        done += 1;
+        progress(done, total);
+        match result {
+            Ok(Outcome::Copied) => summary.copied += 1,
+            Ok(Outcome::Downloaded) => summary.downloaded += 1,
+            Ok(Outcome::Gone) => {
+                warn!("{} of tweet {} is gone", item.url, item.tweet_id);
+                summary.failed += 1;
+            }
+            Ok(Outcome::Left) => {}
+            Err(e) => {
+                warn!("failed to import {}: {}", item.url, e);
+                summary.failed += 1;
+            }
+        }
+    }
+    Ok(summary)
+}
+
+fn media_item(
+    tweet: &ArchiveTweet,
+    media: &ArchiveMedia,
+    download_url: &str,
+    index: Option<usize>,
+) -> MediaItem {
+    let is_video = matches!(media.media_type, MediaType::Video);
+    MediaItem {
+        tweet_id: tweet.tweet_id.clone(),
+        url: media.url.clone(),
+        download_url: download_url.to_string(),
+        media_type: media.media_type.clone(),
+        poster_url: is_video.then(|| media.url.clone()),
+        timestamp: tweet.timestamp,
+        full_text: tweet.full_text.clone(),
+        index,
+        is_retweet: false,
+        engagement: tweet.engagement,
+        pinned: false,
+        duration_ms: media.duration_ms,
+        resolution: is_video
+            .then(|| task::variant_resolution(download_url))
+            .flatten(),
+    }
+}
+
+/// Record the tweet and media of `item`, without a file
+async fn record_item(pool: &SqlitePool, screen_name: &str, item: &MediaItem) -> Result<()> {
+    let tweet_time = item
+        .timestamp
+        .with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string();
+    db::upsert_tweet(
+        pool,
+        &item.tweet_id,
+        screen_name,
+        &tweet_time,
+        item.full_text.as_deref(),
+    )
+    .await?;
+    db::update_engagement(pool, &item.tweet_id, &item.engagement).await?;
+    db::upsert_media(
+        pool,
+        &item.tweet_id,
+        &item.url,
+        Some(&item.download_url),
+        None,
+    )
+    .await?;
+    if item.duration_ms.is_some() || item.resolution.is_some() {
+        db::update_video_details(pool, &item.url, item.duration_ms, item.resolution).await?;
+    }
+    Ok(())
+}
+
+/// Record the file of `item`
+async fn record_file(
+    pool: &SqlitePool,
+    item: &MediaItem,
+    filename: &str,
+    hash: &str,
+    size: u64,
+    algorithm: HashAlgorithm,
+) -> Result<()> {
+    db::upsert_media(pool, &item.tweet_id, &item.url, None, Some(filename)).await?;
+    db::update_hash(pool, &item.url, hash, algorithm).await?;
+    db::update_file_size(pool, &item.url, size).await?;
+    db::clear_gone(pool, &item.url).await
+}
+
+/// Path and name of the file of `item` in the account's folder
+fn target(
+    item: &MediaItem,
+    ext: &str,
+    options: &ImportOptions,
+    max_stem_len: usize,
+) -> Result<(PathBuf, String)> {
+    let segments = task::stem_segments(
+        options.filename_template.as_ref(),
+        options.timezone,
+        item,
+        &options.screen_name,
+    );
+    let stem = filename::fit(&segments, max_stem_len)
+        .ok_or_else(|| Error::FilenameTooLong(item.url.clone()))?;
+    let filename = format!("{stem}.{ext}");
+    Ok((options.folder.join(&filename), filename))
+}
+
+/// Copy the bundled file `entry` of `item` into the account's folder
+async fn copy_bundled(
+    pool: &SqlitePool,
+    archive: &TwitterArchive,
+    entry: &str,
+    item: &MediaItem,
+    options: &ImportOptions,
+    max_stem_len: usize,
+) -> Result<Outcome> {
+    let ext = Path::new(entry)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("jpg")
+        .to_ascii_lowercase();
+    let (path, filename) = target(item, &ext, options, max_stem_len)?;
+    archive.extract(entry, &path).await?;
+
+    let algorithm = options.hash_algorithm;
+    let (hash, size) = tokio::task::spawn_blocking(move || {
+        let hash = algorithm.hash_file(&path)?;
+        Ok::<_, std::io::Error>((hash, std::fs::metadata(&path)?.len()))
+    })
+    .await
+    .map_err(std::io::Error::other)??;
+    record_file(pool, item, &filename, &hash, size, algorithm).await?;
+    Ok(Outcome::Copied)
+}
+
+/// Download `item` into the account's folder
+async fn download(
+    pool: &SqlitePool,
+    api: &Api,
+    item: &MediaItem,
+    options: &ImportOptions,
+    max_stem_len: usize,
+) -> Result<Outcome> {
+    let (response, image_size, ext) = match item.media_type {
+        MediaType::Image => {
+            let (response, size) =
+                task::request_image(api, &item.url, options.image_size, None).await?;
+            let ext = task::image_extension(&item.url).unwrap_or_else(|| "jpg".to_string());
+            (response, Some(size), ext)
+        }
+        MediaType::Video => (
+            api.send(api.client().get(&item.download_url)).await?,
+            None,
+            "mp4".to_string(),
+        ),
+    };
+    if verify::is_gone(response.status()) {
+        db::mark_gone(pool, &item.url).await?;
+        return Ok(Outcome::Gone);
+    }
+    pushback::check(&response)?;
+    if !response.status().is_success() {
+        return Err(Error::Download(response.status()));
+    }
+
+    let (path, filename) = target(item, &ext, options, max_stem_len)?;
+    let download_url = response.url().to_string();
+    let total = response.content_length();
+    let (hash, size) = task::save_body(
+        Body::Response { response, total },
+        &path,
+        options.hash_algorithm,
+        options.max_file_size,
+        |_, _| {},
+    )
+    .await?;
+    record_file(pool, item, &filename, &hash, size, options.hash_algorithm).await?;
+    db::update_download_url(pool, &item.url, &download_url).await?;
+    if let Some(image_size) = image_size {
+        db::update_image_size(pool, &item.url, image_size).await?;
+    }
+    Ok(Outcome::Downloaded)
+}
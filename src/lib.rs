@@ -0,0 +1,78 @@
+//! Rust X/Twitter media downloader.
+//!
+//! The [`Task`] type downloads all media of a single account into a directory,
+//! recording tweets and files in a SQLite database (see [`db`]) so that later
+//! runs skip what is already on disk.
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use std::sync::Arc;
+//!
+//! # async fn run() -> rxd::Result<()> {
+//! let db = rxd::db::init_db(Path::new("rxd.db")).await?;
+//! let task = rxd::Task::builder()
+//!     .screen_name("nasa")
+//!     .credentials("auth_token", "ct0")
+//!     .db(db)
+//!     .build()
+//!     .await?;
+//! let totals = Arc::new(task).execute().await?;
+//! println!("{} new files", totals.downloaded);
+//! # Ok(())
+//! # }
+//! ```
+
+#![warn(clippy::unwrap_used)]
+
+#[cfg(not(any(feature = "rustls", feature = "native-tls")))]
+compile_error!("either the `rustls` or the `native-tls` feature must be enabled");
+
+pub mod api;
+pub mod archive;
+pub mod checksums;
+pub mod cli;
+pub mod config;
+pub mod convert;
+pub mod db;
+pub mod dedupe;
+pub mod deleted;
+pub mod doctor;
+pub mod embed;
+pub mod error;
+pub mod events;
+pub mod filename;
+pub mod filter;
+pub mod following;
+pub mod gallery;
+pub mod graphql;
+pub mod hash;
+pub mod hls;
+pub mod hook;
+pub mod import;
+pub mod list;
+pub mod listing;
+pub mod lock;
+pub mod metrics;
+pub mod network;
+pub mod notify;
+pub mod pack;
+pub mod phash;
+pub mod profile;
+pub mod pushback;
+pub mod query_ids;
+pub mod reload;
+pub mod report;
+pub mod rotation;
+pub mod screen_name;
+pub mod sidecar;
+pub mod stats;
+pub mod summary;
+pub mod syndication;
+pub mod task;
+pub mod thumbs;
+pub mod verify;
+
+pub use api::Api;
+pub use config::{Config, TaskConfig};
+pub use error::{Error, Result};
+pub use task::{ImageSize, MediaItem, MediaType, Task, TaskBuilder, User};
@@ -0,0 +1,107 @@
+//! Members of a List, each downloaded like a configured task.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{info, instrument};
+
+use crate::api::Api;
+use crate::error::{Error, Result};
+use crate::following::{AccountPage, account_page};
+use crate::graphql::{ListMembersData, Response};
+use crate::task::User;
+
+/// Numeric ID of a List, parsed from the ID or a link such as
+/// `https://x.com/i/lists/1234567890`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ListId(String);
+
+impl ListId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for ListId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for ListId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        // e.g. https://x.com/i/lists/123/members?s=20
+        let id = match s.split_once("/lists/") {
+            Some((_, rest)) => rest.split(['/', '?', '#']).next().unwrap_or_default(),
+            None => s,
+        };
+        if !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()) {
+            Ok(Self(id.to_string()))
+        } else {
+            Err(Error::Config(format!("not a list ID or link: {s}")))
+        }
+    }
+}
+
+impl TryFrom<String> for ListId {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// Parse a ListMembers response into accounts and the cursor of the next
+/// page
+#[instrument(skip_all)]
+pub fn parse_list_members_response(raw: &Value) -> Result<AccountPage> {
+    let response = Response::<ListMembersData>::deserialize(raw)
+        .map_err(|e| Error::Parse(format!("invalid ListMembers response: {e}")))?;
+
+    let instructions = response
+        .data
+        .and_then(|d| d.list)
+        .and_then(|l| l.members_timeline)
+        .and_then(|t| t.timeline)
+        .map(|t| t.instructions)
+        .ok_or_else(|| Error::Parse("failed to find instructions".to_string()))?;
+
+    Ok(account_page(&instructions))
+}
+
+/// Members of `list`, in the order they are listed
+#[instrument(skip_all, fields(list = %list))]
+pub async fn list_members(api: &Api, list: &ListId) -> Result<Vec<User>> {
+    let mut members: Vec<User> = Vec::new();
+    let mut unavailable = 0;
+    let mut cursor = None;
+    loop {
+        let page = api.list_members(list, cursor.as_deref()).await?;
+        unavailable += page.unavailable;
+        let listed = page.accounts.len() + page.unavailable;
+        for member in page.accounts {
+            if !members.iter().any(|known| known.rest_id == member.rest_id) {
+                members.push(member);
+            }
+        }
+        // The last page repeats its cursor without any members
+        match page.next_cursor {
+            Some(next) if listed > 0 && cursor.as_ref() != Some(&next) => cursor = Some(next),
+            _ => break,
+        }
+    }
+
+    info!(
+        "list {} has {} members to download, {} unavailable",
+        list,
+        members.len(),
+        unavailable
+    );
+    Ok(members)
+}
@@ -0,0 +1,94 @@
+//! Lines printed by `download --list-only`, one per media item, for handing
+//! to another downloader.
+
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::task::{self, ImageSize, MediaItem, MediaType};
+
+/// Format of a listed item, `{url}` if not given
+///
+/// Placeholders are `{url}`, `{tweet_id}`, `{date}` (RFC 3339), `{type}`
+/// (`image` or `video`) and `{screen_name}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListFormat {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Url,
+    TweetId,
+    Date,
+    Type,
+    ScreenName,
+}
+
+impl Default for ListFormat {
+    fn default() -> Self {
+        Self {
+            parts: vec![Part::Url],
+        }
+    }
+}
+
+impl FromStr for ListFormat {
+    type Err = Error;
+
+    fn from_str(format: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = format;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..].find('}').ok_or_else(|| {
+                Error::Config(format!("unclosed placeholder in --format: {format}"))
+            })?;
+            parts.push(match &rest[start + 1..start + end] {
+                "url" => Part::Url,
+                "tweet_id" => Part::TweetId,
+                "date" => Part::Date,
+                "type" => Part::Type,
+                "screen_name" => Part::ScreenName,
+                other => {
+                    return Err(Error::Config(format!(
+                        "unknown placeholder {{{other}}} in --format"
+                    )));
+                }
+            });
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+}
+
+impl ListFormat {
+    /// Line for `item` of `screen_name`, images at `image_size`
+    pub fn line(&self, item: &MediaItem, screen_name: &str, image_size: ImageSize) -> String {
+        let mut line = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => line.push_str(text),
+                Part::Url => line.push_str(&url(item, image_size)),
+                Part::TweetId => line.push_str(&item.tweet_id),
+                Part::Date => line.push_str(&item.timestamp.to_rfc3339()),
+                Part::Type => line.push_str(item.media_type.as_str()),
+                Part::ScreenName => line.push_str(screen_name),
+            }
+        }
+        line
+    }
+}
+
+/// URL the item would be downloaded from
+fn url(item: &MediaItem, image_size: ImageSize) -> String {
+    match item.media_type {
+        MediaType::Image => task::sized_image_url(&item.download_url, image_size.as_str()),
+        MediaType::Video => item.download_url.clone(),
+    }
+}
@@ -0,0 +1,163 @@
+//! Lock keeping two runs off the same database and download folders.
+//!
+//! A run holds an advisory lock on `<database>.lock` and writes its process
+//! ID and start time into it, so a second run can say who is in the way.
+//! The operating system releases the lock when the process ends, however
+//! it ends, so the details a crashed run left behind are only reported and
+//! overwritten.
+
+use std::fmt;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use tracing::{info, warn};
+
+use crate::error::{Error, Result};
+
+/// Extension added to the database file name for its lock
+const LOCK_EXTENSION: &str = "lock";
+
+/// Lock file of the database at `db`
+pub fn lock_path(db: &Path) -> PathBuf {
+    let mut path = db.as_os_str().to_owned();
+    path.push(".");
+    path.push(LOCK_EXTENSION);
+    PathBuf::from(path)
+}
+
+/// The process holding a lock, as it wrote itself into the lock file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockHolder {
+    pub pid: u32,
+    pub started: DateTime<Local>,
+}
+
+impl LockHolder {
+    fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            started: Local::now(),
+        }
+    }
+
+    fn parse(contents: &str) -> Option<Self> {
+        let (pid, started) = contents.trim().split_once(' ')?;
+        Some(Self {
+            pid: pid.parse().ok()?,
+            started: DateTime::parse_from_rfc3339(started)
+                .ok()?
+                .with_timezone(&Local),
+        })
+    }
+}
+
+impl fmt::Display for LockHolder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "pid {}, started {}",
+            self.pid,
+            self.started.format("%Y-%m-%d %H:%M:%S")
+        )
+    }
+}
+
+/// A held lock, released when dropped
+///
+/// Only runs that write take it, commands that only read the database
+/// rely on SQLite's own locking and run alongside them.
+#[derive(Debug)]
+pub struct RunLock {
+    file: File,
+    /// Holder recorded by a run that ended without releasing the lock
+    stale: Option<LockHolder>,
+}
+
+impl RunLock {
+    /// Take the lock at `path` or fail with [`Error::Locked`] if another
+    /// process holds it
+    pub fn try_acquire(path: &Path) -> Result<Self> {
+        let mut file = open(path)?;
+        match file.try_lock() {
+            Ok(()) => Self::held(file),
+            Err(TryLockError::WouldBlock) => Err(Error::Locked {
+                path: path.to_path_buf(),
+                holder: read_holder(&mut file),
+            }),
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+
+    /// Take the lock at `path`, waiting for another process to release it
+    pub async fn acquire(path: &Path) -> Result<Self> {
+        match Self::try_acquire(path) {
+            Err(Error::Locked { holder, .. }) => {
+                match holder {
+                    Some(holder) => info!("waiting for the run of {} to finish", holder),
+                    None => info!("waiting for another rxd process to finish"),
+                }
+                let path = path.to_path_buf();
+                tokio::task::spawn_blocking(move || {
+                    let file = open(&path)?;
+                    file.lock()?;
+                    Self::held(file)
+                })
+                .await
+                .map_err(io::Error::other)?
+            }
+            result => result,
+        }
+    }
+
+    /// Holder recorded by a run that ended without releasing the lock,
+    /// whose lock this one reclaimed
+    pub fn stale(&self) -> Option<&LockHolder> {
+        self.stale.as_ref()
+    }
+
+    /// Record this process in a lock it just took
+    fn held(mut file: File) -> Result<Self> {
+        let stale = read_holder(&mut file);
+        if let Some(stale) = &stale {
+            warn!(
+                "reclaiming the lock of a run that did not finish ({})",
+                stale
+            );
+        }
+        let holder = LockHolder::current();
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(file, "{} {}", holder.pid, holder.started.to_rfc3339())?;
+        file.sync_data()?;
+        Ok(Self { file, stale })
+    }
+}
+
+impl Drop for RunLock {
+    fn drop(&mut self) {
+        // An empty lock file tells the next run this one finished
+        let _ = self.file.set_len(0);
+    }
+}
+
+fn open(path: &Path) -> Result<File> {
+    // Taken before the database is opened, which creates the directory
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?)
+}
+
+fn read_holder(file: &mut File) -> Option<LockHolder> {
+    let mut contents = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut contents).ok()?;
+    LockHolder::parse(&contents)
+}
@@ -1,84 +1,936 @@
 #![warn(clippy::unwrap_used)]
 
-mod db;
-mod task;
-
-use std::fs;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::time::Duration;
 
-use clap::{Parser, Subcommand};
-use tracing::info;
-use tracing_indicatif::IndicatifLayer;
+use chrono::NaiveDate;
+use clap::{ArgAction, Parser, Subcommand};
+use croner::Cron;
+use rxd::cli::download::{DownloadArgs, LogArgs, ReplayArgs};
+use rxd::cli::export::{GalleryArgs, PackArgs};
+use rxd::cli::maintenance::{ManifestArgs, ThumbArgs, VerifyArgs};
+use rxd::cli::query::{NearDuplicateArgs, StatsArgs};
+use rxd::cli::{
+    EXIT_LOCKED, Globals, download, export, import, maintenance, parse_duration, query, setup,
+};
+use rxd::thumbs::ThumbFormat;
+use rxd::{config, db, events, listing};
 use tracing_subscriber::filter::LevelFilter;
-use tracing_subscriber::layer::SubscriberExt;
-use tracing_subscriber::util::SubscriberInitExt;
 
 #[derive(Parser)]
 #[command(version)]
 struct Cli {
+    /// Path to config file, searched for in standard locations if omitted
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Increase log verbosity (-v for debug, -vv for trace)
+    #[arg(short, long, global = true, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Decrease log verbosity (-q for warnings, -qq for errors only)
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    quiet: u8,
+
+    /// Console log format, json disables progress bars and writes to stdout
+    #[arg(long, global = true, value_enum)]
+    log_format: Option<config::LogFormat>,
+
+    /// Also write logs to this file, appending if it exists
+    #[arg(long, global = true)]
+    log_file: Option<PathBuf>,
+
+    /// Log level for --log-file [default: debug]
+    #[arg(long, global = true)]
+    log_file_level: Option<LevelFilter>,
+
+    /// Log format for --log-file
+    #[arg(long, global = true, value_enum)]
+    log_file_format: Option<config::LogFormat>,
+
+    /// Wait for another run using the same database to finish instead of
+    /// exiting, for commands that write to it
+    #[arg(long, global = true)]
+    wait: bool,
+
     #[command(subcommand)]
     command: Command,
 }
 
 #[derive(Subcommand)]
+// Parsed once, the size of the parsed cron expression does not matter
+#[allow(clippy::large_enum_variant)]
 enum Command {
     /// Download with a config file
     Download {
         /// Path to config file
-        config_path: PathBuf,
+        config_path: Option<PathBuf>,
+
+        /// Emit a machine-readable event stream on stdout, logs go to stderr
+        #[arg(long, value_enum)]
+        events: Option<events::EventFormat>,
+
+        /// Keep running, re-checking all accounts on an interval
+        #[arg(long)]
+        watch: bool,
+
+        /// Time between runs in watch mode, e.g. 30m, 2h [default: 1h]
+        #[arg(long, value_parser = parse_duration, requires = "watch")]
+        interval: Option<Duration>,
+
+        /// Run on a cron schedule instead of an interval, e.g. "0 3 * * *"
+        #[arg(long, value_parser = parse_cron, conflicts_with = "interval")]
+        cron: Option<Cron>,
+
+        /// Rediscover GraphQL query IDs instead of using the cached ones
+        #[arg(long)]
+        refresh_query_ids: bool,
+
+        /// Write a JSON report of every run to this file
+        #[arg(long)]
+        report: Option<PathBuf>,
+
+        /// Keep an existing report, writing the new one with the time appended
+        /// to its name
+        #[arg(long)]
+        keep_reports: bool,
+
+        /// Show a desktop notification when a run finishes
+        #[arg(long)]
+        notify: bool,
+
+        /// Download every item again, replacing the files on disk
+        #[arg(long)]
+        overwrite: bool,
+
+        /// Page every timeline to the end, ignoring anything that would stop
+        /// early; files on disk are still skipped
+        #[arg(long, visible_alias = "full")]
+        force: bool,
+
+        /// Try media the server answered 404 or 410 for in earlier runs again
+        #[arg(long)]
+        recheck_gone: bool,
+
+        /// Only download this account, may be repeated
+        #[arg(long = "user", value_name = "SCREEN_NAME", value_parser = parse_screen_name)]
+        users: Vec<String>,
+
+        /// Also download every account you follow
+        #[arg(long)]
+        following: bool,
+
+        /// Download at most this many followed accounts, the most recently
+        /// followed first
+        #[arg(long)]
+        limit_accounts: Option<usize>,
+
+        /// Download public media without an account, ignoring auth_token and
+        /// ct0
+        #[arg(long, conflicts_with = "following")]
+        guest: bool,
+
+        /// Print the accounts that would be downloaded, including followed
+        /// accounts and list members, without downloading anything
+        #[arg(long, conflicts_with_all = ["watch", "cron"])]
+        dry_run: bool,
+
+        /// Print the media that would be downloaded, one URL per line on
+        /// stdout, without downloading or recording anything
+        #[arg(
+            long,
+            conflicts_with_all = ["watch", "cron", "dry_run", "events", "report", "overwrite"]
+        )]
+        list_only: bool,
+
+        /// Line printed per item with --list-only, with the placeholders
+        /// {url}, {tweet_id}, {date}, {type} and {screen_name} [default: {url}]
+        #[arg(long, requires = "list_only")]
+        format: Option<listing::ListFormat>,
+    },
+    /// Download the media of responses saved with archive_responses, with
+    /// no API requests
+    ///
+    /// Archived pages that cannot be read are reported and skipped.
+    Replay {
+        /// Folder of archived responses, e.g. downloads/<user>/_raw
+        raw_dir: PathBuf,
+
+        /// Folder to save the media to
+        #[arg(long, required_unless_present = "list_only")]
+        save_path: Option<PathBuf>,
+
+        /// Account the responses belong to [default: the one of the
+        /// archived account lookup]
+        #[arg(long, value_parser = parse_screen_name)]
+        user: Option<String>,
+
+        /// Print the media instead of downloading them, like download
+        /// --list-only
+        #[arg(long)]
+        list_only: bool,
+
+        /// Line printed per item with --list-only [default: {url}]
+        #[arg(long, requires = "list_only")]
+        format: Option<listing::ListFormat>,
+    },
+    /// Check the config, connectivity, credentials, save paths and database
+    ///
+    /// Exits with a non-zero status if any check fails.
+    Doctor {
+        /// Path to config file
+        config_path: Option<PathBuf>,
+    },
+    /// Check that the credentials are accepted and show whose they are
+    ///
+    /// Exits with a non-zero status if they are rejected.
+    CheckAuth {
+        /// Path to config file, not needed with --auth-token and --ct0
+        config_path: Option<PathBuf>,
+
+        /// auth_token cookie to check instead of the one in the config
+        #[arg(long, requires = "ct0")]
+        auth_token: Option<String>,
+
+        /// ct0 cookie to check instead of the one in the config
+        #[arg(long, requires = "auth_token")]
+        ct0: Option<String>,
+    },
+    /// Show the details of an account without downloading anything
+    ///
+    /// Exits with a non-zero status if the account is suspended or
+    /// otherwise unavailable. With a database, also shows what is archived
+    /// of it.
+    Info {
+        /// Account to look up, by screen name or profile URL
+        #[arg(value_parser = parse_screen_name)]
+        screen_name: String,
+
+        /// auth_token cookie to use instead of the one in the config
+        #[arg(long, requires = "ct0")]
+        auth_token: Option<String>,
+
+        /// ct0 cookie to use instead of the one in the config
+        #[arg(long, requires = "auth_token")]
+        ct0: Option<String>,
+
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+
+        /// Database to read, the one of the config file if omitted
+        #[arg(long)]
+        db: Option<PathBuf>,
+    },
+    /// List the accounts in the database with their media counts
+    ListUsers {
+        /// Database to read, the one of the config file if omitted
+        #[arg(long)]
+        db: Option<PathBuf>,
+
+        /// Order of the accounts
+        #[arg(long, value_enum, default_value_t)]
+        sort: db::UserOrder,
+
+        /// Print a JSON array instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Tweets, files and bytes archived per account
+    ///
+    /// Sizes of files downloaded before they were recorded are read from
+    /// disk once, which is slower on the first run.
+    Stats {
+        /// Only this account
+        #[arg(long, value_parser = parse_screen_name)]
+        user: Option<String>,
+
+        /// Add tweets per month, bytes per media type and the largest files
+        #[arg(long)]
+        detailed: bool,
+
+        /// Largest files listed with --detailed
+        #[arg(long, default_value_t = 10, requires = "detailed")]
+        top: usize,
+
+        /// Print JSON instead of tables
+        #[arg(long)]
+        json: bool,
+
+        /// Database to read, the one of the config file if omitted
+        #[arg(long)]
+        db: Option<PathBuf>,
+
+        /// Folder with the account's files [default: its save_path, or
+        /// downloads/<user>]
+        #[arg(long, requires = "user")]
+        dir: Option<PathBuf>,
+    },
+    /// Find archived tweets by their text
+    ///
+    /// Prints one tab-separated line per tweet: ID, date, account, a
+    /// snippet of the text and the files of its media.
+    SearchText {
+        /// Text to look for, ignoring case
+        query: String,
+
+        /// Only search tweets of this account
+        #[arg(long, value_parser = parse_screen_name)]
+        user: Option<String>,
+
+        /// Database to read, the one of the config file if omitted
+        #[arg(long)]
+        db: Option<PathBuf>,
+    },
+    /// Record files downloaded before the database existed
+    ///
+    /// Files already recorded are skipped, so the import can be re-run or
+    /// resumed after an interruption.
+    ImportFolder {
+        /// Folder with the account's media
+        dir: PathBuf,
+
+        /// Account the media belongs to
+        #[arg(long, value_parser = parse_screen_name)]
+        user: String,
+
+        /// Database to write, the one of the config file if omitted
+        #[arg(long)]
+        db: Option<PathBuf>,
+
+        /// Files hashed at once [default: number of CPUs]
+        #[arg(long)]
+        threads: Option<usize>,
+    },
+    /// Record what another downloader has downloaded, so it is skipped, or
+    /// what a Twitter data export holds
+    Import {
+        /// Downloader that wrote the archive, or twitter-archive for a data
+        /// export
+        #[arg(long, value_enum)]
+        from: ImportSource,
+
+        /// The data export, as a ZIP file or unpacked
+        #[arg(required_if_eq("from", "twitter-archive"), conflicts_with = "archive")]
+        path: Option<PathBuf>,
+
+        /// The downloader's archive of downloaded items
+        #[arg(long, required_if_eq("from", "gallery-dl"))]
+        archive: Option<PathBuf>,
+
+        /// Folder with the downloader's JSON metadata files
+        #[arg(long)]
+        metadata_dir: Option<PathBuf>,
+
+        /// Account of items without a metadata file, left out if omitted;
+        /// of a data export, instead of the account it names
+        #[arg(long, value_parser = parse_screen_name)]
+        user: Option<String>,
+
+        /// Record media a data export does not include instead of
+        /// downloading it
+        #[arg(long)]
+        no_download: bool,
+
+        /// Database to write, the one of the config file if omitted
+        #[arg(long)]
+        db: Option<PathBuf>,
+    },
+    /// Hash downloaded files recorded without a hash
+    ///
+    /// Only files without a hash are read, so the run can be repeated or
+    /// resumed after an interruption.
+    Hash {
+        /// Database to update, the one of the config file if omitted
+        #[arg(long)]
+        db: Option<PathBuf>,
+
+        /// Only hash files of this account
+        #[arg(long, value_parser = parse_screen_name)]
+        user: Option<String>,
+
+        /// Folder with the account's files [default: its save_path, or
+        /// downloads/<user>]
+        #[arg(long, requires = "user")]
+        dir: Option<PathBuf>,
+
+        /// Files hashed at once [default: number of CPUs]
+        #[arg(long)]
+        threads: Option<usize>,
+    },
+    /// Check downloaded files against their recorded hashes
+    ///
+    /// Prints one tab-separated line per damaged file, `missing` or
+    /// `corrupt` and its path, and exits with 1 if any are left.
+    Verify {
+        /// Database to check, the one of the config file if omitted
+        #[arg(long)]
+        db: Option<PathBuf>,
+
+        /// Only check files of this account
+        #[arg(long, value_parser = parse_screen_name)]
+        user: Option<String>,
+
+        /// Folder with the account's files [default: its save_path, or
+        /// downloads/<user>]
+        #[arg(long, requires = "user")]
+        dir: Option<PathBuf>,
+
+        /// Files hashed at once [default: number of CPUs]
+        #[arg(long)]
+        threads: Option<usize>,
+
+        /// Download missing and corrupt files again with the credentials
+        /// of the config
+        #[arg(long)]
+        repair: bool,
+    },
+    /// List recorded files with identical content
+    ///
+    /// Prints one tab-separated line per file, its hash, account, name and
+    /// whether it is a link, then the links and the space they save.
+    Duplicates {
+        /// Database to read, the one of the config file if omitted
+        #[arg(long)]
+        db: Option<PathBuf>,
+    },
+    /// Write SHA256SUMS files to check the downloads with sha256sum -c
+    ///
+    /// One per account folder, or a single one in --root listing every
+    /// file below it. Recorded hashes are used where possible and entries
+    /// of changed and deleted files are updated on every run.
+    Manifest {
+        /// Only the files of this account
+        #[arg(long, conflicts_with = "root", value_parser = parse_screen_name)]
+        user: Option<String>,
+
+        /// Write one SHA256SUMS in this folder instead of one per account
+        #[arg(long)]
+        root: Option<PathBuf>,
+
+        /// Database to read, the one of the config file if omitted
+        #[arg(long)]
+        db: Option<PathBuf>,
+
+        /// Folder with the account's files [default: its save_path, or
+        /// downloads/<user>]
+        #[arg(long, requires = "user")]
+        dir: Option<PathBuf>,
+
+        /// Files hashed at once [default: number of CPUs]
+        #[arg(long)]
+        threads: Option<usize>,
+    },
+    /// Make small previews of downloaded images with ffmpeg
+    ///
+    /// Thumbnails go into a .thumbs folder in each account's folder and are
+    /// only made again when the image is newer. With --videos, videos
+    /// without a poster get a frame saved next to them. Images that cannot be read
+    /// are listed and skipped.
+    Thumbs {
+        /// Only the images of this account
+        #[arg(long, value_parser = parse_screen_name)]
+        user: Option<String>,
+
+        /// Longest side of thumbnails in pixels
+        #[arg(long, default_value_t = 400)]
+        size: u32,
+
+        /// File type of thumbnails
+        #[arg(long, value_enum, default_value_t)]
+        format: ThumbFormat,
+
+        /// Also save a frame of videos without a thumbnail as
+        /// <name>.thumb.jpg
+        #[arg(long)]
+        videos: bool,
+
+        /// Database to read, the one of the config file if omitted
+        #[arg(long)]
+        db: Option<PathBuf>,
+
+        /// Folder with the account's files [default: its save_path, or
+        /// downloads/<user>]
+        #[arg(long, requires = "user")]
+        dir: Option<PathBuf>,
+
+        /// Thumbnails made at once [default: number of CPUs]
+        #[arg(long)]
+        threads: Option<usize>,
+    },
+    /// Report images that look alike, e.g. reposted at another size
+    ///
+    /// Perceptual hashes of images without one are taken first with
+    /// ffmpeg. Nothing is deleted.
+    NearDuplicates {
+        /// Only the images of this account
+        #[arg(long, value_parser = parse_screen_name)]
+        user: Option<String>,
+
+        /// Most bits of 64 the hashes of near-duplicates may differ in
+        #[arg(long, default_value_t = 6)]
+        distance: u32,
+
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+
+        /// Database to read, the one of the config file if omitted
+        #[arg(long)]
+        db: Option<PathBuf>,
+
+        /// Folder with the account's files [default: its save_path, or
+        /// downloads/<user>]
+        #[arg(long, requires = "user")]
+        dir: Option<PathBuf>,
+
+        /// Images hashed at once [default: number of CPUs]
+        #[arg(long)]
+        threads: Option<usize>,
+    },
+    /// Bundle an account's files, captions and a manifest into one archive
+    ///
+    /// The archive mirrors the account's folder. Its type follows the
+    /// extension of --out: .tar, .tar.gz, .tar.zst or .zip. .tar.gz and
+    /// .tar.zst need the gzip or zstd command.
+    Pack {
+        /// Account to pack
+        #[arg(long, value_parser = parse_screen_name)]
+        user: String,
+
+        /// Archive to write
+        #[arg(long)]
+        out: PathBuf,
+
+        /// Only tweets posted on or after this day, YYYY-MM-DD
+        #[arg(long)]
+        since: Option<NaiveDate>,
+
+        /// Only tweets posted on or before this day, YYYY-MM-DD
+        #[arg(long)]
+        until: Option<NaiveDate>,
+
+        /// Pack files that no longer match their recorded hash instead of
+        /// failing
+        #[arg(long)]
+        allow_mismatch: bool,
+
+        /// Database to read, the one of the config file if omitted
+        #[arg(long)]
+        db: Option<PathBuf>,
+
+        /// Folder with the account's files [default: its save_path, or
+        /// downloads/<user>]
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+    /// Write a static HTML gallery of an account's downloaded files
+    ///
+    /// Pages link to the files where they are and load nothing from the
+    /// network. Generating into the same folder again only rewrites what
+    /// changed.
+    Gallery {
+        /// Account to show
+        #[arg(long, value_parser = parse_screen_name)]
+        user: String,
+
+        /// Folder to write the gallery into
+        #[arg(long)]
+        out: PathBuf,
+
+        /// Files per page
+        #[arg(long, default_value_t = 500)]
+        page_size: usize,
+
+        /// Database to read, the one of the config file if omitted
+        #[arg(long)]
+        db: Option<PathBuf>,
+
+        /// Folder with the account's files [default: its save_path, or
+        /// downloads/<user>]
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+    /// Look up archived tweets and record which were deleted upstream
+    ///
+    /// Uses the credentials of the config. Results are recorded as they
+    /// come in, so an interrupted or rate-limited run continues where it
+    /// stopped. Tweets known to be deleted are not looked up again.
+    CheckDeleted {
+        /// Only tweets of this account
+        #[arg(long, value_parser = parse_screen_name)]
+        user: Option<String>,
+
+        /// Also check tweets again whose last check is at least this old,
+        /// e.g. 30d; otherwise only tweets never checked are looked up
+        #[arg(long, value_parser = parse_duration)]
+        older_than: Option<Duration>,
+
+        /// Print the tweets found deleted with their files
+        #[arg(long)]
+        list: bool,
+
+        /// Database to check, the one of the config file if omitted
+        #[arg(long)]
+        db: Option<PathBuf>,
     },
 }
 
+/// Downloaders `rxd import` reads archives of
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum ImportSource {
+    /// Archive of gallery-dl's --download-archive
+    GalleryDl,
+    /// Data export of one's own account
+    TwitterArchive,
+}
+
+impl Cli {
+    /// Console log level derived from -v / -q flags
+    fn log_level(&self) -> LevelFilter {
+        match (self.verbose, self.quiet) {
+            (0, 0) => LevelFilter::INFO,
+            (1, _) => LevelFilter::DEBUG,
+            (_, 0) => LevelFilter::TRACE,
+            (_, 1) => LevelFilter::WARN,
+            _ => LevelFilter::ERROR,
+        }
+    }
+}
+
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+async fn main() -> rxd::Result<ExitCode> {
     let cli = Cli::parse();
+    let globals = Globals {
+        config: cli.config.clone(),
+        wait: cli.wait,
+    };
+    let log = LogArgs {
+        console_level: cli.log_level(),
+        console_format: cli.log_format,
+        log_file: cli.log_file.clone(),
+        log_file_level: cli.log_file_level,
+        log_file_format: cli.log_file_format,
+    };
+    let success = |()| ExitCode::SUCCESS;
 
-    let indicatif_layer = IndicatifLayer::new();
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::fmt::layer()
-                .with_writer(indicatif_layer.get_stderr_writer())
-                .with_timer(tracing_subscriber::fmt::time::LocalTime::new(
-                    time::macros::format_description!("[hour]:[minute]:[second]"),
-                )),
+    let result = match &cli.command {
+        Command::Download {
+            config_path,
+            events,
+            watch,
+            interval,
+            cron,
+            refresh_query_ids,
+            report,
+            keep_reports,
+            notify,
+            overwrite,
+            force,
+            recheck_gone,
+            users,
+            following,
+            limit_accounts,
+            guest,
+            dry_run,
+            list_only,
+            format,
+        } => {
+            let args = DownloadArgs {
+                config_path: config_path.clone(),
+                events: *events,
+                watch: *watch,
+                interval: *interval,
+                cron: cron.clone(),
+                refresh_query_ids: *refresh_query_ids,
+                report: report.clone(),
+                keep_reports: *keep_reports,
+                notify: *notify,
+                overwrite: *overwrite,
+                full_scan: *force,
+                recheck_gone: *recheck_gone,
+                users: users.clone(),
+                following: *following,
+                limit_accounts: *limit_accounts,
+                guest: *guest,
+                dry_run: *dry_run,
+                list_only: list_only.then(|| format.clone().unwrap_or_default()),
+                quiet: cli.quiet > 0,
+                replay: None,
+            };
+            download::download(&globals, &args, &log).await.map(success)
+        }
+        Command::Replay {
+            raw_dir,
+            save_path,
+            user,
+            list_only,
+            format,
+        } => {
+            let args = DownloadArgs {
+                list_only: list_only.then(|| format.clone().unwrap_or_default()),
+                quiet: cli.quiet > 0,
+                replay: Some(ReplayArgs {
+                    raw_dir: raw_dir.clone(),
+                    save_path: save_path.clone(),
+                    user: user.clone(),
+                }),
+                ..Default::default()
+            };
+            download::download(&globals, &args, &log).await.map(success)
+        }
+        Command::Doctor { config_path } => {
+            setup::doctor(config_path.as_deref().or(cli.config.as_deref())).await
+        }
+        Command::CheckAuth {
+            config_path,
+            auth_token,
+            ct0,
+        } => {
+            setup::check_auth(
+                config_path.as_deref().or(cli.config.as_deref()),
+                auth_token.as_deref().zip(ct0.as_deref()),
+            )
+            .await
+        }
+        Command::Info {
+            screen_name,
+            auth_token,
+            ct0,
+            json,
+            db,
+        } => {
+            let cookies = auth_token.as_deref().zip(ct0.as_deref());
+            query::user_info(&globals, screen_name, cookies, *json, db.as_deref()).await
+        }
+        Command::ListUsers { db, sort, json } => {
+            query::list_users(&globals, db.as_deref(), *sort, *json)
+                .await
+                .map(success)
+        }
+        Command::SearchText { query, user, db } => {
+            query::search_text(&globals, db.as_deref(), query, user.as_deref())
+                .await
+                .map(success)
+        }
+        Command::Stats {
+            user,
+            detailed,
+            top,
+            json,
+            db,
+            dir,
+        } => {
+            let args = StatsArgs {
+                screen_name: user.as_deref(),
+                detailed: *detailed,
+                top: *top,
+                json: *json,
+                db: db.as_deref(),
+                dir: dir.as_deref(),
+            };
+            query::stats(&globals, &args).await.map(success)
+        }
+        Command::Duplicates { db } => query::list_duplicates(&globals, db.as_deref())
+            .await
+            .map(success),
+        Command::NearDuplicates {
+            user,
+            distance,
+            json,
+            db,
+            dir,
+            threads,
+        } => {
+            let args = NearDuplicateArgs {
+                screen_name: user.as_deref(),
+                max_distance: *distance,
+                json: *json,
+                db: db.as_deref(),
+                dir: dir.as_deref(),
+                threads: *threads,
+            };
+            query::near_duplicates(&globals, &args).await.map(success)
+        }
+        Command::ImportFolder {
+            dir,
+            user,
+            db,
+            threads,
+        } => import::import_folder(&globals, db.as_deref(), dir, user, *threads)
+            .await
+            .map(success),
+        Command::Import {
+            from: ImportSource::GalleryDl,
+            archive,
+            metadata_dir,
+            user,
+            db,
+            ..
+        } => {
+            let archive = archive.as_deref().expect("required by clap");
+            import::import_gallery_dl(
+                &globals,
+                db.as_deref(),
+                archive,
+                metadata_dir.as_deref(),
+                user.as_deref(),
+            )
+            .await
+            .map(success)
+        }
+        Command::Import {
+            from: ImportSource::TwitterArchive,
+            path,
+            user,
+            no_download,
+            db,
+            ..
+        } => {
+            let path = path.as_deref().expect("required by clap");
+            import::import_twitter_archive(
+                &globals,
+                db.as_deref(),
+                path,
+                user.as_deref(),
+                *no_download,
+            )
+            .await
+            .map(success)
+        }
+        Command::Hash {
+            db,
+            user,
+            dir,
+            threads,
+        } => maintenance::backfill_hashes(
+            &globals,
+            db.as_deref(),
+            user.as_deref(),
+            dir.as_deref(),
+            *threads,
         )
-        .with(indicatif_layer)
-        .with(LevelFilter::INFO)
-        .init();
-    info!("tracing initialized");
-
-    let (raw_config, config_dir) = match &cli.command {
-        Command::Download { config_path } => {
-            info!("reading {}", config_path.display());
-            let content = fs::read_to_string(config_path)?;
-            let dir = config_path
-                .parent()
-                .map(|p| p.to_path_buf())
-                .unwrap_or_else(|| PathBuf::from("."));
-            (content, dir)
+        .await
+        .map(success),
+        Command::Verify {
+            db,
+            user,
+            dir,
+            threads,
+            repair,
+        } => {
+            let args = VerifyArgs {
+                screen_name: user.as_deref(),
+                db: db.as_deref(),
+                dir: dir.as_deref(),
+                threads: *threads,
+                repair: *repair,
+            };
+            maintenance::verify_files(&globals, &args).await
+        }
+        Command::Manifest {
+            user,
+            root,
+            db,
+            dir,
+            threads,
+        } => {
+            let args = ManifestArgs {
+                screen_name: user.as_deref(),
+                root: root.as_deref(),
+                db: db.as_deref(),
+                dir: dir.as_deref(),
+                threads: *threads,
+            };
+            maintenance::write_manifests(&globals, &args)
+                .await
+                .map(success)
+        }
+        Command::Thumbs {
+            user,
+            size,
+            format,
+            videos,
+            db,
+            dir,
+            threads,
+        } => {
+            let args = ThumbArgs {
+                screen_name: user.as_deref(),
+                size: *size,
+                format: *format,
+                videos: *videos,
+                db: db.as_deref(),
+                dir: dir.as_deref(),
+                threads: *threads,
+            };
+            maintenance::make_thumbs(&globals, &args).await.map(success)
+        }
+        Command::CheckDeleted {
+            user,
+            older_than,
+            list,
+            db,
+        } => {
+            maintenance::check_deleted(&globals, db.as_deref(), user.as_deref(), *older_than, *list)
+                .await
+                .map(success)
+        }
+        Command::Pack {
+            user,
+            out,
+            since,
+            until,
+            allow_mismatch,
+            db,
+            dir,
+        } => {
+            let args = PackArgs {
+                screen_name: user,
+                out,
+                since: *since,
+                until: *until,
+                allow_mismatch: *allow_mismatch,
+                db: db.as_deref(),
+                dir: dir.as_deref(),
+            };
+            export::pack(&globals, &args).await.map(success)
+        }
+        Command::Gallery {
+            user,
+            out,
+            page_size,
+            db,
+            dir,
+        } => {
+            let args = GalleryArgs {
+                screen_name: user,
+                out,
+                page_size: *page_size,
+                db: db.as_deref(),
+                dir: dir.as_deref(),
+            };
+            export::gallery(&globals, &args).await.map(success)
         }
     };
-    let config: task::Config = toml::from_str(&raw_config)?;
-
-    // Initialize database in the same directory as config file
-    let db_path = config_dir.join("rxd.db");
-    let db = db::init_db(&db_path).await?;
-
-    for task_config in config.tasks.iter() {
-        let task = Arc::new(
-            task::Task::new(
-                &task_config.screen_name,
-                &config.auth_token,
-                &config.ct0,
-                config.concurrent_downloads,
-                task_config.save_path.as_deref(),
-                db.clone(),
-            )
-            .await?,
-        );
-        task.execute().await?;
+
+    match result {
+        Err(e @ rxd::Error::Locked { .. }) => {
+            eprintln!("{e}, pass --wait to wait for it");
+            Ok(ExitCode::from(EXIT_LOCKED))
+        }
+        result => result,
     }
+}
+
+/// Parse a standard 5-field cron expression
+fn parse_cron(s: &str) -> Result<Cron, String> {
+    Cron::from_str(s).map_err(|e| format!("invalid cron expression \"{s}\": {e}"))
+}
 
-    Ok(())
+/// Parse a screen name, `@handle` or profile URL into the screen name
+fn parse_screen_name(s: &str) -> Result<String, String> {
+    rxd::screen_name::normalize(s).map_err(|e| e.to_string())
 }
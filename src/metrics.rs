@@ -0,0 +1,346 @@
+//! Prometheus metrics of watch mode.
+//!
+//! Counters are kept from the same events the event stream and run reports
+//! are made of, and served in the Prometheus text format on
+//! `metrics_listen` for as long as watch mode runs. Nothing listens when
+//! `metrics_listen` is not set.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, instrument};
+
+use crate::api::ApiUsage;
+use crate::error::Result;
+use crate::events::{Event, EventSender, SkipReason};
+
+/// Path metrics are served on, any other answers 404
+pub const METRICS_PATH: &str = "/metrics";
+
+/// Longest a scraper may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Counters of one account
+#[derive(Debug, Default)]
+struct AccountCounters {
+    downloaded: u64,
+    skipped: BTreeMap<&'static str, u64>,
+    failed: u64,
+    bytes: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    accounts: BTreeMap<String, AccountCounters>,
+    /// Account and URL of every download between its start and its result
+    in_flight: HashSet<(String, String)>,
+    runs: u64,
+    last_run: Option<(DateTime<Local>, Duration)>,
+}
+
+/// Metrics of a watch mode process
+#[derive(Debug)]
+pub struct Metrics {
+    api: ApiUsage,
+    state: Mutex<State>,
+}
+
+impl Metrics {
+    /// Metrics of runs sending their API requests with the client `api`
+    /// belongs to
+    pub fn new(api: ApiUsage) -> Self {
+        Self {
+            api,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Count an event
+    pub fn record(&self, event: &Event) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match event {
+            Event::DownloadStarted {
+                screen_name, url, ..
+            } => {
+                state.in_flight.insert((screen_name.clone(), url.clone()));
+            }
+            Event::ItemDownloaded {
+                screen_name,
+                url,
+                bytes,
+                ..
+            } => {
+                state.in_flight.remove(&(screen_name.clone(), url.clone()));
+                let account = state.accounts.entry(screen_name.clone()).or_default();
+                account.downloaded += 1;
+                account.bytes = account.bytes.saturating_add(*bytes);
+            }
+            Event::ItemSkipped {
+                screen_name,
+                url,
+                reason,
+                ..
+            } => {
+                state.in_flight.remove(&(screen_name.clone(), url.clone()));
+                let account = state.accounts.entry(screen_name.clone()).or_default();
+                *account.skipped.entry(reason_label(*reason)).or_default() += 1;
+            }
+            Event::ItemFailed {
+                screen_name, url, ..
+            } => {
+                state.in_flight.remove(&(screen_name.clone(), url.clone()));
+                state
+                    .accounts
+                    .entry(screen_name.clone())
+                    .or_default()
+                    .failed += 1;
+            }
+            // Downloads without a result, such as cancelled ones, are over
+            Event::TaskFinished { screen_name, .. } => {
+                state
+                    .in_flight
+                    .retain(|(account, _)| account != screen_name);
+            }
+            Event::TaskStarted { .. }
+            | Event::PageFetched { .. }
+            | Event::DownloadProgress { .. } => {}
+        }
+    }
+
+    /// Record a finished run
+    pub fn record_run(&self, started: DateTime<Local>, elapsed: Duration) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.runs += 1;
+        state.last_run = Some((started, elapsed));
+    }
+
+    /// Count the events of a channel, passing each one on to `forward`
+    ///
+    /// Returns once every sender has been dropped.
+    pub async fn collect(
+        self: Arc<Self>,
+        mut rx: mpsc::UnboundedReceiver<Event>,
+        forward: Option<EventSender>,
+    ) {
+        while let Some(event) = rx.recv().await {
+            self.record(&event);
+            if let Some(forward) = &forward {
+                let _ = forward.send(event);
+            }
+        }
+    }
+
+    /// All metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+        // Families without samples, such as a rate limit not yet seen, are
+        // left out
+        let mut family = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+            if samples.is_empty() {
+                return;
+            }
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{name}{labels} {value}");
+            }
+        };
+        let per_account = |value: &dyn Fn(&AccountCounters) -> u64| -> Vec<(String, String)> {
+            state
+                .accounts
+                .iter()
+                .map(|(screen_name, counters)| {
+                    (
+                        format!("{{screen_name=\"{}\"}}", escape(screen_name)),
+                        value(counters).to_string(),
+                    )
+                })
+                .collect()
+        };
+
+        family(
+            "rxd_items_downloaded_total",
+            "counter",
+            "Files downloaded.",
+            per_account(&|c| c.downloaded),
+        );
+        family(
+            "rxd_items_skipped_total",
+            "counter",
+            "Items not downloaded, by reason.",
+            state
+                .accounts
+                .iter()
+                .flat_map(|(screen_name, counters)| {
+                    counters.skipped.iter().map(move |(reason, count)| {
+                        (
+                            format!(
+                                "{{screen_name=\"{}\",reason=\"{}\"}}",
+                                escape(screen_name),
+                                reason
+                            ),
+                            count.to_string(),
+                        )
+                    })
+                })
+                .collect(),
+        );
+        family(
+            "rxd_items_failed_total",
+            "counter",
+            "Downloads that failed.",
+            per_account(&|c| c.failed),
+        );
+        family(
+            "rxd_downloaded_bytes_total",
+            "counter",
+            "Bytes written by new downloads.",
+            per_account(&|c| c.bytes),
+        );
+        family(
+            "rxd_downloads_in_flight",
+            "gauge",
+            "Downloads started and not yet finished.",
+            vec![(String::new(), state.in_flight.len().to_string())],
+        );
+        family(
+            "rxd_api_requests_total",
+            "counter",
+            "GraphQL API requests answered.",
+            vec![(String::new(), self.api.requests().to_string())],
+        );
+        family(
+            "rxd_rate_limit_remaining",
+            "gauge",
+            "Requests left in the rate limit window of the latest API response.",
+            self.api
+                .rate_limit_remaining()
+                .map(|remaining| (String::new(), remaining.to_string()))
+                .into_iter()
+                .collect(),
+        );
+        family(
+            "rxd_runs_total",
+            "counter",
+            "Runs finished.",
+            vec![(String::new(), state.runs.to_string())],
+        );
+        if let Some((started, elapsed)) = state.last_run {
+            family(
+                "rxd_last_run_timestamp_seconds",
+                "gauge",
+                "Unix time the latest finished run started at.",
+                vec![(String::new(), started.timestamp().to_string())],
+            );
+            family(
+                "rxd_last_run_duration_seconds",
+                "gauge",
+                "Time the latest finished run took.",
+                vec![(String::new(), format!("{:.3}", elapsed.as_secs_f64()))],
+            );
+        }
+        out
+    }
+}
+
+fn reason_label(reason: SkipReason) -> &'static str {
+    match reason {
+        SkipReason::Verified => "verified",
+        SkipReason::Exists => "exists",
+        SkipReason::Filtered => "filtered",
+        SkipReason::TooLarge => "too_large",
+        SkipReason::Imported => "imported",
+        SkipReason::NotModified => "not_modified",
+        SkipReason::Gone => "gone",
+    }
+}
+
+/// Label value with `\`, `"` and newlines escaped
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Listen on `addr` for metrics scrapes
+///
+/// Fails if the address cannot be bound. Serving runs in the background
+/// until `cancel` is cancelled, returning the address actually bound.
+#[instrument(skip_all)]
+pub async fn serve(
+    addr: SocketAddr,
+    metrics: Arc<Metrics>,
+    cancel: CancellationToken,
+) -> Result<SocketAddr> {
+    let listener = TcpListener::bind(addr).await?;
+    let bound = listener.local_addr()?;
+    info!("serving metrics on http://{}{}", bound, METRICS_PATH);
+    tokio::spawn(async move {
+        loop {
+            let stream = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        debug!("failed to accept a metrics connection: {}", e);
+                        continue;
+                    }
+                },
+                _ = cancel.cancelled() => break,
+            };
+            let metrics = Arc::clone(&metrics);
+            tokio::spawn(async move {
+                if let Err(e) = respond(stream, &metrics).await {
+                    debug!("metrics request failed: {}", e);
+                }
+            });
+        }
+    });
+    Ok(bound)
+}
+
+/// Answer one HTTP request on `stream`
+async fn respond(mut stream: TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    // Only the request line matters, the rest of the head is read and ignored
+    let read = async {
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < 16 * 1024 {
+            let n = stream.read(&mut buffer).await?;
+            if n == 0 {
+                break;
+            }
+            request.extend_from_slice(&buffer[..n]);
+        }
+        std::io::Result::Ok(())
+    };
+    tokio::time::timeout(REQUEST_TIMEOUT, read)
+        .await
+        .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??;
+
+    let request_line = String::from_utf8_lossy(&request);
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) if path.split('?').next() == Some(METRICS_PATH) => {
+            ("200 OK", metrics.render())
+        }
+        (Some("GET"), Some(_)) => ("404 Not Found", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "method not allowed\n".to_string()),
+    };
+    let head = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await
+}
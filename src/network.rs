@@ -0,0 +1,213 @@
+//! Recovery from a network connection lost in the middle of a task.
+//!
+//! A burst of consecutive transport failures (DNS, connect, timeouts) is
+//! taken as the link being down. The task then pauses, probes the API host
+//! until it answers and resumes where it stopped, up to
+//! [`OutagePolicy::max_outage`].
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use crate::api::Api;
+use crate::error::Error;
+
+/// Retries of a request that fails outside an outage before giving up on it
+const MAX_NETWORK_RETRIES: u32 = 3;
+
+/// Wait before retrying a request while the link is not known to be down
+const NETWORK_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Time allowed for a connectivity probe to answer
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How a task waits for a lost connection to come back
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct OutagePolicy {
+    /// Consecutive network failures taken as the link being down
+    pub failures: u32,
+    /// Give up once the link has been down this long
+    pub max_outage: Duration,
+    /// Time between connectivity probes while the link is down
+    pub probe_interval: Duration,
+}
+
+impl Default for OutagePolicy {
+    fn default() -> Self {
+        Self {
+            failures: 3,
+            max_outage: Duration::from_secs(30 * 60),
+            probe_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+impl OutagePolicy {
+    /// Consecutive network failures taken as the link being down, at least 1
+    pub fn failures(mut self, failures: u32) -> Self {
+        self.failures = failures.max(1);
+        self
+    }
+
+    /// Give up once the link has been down this long
+    pub fn max_outage(mut self, max_outage: Duration) -> Self {
+        self.max_outage = max_outage;
+        self
+    }
+
+    /// Time between connectivity probes while the link is down
+    pub fn probe_interval(mut self, probe_interval: Duration) -> Self {
+        self.probe_interval = probe_interval;
+        self
+    }
+}
+
+/// Whether `e` is a transport failure rather than an answer from the server
+pub fn is_network_error(e: &Error) -> bool {
+    match e {
+        Error::Http(e) => {
+            (e.status().is_none() && (e.is_connect() || e.is_timeout() || e.is_request()))
+                || e.is_body()
+        }
+        _ => false,
+    }
+}
+
+/// State of the connection shared by the fetch and downloads of a task
+#[derive(Debug)]
+pub(crate) struct LinkMonitor {
+    policy: OutagePolicy,
+    /// Network failures since the last request that got through
+    consecutive: AtomicU32,
+    /// Outages confirmed by a failed probe so far
+    outages: AtomicU32,
+    gave_up: AtomicBool,
+    /// Held while probing, so a single probe loop runs at a time
+    pausing: tokio::sync::Mutex<()>,
+}
+
+impl LinkMonitor {
+    pub(crate) fn new(policy: OutagePolicy) -> Self {
+        Self {
+            policy,
+            consecutive: AtomicU32::new(0),
+            outages: AtomicU32::new(0),
+            gave_up: AtomicBool::new(false),
+            pausing: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Record a request that got an answer
+    pub(crate) fn succeeded(&self) {
+        self.consecutive.store(0, Ordering::Relaxed);
+    }
+
+    fn is_down(&self) -> bool {
+        self.consecutive.load(Ordering::Relaxed) >= self.policy.failures
+    }
+
+    /// Outages so far, compared before and after a request to tell whether
+    /// it failed during one
+    pub(crate) fn outages(&self) -> u32 {
+        self.outages.load(Ordering::Relaxed)
+    }
+
+    /// Whether an outage outlasted [`OutagePolicy::max_outage`]
+    pub(crate) fn gave_up(&self) -> bool {
+        self.gave_up.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn max_outage(&self) -> Duration {
+        self.policy.max_outage
+    }
+
+    /// Record a network failure of a request made when [`Self::outages`]
+    /// was `since`, waiting for the link to come back if it is down
+    ///
+    /// Returns whether to retry the request. Failures during an outage do
+    /// not count towards the retries in `attempts`.
+    pub(crate) async fn recover(
+        &self,
+        api: &Api,
+        cancel: &CancellationToken,
+        since: u32,
+        attempts: &mut u32,
+    ) -> bool {
+        let failures = self.consecutive.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= self.policy.failures {
+            if !self.wait_for_link(api, cancel).await {
+                return false;
+            }
+        } else {
+            tokio::select! {
+                _ = tokio::time::sleep(NETWORK_RETRY_DELAY) => {}
+                _ = cancel.cancelled() => {}
+            }
+        }
+        if cancel.is_cancelled() {
+            return false;
+        }
+        if self.outages() == since {
+            *attempts += 1;
+        }
+        *attempts <= MAX_NETWORK_RETRIES
+    }
+
+    /// Probe the API host until it answers, `false` once the outage lasted
+    /// longer than allowed
+    async fn wait_for_link(&self, api: &Api, cancel: &CancellationToken) -> bool {
+        let _pausing = self.pausing.lock().await;
+        if self.gave_up() {
+            return false;
+        }
+        // Another request already waited the outage out
+        if !self.is_down() {
+            return true;
+        }
+
+        warn!(
+            "network down after {} failed requests, pausing",
+            self.consecutive.load(Ordering::Relaxed)
+        );
+        let started = Instant::now();
+        let mut confirmed = false;
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(self.policy.probe_interval) => {}
+                _ = cancel.cancelled() => return true,
+            }
+            if probe(api).await {
+                self.succeeded();
+                info!(
+                    "network back after {}s, resuming",
+                    started.elapsed().as_secs()
+                );
+                return true;
+            }
+            if !confirmed {
+                confirmed = true;
+                self.outages.fetch_add(1, Ordering::Relaxed);
+            }
+            let down = started.elapsed();
+            if down >= self.policy.max_outage {
+                self.gave_up.store(true, Ordering::Relaxed);
+                error!("network still down after {}s, giving up", down.as_secs());
+                return false;
+            }
+            debug!("network still down after {}s", down.as_secs());
+        }
+    }
+}
+
+/// Whether the API host answers at all, whatever the status
+async fn probe(api: &Api) -> bool {
+    api.client()
+        .head(api.base_url())
+        .timeout(PROBE_TIMEOUT)
+        .send()
+        .await
+        .is_ok()
+}
@@ -0,0 +1,171 @@
+use std::time::Duration;
+
+use indicatif::{HumanBytes, HumanDuration};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tracing::{info, instrument};
+
+use crate::error::{Error, Result};
+use crate::summary::RunSummary;
+
+#[cfg(feature = "desktop-notify")]
+pub mod desktop;
+
+/// Discord limits message content to 2000 characters
+const DISCORD_MAX_CONTENT: usize = 2000;
+
+#[derive(Debug, Deserialize)]
+pub struct NotificationConfig {
+    pub webhook_url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    #[serde(default)]
+    pub notify_on: NotifyOn,
+    /// Required by the telegram format, the chat to send the message to
+    #[serde(default)]
+    pub telegram_chat_id: Option<String>,
+    /// Seconds to wait for the webhook to answer before giving up
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+/// Payload shape expected by the webhook receiver
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WebhookFormat {
+    Discord,
+    Telegram,
+    #[default]
+    GenericJson,
+}
+
+/// When a notification should be sent
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotifyOn {
+    #[default]
+    Always,
+    /// Only when a task aborted or a download failed
+    Failure,
+    /// Only when something new was downloaded
+    NewItems,
+}
+
+impl NotificationConfig {
+    fn should_notify(&self, summary: &RunSummary) -> bool {
+        match self.notify_on {
+            NotifyOn::Always => true,
+            NotifyOn::Failure => summary.has_failures(),
+            NotifyOn::NewItems => summary.totals().downloaded > 0,
+        }
+    }
+}
+
+/// Post the run summary to the configured webhook
+#[instrument(skip_all)]
+pub async fn send(
+    client: &Client,
+    config: &NotificationConfig,
+    summary: &RunSummary,
+) -> Result<()> {
+    if !config.should_notify(summary) {
+        return Ok(());
+    }
+
+    let payload = match config.format {
+        WebhookFormat::Discord => {
+            let mut content = message_text(summary);
+            if content.len() > DISCORD_MAX_CONTENT {
+                let mut end = DISCORD_MAX_CONTENT - 3;
+                while !content.is_char_boundary(end) {
+                    end -= 1;
+                }
+                content.truncate(end);
+                content.push_str("...");
+            }
+            json!({ "content": content })
+        }
+        WebhookFormat::Telegram => {
+            let chat_id = config.telegram_chat_id.as_deref().ok_or_else(|| {
+                Error::Config("telegram_chat_id is required for the telegram format".to_string())
+            })?;
+            json!({ "chat_id": chat_id, "text": message_text(summary) })
+        }
+        WebhookFormat::GenericJson => generic_payload(summary),
+    };
+
+    // A webhook that never answers would hold up every later run
+    let timeout = Duration::from_secs(config.timeout_secs);
+    let response = client
+        .post(&config.webhook_url)
+        .json(&payload)
+        .timeout(timeout)
+        .send()
+        .await
+        .map_err(|e| {
+            if e.is_timeout() {
+                Error::Timeout(timeout)
+            } else {
+                e.into()
+            }
+        })?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(Error::Api { status, body });
+    }
+
+    info!("notification sent");
+    Ok(())
+}
+
+/// Short human-readable summary for chat webhooks
+fn message_text(summary: &RunSummary) -> String {
+    let totals = summary.totals();
+    let mut text = format!(
+        "rxd run finished in {}: {} downloaded, {} skipped, {} failed, {}",
+        HumanDuration(summary.elapsed),
+        totals.downloaded,
+        totals.skipped,
+        totals.failed,
+        HumanBytes(totals.bytes),
+    );
+    for task in &summary.tasks {
+        text.push_str(&format!(
+            "\n@{}: {} downloaded, {} skipped, {} failed",
+            task.screen_name, task.totals.downloaded, task.totals.skipped, task.totals.failed
+        ));
+        if let Some(error) = &task.error {
+            text.push_str(&format!(" (aborted: {error})"));
+        }
+    }
+    text
+}
+
+fn generic_payload(summary: &RunSummary) -> Value {
+    let tasks: Vec<Value> = summary
+        .tasks
+        .iter()
+        .map(|t| {
+            json!({
+                "screen_name": t.screen_name,
+                "totals": t.totals,
+                "elapsed_secs": t.elapsed.as_secs_f64(),
+                "error": t.error,
+            })
+        })
+        .collect();
+
+    json!({
+        "tasks": tasks,
+        "totals": summary.totals(),
+        "elapsed_secs": summary.elapsed.as_secs_f64(),
+        "has_failures": summary.has_failures(),
+    })
+}
@@ -0,0 +1,176 @@
+//! Native desktop notification at the end of a run.
+//!
+//! Shown with the notifier of the platform: `notify-send` on Linux and the
+//! BSDs, `osascript` on macOS and a toast through PowerShell on Windows.
+//! The notifier is an external program, not part of rxd: without it on
+//! `PATH` no notification is shown and `rxd doctor` reports it missing.
+
+use std::ffi::OsStr;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::process::Command;
+use tracing::{debug, instrument};
+
+use crate::error::{Error, Result};
+use crate::summary::RunSummary;
+
+/// Notifiers that hang, e.g. waiting for a session bus, are given up on
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Program showing the notifications on this platform
+#[cfg(target_os = "macos")]
+pub const NOTIFIER: &str = "osascript";
+/// Program showing the notifications on this platform
+#[cfg(windows)]
+pub const NOTIFIER: &str = "powershell";
+/// Program showing the notifications on this platform
+#[cfg(not(any(target_os = "macos", windows)))]
+pub const NOTIFIER: &str = "notify-send";
+
+/// Where [`NOTIFIER`] is installed, searched for in `PATH`
+pub fn find_notifier() -> Option<PathBuf> {
+    find_notifier_in(&std::env::var_os("PATH")?)
+}
+
+/// Where [`NOTIFIER`] is installed, searched for in the directories of
+/// `path`, a list like `PATH`
+pub fn find_notifier_in(path: &OsStr) -> Option<PathBuf> {
+    let file_name = if cfg!(windows) {
+        format!("{NOTIFIER}.exe")
+    } else {
+        NOTIFIER.to_string()
+    };
+    std::env::split_paths(path)
+        .map(|dir| dir.join(&file_name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// A notification to show
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DesktopNotification {
+    pub title: String,
+    pub body: String,
+    /// Shown with critical urgency, set when a task aborted or a download failed
+    pub urgent: bool,
+}
+
+impl DesktopNotification {
+    /// Notification for a finished run, e.g.
+    /// `3 tasks finished, 412 new files, 2 failures`
+    pub fn for_run(summary: &RunSummary) -> Self {
+        let totals = summary.totals();
+        let failures = totals.failed + summary.tasks.iter().filter(|t| t.error.is_some()).count();
+        let title = match failures {
+            0 => "rxd: run finished",
+            _ => "rxd: run finished with failures",
+        };
+        let mut body = format!(
+            "{} finished, {} new {}",
+            plural(summary.tasks.len(), "task"),
+            totals.downloaded,
+            match totals.downloaded {
+                1 => "file",
+                _ => "files",
+            }
+        );
+        if failures > 0 {
+            body.push_str(&format!(", {}", plural(failures, "failure")));
+        }
+        Self {
+            title: title.to_string(),
+            body,
+            urgent: failures > 0,
+        }
+    }
+
+    /// Show the notification
+    ///
+    /// Fails when [`NOTIFIER`] is not installed or has no desktop session to
+    /// show it in.
+    #[instrument(skip_all)]
+    pub async fn show(&self) -> Result<()> {
+        let mut command = self.command();
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        let output = match tokio::time::timeout(TIMEOUT, command.output()).await {
+            Err(_) => return Err(Error::Timeout(TIMEOUT)),
+            Ok(Err(e)) if e.kind() == ErrorKind::NotFound => {
+                return Err(Error::Notification(format!(
+                    "desktop notifications need {NOTIFIER}, which is not installed"
+                )));
+            }
+            Ok(output) => output?,
+        };
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(Error::Notification(format!(
+                "notifier exited with {}: {}",
+                output.status,
+                stderr.trim()
+            )));
+        }
+        debug!("desktop notification shown");
+        Ok(())
+    }
+
+    #[cfg(target_os = "macos")]
+    fn command(&self) -> Command {
+        // Passed as arguments so the text needs no AppleScript quoting
+        let display = if self.urgent {
+            "display notification (item 2 of argv) with title (item 1 of argv) sound name \"Basso\""
+        } else {
+            "display notification (item 2 of argv) with title (item 1 of argv)"
+        };
+        let mut command = Command::new("osascript");
+        command
+            .args(["-e", "on run argv", "-e", display, "-e", "end run"])
+            .args([&self.title, &self.body]);
+        command
+    }
+
+    #[cfg(windows)]
+    fn command(&self) -> Command {
+        // Passed through the environment so the text needs no quoting
+        const SCRIPT: &str = "\
+[Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime] > $null
+$template = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02)
+$text = $template.GetElementsByTagName('text')
+$text.Item(0).AppendChild($template.CreateTextNode($env:RXD_NOTIFY_TITLE)) > $null
+$text.Item(1).AppendChild($template.CreateTextNode($env:RXD_NOTIFY_BODY)) > $null
+[Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier('rxd').Show([Windows.UI.Notifications.ToastNotification]::new($template))";
+        let mut command = Command::new("powershell");
+        command
+            .args(["-NoProfile", "-NonInteractive", "-Command", SCRIPT])
+            .env("RXD_NOTIFY_TITLE", &self.title)
+            .env("RXD_NOTIFY_BODY", &self.body);
+        command
+    }
+
+    #[cfg(not(any(target_os = "macos", windows)))]
+    fn command(&self) -> Command {
+        let urgency = if self.urgent {
+            "--urgency=critical"
+        } else {
+            "--urgency=normal"
+        };
+        let mut command = Command::new("notify-send");
+        command
+            .args(["--app-name=rxd", urgency, "--"])
+            .args([&self.title, &self.body]);
+        command
+    }
+}
+
+/// `count` with `noun`, plural unless it is one
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {noun}"),
+        _ => format!("{count} {noun}s"),
+    }
+}
@@ -0,0 +1,915 @@
+//! `rxd pack`: an account's downloaded files, their caption and poster
+//! files and a manifest of their tweets in one tar or zip archive.
+//!
+//! Entries are laid out like the account's folder, `<folder>/<file>`, so
+//! unpacking the archive restores the folder. Files are streamed into the
+//! archive and hashed on the way, a file that no longer matches its
+//! recorded hash fails the pack unless [`PackOptions::allow_mismatch`] is
+//! set. `.tar.gz` and `.tar.zst` archives are compressed with the `gzip` and
+//! `zstd` commands. `.zip` archives store their files uncompressed, media
+//! barely shrink anyway, with ZIP64 records for files over 4 GiB.
+
+use std::fs::File;
+use std::io::{self, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::time::UNIX_EPOCH;
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, Timelike};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tracing::{debug, info, instrument, warn};
+
+use crate::db::{self, DownloadedMedia};
+use crate::error::{Error, Result};
+use crate::hash::Hasher;
+use crate::sidecar::TextSidecars;
+use crate::task::thumbnail_path;
+
+/// Size of tar headers and the unit entries are padded to
+const BLOCK: usize = 512;
+
+/// Name of the generated manifest inside the account's folder
+pub const MANIFEST: &str = "manifest.json";
+
+/// What to pack and how strictly to check it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PackOptions {
+    /// Only tweets posted on or after this day
+    pub since: Option<NaiveDate>,
+    /// Only tweets posted on or before this day
+    pub until: Option<NaiveDate>,
+    /// Pack files that differ from their recorded hash with a warning
+    /// instead of failing
+    pub allow_mismatch: bool,
+}
+
+impl PackOptions {
+    /// Only tweets posted on or after `since`
+    pub fn since(mut self, since: Option<NaiveDate>) -> Self {
+        self.since = since;
+        self
+    }
+
+    /// Only tweets posted on or before `until`
+    pub fn until(mut self, until: Option<NaiveDate>) -> Self {
+        self.until = until;
+        self
+    }
+
+    /// Pack files that differ from their recorded hash with a warning
+    pub fn allow_mismatch(mut self, allow_mismatch: bool) -> Self {
+        self.allow_mismatch = allow_mismatch;
+        self
+    }
+}
+
+/// What went into an archive
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackSummary {
+    /// Media files packed
+    pub files: u64,
+    /// Caption and poster files packed next to them
+    pub sidecars: u64,
+    /// Bytes of packed files, before compression
+    pub bytes: u64,
+    /// Packed files that differ from their recorded hash
+    pub mismatched: u64,
+    /// Packed files without a hash to check
+    pub unverified: u64,
+    /// Recorded files not on disk, left out
+    pub missing: u64,
+}
+
+/// Format of an archive, picked by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveType {
+    Tar(Compression),
+    Zip,
+}
+
+impl ArchiveType {
+    /// Format of an archive at `path`, e.g. `foo.tar.zst`
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match name {
+            _ if name.ends_with(".tar") => Ok(ArchiveType::Tar(Compression::None)),
+            _ if name.ends_with(".tar.gz") || name.ends_with(".tgz") => {
+                Ok(ArchiveType::Tar(Compression::Gzip))
+            }
+            _ if name.ends_with(".tar.zst") || name.ends_with(".tzst") => {
+                Ok(ArchiveType::Tar(Compression::Zstd))
+            }
+            _ if name.ends_with(".zip") => Ok(ArchiveType::Zip),
+            _ => Err(Error::Config(format!(
+                "unknown archive type of {}, use .tar, .tar.gz, .tar.zst or .zip",
+                path.display()
+            ))),
+        }
+    }
+}
+
+/// Compression of a tar archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Command compressing stdin to stdout
+    fn command(self) -> Option<(&'static str, &'static [&'static str])> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some(("gzip", &["-c", "-n"])),
+            Compression::Zstd => Some(("zstd", &["-q", "-c"])),
+        }
+    }
+}
+
+/// Pack the downloaded files of `screen_name` in `folder` into a tar or
+/// zip archive at `out`
+///
+/// The archive is written next to `out` and only moved there once
+/// complete, a failed pack leaves nothing behind.
+#[instrument(skip_all, fields(user = screen_name))]
+pub async fn pack(
+    pool: &SqlitePool,
+    screen_name: &str,
+    folder: &Path,
+    out: &Path,
+    options: &PackOptions,
+) -> Result<PackSummary> {
+    let archive_type = ArchiveType::from_path(out)?;
+    let since = options.since.map(|day| day.format("%Y-%m-%d").to_string());
+    // Tweet times have a time of day, anything before the next day is in
+    let until = options
+        .until
+        .and_then(|day| day.succ_opt())
+        .map(|day| day.format("%Y-%m-%d").to_string());
+    let media = db::downloaded_media(pool, screen_name, since.as_deref(), until.as_deref()).await?;
+    info!("packing {} files of @{}", media.len(), screen_name);
+
+    let job = PackJob {
+        screen_name: screen_name.to_string(),
+        folder: folder.to_path_buf(),
+        options: *options,
+        media,
+    };
+    let out = out.to_path_buf();
+    tokio::task::spawn_blocking(move || job.write(&out, archive_type))
+        .await
+        .map_err(io::Error::other)?
+}
+
+/// Everything a pack needs, moved to a blocking thread
+struct PackJob {
+    screen_name: String,
+    folder: PathBuf,
+    options: PackOptions,
+    media: Vec<DownloadedMedia>,
+}
+
+impl PackJob {
+    fn write(&self, out: &Path, archive_type: ArchiveType) -> Result<PackSummary> {
+        let mut partial = out.as_os_str().to_owned();
+        partial.push(".part");
+        let partial = PathBuf::from(partial);
+
+        let result = match archive_type {
+            ArchiveType::Tar(compression) => Sink::create(&partial, compression).and_then(|sink| {
+                let mut tar = TarWriter { out: sink };
+                let summary = self.write_entries(&mut tar)?;
+                tar.finish()?.finish()?;
+                Ok(summary)
+            }),
+            ArchiveType::Zip => File::create(&partial)
+                .map_err(Error::from)
+                .and_then(|file| {
+                    let mut zip = ZipWriter::new(BufWriter::new(file));
+                    let summary = self.write_entries(&mut zip)?;
+                    zip.finish()?.flush()?;
+                    Ok(summary)
+                }),
+        };
+        match result {
+            Ok(summary) => {
+                std::fs::rename(&partial, out)?;
+                Ok(summary)
+            }
+            Err(e) => {
+                let _ = std::fs::remove_file(&partial);
+                Err(e)
+            }
+        }
+    }
+
+    fn write_entries(&self, archive: &mut impl ArchiveWriter) -> Result<PackSummary> {
+        let root = self
+            .folder
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(&self.screen_name)
+            .to_string();
+        let mut summary = PackSummary::default();
+        let mut manifest = Manifest {
+            screen_name: &self.screen_name,
+            packed_at: Local::now().to_rfc3339(),
+            since: self.options.since.map(|day| day.to_string()),
+            until: self.options.until.map(|day| day.to_string()),
+            tweets: Vec::new(),
+        };
+        // Per-tweet captions are shared by the tweet's files
+        let mut packed_sidecars = std::collections::HashSet::new();
+
+        for media in &self.media {
+            let path = self.folder.join(&media.filename);
+            let name = format!("{root}/{}", media.filename);
+            let mut hasher = media.hash_algorithm.map(Hasher::new);
+            let size = match archive.append_file(&name, &path, hasher.as_mut()) {
+                Ok(size) => size,
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    warn!("not on disk, leaving out: {}", path.display());
+                    summary.missing += 1;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            summary.files += 1;
+            summary.bytes += size;
+            match (hasher.map(Hasher::finalize), &media.file_hash) {
+                (Some(actual), Some(recorded)) if actual != *recorded => {
+                    if !self.options.allow_mismatch {
+                        return Err(Error::HashMismatch(path));
+                    }
+                    warn!("packed despite a hash mismatch: {}", path.display());
+                    summary.mismatched += 1;
+                }
+                (Some(_), Some(_)) => {}
+                _ => summary.unverified += 1,
+            }
+
+            let sidecars = [
+                TextSidecars::default().path(&path, &media.tweet_id),
+                TextSidecars {
+                    per_tweet: true,
+                    ..Default::default()
+                }
+                .path(&path, &media.tweet_id),
+                thumbnail_path(&path),
+            ];
+            for sidecar in sidecars {
+                if !sidecar.is_file() || !packed_sidecars.insert(sidecar.clone()) {
+                    continue;
+                }
+                let Some(file_name) = sidecar.file_name().and_then(|n| n.to_str()) else {
+                    continue;
+                };
+                let size = archive.append_file(&format!("{root}/{file_name}"), &sidecar, None)?;
+                summary.sidecars += 1;
+                summary.bytes += size;
+            }
+
+            manifest.add(media);
+        }
+
+        let manifest = serde_json::to_vec_pretty(&manifest)?;
+        archive.append_bytes(&format!("{root}/{MANIFEST}"), &manifest)?;
+        debug!(
+            "packed {} files and {} sidecars",
+            summary.files, summary.sidecars
+        );
+        Ok(summary)
+    }
+}
+
+/// `manifest.json` of an archive, the packed tweets with their files
+#[derive(Debug, Serialize)]
+struct Manifest<'a> {
+    screen_name: &'a str,
+    packed_at: String,
+    since: Option<String>,
+    until: Option<String>,
+    tweets: Vec<ManifestTweet<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ManifestTweet<'a> {
+    tweet_id: &'a str,
+    tweet_time: &'a str,
+    full_text: Option<&'a str>,
+    favorite_count: Option<u64>,
+    retweet_count: Option<u64>,
+    reply_count: Option<u64>,
+    quote_count: Option<u64>,
+    view_count: Option<u64>,
+    files: Vec<ManifestFile<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ManifestFile<'a> {
+    filename: &'a str,
+    media_url: &'a str,
+    hash: Option<&'a str>,
+    hash_algorithm: Option<&'static str>,
+}
+
+impl<'a> Manifest<'a> {
+    /// Add a packed file, media come grouped by tweet
+    fn add(&mut self, media: &'a DownloadedMedia) {
+        let file = ManifestFile {
+            filename: &media.filename,
+            media_url: &media.media_url,
+            hash: media.file_hash.as_deref(),
+            hash_algorithm: media.hash_algorithm.map(|a| a.as_str()),
+        };
+        if let Some(tweet) = self.tweets.last_mut()
+            && tweet.tweet_id == media.tweet_id
+        {
+            tweet.files.push(file);
+            return;
+        }
+        let engagement = &media.engagement;
+        self.tweets.push(ManifestTweet {
+            tweet_id: &media.tweet_id,
+            tweet_time: &media.tweet_time,
+            full_text: media.full_text.as_deref(),
+            favorite_count: engagement.favorite_count,
+            retweet_count: engagement.retweet_count,
+            reply_count: engagement.reply_count,
+            quote_count: engagement.quote_count,
+            view_count: engagement.view_count,
+            files: vec![file],
+        });
+    }
+}
+
+/// Where the tar stream goes, a file or a compressor writing to one
+enum Sink {
+    File(BufWriter<File>),
+    Compressor {
+        program: &'static str,
+        child: Child,
+        stdin: BufWriter<ChildStdin>,
+    },
+}
+
+impl Sink {
+    fn create(path: &Path, compression: Compression) -> Result<Self> {
+        let file = File::create(path)?;
+        let Some((program, args)) = compression.command() else {
+            return Ok(Sink::File(BufWriter::new(file)));
+        };
+        let mut child = match Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(file)
+            .stderr(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Err(Error::Config(format!(
+                    "{} needs {program}, which is not installed",
+                    path.display()
+                )));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| io::Error::other(format!("no stdin for {program}")))?;
+        Ok(Sink::Compressor {
+            program,
+            child,
+            stdin: BufWriter::new(stdin),
+        })
+    }
+
+    /// Flush everything and wait for the compressor to finish
+    fn finish(self) -> Result<()> {
+        match self {
+            Sink::File(mut file) => file.flush()?,
+            Sink::Compressor {
+                program,
+                child,
+                mut stdin,
+            } => {
+                stdin.flush()?;
+                drop(stdin);
+                let output = child.wait_with_output()?;
+                if !output.status.success() {
+                    return Err(io::Error::other(format!(
+                        "{program} failed: {}",
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ))
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::File(file) => file.write(buf),
+            Sink::Compressor { stdin, .. } => stdin.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::File(file) => file.flush(),
+            Sink::Compressor { stdin, .. } => stdin.flush(),
+        }
+    }
+}
+
+/// Entries of an archive being written
+trait ArchiveWriter {
+    /// Stream the file at `path` into an entry `name`, feeding `hasher`
+    /// on the way, returning its size
+    fn append_file(
+        &mut self,
+        name: &str,
+        path: &Path,
+        hasher: Option<&mut Hasher>,
+    ) -> io::Result<u64>;
+
+    /// Entry `name` holding `data`
+    fn append_bytes(&mut self, name: &str, data: &[u8]) -> io::Result<()>;
+}
+
+/// Copy the `size` bytes of `file` at `path` to `out`, showing every chunk
+/// to `inspect`
+fn copy_file(
+    file: File,
+    path: &Path,
+    size: u64,
+    out: &mut impl Write,
+    mut inspect: impl FnMut(&[u8]),
+) -> io::Result<()> {
+    let mut reader = file.take(size);
+    let mut buf = vec![0; 64 * 1024];
+    let mut copied = 0;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        out.write_all(&buf[..n])?;
+        inspect(&buf[..n]);
+        copied += n as u64;
+    }
+    if copied != size {
+        return Err(io::Error::new(
+            ErrorKind::UnexpectedEof,
+            format!("{} shrank while it was packed", path.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// Modification time of a file in seconds since the epoch, 0 if unknown
+fn modified_secs(metadata: &std::fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Now in seconds since the epoch
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Writes ustar entries, with GNU long names for paths that do not fit
+struct TarWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> ArchiveWriter for TarWriter<W> {
+    fn append_file(
+        &mut self,
+        name: &str,
+        path: &Path,
+        mut hasher: Option<&mut Hasher>,
+    ) -> io::Result<u64> {
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let size = metadata.len();
+        self.header(name, size, modified_secs(&metadata))?;
+        copy_file(file, path, size, &mut self.out, |chunk| {
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(chunk);
+            }
+        })?;
+        self.pad(size)?;
+        Ok(size)
+    }
+
+    fn append_bytes(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        self.header(name, data.len() as u64, now_secs())?;
+        self.out.write_all(data)?;
+        self.pad(data.len() as u64)
+    }
+}
+
+impl<W: Write> TarWriter<W> {
+    /// End-of-archive marker, returning the writer
+    fn finish(mut self) -> io::Result<W> {
+        self.out.write_all(&[0; 2 * BLOCK])?;
+        Ok(self.out)
+    }
+
+    fn header(&mut self, name: &str, size: u64, mtime: u64) -> io::Result<()> {
+        if let Some((prefix, name)) = split_name(name) {
+            return self.raw_header(prefix, name, size, mtime, b'0');
+        }
+        // GNU extension, read by every common tar
+        let mut long = name.as_bytes().to_vec();
+        long.push(0);
+        self.raw_header("", "././@LongLink", long.len() as u64, 0, b'L')?;
+        self.out.write_all(&long)?;
+        self.pad(long.len() as u64)?;
+        let mut end = 100;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.raw_header("", &name[..end], size, mtime, b'0')
+    }
+
+    fn raw_header(
+        &mut self,
+        prefix: &str,
+        name: &str,
+        size: u64,
+        mtime: u64,
+        kind: u8,
+    ) -> io::Result<()> {
+        let mut header = [0u8; BLOCK];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        octal(&mut header[100..108], 0o644)?;
+        octal(&mut header[108..116], 0)?;
+        octal(&mut header[116..124], 0)?;
+        octal(&mut header[124..136], size)?;
+        octal(&mut header[136..148], mtime)?;
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+        // Summed with the checksum field as spaces
+        header[148..156].fill(b' ');
+        let checksum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+        header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+        self.out.write_all(&header)
+    }
+
+    /// Zeros up to the next block after `size` bytes of data
+    fn pad(&mut self, size: u64) -> io::Result<()> {
+        let rest = (size % BLOCK as u64) as usize;
+        if rest > 0 {
+            self.out.write_all(&[0; BLOCK][..BLOCK - rest])?;
+        }
+        Ok(())
+    }
+}
+
+/// `name` as the prefix and name fields of a ustar header, `None` if it
+/// does not fit them
+fn split_name(name: &str) -> Option<(&str, &str)> {
+    if name.len() <= 100 {
+        return Some(("", name));
+    }
+    name.match_indices('/')
+        .map(|(i, _)| (&name[..i], &name[i + 1..]))
+        .find(|(prefix, rest)| prefix.len() <= 155 && !rest.is_empty() && rest.len() <= 100)
+}
+
+/// `value` as a NUL-terminated octal number filling `field`
+fn octal(field: &mut [u8], value: u64) -> io::Result<()> {
+    let digits = field.len() - 1;
+    let text = format!("{value:0digits$o}");
+    if text.len() > digits {
+        return Err(io::Error::other(format!("{value} is too large for tar")));
+    }
+    field[..digits].copy_from_slice(text.as_bytes());
+    field[digits] = 0;
+    Ok(())
+}
+
+/// Sizes and offsets from this value on need ZIP64 records
+const ZIP64_LIMIT: u64 = 0xffff_ffff;
+
+/// Entry counts from this value on need ZIP64 records
+const ZIP64_ENTRIES: usize = 0xffff;
+
+/// Names are UTF-8, general purpose flag bit 11
+const ZIP_UTF8: u16 = 1 << 11;
+
+/// Writes zip entries with the stored method, patching each CRC-32 into
+/// its local header once the file is streamed
+struct ZipWriter<W: Write + Seek> {
+    out: W,
+    /// Bytes written so far
+    offset: u64,
+    entries: Vec<ZipEntry>,
+}
+
+/// What the central directory needs of a written entry
+struct ZipEntry {
+    name: String,
+    crc: u32,
+    size: u64,
+    /// Of its local header
+    offset: u64,
+    time: u16,
+    date: u16,
+}
+
+impl ZipEntry {
+    /// Version of the format needed to extract it, 4.5 with ZIP64 records
+    fn version(&self) -> u16 {
+        if self.size >= ZIP64_LIMIT || self.offset >= ZIP64_LIMIT {
+            45
+        } else {
+            20
+        }
+    }
+}
+
+impl<W: Write + Seek> ArchiveWriter for ZipWriter<W> {
+    fn append_file(
+        &mut self,
+        name: &str,
+        path: &Path,
+        mut hasher: Option<&mut Hasher>,
+    ) -> io::Result<u64> {
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let size = metadata.len();
+        let entry = self.local_header(name, size, modified_secs(&metadata), 0)?;
+
+        let mut crc = Crc32::new();
+        copy_file(file, path, size, &mut self.out, |chunk| {
+            crc.update(chunk);
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(chunk);
+            }
+        })?;
+        self.offset += size;
+
+        let crc = crc.finish();
+        // CRC-32 is at byte 14 of the local header
+        self.out.seek(SeekFrom::Start(entry.offset + 14))?;
+        self.out.write_all(&crc.to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(self.offset))?;
+        self.entries.push(ZipEntry { crc, ..entry });
+        Ok(size)
+    }
+
+    fn append_bytes(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let mut crc = Crc32::new();
+        crc.update(data);
+        let entry = self.local_header(name, data.len() as u64, now_secs(), crc.finish())?;
+        self.write(data)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> ZipWriter<W> {
+    fn new(out: W) -> Self {
+        Self {
+            out,
+            offset: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Central directory and end records, returning the writer
+    fn finish(mut self) -> io::Result<W> {
+        let start = self.offset;
+        let entries = std::mem::take(&mut self.entries);
+        for entry in &entries {
+            self.central_header(entry)?;
+        }
+        let count = entries.len();
+        let size = self.offset - start;
+
+        if count >= ZIP64_ENTRIES || start >= ZIP64_LIMIT || size >= ZIP64_LIMIT {
+            let end = self.offset;
+            let mut record = Vec::with_capacity(56 + 20);
+            record.extend_from_slice(&0x0606_4b50_u32.to_le_bytes());
+            // Size of the rest of the record
+            record.extend_from_slice(&44_u64.to_le_bytes());
+            record.extend_from_slice(&(0x0300 | 45_u16).to_le_bytes());
+            record.extend_from_slice(&45_u16.to_le_bytes());
+            record.extend_from_slice(&0_u32.to_le_bytes());
+            record.extend_from_slice(&0_u32.to_le_bytes());
+            record.extend_from_slice(&(count as u64).to_le_bytes());
+            record.extend_from_slice(&(count as u64).to_le_bytes());
+            record.extend_from_slice(&size.to_le_bytes());
+            record.extend_from_slice(&start.to_le_bytes());
+            // Locator of the record above
+            record.extend_from_slice(&0x0706_4b50_u32.to_le_bytes());
+            record.extend_from_slice(&0_u32.to_le_bytes());
+            record.extend_from_slice(&end.to_le_bytes());
+            record.extend_from_slice(&1_u32.to_le_bytes());
+            self.write(&record)?;
+        }
+
+        let mut record = Vec::with_capacity(22);
+        record.extend_from_slice(&0x0605_4b50_u32.to_le_bytes());
+        record.extend_from_slice(&0_u16.to_le_bytes());
+        record.extend_from_slice(&0_u16.to_le_bytes());
+        let count = if count >= ZIP64_ENTRIES {
+            u16::MAX
+        } else {
+            count as u16
+        };
+        record.extend_from_slice(&count.to_le_bytes());
+        record.extend_from_slice(&count.to_le_bytes());
+        record.extend_from_slice(&zip32(size).to_le_bytes());
+        record.extend_from_slice(&zip32(start).to_le_bytes());
+        // No comment
+        record.extend_from_slice(&0_u16.to_le_bytes());
+        self.write(&record)?;
+        Ok(self.out)
+    }
+
+    /// Local header of an entry `name` of `size` bytes, returning the
+    /// entry for the central directory
+    fn local_header(
+        &mut self,
+        name: &str,
+        size: u64,
+        mtime: u64,
+        crc: u32,
+    ) -> io::Result<ZipEntry> {
+        let name_len = u16::try_from(name.len())
+            .map_err(|_| io::Error::other(format!("{name} is too long for zip")))?;
+        let (time, date) = dos_time(mtime);
+        let entry = ZipEntry {
+            name: name.to_string(),
+            crc,
+            size,
+            offset: self.offset,
+            time,
+            date,
+        };
+        let zip64 = size >= ZIP64_LIMIT;
+
+        let mut header = Vec::with_capacity(30 + name.len() + 20);
+        header.extend_from_slice(&0x0403_4b50_u32.to_le_bytes());
+        header.extend_from_slice(&entry.version().to_le_bytes());
+        header.extend_from_slice(&ZIP_UTF8.to_le_bytes());
+        // Stored
+        header.extend_from_slice(&0_u16.to_le_bytes());
+        header.extend_from_slice(&time.to_le_bytes());
+        header.extend_from_slice(&date.to_le_bytes());
+        header.extend_from_slice(&crc.to_le_bytes());
+        header.extend_from_slice(&zip32(size).to_le_bytes());
+        header.extend_from_slice(&zip32(size).to_le_bytes());
+        header.extend_from_slice(&name_len.to_le_bytes());
+        header.extend_from_slice(&(if zip64 { 20_u16 } else { 0 }).to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        if zip64 {
+            header.extend_from_slice(&1_u16.to_le_bytes());
+            header.extend_from_slice(&16_u16.to_le_bytes());
+            header.extend_from_slice(&size.to_le_bytes());
+            header.extend_from_slice(&size.to_le_bytes());
+        }
+        self.write(&header)?;
+        Ok(entry)
+    }
+
+    fn central_header(&mut self, entry: &ZipEntry) -> io::Result<()> {
+        // ZIP64 extra field, holding only the values too large for theirs
+        let mut extra = Vec::new();
+        if entry.size >= ZIP64_LIMIT {
+            extra.extend_from_slice(&entry.size.to_le_bytes());
+            extra.extend_from_slice(&entry.size.to_le_bytes());
+        }
+        if entry.offset >= ZIP64_LIMIT {
+            extra.extend_from_slice(&entry.offset.to_le_bytes());
+        }
+
+        let version = entry.version();
+        let mut header = Vec::with_capacity(46 + entry.name.len() + 4 + extra.len());
+        header.extend_from_slice(&0x0201_4b50_u32.to_le_bytes());
+        // Made by Unix, so the external attributes are a file mode
+        header.extend_from_slice(&(0x0300 | version).to_le_bytes());
+        header.extend_from_slice(&version.to_le_bytes());
+        header.extend_from_slice(&ZIP_UTF8.to_le_bytes());
+        header.extend_from_slice(&0_u16.to_le_bytes());
+        header.extend_from_slice(&entry.time.to_le_bytes());
+        header.extend_from_slice(&entry.date.to_le_bytes());
+        header.extend_from_slice(&entry.crc.to_le_bytes());
+        header.extend_from_slice(&zip32(entry.size).to_le_bytes());
+        header.extend_from_slice(&zip32(entry.size).to_le_bytes());
+        header.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+        let extra_len = if extra.is_empty() { 0 } else { 4 + extra.len() };
+        header.extend_from_slice(&(extra_len as u16).to_le_bytes());
+        // Comment length, disk number and internal attributes
+        header.extend_from_slice(&[0; 6]);
+        header.extend_from_slice(&(0o100644_u32 << 16).to_le_bytes());
+        header.extend_from_slice(&zip32(entry.offset).to_le_bytes());
+        header.extend_from_slice(entry.name.as_bytes());
+        if !extra.is_empty() {
+            header.extend_from_slice(&1_u16.to_le_bytes());
+            header.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+            header.extend_from_slice(&extra);
+        }
+        self.write(&header)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+/// `value` for a 32-bit field, all ones if it is in a ZIP64 record instead
+fn zip32(value: u64) -> u32 {
+    if value >= ZIP64_LIMIT {
+        u32::MAX
+    } else {
+        value as u32
+    }
+}
+
+/// MS-DOS time and date of `mtime` in local time, as zip stores them,
+/// clamped to the years they can hold
+fn dos_time(mtime: u64) -> (u16, u16) {
+    let Some(time) = i64::try_from(mtime)
+        .ok()
+        .and_then(|secs| DateTime::from_timestamp(secs, 0))
+        .map(|t| t.with_timezone(&Local))
+        .filter(|t| t.year() >= 1980)
+    else {
+        // 1980-01-01 00:00
+        return (0, (1 << 5) | 1);
+    };
+    if time.year() > 2107 {
+        // 2107-12-31 23:59:58
+        return ((23 << 11) | (59 << 5) | 29, (127 << 9) | (12 << 5) | 31);
+    }
+    let dos_time = (time.hour() << 11) | (time.minute() << 5) | (time.second() / 2);
+    let dos_date = ((time.year() as u32 - 1980) << 9) | (time.month() << 5) | time.day();
+    (dos_time as u16, dos_date as u16)
+}
+
+/// CRC-32 of zip, the reflected polynomial 0xedb88320
+struct Crc32(u32);
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+impl Crc32 {
+    fn new() -> Self {
+        Self(!0)
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 = CRC_TABLE[((self.0 ^ u32::from(byte)) & 0xff) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    fn finish(self) -> u32 {
+        !self.0
+    }
+}
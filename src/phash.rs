@@ -0,0 +1,365 @@
+//! `rxd near-duplicates`: images that look alike without being identical,
+//! such as the same artwork reposted at another size or quality.
+//!
+//! Every image gets a 64-bit dHash: `ffmpeg` shrinks it to 9×8 grey
+//! pixels and each bit tells whether a pixel is brighter than its right
+//! neighbour. Hashes are recorded, so only new files are hashed on later
+//! runs. Images whose hashes differ in at most a given number of bits are
+//! near-duplicates, and chains of them form one cluster. Nothing is ever
+//! deleted, clusters are only reported.
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use futures::StreamExt;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tokio::process::Command;
+use tracing::{debug, instrument, warn};
+
+use crate::db::{self, PhashedMedia};
+use crate::error::{Error, Result};
+use crate::thumbs::IMAGE_EXTENSIONS;
+
+/// Width and height images are shrunk to, one column more for the
+/// neighbour of the last
+const WIDTH: usize = 9;
+const HEIGHT: usize = 8;
+
+/// How images are hashed
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PhashOptions {
+    /// Images hashed at once
+    pub concurrency: usize,
+    /// The `ffmpeg` program to run
+    pub ffmpeg: PathBuf,
+    /// Longest an `ffmpeg` run may take before the image is skipped
+    pub timeout: Duration,
+}
+
+impl Default for PhashOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            ffmpeg: PathBuf::from("ffmpeg"),
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl PhashOptions {
+    /// Images hashed at once
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// The `ffmpeg` program to run
+    pub fn ffmpeg(mut self, ffmpeg: impl Into<PathBuf>) -> Self {
+        self.ffmpeg = ffmpeg.into();
+        self
+    }
+
+    /// Longest an `ffmpeg` run may take before the image is skipped
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// Counts of hashing the new images
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PhashSummary {
+    pub hashed: u64,
+    /// Recorded images not on disk
+    pub missing: u64,
+    /// Unreadable or corrupt images with what `ffmpeg` said, they are
+    /// tried again on the next run
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+/// Images that look alike
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Cluster {
+    /// Oldest recorded first
+    pub media: Vec<PhashedMedia>,
+    /// Most bits any image differs in from the first
+    pub max_distance: u32,
+}
+
+/// dHash of 9×8 grey pixels, row by row
+pub fn dhash(pixels: &[u8; WIDTH * HEIGHT]) -> u64 {
+    let mut hash = 0;
+    for row in pixels.chunks_exact(WIDTH) {
+        for pair in row.windows(2) {
+            hash = hash << 1 | u64::from(pair[0] > pair[1]);
+        }
+    }
+    hash
+}
+
+/// Bits two hashes differ in
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Record the perceptual hash of every downloaded image of `screen_name`,
+/// or of every account, that has none yet
+///
+/// Images are looked up in the folder `folder` returns for their account
+/// and `progress` is called with the number of handled images and the
+/// total. Fails only if `ffmpeg` cannot be run at all.
+#[instrument(skip_all)]
+pub async fn hash_images(
+    pool: &SqlitePool,
+    screen_name: Option<&str>,
+    folder: impl Fn(&str) -> PathBuf,
+    options: &PhashOptions,
+    mut progress: impl FnMut(u64, u64),
+) -> Result<PhashSummary> {
+    let images: Vec<(String, PathBuf)> = db::unphashed_media(pool, screen_name)
+        .await?
+        .into_iter()
+        .filter_map(|media| {
+            let path = folder(&media.screen_name).join(&media.filename);
+            is_image(&path).then_some((media.media_url, path))
+        })
+        .collect();
+    let mut summary = PhashSummary::default();
+
+    let total = images.len() as u64;
+    let mut done = 0;
+    progress(0, total);
+    let mut hashes = futures::stream::iter(images)
+        .map(|(media_url, path)| async move {
+            let result = image_dhash(&options.ffmpeg, &path, options.timeout).await;
+            (media_url, path, result)
+        })
+        .buffer_unordered(options.concurrency.max(1));
+
+    while let Some((media_url, path, result)) = hashes.next().await {
+        done += 1;
+        progress(done, total);
+        match result {
+            Ok(hash) => {
+                db::update_phash(pool, &media_url, hash).await?;
+                summary.hashed += 1;
+            }
+            Err(Error::Io(e)) if e.kind() == ErrorKind::NotFound && !path.exists() => {
+                debug!("not on disk: {}", path.display());
+                summary.missing += 1;
+            }
+            Err(e @ (Error::PerceptualHash(_) | Error::Timeout(_))) => {
+                warn!("failed to hash {}: {}", path.display(), e);
+                summary.failed.push((path, e.to_string()));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(summary)
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// dHash of the image at `path`, shrunk and greyed by `ffmpeg`
+async fn image_dhash(ffmpeg: &Path, path: &Path, timeout: Duration) -> Result<u64> {
+    // A missing file is not worth running ffmpeg for
+    tokio::fs::metadata(path).await?;
+    let mut command = Command::new(ffmpeg);
+    command
+        .args(["-nostdin", "-loglevel", "error", "-i"])
+        .arg(path)
+        .args([
+            "-vf",
+            &format!("scale={WIDTH}:{HEIGHT}:flags=area,format=gray"),
+            "-frames:v",
+            "1",
+            "-f",
+            "rawvideo",
+            "-",
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let output = match tokio::time::timeout(timeout, command.output()).await {
+        Err(_) => return Err(Error::Timeout(timeout)),
+        Ok(Err(e)) if e.kind() == ErrorKind::NotFound => {
+            return Err(Error::Config(format!(
+                "perceptual hashes need {}, which is not installed",
+                ffmpeg.display()
+            )));
+        }
+        Ok(output) => output?,
+    };
+    if !output.status.success() {
+        let reason = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(Error::PerceptualHash(if reason.is_empty() {
+            format!("ffmpeg failed with {}", output.status)
+        } else {
+            reason
+        }));
+    }
+    let pixels: [u8; WIDTH * HEIGHT] = output.stdout.as_slice().try_into().map_err(|_| {
+        Error::PerceptualHash(format!(
+            "expected {} pixels from ffmpeg, got {}",
+            WIDTH * HEIGHT,
+            output.stdout.len()
+        ))
+    })?;
+    Ok(dhash(&pixels))
+}
+
+/// Clusters of the hashed images of `screen_name`, or of every account,
+/// that differ in at most `max_distance` bits, largest first
+#[instrument(skip_all)]
+pub async fn near_duplicates(
+    pool: &SqlitePool,
+    screen_name: Option<&str>,
+    max_distance: u32,
+) -> Result<Vec<Cluster>> {
+    let media = db::phashed_media(pool, screen_name).await?;
+    Ok(cluster(media, max_distance))
+}
+
+/// Group `media` into clusters of images that differ in at most
+/// `max_distance` bits from another one of the cluster, largest first
+///
+/// Images without a near-duplicate are left out.
+pub fn cluster(media: Vec<PhashedMedia>, max_distance: u32) -> Vec<Cluster> {
+    let mut tree = BkTree::default();
+    let mut groups = UnionFind::new(media.len());
+    for (index, item) in media.iter().enumerate() {
+        for other in tree.within(item.phash, max_distance) {
+            groups.union(index, other);
+        }
+        tree.insert(item.phash, index);
+    }
+
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for index in 0..media.len() {
+        members.entry(groups.find(index)).or_default().push(index);
+    }
+    let mut media: Vec<Option<PhashedMedia>> = media.into_iter().map(Some).collect();
+    let mut clusters: Vec<Cluster> = members
+        .into_values()
+        .filter(|indexes| indexes.len() > 1)
+        .map(|mut indexes| {
+            indexes.sort_unstable();
+            let media: Vec<PhashedMedia> = indexes
+                .into_iter()
+                .filter_map(|index| media[index].take())
+                .collect();
+            let first = media[0].phash;
+            let max_distance = media
+                .iter()
+                .map(|m| distance(first, m.phash))
+                .max()
+                .unwrap_or_default();
+            Cluster {
+                media,
+                max_distance,
+            }
+        })
+        .collect();
+    clusters.sort_by(|a, b| {
+        b.media
+            .len()
+            .cmp(&a.media.len())
+            .then_with(|| a.media[0].media_url.cmp(&b.media[0].media_url))
+    });
+    clusters
+}
+
+/// Hashes by their distance to each other, to find the ones close to a
+/// hash without comparing it to all of them
+#[derive(Default)]
+struct BkTree {
+    /// Hash, index of its image and children by distance
+    nodes: Vec<(u64, usize, HashMap<u32, usize>)>,
+}
+
+impl BkTree {
+    fn insert(&mut self, hash: u64, index: usize) {
+        let new = self.nodes.len();
+        if new == 0 {
+            self.nodes.push((hash, index, HashMap::new()));
+            return;
+        }
+        let mut node = 0;
+        loop {
+            let d = distance(self.nodes[node].0, hash);
+            match self.nodes[node].2.get(&d) {
+                Some(&child) => node = child,
+                None => {
+                    self.nodes[node].2.insert(d, new);
+                    self.nodes.push((hash, index, HashMap::new()));
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Indexes of the images within `max_distance` of `hash`
+    fn within(&self, hash: u64, max_distance: u32) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut pending = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![0]
+        };
+        while let Some(node) = pending.pop() {
+            let (node_hash, index, children) = &self.nodes[node];
+            let d = distance(*node_hash, hash);
+            if d <= max_distance {
+                found.push(*index);
+            }
+            let range = d.saturating_sub(max_distance)..=d + max_distance;
+            pending.extend(
+                children
+                    .iter()
+                    .filter(|(child_distance, _)| range.contains(child_distance))
+                    .map(|(_, &child)| child),
+            );
+        }
+        found
+    }
+}
+
+struct UnionFind {
+    parents: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(len: usize) -> Self {
+        Self {
+            parents: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, mut index: usize) -> usize {
+        while self.parents[index] != index {
+            self.parents[index] = self.parents[self.parents[index]];
+            index = self.parents[index];
+        }
+        index
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parents[a.max(b)] = a.min(b);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Details of an account for `rxd info`, from the same UserByScreenName
+//! lookup a download starts with.
+
+use chrono::DateTime;
+use serde::Serialize;
+
+use crate::graphql::UserData;
+
+/// Public details of an account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct Profile {
+    pub screen_name: String,
+    /// Display name
+    pub name: String,
+    pub rest_id: String,
+    pub media_count: u64,
+    /// Tweets including replies and retweets
+    pub statuses_count: u64,
+    pub followers_count: u64,
+    /// Tweets are only visible to approved followers
+    pub protected: bool,
+    /// When the account was created, RFC 3339
+    pub created_at: Option<String>,
+    pub avatar_url: Option<String>,
+    pub banner_url: Option<String>,
+}
+
+impl Profile {
+    pub(crate) fn new(screen_name: &str, user: UserData) -> Self {
+        let legacy = user.legacy;
+        Self {
+            screen_name: legacy
+                .screen_name
+                .unwrap_or_else(|| screen_name.to_string()),
+            name: legacy.name,
+            rest_id: user.rest_id,
+            media_count: legacy.media_count,
+            statuses_count: legacy.statuses_count,
+            followers_count: legacy.followers_count,
+            protected: legacy.protected,
+            created_at: legacy.created_at.map(|created| {
+                DateTime::parse_from_str(&created, "%a %b %d %H:%M:%S %z %Y")
+                    .map(|date| date.to_rfc3339())
+                    .unwrap_or(created)
+            }),
+            // `_normal` is a 48px thumbnail, without it the upload
+            avatar_url: legacy
+                .profile_image_url_https
+                .map(|url| url.replacen("_normal.", ".", 1)),
+            banner_url: legacy.profile_banner_url,
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Media hosts pushing back with `429 Too Many Requests` or `503 Service
+//! Unavailable` and a `Retry-After` header.
+//!
+//! This is separate from the GraphQL rate limits, which are announced with
+//! `x-rate-limit-*` headers and waited out in [`Api`](crate::api::Api). A
+//! download that is pushed back waits as long as the host asks, up to
+//! [`PushbackPolicy::max_wait`], and is retried. Every pushback also halves
+//! how many files are downloaded from that host at once until it has left
+//! the downloads alone for [`PushbackPolicy::cooldown`].
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::DateTime;
+use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use tokio::sync::Notify;
+use tracing::info;
+
+use crate::error::{Error, Result};
+
+/// How downloads answer a media host pushing back
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct PushbackPolicy {
+    /// Longest wait before retrying, whatever `Retry-After` asks for
+    pub max_wait: Duration,
+    /// Retries of a download pushed back again and again before it fails
+    pub max_retries: u32,
+    /// Time without pushback before a host gets its full concurrency back
+    pub cooldown: Duration,
+}
+
+impl Default for PushbackPolicy {
+    fn default() -> Self {
+        Self {
+            max_wait: Duration::from_secs(5 * 60),
+            max_retries: 3,
+            cooldown: Duration::from_secs(60),
+        }
+    }
+}
+
+impl PushbackPolicy {
+    /// Longest wait before retrying, whatever `Retry-After` asks for
+    pub fn max_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait = max_wait;
+        self
+    }
+
+    /// Retries of a download pushed back again and again before it fails
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Time without pushback before a host gets its full concurrency back
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+}
+
+/// Wait asked for by a `Retry-After` header at `now`, in delta-seconds or
+/// as an HTTP-date
+///
+/// Dates in the past ask for no wait. `None` without a header or with one
+/// that cannot be read.
+pub fn retry_after(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = SystemTime::from(DateTime::parse_from_rfc2822(value).ok()?);
+    Some(date.duration_since(now).unwrap_or_default())
+}
+
+/// [`Error::PushedBack`] for a media response that asks to come back later
+pub(crate) fn check(response: &reqwest::Response) -> Result<()> {
+    let status = response.status();
+    if !matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    ) {
+        return Ok(());
+    }
+    match retry_after(response.headers(), SystemTime::now()) {
+        Some(retry_after) => Err(Error::PushedBack {
+            status,
+            retry_after,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Host of a media URL, the unit downloads are throttled by
+pub(crate) fn host(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// Downloads in flight per host, lowered for hosts that push back
+#[derive(Debug)]
+pub(crate) struct HostThrottle {
+    policy: PushbackPolicy,
+    concurrency: usize,
+    hosts: Mutex<HashMap<String, HostState>>,
+    /// Signalled whenever a download ends
+    freed: Notify,
+}
+
+#[derive(Debug)]
+struct HostState {
+    in_flight: usize,
+    limit: usize,
+    /// When the limit goes back to the full concurrency
+    restore_at: Option<Instant>,
+}
+
+/// A download slot of a host, given back when dropped
+pub(crate) struct HostPermit<'a> {
+    throttle: &'a HostThrottle,
+    host: String,
+}
+
+impl HostThrottle {
+    pub(crate) fn new(policy: PushbackPolicy, concurrency: usize) -> Self {
+        Self {
+            policy,
+            concurrency: concurrency.max(1),
+            hosts: Mutex::default(),
+            freed: Notify::new(),
+        }
+    }
+
+    pub(crate) fn policy(&self) -> &PushbackPolicy {
+        &self.policy
+    }
+
+    /// Wait until `host` has a free download slot
+    pub(crate) async fn acquire(&self, host: &str) -> HostPermit<'_> {
+        loop {
+            let freed = self.freed.notified();
+            let restore_at = {
+                let mut hosts = self.hosts.lock().expect("host throttle poisoned");
+                let state = hosts.entry(host.to_string()).or_insert(HostState {
+                    in_flight: 0,
+                    limit: self.concurrency,
+                    restore_at: None,
+                });
+                if state.restore_at.is_some_and(|at| at <= Instant::now()) {
+                    info!(
+                        "{} recovered, downloading {} files at once again",
+                        host, self.concurrency
+                    );
+                    state.limit = self.concurrency;
+                    state.restore_at = None;
+                }
+                if state.in_flight < state.limit {
+                    state.in_flight += 1;
+                    return HostPermit {
+                        throttle: self,
+                        host: host.to_string(),
+                    };
+                }
+                state.restore_at
+            };
+            match restore_at {
+                Some(at) => {
+                    let _ = tokio::time::timeout_at(at.into(), freed).await;
+                }
+                None => freed.await,
+            }
+        }
+    }
+
+    /// Record `host` pushing back, halving its concurrency for a while
+    pub(crate) fn pushed_back(&self, host: &str) {
+        let mut hosts = self.hosts.lock().expect("host throttle poisoned");
+        let Some(state) = hosts.get_mut(host) else {
+            return;
+        };
+        let limit = (state.limit / 2).max(1);
+        if limit < state.limit {
+            info!(
+                "{} pushed back, downloading at most {} files at once from it",
+                host, limit
+            );
+        }
+        state.limit = limit;
+        state.restore_at = Some(Instant::now() + self.policy.cooldown);
+    }
+}
+
+impl Drop for HostPermit<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut hosts) = self.throttle.hosts.lock()
+            && let Some(state) = hosts.get_mut(&self.host)
+        {
+            state.in_flight -= 1;
+        }
+        self.throttle.freed.notify_waiters();
+    }
+}
@@ -0,0 +1,204 @@
+//! Discovery of the GraphQL query IDs used by the web client.
+//!
+//! Query IDs rotate whenever the web client is redeployed. The current ones
+//! are read from the main JS bundle and cached on disk, with the IDs compiled
+//! into rxd as a fallback.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::LazyLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use regex::Regex;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, instrument, warn};
+
+use crate::error::{Error, Result};
+
+pub const USER_BY_SCREEN_NAME: &str = "UserByScreenName";
+pub const USER_MEDIA: &str = "UserMedia";
+pub const FOLLOWING: &str = "Following";
+pub const LIST_MEMBERS: &str = "ListMembers";
+pub const TWEET_RESULT_BY_REST_ID: &str = "TweetResultByRestId";
+
+const DEFAULT_USER_BY_SCREEN_NAME_ID: &str = "xc8f1g7BYqr6VTzTbvNlGw";
+const DEFAULT_USER_MEDIA_ID: &str = "Le6KlbilFmSu-5VltFND-Q";
+const DEFAULT_FOLLOWING_ID: &str = "iSicc7LrzWGBgDPL0tM_TQ";
+const DEFAULT_LIST_MEMBERS_ID: &str = "BQp2IEYkgxuSxqbTAr1e1g";
+const DEFAULT_TWEET_RESULT_BY_REST_ID_ID: &str = "Xl5pC_lBk_gcO2ItU39DQw";
+
+/// How long discovered IDs are trusted before the bundle is fetched again
+pub const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+static MAIN_BUNDLE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"src="([^"]*/responsive-web/client-web[^/"]*/main\.[0-9a-zA-Z]+\.js)""#)
+        .expect("valid regex")
+});
+static OPERATION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"queryId:"([^"]+)",operationName:"([^"]+)""#).expect("valid regex")
+});
+
+/// Query IDs of the operations rxd uses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryIds {
+    pub user_by_screen_name: String,
+    pub user_media: String,
+    pub following: String,
+    pub list_members: String,
+    pub tweet_result_by_rest_id: String,
+}
+
+impl Default for QueryIds {
+    fn default() -> Self {
+        Self {
+            user_by_screen_name: DEFAULT_USER_BY_SCREEN_NAME_ID.to_string(),
+            user_media: DEFAULT_USER_MEDIA_ID.to_string(),
+            following: DEFAULT_FOLLOWING_ID.to_string(),
+            list_members: DEFAULT_LIST_MEMBERS_ID.to_string(),
+            tweet_result_by_rest_id: DEFAULT_TWEET_RESULT_BY_REST_ID_ID.to_string(),
+        }
+    }
+}
+
+impl QueryIds {
+    /// Pick the operations rxd uses from a discovered mapping, keeping the
+    /// compiled-in ID for any that are missing
+    pub fn from_operations(operations: &HashMap<String, String>) -> Self {
+        let mut ids = Self::default();
+        if let Some(id) = operations.get(USER_BY_SCREEN_NAME) {
+            ids.user_by_screen_name = id.clone();
+        }
+        if let Some(id) = operations.get(USER_MEDIA) {
+            ids.user_media = id.clone();
+        }
+        if let Some(id) = operations.get(FOLLOWING) {
+            ids.following = id.clone();
+        }
+        if let Some(id) = operations.get(LIST_MEMBERS) {
+            ids.list_members = id.clone();
+        }
+        if let Some(id) = operations.get(TWEET_RESULT_BY_REST_ID) {
+            ids.tweet_result_by_rest_id = id.clone();
+        }
+        ids
+    }
+}
+
+/// On-disk cache of discovered operations
+#[derive(Debug, Serialize, Deserialize)]
+struct Cache {
+    /// Unix timestamp of the discovery
+    fetched_at: u64,
+    operations: HashMap<String, String>,
+}
+
+/// Query IDs from the cache, rediscovering them if the cache is stale,
+/// missing or `refresh` is set
+///
+/// Never fails, the compiled-in IDs are used when discovery does not work.
+#[instrument(skip_all)]
+pub async fn resolve(
+    client: &Client,
+    base_url: &str,
+    cache_path: &Path,
+    refresh: bool,
+) -> QueryIds {
+    let now = unix_now();
+    if !refresh
+        && let Some(cache) = read_cache(cache_path).await
+        && now.saturating_sub(cache.fetched_at) < CACHE_TTL.as_secs()
+    {
+        debug!("using cached query ids from {}", cache_path.display());
+        return QueryIds::from_operations(&cache.operations);
+    }
+
+    match discover(client, base_url).await {
+        Ok(operations) => {
+            info!("discovered {} graphql query ids", operations.len());
+            let ids = QueryIds::from_operations(&operations);
+            let cache = Cache {
+                fetched_at: now,
+                operations,
+            };
+            if let Err(e) = write_cache(cache_path, &cache).await {
+                warn!(
+                    "failed to cache query ids at {}: {}",
+                    cache_path.display(),
+                    e
+                );
+            }
+            ids
+        }
+        Err(e) => {
+            warn!("query id discovery failed: {}", e);
+            // A stale cache is still more recent than the compiled-in IDs
+            match read_cache(cache_path).await {
+                Some(cache) => QueryIds::from_operations(&cache.operations),
+                None => QueryIds::default(),
+            }
+        }
+    }
+}
+
+/// Fetch the web client and its main bundle, returning operation name → query ID
+#[instrument(skip_all)]
+pub async fn discover(client: &Client, base_url: &str) -> Result<HashMap<String, String>> {
+    let html = client
+        .get(format!("{base_url}/"))
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let bundle_url = find_main_bundle(&html)
+        .ok_or_else(|| Error::Parse("main bundle not found in web client page".to_string()))?;
+    debug!("fetching main bundle {}", bundle_url);
+
+    let js = client
+        .get(&bundle_url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let operations = parse_bundle(&js);
+    if operations.is_empty() {
+        return Err(Error::Parse(
+            "no query ids found in main bundle".to_string(),
+        ));
+    }
+    Ok(operations)
+}
+
+/// URL of the main JS bundle referenced by the web client page
+pub fn find_main_bundle(html: &str) -> Option<String> {
+    MAIN_BUNDLE
+        .captures(html)
+        .map(|captures| captures[1].to_string())
+}
+
+/// Operation name → query ID pairs defined in a JS bundle
+pub fn parse_bundle(js: &str) -> HashMap<String, String> {
+    OPERATION
+        .captures_iter(js)
+        .map(|captures| (captures[2].to_string(), captures[1].to_string()))
+        .collect()
+}
+
+async fn read_cache(path: &Path) -> Option<Cache> {
+    let content = tokio::fs::read(path).await.ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+async fn write_cache(path: &Path, cache: &Cache) -> Result<()> {
+    tokio::fs::write(path, serde_json::to_vec_pretty(cache)?).await?;
+    Ok(())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
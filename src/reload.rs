@@ -0,0 +1,163 @@
+//! Config reloads in watch mode.
+//!
+//! The config file is read again before every run of watch mode. Tasks,
+//! filters and other per-run settings come from the new version, while what
+//! is set up once at startup (credentials, the database, logging, hooks)
+//! stays as it was until rxd is restarted. A config that fails to load is
+//! rejected and the previous one kept.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use toml::{Table, Value};
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::filename::Template;
+
+/// Settings whose values are never logged
+const SECRETS: &[&str] = &["auth_token", "ct0", "accounts"];
+
+/// Settings only read at startup, changing them needs a restart
+const STARTUP: &[&str] = &[
+    "accounts",
+    "api_base_url",
+    "api_concurrency",
+    "auth_token",
+    "ca_cert",
+    "ct0",
+    "database",
+    "desktop_notify",
+    "extract_video_frames",
+    "ffmpeg_concurrency",
+    "ffmpeg_timeout_secs",
+    "guest",
+    "hook_concurrency",
+    "hook_timeout_secs",
+    "keep_reports",
+    "log_file",
+    "log_file_format",
+    "log_file_level",
+    "log_format",
+    "metrics_listen",
+    "page_size",
+    "per_task_logs",
+    "post_download_hook",
+    "post_process",
+    "report",
+    "rotate_accounts",
+    "schedule",
+    "tls_os_roots",
+];
+
+/// A config file as loaded, with its raw contents to compare versions by
+#[derive(Debug)]
+pub struct LoadedConfig {
+    pub config: Config,
+    table: Table,
+}
+
+impl LoadedConfig {
+    /// Read, parse and check a config file
+    ///
+    /// Fails on anything a run would only fail on later, such as a filename
+    /// template that does not parse.
+    pub fn load(path: &Path) -> Result<Self> {
+        let config = Config::load(path)?;
+        let raw = std::fs::read_to_string(path)?;
+        let table = raw
+            .parse::<Table>()
+            .map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))?;
+        let templates = config
+            .tasks
+            .iter()
+            .filter_map(|task| task.filename_template.as_deref())
+            .chain(config.filename_template.as_deref());
+        for template in templates {
+            Template::parse(template)?;
+        }
+        Ok(Self { config, table })
+    }
+
+    /// What changed in `new`, one line each, tasks first, e.g.
+    /// `added @foo`, `removed @bar` or `concurrent_downloads 4→8`
+    pub fn changes(&self, new: &LoadedConfig) -> Vec<String> {
+        let mut changes = Vec::new();
+        let old_tasks = tasks(&self.table);
+        let new_tasks = tasks(&new.table);
+        for (key, (screen_name, _)) in &new_tasks {
+            if !old_tasks.contains_key(key) {
+                changes.push(format!("added @{screen_name}"));
+            }
+        }
+        for (key, (screen_name, _)) in &old_tasks {
+            if !new_tasks.contains_key(key) {
+                changes.push(format!("removed @{screen_name}"));
+            }
+        }
+        for (key, (screen_name, old)) in &old_tasks {
+            if let Some((_, new)) = new_tasks.get(key) {
+                changes.extend(
+                    table_changes(old, new, &["screen_name"])
+                        .into_iter()
+                        .map(|change| format!("@{screen_name} {change}")),
+                );
+            }
+        }
+        changes.extend(
+            table_changes(&self.table, &new.table, &["tasks"])
+                .into_iter()
+                .map(|change| {
+                    let key = change.split_whitespace().next().unwrap_or_default();
+                    if STARTUP.contains(&key) {
+                        format!("{change}, restart rxd to apply it")
+                    } else {
+                        change
+                    }
+                }),
+        );
+        changes
+    }
+}
+
+/// Tasks of a raw config by lowercase screen name
+fn tasks(table: &Table) -> BTreeMap<String, (&str, &Table)> {
+    let mut tasks = BTreeMap::new();
+    let entries = table.get("tasks").and_then(Value::as_array);
+    for task in entries.into_iter().flatten().filter_map(Value::as_table) {
+        if let Some(screen_name) = task.get("screen_name").and_then(Value::as_str) {
+            let screen_name = screen_name.trim_start_matches('@');
+            tasks
+                .entry(screen_name.to_ascii_lowercase())
+                .or_insert((screen_name, task));
+        }
+    }
+    tasks
+}
+
+/// Changed settings of two tables, leaving out `ignored`
+fn table_changes(old: &Table, new: &Table, ignored: &[&str]) -> Vec<String> {
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    keys.into_iter()
+        .filter(|key| !ignored.contains(&key.as_str()))
+        .filter_map(|key| {
+            let (old, new) = (old.get(key), new.get(key));
+            if old == new {
+                return None;
+            }
+            let nested = |value: Option<&Value>| match value {
+                Some(Value::Table(_)) => true,
+                Some(Value::Array(values)) => values.iter().any(Value::is_table),
+                _ => false,
+            };
+            if SECRETS.contains(&key.as_str()) || nested(old) || nested(new) {
+                return Some(format!("{key} changed"));
+            }
+            let show = |value: Option<&Value>| match value {
+                Some(value) => value.to_string(),
+                None => "unset".to_string(),
+            };
+            Some(format!("{key} {}→{}", show(old), show(new)))
+        })
+        .collect()
+}
@@ -0,0 +1,127 @@
+//! JSON report of a download run, written after the run for other tools.
+//!
+//! The layout is versioned with [`REPORT_VERSION`]. Fields are only added
+//! within a version, so readers should ignore fields they do not know.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, SecondsFormat};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::events::Totals;
+use crate::summary::{DownloadedItem, FailedItem, RunSummary, SkippedItem};
+
+/// Version of the report layout, raised on changes readers must know about
+pub const REPORT_VERSION: u32 = 1;
+
+/// Outcome of a run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Report {
+    pub version: u32,
+    /// RFC 3339 start of the run
+    pub started_at: String,
+    /// RFC 3339 end of the run
+    pub finished_at: String,
+    /// SHA-256 of the config file of the run
+    pub config_digest: String,
+    /// Files on disk were downloaded again
+    #[serde(default)]
+    pub overwrite: bool,
+    /// Timelines were paged to the end with --force
+    #[serde(default)]
+    pub full_scan: bool,
+    pub tasks: Vec<TaskReport>,
+}
+
+/// Outcome of a single task
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskReport {
+    pub screen_name: String,
+    pub totals: Totals,
+    pub elapsed_secs: f64,
+    /// Set when the task was aborted before finishing
+    pub error: Option<String>,
+    pub downloaded: Vec<DownloadedItem>,
+    pub skipped: Vec<SkippedItem>,
+    pub failed: Vec<FailedItem>,
+}
+
+impl Report {
+    /// Report of a run summarised in `summary`
+    pub fn new(
+        summary: &RunSummary,
+        started_at: DateTime<Local>,
+        finished_at: DateTime<Local>,
+        config_digest: String,
+    ) -> Self {
+        Self {
+            version: REPORT_VERSION,
+            started_at: started_at.to_rfc3339_opts(SecondsFormat::Secs, false),
+            finished_at: finished_at.to_rfc3339_opts(SecondsFormat::Secs, false),
+            config_digest,
+            overwrite: summary.overwrite,
+            full_scan: summary.full_scan,
+            tasks: summary
+                .tasks
+                .iter()
+                .map(|task| TaskReport {
+                    screen_name: task.screen_name.clone(),
+                    totals: task.totals,
+                    elapsed_secs: task.elapsed.as_secs_f64(),
+                    error: task.error.clone(),
+                    downloaded: task.items.downloaded.clone(),
+                    skipped: task.items.skipped.clone(),
+                    failed: task.items.failed.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Write the report to `path` and return where it went
+    ///
+    /// The file is replaced at once, readers never see a partial report. An
+    /// existing report is overwritten, or kept with `keep_existing` by
+    /// writing next to it with the time appended to the name, e.g.
+    /// `report-20250312-100000.json`.
+    pub fn write(&self, path: &Path, keep_existing: bool) -> Result<PathBuf> {
+        let path = if keep_existing && path.exists() {
+            timestamped(path, Local::now())
+        } else {
+            path.to_path_buf()
+        };
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let json = serde_json::to_vec_pretty(self)?;
+        if let Err(e) = std::fs::write(&tmp, json).and_then(|_| std::fs::rename(&tmp, &path)) {
+            let _ = std::fs::remove_file(&tmp);
+            return Err(e.into());
+        }
+        Ok(path)
+    }
+}
+
+/// Free name next to `path` with `at` appended to its stem
+fn timestamped(path: &Path, at: DateTime<Local>) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    let base = format!("{stem}-{}", at.format("%Y%m%d-%H%M%S"));
+    let mut candidate = path.with_file_name(format!("{base}{ext}"));
+    // Runs finishing within the same second
+    let mut n = 2;
+    while candidate.exists() {
+        candidate = path.with_file_name(format!("{base}-{n}{ext}"));
+        n += 1;
+    }
+    candidate
+}
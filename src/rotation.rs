@@ -0,0 +1,173 @@
+//! Rotation of GraphQL requests between several accounts, so a rate-limited
+//! account is swapped for another instead of waiting out its window.
+//!
+//! Rate limits are tracked per account and operation from the
+//! `x-rate-limit-*` headers. Media downloads are not rotated.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::api::{MAX_RATE_LIMIT_RETRIES, MAX_RATE_LIMIT_WAIT, RateLimit};
+use crate::error::{Error, Result};
+
+/// Cookies of an extra account in `[[accounts]]`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Credentials {
+    /// Shown in logs, `account <n>` if not set
+    #[serde(default)]
+    pub name: Option<String>,
+    pub auth_token: String,
+    pub ct0: String,
+}
+
+/// Clients of several accounts, each request sent by the one with the most
+/// requests left
+#[derive(Debug)]
+pub struct AccountPool {
+    accounts: Vec<PooledAccount>,
+}
+
+#[derive(Debug)]
+struct PooledAccount {
+    name: String,
+    client: Client,
+    /// Operation → its last known rate limit window
+    limits: Mutex<HashMap<String, RateLimit>>,
+}
+
+impl PooledAccount {
+    /// Requests left for `operation` at `now`, `u64::MAX` when unknown or
+    /// the window has reset
+    fn budget(&self, operation: &str, now: u64) -> u64 {
+        let limits = self.limits.lock().unwrap_or_else(|e| e.into_inner());
+        match limits.get(operation) {
+            Some(RateLimit {
+                remaining: Some(remaining),
+                reset: Some(reset),
+                ..
+            }) if *reset > now => *remaining,
+            _ => u64::MAX,
+        }
+    }
+
+    /// Seconds until the window of `operation` resets
+    fn reset_in(&self, operation: &str, now: u64) -> Option<u64> {
+        let limits = self.limits.lock().unwrap_or_else(|e| e.into_inner());
+        limits
+            .get(operation)
+            .and_then(|limit| limit.reset)
+            .map(|reset| reset.saturating_sub(now))
+    }
+
+    fn record(&self, operation: &str, mut limit: RateLimit, status: StatusCode) {
+        // Rate limited without headers, assume the usual 15 minute window
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            limit.remaining = Some(0);
+            limit.reset = limit
+                .reset
+                .or_else(|| Some(unix_now() + MAX_RATE_LIMIT_WAIT.as_secs()));
+        }
+        if limit.remaining.is_some() || limit.reset.is_some() {
+            self.limits
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(operation.to_string(), limit);
+        }
+    }
+}
+
+impl AccountPool {
+    /// Pool of `(name, client)` pairs, the first is preferred on ties
+    pub fn new(accounts: Vec<(String, Client)>) -> Self {
+        Self {
+            accounts: accounts
+                .into_iter()
+                .map(|(name, client)| PooledAccount {
+                    name,
+                    client,
+                    limits: Mutex::default(),
+                })
+                .collect(),
+        }
+    }
+
+    fn acquire(&self, operation: &str, now: u64) -> Option<&PooledAccount> {
+        self.accounts
+            .iter()
+            .min_by_key(|a| Reverse(a.budget(operation, now)))
+    }
+
+    /// Send the request `build` makes for a client with the account that
+    /// has the most requests of `operation` left
+    ///
+    /// A rate-limited account is swapped for one with requests left. Once
+    /// every account is limited the earliest window is waited out, up to
+    /// [`MAX_RATE_LIMIT_RETRIES`] times.
+    pub(crate) async fn send(
+        &self,
+        operation: &str,
+        build: impl Fn(&Client) -> RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        let mut attempt = 0;
+        loop {
+            let account = self
+                .acquire(operation, unix_now())
+                .ok_or_else(|| Error::Config("no accounts to rotate between".to_string()))?;
+            let response = build(&account.client).send().await?;
+            let status = response.status();
+            debug!("{} served by {}: {}", operation, account.name, status);
+            account.record(
+                operation,
+                RateLimit::from_headers(response.headers()),
+                status,
+            );
+            if status != StatusCode::TOO_MANY_REQUESTS {
+                return Ok(response);
+            }
+
+            let now = unix_now();
+            if let Some(next) = self
+                .acquire(operation, now)
+                .filter(|next| next.budget(operation, now) > 0)
+            {
+                warn!(
+                    "{} rate limited on {}, switching to {}",
+                    account.name, operation, next.name
+                );
+                continue;
+            }
+            if attempt >= MAX_RATE_LIMIT_RETRIES {
+                return Ok(response);
+            }
+            attempt += 1;
+            let wait = self
+                .accounts
+                .iter()
+                .filter_map(|a| a.reset_in(operation, now))
+                .min()
+                .map_or(Duration::from_secs(60), |secs| {
+                    Duration::from_secs(secs.max(1)).min(MAX_RATE_LIMIT_WAIT)
+                });
+            warn!(
+                "every account rate limited, waiting {}s before retrying ({}/{})",
+                wait.as_secs(),
+                attempt,
+                MAX_RATE_LIMIT_RETRIES
+            );
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
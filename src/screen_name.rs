@@ -0,0 +1,92 @@
+//! Screen names as they are pasted: bare handles, `@handle` or profile URLs.
+
+use serde::{Deserialize, Deserializer};
+
+use crate::error::{Error, Result};
+
+/// Longest handle X allows
+const MAX_LEN: usize = 15;
+
+/// Hosts of profile URLs, matched ignoring case
+const HOSTS: &[&str] = &[
+    "x.com",
+    "www.x.com",
+    "mobile.x.com",
+    "twitter.com",
+    "www.twitter.com",
+    "mobile.twitter.com",
+];
+
+/// First path segments of X pages that are not profiles
+const RESERVED: &[&str] = &[
+    "i",
+    "home",
+    "explore",
+    "search",
+    "settings",
+    "notifications",
+    "messages",
+    "intent",
+    "share",
+    "hashtag",
+];
+
+/// Handle in `input`, which may be a screen name, `@screen_name` or a
+/// profile URL such as `https://x.com/nasa/media?s=21`
+///
+/// The scheme, host, query, fragment, a leading `@` and anything after the
+/// first path segment are dropped, and what is left must be 1 to 15
+/// letters, digits or underscores. The case is kept.
+pub fn normalize(input: &str) -> Result<String> {
+    let invalid = || Error::InvalidScreenName(input.to_string());
+
+    let mut rest = input.trim();
+    for scheme in ["https://", "http://"] {
+        if rest
+            .get(..scheme.len())
+            .is_some_and(|s| s.eq_ignore_ascii_case(scheme))
+        {
+            rest = &rest[scheme.len()..];
+            break;
+        }
+    }
+    let had_scheme = rest.len() != input.trim().len();
+    rest = rest.split(['?', '#']).next().unwrap_or_default();
+
+    let mut segments = rest.split('/').filter(|s| !s.is_empty());
+    let first = segments.next().ok_or_else(invalid)?;
+    let handle = if HOSTS.iter().any(|host| host.eq_ignore_ascii_case(first)) {
+        let handle = segments.next().ok_or_else(invalid)?;
+        if RESERVED
+            .iter()
+            .any(|page| page.eq_ignore_ascii_case(handle))
+        {
+            return Err(invalid());
+        }
+        handle
+    } else if had_scheme || rest.trim_end_matches('/').contains('/') {
+        // A URL of another site, or a path of something else
+        return Err(invalid());
+    } else {
+        first
+    };
+
+    let handle = handle.strip_prefix('@').unwrap_or(handle);
+    let valid = (1..=MAX_LEN).contains(&handle.len())
+        && handle
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(handle.to_string())
+    } else {
+        Err(invalid())
+    }
+}
+
+/// Deserialize a screen name with [`normalize`]
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<String, D::Error> {
+    let input = String::deserialize(deserializer)?;
+    normalize(&input).map_err(serde::de::Error::custom)
+}
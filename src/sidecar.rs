@@ -0,0 +1,86 @@
+//! Plain-text caption files written next to downloaded media, for grepping
+//! and for gallery apps that show `.txt` captions.
+
+use std::path::{Path, PathBuf};
+
+use crate::task::permalink;
+
+/// How caption files are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextSidecars {
+    /// One `<tweet_id>.txt` per tweet instead of one per media file
+    pub per_tweet: bool,
+    /// Add the tweet's permalink on a second line
+    pub permalink: bool,
+}
+
+impl TextSidecars {
+    /// Caption file of the media file at `media_path` of `tweet_id`
+    pub fn path(&self, media_path: &Path, tweet_id: &str) -> PathBuf {
+        if self.per_tweet {
+            media_path.with_file_name(format!("{tweet_id}.txt"))
+        } else {
+            media_path.with_extension("txt")
+        }
+    }
+
+    /// Content of the caption file, `None` for a tweet without text
+    pub fn caption(&self, text: Option<&str>, screen_name: &str, tweet_id: &str) -> Option<String> {
+        let text = decode_entities(text?.trim());
+        if text.is_empty() {
+            return None;
+        }
+        Some(if self.permalink {
+            format!("{text}\n{}\n", permalink(screen_name, tweet_id))
+        } else {
+            format!("{text}\n")
+        })
+    }
+}
+
+/// `text` with the HTML entities of tweet texts decoded, e.g. `&amp;` to `&`
+///
+/// Unknown entities are kept as they are.
+pub fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((entity(&rest[1..end])?, end)));
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Character of an entity name without `&` and `;`
+fn entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => {
+            let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => name.strip_prefix('#')?.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! `rxd stats`: tweets, files and bytes archived per account, and in
+//! detail by month, by media type and the largest files.
+//!
+//! Sizes come from the database. Files downloaded before sizes were
+//! recorded are looked up on disk, which is slower, and their size is
+//! recorded so the next run does not have to.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tracing::{debug, instrument};
+
+use crate::db::{self, SizedMedia};
+use crate::error::Result;
+use crate::task::MediaType;
+use crate::verify::VIDEO_EXTENSIONS;
+
+/// What to collect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct StatsOptions {
+    /// Add the months, media types and largest files
+    pub detailed: bool,
+    /// Largest files listed in detail
+    pub top: usize,
+}
+
+impl Default for StatsOptions {
+    fn default() -> Self {
+        Self {
+            detailed: false,
+            top: 10,
+        }
+    }
+}
+
+impl StatsOptions {
+    /// Add the months, media types and largest files
+    pub fn detailed(mut self, detailed: bool) -> Self {
+        self.detailed = detailed;
+        self
+    }
+
+    /// List the `top` largest files in detail
+    pub fn top(mut self, top: usize) -> Self {
+        self.top = top;
+        self
+    }
+}
+
+/// Statistics of one or every account
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    pub accounts: Vec<AccountStats>,
+    /// Files whose size was read from disk instead of the database
+    pub sized_from_disk: u64,
+    /// Files of unknown size, not on disk, counted with 0 bytes
+    pub unknown_size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<Detail>,
+}
+
+/// Totals of one account
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AccountStats {
+    pub screen_name: String,
+    /// Tweets with downloaded files
+    pub tweets: u64,
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// Breakdowns over every counted file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Detail {
+    /// Months with files, oldest first
+    pub months: Vec<Totals>,
+    /// Media types with files
+    pub media_types: Vec<Totals>,
+    /// Largest files, largest first
+    pub largest: Vec<LargeFile>,
+}
+
+/// Tweets, files and bytes of one month or media type
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Totals {
+    /// `YYYY-MM` or the media type
+    pub key: String,
+    pub tweets: u64,
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// One of the largest files
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LargeFile {
+    pub screen_name: String,
+    pub tweet_id: String,
+    pub filename: String,
+    pub bytes: u64,
+}
+
+/// Collect the statistics of `screen_name`, or of every account
+///
+/// Files without a recorded size are looked up in the folder `folder`
+/// returns for their account.
+#[instrument(skip_all)]
+pub async fn collect(
+    pool: &SqlitePool,
+    screen_name: Option<&str>,
+    folder: impl Fn(&str) -> PathBuf,
+    options: &StatsOptions,
+) -> Result<Stats> {
+    let mut media = db::sized_media(pool, screen_name).await?;
+    let mut stats = Stats::default();
+
+    for item in media.iter_mut().filter(|m| m.file_size.is_none()) {
+        if item.imported {
+            stats.unknown_size += 1;
+            continue;
+        }
+        let path = folder(&item.screen_name).join(&item.filename);
+        match tokio::fs::metadata(&path).await {
+            Ok(metadata) => {
+                item.file_size = Some(metadata.len());
+                db::update_file_size(pool, &item.media_url, metadata.len()).await?;
+                stats.sized_from_disk += 1;
+            }
+            Err(e) => {
+                debug!("no size of {}: {}", path.display(), e);
+                stats.unknown_size += 1;
+            }
+        }
+    }
+
+    // Accounts are matched ignoring case, like the database does
+    let mut accounts: BTreeMap<String, (AccountStats, HashSet<&str>)> = BTreeMap::new();
+    for item in &media {
+        let (account, tweets) = accounts
+            .entry(item.screen_name.to_lowercase())
+            .or_insert_with(|| {
+                let account = AccountStats {
+                    screen_name: item.screen_name.clone(),
+                    ..Default::default()
+                };
+                (account, HashSet::new())
+            });
+        tweets.insert(&item.tweet_id);
+        account.files += 1;
+        account.bytes += item.file_size.unwrap_or(0);
+    }
+    stats.accounts = accounts
+        .into_values()
+        .map(|(account, tweets)| AccountStats {
+            tweets: tweets.len() as u64,
+            ..account
+        })
+        .collect();
+
+    if options.detailed {
+        stats.detail = Some(detail(&media, options.top));
+    }
+    Ok(stats)
+}
+
+fn detail(media: &[SizedMedia], top: usize) -> Detail {
+    let month = |item: &SizedMedia| item.tweet_time.get(..7).unwrap_or_default().to_string();
+    let media_type = |item: &SizedMedia| media_type(&item.filename).as_str().to_string();
+
+    let mut largest: Vec<LargeFile> = media
+        .iter()
+        .filter_map(|item| {
+            Some(LargeFile {
+                screen_name: item.screen_name.clone(),
+                tweet_id: item.tweet_id.clone(),
+                filename: item.filename.clone(),
+                bytes: item.file_size?,
+            })
+        })
+        .collect();
+    largest.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.filename.cmp(&b.filename)));
+    largest.truncate(top);
+
+    Detail {
+        months: totals(media, month),
+        media_types: totals(media, media_type),
+        largest,
+    }
+}
+
+/// Totals of `media` grouped by `key`, only keys with files
+fn totals(media: &[SizedMedia], key: impl Fn(&SizedMedia) -> String) -> Vec<Totals> {
+    let mut groups: BTreeMap<String, (Totals, HashSet<&str>)> = BTreeMap::new();
+    for item in media {
+        let key = key(item);
+        let (totals, tweets) = groups.entry(key.clone()).or_insert_with(|| {
+            (
+                Totals {
+                    key,
+                    ..Default::default()
+                },
+                HashSet::new(),
+            )
+        });
+        tweets.insert(&item.tweet_id);
+        totals.files += 1;
+        totals.bytes += item.file_size.unwrap_or(0);
+    }
+    groups
+        .into_values()
+        .map(|(totals, tweets)| Totals {
+            tweets: tweets.len() as u64,
+            ..totals
+        })
+        .collect()
+}
+
+/// Media type of a file by its extension, GIFs are saved as videos
+fn media_type(filename: &str) -> MediaType {
+    let extension = filename
+        .rsplit_once('.')
+        .map(|(_, e)| e.to_ascii_lowercase());
+    match extension {
+        Some(e) if VIDEO_EXTENSIONS.contains(&e.as_str()) => MediaType::Video,
+        _ => MediaType::Image,
+    }
+}
@@ -0,0 +1,271 @@
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use indicatif::{HumanBytes, HumanDuration};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::events::{Event, EventSender, SkipReason, Totals};
+
+/// Outcome of a single task, collected for the end-of-run report
+#[derive(Debug, Clone)]
+pub struct TaskSummary {
+    pub screen_name: String,
+    pub totals: Totals,
+    pub elapsed: Duration,
+    /// Set when the task was aborted before finishing
+    pub error: Option<String>,
+    /// Items handled by the task, only collected for a run report
+    pub items: ItemLog,
+}
+
+/// A new download
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DownloadedItem {
+    pub path: PathBuf,
+    pub tweet_id: String,
+    pub url: String,
+    pub bytes: u64,
+}
+
+/// An item that was not downloaded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedItem {
+    pub tweet_id: String,
+    pub url: String,
+    pub reason: SkipReason,
+}
+
+/// A failed download
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailedItem {
+    pub tweet_id: String,
+    pub url: String,
+    pub error: String,
+}
+
+/// Items of a task by outcome, in the order they were handled
+#[derive(Debug, Clone, Default)]
+pub struct ItemLog {
+    pub downloaded: Vec<DownloadedItem>,
+    pub skipped: Vec<SkippedItem>,
+    pub failed: Vec<FailedItem>,
+}
+
+impl ItemLog {
+    /// Add the item of `event`, other events are ignored
+    pub fn record(&mut self, event: &Event) {
+        match event {
+            Event::ItemDownloaded {
+                tweet_id,
+                url,
+                path,
+                bytes,
+                ..
+            } => self.downloaded.push(DownloadedItem {
+                path: path.clone(),
+                tweet_id: tweet_id.clone(),
+                url: url.clone(),
+                bytes: *bytes,
+            }),
+            Event::ItemSkipped {
+                tweet_id,
+                url,
+                reason,
+                ..
+            } => self.skipped.push(SkippedItem {
+                tweet_id: tweet_id.clone(),
+                url: url.clone(),
+                reason: *reason,
+            }),
+            Event::ItemFailed {
+                tweet_id,
+                url,
+                error,
+                ..
+            } => self.failed.push(FailedItem {
+                tweet_id: tweet_id.clone(),
+                url: url.clone(),
+                error: error.clone(),
+            }),
+            _ => {}
+        }
+    }
+
+    /// Record the events of a task's channel, passing each one on to
+    /// `forward`
+    ///
+    /// Returns once every sender has been dropped.
+    pub async fn collect(
+        mut rx: mpsc::UnboundedReceiver<Event>,
+        forward: Option<EventSender>,
+    ) -> Self {
+        let mut log = Self::default();
+        while let Some(event) = rx.recv().await {
+            log.record(&event);
+            if let Some(forward) = &forward {
+                let _ = forward.send(event);
+            }
+        }
+        log
+    }
+}
+
+/// Summary of all tasks in a run
+#[derive(Debug, Clone, Default)]
+pub struct RunSummary {
+    pub tasks: Vec<TaskSummary>,
+    pub elapsed: Duration,
+    /// Files on disk were downloaded again
+    pub overwrite: bool,
+    /// Timelines were paged to the end with --force
+    pub full_scan: bool,
+}
+
+impl RunSummary {
+    /// Counters summed over all tasks
+    pub fn totals(&self) -> Totals {
+        self.tasks.iter().fold(Totals::default(), |mut acc, t| {
+            acc.fetched += t.totals.fetched;
+            acc.downloaded += t.totals.downloaded;
+            acc.skipped += t.totals.skipped;
+            acc.failed += t.totals.failed;
+            acc.filtered += t.totals.filtered;
+            acc.too_large += t.totals.too_large;
+            acc.bytes = acc.bytes.saturating_add(t.totals.bytes);
+            acc.hook_failures += t.totals.hook_failures;
+            acc.replaced += t.totals.replaced;
+            acc.gone += t.totals.gone;
+            acc.converted += t.totals.converted;
+            acc.unconverted += t.totals.unconverted;
+            acc
+        })
+    }
+
+    /// Whether any task aborted or had failed downloads
+    pub fn has_failures(&self) -> bool {
+        self.tasks
+            .iter()
+            .any(|t| t.error.is_some() || t.totals.failed > 0)
+    }
+
+    /// Render the report as a plain-text table
+    pub fn to_table(&self) -> String {
+        let name_width = self
+            .tasks
+            .iter()
+            .map(|t| t.screen_name.len() + 1)
+            .chain(["TOTAL".len()])
+            .max()
+            .unwrap_or(0);
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{:<name_width$}  {:>10}  {:>7}  {:>8}  {:>9}  {:>6}  {:>10}  {:>12}  {:>10}",
+            "USER",
+            "DOWNLOADED",
+            "SKIPPED",
+            "FILTERED",
+            "TOO LARGE",
+            "FAILED",
+            "BYTES",
+            "SPEED",
+            "ELAPSED"
+        );
+        for task in &self.tasks {
+            let _ = write!(
+                out,
+                "{:<name_width$}  {:>10}  {:>7}  {:>8}  {:>9}  {:>6}  {:>10}  {:>12}  {:>10}",
+                format!("@{}", task.screen_name),
+                task.totals.downloaded,
+                task.totals.skipped,
+                task.totals.filtered,
+                task.totals.too_large,
+                task.totals.failed,
+                HumanBytes(task.totals.bytes).to_string(),
+                speed(task.totals.bytes, task.elapsed),
+                HumanDuration(task.elapsed).to_string(),
+            );
+            if let Some(error) = &task.error {
+                let _ = write!(out, "  aborted: {error}");
+            }
+            out.push('\n');
+        }
+        let totals = self.totals();
+        let _ = writeln!(
+            out,
+            "{:<name_width$}  {:>10}  {:>7}  {:>8}  {:>9}  {:>6}  {:>10}  {:>12}  {:>10}",
+            "TOTAL",
+            totals.downloaded,
+            totals.skipped,
+            totals.filtered,
+            totals.too_large,
+            totals.failed,
+            HumanBytes(totals.bytes).to_string(),
+            speed(totals.bytes, self.elapsed),
+            HumanDuration(self.elapsed).to_string(),
+        );
+        let _ = writeln!(out, "\n{}", self.transfer());
+        if self.overwrite {
+            let _ = writeln!(
+                out,
+                "overwrite mode: {} files on disk replaced",
+                totals.replaced
+            );
+        }
+        if self.full_scan {
+            let _ = writeln!(out, "full scan: every timeline paged to the end");
+        }
+        if totals.gone > 0 {
+            let _ = writeln!(
+                out,
+                "known gone: {} media skipped, --recheck-gone tries them again",
+                totals.gone
+            );
+        }
+        if totals.converted + totals.unconverted > 0 {
+            let _ = writeln!(
+                out,
+                "post-process: {} images converted, {} kept as downloaded",
+                totals.converted, totals.unconverted
+            );
+        }
+        out
+    }
+
+    /// One line on the data moved, e.g.
+    /// `1.20 GiB downloaded in 42m 10s (avg 3.40 MiB/s)`
+    pub fn transfer(&self) -> String {
+        let bytes = self.totals().bytes;
+        format!(
+            "{} downloaded in {} (avg {})",
+            HumanBytes(bytes),
+            compact_duration(self.elapsed),
+            speed(bytes, self.elapsed)
+        )
+    }
+}
+
+/// Average rate of `bytes` over `elapsed` in binary units per second
+pub fn speed(bytes: u64, elapsed: Duration) -> String {
+    let secs = elapsed.as_secs_f64();
+    let per_sec = if secs > 0.0 {
+        (bytes as f64 / secs) as u64
+    } else {
+        0
+    };
+    format!("{}/s", HumanBytes(per_sec))
+}
+
+/// Duration as `1h 02m`, `42m 10s` or `8s`
+pub fn compact_duration(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    match (hours, minutes) {
+        (0, 0) => format!("{seconds}s"),
+        (0, _) => format!("{minutes}m {seconds:02}s"),
+        _ => format!("{hours}h {minutes:02}m"),
+    }
+}
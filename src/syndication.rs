@@ -0,0 +1,254 @@
+//! Public tweets from the syndication API behind embedded tweets.
+//!
+//! `cdn.syndication.twimg.com/tweet-result` needs neither cookies nor
+//! GraphQL query IDs, so it still answers when those are rejected or
+//! rotated. It only shows what a logged-out visitor sees: tweets of
+//! protected accounts are not found and sensitive media are replaced by a
+//! notice. Both are reported as [`Error::SyndicationUnavailable`] instead of
+//! as a tweet without media.
+
+use chrono::{DateTime, FixedOffset};
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{instrument, trace};
+
+use crate::api::TlsOptions;
+use crate::error::{Error, Result};
+use crate::graphql::Media;
+use crate::task::{Engagement, ExtractOptions, MediaItem, media_url};
+
+/// Host of the syndication API
+pub const DEFAULT_SYNDICATION_URL: &str = "https://cdn.syndication.twimg.com";
+
+/// Client for the syndication API, sending no account cookies
+#[derive(Debug, Clone)]
+pub struct Syndication {
+    client: Client,
+    base_url: String,
+}
+
+impl Syndication {
+    /// Client for the default syndication host
+    pub fn new(tls: &TlsOptions) -> Result<Self> {
+        Self::with_base_url(DEFAULT_SYNDICATION_URL, tls)
+    }
+
+    /// Client for a custom host, e.g. a mock server in tests
+    pub fn with_base_url(base_url: &str, tls: &TlsOptions) -> Result<Self> {
+        Ok(Self {
+            client: tls.client_builder()?.build()?,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// Fetch a tweet and its media
+    #[instrument(skip_all, fields(tweet = tweet_id))]
+    pub async fn tweet(&self, tweet_id: &str, options: &ExtractOptions) -> Result<SyndicatedTweet> {
+        let response = self
+            .client
+            .get(format!("{}/tweet-result", self.base_url))
+            .query(&[
+                ("id", tweet_id),
+                ("lang", "en"),
+                ("token", &token(tweet_id)),
+            ])
+            .send()
+            .await?;
+
+        let status = response.status();
+        trace!("tweet-result response status: {}", status);
+        if status == StatusCode::NOT_FOUND {
+            return Err(not_found(tweet_id));
+        }
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(Error::Api { status, body });
+        }
+        // Tweets of protected accounts come back empty rather than as 404
+        if body.trim().is_empty() {
+            return Err(not_found(tweet_id));
+        }
+        parse_tweet_result(tweet_id, &serde_json::from_str(&body)?, options)
+    }
+}
+
+/// A tweet as the syndication API shows it
+#[derive(Debug)]
+pub struct SyndicatedTweet {
+    pub tweet_id: String,
+    /// Author of the tweet, `None` if the response left the user out
+    pub screen_name: Option<String>,
+    pub media: Vec<MediaItem>,
+}
+
+/// The `token` parameter embedded tweets send, which the API checks
+///
+/// Computed like the embed script does:
+/// `((id / 1e15) * Math.PI).toString(36)` without zeros and the point.
+pub fn token(tweet_id: &str) -> String {
+    const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+    let value = tweet_id.parse::<f64>().unwrap_or(0.0) / 1e15 * std::f64::consts::PI;
+    let mut integer = value.trunc() as u64;
+    let mut fraction = value.fract();
+
+    let mut digits = Vec::new();
+    loop {
+        digits.push(DIGITS[(integer % 36) as usize]);
+        integer /= 36;
+        if integer == 0 {
+            break;
+        }
+    }
+    digits.reverse();
+    // A double holds about 10 base-36 digits after the point
+    for _ in 0..10 {
+        if fraction == 0.0 {
+            break;
+        }
+        fraction *= 36.0;
+        let digit = fraction.trunc();
+        digits.push(DIGITS[digit as usize]);
+        fraction -= digit;
+    }
+    digits
+        .into_iter()
+        .filter(|&d| d != b'0')
+        .map(char::from)
+        .collect()
+}
+
+/// The parts of a `tweet-result` response rxd uses
+#[derive(Deserialize)]
+struct TweetResult {
+    #[serde(rename = "__typename", default)]
+    typename: Option<String>,
+    #[serde(default)]
+    id_str: Option<String>,
+    #[serde(default)]
+    text: Option<String>,
+    #[serde(default)]
+    created_at: Option<String>,
+    #[serde(default)]
+    possibly_sensitive: bool,
+    #[serde(default)]
+    user: Option<TweetUser>,
+    #[serde(default)]
+    entities: Option<TweetEntities>,
+    /// Same shape as the GraphQL media entities
+    #[serde(rename = "mediaDetails", default)]
+    media_details: Vec<Media>,
+    #[serde(default)]
+    favorite_count: Option<u64>,
+    #[serde(default)]
+    conversation_count: Option<u64>,
+    #[serde(default)]
+    tombstone: Option<Tombstone>,
+}
+
+#[derive(Deserialize)]
+struct TweetUser {
+    screen_name: String,
+}
+
+#[derive(Deserialize)]
+struct TweetEntities {
+    #[serde(default)]
+    media: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+struct Tombstone {
+    text: TombstoneText,
+}
+
+#[derive(Deserialize)]
+struct TombstoneText {
+    text: String,
+}
+
+/// Map a `tweet-result` response into media items
+///
+/// A tombstone, such as the age-restriction notice shown instead of
+/// sensitive media, an empty response and a sensitive tweet whose media
+/// were left out are errors, not tweets without media.
+pub fn parse_tweet_result(
+    tweet_id: &str,
+    raw: &Value,
+    options: &ExtractOptions,
+) -> Result<SyndicatedTweet> {
+    let result = TweetResult::deserialize(raw)
+        .map_err(|e| Error::Parse(format!("invalid tweet-result response: {e}")))?;
+
+    let unavailable = |reason: String| Error::SyndicationUnavailable {
+        tweet_id: tweet_id.to_string(),
+        reason,
+    };
+    if let Some(tombstone) = result.tombstone {
+        return Err(unavailable(tombstone.text.text));
+    }
+    if result.typename.as_deref() == Some("TweetTombstone") {
+        return Err(unavailable("tombstone without a notice".to_string()));
+    }
+    let Some(id) = result.id_str else {
+        return Err(not_found(tweet_id));
+    };
+    let entity_count = result.entities.as_ref().map_or(0, |e| e.media.len());
+    if result.possibly_sensitive && result.media_details.len() < entity_count {
+        return Err(unavailable(
+            "sensitive media are only shown to accounts".to_string(),
+        ));
+    }
+
+    let timestamp = result
+        .created_at
+        .as_deref()
+        // 2025-03-12T18:47:51.000Z
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .unwrap_or(DateTime::<FixedOffset>::default());
+    let engagement = Engagement {
+        favorite_count: result.favorite_count,
+        reply_count: result.conversation_count,
+        ..Engagement::default()
+    };
+
+    let found: Vec<_> = result
+        .media_details
+        .iter()
+        .filter_map(|media| media_url(media, &id, options))
+        .collect();
+    let multiple = found.len() > 1;
+    let media = found
+        .into_iter()
+        .enumerate()
+        .map(|(i, found)| MediaItem {
+            tweet_id: id.clone(),
+            url: found.url,
+            download_url: found.download_url,
+            media_type: found.media_type,
+            poster_url: found.poster_url,
+            timestamp,
+            full_text: result.text.clone(),
+            index: multiple.then_some(i + 1),
+            is_retweet: false,
+            engagement,
+            pinned: false,
+            duration_ms: found.duration_ms,
+            resolution: found.resolution,
+        })
+        .collect();
+
+    Ok(SyndicatedTweet {
+        tweet_id: id,
+        screen_name: result.user.map(|u| u.screen_name),
+        media,
+    })
+}
+
+fn not_found(tweet_id: &str) -> Error {
+    Error::SyndicationUnavailable {
+        tweet_id: tweet_id.to_string(),
+        reason: "not found, it was deleted or its account is protected".to_string(),
+    }
+}
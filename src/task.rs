@@ -1,261 +1,1299 @@
-use std::path::PathBuf;
-use std::str::FromStr;
+use std::collections::{HashMap, HashSet};
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use chrono::{DateTime, FixedOffset, Local};
 use futures::stream::{FuturesUnordered, StreamExt};
-use reqwest::Client;
-use reqwest::header::{AUTHORIZATION, COOKIE, REFERER, USER_AGENT};
-use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use indicatif::HumanBytes;
+use reqwest::StatusCode;
+use reqwest::header::{CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
 use serde::Deserialize;
 use serde_json::{Value, json};
 use sqlx::SqlitePool;
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
-use tokio::sync::mpsc;
-use tracing::instrument;
-use tracing::{error, info, trace, warn};
-
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, instrument};
+use tracing::{debug, error, info, trace, warn};
+
+use crate::api::Api;
+use crate::archive::{self, ArchivedResponses, ResponseArchive};
+use crate::convert::PostProcess;
 use crate::db;
+use crate::dedupe::{self, Dedupe, Link};
+use crate::embed::{self, ImageMetadata};
+use crate::error::{Error, Result};
+use crate::events::{Event, EventSender, SkipReason, Totals};
+use crate::filename::{self, Fields, Segment, Template, Timezone};
+use crate::filter::{self, TextPattern};
+use crate::graphql::{
+    CardLegacy, Instruction, ItemContent, Media, MediaKind, ModuleItem, NoteTweetResult, Response,
+    Tweet, TweetLegacy, TweetResult, UnifiedCard, UserMediaData,
+};
+use crate::hash::{HashAlgorithm, Hasher};
+use crate::hls;
+use crate::hook::{HookContext, PostDownloadHook};
+use crate::import::gallery_dl;
+use crate::network::{self, LinkMonitor, OutagePolicy};
+use crate::pushback::{self, HostThrottle, PushbackPolicy};
+use crate::query_ids;
+use crate::sidecar::TextSidecars;
+use crate::thumbs::FrameExtractor;
+use crate::verify;
+
+/// Account being downloaded
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct User {
+    pub screen_name: String,
+    /// Display name
+    pub name: String,
+    /// Numeric user ID used by the GraphQL API
+    pub rest_id: String,
+    pub media_count: u64,
+}
 
-const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/114.0.0.0 Safari/537.36";
-const DEFAULT_AUTHORIZATION: &str = "Bearer AAAAAAAAAAAAAAAAAAAAANRILgAAAAAAnNwIzUejRCOuH5E6I8xnZz4puTs%3D1Zv7ttfk8LF81IUq16cHjhLTvJu4FA33AGWWjCpTnA";
+/// A single downloadable media file of a tweet
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct MediaItem {
+    pub tweet_id: String,
+    /// Canonical URL the media is recorded and deduplicated by
+    pub url: String,
+    /// URL the file is fetched from, the chosen variant of a video
+    pub download_url: String,
+    pub media_type: MediaType,
+    /// Poster image of a video, `None` for photos and card players
+    pub poster_url: Option<String>,
+    /// When the tweet was posted
+    pub timestamp: DateTime<FixedOffset>,
+    pub full_text: Option<String>,
+    /// 1-based position among the media of a tweet with several, `None`
+    /// when the tweet has a single media
+    pub index: Option<usize>,
+    /// Whether the tweet is a retweet of someone else's
+    pub is_retweet: bool,
+    /// Counts of the tweet when it was fetched
+    pub engagement: Engagement,
+    /// From the pinned tweet, listed on the first page of every run
+    pub pinned: bool,
+    /// Length of a video, `None` for photos and GIFs
+    pub duration_ms: Option<u64>,
+    /// Width and height of the chosen video variant, `None` for photos
+    pub resolution: Option<(u32, u32)>,
+}
 
-#[derive(Debug, Deserialize)]
-pub struct Config {
-    pub auth_token: String,
-    pub ct0: String,
-    #[serde(default = "default_concurrent_downloads")]
-    pub concurrent_downloads: usize,
-    pub tasks: Vec<TaskConfig>,
+/// How popular a tweet was when it was fetched, `None` where the API left a
+/// count out, e.g. views of older tweets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Engagement {
+    pub favorite_count: Option<u64>,
+    pub retweet_count: Option<u64>,
+    pub reply_count: Option<u64>,
+    pub quote_count: Option<u64>,
+    pub view_count: Option<u64>,
 }
 
-fn default_concurrent_downloads() -> usize {
-    4
+impl Engagement {
+    fn of(tweet: &Tweet, legacy: &TweetLegacy) -> Self {
+        Self {
+            favorite_count: legacy.favorite_count,
+            retweet_count: legacy.retweet_count,
+            reply_count: legacy.reply_count,
+            quote_count: legacy.quote_count,
+            view_count: tweet
+                .views
+                .as_ref()
+                .and_then(|v| v.count.as_deref())
+                .and_then(|count| count.parse().ok()),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct TaskConfig {
-    pub screen_name: String,
-    #[serde(default)]
-    pub save_path: Option<String>,
+/// Account of a replay, from its archived lookup if there is one
+async fn archived_user(
+    api: &Api,
+    screen_name: &str,
+    responses: &ArchivedResponses,
+) -> Result<User> {
+    match responses.lookup() {
+        Some(path) => api.parse_user(screen_name, &archive::read(path).await?),
+        None => Ok(User {
+            screen_name: screen_name.to_string(),
+            name: screen_name.to_string(),
+            rest_id: String::new(),
+            media_count: 0,
+        }),
+    }
 }
 
-#[derive(Debug, Clone)]
-struct User {
-    screen_name: String,
-    name: String,
-    rest_id: String,
-    media_count: u64,
+/// Poster image saved for the video at `video_path`, e.g.
+/// `2024-01-01-abc.thumb.jpg`
+pub fn thumbnail_path(video_path: &Path) -> PathBuf {
+    video_path.with_extension("thumb.jpg")
 }
 
-#[derive(Debug, Clone)]
-struct MediaItem {
-    tweet_id: String,
-    url: String,
-    media_type: MediaType,
-    timestamp: DateTime<FixedOffset>,
-    full_text: Option<String>,
+/// Link to a tweet
+pub fn permalink(screen_name: &str, tweet_id: &str) -> String {
+    format!("https://x.com/{screen_name}/status/{tweet_id}")
 }
 
 #[derive(Debug, Clone)]
-enum MediaType {
+#[non_exhaustive]
+pub enum MediaType {
     Image,
     Video,
 }
 
+impl MediaType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MediaType::Image => "image",
+            MediaType::Video => "video",
+        }
+    }
+}
+
+/// How long in-flight downloads may keep running after cancellation
+const CANCEL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Pages of items fetched ahead of the downloads before fetching waits
+const PAGES_AHEAD: usize = 2;
+
+/// Image sizes from largest to smallest, a size that is not found falls
+/// back to the next one down to `medium`
+const IMAGE_SIZES: [&str; 5] = ["orig", "4096x4096", "large", "medium", "small"];
+const SMALLEST_FALLBACK: usize = 3;
+
+/// Size of images to download, the `name=` parameter of image URLs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageSize {
+    #[default]
+    Orig,
+    Large,
+    Medium,
+    Small,
+}
+
+impl ImageSize {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageSize::Orig => "orig",
+            ImageSize::Large => "large",
+            ImageSize::Medium => "medium",
+            ImageSize::Small => "small",
+        }
+    }
+}
+
 /// Result of a download operation
 enum DownloadResult {
-    Downloaded,
+    Downloaded {
+        bytes: u64,
+        hook_failed: bool,
+        /// Replaced a file on disk in overwrite mode
+        replaced: bool,
+        /// Whether `post_process` converted the file, `None` if not tried
+        converted: Option<bool>,
+    },
     Skipped,
+    TooLarge,
+    /// Gone from the server in an earlier run
+    Gone,
+    Failed,
+}
+
+/// An item on its way to the downloads, with where to report whether it
+/// was downloaded
+struct Queued {
+    item: MediaItem,
+    downloaded: Option<oneshot::Sender<bool>>,
+}
+
+/// What fetching or replaying the timeline got through
+#[derive(Default)]
+struct TimelineFetch {
+    items: usize,
+    filtered: usize,
+    /// Page that could not be fetched and why, later pages were not tried
+    failure: Option<(u32, Error)>,
+}
+
+/// File produced by `download_media`
+struct DownloadedFile {
+    path: PathBuf,
+    hash: String,
+    size: u64,
+    is_new: bool,
+    /// Downloaded over a file on disk in overwrite mode
+    replaced: bool,
+    /// Whether `post_process` converted the file, `None` if not tried
+    converted: Option<bool>,
+    /// Set when the download was swapped for a link to an identical file
+    link: Option<Link>,
+    /// `name=` size actually downloaded for images
+    image_size: Option<&'static str>,
+    /// Headers the file was served with, `None` when it was not requested
+    validators: Option<Validators>,
+    /// URL the file was fetched from, `None` when it was not requested
+    download_url: Option<String>,
+}
+
+/// `ETag` and `Last-Modified` of a response, for conditional requests
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
 }
 
+impl Validators {
+    fn from_response(response: &reqwest::Response) -> Self {
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        Self {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+
+    /// Add `If-None-Match` and `If-Modified-Since` to a request
+    fn apply(&self, mut request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if let Some(etag) = &self.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &self.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        request
+    }
+}
+
+/// Headers of a fetched media file, with its body
+struct Fetched {
+    body: Body,
+    /// Extension of the `Content-Type`
+    served_ext: Option<&'static str>,
+    image_size: Option<&'static str>,
+    validators: Option<Validators>,
+    /// URL the response came from, after redirects
+    download_url: String,
+}
+
+/// Body of a fetched media file
+pub(crate) enum Body {
+    /// Already read, e.g. an HLS video assembled from segments
+    Bytes(Vec<u8>),
+    /// Read while it is written to disk
+    Response {
+        response: reqwest::Response,
+        /// `Content-Length`, if sent
+        total: Option<u64>,
+    },
+}
+
+/// Where a media file is saved
+enum Target {
+    /// Not on disk yet
+    New(PathBuf),
+    /// Already downloaded
+    Existing(PathBuf),
+    /// Already downloaded, to be downloaded again in overwrite mode
+    Replace(PathBuf),
+}
+
+/// Removes a partially written file when dropped before completion
+struct PartFile(Option<PathBuf>);
+
+impl PartFile {
+    /// Keep the file, it was written completely
+    fn keep(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for PartFile {
+    fn drop(&mut self) {
+        if let Some(path) = &self.0 {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Downloads all media of one account
 pub struct Task {
-    client: Client,
+    api: Api,
     user: User,
     save_path: PathBuf,
     concurrent_downloads: usize,
+    image_size: ImageSize,
+    filename_template: Option<Template>,
+    timezone: Timezone,
+    exclude_retweets: bool,
+    text_include: Vec<TextPattern>,
+    text_exclude: Vec<TextPattern>,
+    min_favorites: u64,
+    min_retweets: u64,
+    max_file_size: Option<u64>,
+    extract: ExtractOptions,
+    hash_algorithm: HashAlgorithm,
+    overwrite: bool,
+    /// Retry videos whose URL fails with the one the syndication API gives
+    syndication_rescue: bool,
+    full_scan: bool,
+    /// Stop paging after this many pages in a row without a download
+    stop_after_known_pages: Option<NonZeroU32>,
+    /// Media found gone longer ago than this is tried again
+    recheck_gone_after: Option<Duration>,
+    dedupe: Dedupe,
+    /// Folder of every account's files, for links to other accounts
+    account_folder: AccountFolder,
+    text_sidecars: Option<TextSidecars>,
+    embed_metadata: bool,
+    video_thumbnails: bool,
+    frame_extractor: Option<Arc<FrameExtractor>>,
+    post_process: Option<PostProcess>,
+    /// Set in list-only mode, items go here instead of being downloaded
+    list: Option<ListItem>,
+    archive: Option<ResponseArchive>,
+    /// Archived timeline pages read instead of fetching the timeline
+    replay: Option<Vec<PathBuf>>,
     db: SqlitePool,
+    events: Option<EventSender>,
+    post_download_hook: Option<Arc<PostDownloadHook>>,
+    cancel: CancellationToken,
+    link: LinkMonitor,
+    hosts: HostThrottle,
+    page_retries: PageRetryPolicy,
+    /// Paths handed out in this run → URL they were handed out for
+    claimed: tokio::sync::Mutex<HashMap<PathBuf, String>>,
 }
 
-impl Task {
-    #[instrument(skip_all)]
-    pub async fn new(
-        screen_name: &str,
-        auth_token: &str,
-        ct0: &str,
-        concurrent_downloads: usize,
-        save_path: Option<&str>,
-        db: SqlitePool,
-    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let client = build_client(screen_name, auth_token, ct0)?;
-        let user = fetch_user_info(&client, screen_name).await?;
+/// Folder of the files of an account by screen name
+pub type AccountFolder = Arc<dyn Fn(&str) -> PathBuf + Send + Sync>;
+
+/// Receives every item a task lists instead of downloading it
+pub type ListItem = Arc<dyn Fn(&MediaItem) + Send + Sync>;
+
+/// Concurrency used when the builder is not given one
+const DEFAULT_CONCURRENT_DOWNLOADS: usize = 4;
+
+/// Builds a [`Task`], see [`Task::builder`]
+#[derive(Default)]
+pub struct TaskBuilder {
+    screen_name: Option<String>,
+    credentials: Option<(String, String)>,
+    api: Option<Api>,
+    save_path: Option<PathBuf>,
+    concurrency: Option<usize>,
+    image_size: ImageSize,
+    filename_template: Option<Template>,
+    timezone: Timezone,
+    exclude_retweets: Option<bool>,
+    text_include: Vec<TextPattern>,
+    text_exclude: Vec<TextPattern>,
+    min_favorites: u64,
+    min_retweets: u64,
+    max_file_size: Option<u64>,
+    max_video_bitrate: Option<u64>,
+    hash_algorithm: HashAlgorithm,
+    overwrite: bool,
+    syndication_rescue: bool,
+    full_scan: bool,
+    stop_after_known_pages: Option<NonZeroU32>,
+    recheck_gone_after: Option<Duration>,
+    dedupe: Dedupe,
+    account_folder: Option<AccountFolder>,
+    list: Option<ListItem>,
+    archive: Option<ResponseArchive>,
+    replay: Option<ArchivedResponses>,
+    text_sidecars: Option<TextSidecars>,
+    embed_metadata: bool,
+    video_thumbnails: bool,
+    frame_extractor: Option<Arc<FrameExtractor>>,
+    post_process: Option<PostProcess>,
+    db: Option<SqlitePool>,
+    events: Option<EventSender>,
+    post_download_hook: Option<Arc<PostDownloadHook>>,
+    cancel: Option<CancellationToken>,
+    outage_policy: OutagePolicy,
+    pushback_policy: PushbackPolicy,
+    page_retries: PageRetryPolicy,
+}
 
-        let save_path = if let Some(custom_path) = save_path {
-            PathBuf::from(custom_path)
-        } else {
-            PathBuf::from("downloads").join(&user.screen_name)
+impl TaskBuilder {
+    /// Account to download, required
+    pub fn screen_name(mut self, screen_name: impl Into<String>) -> Self {
+        self.screen_name = Some(screen_name.into());
+        self
+    }
+
+    /// `auth_token` and `ct0` cookies, required unless [`TaskBuilder::api`] is set
+    pub fn credentials(mut self, auth_token: impl Into<String>, ct0: impl Into<String>) -> Self {
+        self.credentials = Some((auth_token.into(), ct0.into()));
+        self
+    }
+
+    /// Use a preconfigured [`Api`] client instead of building one from credentials
+    pub fn api(mut self, api: Api) -> Self {
+        self.api = Some(api);
+        self
+    }
+
+    /// Directory to save files to, `downloads/<screen_name>` if not set
+    pub fn save_path(mut self, save_path: impl Into<PathBuf>) -> Self {
+        self.save_path = Some(save_path.into());
+        self
+    }
+
+    /// Number of parallel downloads, 4 if not set
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    /// Size of images to download, `orig` if not set
+    ///
+    /// Smaller sizes are tried when the chosen one is not found.
+    pub fn image_size(mut self, image_size: ImageSize) -> Self {
+        self.image_size = image_size;
+        self
+    }
+
+    /// Name files with a template instead of the default
+    /// `<date>-<media id>` scheme
+    pub fn filename_template(mut self, template: Template) -> Self {
+        self.filename_template = Some(template);
+        self
+    }
+
+    /// Timezone of dates in file names, local time if not set
+    pub fn timezone(mut self, timezone: Timezone) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Leave out retweets, `true` if not set
+    pub fn exclude_retweets(mut self, exclude_retweets: bool) -> Self {
+        self.exclude_retweets = Some(exclude_retweets);
+        self
+    }
+
+    /// Only download tweets whose text matches one of `patterns`
+    pub fn text_include(mut self, patterns: Vec<TextPattern>) -> Self {
+        self.text_include = patterns;
+        self
+    }
+
+    /// Skip tweets whose text matches one of `patterns`, even if they
+    /// match [`TaskBuilder::text_include`]
+    pub fn text_exclude(mut self, patterns: Vec<TextPattern>) -> Self {
+        self.text_exclude = patterns;
+        self
+    }
+
+    /// Skip tweets with fewer likes when fetched, 0 to keep all
+    pub fn min_favorites(mut self, min_favorites: u64) -> Self {
+        self.min_favorites = min_favorites;
+        self
+    }
+
+    /// Skip tweets with fewer retweets when fetched, 0 to keep all
+    pub fn min_retweets(mut self, min_retweets: u64) -> Self {
+        self.min_retweets = min_retweets;
+        self
+    }
+
+    /// Skip files larger than `bytes`, they are counted as too large
+    pub fn max_file_size(mut self, bytes: u64) -> Self {
+        self.max_file_size = Some(bytes);
+        self
+    }
+
+    /// Pick the best video variant at or below `bits_per_second`, or the
+    /// smallest one if none is
+    pub fn max_video_bitrate(mut self, bits_per_second: u64) -> Self {
+        self.max_video_bitrate = Some(bits_per_second);
+        self
+    }
+
+    /// Hash new downloads with `algorithm`, recorded hashes keep theirs
+    pub fn hash_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = algorithm;
+        self
+    }
+
+    /// Download every item again, replacing files on disk and their
+    /// recorded hashes
+    pub fn overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// When a video of a public tweet fails to download, look the tweet up
+    /// in the syndication API and retry with the variant it offers
+    pub fn syndication_rescue(mut self, enabled: bool) -> Self {
+        self.syndication_rescue = enabled;
+        self
+    }
+
+    /// Page the timeline to the end, ignoring anything that would stop
+    /// early; files on disk are still skipped
+    pub fn full_scan(mut self, full_scan: bool) -> Self {
+        self.full_scan = full_scan;
+        self
+    }
+
+    /// Stop paging once `pages` pages in a row downloaded nothing new
+    ///
+    /// Only downloaded files count as new, not skipped or failed ones, and
+    /// the pinned tweet is ignored. Each page's downloads finish before the
+    /// next page is requested. Ignored in a full scan.
+    pub fn stop_after_known_pages(mut self, pages: NonZeroU32) -> Self {
+        self.stop_after_known_pages = Some(pages);
+        self
+    }
+
+    /// Try media the server answered 404 or 410 for in an earlier run again
+    /// once that was at least `age` ago, [`Duration::ZERO`] tries all of it
+    ///
+    /// Such media is skipped and counted as gone otherwise.
+    pub fn recheck_gone_after(mut self, age: Duration) -> Self {
+        self.recheck_gone_after = Some(age);
+        self
+    }
+
+    /// Link new downloads to identical recorded files as `dedupe` says
+    ///
+    /// `account_folder` gives the folder of other accounts' files,
+    /// `downloads/<screen_name>` if not set.
+    pub fn dedupe(
+        mut self,
+        dedupe: Dedupe,
+        account_folder: impl Fn(&str) -> PathBuf + Send + Sync + 'static,
+    ) -> Self {
+        self.dedupe = dedupe;
+        self.account_folder = Some(Arc::new(account_folder));
+        self
+    }
+
+    /// Write the tweet text of every downloaded file to a caption file
+    pub fn text_sidecars(mut self, sidecars: TextSidecars) -> Self {
+        self.text_sidecars = Some(sidecars);
+        self
+    }
+
+    /// Embed the tweet's date, author, text and link in downloaded JPEGs
+    /// and PNGs, see [`embed`](crate::embed)
+    pub fn embed_metadata(mut self, embed_metadata: bool) -> Self {
+        self.embed_metadata = embed_metadata;
+        self
+    }
+
+    /// Save the poster image of every video next to it as
+    /// `<name>.thumb.jpg`
+    pub fn video_thumbnails(mut self, video_thumbnails: bool) -> Self {
+        self.video_thumbnails = video_thumbnails;
+        self
+    }
+
+    /// Save a frame of every video as its thumbnail instead of the poster,
+    /// which is still saved when that fails
+    pub fn frame_extractor(mut self, frame_extractor: Arc<FrameExtractor>) -> Self {
+        self.frame_extractor = Some(frame_extractor);
+        self
+    }
+
+    /// Convert matching images after they are downloaded, keeping the
+    /// original when that fails
+    pub fn post_process(mut self, post_process: PostProcess) -> Self {
+        self.post_process = Some(post_process);
+        self
+    }
+
+    /// Hand every item that passes the filters to `list` instead of
+    /// downloading it, writing no files and nothing to the database
+    pub fn list_only(mut self, list: impl Fn(&MediaItem) + Send + Sync + 'static) -> Self {
+        self.list = Some(Arc::new(list));
+        self
+    }
+
+    /// Keep every raw API response in `<save_path>/_raw`, see
+    /// [`archive`](crate::archive)
+    pub fn archive_responses(mut self, archive: ResponseArchive) -> Self {
+        self.archive = Some(archive);
+        self
+    }
+
+    /// Read the account and its timeline from archived `responses`
+    /// instead of the API, only media are downloaded
+    pub fn replay(mut self, responses: ArchivedResponses) -> Self {
+        self.replay = Some(responses);
+        self
+    }
+
+    /// Database recording tweets and files, required
+    pub fn db(mut self, db: SqlitePool) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    /// Send progress events to a channel
+    ///
+    /// Without a channel no events are built at all.
+    pub fn events(mut self, events: EventSender) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Run a command after every new download
+    pub fn post_download_hook(mut self, hook: Arc<PostDownloadHook>) -> Self {
+        self.post_download_hook = Some(hook);
+        self
+    }
+
+    /// Stop fetching and downloading once `token` is cancelled
+    ///
+    /// `execute` then returns [`Error::Cancelled`] with the totals reached so far.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// How long to wait for a lost network connection to come back
+    ///
+    /// `execute` returns [`Error::NetworkDown`] once an outage lasts longer.
+    pub fn outage_policy(mut self, policy: OutagePolicy) -> Self {
+        self.outage_policy = policy;
+        self
+    }
+
+    /// How downloads answer a media host pushing back with `Retry-After`
+    pub fn pushback_policy(mut self, policy: PushbackPolicy) -> Self {
+        self.pushback_policy = policy;
+        self
+    }
+
+    /// How timeline pages answered with a server error are retried
+    pub fn page_retries(mut self, policy: PageRetryPolicy) -> Self {
+        self.page_retries = policy;
+        self
+    }
+
+    /// Look up the account and prepare its download directory
+    #[instrument(skip_all, fields(user = self.screen_name.as_deref()))]
+    pub async fn build(self) -> Result<Task> {
+        let screen_name = self
+            .screen_name
+            .ok_or_else(|| Error::Config("screen_name is required".to_string()))?;
+        let db = self
+            .db
+            .ok_or_else(|| Error::Config("db is required".to_string()))?;
+        let api = match (self.api, self.credentials) {
+            (Some(api), _) => api,
+            (None, Some((auth_token, ct0))) => Api::new(&auth_token, &ct0)?,
+            (None, None) => {
+                return Err(Error::Config(
+                    "credentials or an api client are required".to_string(),
+                ));
+            }
+        };
+
+        let save_path = self
+            .save_path
+            .unwrap_or_else(|| PathBuf::from("downloads").join(&screen_name));
+        // Listing writes nothing, raw responses included, and replays read
+        // the archive
+        let archive = self
+            .archive
+            .filter(|_| self.list.is_none() && self.replay.is_none());
+
+        let user = match (&self.replay, &archive) {
+            (Some(responses), _) => archived_user(&api, &screen_name, responses).await?,
+            (None, Some(archive)) => {
+                let body = api.user_by_screen_name_body(&screen_name).await?;
+                if let Err(e) = archive
+                    .write(&save_path, query_ids::USER_BY_SCREEN_NAME, None, &body)
+                    .await
+                {
+                    warn!("failed to archive the account lookup: {}", e);
+                }
+                api.parse_user(&screen_name, &body)?
+            }
+            (None, None) => api.user_by_screen_name(&screen_name).await?,
         };
-        fs::create_dir_all(&save_path).await?;
+
+        if self.list.is_none() {
+            fs::create_dir_all(&save_path).await?;
+        }
+        if let Some(archive) = &archive
+            && let Err(e) = archive.prune(&save_path).await
+        {
+            warn!("failed to prune archived responses: {}", e);
+        }
 
         info!(
             "task created for @{} ({}) - {} media tweets",
             user.screen_name, user.name, user.media_count
         );
 
-        Ok(Self {
-            client,
+        let concurrent_downloads = self.concurrency.unwrap_or(DEFAULT_CONCURRENT_DOWNLOADS);
+        Ok(Task {
+            api,
             user,
             save_path,
             concurrent_downloads,
+            image_size: self.image_size,
+            filename_template: self.filename_template,
+            timezone: self.timezone,
+            exclude_retweets: self.exclude_retweets.unwrap_or(true),
+            text_include: self.text_include,
+            text_exclude: self.text_exclude,
+            min_favorites: self.min_favorites,
+            min_retweets: self.min_retweets,
+            max_file_size: self.max_file_size,
+            extract: ExtractOptions::default().max_video_bitrate(self.max_video_bitrate),
+            hash_algorithm: self.hash_algorithm,
+            overwrite: self.overwrite,
+            syndication_rescue: self.syndication_rescue,
+            full_scan: self.full_scan,
+            // Listed items are never downloaded, every page would count
+            stop_after_known_pages: self
+                .stop_after_known_pages
+                .filter(|_| !self.full_scan && self.list.is_none()),
+            recheck_gone_after: self.recheck_gone_after,
+            dedupe: self.dedupe,
+            account_folder: self.account_folder.unwrap_or_else(|| {
+                Arc::new(|screen_name: &str| PathBuf::from("downloads").join(screen_name))
+            }),
+            text_sidecars: self.text_sidecars,
+            embed_metadata: self.embed_metadata,
+            video_thumbnails: self.video_thumbnails,
+            frame_extractor: self.frame_extractor,
+            post_process: self.post_process,
+            list: self.list,
+            archive,
+            replay: self.replay.map(|responses| responses.pages().to_vec()),
             db,
+            events: self.events,
+            post_download_hook: self.post_download_hook,
+            cancel: self.cancel.unwrap_or_default(),
+            link: LinkMonitor::new(self.outage_policy),
+            hosts: HostThrottle::new(self.pushback_policy, concurrent_downloads),
+            page_retries: self.page_retries,
+            claimed: Default::default(),
         })
     }
+}
 
-    #[instrument(skip_all)]
-    pub async fn execute(self: Arc<Self>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        info!("starting parallel fetch and download");
+impl Task {
+    /// Start building a task
+    pub fn builder() -> TaskBuilder {
+        TaskBuilder::default()
+    }
 
-        // Create a channel for media items
-        let (tx, mut rx) = mpsc::channel::<MediaItem>(1000);
+    /// Look up the account and prepare its download directory
+    ///
+    /// Files are saved to `save_path`, or `downloads/<screen_name>` if not given.
+    /// Shorthand for [`Task::builder`] with the common options.
+    pub async fn new(
+        screen_name: &str,
+        auth_token: &str,
+        ct0: &str,
+        concurrent_downloads: usize,
+        save_path: Option<&str>,
+        db: SqlitePool,
+    ) -> Result<Self> {
+        let mut builder = Self::builder()
+            .screen_name(screen_name)
+            .credentials(auth_token, ct0)
+            .concurrency(concurrent_downloads)
+            .db(db);
+        if let Some(save_path) = save_path {
+            builder = builder.save_path(save_path);
+        }
+        builder.build().await
+    }
+
+    /// Account this task downloads
+    pub fn user(&self) -> &User {
+        &self.user
+    }
+
+    /// Fetch the media timeline and download everything not already on disk
+    ///
+    /// A page that cannot be fetched fails the task with
+    /// [`Error::FetchFailed`] once the items of the pages before it are
+    /// downloaded.
+    #[instrument(skip_all, fields(user = %self.user.screen_name))]
+    pub async fn execute(self: Arc<Self>) -> Result<Totals> {
+        if self.full_scan {
+            info!("starting full scan, paging to the end of the timeline");
+        } else {
+            info!("starting parallel fetch and download");
+        }
+        match &self.replay {
+            Some(pages) => info!("replaying {} archived pages", pages.len()),
+            None => info!("requesting {} tweets per page", self.api.page_size()),
+        }
+        self.emit(|| Event::TaskStarted {
+            screen_name: self.user.screen_name.clone(),
+        });
+
+        // Nothing to page through, unless a full scan doubts the count
+        if self.user.media_count == 0 && !self.full_scan && self.replay.is_none() {
+            info!(
+                "no media found for @{} (media_count = 0)",
+                self.user.screen_name
+            );
+            let totals = Totals::default();
+            self.emit(|| Event::TaskFinished {
+                screen_name: self.user.screen_name.clone(),
+                totals,
+            });
+            return Ok(totals);
+        }
+
+        // Pages are fetched while earlier ones download, bounded so fetching
+        // cannot run far ahead
+        let backlog = (self.api.page_size() as usize * PAGES_AHEAD).max(self.concurrent_downloads);
+        let (tx, mut rx) = mpsc::channel::<Queued>(backlog);
 
         // Spawn a task to fetch media items and save to database
-        let fetch_task = {
-            let self_clone = Arc::clone(&self);
-            tokio::spawn(async move {
-                let mut cursor: Option<String> = None;
-                let mut page = 0u32;
-                let mut total_items = 0usize;
-
-                loop {
-                    page += 1;
-                    info!("fetching page {}", page);
-
-                    match self_clone.fetch_user_media(cursor.as_deref()).await {
-                        Ok((media_items, next_cursor)) => {
-                            if media_items.is_empty() {
-                                info!("no more media items found");
+        let self_clone = Arc::clone(&self);
+        let fetch_task = match self.replay.clone() {
+            Some(pages) => tokio::spawn(self_clone.replay_pages(pages, tx).in_current_span()),
+            None => tokio::spawn(
+                async move {
+                    let mut cursor: Option<String> = None;
+                    let mut page = 0u32;
+                    let mut total_items = 0usize;
+                    let mut filtered = 0usize;
+                    let mut network_attempts = 0;
+                    let mut server_retries = 0;
+                    // URLs of the pinned tweet, also listed again where it was posted
+                    let mut pinned = HashSet::new();
+                    let stop_after = self_clone.stop_after_known_pages;
+                    let mut known_pages = 0;
+                    let mut failure = None;
+
+                    loop {
+                        if self_clone.cancel.is_cancelled() {
+                            info!("cancelled, stopping fetch");
+                            break;
+                        }
+                        page += 1;
+                        info!("fetching page {}", page);
+
+                        let outages = self_clone.link.outages();
+                        let response = tokio::select! {
+                            response = self_clone.fetch_page(cursor.as_deref(), page) => response,
+                            _ = self_clone.cancel.cancelled() => {
+                                info!("cancelled, stopping fetch");
                                 break;
                             }
+                        };
+                        if !matches!(&response, Err(e) if network::is_network_error(e)) {
+                            self_clone.link.succeeded();
+                        }
+                        match response {
+                            Ok(MediaPage {
+                                items: media_items,
+                                next_cursor,
+                                tombstones,
+                            }) => {
+                                network_attempts = 0;
+                                server_retries = 0;
+                                if media_items.is_empty() && tombstones.is_empty() {
+                                    match total_items {
+                                        0 => info!(
+                                            "no media found for @{} (media_count = {})",
+                                            self_clone.user.screen_name,
+                                            self_clone.user.media_count
+                                        ),
+                                        _ => info!("no more media items found"),
+                                    }
+                                    break;
+                                }
+                                if self_clone.list.is_none() {
+                                    self_clone.record_tombstones(page, &tombstones).await;
+                                }
 
-                            info!("found {} media items on page {}", media_items.len(), page);
-                            total_items += media_items.len();
-
-                            // Save to database and send media items to the channel
-                            for item in media_items {
-                                let local_dt = item.timestamp.with_timezone(&Local);
-                                let tweet_time = local_dt.format("%Y-%m-%d %H:%M:%S").to_string();
-
-                                // Upsert tweet record
-                                if let Err(e) = db::upsert_tweet(
-                                    &self_clone.db,
-                                    &item.tweet_id,
-                                    &self_clone.user.screen_name,
-                                    &tweet_time,
-                                    item.full_text.as_deref(),
-                                )
-                                .await
-                                {
-                                    warn!("failed to save tweet {}: {}", item.tweet_id, e);
+                                info!("found {} media items on page {}", media_items.len(), page);
+                                total_items += media_items.len();
+                                self_clone.emit(|| Event::PageFetched {
+                                    screen_name: self_clone.user.screen_name.clone(),
+                                    page,
+                                    count: media_items.len(),
+                                    cursor: next_cursor.clone(),
+                                });
+
+                                // Save to database and send media items to the channel
+                                let mut outcomes = Vec::new();
+                                for item in media_items {
+                                    if item.pinned {
+                                        pinned.insert(item.url.clone());
+                                    } else if pinned.contains(&item.url) {
+                                        trace!("pinned tweet listed again: {}", item.url);
+                                        continue;
+                                    }
+                                    if self_clone.is_filtered(&item) {
+                                        trace!("filtered: {}", item.url);
+                                        filtered += 1;
+                                        self_clone.emit_skipped(&item, SkipReason::Filtered);
+                                        continue;
+                                    }
+                                    if let Some(list) = &self_clone.list {
+                                        list(&item);
+                                        continue;
+                                    }
+
+                                    self_clone.record_item(&item).await;
+
+                                    // The pinned tweet is on the first page of every run
+                                    let downloaded = if stop_after.is_some() && !item.pinned {
+                                        let (report, outcome) = oneshot::channel();
+                                        outcomes.push(outcome);
+                                        Some(report)
+                                    } else {
+                                        None
+                                    };
+                                    if tx.send(Queued { item, downloaded }).await.is_err() {
+                                        warn!("receiver dropped, stopping fetch");
+                                        return TimelineFetch {
+                                            items: total_items,
+                                            filtered,
+                                            failure: None,
+                                        };
+                                    }
                                 }
 
-                                // Upsert media record (filename will be updated after download)
-                                if let Err(e) = db::upsert_media(
-                                    &self_clone.db,
-                                    &item.tweet_id,
-                                    &item.url,
-                                    None,
-                                )
-                                .await
-                                {
-                                    warn!("failed to save media {}: {}", item.url, e);
+                                if let Some(stop_after) = stop_after {
+                                    let mut new = 0;
+                                    for outcome in outcomes {
+                                        tokio::select! {
+                                            downloaded = outcome => new += usize::from(downloaded.unwrap_or(false)),
+                                            _ = self_clone.cancel.cancelled() => break,
+                                        }
+                                    }
+                                    match new {
+                                        0 => known_pages += 1,
+                                        _ => known_pages = 0,
+                                    }
+                                    if known_pages >= stop_after.get() && next_cursor.is_some() {
+                                        info!(
+                                            "stopping after {} pages in a row without new downloads (stop_after_known_pages), not at the end of the timeline",
+                                            known_pages
+                                        );
+                                        break;
+                                    }
                                 }
 
-                                if tx.send(item).await.is_err() {
-                                    warn!("receiver dropped, stopping fetch");
-                                    return total_items;
+                                match next_cursor {
+                                    Some(c) => cursor = Some(c),
+                                    None => {
+                                        info!("no more pages");
+                                        break;
+                                    }
                                 }
                             }
-
-                            match next_cursor {
-                                Some(c) => cursor = Some(c),
-                                None => {
-                                    info!("no more pages");
+                            Err(e) if network::is_network_error(&e) => {
+                                warn!("network error fetching page {}: {}", page, e);
+                                if !self_clone
+                                    .link
+                                    .recover(
+                                        &self_clone.api,
+                                        &self_clone.cancel,
+                                        outages,
+                                        &mut network_attempts,
+                                    )
+                                    .await
+                                {
+                                    if !self_clone.cancel.is_cancelled() {
+                                        error!("failed to fetch media: {}", e);
+                                    }
                                     break;
                                 }
+                                // Same cursor again
+                                info!("retrying page {}", page);
+                                page -= 1;
+                            }
+                            // Rate limits were already waited out by the API client
+                            Err(Error::Api { status, .. })
+                                if status.is_server_error()
+                                    && server_retries < self_clone.page_retries.max_retries =>
+                            {
+                                let wait =
+                                    self_clone.page_retries.backoff * 2u32.pow(server_retries);
+                                server_retries += 1;
+                                warn!(
+                                    "page {} failed with {}, retrying in {}ms ({}/{})",
+                                    page,
+                                    status,
+                                    wait.as_millis(),
+                                    server_retries,
+                                    self_clone.page_retries.max_retries
+                                );
+                                tokio::select! {
+                                    _ = tokio::time::sleep(wait) => {}
+                                    _ = self_clone.cancel.cancelled() => {
+                                        info!("cancelled, stopping fetch");
+                                        break;
+                                    }
+                                }
+                                // Same cursor again
+                                page -= 1;
+                            }
+                            Err(e) => {
+                                error!("failed to fetch media: {}", e);
+                                failure = Some((page, e));
+                                break;
                             }
-                        }
-                        Err(e) => {
-                            error!("failed to fetch media: {}", e);
-                            break;
                         }
                     }
-                }
 
-                info!("fetch complete: {} total media items", total_items);
-                total_items
-            })
+                    info!(
+                        "fetch complete: {} total media items, {} filtered",
+                        total_items, filtered
+                    );
+                    TimelineFetch {
+                        items: total_items,
+                        filtered,
+                        failure,
+                    }
+                }
+                .in_current_span(),
+            ),
         };
 
         // Download media items as they arrive using FuturesUnordered for true concurrency
-        let mut total_downloaded: usize = 0;
-        let mut total_skipped: usize = 0;
+        let mut totals = Totals::default();
         let mut downloads = FuturesUnordered::new();
         let mut receiving = true;
+        let mut cancelled = false;
 
         loop {
             tokio::select! {
+                biased;
+
+                // Stop taking new items, in-flight downloads are drained below
+                _ = self.cancel.cancelled(), if receiving || !downloads.is_empty() => {
+                    cancelled = true;
+                    rx.close();
+                    break;
+                }
                 // Receive new media items from the channel
                 item = rx.recv(), if receiving && downloads.len() < self.concurrent_downloads => {
                     match item {
-                        Some(item) => {
+                        Some(Queued { item, downloaded }) => {
                             let self_clone = Arc::clone(&self);
-                            let local_dt = item.timestamp.with_timezone(&Local);
-                            let date_str = local_dt.format("%Y-%m-%d").to_string();
 
-                            downloads.push(async move {
+                            let download = async move {
+                                // Overwrite mode downloads whatever is recorded
+                                let placeholder = gallery_dl::placeholder_url(&item.tweet_id, item.index.unwrap_or(1));
+                                match db::is_imported_elsewhere(&self_clone.db, &item.url, &placeholder).await {
+                                    Ok(true) if !self_clone.overwrite => {
+                                        trace!("downloaded by another tool, skipping: {}", item.url);
+                                        self_clone.emit_skipped(&item, SkipReason::Imported);
+                                        return DownloadResult::Skipped;
+                                    }
+                                    Ok(_) => {}
+                                    Err(e) => {
+                                        warn!("failed to check imports for {}: {}", item.url, e);
+                                    }
+                                }
+
                                 // Check if file is already verified in database
                                 match db::verify_file(&self_clone.db, &item.url, &self_clone.save_path).await {
-                                    Ok(true) => {
+                                    Ok(true) if !self_clone.overwrite => {
                                         trace!("file verified, skipping: {}", item.url);
-                                        return Ok(DownloadResult::Skipped);
+                                        self_clone.save_recorded_thumbnail(&item).await;
+                                        self_clone.emit_skipped(&item, SkipReason::Verified);
+                                        return DownloadResult::Skipped;
                                     }
-                                    Ok(false) => {}
+                                    Ok(_) => {}
                                     Err(e) => {
                                         warn!("failed to verify file {}: {}", item.url, e);
                                     }
                                 }
 
-                                match self_clone.download_media(&item, &date_str).await {
-                                    Ok((path, hash, is_new)) => {
+                                match db::is_gone(&self_clone.db, &item.url, self_clone.recheck_gone_after).await {
+                                    Ok(true) => {
+                                        trace!("known gone, skipping: {}", item.url);
+                                        self_clone.emit_skipped(&item, SkipReason::Gone);
+                                        return DownloadResult::Gone;
+                                    }
+                                    Ok(false) => {}
+                                    Err(e) => {
+                                        warn!("failed to check whether {} is gone: {}", item.url, e);
+                                    }
+                                }
+
+                                // Retried once the network is back rather than failed
+                                let mut network_attempts = 0;
+                                let mut pushbacks = 0;
+                                let host = pushback::host(&item.download_url);
+                                let result = loop {
+                                    let outages = self_clone.link.outages();
+                                    let permit = self_clone.hosts.acquire(&host).await;
+                                    let result = self_clone.download_or_rescue(&item).await;
+                                    drop(permit);
+                                    match &result {
+                                        Err(Error::PushedBack { status, retry_after })
+                                            if pushbacks < self_clone.hosts.policy().max_retries =>
+                                        {
+                                            pushbacks += 1;
+                                            self_clone.link.succeeded();
+                                            self_clone.hosts.pushed_back(&host);
+                                            let wait = (*retry_after).min(self_clone.hosts.policy().max_wait);
+                                            info!(
+                                                "{} answered {} for {}, retrying in {}s ({}/{})",
+                                                host, status, item.url, wait.as_secs(), pushbacks, self_clone.hosts.policy().max_retries
+                                            );
+                                            tokio::select! {
+                                                _ = self_clone.cancel.cancelled() => break result,
+                                                _ = tokio::time::sleep(wait) => {}
+                                            }
+                                        }
+                                        Err(e) if network::is_network_error(e) => {
+                                            debug!("network error downloading {}: {}", item.url, e);
+                                            if !self_clone.link.recover(&self_clone.api, &self_clone.cancel, outages, &mut network_attempts).await {
+                                                break result;
+                                            }
+                                            debug!("retrying {}", item.url);
+                                        }
+                                        _ => {
+                                            self_clone.link.succeeded();
+                                            break result;
+                                        }
+                                    }
+                                };
+                                match result {
+                                    Ok(file) => {
                                         // Update database with filename and hash
-                                        let filename = path.file_name()
+                                        let filename = file.path.file_name()
                                             .and_then(|n| n.to_str())
                                             .unwrap_or("unknown");
                                         if let Err(e) = db::upsert_media(
                                             &self_clone.db,
                                             &item.tweet_id,
                                             &item.url,
+                                            file.download_url.as_deref(),
                                             Some(filename),
                                         ).await {
                                             warn!("failed to update media filename: {}", e);
                                         }
-                                        if let Err(e) = db::update_hash(&self_clone.db, &item.url, &hash).await {
+                                        if let Err(e) = db::update_hash(&self_clone.db, &item.url, &file.hash, self_clone.hash_algorithm).await {
                                             warn!("failed to update hash: {}", e);
                                         }
-                                        if is_new {
-                                            info!("downloaded: {}", path.display());
-                                            Ok(DownloadResult::Downloaded)
+                                        if let Err(e) = db::update_file_size(&self_clone.db, &item.url, file.size).await {
+                                            warn!("failed to update file size: {}", e);
+                                        }
+                                        if let Err(e) = db::clear_gone(&self_clone.db, &item.url).await {
+                                            warn!("failed to clear gone mark: {}", e);
+                                        }
+                                        if let Some(image_size) = file.image_size
+                                            && let Err(e) = db::update_image_size(&self_clone.db, &item.url, image_size).await
+                                        {
+                                            warn!("failed to update image size: {}", e);
+                                        }
+                                        if file.is_new
+                                            && let Err(e) = db::update_link(&self_clone.db, &item.url, file.link.as_ref()).await
+                                        {
+                                            warn!("failed to record link: {}", e);
+                                        }
+                                        if let Some(validators) = &file.validators
+                                            && let Err(e) = db::update_validators(
+                                                &self_clone.db,
+                                                &item.url,
+                                                validators.etag.as_deref(),
+                                                validators.last_modified.as_deref(),
+                                            ).await
+                                        {
+                                            warn!("failed to update validators: {}", e);
+                                        }
+                                        self_clone.save_thumbnail(&item, &file.path).await;
+                                        if file.is_new {
+                                            if file.replaced {
+                                                info!("replaced: {}", file.path.display());
+                                            } else {
+                                                info!("downloaded: {}", file.path.display());
+                                            }
+                                            self_clone.emit(|| Event::ItemDownloaded {
+                                                screen_name: self_clone.user.screen_name.clone(),
+                                                tweet_id: item.tweet_id.clone(),
+                                                url: item.url.clone(),
+                                                path: file.path.clone(),
+                                                bytes: file.size,
+                                            });
+                                            let hook_failed = match self_clone.run_post_download_hook(&item, &file.path).await {
+                                                Ok(()) => false,
+                                                Err(e) => {
+                                                    warn!("post-download hook failed for {}: {}", file.path.display(), e);
+                                                    true
+                                                }
+                                            };
+                                            DownloadResult::Downloaded { bytes: file.size, hook_failed, replaced: file.replaced, converted: file.converted }
                                         } else {
-                                            trace!("file exists, skipped: {}", path.display());
-                                            Ok(DownloadResult::Skipped)
+                                            trace!("file exists, skipped: {}", file.path.display());
+                                            self_clone.emit_skipped(&item, SkipReason::Exists);
+                                            DownloadResult::Skipped
                                         }
                                     }
+                                    Err(Error::NotModified) => {
+                                        trace!("not modified, skipped: {}", item.url);
+                                        self_clone.emit_skipped(&item, SkipReason::NotModified);
+                                        DownloadResult::Skipped
+                                    }
+                                    Err(Error::TooLarge { size, limit }) => {
+                                        info!(
+                                            "skipping {}: {} exceeds max_file_size {}",
+                                            item.url,
+                                            HumanBytes(size),
+                                            HumanBytes(limit)
+                                        );
+                                        self_clone.emit_skipped(&item, SkipReason::TooLarge);
+                                        DownloadResult::TooLarge
+                                    }
                                     Err(e) => {
-                                        warn!("failed to download {}: {}", item.url, e);
-                                        Err(e)
+                                        if let Error::Download(status) = e
+                                            && verify::is_gone(status)
+                                            && let Err(e) = db::mark_gone(&self_clone.db, &item.url).await
+                                        {
+                                            warn!("failed to record {} as gone: {}", item.url, e);
+                                        }
+                                        warn!(
+                                            "failed to download {} of tweet {}: {}",
+                                            item.url, item.tweet_id, e
+                                        );
+                                        self_clone.emit(|| Event::ItemFailed {
+                                            screen_name: self_clone.user.screen_name.clone(),
+                                            tweet_id: item.tweet_id.clone(),
+                                            url: item.url.clone(),
+                                            error: e.to_string(),
+                                        });
+                                        DownloadResult::Failed
                                     }
                                 }
+                            };
+                            downloads.push(async move {
+                                let result = download.await;
+                                if let Some(downloaded) = downloaded {
+                                    let _ = downloaded.send(matches!(result, DownloadResult::Downloaded { .. }));
+                                }
+                                result
                             });
                         }
                         None => {
@@ -266,10 +1304,8 @@ impl Task {
                 }
                 // Process completed downloads
                 result = downloads.next(), if !downloads.is_empty() => {
-                    match result {
-                        Some(Ok(DownloadResult::Downloaded)) => total_downloaded += 1,
-                        Some(Ok(DownloadResult::Skipped)) => total_skipped += 1,
-                        _ => {}
+                    if let Some(result) = result {
+                        record(&mut totals, result);
                     }
                 }
                 // Exit when channel is closed and all downloads are complete
@@ -277,320 +1313,1288 @@ impl Task {
             }
         }
 
+        if cancelled {
+            info!(
+                "cancelled, waiting up to {}s for {} in-flight downloads",
+                CANCEL_GRACE_PERIOD.as_secs(),
+                downloads.len()
+            );
+            let drain = async {
+                while let Some(result) = downloads.next().await {
+                    record(&mut totals, result);
+                }
+            };
+            if tokio::time::timeout(CANCEL_GRACE_PERIOD, drain)
+                .await
+                .is_err()
+            {
+                // Dropping the futures aborts them, their part files are removed
+                warn!("aborting {} downloads still in flight", downloads.len());
+            }
+            drop(downloads);
+        }
+
         // Wait for fetch task to complete
-        let total_fetched = fetch_task.await.unwrap_or(0);
+        let fetched = fetch_task.await.unwrap_or_default();
+        (totals.fetched, totals.filtered) = (fetched.items, fetched.filtered);
 
         info!(
-            "complete for @{}: {} downloaded, {} skipped, {} total fetched",
-            self.user.screen_name, total_downloaded, total_skipped, total_fetched
+            "complete for @{}: {} downloaded, {} skipped, {} failed, {} total fetched",
+            self.user.screen_name, totals.downloaded, totals.skipped, totals.failed, totals.fetched
         );
-        Ok(())
+        self.emit(|| Event::TaskFinished {
+            screen_name: self.user.screen_name.clone(),
+            totals,
+        });
+        if cancelled {
+            return Err(Error::Cancelled { totals });
+        }
+        if self.link.gave_up() {
+            return Err(Error::NetworkDown {
+                outage: self.link.max_outage(),
+                totals,
+            });
+        }
+        if let Some((page, e)) = fetched.failure {
+            return Err(Error::FetchFailed {
+                page,
+                source: Box::new(e),
+                totals,
+            });
+        }
+        Ok(totals)
     }
 
-    #[instrument(skip_all)]
-    async fn fetch_user_media(
-        &self,
-        cursor: Option<&str>,
-    ) -> Result<(Vec<MediaItem>, Option<String>), Box<dyn std::error::Error + Send + Sync>> {
-        let variables = if let Some(c) = cursor {
-            json!({
-                "userId": self.user.rest_id,
-                "count": 100,
-                "cursor": c,
-                "includePromotedContent": false,
-                "withClientEventToken": false,
-                "withBirdwatchNotes": false,
-                "withVoice": true,
-                "withV2Timeline": true
-            })
-        } else {
-            json!({
-                "userId": self.user.rest_id,
-                "count": 100,
-                "includePromotedContent": false,
-                "withClientEventToken": false,
-                "withBirdwatchNotes": false,
-                "withVoice": true,
-                "withV2Timeline": true
-            })
-        };
+    /// Whether the settings of this task leave out an item
+    fn is_filtered(&self, item: &MediaItem) -> bool {
+        let text = item.full_text.as_deref().unwrap_or_default();
+        (self.exclude_retweets && item.is_retweet)
+            || (!self.text_include.is_empty() && !filter::matches_any(&self.text_include, text))
+            || filter::matches_any(&self.text_exclude, text)
+            // Tweets without counts are kept, there is nothing to compare
+            || item.engagement.favorite_count.is_some_and(|n| n < self.min_favorites)
+            || item.engagement.retweet_count.is_some_and(|n| n < self.min_retweets)
+    }
 
-        let features = json!({
-            "responsive_web_graphql_exclude_directive_enabled": true,
-            "verified_phone_label_enabled": false,
-            "creator_subscriptions_tweet_preview_api_enabled": true,
-            "responsive_web_graphql_timeline_navigation_enabled": true,
-            "responsive_web_graphql_skip_user_profile_image_extensions_enabled": false,
-            "tweetypie_unmention_optimization_enabled": true,
-            "responsive_web_edit_tweet_api_enabled": true,
-            "graphql_is_translatable_rweb_tweet_is_translatable_enabled": true,
-            "view_counts_everywhere_api_enabled": true,
-            "longform_notetweets_consumption_enabled": true,
-            "responsive_web_twitter_article_tweet_consumption_enabled": false,
-            "tweet_awards_web_tipping_enabled": false,
-            "freedom_of_speech_not_reach_fetch_enabled": true,
-            "standardized_nudges_misinfo": true,
-            "tweet_with_visibility_results_prefer_gql_limited_actions_policy_enabled": true,
-            "longform_notetweets_rich_text_read_enabled": true,
-            "longform_notetweets_inline_media_enabled": true,
-            "responsive_web_media_download_video_enabled": false,
-            "responsive_web_enhance_cards_enabled": false
+    async fn run_post_download_hook(&self, item: &MediaItem, path: &Path) -> Result<()> {
+        let Some(hook) = &self.post_download_hook else {
+            return Ok(());
+        };
+        let tweet_json = json!({
+            "tweet_id": item.tweet_id,
+            "screen_name": self.user.screen_name,
+            "url": item.url,
+            "download_url": item.download_url,
+            "media_type": item.media_type.as_str(),
+            "created_at": item.timestamp.to_rfc3339(),
+            "full_text": item.full_text,
+            "path": path,
+            "duration_ms": item.duration_ms,
+            "width": item.resolution.map(|(width, _)| width),
+            "height": item.resolution.map(|(_, height)| height),
         });
+        hook.run(&HookContext {
+            path,
+            tweet_id: &item.tweet_id,
+            screen_name: &self.user.screen_name,
+            media_type: item.media_type.as_str(),
+            tweet_json: tweet_json.to_string(),
+        })
+        .await
+    }
 
-        let response = self
-            .client
-            .get("https://twitter.com/i/api/graphql/Le6KlbilFmSu-5VltFND-Q/UserMedia")
-            .query(&[
-                ("variables", serde_json::to_string(&variables)?),
-                ("features", serde_json::to_string(&features)?),
-            ])
-            .send()
-            .await?;
+    /// Emit an event if a channel is attached, building it lazily
+    fn emit(&self, event: impl FnOnce() -> Event) {
+        if let Some(events) = &self.events {
+            // A dropped receiver only means nobody is listening anymore
+            let _ = events.send(event());
+        }
+    }
 
-        let status = response.status();
-        trace!("UserMedia response status: {}", status);
+    /// One page of the timeline, archived before it is parsed if raw
+    /// responses are kept
+    async fn fetch_page(&self, cursor: Option<&str>, page: u32) -> Result<MediaPage> {
+        let Some(archive) = &self.archive else {
+            return self.api.user_media(&self.user, cursor, &self.extract).await;
+        };
+        let body = self.api.user_media_body(&self.user, cursor).await?;
+        if let Err(e) = archive
+            .write(&self.save_path, query_ids::USER_MEDIA, Some(page), &body)
+            .await
+        {
+            warn!("failed to archive page {}: {}", page, e);
+        }
+        let raw: Value = serde_json::from_str(&body)?;
+        drop(body);
+        parse_user_media_response(&raw, &self.extract)
+    }
 
-        if !status.is_success() {
-            let body = response.text().await?;
-            error!("UserMedia API error: {}", body);
-            return Err(format!("API error: {}", status).into());
+    /// Queue the media of archived timeline pages like fetched ones,
+    /// returning the items found and filtered
+    ///
+    /// Pages that cannot be read are reported and skipped.
+    async fn replay_pages(
+        self: Arc<Self>,
+        pages: Vec<PathBuf>,
+        tx: mpsc::Sender<Queued>,
+    ) -> TimelineFetch {
+        let mut total_items = 0;
+        let mut filtered = 0;
+        let mut unreadable = 0;
+        // Every archived run lists the media of the runs before it again
+        let mut seen = HashSet::new();
+        for (i, path) in pages.iter().enumerate() {
+            if self.cancel.is_cancelled() {
+                info!("cancelled, stopping replay");
+                break;
+            }
+            let page = match archive::read_page(path, &self.extract).await {
+                Ok(page) => page,
+                Err(e) => {
+                    error!("failed to replay {}: {}", path.display(), e);
+                    unreadable += 1;
+                    continue;
+                }
+            };
+            let number = i as u32 + 1;
+            info!(
+                "replaying {} media items from {}",
+                page.items.len(),
+                path.display()
+            );
+            if self.list.is_none() {
+                self.record_tombstones(number, &page.tombstones).await;
+            }
+            self.emit(|| Event::PageFetched {
+                screen_name: self.user.screen_name.clone(),
+                page: number,
+                count: page.items.len(),
+                cursor: page.next_cursor.clone(),
+            });
+
+            for item in page.items {
+                if !seen.insert(item.url.clone()) {
+                    continue;
+                }
+                total_items += 1;
+                if self.is_filtered(&item) {
+                    trace!("filtered: {}", item.url);
+                    filtered += 1;
+                    self.emit_skipped(&item, SkipReason::Filtered);
+                    continue;
+                }
+                if let Some(list) = &self.list {
+                    list(&item);
+                    continue;
+                }
+                self.record_item(&item).await;
+                let queued = Queued {
+                    item,
+                    downloaded: None,
+                };
+                if tx.send(queued).await.is_err() {
+                    warn!("receiver dropped, stopping replay");
+                    return TimelineFetch {
+                        items: total_items,
+                        filtered,
+                        failure: None,
+                    };
+                }
+            }
         }
 
-        let body = response.text().await?;
-        let raw: Value = serde_json::from_str(&body)?;
+        if unreadable > 0 {
+            warn!(
+                "{} of {} archived pages could not be read",
+                unreadable,
+                pages.len()
+            );
+        }
+        info!(
+            "replay complete: {} total media items, {} filtered",
+            total_items, filtered
+        );
+        TimelineFetch {
+            items: total_items,
+            filtered,
+            failure: None,
+        }
+    }
 
-        let (media_items, next_cursor) = parse_user_media_response(&raw)?;
+    /// Save the tweet and media record of `item` before it is downloaded
+    async fn record_item(&self, item: &MediaItem) {
+        let local_dt = item.timestamp.with_timezone(&Local);
+        let tweet_time = local_dt.format("%Y-%m-%d %H:%M:%S").to_string();
+
+        // Upsert tweet record
+        if let Err(e) = db::upsert_tweet(
+            &self.db,
+            &item.tweet_id,
+            &self.user.screen_name,
+            &tweet_time,
+            item.full_text.as_deref(),
+        )
+        .await
+        {
+            warn!("failed to save tweet {}: {}", item.tweet_id, e);
+        }
+        if let Err(e) = db::update_engagement(&self.db, &item.tweet_id, &item.engagement).await {
+            warn!(
+                "failed to save engagement of tweet {}: {}",
+                item.tweet_id, e
+            );
+        }
 
-        Ok((media_items, next_cursor))
+        if item.download_url != item.url
+            && let Err(e) = db::rekey_media(&self.db, &item.download_url, &item.url).await
+        {
+            warn!(
+                "failed to move media {} to {}: {}",
+                item.download_url, item.url, e
+            );
+        }
+        // Upsert media record (filename will be updated after download)
+        if let Err(e) = db::upsert_media(&self.db, &item.tweet_id, &item.url, None, None).await {
+            warn!("failed to save media {}: {}", item.url, e);
+        }
+        if (item.duration_ms.is_some() || item.resolution.is_some())
+            && let Err(e) =
+                db::update_video_details(&self.db, &item.url, item.duration_ms, item.resolution)
+                    .await
+        {
+            warn!("failed to save video details of {}: {}", item.url, e);
+        }
     }
 
-    /// Returns (filepath, hash, is_new_download)
-    #[instrument(skip_all)]
-    async fn download_media(
-        &self,
-        item: &MediaItem,
-        date_str: &str,
-    ) -> Result<(PathBuf, String, bool), Box<dyn std::error::Error + Send + Sync>> {
-        let download_url = match item.media_type {
-            MediaType::Image => format!("{}?name=orig", item.url),
-            MediaType::Video => item.url.clone(),
+    /// Record tweets that are gone, marking archived ones as deleted
+    async fn record_tombstones(&self, page: u32, tombstones: &[Tombstone]) {
+        if tombstones.is_empty() {
+            return;
+        }
+        let mut archived = 0;
+        for tombstone in tombstones {
+            match db::record_tombstone(
+                &self.db,
+                &self.user.screen_name,
+                &tombstone.entry_id,
+                tombstone.tweet_id.as_deref(),
+                &tombstone.reason,
+            )
+            .await
+            {
+                Ok(true) => archived += 1,
+                Ok(false) => {}
+                Err(e) => warn!("failed to record tombstone {}: {}", tombstone.entry_id, e),
+            }
+        }
+        info!(
+            "{} unavailable tweets on page {}, {} of them archived before",
+            tombstones.len(),
+            page,
+            archived
+        );
+    }
+
+    fn emit_skipped(&self, item: &MediaItem, reason: SkipReason) {
+        self.emit(|| Event::ItemSkipped {
+            screen_name: self.user.screen_name.clone(),
+            tweet_id: item.tweet_id.clone(),
+            url: item.url.clone(),
+            reason,
+        });
+    }
+
+    /// [`Task::download_media`], retrying a video whose URL was refused with
+    /// the variant the syndication API offers if `syndication_rescue` is set
+    ///
+    /// The item keeps its name and record, only the URL it is fetched from
+    /// changes. Tweets the syndication API does not serve, e.g. of
+    /// protected accounts or with sensitive media, fail with the original
+    /// error.
+    async fn download_or_rescue(&self, item: &MediaItem) -> Result<DownloadedFile> {
+        let error = match self.download_media(item).await {
+            Err(Error::Download(status))
+                if self.syndication_rescue
+                    && matches!(item.media_type, MediaType::Video)
+                    && (status == StatusCode::FORBIDDEN || verify::is_gone(status)) =>
+            {
+                Error::Download(status)
+            }
+            result => return result,
+        };
+        let tweet = match self
+            .api
+            .syndicated_tweet(&item.tweet_id, &self.extract)
+            .await
+        {
+            Ok(tweet) => tweet,
+            Err(e) => {
+                debug!("no syndication rescue for {}: {}", item.url, e);
+                return Err(error);
+            }
+        };
+        let Some(rescued) = tweet
+            .media
+            .into_iter()
+            .find(|m| m.url == item.url && m.download_url != item.download_url)
+        else {
+            debug!("syndication API offers no other URL for {}", item.url);
+            return Err(error);
+        };
+        info!(
+            "{} failed with {}, retrying with {} from the syndication API",
+            item.download_url, error, rescued.download_url
+        );
+        let rescued = MediaItem {
+            download_url: rescued.download_url,
+            resolution: rescued.resolution.or(item.resolution),
+            ..item.clone()
         };
+        self.download_media(&rescued).await
+    }
 
+    #[instrument(skip_all, fields(tweet_id = %item.tweet_id, url = %item.url))]
+    async fn download_media(&self, item: &MediaItem) -> Result<DownloadedFile> {
         let ext = match item.media_type {
-            MediaType::Image => item.url.rsplit('.').next().unwrap_or("jpg"),
-            MediaType::Video => "mp4",
+            MediaType::Image => image_extension(&item.url).unwrap_or_else(|| "jpg".to_string()),
+            MediaType::Video => "mp4".to_string(),
+        };
+        let ext = ext.as_str();
+
+        let segments = stem_segments(
+            self.filename_template.as_ref(),
+            self.timezone,
+            item,
+            &self.user.screen_name,
+        );
+        // Checked before downloading, a name that cannot be created fails early
+        let stem = filename::fit(&segments, filename::max_stem_len(&self.save_path))
+            .ok_or_else(|| Error::FilenameTooLong(item.url.clone()))?;
+        let (mut filepath, mut replaced) = match self.resolve_target(item, &stem, ext).await? {
+            Target::New(path) => (path, false),
+            Target::Replace(path) => (path, true),
+            Target::Existing(filepath) => {
+                let (hash, size) = self.hash_existing(&filepath).await?;
+                self.write_text_sidecar(item, &filepath, false).await;
+                return Ok(DownloadedFile {
+                    path: filepath,
+                    hash,
+                    size,
+                    is_new: false,
+                    replaced: false,
+                    converted: None,
+                    link: None,
+                    image_size: None,
+                    validators: None,
+                    download_url: None,
+                });
+            }
+        };
+
+        self.emit(|| Event::DownloadStarted {
+            screen_name: self.user.screen_name.clone(),
+            tweet_id: item.tweet_id.clone(),
+            url: item.url.clone(),
+        });
+        let fetched = if hls::is_playlist(&item.download_url) {
+            let scratch = filepath.with_extension("hls.part");
+            let video = hls::download(
+                self.api.client(),
+                &item.download_url,
+                &scratch,
+                self.max_file_size,
+                |bytes| {
+                    self.emit(|| Event::DownloadProgress {
+                        screen_name: self.user.screen_name.clone(),
+                        url: item.url.clone(),
+                        bytes,
+                        total: None,
+                    });
+                },
+            )
+            .await?;
+            Fetched {
+                body: Body::Bytes(video.bytes),
+                served_ext: Some(video.extension),
+                image_size: None,
+                validators: None,
+                download_url: item.download_url.clone(),
+            }
+        } else {
+            self.fetch_file(item).await?
+        };
+        let Fetched {
+            body,
+            served_ext,
+            image_size,
+            validators,
+            download_url,
+        } = fetched;
+
+        // The URL extension is only a guess, the served type is authoritative
+        if let Some(served_ext) = served_ext
+            && served_ext != ext
+        {
+            debug!("{} is served as {}, not {}", item.url, served_ext, ext);
+            (filepath, replaced) = match self.resolve_target(item, &stem, served_ext).await? {
+                Target::New(path) => (path, false),
+                Target::Replace(path) => (path, true),
+                Target::Existing(path) => {
+                    let (hash, size) = self.hash_existing(&path).await?;
+                    self.write_text_sidecar(item, &path, false).await;
+                    return Ok(DownloadedFile {
+                        path,
+                        hash,
+                        size,
+                        is_new: false,
+                        replaced: false,
+                        converted: None,
+                        link: None,
+                        image_size,
+                        validators,
+                        download_url: Some(download_url),
+                    });
+                }
+            };
+        }
+        let (mut hash, mut size) = self.save(item, body, &filepath).await?;
+        let converted = match &self.post_process {
+            Some(post_process)
+                if matches!(item.media_type, MediaType::Image)
+                    && post_process.matches(&filepath) =>
+            {
+                match post_process
+                    .convert(&filepath, self.overwrite, self.hash_algorithm)
+                    .await
+                {
+                    Ok(file) => {
+                        self.claimed
+                            .lock()
+                            .await
+                            .insert(file.path.clone(), item.url.clone());
+                        (filepath, hash, size) = (file.path, file.hash, file.size);
+                        Some(true)
+                    }
+                    Err(e) => {
+                        warn!(
+                            "failed to convert {}, keeping it: {}",
+                            filepath.display(),
+                            e
+                        );
+                        Some(false)
+                    }
+                }
+            }
+            _ => None,
+        };
+        // Hashed as embedded, so the file still verifies
+        if self.embed_metadata
+            && matches!(item.media_type, MediaType::Image)
+            && let Some(embedded) = self.embed_metadata(item, &filepath).await
+        {
+            (hash, size) = embedded;
+        }
+        let link = match self.dedupe {
+            Dedupe::Hardlink => self.link_duplicate(item, &filepath, &hash, size).await,
+            Dedupe::Off => None,
         };
+        self.write_text_sidecar(item, &filepath, true).await;
+
+        Ok(DownloadedFile {
+            path: filepath,
+            hash,
+            size,
+            is_new: true,
+            replaced,
+            converted,
+            link,
+            image_size,
+            validators,
+            download_url: Some(download_url),
+        })
+    }
 
-        let media_id = item
-            .url
-            .rsplit('/')
-            .next()
-            .and_then(|s| s.split('.').next())
-            .unwrap_or("unknown");
+    /// Write `body` to `filepath` with [`save_body`], reporting progress
+    async fn save(&self, item: &MediaItem, body: Body, filepath: &Path) -> Result<(String, u64)> {
+        save_body(
+            body,
+            filepath,
+            self.hash_algorithm,
+            self.max_file_size,
+            |bytes, total| {
+                self.emit(|| Event::DownloadProgress {
+                    screen_name: self.user.screen_name.clone(),
+                    url: item.url.clone(),
+                    bytes,
+                    total,
+                })
+            },
+        )
+        .await
+    }
 
-        let filename = format!("{}-{}.{}", date_str, media_id, ext);
-        let filepath = self.save_path.join(&filename);
+    /// Embed the tweet's metadata in the image at `path` and return its new
+    /// hash and size
+    ///
+    /// The image is left as it was written when it cannot be edited.
+    async fn embed_metadata(&self, item: &MediaItem, path: &Path) -> Option<(String, u64)> {
+        let image = match tokio::fs::read(path).await {
+            Ok(image) => image,
+            Err(e) => {
+                warn!("failed to read {} to embed metadata: {}", path.display(), e);
+                return None;
+            }
+        };
+        let permalink = permalink(&self.user.screen_name, &item.tweet_id);
+        let metadata = ImageMetadata {
+            date: self.timezone.convert(item.timestamp),
+            author: &self.user.screen_name,
+            text: item.full_text.as_deref(),
+            permalink: &permalink,
+        };
+        let Some(embedded) = embed::embed(&image, &metadata) else {
+            debug!("cannot embed metadata in {}", path.display());
+            return None;
+        };
+        match save_body(
+            Body::Bytes(embedded),
+            path,
+            self.hash_algorithm,
+            None,
+            |_, _| {},
+        )
+        .await
+        {
+            Ok(saved) => Some(saved),
+            Err(e) => {
+                warn!("failed to embed metadata in {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
 
-        // Check if file exists and compute hash
-        if filepath.exists() {
-            let content = fs::read(&filepath).await?;
-            let hash = db::calculate_hash(&content);
-            return Ok((filepath, hash, false)); // false = not newly downloaded
+    /// Write the caption file of the media file at `path` if sidecars are
+    /// on, replacing one on disk only with `replace`
+    ///
+    /// A caption that cannot be written does not fail the download.
+    async fn write_text_sidecar(&self, item: &MediaItem, path: &Path, replace: bool) {
+        let Some(sidecars) = self.text_sidecars else {
+            return;
+        };
+        let Some(caption) = sidecars.caption(
+            item.full_text.as_deref(),
+            &self.user.screen_name,
+            &item.tweet_id,
+        ) else {
+            return;
+        };
+        let sidecar = sidecars.path(path, &item.tweet_id);
+        if !replace && sidecar.exists() {
+            return;
+        }
+        if let Err(e) = tokio::fs::write(&sidecar, caption).await {
+            warn!("failed to write caption {}: {}", sidecar.display(), e);
         }
+    }
 
-        let response = self.client.get(&download_url).send().await?;
+    /// Save a frame or the poster of the video at `video_path` next to it
+    /// if video thumbnails are on and record it with the video
+    ///
+    /// A thumbnail on disk is kept unless files are overwritten, one that
+    /// cannot be made does not fail the download.
+    async fn save_thumbnail(&self, item: &MediaItem, video_path: &Path) {
+        if !self.video_thumbnails || !matches!(item.media_type, MediaType::Video) {
+            return;
+        }
+        let path = thumbnail_path(video_path);
+        let Some(filename) = path.file_name().and_then(|n| n.to_str()) else {
+            return;
+        };
+        if self.overwrite || !path.exists() {
+            let extracted = match &self.frame_extractor {
+                Some(frames) => match frames.extract(video_path, &path).await {
+                    Ok(()) => true,
+                    Err(e) => {
+                        warn!(
+                            "failed to extract a frame of {}: {}",
+                            video_path.display(),
+                            e
+                        );
+                        false
+                    }
+                },
+                None => false,
+            };
+            if !extracted {
+                let Some(poster_url) = item.poster_url.as_deref() else {
+                    return;
+                };
+                if let Err(e) = self.fetch_thumbnail(poster_url, &path).await {
+                    warn!("failed to save thumbnail of {}: {}", item.url, e);
+                    return;
+                }
+            }
+            info!("thumbnail: {}", path.display());
+        }
+        if let Err(e) = db::update_thumbnail(&self.db, &item.url, Some(filename)).await {
+            warn!("failed to record thumbnail: {}", e);
+        }
+    }
 
+    /// [`save_thumbnail`](Self::save_thumbnail) for a video verified on
+    /// disk under its recorded name
+    async fn save_recorded_thumbnail(&self, item: &MediaItem) {
+        if !self.video_thumbnails
+            || !matches!(item.media_type, MediaType::Video)
+            || item.poster_url.is_none() && self.frame_extractor.is_none()
+        {
+            return;
+        }
+        match db::get_media_by_url(&self.db, &item.url).await {
+            Ok(Some(db::MediaRecord {
+                filename: Some(filename),
+                thumbnail,
+                ..
+            })) => {
+                let video_path = self.save_path.join(&filename);
+                if thumbnail.is_none() || !thumbnail_path(&video_path).exists() {
+                    self.save_thumbnail(item, &video_path).await;
+                }
+            }
+            Ok(_) => {}
+            Err(e) => warn!("failed to look up {}: {}", item.url, e),
+        }
+    }
+
+    /// Download the poster image at `url` to `path`
+    async fn fetch_thumbnail(&self, url: &str, path: &Path) -> Result<()> {
+        let (response, _) = self.request_image(url, None).await?;
         if !response.status().is_success() {
-            return Err(format!("download failed: {}", response.status()).into());
+            return Err(Error::Download(response.status()));
+        }
+        let total = response.content_length();
+        save_body(
+            Body::Response { response, total },
+            path,
+            self.hash_algorithm,
+            self.max_file_size,
+            |_, _| {},
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Replace the download at `path` with a link to a recorded file with
+    /// the same hash
+    ///
+    /// The download is kept as a copy when there is none or it cannot be
+    /// linked to.
+    async fn link_duplicate(
+        &self,
+        item: &MediaItem,
+        path: &Path,
+        hash: &str,
+        size: u64,
+    ) -> Option<Link> {
+        let candidates =
+            match db::find_by_hash(&self.db, hash, self.hash_algorithm, &item.url).await {
+                Ok(candidates) => candidates,
+                Err(e) => {
+                    warn!("failed to look up duplicates of {}: {}", item.url, e);
+                    return None;
+                }
+            };
+        for candidate in candidates {
+            let folder = if candidate
+                .screen_name
+                .eq_ignore_ascii_case(&self.user.screen_name)
+            {
+                self.save_path.clone()
+            } else {
+                (self.account_folder)(&candidate.screen_name)
+            };
+            let original = folder.join(&candidate.filename);
+            // Files changed since they were hashed are not linked to
+            if original == path || std::fs::metadata(&original).map(|m| m.len()).ok() != Some(size)
+            {
+                continue;
+            }
+            let (from, to) = (original.clone(), path.to_path_buf());
+            return match tokio::task::spawn_blocking(move || dedupe::link(&from, &to)).await {
+                Ok(Ok(kind)) => {
+                    debug!("{} is a {} to {}", path.display(), kind, original.display());
+                    Some(Link {
+                        to: candidate.media_url,
+                        kind,
+                        bytes: size,
+                    })
+                }
+                Ok(Err(e)) => {
+                    debug!("keeping a copy of {}: {}", original.display(), e);
+                    None
+                }
+                Err(e) => {
+                    warn!("failed to link {}: {}", path.display(), e);
+                    None
+                }
+            };
         }
+        None
+    }
 
-        let bytes = response.bytes().await?;
-        let hash = db::calculate_hash(&bytes);
+    /// Hash and size of a file already on disk
+    async fn hash_existing(&self, path: &Path) -> Result<(String, u64)> {
+        let algorithm = self.hash_algorithm;
+        let path = path.to_path_buf();
+        let hashed = tokio::task::spawn_blocking(move || {
+            let size = std::fs::metadata(&path)?.len();
+            Ok::<_, std::io::Error>((algorithm.hash_file(&path)?, size))
+        })
+        .await
+        .map_err(std::io::Error::other)??;
+        Ok(hashed)
+    }
 
-        let mut file = fs::File::create(&filepath).await?;
-        file.write_all(&bytes).await?;
+    /// Path for a media file, appending `-1`, `-2`, … while the name is taken
+    /// by a different media
+    ///
+    /// A file of the same name that is not known to belong to another media
+    /// counts as already downloaded, or is replaced in overwrite mode.
+    async fn resolve_target(&self, item: &MediaItem, stem: &str, ext: &str) -> Result<Target> {
+        let mut claimed = self.claimed.lock().await;
+        let mut counter = 0u32;
+        loop {
+            let filename = match counter {
+                0 => format!("{stem}.{ext}"),
+                n => format!("{stem}-{n}.{ext}"),
+            };
+            counter += 1;
+            let path = self.save_path.join(&filename);
+
+            let owner = match claimed.get(&path) {
+                Some(url) => Some(url.clone()),
+                None if path.exists() => {
+                    db::get_media_url_by_filename(&self.db, &self.user.screen_name, &filename)
+                        .await?
+                }
+                None => None,
+            };
+            if let Some(owner) = owner
+                && owner != item.url
+            {
+                debug!("{} belongs to {}, trying the next name", filename, owner);
+                continue;
+            }
 
-        Ok((filepath, hash, true)) // true = newly downloaded
+            claimed.insert(path.clone(), item.url.clone());
+            return Ok(if !path.exists() {
+                Target::New(path)
+            } else if self.overwrite {
+                Target::Replace(path)
+            } else {
+                Target::Existing(path)
+            });
+        }
+    }
+
+    /// Request a single file, returning its unread body, the extension of
+    /// its `Content-Type` and the size of an image
+    ///
+    /// A recorded file under another name is only downloaded again if the
+    /// server has a different version, [`Error::NotModified`] otherwise.
+    async fn fetch_file(&self, item: &MediaItem) -> Result<Fetched> {
+        let conditional = self.recorded_validators(item).await;
+        let (response, image_size) = match item.media_type {
+            MediaType::Image => {
+                let (response, size) = self
+                    .request_image(&item.download_url, conditional.as_ref())
+                    .await?;
+                (response, Some(size))
+            }
+            MediaType::Video => {
+                let request = self.api.client().get(&item.download_url);
+                let request = match &conditional {
+                    Some(validators) => validators.apply(request),
+                    None => request,
+                };
+                (self.api.send(request).await?, None)
+            }
+        };
+
+        if response.status() == StatusCode::NOT_MODIFIED && conditional.is_some() {
+            return Err(Error::NotModified);
+        }
+        pushback::check(&response)?;
+        if !response.status().is_success() {
+            return Err(Error::Download(response.status()));
+        }
+
+        let served_ext = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(extension_for_content_type);
+
+        let validators = Validators::from_response(&response);
+        let total = response.content_length();
+        if let (Some(size), Some(limit)) = (total, self.max_file_size)
+            && size > limit
+        {
+            return Err(Error::TooLarge { size, limit });
+        }
+        Ok(Fetched {
+            download_url: response.url().to_string(),
+            body: Body::Response { response, total },
+            served_ext,
+            image_size,
+            validators: Some(validators),
+        })
+    }
+
+    /// Validators of the recorded file of `item`, if that file is on disk
+    /// and is not to be overwritten
+    async fn recorded_validators(&self, item: &MediaItem) -> Option<Validators> {
+        if self.overwrite {
+            return None;
+        }
+        let record = match db::get_media_by_url(&self.db, &item.url).await {
+            Ok(record) => record?,
+            Err(e) => {
+                warn!("failed to look up {}: {}", item.url, e);
+                return None;
+            }
+        };
+        let filename = record.filename?;
+        let validators = Validators {
+            etag: record.etag,
+            last_modified: record.last_modified,
+        };
+        (!validators.is_empty() && self.save_path.join(filename).exists()).then_some(validators)
+    }
+
+    /// Request an image in the configured size with [`request_image`]
+    async fn request_image(
+        &self,
+        url: &str,
+        conditional: Option<&Validators>,
+    ) -> Result<(reqwest::Response, &'static str)> {
+        request_image(&self.api, url, self.image_size, conditional).await
     }
 }
 
-#[instrument(skip_all)]
-fn build_client(
+/// Segments of the file name stem of `item`, to be joined with
+/// [`filename::fit`]
+///
+/// Without a template, tweets with several media are grouped by tweet and
+/// numbered in order.
+pub(crate) fn stem_segments(
+    template: Option<&Template>,
+    timezone: Timezone,
+    item: &MediaItem,
     screen_name: &str,
-    auth_token: &str,
-    ct0: &str,
-) -> Result<Client, Box<dyn std::error::Error + Send + Sync>> {
-    let mut headers = HeaderMap::new();
-    headers.insert(USER_AGENT, HeaderValue::from_static(DEFAULT_USER_AGENT));
-    headers.insert(
-        AUTHORIZATION,
-        HeaderValue::from_static(DEFAULT_AUTHORIZATION),
-    );
-    headers.insert(
-        COOKIE,
-        HeaderValue::from_str(&format!("auth_token={auth_token}; ct0={ct0};"))?,
-    );
-    headers.insert(
-        HeaderName::from_str("x-csrf-token")?,
-        HeaderValue::from_str(ct0)?,
-    );
-    headers.insert(
-        REFERER,
-        HeaderValue::from_str(&format!("https://twitter.com/{screen_name}"))?,
-    );
-
-    let client = Client::builder().default_headers(headers).build()?;
-    Ok(client)
+) -> Vec<Segment<'static>> {
+    // Of the variant for videos, names stay as they were before canonical
+    // URLs were recorded
+    let media_id = item
+        .download_url
+        .split(['?', '#'])
+        .next()
+        .and_then(|path| path.rsplit('/').next())
+        .and_then(|s| s.split('.').next())
+        .unwrap_or("unknown");
+
+    let posted = timezone.convert(item.timestamp);
+    let date_str = posted.format("%Y-%m-%d").to_string();
+    match (template, item.index) {
+        (Some(template), _) => template.render(&Fields {
+            date: &date_str,
+            time: &posted.format("%H%M%S").to_string(),
+            tweet_id: &item.tweet_id,
+            media_id,
+            index: item.index,
+            screen_name,
+            text: item.full_text.as_deref(),
+        }),
+        (None, Some(index)) => vec![Segment::fixed(format!(
+            "{}-{}-{:02}-{}",
+            date_str, item.tweet_id, index, media_id
+        ))],
+        (None, None) => vec![Segment::fixed(format!("{}-{}", date_str, media_id))],
+    }
 }
 
-#[instrument(skip_all)]
-async fn fetch_user_info(
-    client: &Client,
-    screen_name: &str,
-) -> Result<User, Box<dyn std::error::Error + Send + Sync>> {
-    let variables = json!({
-        "screen_name": screen_name,
-        "withSafetyModeUserFields": false,
-    });
-
-    let features = json!({
-        "hidden_profile_likes_enabled": false,
-        "hidden_profile_subscriptions_enabled": false,
-        "responsive_web_graphql_exclude_directive_enabled": true,
-        "verified_phone_label_enabled": false,
-        "subscriptions_verification_info_verified_since_enabled": true,
-        "highlights_tweets_tab_ui_enabled": true,
-        "creator_subscriptions_tweet_preview_api_enabled": true,
-        "responsive_web_graphql_skip_user_profile_image_extensions_enabled": false,
-        "responsive_web_graphql_timeline_navigation_enabled": true,
-    });
-
-    let field_toggles = json!({
-        "withAuxiliaryUserLabels": false,
-    });
-
-    let response = client
-        .get("https://twitter.com/i/api/graphql/xc8f1g7BYqr6VTzTbvNlGw/UserByScreenName")
-        .query(&[
-            ("variables", serde_json::to_string(&variables)?),
-            ("features", serde_json::to_string(&features)?),
-            ("fieldToggles", serde_json::to_string(&field_toggles)?),
-        ])
-        .send()
-        .await?;
+/// `url` of an image requested in `size`
+///
+/// Both URL styles are understood: `.../media/ID.jpg` gets `?name=<size>`
+/// and `.../media/ID?format=jpg&name=small` has its `name` replaced.
+pub fn sized_image_url(url: &str, size: &str) -> String {
+    let Ok(mut parsed) = reqwest::Url::parse(url) else {
+        return format!("{url}?name={size}");
+    };
+    let kept: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(key, _)| key != "name")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    parsed
+        .query_pairs_mut()
+        .clear()
+        .extend_pairs(kept)
+        .append_pair("name", size);
+    parsed.into()
+}
 
-    let status = response.status();
-    trace!("UserByScreenName response status: {}", status);
-
-    if !status.is_success() {
-        let body = response.text().await?;
-        error!("UserByScreenName API error: {}", body);
-        return Err(format!("API error: {}", status).into());
-    }
-
-    let raw: Value = response.json().await?;
-
-    let result = raw
-        .pointer("/data/user/result")
-        .ok_or("Failed to find user result")?;
-    let legacy = result.get("legacy").ok_or("Failed to find legacy")?;
-
-    let rest_id = result
-        .get("rest_id")
-        .and_then(|v| v.as_str())
-        .ok_or("Failed to get rest_id")?
-        .to_string();
-    let name = legacy
-        .get("name")
-        .and_then(|v| v.as_str())
-        .ok_or("Failed to get name")?
-        .to_string();
-    let media_count = legacy
-        .get("media_count")
-        .and_then(|v| v.as_u64())
-        .ok_or("Failed to get media_count")?;
-
-    Ok(User {
-        screen_name: screen_name.to_string(),
-        name,
-        rest_id,
-        media_count,
-    })
+/// Extension of an image URL, from its `format` parameter or else its path,
+/// lowercase
+pub fn image_extension(url: &str) -> Option<String> {
+    let parsed = reqwest::Url::parse(url).ok()?;
+    if let Some((_, format)) = parsed.query_pairs().find(|(key, _)| key == "format") {
+        return Some(format.to_ascii_lowercase()).filter(|f| !f.is_empty());
+    }
+    let name = parsed.path_segments()?.next_back()?;
+    name.rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .filter(|ext| !ext.is_empty())
 }
 
-#[instrument(skip_all)]
-fn parse_user_media_response(
-    raw: &Value,
-) -> Result<(Vec<MediaItem>, Option<String>), Box<dyn std::error::Error + Send + Sync>> {
-    let mut media_items = Vec::new();
-    let mut next_cursor: Option<String> = None;
-
-    let instructions = raw
-        .pointer("/data/user/result/timeline_v2/timeline/instructions")
-        .and_then(|v| v.as_array())
-        .ok_or("Failed to find instructions")?;
-
-    for instruction in instructions {
-        if let Some(module_items) = instruction.get("moduleItems").and_then(|v| v.as_array()) {
-            for item in module_items {
-                if let Some(media) = extract_media_from_item(item) {
-                    media_items.extend(media);
-                }
-            }
+/// Request an image in `image_size`, falling back to smaller sizes on 404
+///
+/// Returns the last response and the size it was requested in.
+pub(crate) async fn request_image(
+    api: &Api,
+    url: &str,
+    image_size: ImageSize,
+    conditional: Option<&Validators>,
+) -> Result<(reqwest::Response, &'static str)> {
+    let preferred = image_size.as_str();
+    let start = IMAGE_SIZES
+        .iter()
+        .position(|size| *size == preferred)
+        .unwrap_or(0);
+    let mut sizes = IMAGE_SIZES[start..=start.max(SMALLEST_FALLBACK)]
+        .iter()
+        .copied()
+        .peekable();
+    loop {
+        let size = sizes.next().unwrap_or(preferred);
+        let request = api.client().get(sized_image_url(url, size));
+        let request = match conditional {
+            Some(validators) => validators.apply(request),
+            None => request,
+        };
+        let response = api.send(request).await?;
+        if response.status() != StatusCode::NOT_FOUND || sizes.peek().is_none() {
+            return Ok((response, size));
         }
+        debug!("{} not found as {}, trying a smaller size", url, size);
+    }
+}
 
-        if let Some(entries) = instruction.get("entries").and_then(|v| v.as_array()) {
-            for entry in entries {
-                let entry_id = entry.get("entryId").and_then(|v| v.as_str()).unwrap_or("");
-
-                if entry_id.contains("cursor-bottom")
-                    && let Some(cursor_value) =
-                        entry.pointer("/content/value").and_then(|v| v.as_str())
+/// Write `body` to `filepath`, hashing it on the way, and return its hash
+/// and size
+///
+/// Written under a temporary name and renamed over `filepath`, so an
+/// aborted or oversized download leaves nothing behind and an existing file
+/// is replaced at once. `on_progress` is called with the bytes written so
+/// far and the announced total.
+pub(crate) async fn save_body(
+    body: Body,
+    filepath: &Path,
+    algorithm: HashAlgorithm,
+    max_file_size: Option<u64>,
+    mut on_progress: impl FnMut(u64, Option<u64>),
+) -> Result<(String, u64)> {
+    let mut part_path = filepath.to_path_buf().into_os_string();
+    part_path.push(".part");
+    let part_path = PathBuf::from(part_path);
+    let part = PartFile(Some(part_path.clone()));
+    let mut file = fs::File::create(&part_path).await?;
+    let mut hasher = Hasher::new(algorithm);
+    let mut size = 0u64;
+    match body {
+        Body::Bytes(bytes) => {
+            hasher.update(&bytes);
+            file.write_all(&bytes).await?;
+            size = bytes.len() as u64;
+        }
+        Body::Response {
+            mut response,
+            total,
+        } => {
+            while let Some(chunk) = response.chunk().await? {
+                size += chunk.len() as u64;
+                // Content-Length is missing or wrong
+                if let Some(limit) = max_file_size
+                    && size > limit
                 {
-                    next_cursor = Some(cursor_value.to_string());
+                    return Err(Error::TooLarge { size, limit });
                 }
+                hasher.update(&chunk);
+                file.write_all(&chunk).await?;
+                on_progress(size, total);
+            }
+        }
+    }
+    file.flush().await?;
+    drop(file);
+    fs::rename(&part_path, filepath).await?;
+    part.keep();
+    Ok((hasher.finalize(), size))
+}
 
-                if let Some(items) = entry.pointer("/content/items").and_then(|v| v.as_array()) {
-                    for item in items {
-                        if let Some(media) = extract_media_from_item(item) {
-                            media_items.extend(media);
-                        }
-                    }
-                }
+/// File extension for a media `Content-Type`, ignoring parameters
+fn extension_for_content_type(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next()?.trim();
+    match mime.to_ascii_lowercase().as_str() {
+        "image/jpeg" | "image/jpg" => Some("jpg"),
+        "image/png" => Some("png"),
+        "image/webp" => Some("webp"),
+        "image/gif" => Some("gif"),
+        "video/mp4" => Some("mp4"),
+        _ => None,
+    }
+}
+
+fn record(totals: &mut Totals, result: DownloadResult) {
+    match result {
+        DownloadResult::Downloaded {
+            bytes,
+            hook_failed,
+            replaced,
+            converted,
+        } => {
+            totals.downloaded += 1;
+            if replaced {
+                totals.replaced += 1;
+            }
+            match converted {
+                Some(true) => totals.converted += 1,
+                Some(false) => totals.unconverted += 1,
+                None => {}
+            }
+            totals.bytes = totals.bytes.saturating_add(bytes);
+            if hook_failed {
+                totals.hook_failures += 1;
             }
         }
+        DownloadResult::Skipped => totals.skipped += 1,
+        DownloadResult::TooLarge => totals.too_large += 1,
+        DownloadResult::Gone => totals.gone += 1,
+        DownloadResult::Failed => totals.failed += 1,
     }
+}
 
-    Ok((media_items, next_cursor))
+/// Choices made while extracting media items from a response
+#[derive(Debug, Clone, Copy, Default)]
+#[non_exhaustive]
+pub struct ExtractOptions {
+    /// Highest video bitrate to pick, in bits per second
+    pub max_video_bitrate: Option<u64>,
 }
 
+impl ExtractOptions {
+    /// Highest video bitrate to pick, `None` for the best available
+    pub fn max_video_bitrate(mut self, max_video_bitrate: Option<u64>) -> Self {
+        self.max_video_bitrate = max_video_bitrate;
+        self
+    }
+}
+
+/// How a timeline page answered with a server error is retried, apart from
+/// media downloads since pages are cheap but rate limited
+///
+/// Client errors are not retried. Network errors are retried once the
+/// connection is back, see [`OutagePolicy`].
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct PageRetryPolicy {
+    /// Retries of a page before the task stops fetching
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled for every further one
+    pub backoff: Duration,
+}
+
+impl Default for PageRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 4,
+            backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl PageRetryPolicy {
+    /// Retries of a page before the task stops fetching
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Wait before the first retry, doubled for every further one
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+}
+
+/// One page of an account's media timeline
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct MediaPage {
+    pub items: Vec<MediaItem>,
+    /// Cursor of the next page, `None` on the last one
+    pub next_cursor: Option<String>,
+    /// Entries in place of tweets that cannot be shown
+    pub tombstones: Vec<Tombstone>,
+}
+
+/// A timeline entry for a deleted, withheld or otherwise unavailable tweet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tombstone {
+    /// e.g. `profile-grid-0-tweet-1900000000000000003`
+    pub entry_id: String,
+    /// Taken from the entry ID, when it has one
+    pub tweet_id: Option<String>,
+    /// Notice shown instead of the tweet, or why it is unavailable
+    pub reason: String,
+}
+
+/// Parse a UserMedia response into media items, tombstones and the cursor of
+/// the next page
 #[instrument(skip_all)]
-fn extract_media_from_item(item: &Value) -> Option<Vec<MediaItem>> {
-    let mut results = Vec::new();
+pub fn parse_user_media_response(raw: &Value, options: &ExtractOptions) -> Result<MediaPage> {
+    let response = Response::<UserMediaData>::deserialize(raw)
+        .map_err(|e| Error::Parse(format!("invalid UserMedia response: {e}")))?;
+
+    let mut page = MediaPage::default();
+
+    let timeline = response
+        .data
+        .and_then(|d| d.user)
+        .and_then(|u| u.result)
+        .and_then(|r| r.timeline_v2)
+        .ok_or_else(|| Error::Parse("failed to find instructions".to_string()))?;
+    // Accounts that never posted media may get an empty timeline object
+    let instructions = timeline
+        .timeline
+        .map(|t| t.instructions)
+        .unwrap_or_default();
+
+    // Listed first, so a copy of the pinned tweet in the grid is dropped below
+    for instruction in instructions.iter().filter(|i| is_pin(i)) {
+        let content = instruction
+            .entry
+            .as_ref()
+            .and_then(|e| e.content.item_content.as_ref());
+        for mut item in extract_media(content, options) {
+            item.pinned = true;
+            page.items.push(item);
+        }
+    }
+
+    for instruction in &instructions {
+        for item in &instruction.module_items {
+            page.items.extend(extract_media_from_item(item, options));
+            page.tombstones.extend(tombstone_of(item));
+        }
+
+        for entry in &instruction.entries {
+            if entry.entry_id.contains("cursor-bottom")
+                && let Some(cursor_value) = &entry.content.value
+            {
+                page.next_cursor = Some(cursor_value.clone());
+            }
+
+            for item in &entry.content.items {
+                page.items.extend(extract_media_from_item(item, options));
+                page.tombstones.extend(tombstone_of(item));
+            }
+        }
+    }
 
-    let result = item.pointer("/item/itemContent/tweet_results/result")?;
+    // A tweet can be both a module item and an entry of the same response
+    let mut seen = HashSet::new();
+    let before = page.items.len();
+    page.items.retain(|item| seen.insert(item.url.clone()));
+    if page.items.len() < before {
+        debug!(
+            "dropped {} duplicate media items",
+            before - page.items.len()
+        );
+    }
 
-    // Get tweet_id - try from rest_id first, then from tweet/rest_id
-    let tweet_id = result
-        .get("rest_id")
-        .or_else(|| result.pointer("/tweet/rest_id"))
-        .and_then(|v| v.as_str())
-        .unwrap_or("unknown")
-        .to_string();
+    Ok(page)
+}
 
-    let legacy = result
-        .get("legacy")
-        .or_else(|| result.pointer("/tweet/legacy"))?;
+/// The tombstone an item holds instead of a tweet
+fn tombstone_of(item: &ModuleItem) -> Option<Tombstone> {
+    let result = item
+        .item
+        .as_ref()
+        .and_then(|i| i.item_content.as_ref())
+        .and_then(|c| c.tweet_results.as_ref())
+        .and_then(|t| t.result.as_ref())?;
+    let reason = match result {
+        TweetResult::TweetTombstone { tombstone } => tombstone
+            .as_ref()
+            .and_then(|t| t.text.as_ref())
+            .map(|t| t.text.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .unwrap_or_else(|| "tombstone".to_string()),
+        TweetResult::TweetUnavailable { reason } => {
+            reason.clone().unwrap_or_else(|| "unavailable".to_string())
+        }
+        _ => return None,
+    };
+    // e.g. profile-grid-0-tweet-1900000000000000003
+    let tweet_id = item
+        .entry_id
+        .rsplit_once("tweet-")
+        .map(|(_, id)| id)
+        .filter(|id| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()))
+        .map(str::to_string);
+    Some(Tombstone {
+        entry_id: item.entry_id.clone(),
+        tweet_id,
+        reason,
+    })
+}
 
-    // Extract full_text from the tweet
-    let full_text = legacy
-        .get("full_text")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
+#[instrument(skip_all)]
+fn extract_media_from_item(item: &ModuleItem, options: &ExtractOptions) -> Vec<MediaItem> {
+    extract_media(
+        item.item.as_ref().and_then(|i| i.item_content.as_ref()),
+        options,
+    )
+}
+
+/// Whether `instruction` holds the pinned tweet
+fn is_pin(instruction: &Instruction) -> bool {
+    instruction.kind == "TimelinePinEntry"
+}
+
+fn extract_media(content: Option<&ItemContent>, options: &ExtractOptions) -> Vec<MediaItem> {
+    let Some(result) = content
+        .and_then(|c| c.tweet_results.as_ref())
+        .and_then(|t| t.result.as_ref())
+    else {
+        return Vec::new();
+    };
+
+    // Tombstones and unavailable tweets carry no media
+    let Some(tweet) = result.tweet() else {
+        trace!("skipping tweet result without content");
+        return Vec::new();
+    };
+    let Some(legacy) = &tweet.legacy else {
+        return Vec::new();
+    };
+
+    let tweet_id = tweet.rest_id.as_deref().unwrap_or("unknown");
 
     let timestamp = legacy
-        .get("created_at")
-        .and_then(|v| v.as_str())
+        .created_at
+        .as_deref()
         .and_then(|s| {
             // Wed Mar 12 18:47:51 +0000 2025
             //  %a  %b %d %H:%M:%S    %z   %Y
@@ -598,63 +2602,208 @@ fn extract_media_from_item(item: &Value) -> Option<Vec<MediaItem>> {
         })
         .unwrap_or(DateTime::<FixedOffset>::default());
 
-    let media_array = legacy
-        .pointer("/extended_entities/media")
-        .and_then(|v| v.as_array())?;
-
-    for media in media_array {
-        let media_type_str = media
-            .get("type")
-            .and_then(|v| v.as_str())
-            .unwrap_or("photo");
-
-        match media_type_str {
-            "photo" => {
-                if let Some(url) = media.get("media_url_https").and_then(|v| v.as_str()) {
-                    results.push(MediaItem {
-                        tweet_id: tweet_id.clone(),
-                        url: url.to_string(),
-                        media_type: MediaType::Image,
-                        timestamp,
-                        full_text: full_text.clone(),
-                    });
-                }
+    let mut media: Vec<FoundMedia> = legacy
+        .extended_entities
+        .iter()
+        .flat_map(|entities| &entities.media)
+        .filter_map(|media| media_url(media, tweet_id, options))
+        .collect();
+    if let Some(note) = tweet.note() {
+        for found in note_media(note, legacy, tweet_id, options) {
+            if !media.iter().any(|known| known.url == found.url) {
+                media.push(found);
             }
-            "video" | "animated_gif" => {
-                if let Some(variants) = media
-                    .pointer("/video_info/variants")
-                    .and_then(|v| v.as_array())
-                {
-                    let best_video = variants
-                        .iter()
-                        .filter(|v| {
-                            v.get("content_type")
-                                .and_then(|t| t.as_str())
-                                .map(|t| t.contains("mp4"))
-                                .unwrap_or(false)
-                        })
-                        .max_by_key(|v| v.get("bitrate").and_then(|b| b.as_u64()).unwrap_or(0));
-
-                    if let Some(video) = best_video
-                        && let Some(url) = video.get("url").and_then(|v| v.as_str())
-                    {
-                        results.push(MediaItem {
-                            tweet_id: tweet_id.clone(),
-                            url: url.to_string(),
-                            media_type: MediaType::Video,
-                            timestamp,
-                            full_text: full_text.clone(),
-                        });
-                    }
+        }
+    }
+    // Amplify and player videos only show up in the card
+    if media.is_empty()
+        && let Some(card) = tweet.card.as_ref().and_then(|c| c.legacy.as_ref())
+    {
+        media = card_media(card, tweet_id, options);
+    }
+
+    // `legacy.full_text` of notes is cut off
+    let full_text = tweet
+        .note()
+        .and_then(|note| note.text.clone())
+        .or_else(|| legacy.full_text.clone());
+
+    let engagement = Engagement::of(tweet, legacy);
+    let multiple = media.len() > 1;
+    media
+        .into_iter()
+        .enumerate()
+        .map(|(i, found)| MediaItem {
+            tweet_id: tweet_id.to_string(),
+            url: found.url,
+            download_url: found.download_url,
+            media_type: found.media_type,
+            poster_url: found.poster_url,
+            timestamp,
+            full_text: full_text.clone(),
+            index: multiple.then_some(i + 1),
+            is_retweet: legacy.retweeted_status_result.is_some(),
+            engagement,
+            pinned: false,
+            duration_ms: found.duration_ms,
+            resolution: found.resolution,
+        })
+        .collect()
+}
+
+/// URLs of a media found in a tweet
+pub(crate) struct FoundMedia {
+    pub(crate) url: String,
+    pub(crate) download_url: String,
+    pub(crate) media_type: MediaType,
+    pub(crate) poster_url: Option<String>,
+    pub(crate) duration_ms: Option<u64>,
+    pub(crate) resolution: Option<(u32, u32)>,
+}
+
+impl FoundMedia {
+    /// Media fetched from its canonical URL
+    fn at(url: String, media_type: MediaType) -> Self {
+        Self {
+            download_url: url.clone(),
+            url,
+            media_type,
+            poster_url: None,
+            duration_ms: None,
+            resolution: None,
+        }
+    }
+}
+
+/// Media embedded in a long-form note, resolved from their media IDs
+fn note_media(
+    note: &NoteTweetResult,
+    legacy: &TweetLegacy,
+    tweet_id: &str,
+    options: &ExtractOptions,
+) -> Vec<FoundMedia> {
+    let known = || {
+        note.entity_set
+            .iter()
+            .chain(&legacy.extended_entities)
+            .flat_map(|entities| &entities.media)
+    };
+    note.media
+        .iter()
+        .flat_map(|m| &m.inline_media)
+        .filter_map(|inline| {
+            let found = known().find(|m| m.id_str.as_deref() == Some(inline.media_id.as_str()));
+            if found.is_none() {
+                warn!(
+                    "inline media {} of note {} not found",
+                    inline.media_id, tweet_id
+                );
+            }
+            found.and_then(|media| media_url(media, tweet_id, options))
+        })
+        .collect()
+}
+
+/// URL of a photo, or of the best variant of a video
+///
+/// Videos are keyed by their thumbnail, which stays the same when the
+/// offered variants change.
+pub(crate) fn media_url(
+    media: &Media,
+    tweet_id: &str,
+    options: &ExtractOptions,
+) -> Option<FoundMedia> {
+    match media.kind {
+        MediaKind::Photo => media
+            .media_url_https
+            .clone()
+            .map(|url| FoundMedia::at(url, MediaType::Image)),
+        MediaKind::Video | MediaKind::AnimatedGif => {
+            let variants = || media.video_info.iter().flat_map(|info| &info.variants);
+            let mp4 = || {
+                variants().filter(|v| v.content_type.as_deref().is_some_and(|t| t.contains("mp4")))
+            };
+            let best_video = mp4()
+                .filter(|v| {
+                    options
+                        .max_video_bitrate
+                        .is_none_or(|max| v.bitrate.unwrap_or(0) <= max)
+                })
+                .max_by_key(|v| v.bitrate.unwrap_or(0))
+                // Every variant is above the limit, the smallest comes closest
+                .or_else(|| mp4().min_by_key(|v| v.bitrate.unwrap_or(0)))
+                // Some videos are only offered as HLS
+                .or_else(|| {
+                    variants().find(|v| {
+                        v.content_type
+                            .as_deref()
+                            .is_some_and(|t| t.eq_ignore_ascii_case(hls::PLAYLIST_CONTENT_TYPE))
+                    })
+                });
+            match best_video {
+                Some(video) => Some(FoundMedia {
+                    url: media
+                        .media_url_https
+                        .clone()
+                        .unwrap_or_else(|| video.url.clone()),
+                    download_url: video.url.clone(),
+                    media_type: MediaType::Video,
+                    poster_url: media.media_url_https.clone(),
+                    duration_ms: media.video_info.as_ref().and_then(|i| i.duration_millis),
+                    resolution: variant_resolution(&video.url)
+                        .or_else(|| media.original_info.as_ref().map(|i| (i.width, i.height))),
+                }),
+                None => {
+                    warn!("no downloadable variant for video in tweet {}", tweet_id);
+                    None
                 }
             }
-            _ => {}
         }
+        MediaKind::Unknown => None,
     }
+}
 
-    if results.is_empty() {
-        None
-    } else {
-        Some(results)
+/// Width and height in a variant URL, e.g. `.../vid/1280x720/high.mp4`
+pub(crate) fn variant_resolution(url: &str) -> Option<(u32, u32)> {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.split('/').find_map(|segment| {
+        let (width, height) = segment.split_once('x')?;
+        Some((width.parse().ok()?, height.parse().ok()?))
+    })
+}
+
+/// Videos attached to a card, link previews have none
+fn card_media(card: &CardLegacy, tweet_id: &str, options: &ExtractOptions) -> Vec<FoundMedia> {
+    if let Some(payload) = card.string_value("unified_card") {
+        return match serde_json::from_str::<UnifiedCard>(payload) {
+            Ok(unified) => unified
+                .media_entities
+                .values()
+                .filter_map(|media| media_url(media, tweet_id, options))
+                .collect(),
+            Err(e) => {
+                warn!("invalid unified card in tweet {}: {}", tweet_id, e);
+                Vec::new()
+            }
+        };
+    }
+
+    // Player cards of amplify videos link the stream directly, players of
+    // external sites embed a page instead
+    let is_video = card
+        .string_value("player_stream_content_type")
+        .is_some_and(|t| {
+            t.starts_with("video/") || t.eq_ignore_ascii_case(hls::PLAYLIST_CONTENT_TYPE)
+        });
+    match card.string_value("player_stream_url") {
+        Some(url) if is_video => {
+            debug!(
+                "using player stream of {} card in tweet {}",
+                card.name.as_deref().unwrap_or("unnamed"),
+                tweet_id
+            );
+            vec![FoundMedia::at(url.to_string(), MediaType::Video)]
+        }
+        _ => Vec::new(),
     }
 }
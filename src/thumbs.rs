@@ -0,0 +1,416 @@
+//! `rxd thumbs`: small previews of downloaded images for browsing.
+//!
+//! Thumbnails are written with `ffmpeg` into a `.thumbs` folder in each
+//! account's folder, mirroring the names of the images, e.g.
+//! `.thumbs/abc.jpg` for `abc.png`. A thumbnail newer than its image is up
+//! to date and not made again, so a run can be interrupted and repeated.
+//! Images `ffmpeg` cannot read are reported and skipped.
+//!
+//! Videos get a frame as their `<name>.thumb.jpg` next to them instead,
+//! the file a saved poster goes to, either by [`FrameExtractor`] right
+//! after they are downloaded or later with [`ThumbOptions::videos`].
+
+use std::ffi::OsStr;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, SystemTime};
+
+use clap::ValueEnum;
+use futures::StreamExt;
+use sqlx::SqlitePool;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+use tracing::{debug, instrument, warn};
+
+use crate::db;
+use crate::error::{Error, Result};
+use crate::task::thumbnail_path;
+use crate::verify::VIDEO_EXTENSIONS;
+
+/// Folder of the thumbnails inside an account's folder
+pub const THUMBS_DIR: &str = ".thumbs";
+
+/// Extensions of image files thumbnails are made of
+pub const IMAGE_EXTENSIONS: [&str; 4] = ["jpg", "jpeg", "png", "webp"];
+
+/// Seconds into a video its thumbnail frame is taken from
+const FRAME_AT: &str = "1";
+
+/// File type of thumbnails
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ThumbFormat {
+    #[default]
+    Jpeg,
+    /// Smaller, needs an `ffmpeg` built with libwebp
+    Webp,
+}
+
+impl ThumbFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ThumbFormat::Jpeg => "jpg",
+            ThumbFormat::Webp => "webp",
+        }
+    }
+
+    /// `ffmpeg` encoder arguments
+    fn codec(self) -> &'static [&'static str] {
+        match self {
+            ThumbFormat::Jpeg => &["-c:v", "mjpeg", "-q:v", "4"],
+            ThumbFormat::Webp => &["-c:v", "libwebp", "-quality", "75"],
+        }
+    }
+}
+
+/// How thumbnails are made
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ThumbOptions {
+    /// Longest side in pixels, smaller images keep their size
+    pub size: u32,
+    pub format: ThumbFormat,
+    /// Thumbnails made at once
+    pub concurrency: usize,
+    /// The `ffmpeg` program to run
+    pub ffmpeg: PathBuf,
+    /// Longest an `ffmpeg` run may take before the file is skipped
+    pub timeout: Duration,
+    /// Also save a frame of videos without a thumbnail
+    pub videos: bool,
+}
+
+impl Default for ThumbOptions {
+    fn default() -> Self {
+        Self {
+            size: 400,
+            format: ThumbFormat::default(),
+            concurrency: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            ffmpeg: PathBuf::from("ffmpeg"),
+            timeout: Duration::from_secs(60),
+            videos: false,
+        }
+    }
+}
+
+impl ThumbOptions {
+    /// Longest side in pixels, smaller images keep their size
+    pub fn size(mut self, size: u32) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn format(mut self, format: ThumbFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Thumbnails made at once
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// The `ffmpeg` program to run
+    pub fn ffmpeg(mut self, ffmpeg: impl Into<PathBuf>) -> Self {
+        self.ffmpeg = ffmpeg.into();
+        self
+    }
+
+    /// Longest an `ffmpeg` run may take before the file is skipped
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Also save a frame of videos without a thumbnail
+    pub fn videos(mut self, videos: bool) -> Self {
+        self.videos = videos;
+        self
+    }
+}
+
+/// An image no thumbnail could be made of
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedThumb {
+    pub path: PathBuf,
+    /// What `ffmpeg` said
+    pub reason: String,
+}
+
+/// Counts of a run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ThumbSummary {
+    pub made: u64,
+    /// Thumbnails newer than their image
+    pub up_to_date: u64,
+    /// Recorded images not on disk
+    pub missing: u64,
+    /// Unreadable or corrupt images
+    pub failed: Vec<FailedThumb>,
+}
+
+/// Thumbnail of the image `filename` in the account folder `folder`
+pub fn thumb_path(folder: &Path, filename: &str, format: ThumbFormat) -> PathBuf {
+    folder
+        .join(THUMBS_DIR)
+        .join(filename)
+        .with_extension(format.extension())
+}
+
+/// Whether the program `ffmpeg` can be run
+pub async fn ffmpeg_available(ffmpeg: &Path) -> bool {
+    Command::new(ffmpeg)
+        .arg("-version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .status()
+        .await
+        .is_ok_and(|status| status.success())
+}
+
+/// What became of one image
+enum Outcome {
+    Made,
+    UpToDate,
+    Missing,
+    Failed(String),
+}
+
+/// Make the missing and outdated thumbnails of the downloaded images of
+/// `screen_name`, or of every account, and with [`ThumbOptions::videos`]
+/// those of its videos
+///
+/// Files are looked up in the folder `folder` returns for their account
+/// and `progress` is called with the number of handled images and the
+/// total. Fails only if `ffmpeg` cannot be run at all.
+#[instrument(skip_all)]
+pub async fn make_thumbs(
+    pool: &SqlitePool,
+    screen_name: Option<&str>,
+    folder: impl Fn(&str) -> PathBuf,
+    options: &ThumbOptions,
+    mut progress: impl FnMut(u64, u64),
+) -> Result<ThumbSummary> {
+    let files: Vec<(String, PathBuf, PathBuf)> = db::account_files(pool, screen_name)
+        .await?
+        .into_iter()
+        .filter_map(|file| {
+            let folder = folder(&file.screen_name);
+            let path = folder.join(&file.filename);
+            let thumb = if is_video(&path) && options.videos {
+                thumbnail_path(&path)
+            } else if is_image(&path) {
+                thumb_path(&folder, &file.filename, options.format)
+            } else {
+                return None;
+            };
+            Some((file.media_url, path, thumb))
+        })
+        .collect();
+    let frames = FrameExtractor::new(&options.ffmpeg, options.timeout, options.concurrency);
+    let mut summary = ThumbSummary::default();
+
+    let total = files.len() as u64;
+    let mut done = 0;
+    progress(0, total);
+    let frames = &frames;
+    let mut thumbs = futures::stream::iter(files)
+        .map(|(media_url, image, thumb)| async move {
+            let outcome = make_thumb(&image, &thumb, options, frames).await;
+            (media_url, image, thumb, outcome)
+        })
+        .buffer_unordered(options.concurrency.max(1));
+
+    while let Some((media_url, image, thumb, outcome)) = thumbs.next().await {
+        done += 1;
+        progress(done, total);
+        match outcome? {
+            Outcome::Made if is_video(&image) => {
+                let filename = thumb.file_name().and_then(|n| n.to_str());
+                db::update_thumbnail(pool, &media_url, filename).await?;
+                summary.made += 1;
+            }
+            Outcome::Made => summary.made += 1,
+            Outcome::UpToDate => summary.up_to_date += 1,
+            Outcome::Missing => summary.missing += 1,
+            Outcome::Failed(reason) => {
+                warn!("no thumbnail of {}: {}", image.display(), reason);
+                summary.failed.push(FailedThumb {
+                    path: image,
+                    reason,
+                });
+            }
+        }
+    }
+    Ok(summary)
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| VIDEO_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+async fn modified(path: &Path) -> std::io::Result<SystemTime> {
+    tokio::fs::metadata(path).await?.modified()
+}
+
+/// Make the thumbnail of `image` at `thumb` unless it is up to date
+async fn make_thumb(
+    image: &Path,
+    thumb: &Path,
+    options: &ThumbOptions,
+    frames: &FrameExtractor,
+) -> Result<Outcome> {
+    let image_modified = match modified(image).await {
+        Ok(modified) => modified,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Outcome::Missing),
+        Err(e) => return Ok(Outcome::Failed(e.to_string())),
+    };
+    if modified(thumb).await.is_ok_and(|t| t >= image_modified) {
+        return Ok(Outcome::UpToDate);
+    }
+    if let Some(parent) = thumb.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    debug!("making thumbnail {}", thumb.display());
+    let result = if is_video(image) {
+        frames.extract(image, thumb).await
+    } else {
+        let size = options.size.max(1);
+        let scale =
+            format!("scale='min(iw,{size})':'min(ih,{size})':force_original_aspect_ratio=decrease");
+        let mut args: Vec<&OsStr> = vec!["-i".as_ref(), image.as_os_str()];
+        args.extend(["-vf", &scale].map(OsStr::new));
+        args.extend(options.format.codec().iter().map(OsStr::new));
+        match run_ffmpeg(&options.ffmpeg, &args, thumb, options.timeout).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(Error::Thumbnail("ffmpeg wrote no image".to_string())),
+            Err(e) => Err(e),
+        }
+    };
+    match result {
+        Ok(()) => Ok(Outcome::Made),
+        Err(Error::Thumbnail(reason)) => Ok(Outcome::Failed(reason)),
+        Err(e @ Error::Timeout(_)) => Ok(Outcome::Failed(e.to_string())),
+        Err(e) => Err(e),
+    }
+}
+
+/// Run `ffmpeg` with `args` writing one frame as `output`, `false` if it
+/// succeeded without writing any
+///
+/// The frame is written next to `output` and only moved there once
+/// complete.
+async fn run_ffmpeg(
+    ffmpeg: &Path,
+    args: &[&OsStr],
+    output: &Path,
+    timeout: Duration,
+) -> Result<bool> {
+    let mut partial = output.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+    let mut command = Command::new(ffmpeg);
+    command
+        .args(["-nostdin", "-loglevel", "error", "-y"])
+        .args(args)
+        .args(["-frames:v", "1", "-update", "1", "-f", "image2"])
+        .arg(&partial)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let result = match tokio::time::timeout(timeout, command.output()).await {
+        Err(_) => Err(Error::Timeout(timeout)),
+        Ok(Err(e)) if e.kind() == ErrorKind::NotFound => Err(Error::Config(format!(
+            "thumbnails need {}, which is not installed",
+            ffmpeg.display()
+        ))),
+        Ok(Err(e)) => Err(e.into()),
+        Ok(Ok(run)) if !run.status.success() => {
+            let reason = String::from_utf8_lossy(&run.stderr).trim().to_string();
+            Err(Error::Thumbnail(if reason.is_empty() {
+                format!("ffmpeg failed with {}", run.status)
+            } else {
+                reason
+            }))
+        }
+        Ok(Ok(_)) => match tokio::fs::metadata(&partial).await {
+            Ok(written) if written.len() > 0 => {
+                tokio::fs::rename(&partial, output).await?;
+                return Ok(true);
+            }
+            _ => Ok(false),
+        },
+    };
+    let _ = tokio::fs::remove_file(&partial).await;
+    result
+}
+
+/// Saves a frame of downloaded videos as their `<name>.thumb.jpg` with
+/// `ffmpeg`
+///
+/// Shared by every task of a run, so the cap on `ffmpeg` processes applies
+/// to the whole run.
+#[derive(Debug)]
+pub struct FrameExtractor {
+    ffmpeg: PathBuf,
+    timeout: Duration,
+    permits: Semaphore,
+}
+
+impl FrameExtractor {
+    pub fn new(ffmpeg: impl Into<PathBuf>, timeout: Duration, concurrency: usize) -> Self {
+        Self {
+            ffmpeg: ffmpeg.into(),
+            timeout,
+            permits: Semaphore::new(concurrency.max(1)),
+        }
+    }
+
+    /// Whether `ffmpeg` can be run
+    pub async fn is_available(&self) -> bool {
+        ffmpeg_available(&self.ffmpeg).await
+    }
+
+    /// Save the frame about a second into `video` as the JPEG `thumb`, the
+    /// first frame of shorter videos, waiting for a free slot first
+    #[instrument(skip_all)]
+    pub async fn extract(&self, video: &Path, thumb: &Path) -> Result<()> {
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .map_err(|e| Error::Thumbnail(e.to_string()))?;
+        for seek in [FRAME_AT, "0"] {
+            let args = [
+                "-ss".as_ref(),
+                seek.as_ref(),
+                "-i".as_ref(),
+                video.as_os_str(),
+            ];
+            let args: Vec<&OsStr> = args
+                .into_iter()
+                .chain(ThumbFormat::Jpeg.codec().iter().map(OsStr::new))
+                .collect();
+            if run_ffmpeg(&self.ffmpeg, &args, thumb, self.timeout).await? {
+                return Ok(());
+            }
+        }
+        Err(Error::Thumbnail(format!("no frame in {}", video.display())))
+    }
+}
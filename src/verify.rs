@@ -0,0 +1,285 @@
+//! Verification of downloaded files against their recorded hashes, and
+//! repair of damaged ones by downloading them again.
+//!
+//! Repaired files keep their recorded name and are replaced at once, a
+//! failed repair leaves the damaged file as it was.
+
+use std::path::PathBuf;
+
+use futures::StreamExt;
+use reqwest::StatusCode;
+use sqlx::SqlitePool;
+use tracing::{debug, instrument, warn};
+
+use crate::api::Api;
+use crate::db::{self, HashedMedia};
+use crate::error::{Error, Result};
+use crate::hash::HashAlgorithm;
+use crate::hls;
+use crate::pushback;
+use crate::task::{self, Body, ImageSize};
+
+/// Extensions of files saved from video URLs
+pub(crate) const VIDEO_EXTENSIONS: [&str; 4] = ["mp4", "m4v", "mov", "ts"];
+
+/// What is wrong with a downloaded file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Damage {
+    /// Not on disk
+    Missing,
+    /// On disk with a different hash than recorded
+    Mismatch,
+}
+
+/// A downloaded file that does not verify
+#[derive(Debug, Clone)]
+pub struct Damaged {
+    pub media: HashedMedia,
+    pub path: PathBuf,
+    pub damage: Damage,
+}
+
+/// Outcome of a verification
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    /// Files matching their recorded hash
+    pub intact: u64,
+    /// Files hashed with an algorithm unknown to this version
+    pub unchecked: u64,
+    /// Files that could not be read
+    pub unreadable: u64,
+    pub damaged: Vec<Damaged>,
+}
+
+/// Check the recorded files of `screen_name`, or of every account, against
+/// their hashes
+///
+/// Files are looked up in the folder `folder` returns for their account.
+/// Up to `threads` files are hashed at once and `progress` is called with
+/// the number of checked files and the total.
+#[instrument(skip_all)]
+pub async fn verify(
+    pool: &SqlitePool,
+    screen_name: Option<&str>,
+    folder: impl Fn(&str) -> PathBuf,
+    threads: usize,
+    mut progress: impl FnMut(u64, u64),
+) -> Result<VerifyReport> {
+    let recorded = db::hashed_media(pool, screen_name).await?;
+    let mut report = VerifyReport::default();
+
+    let total = recorded.len() as u64;
+    let mut done = 0;
+    progress(0, total);
+    let mut checked = futures::stream::iter(recorded)
+        .map(|media| {
+            let path = folder(&media.screen_name).join(&media.filename);
+            async move {
+                let result = match media.hash_algorithm {
+                    Some(algorithm) => hash_file(path.clone(), algorithm).await.map(Some),
+                    None => Ok(None),
+                };
+                (media, path, result)
+            }
+        })
+        .buffer_unordered(threads.max(1));
+
+    while let Some((media, path, result)) = checked.next().await {
+        done += 1;
+        progress(done, total);
+        let damage = match result {
+            Ok(Some(hash)) if hash == media.file_hash => {
+                report.intact += 1;
+                continue;
+            }
+            Ok(Some(_)) => Damage::Mismatch,
+            Ok(None) => {
+                debug!("unknown hash algorithm for {}", path.display());
+                report.unchecked += 1;
+                continue;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Damage::Missing,
+            Err(e) => {
+                warn!("failed to read {}: {}", path.display(), e);
+                report.unreadable += 1;
+                continue;
+            }
+        };
+        report.damaged.push(Damaged {
+            media,
+            path,
+            damage,
+        });
+    }
+    Ok(report)
+}
+
+async fn hash_file(path: PathBuf, algorithm: HashAlgorithm) -> std::io::Result<String> {
+    tokio::task::spawn_blocking(move || algorithm.hash_file(&path))
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+/// How damaged files are downloaded again
+#[derive(Debug, Clone)]
+pub struct RepairOptions {
+    /// Files downloaded at once
+    pub concurrency: usize,
+    /// Preferred size of images, smaller ones are tried when it is gone
+    pub image_size: ImageSize,
+    /// Hash recorded for repaired files
+    pub hash_algorithm: HashAlgorithm,
+    pub max_file_size: Option<u64>,
+}
+
+/// Counts of a repair
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RepairSummary {
+    /// Files downloaded again and recorded with their new hash
+    pub repaired: u64,
+    /// Files whose media is gone or was never downloaded from a URL
+    pub unrepairable: u64,
+    /// Files whose download failed for now
+    pub failed: u64,
+}
+
+/// Download `damaged` files again over the damaged ones
+///
+/// `progress` is called with the number of handled files and the total.
+#[instrument(skip_all)]
+pub async fn repair(
+    pool: &SqlitePool,
+    api: &Api,
+    damaged: Vec<Damaged>,
+    options: &RepairOptions,
+    mut progress: impl FnMut(u64, u64),
+) -> Result<RepairSummary> {
+    let mut summary = RepairSummary::default();
+    let total = damaged.len() as u64;
+    let mut done = 0;
+    progress(0, total);
+    let mut repairs = futures::stream::iter(damaged)
+        .map(|damaged| async move {
+            let result = download_again(pool, api, &damaged, options).await;
+            (damaged, result)
+        })
+        .buffer_unordered(options.concurrency.max(1));
+
+    while let Some((damaged, result)) = repairs.next().await {
+        done += 1;
+        progress(done, total);
+        let media_url = &damaged.media.media_url;
+        match result {
+            Ok(Some(repaired)) => {
+                db::update_hash(pool, media_url, &repaired.hash, options.hash_algorithm).await?;
+                db::update_download_url(pool, media_url, &repaired.download_url).await?;
+                if let Some(image_size) = repaired.image_size {
+                    db::update_image_size(pool, media_url, image_size).await?;
+                }
+                summary.repaired += 1;
+            }
+            Ok(None) => {
+                warn!(
+                    "{} is gone, {} is left as is",
+                    media_url,
+                    damaged.path.display()
+                );
+                summary.unrepairable += 1;
+            }
+            Err(e) => {
+                warn!("failed to repair {}: {}", damaged.path.display(), e);
+                summary.failed += 1;
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// A file downloaded again
+struct Repaired {
+    hash: String,
+    download_url: String,
+    image_size: Option<&'static str>,
+}
+
+/// Download the media of `damaged` over its file, `None` if it is gone
+///
+/// Media recorded as gone is not requested, media found gone is recorded.
+async fn download_again(
+    pool: &SqlitePool,
+    api: &Api,
+    damaged: &Damaged,
+    options: &RepairOptions,
+) -> Result<Option<Repaired>> {
+    let media = &damaged.media;
+    if media.gone {
+        return Ok(None);
+    }
+    let is_video = damaged
+        .path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| VIDEO_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()));
+    let url = if is_video {
+        media.download_url.as_deref().unwrap_or(&media.media_url)
+    } else {
+        &media.media_url
+    };
+    // Placeholders of imported files
+    if !url.starts_with("http") {
+        return Ok(None);
+    }
+    if let Some(parent) = damaged.path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let (body, download_url, image_size) = if is_video && hls::is_playlist(url) {
+        let scratch = damaged.path.with_extension("hls.part");
+        let video =
+            match hls::download(api.client(), url, &scratch, options.max_file_size, |_| {}).await {
+                Err(Error::Download(status)) if is_gone(status) => {
+                    db::mark_gone(pool, &media.media_url).await?;
+                    return Ok(None);
+                }
+                result => result?,
+            };
+        (Body::Bytes(video.bytes), url.to_string(), None)
+    } else {
+        let (response, image_size) = if is_video {
+            (api.send(api.client().get(url)).await?, None)
+        } else {
+            let (response, size) = task::request_image(api, url, options.image_size, None).await?;
+            (response, Some(size))
+        };
+        if is_gone(response.status()) {
+            db::mark_gone(pool, &media.media_url).await?;
+            return Ok(None);
+        }
+        pushback::check(&response)?;
+        if !response.status().is_success() {
+            return Err(Error::Download(response.status()));
+        }
+        let download_url = response.url().to_string();
+        let total = response.content_length();
+        (Body::Response { response, total }, download_url, image_size)
+    };
+
+    let (hash, _) = task::save_body(
+        body,
+        &damaged.path,
+        options.hash_algorithm,
+        options.max_file_size,
+        |_, _| {},
+    )
+    .await?;
+    Ok(Some(Repaired {
+        hash,
+        download_url,
+        image_size,
+    }))
+}
+
+/// Whether the media was removed from the server
+pub(crate) fn is_gone(status: StatusCode) -> bool {
+    status == StatusCode::NOT_FOUND || status == StatusCode::GONE
+}
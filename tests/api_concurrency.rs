@@ -0,0 +1,70 @@
+//! The limit on GraphQL requests in flight, shared by clones of a client.
+
+use std::time::{Duration, Instant};
+
+use rxd::Api;
+use serde_json::json;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const USER_BY_SCREEN_NAME: &str = "/i/api/graphql/xc8f1g7BYqr6VTzTbvNlGw/UserByScreenName";
+const DELAY: Duration = Duration::from_millis(300);
+
+async fn slow_server() -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "data": { "user": { "result": {
+                        "__typename": "User",
+                        "rest_id": "42",
+                        "legacy": { "name": "Test User", "media_count": 1 }
+                    }}}
+                }))
+                .set_delay(DELAY),
+        )
+        .mount(&server)
+        .await;
+    server
+}
+
+/// Time three lookups started at once through clones of `api` take
+async fn three_lookups(api: Api) -> Duration {
+    let (a, b, c) = (api.clone(), api.clone(), api);
+    let started = Instant::now();
+    let (a, b, c) = tokio::join!(
+        a.user_by_screen_name("a"),
+        b.user_by_screen_name("b"),
+        c.user_by_screen_name("c"),
+    );
+    for result in [a, b, c] {
+        result.expect("lookup");
+    }
+    started.elapsed()
+}
+
+#[tokio::test]
+async fn requests_wait_for_a_free_slot_across_clones() {
+    let server = slow_server().await;
+    let api = Api::with_base_url("token", "ct0", &server.uri())
+        .expect("api")
+        .with_api_concurrency(1);
+
+    let elapsed = three_lookups(api).await;
+
+    assert!(elapsed >= DELAY * 3, "{elapsed:?}");
+}
+
+#[tokio::test]
+async fn requests_run_side_by_side_up_to_the_limit() {
+    let server = slow_server().await;
+    let api = Api::with_base_url("token", "ct0", &server.uri())
+        .expect("api")
+        .with_api_concurrency(3);
+
+    let elapsed = three_lookups(api).await;
+
+    assert!(elapsed < DELAY * 2, "{elapsed:?}");
+}
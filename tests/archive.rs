@@ -0,0 +1,125 @@
+//! Raw API responses kept in `<save_path>/_raw`.
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use rxd::archive::{RAW_DIR, ResponseArchive};
+use rxd::{Api, Task};
+use serde_json::json;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const USER_BY_SCREEN_NAME: &str = "/i/api/graphql/xc8f1g7BYqr6VTzTbvNlGw/UserByScreenName";
+const USER_MEDIA: &str = "/i/api/graphql/Le6KlbilFmSu-5VltFND-Q/UserMedia";
+
+async fn mount_account(server: &MockServer) {
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": { "user": { "result": {
+                "__typename": "User",
+                "rest_id": "42",
+                "legacy": { "name": "Test User", "media_count": 1 }
+            }}}
+        })))
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": { "user": { "result": { "timeline_v2": { "timeline": {
+                "instructions": []
+            }}}}}
+        })))
+        .mount(server)
+        .await;
+}
+
+async fn run_archived(server: &MockServer, save_path: &Path, archive: ResponseArchive) {
+    let db = rxd::db::init_memory_db().await.expect("db");
+    let task = Task::builder()
+        .api(Api::with_base_url("token", "ct0", &server.uri()).expect("api"))
+        .screen_name("test_user")
+        .save_path(save_path)
+        .archive_responses(archive)
+        .db(db)
+        .build()
+        .await
+        .expect("task");
+    Arc::new(task).execute().await.expect("execute");
+}
+
+/// Names in the archive, sorted
+fn archived(save_path: &Path) -> Vec<String> {
+    let mut names: Vec<_> = std::fs::read_dir(save_path.join(RAW_DIR))
+        .expect("raw dir")
+        .map(|e| e.expect("entry").file_name().into_string().expect("utf-8"))
+        .collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn responses_are_archived_before_parsing() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_account(&server).await;
+
+    run_archived(&server, dir.path(), ResponseArchive::default()).await;
+    run_archived(&server, dir.path(), ResponseArchive::default()).await;
+
+    let names = archived(dir.path());
+    assert_eq!(names.len(), 4, "{names:?}");
+    let lookups: Vec<_> = names
+        .iter()
+        .filter(|n| n.ends_with("-UserByScreenName.json"))
+        .collect();
+    let pages: Vec<_> = names
+        .iter()
+        .filter(|n| n.ends_with("-UserMedia-page1.json"))
+        .collect();
+    assert_eq!((lookups.len(), pages.len()), (2, 2), "{names:?}");
+    let page = std::fs::read_to_string(dir.path().join(RAW_DIR).join(pages[0])).expect("read");
+    assert!(page.contains("timeline_v2"), "{page}");
+}
+
+#[tokio::test]
+async fn old_responses_are_pruned() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_account(&server).await;
+    let raw = dir.path().join(RAW_DIR);
+    std::fs::create_dir_all(&raw).expect("raw dir");
+    let old = std::fs::File::create(raw.join("old-UserMedia-page1.json")).expect("old");
+    old.set_modified(SystemTime::now() - Duration::from_secs(10 * 24 * 60 * 60))
+        .expect("backdate");
+    std::fs::write(raw.join("recent-UserMedia-page1.json"), "{}").expect("recent");
+
+    let archive = ResponseArchive::default().max_age_days(Some(7));
+    run_archived(&server, dir.path(), archive).await;
+
+    let names = archived(dir.path());
+    assert!(!names.iter().any(|n| n.starts_with("old-")), "{names:?}");
+    assert!(names.iter().any(|n| n.starts_with("recent-")), "{names:?}");
+}
+
+#[tokio::test]
+async fn compressed_responses_are_gzipped() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let archive = ResponseArchive::default().compress(true);
+
+    let path = match archive
+        .write(dir.path(), "UserMedia", Some(1), "{\"data\":{}}")
+        .await
+    {
+        Ok(path) => path,
+        // Not every machine running the tests has gzip
+        Err(rxd::Error::Config(e)) if e.contains("not installed") => return,
+        Err(e) => panic!("{e}"),
+    };
+
+    assert!(path.to_string_lossy().ends_with("-UserMedia-page1.json.gz"));
+    let bytes = std::fs::read(&path).expect("read");
+    assert_eq!(bytes[..2], [0x1f, 0x8b]);
+}
@@ -0,0 +1,97 @@
+//! Classification of rejected credentials.
+
+use reqwest::StatusCode;
+use rxd::api::AuthFailure;
+use rxd::{Api, Error};
+use serde_json::json;
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const USER_BY_SCREEN_NAME: &str = "/i/api/graphql/xc8f1g7BYqr6VTzTbvNlGw/UserByScreenName";
+const ACCOUNT_SETTINGS: &str = "/i/api/1.1/account/settings.json";
+
+fn error_body(code: u32) -> String {
+    json!({ "errors": [{ "code": code, "message": "..." }] }).to_string()
+}
+
+#[test]
+fn classifies_error_codes() {
+    let classify = |status: u16, body: &str| {
+        AuthFailure::classify(StatusCode::from_u16(status).expect("status"), body)
+    };
+
+    assert_eq!(
+        classify(401, &error_body(32)),
+        Some(AuthFailure::InvalidToken)
+    );
+    assert_eq!(
+        classify(403, &error_body(353)),
+        Some(AuthFailure::CsrfMismatch)
+    );
+    assert_eq!(
+        classify(403, &error_body(326)),
+        Some(AuthFailure::AccountLocked)
+    );
+    assert_eq!(classify(403, &error_body(64)), Some(AuthFailure::Suspended));
+    // Any other 401 is about the token, other errors are not about credentials
+    assert_eq!(classify(401, "not json"), Some(AuthFailure::InvalidToken));
+    assert_eq!(classify(403, &error_body(200)), None);
+    assert_eq!(classify(404, ""), None);
+}
+
+#[tokio::test]
+async fn verify_credentials_returns_screen_name() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(ACCOUNT_SETTINGS))
+        .and(header("x-csrf-token", "ct0"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "screen_name": "archivist" })),
+        )
+        .mount(&server)
+        .await;
+
+    let api = Api::with_base_url("token", "ct0", &server.uri()).expect("api");
+
+    assert_eq!(
+        api.verify_credentials().await.expect("accepted"),
+        "archivist"
+    );
+}
+
+#[tokio::test]
+async fn verify_credentials_classifies_rejection() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(ACCOUNT_SETTINGS))
+        .respond_with(ResponseTemplate::new(403).set_body_string(error_body(353)))
+        .mount(&server)
+        .await;
+
+    let api = Api::with_base_url("token", "wrong", &server.uri()).expect("api");
+    let error = api.verify_credentials().await.expect_err("rejected");
+
+    assert!(matches!(error, Error::Auth(AuthFailure::CsrfMismatch)));
+}
+
+#[tokio::test]
+async fn rejected_lookups_during_a_run_are_classified() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .respond_with(ResponseTemplate::new(403).set_body_string(error_body(326)))
+        .mount(&server)
+        .await;
+
+    let api = Api::with_base_url("token", "ct0", &server.uri()).expect("api");
+    let error = api
+        .user_by_screen_name("test_user")
+        .await
+        .expect_err("rejected");
+
+    assert!(matches!(error, Error::Auth(AuthFailure::AccountLocked)));
+    assert_eq!(
+        error.to_string(),
+        "credentials rejected: the account is locked"
+    );
+}
@@ -0,0 +1,134 @@
+//! `SHA256SUMS` files of the downloads.
+
+use std::time::{Duration, SystemTime};
+
+use common::{folder, media};
+use rxd::checksums::{self, SHA256SUMS};
+use rxd::db;
+use rxd::hash::HashAlgorithm;
+
+mod common;
+
+fn sha256(content: &[u8]) -> String {
+    HashAlgorithm::Sha256.hash(content)
+}
+
+#[tokio::test]
+async fn one_file_per_account_from_recorded_and_computed_hashes() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let pool = db::init_memory_db().await.expect("db");
+    for screen_name in ["alice", "bob"] {
+        std::fs::create_dir(dir.path().join(screen_name)).expect("mkdir");
+    }
+    // The recorded hash is trusted without reading the file
+    let (alice, bob) = (dir.path().join("alice"), dir.path().join("bob"));
+    media("b.jpg")
+        .hash("recorded")
+        .file(&alice, b"b")
+        .record(&pool)
+        .await;
+    media("a.jpg").file(&alice, b"a").record(&pool).await;
+    media("gone.jpg").record(&pool).await;
+    media("c.jpg")
+        .user("bob")
+        .tweet("2")
+        .file(&bob, b"c")
+        .record(&pool)
+        .await;
+
+    let summary = checksums::write(&pool, None, folder(dir.path()), None, 2)
+        .await
+        .expect("write");
+
+    assert_eq!(summary.written, 2);
+    assert_eq!(summary.entries, 3);
+    assert_eq!(summary.hashed, 2);
+    assert_eq!(summary.missing, 1);
+    let alice = std::fs::read_to_string(dir.path().join("alice").join(SHA256SUMS)).expect("read");
+    assert_eq!(alice, format!("{}  a.jpg\nrecorded  b.jpg\n", sha256(b"a")));
+    let bob = std::fs::read_to_string(dir.path().join("bob").join(SHA256SUMS)).expect("read");
+    assert_eq!(bob, format!("{}  c.jpg\n", sha256(b"c")));
+}
+
+#[tokio::test]
+async fn changed_files_are_hashed_again_and_deleted_ones_dropped() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let pool = db::init_memory_db().await.expect("db");
+    let files = dir.path().join("alice");
+    std::fs::create_dir(&files).expect("mkdir");
+    for (name, content) in [("a.jpg", b"a"), ("b.jpg", b"b")] {
+        media(name)
+            .hash_of(content)
+            .file(&files, content)
+            .record(&pool)
+            .await;
+    }
+    checksums::write(&pool, Some("alice"), folder(dir.path()), None, 1)
+        .await
+        .expect("write");
+
+    std::fs::remove_file(files.join("b.jpg")).expect("remove");
+    std::fs::write(files.join("a.jpg"), b"changed").expect("write");
+    let later = SystemTime::now() + Duration::from_secs(60);
+    std::fs::File::options()
+        .write(true)
+        .open(files.join("a.jpg"))
+        .and_then(|file| file.set_modified(later))
+        .expect("set mtime");
+    let summary = checksums::write(&pool, Some("alice"), folder(dir.path()), None, 1)
+        .await
+        .expect("write");
+
+    assert_eq!(summary.hashed, 1);
+    assert_eq!(summary.missing, 1);
+    let alice = std::fs::read_to_string(files.join(SHA256SUMS)).expect("read");
+    assert_eq!(alice, format!("{}  a.jpg\n", sha256(b"changed")));
+}
+
+#[tokio::test]
+async fn a_single_file_in_the_root_lists_paths_below_it() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let pool = db::init_memory_db().await.expect("db");
+    let root = dir.path().join("downloads");
+    // carol is outside of the root, left out
+    for (screen_name, folder) in [
+        ("alice", root.join("alice")),
+        ("bob", root.join("bob")),
+        ("carol", dir.path().join("carol")),
+    ] {
+        std::fs::create_dir_all(&folder).expect("mkdir");
+        media("a.jpg")
+            .user(screen_name)
+            .tweet(screen_name)
+            .media_url(&format!("https://pbs.twimg.com/media/{screen_name}-a.jpg"))
+            .file(&folder, screen_name.as_bytes())
+            .record(&pool)
+            .await;
+    }
+
+    let summary = checksums::write(
+        &pool,
+        None,
+        |screen_name: &str| match screen_name {
+            "carol" => dir.path().join("carol"),
+            other => root.join(other),
+        },
+        Some(&root),
+        2,
+    )
+    .await
+    .expect("write");
+
+    assert_eq!(summary.written, 1);
+    assert_eq!(summary.skipped, 1);
+    let sums = std::fs::read_to_string(root.join(SHA256SUMS)).expect("read");
+    assert_eq!(
+        sums,
+        format!(
+            "{}  alice/a.jpg\n{}  bob/a.jpg\n",
+            sha256(b"alice"),
+            sha256(b"bob")
+        )
+    );
+    assert!(!root.join("alice").join(SHA256SUMS).exists());
+}
@@ -0,0 +1,50 @@
+//! Values of command-line options.
+
+use std::time::Duration;
+
+use rxd::cli::parse_duration;
+
+#[test]
+fn durations_take_a_unit() {
+    let cases = [
+        ("90", 90),
+        ("90s", 90),
+        ("30m", 30 * 60),
+        ("2h", 2 * 60 * 60),
+        ("1d", 24 * 60 * 60),
+        (" 5m ", 5 * 60),
+    ];
+    for (input, secs) in cases {
+        assert_eq!(
+            parse_duration(input),
+            Ok(Duration::from_secs(secs)),
+            "{input}"
+        );
+    }
+}
+
+#[test]
+fn invalid_durations_are_rejected() {
+    for input in ["", "m", "0", "0h", "5w", "1.5h", "-1m"] {
+        assert!(parse_duration(input).is_err(), "{input}");
+    }
+}
+
+#[test]
+fn overflowing_durations_are_too_large() {
+    for input in [
+        "999999999999999999d",
+        "999999999999999999h",
+        "307445734561825861m",
+    ] {
+        assert_eq!(
+            parse_duration(input),
+            Err("duration too large".to_string()),
+            "{input}"
+        );
+    }
+    assert_eq!(
+        parse_duration("18446744073709551615s"),
+        Ok(Duration::from_secs(u64::MAX))
+    );
+}
@@ -0,0 +1,171 @@
+//! Fixtures shared by the integration tests.
+
+// Every test crate uses only part of these
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+use rxd::db;
+use rxd::hash::HashAlgorithm;
+use sqlx::SqlitePool;
+
+/// Media file `filename` to record, of tweet 1 of `alice` posted on
+/// 2025-03-12 unless set otherwise
+pub fn media(filename: &str) -> Media<'_> {
+    Media {
+        filename,
+        screen_name: "alice",
+        tweet_id: "1",
+        time: "2025-03-12 10:00:00",
+        text: None,
+        media_url: None,
+        download_url: None,
+        hash: None,
+        size: None,
+        file: None,
+    }
+}
+
+/// Tweet and media rows of a downloaded file, recorded by [`Media::record`]
+pub struct Media<'a> {
+    filename: &'a str,
+    screen_name: &'a str,
+    tweet_id: &'a str,
+    time: &'a str,
+    text: Option<&'a str>,
+    media_url: Option<&'a str>,
+    download_url: Option<&'a str>,
+    hash: Option<String>,
+    size: Option<u64>,
+    file: Option<(&'a Path, &'a [u8])>,
+}
+
+impl<'a> Media<'a> {
+    pub fn user(mut self, screen_name: &'a str) -> Self {
+        self.screen_name = screen_name;
+        self
+    }
+
+    pub fn tweet(mut self, tweet_id: &'a str) -> Self {
+        self.tweet_id = tweet_id;
+        self
+    }
+
+    /// Posting time of the tweet, `YYYY-MM-DD HH:MM:SS`
+    pub fn time(mut self, time: &'a str) -> Self {
+        self.time = time;
+        self
+    }
+
+    pub fn text(mut self, text: &'a str) -> Self {
+        self.text = Some(text);
+        self
+    }
+
+    /// Instead of `https://pbs.twimg.com/media/<filename>`
+    pub fn media_url(mut self, media_url: &'a str) -> Self {
+        self.media_url = Some(media_url);
+        self
+    }
+
+    pub fn download_url(mut self, download_url: &'a str) -> Self {
+        self.download_url = Some(download_url);
+        self
+    }
+
+    /// `hash` as the recorded SHA-256
+    pub fn hash(mut self, hash: &str) -> Self {
+        self.hash = Some(hash.to_string());
+        self
+    }
+
+    /// The SHA-256 of `content` as the recorded hash
+    pub fn hash_of(self, content: &[u8]) -> Self {
+        self.hash(&HashAlgorithm::Sha256.hash(content))
+    }
+
+    pub fn size(mut self, size: u64) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Also write the file with `content` into `folder`
+    pub fn file(mut self, folder: &'a Path, content: &'a [u8]) -> Self {
+        self.file = Some((folder, content));
+        self
+    }
+
+    pub async fn record(self, pool: &SqlitePool) {
+        db::upsert_tweet(pool, self.tweet_id, self.screen_name, self.time, self.text)
+            .await
+            .expect("tweet");
+        let media_url = match self.media_url {
+            Some(media_url) => media_url.to_string(),
+            None => format!("https://pbs.twimg.com/media/{}", self.filename),
+        };
+        db::upsert_media(
+            pool,
+            self.tweet_id,
+            &media_url,
+            self.download_url,
+            Some(self.filename),
+        )
+        .await
+        .expect("media");
+        if let Some(hash) = &self.hash {
+            db::update_hash(pool, &media_url, hash, HashAlgorithm::Sha256)
+                .await
+                .expect("hash");
+        }
+        if let Some(size) = self.size {
+            db::update_file_size(pool, &media_url, size)
+                .await
+                .expect("size");
+        }
+        if let Some((folder, content)) = self.file {
+            std::fs::write(folder.join(self.filename), content).expect("file");
+        }
+    }
+}
+
+/// Folder of each account's files, `<root>/<screen_name>`
+pub fn folder(root: &Path) -> impl Fn(&str) -> PathBuf + '_ {
+    move |screen_name: &str| root.join(screen_name)
+}
+
+/// Stand-in for ffmpeg, or one failing on every input unless `works`
+///
+/// The working one prints the pixels in `<input>.gray` if there is such a
+/// file and otherwise writes its arguments as the output. It fails on
+/// inputs named `corrupt` and finds no frame a second into `short` ones.
+#[cfg(unix)]
+pub fn fake_ffmpeg(dir: &Path, works: bool) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = dir.join(if works { "ffmpeg" } else { "broken-ffmpeg" });
+    let script = if works {
+        r#"#!/bin/sh
+[ "$1" = "-version" ] && exit 0
+input=""; seek=""; previous=""
+for arg; do
+    [ "$previous" = "-i" ] && input="$arg"
+    [ "$previous" = "-ss" ] && seek="$arg"
+    previous="$arg"; output="$arg"
+done
+case "$input" in
+    *corrupt*) echo "$input: Invalid data found when processing input" >&2; exit 1 ;;
+    *short*) [ "$seek" = "1" ] && exit 0 ;;
+esac
+if [ -f "$input.gray" ]; then
+    cat "$input.gray"
+else
+    echo "$@" > "$output"
+fi
+"#
+    } else {
+        "#!/bin/sh\necho 'moov atom not found' >&2\nexit 1\n"
+    };
+    std::fs::write(&path, script).expect("script");
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).expect("chmod");
+    path
+}
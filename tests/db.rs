@@ -0,0 +1,326 @@
+//! Database queries against a seeded in-memory database.
+
+use std::path::PathBuf;
+
+use rxd::config::{self, Config};
+use rxd::db::{self, UserOrder, UserStats};
+use rxd::hash::HashAlgorithm;
+use rxd::task::Engagement;
+use sqlx::SqlitePool;
+
+async fn seeded() -> SqlitePool {
+    let pool = db::init_memory_db().await.expect("db");
+    let tweets = [
+        ("1", "bob", "2025-01-01 10:00:00"),
+        ("2", "bob", "2025-03-01 10:00:00"),
+        ("3", "Alice", "2025-02-01 10:00:00"),
+        ("4", "carol", "2024-12-31 23:59:59"),
+    ];
+    for (tweet_id, screen_name, tweet_time) in tweets {
+        db::upsert_tweet(&pool, tweet_id, screen_name, tweet_time, None)
+            .await
+            .expect("tweet");
+    }
+    for (tweet_id, url, hash) in [
+        ("1", "https://pbs.twimg.com/media/A.jpg", Some("aa")),
+        ("1", "https://pbs.twimg.com/media/B.jpg", None),
+        ("2", "https://pbs.twimg.com/media/C.jpg", Some("cc")),
+        ("3", "https://pbs.twimg.com/media/D.jpg", Some("dd")),
+    ] {
+        db::upsert_media(&pool, tweet_id, url, None, None)
+            .await
+            .expect("media");
+        if let Some(hash) = hash {
+            db::update_hash(&pool, url, hash, HashAlgorithm::Sha256)
+                .await
+                .expect("hash");
+        }
+    }
+    pool
+}
+
+fn stats(screen_name: &str, media: i64, hashed: i64, newest_tweet: &str) -> UserStats {
+    UserStats {
+        screen_name: screen_name.to_string(),
+        media,
+        hashed,
+        newest_tweet: Some(newest_tweet.to_string()),
+    }
+}
+
+#[tokio::test]
+async fn lists_users_by_name() {
+    let pool = seeded().await;
+
+    assert_eq!(
+        db::list_users(&pool, UserOrder::Name).await.expect("users"),
+        vec![
+            stats("Alice", 1, 1, "2025-02-01 10:00:00"),
+            stats("bob", 3, 2, "2025-03-01 10:00:00"),
+            // Tweets without media still count as archived
+            stats("carol", 0, 0, "2024-12-31 23:59:59"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn lists_users_by_recency() {
+    let pool = seeded().await;
+
+    let names: Vec<String> = db::list_users(&pool, UserOrder::Recent)
+        .await
+        .expect("users")
+        .into_iter()
+        .map(|u| u.screen_name)
+        .collect();
+    assert_eq!(names, ["bob", "Alice", "carol"]);
+}
+
+#[tokio::test]
+async fn empty_database_has_no_users() {
+    let pool = db::init_memory_db().await.expect("db");
+
+    assert!(
+        db::list_users(&pool, UserOrder::Name)
+            .await
+            .expect("users")
+            .is_empty()
+    );
+}
+
+async fn with_texts(texts: &[(&str, &str, &str)]) -> SqlitePool {
+    let pool = db::init_memory_db().await.expect("db");
+    for (i, (tweet_id, screen_name, text)) in texts.iter().enumerate() {
+        let tweet_time = format!("2025-01-{:02} 12:00:00", i + 1);
+        db::upsert_tweet(&pool, tweet_id, screen_name, &tweet_time, Some(text))
+            .await
+            .expect("tweet");
+    }
+    pool
+}
+
+async fn search(pool: &SqlitePool, query: &str, screen_name: Option<&str>) -> Vec<String> {
+    db::search_text(pool, query, screen_name)
+        .await
+        .expect("search")
+        .into_iter()
+        .map(|m| m.tweet_id)
+        .collect()
+}
+
+#[tokio::test]
+async fn searches_text_newest_first() {
+    let pool = with_texts(&[
+        ("1", "alice", "Blue hair, green eyes"),
+        ("2", "bob", "my BLUE HAIR phase"),
+        ("3", "alice", "blue shirt and hair"),
+    ])
+    .await;
+    db::upsert_media(
+        &pool,
+        "2",
+        "https://pbs.twimg.com/media/X.jpg",
+        None,
+        Some("x.jpg"),
+    )
+    .await
+    .expect("media");
+
+    assert_eq!(search(&pool, "blue hair", None).await, ["2", "1"]);
+    assert_eq!(search(&pool, "blue hair", Some("ALICE")).await, ["1"]);
+    let found = db::search_text(&pool, "blue hair", Some("bob"))
+        .await
+        .expect("search");
+    assert_eq!(found[0].filenames, ["x.jpg"]);
+    assert_eq!(found[0].tweet_time, "2025-01-02 12:00:00");
+}
+
+#[tokio::test]
+async fn searches_unicode_and_emoji() {
+    let pool = with_texts(&[
+        ("1", "alice", "新しいイラストを描きました🎨"),
+        ("2", "bob", "Grand ÉVÉNEMENT ce soir 🎉🎉"),
+        ("3", "carol", "giveaway 🎁 today"),
+    ])
+    .await;
+
+    // Scripts without spaces between words
+    assert_eq!(search(&pool, "イラスト", None).await, ["1"]);
+    assert_eq!(search(&pool, "événement", None).await, ["2"]);
+    // Too short for the index, found by a scan
+    assert_eq!(search(&pool, "🎁", None).await, ["3"]);
+    assert_eq!(search(&pool, "🎉🎉", None).await, ["2"]);
+    assert_eq!(search(&pool, "🎨", None).await, ["1"]);
+}
+
+#[tokio::test]
+async fn query_syntax_is_taken_literally() {
+    let pool = with_texts(&[
+        ("1", "alice", "100% \"done\" OR not"),
+        ("2", "bob", "100 percent done"),
+    ])
+    .await;
+
+    assert_eq!(search(&pool, "\"done\" OR", None).await, ["1"]);
+    assert_eq!(search(&pool, "0%", None).await, ["1"]);
+    assert!(search(&pool, "  ", None).await.is_empty());
+}
+
+#[tokio::test]
+async fn edited_text_is_reindexed() {
+    let pool = with_texts(&[("1", "alice", "first draft")]).await;
+    db::upsert_tweet(
+        &pool,
+        "1",
+        "alice",
+        "2025-01-01 12:00:00",
+        Some("final version"),
+    )
+    .await
+    .expect("tweet");
+    let engagement = Engagement {
+        favorite_count: Some(5),
+        ..Engagement::default()
+    };
+    db::update_engagement(&pool, "1", &engagement)
+        .await
+        .expect("engagement");
+
+    assert!(search(&pool, "draft", None).await.is_empty());
+    assert_eq!(search(&pool, "final", None).await, ["1"]);
+}
+
+#[tokio::test]
+async fn engagement_is_refreshed_and_missing_counts_kept() {
+    let pool = with_texts(&[("1", "alice", "text")]).await;
+    sqlx::query("UPDATE tweets SET created_at = '2025-01-01 00:00:00'")
+        .execute(&pool)
+        .await
+        .expect("backdate");
+    let first = Engagement {
+        favorite_count: Some(10),
+        retweet_count: Some(2),
+        reply_count: Some(1),
+        quote_count: Some(0),
+        view_count: Some(500),
+    };
+    db::update_engagement(&pool, "1", &first)
+        .await
+        .expect("first");
+    let later = Engagement {
+        favorite_count: Some(12),
+        view_count: None,
+        ..first
+    };
+    db::upsert_tweet(&pool, "1", "alice", "2025-01-01 12:00:00", Some("text"))
+        .await
+        .expect("tweet");
+    db::update_engagement(&pool, "1", &later)
+        .await
+        .expect("later");
+
+    let row: (i64, i64, i64, i64, i64, String) = sqlx::query_as(
+        "SELECT favorite_count, retweet_count, reply_count, quote_count, view_count, created_at
+         FROM tweets WHERE tweet_id = '1'",
+    )
+    .fetch_one(&pool)
+    .await
+    .expect("row");
+    assert_eq!(row, (12, 2, 1, 0, 500, "2025-01-01 00:00:00".to_string()));
+}
+
+#[tokio::test]
+async fn tombstones_mark_archived_tweets() {
+    let pool = seeded().await;
+    let entry = |id: &str| format!("profile-grid-0-tweet-{id}");
+
+    // Archived before, marked once with the first reason
+    for reason in ["This Post was deleted by the Post author.", "later"] {
+        assert!(
+            db::record_tombstone(&pool, "bob", &entry("2"), Some("2"), reason)
+                .await
+                .expect("record")
+        );
+    }
+    // Never archived, recorded once
+    for _ in 0..2 {
+        assert!(
+            !db::record_tombstone(&pool, "bob", &entry("9"), Some("9"), "Suspended")
+                .await
+                .expect("record")
+        );
+    }
+
+    let deleted: (Option<String>, Option<String>) =
+        sqlx::query_as("SELECT deleted_at, deleted_reason FROM tweets WHERE tweet_id = '2'")
+            .fetch_one(&pool)
+            .await
+            .expect("tweet");
+    assert!(deleted.0.is_some());
+    assert_eq!(
+        deleted.1.as_deref(),
+        Some("This Post was deleted by the Post author.")
+    );
+    let tombstones: Vec<(Option<String>, String)> =
+        sqlx::query_as("SELECT tweet_id, reason FROM tombstones")
+            .fetch_all(&pool)
+            .await
+            .expect("tombstones");
+    assert_eq!(
+        tombstones,
+        [(Some("9".to_string()), "Suspended".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn open_creates_missing_directories() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("nested/dirs/rxd.db");
+
+    let pool = db::open(&path).await.expect("open");
+    pool.close().await;
+
+    assert!(path.exists());
+    let pool = db::open(std::path::Path::new(db::MEMORY))
+        .await
+        .expect("memory");
+    assert!(
+        db::list_users(&pool, UserOrder::Name)
+            .await
+            .expect("users")
+            .is_empty()
+    );
+}
+
+#[test]
+fn database_path_follows_the_config() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let config_path = dir.path().join("config.toml");
+    let config_with = |database: &str| {
+        std::fs::write(
+            &config_path,
+            format!("auth_token = \"t\"\nct0 = \"c\"\n{database}\ntasks = []\n"),
+        )
+        .expect("write");
+        Config::load(&config_path).expect("config")
+    };
+    let config_dir = config::config_dir(&config_path);
+
+    assert_eq!(
+        config_with("").database_path(&config_dir),
+        dir.path().join("rxd.db")
+    );
+    assert_eq!(
+        config_with("database = \"data/archive.db\"").database_path(&config_dir),
+        dir.path().join("data/archive.db")
+    );
+    assert_eq!(
+        config_with("database = \":memory:\"").database_path(&config_dir),
+        PathBuf::from(db::MEMORY)
+    );
+    let home = std::env::var_os("HOME").expect("HOME");
+    assert_eq!(
+        config_with("database = \"~/rxd/rxd.db\"").database_path(&config_dir),
+        PathBuf::from(home).join("rxd/rxd.db")
+    );
+}
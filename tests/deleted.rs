@@ -0,0 +1,213 @@
+//! Checking which archived tweets were deleted upstream.
+
+use std::time::Duration;
+
+use rxd::deleted::{self, TweetStatus};
+use rxd::{Api, db};
+use serde_json::{Value, json};
+use sqlx::{Row, SqlitePool};
+use wiremock::matchers::{method, path, query_param_contains};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const TWEET_RESULT_BY_REST_ID: &str = "/i/api/graphql/Xl5pC_lBk_gcO2ItU39DQw/TweetResultByRestId";
+
+fn live() -> Value {
+    json!({ "data": { "tweetResult": { "result": {
+        "__typename": "Tweet",
+        "rest_id": "1",
+        "legacy": { "full_text": "still here" }
+    }}}})
+}
+
+fn not_found() -> Value {
+    json!({ "data": { "tweetResult": {} } })
+}
+
+fn tombstone(text: &str) -> Value {
+    json!({ "data": { "tweetResult": { "result": {
+        "__typename": "TweetTombstone",
+        "tombstone": { "text": { "text": text } }
+    }}}})
+}
+
+async fn answer(server: &MockServer, tweet_id: &str, response: ResponseTemplate) {
+    Mock::given(method("GET"))
+        .and(path(TWEET_RESULT_BY_REST_ID))
+        .and(query_param_contains(
+            "variables",
+            format!(r#""tweetId":"{tweet_id}""#),
+        ))
+        .respond_with(response)
+        .mount(server)
+        .await;
+}
+
+async fn record(pool: &SqlitePool, tweet_id: &str, filename: Option<&str>) {
+    db::upsert_tweet(pool, tweet_id, "alice", "2025-03-12 10:00:00", None)
+        .await
+        .expect("tweet");
+    if let Some(filename) = filename {
+        let media_url = format!("https://pbs.twimg.com/media/{tweet_id}.jpg");
+        db::upsert_media(pool, tweet_id, &media_url, None, Some(filename))
+            .await
+            .expect("media");
+    }
+}
+
+async fn check_status(pool: &SqlitePool, tweet_id: &str) -> (Option<String>, bool, bool) {
+    let row = sqlx::query(
+        "SELECT check_status, checked_at IS NOT NULL AS checked, deleted_at IS NOT NULL AS deleted
+         FROM tweets WHERE tweet_id = ?",
+    )
+    .bind(tweet_id)
+    .fetch_one(pool)
+    .await
+    .expect("tweet row");
+    (
+        row.get("check_status"),
+        row.get("checked"),
+        row.get("deleted"),
+    )
+}
+
+#[test]
+fn classifies_tweet_results() {
+    let parse = |raw: Value| deleted::parse_tweet_result_response(&raw).expect("parse");
+    assert_eq!(parse(live()), TweetStatus::Live);
+    assert_eq!(
+        parse(not_found()),
+        TweetStatus::Deleted("not found".to_string())
+    );
+    assert_eq!(
+        parse(tombstone(
+            "This Post was deleted by the Post author. Learn more"
+        )),
+        TweetStatus::Deleted("This Post was deleted by the Post author. Learn more".to_string())
+    );
+    assert_eq!(
+        parse(tombstone("This Post has been withheld in your country.")),
+        TweetStatus::Withheld("This Post has been withheld in your country.".to_string())
+    );
+    let unavailable = json!({ "data": { "tweetResult": { "result": {
+        "__typename": "TweetUnavailable",
+        "reason": "Suspended"
+    }}}});
+    assert_eq!(
+        parse(unavailable),
+        TweetStatus::Withheld("Suspended".to_string())
+    );
+}
+
+#[tokio::test]
+async fn records_live_deleted_and_withheld_tweets() {
+    let server = MockServer::start().await;
+    let pool = db::init_memory_db().await.expect("db");
+    record(&pool, "1", Some("live.jpg")).await;
+    record(&pool, "2", Some("gone.jpg")).await;
+    record(&pool, "3", None).await;
+    answer(
+        &server,
+        "1",
+        ResponseTemplate::new(200).set_body_json(live()),
+    )
+    .await;
+    answer(
+        &server,
+        "2",
+        ResponseTemplate::new(200).set_body_json(not_found()),
+    )
+    .await;
+    answer(
+        &server,
+        "3",
+        ResponseTemplate::new(200).set_body_json(tombstone("This Post is withheld.")),
+    )
+    .await;
+    let api = Api::with_base_url("token", "ct0", &server.uri()).expect("api");
+
+    let summary = deleted::check(&pool, &api, None, None, 2, |_, _| {})
+        .await
+        .expect("check");
+
+    assert_eq!(summary.live, 1);
+    assert_eq!(summary.withheld, 1);
+    assert_eq!(summary.failed, 0);
+    assert_eq!(summary.newly_deleted.len(), 1);
+    let gone = &summary.newly_deleted[0];
+    assert_eq!(gone.tweet_id, "2");
+    assert_eq!(gone.screen_name, "alice");
+    assert_eq!(gone.filenames, ["gone.jpg"]);
+    assert_eq!(
+        check_status(&pool, "1").await,
+        (Some("live".to_string()), true, false)
+    );
+    assert_eq!(
+        check_status(&pool, "2").await,
+        (Some("deleted".to_string()), true, true)
+    );
+    assert_eq!(
+        check_status(&pool, "3").await,
+        (Some("withheld".to_string()), true, false)
+    );
+
+    // Everything was checked just now
+    let again = deleted::check(&pool, &api, None, None, 2, |_, _| {})
+        .await
+        .expect("check");
+    assert_eq!(again, deleted::CheckSummary::default());
+    let recent = deleted::check(
+        &pool,
+        &api,
+        None,
+        Some(Duration::from_secs(24 * 60 * 60)),
+        2,
+        |_, _| {},
+    )
+    .await
+    .expect("check");
+    assert_eq!(recent, deleted::CheckSummary::default());
+}
+
+#[tokio::test]
+async fn failed_lookups_are_checked_on_the_next_run() {
+    let server = MockServer::start().await;
+    let pool = db::init_memory_db().await.expect("db");
+    record(&pool, "1", None).await;
+    record(&pool, "2", None).await;
+    answer(
+        &server,
+        "1",
+        ResponseTemplate::new(200).set_body_json(live()),
+    )
+    .await;
+    Mock::given(method("GET"))
+        .and(path(TWEET_RESULT_BY_REST_ID))
+        .and(query_param_contains("variables", r#""tweetId":"2""#))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    answer(
+        &server,
+        "2",
+        ResponseTemplate::new(200).set_body_json(live()),
+    )
+    .await;
+    let api = Api::with_base_url("token", "ct0", &server.uri()).expect("api");
+
+    let first = deleted::check(&pool, &api, Some("alice"), None, 1, |_, _| {})
+        .await
+        .expect("check");
+    assert_eq!(first.live, 1);
+    assert_eq!(first.failed, 1);
+
+    let mut totals = Vec::new();
+    let second = deleted::check(&pool, &api, Some("alice"), None, 1, |_, total| {
+        totals.push(total)
+    })
+    .await
+    .expect("check");
+    assert_eq!(second.live, 1);
+    assert_eq!(totals.first(), Some(&1));
+}
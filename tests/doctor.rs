@@ -0,0 +1,133 @@
+//! `rxd doctor` checks against a mock API server.
+
+use std::path::{Path, PathBuf};
+
+use rxd::doctor::{self, Status};
+use serde_json::json;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const USER_BY_SCREEN_NAME: &str = "/i/api/graphql/xc8f1g7BYqr6VTzTbvNlGw/UserByScreenName";
+
+fn write_config(dir: &Path, server: &MockServer) -> PathBuf {
+    let path = dir.join("config.toml");
+    std::fs::write(
+        &path,
+        format!(
+            r#"
+auth_token = "token"
+ct0 = "ct0"
+api_base_url = "{}"
+
+[[tasks]]
+screen_name = "test_user"
+save_path = "{}"
+"#,
+            server.uri(),
+            dir.join("media").display()
+        ),
+    )
+    .expect("write config");
+    path
+}
+
+fn status_of(report: &doctor::Report, name: &str) -> Status {
+    report
+        .checks
+        .iter()
+        .find(|c| c.name.starts_with(name))
+        .unwrap_or_else(|| panic!("no {name} check in\n{}", report.to_text()))
+        .status
+}
+
+#[tokio::test]
+async fn healthy_setup_passes() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    let config_path = write_config(dir.path(), &server);
+    rxd::db::init_db(&dir.path().join("rxd.db"))
+        .await
+        .expect("db");
+
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("x-rate-limit-limit", "95")
+                .insert_header("x-rate-limit-remaining", "94")
+                .set_body_json(json!({ "data": {} })),
+        )
+        .mount(&server)
+        .await;
+
+    let report = doctor::run(&config_path, &server.uri()).await;
+
+    assert!(!report.has_failures(), "{}", report.to_text());
+    assert_eq!(status_of(&report, "credentials"), Status::Pass);
+    assert!(report.to_text().contains("94 of 95 requests left"));
+    assert_eq!(status_of(&report, "save path"), Status::Pass);
+    assert_eq!(status_of(&report, "database"), Status::Pass);
+    // Nothing is created by the check
+    assert!(!dir.path().join("media").exists());
+}
+
+#[tokio::test]
+async fn rejected_credentials_fail_with_a_fix() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    let config_path = write_config(dir.path(), &server);
+
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&server)
+        .await;
+
+    let report = doctor::run(&config_path, &server.uri()).await;
+
+    assert!(report.has_failures());
+    let check = report
+        .checks
+        .iter()
+        .find(|c| c.name == "credentials")
+        .expect("credentials check");
+    assert_eq!(check.status, Status::Fail);
+    assert!(
+        check
+            .fix
+            .as_deref()
+            .is_some_and(|f| f.contains("auth_token"))
+    );
+    // A database that does not exist yet is not a failure
+    assert_eq!(status_of(&report, "database"), Status::Warn);
+}
+
+#[tokio::test]
+async fn invalid_config_stops_early() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let config_path = dir.path().join("config.toml");
+    std::fs::write(&config_path, "auth_token = \"token\"\n").expect("write config");
+
+    let report = doctor::run(&config_path, "http://127.0.0.1:9").await;
+
+    assert!(report.has_failures());
+    assert_eq!(report.checks.len(), 1);
+    assert_eq!(report.checks[0].name, "config");
+}
+
+#[tokio::test]
+async fn notifier_is_only_checked_when_notifications_are_on() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    let config_path = write_config(dir.path(), &server);
+
+    let report = doctor::run(&config_path, &server.uri()).await;
+    assert!(!report.checks.iter().any(|c| c.name == "notifications"));
+
+    let config = std::fs::read_to_string(&config_path).expect("read config");
+    std::fs::write(&config_path, format!("desktop_notify = true\n{config}")).expect("write config");
+    let report = doctor::run(&config_path, &server.uri()).await;
+    // Whether the notifier is installed depends on the machine, but it is
+    // never a failure since downloads work without it
+    assert_ne!(status_of(&report, "notifications"), Status::Fail);
+}
@@ -0,0 +1,2560 @@
+//! End-to-end tests of a download task against a mock API server.
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Local};
+#[cfg(unix)]
+use common::fake_ffmpeg;
+use rxd::convert::{ConvertFormat, PostProcess};
+use rxd::dedupe::{Dedupe, DuplicateReport, LinkKind};
+use rxd::events::Event;
+use rxd::filename::Template;
+use rxd::hash::HashAlgorithm;
+use rxd::listing::ListFormat;
+use rxd::pushback::PushbackPolicy;
+use rxd::sidecar::TextSidecars;
+use rxd::task::PageRetryPolicy;
+use rxd::{Api, ImageSize, Task, TaskBuilder};
+use serde_json::{Value, json};
+use sqlx::{Row, SqlitePool};
+use tokio_util::sync::CancellationToken;
+use wiremock::matchers::{
+    header, header_exists, method, path, path_regex, query_param, query_param_contains,
+};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+mod common;
+
+const USER_BY_SCREEN_NAME: &str = "/i/api/graphql/xc8f1g7BYqr6VTzTbvNlGw/UserByScreenName";
+const USER_MEDIA: &str = "/i/api/graphql/Le6KlbilFmSu-5VltFND-Q/UserMedia";
+const CREATED_AT: &str = "Wed Mar 12 12:00:00 +0000 2025";
+
+fn user_response() -> Value {
+    json!({
+        "data": { "user": { "result": {
+            "__typename": "User",
+            "rest_id": "42",
+            "legacy": { "name": "Test User", "media_count": 3 }
+        }}}
+    })
+}
+
+fn photo_item(tweet_id: &str, url: &str) -> Value {
+    json!({
+        "item": { "itemContent": { "tweet_results": { "result": {
+            "__typename": "Tweet",
+            "rest_id": tweet_id,
+            "legacy": {
+                "created_at": CREATED_AT,
+                "full_text": format!("tweet {tweet_id}"),
+                "extended_entities": { "media": [
+                    { "type": "photo", "media_url_https": url }
+                ]}
+            }
+        }}}}
+    })
+}
+
+fn media_page(items: Vec<Value>, cursor: Option<&str>) -> Value {
+    let mut entries = vec![json!({
+        "entryId": "profile-grid-0",
+        "content": { "items": items }
+    })];
+    if let Some(cursor) = cursor {
+        entries.push(json!({
+            "entryId": "cursor-bottom-0",
+            "content": { "value": cursor }
+        }));
+    }
+    json!({
+        "data": { "user": { "result": { "timeline_v2": { "timeline": {
+            "instructions": [{ "type": "TimelineAddEntries", "entries": entries }]
+        }}}}}
+    })
+}
+
+/// Filename the default scheme produces for a media ID
+fn expected_filename(media_id: &str) -> String {
+    expected_filename_with_ext(media_id, "jpg")
+}
+
+fn expected_filename_with_ext(media_id: &str, ext: &str) -> String {
+    let date = DateTime::parse_from_str(CREATED_AT, "%a %b %d %H:%M:%S %z %Y")
+        .expect("valid timestamp")
+        .with_timezone(&Local)
+        .format("%Y-%m-%d");
+    format!("{date}-{media_id}.{ext}")
+}
+
+async fn mount_user(server: &MockServer) {
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .respond_with(ResponseTemplate::new(200).set_body_json(user_response()))
+        .mount(server)
+        .await;
+}
+
+async fn mount_media(server: &MockServer, media_id: &str) -> String {
+    let media_path = format!("/media/{media_id}.jpg");
+    Mock::given(method("GET"))
+        .and(path(media_path.as_str()))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(media_id.as_bytes()))
+        .mount(server)
+        .await;
+    format!("{}{}", server.uri(), media_path)
+}
+
+/// Builder for a task downloading `test_user` from the mock server into `dir/media`
+async fn task_builder(server: &MockServer, dir: &Path) -> (TaskBuilder, SqlitePool) {
+    let db = rxd::db::init_db(&dir.join("rxd.db")).await.expect("db");
+    let api = Api::with_base_url("token", "ct0", &server.uri()).expect("api");
+    let builder = Task::builder()
+        .api(api)
+        .screen_name("test_user")
+        .concurrency(2)
+        .save_path(dir.join("media"))
+        .db(db.clone());
+    (builder, db)
+}
+
+async fn run_task(server: &MockServer, dir: &Path) -> (rxd::events::Totals, SqlitePool) {
+    let (builder, db) = task_builder(server, dir).await;
+    let task = builder.build().await.expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+    (totals, db)
+}
+
+#[tokio::test]
+async fn downloads_across_two_pages() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let first = mount_media(&server, "AAA").await;
+    let second = mount_media(&server, "BBB").await;
+
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .and(query_param_contains("variables", "page-2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(vec![], None)))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .and(query_param_contains("variables", "page-1"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(media_page(vec![photo_item("2", &second)], Some("page-2"))),
+        )
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(media_page(vec![photo_item("1", &first)], Some("page-1"))),
+        )
+        .with_priority(10)
+        .mount(&server)
+        .await;
+
+    let (totals, db) = run_task(&server, dir.path()).await;
+    assert_eq!(totals.fetched, 2);
+    assert_eq!(totals.downloaded, 2);
+    assert_eq!(totals.failed, 0);
+
+    for (media_id, url) in [("AAA", &first), ("BBB", &second)] {
+        let filename = expected_filename(media_id);
+        let content = std::fs::read(dir.path().join("media").join(&filename)).expect("file");
+        assert_eq!(content, media_id.as_bytes());
+
+        let row =
+            sqlx::query("SELECT filename, file_hash, file_size FROM media WHERE media_url = ?")
+                .bind(url)
+                .fetch_one(&db)
+                .await
+                .expect("media row");
+        assert_eq!(row.get::<Option<String>, _>("filename"), Some(filename));
+        assert_eq!(
+            row.get::<Option<i64>, _>("file_size"),
+            Some(media_id.len() as i64)
+        );
+        assert_eq!(
+            row.get::<Option<String>, _>("file_hash"),
+            Some(rxd::db::calculate_hash(
+                media_id.as_bytes(),
+                HashAlgorithm::Sha256
+            ))
+        );
+    }
+
+    let tweets: i64 = sqlx::query("SELECT COUNT(*) AS n FROM tweets WHERE screen_name = ?")
+        .bind("test_user")
+        .fetch_one(&db)
+        .await
+        .expect("count")
+        .get("n");
+    assert_eq!(tweets, 2);
+}
+
+#[tokio::test]
+async fn waits_for_rate_limit_reset() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let url = mount_media(&server, "AAA").await;
+
+    let reset = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time")
+        .as_secs()
+        + 1;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(429).insert_header("x-rate-limit-reset", reset.to_string()),
+        )
+        .up_to_n_times(1)
+        .expect(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(media_page(vec![photo_item("1", &url)], None)),
+        )
+        .with_priority(10)
+        .mount(&server)
+        .await;
+
+    let (totals, _db) = run_task(&server, dir.path()).await;
+    assert_eq!(totals.downloaded, 1);
+    assert!(
+        dir.path()
+            .join("media")
+            .join(expected_filename("AAA"))
+            .exists()
+    );
+}
+
+#[tokio::test]
+async fn numbers_media_of_multi_photo_tweets() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let first = mount_media(&server, "ZZZ").await;
+    let second = mount_media(&server, "AAA").await;
+
+    let mut item = photo_item("7", &first);
+    item["item"]["itemContent"]["tweet_results"]["result"]["legacy"]["extended_entities"]["media"]
+        .as_array_mut()
+        .expect("media array")
+        .push(json!({ "type": "photo", "media_url_https": second }));
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(vec![item], None)))
+        .mount(&server)
+        .await;
+
+    let (totals, _db) = run_task(&server, dir.path()).await;
+    assert_eq!(totals.downloaded, 2);
+    let media = dir.path().join("media");
+    assert!(media.join(expected_filename("7-01-ZZZ")).exists());
+    assert!(media.join(expected_filename("7-02-AAA")).exists());
+}
+
+#[tokio::test]
+async fn tweets_mixing_both_image_url_styles_download_each_in_its_format() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let classic = mount_media(&server, "ZZZ").await;
+    Mock::given(method("GET"))
+        .and(path("/media/AAA"))
+        .and(query_param("format", "png"))
+        .and(query_param("name", "orig"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"AAA".to_vec()))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let format_style = format!("{}/media/AAA?format=png&name=small", server.uri());
+
+    let mut item = photo_item("7", &classic);
+    item["item"]["itemContent"]["tweet_results"]["result"]["legacy"]["extended_entities"]["media"]
+        .as_array_mut()
+        .expect("media array")
+        .push(json!({ "type": "photo", "media_url_https": format_style }));
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(vec![item], None)))
+        .mount(&server)
+        .await;
+
+    let (totals, _db) = run_task(&server, dir.path()).await;
+    assert_eq!(totals.downloaded, 2);
+    let media = dir.path().join("media");
+    assert!(media.join(expected_filename("7-01-ZZZ")).exists());
+    assert!(
+        media
+            .join(expected_filename_with_ext("7-02-AAA", "png"))
+            .exists()
+    );
+}
+
+#[tokio::test]
+async fn distinct_media_with_the_same_name_get_a_counter() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let first = mount_media(&server, "AAA").await;
+    Mock::given(method("GET"))
+        .and(path("/other/AAA.jpg"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"other".to_vec()))
+        .mount(&server)
+        .await;
+    let second = format!("{}/other/AAA.jpg", server.uri());
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(
+            vec![photo_item("1", &first), photo_item("2", &second)],
+            None,
+        )))
+        .mount(&server)
+        .await;
+
+    let (totals, db) = run_task(&server, dir.path()).await;
+    assert_eq!(totals.downloaded, 2);
+    // Either one may claim the name first, each is recorded with its own file
+    let media = dir.path().join("media");
+    let mut names = Vec::new();
+    for (url, content) in [(&first, b"AAA".as_slice()), (&second, b"other")] {
+        let record = rxd::db::get_media_by_url(&db, url)
+            .await
+            .expect("query")
+            .expect("record");
+        let filename = record.filename.expect("filename");
+        assert_eq!(std::fs::read(media.join(&filename)).expect("file"), content);
+        names.push(filename);
+    }
+    names.sort();
+    assert_eq!(
+        names,
+        vec![expected_filename("AAA-1"), expected_filename("AAA")]
+    );
+
+    // Both are recognized as downloaded on the next run
+    let (totals, _db) = run_task(&server, dir.path()).await;
+    assert_eq!(totals.downloaded, 0);
+    assert_eq!(totals.skipped, 2);
+}
+
+#[tokio::test]
+async fn names_recorded_by_other_accounts_are_not_taken() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let url = mount_media(&server, "AAA").await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(media_page(vec![photo_item("1", &url)], None)),
+        )
+        .mount(&server)
+        .await;
+    let (builder, db) = task_builder(&server, dir.path()).await;
+    // Another account saved a different media under the same name
+    rxd::db::upsert_tweet(&db, "9", "someone_else", "2025-03-12 12:00:00", None)
+        .await
+        .expect("tweet");
+    rxd::db::upsert_media(
+        &db,
+        "9",
+        "https://pbs.twimg.com/media/other.jpg",
+        None,
+        Some(&expected_filename("AAA")),
+    )
+    .await
+    .expect("media");
+    let media = dir.path().join("media");
+    std::fs::create_dir_all(&media).expect("media dir");
+    std::fs::write(media.join(expected_filename("AAA")), b"AAA").expect("file");
+
+    let task = builder.build().await.expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+
+    // The file on disk is this account's own, no suffixed copy is made
+    assert_eq!(totals.downloaded, 0);
+    assert!(!media.join(expected_filename("AAA-1")).exists());
+    let record = rxd::db::get_media_by_url(&db, &url)
+        .await
+        .expect("query")
+        .expect("record");
+    assert_eq!(record.filename, Some(expected_filename("AAA")));
+}
+
+#[tokio::test]
+async fn names_files_with_a_template() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let url = mount_media(&server, "AAA").await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(media_page(vec![photo_item("1", &url)], None)),
+        )
+        .mount(&server)
+        .await;
+
+    let (builder, _db) = task_builder(&server, dir.path()).await;
+    let template = Template::parse("{screen_name}-{index}-{text}-{media_id}").expect("template");
+    let task = builder
+        .filename_template(template)
+        .build()
+        .await
+        .expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+
+    assert_eq!(totals.downloaded, 1);
+    assert!(
+        dir.path()
+            .join("media")
+            .join("test_user-tweet-1-AAA.jpg")
+            .exists()
+    );
+}
+
+#[tokio::test]
+async fn dates_in_names_use_the_configured_timezone() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let url = mount_media(&server, "AAA").await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(media_page(vec![photo_item("1", &url)], None)),
+        )
+        .mount(&server)
+        .await;
+
+    let (builder, _db) = task_builder(&server, dir.path()).await;
+    let task = builder
+        .filename_template(Template::parse("{date}-{time}-{media_id}").expect("template"))
+        .timezone("+13:00".parse().expect("timezone"))
+        .build()
+        .await
+        .expect("task");
+    Arc::new(task).execute().await.expect("execute");
+
+    // Posted at noon UTC, which is already the next day at +13:00
+    assert!(
+        dir.path()
+            .join("media")
+            .join("2025-03-13-010000-AAA.jpg")
+            .exists()
+    );
+}
+
+#[tokio::test]
+async fn original_names_adopt_existing_files() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let existing = format!("{}/media/GJxyzAbCdE.jpg", server.uri());
+    Mock::given(method("GET"))
+        .and(path("/media/GJxyzAbCdE.jpg"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"new".to_vec()))
+        .expect(0)
+        .mount(&server)
+        .await;
+    let new = mount_media(&server, "AAA").await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(
+            vec![photo_item("1", &existing), photo_item("2", &new)],
+            None,
+        )))
+        .mount(&server)
+        .await;
+
+    // Archive from another tool, named after the media key only
+    let media = dir.path().join("media");
+    std::fs::create_dir_all(&media).expect("media dir");
+    std::fs::write(media.join("GJxyzAbCdE.jpg"), b"archived").expect("existing file");
+
+    let (builder, db) = task_builder(&server, dir.path()).await;
+    let task = builder
+        .filename_template(Template::parse("{media_id}").expect("template"))
+        .build()
+        .await
+        .expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+
+    assert_eq!(totals.downloaded, 1);
+    assert_eq!(totals.skipped, 1);
+    assert_eq!(
+        std::fs::read(media.join("GJxyzAbCdE.jpg")).expect("file"),
+        b"archived"
+    );
+    assert!(media.join("AAA.jpg").exists());
+
+    // The adopted file is linked to its tweet like a downloaded one
+    let row = sqlx::query("SELECT tweet_id, filename, file_hash FROM media WHERE media_url = ?")
+        .bind(&existing)
+        .fetch_one(&db)
+        .await
+        .expect("media row");
+    assert_eq!(row.get::<String, _>("tweet_id"), "1");
+    assert_eq!(
+        row.get::<Option<String>, _>("filename").as_deref(),
+        Some("GJxyzAbCdE.jpg")
+    );
+    assert_eq!(
+        row.get::<Option<String>, _>("file_hash"),
+        Some(rxd::db::calculate_hash(b"archived", HashAlgorithm::Sha256))
+    );
+}
+
+fn retweet_item(tweet_id: &str, url: &str) -> Value {
+    let mut item = photo_item(tweet_id, url);
+    item["item"]["itemContent"]["tweet_results"]["result"]["legacy"]["retweeted_status_result"] =
+        json!({ "result": { "__typename": "Tweet", "rest_id": "99" } });
+    item
+}
+
+#[tokio::test]
+async fn retweets_are_filtered_unless_included() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let own = mount_media(&server, "AAA").await;
+    let retweeted = mount_media(&server, "RRR").await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(
+            vec![photo_item("1", &own), retweet_item("2", &retweeted)],
+            None,
+        )))
+        .mount(&server)
+        .await;
+
+    let (totals, _db) = run_task(&server, dir.path()).await;
+    assert_eq!(totals.downloaded, 1);
+    assert_eq!(totals.filtered, 1);
+    assert!(
+        !dir.path()
+            .join("media")
+            .join(expected_filename("RRR"))
+            .exists()
+    );
+
+    let (builder, _db) = task_builder(&server, dir.path()).await;
+    let task = builder.exclude_retweets(false).build().await.expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+    assert_eq!(totals.downloaded, 1);
+    assert_eq!(totals.filtered, 0);
+    assert!(
+        dir.path()
+            .join("media")
+            .join(expected_filename("RRR"))
+            .exists()
+    );
+}
+
+#[tokio::test]
+async fn text_include_skips_tweets_matching_no_pattern() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let first = mount_media(&server, "AAA").await;
+    let second = mount_media(&server, "BBB").await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(
+            vec![photo_item("1", &first), photo_item("2", &second)],
+            None,
+        )))
+        .mount(&server)
+        .await;
+
+    let (builder, db) = task_builder(&server, dir.path()).await;
+    let task = builder
+        .text_include(vec!["TWEET 2$".parse().expect("pattern")])
+        .build()
+        .await
+        .expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+
+    assert_eq!(totals.downloaded, 1);
+    assert_eq!(totals.filtered, 1);
+    assert!(
+        dir.path()
+            .join("media")
+            .join(expected_filename("BBB"))
+            .exists()
+    );
+    // Filtered tweets are not recorded
+    let tweets: i64 = sqlx::query("SELECT COUNT(*) AS n FROM tweets")
+        .fetch_one(&db)
+        .await
+        .expect("count")
+        .get("n");
+    assert_eq!(tweets, 1);
+}
+
+#[tokio::test]
+async fn text_exclude_wins_over_text_include() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let first = mount_media(&server, "AAA").await;
+    let second = mount_media(&server, "BBB").await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(
+            vec![photo_item("1", &first), photo_item("2", &second)],
+            None,
+        )))
+        .mount(&server)
+        .await;
+
+    let (builder, _db) = task_builder(&server, dir.path()).await;
+    let task = builder
+        .text_include(vec!["tweet".parse().expect("pattern")])
+        .text_exclude(vec!["1$".parse().expect("pattern")])
+        .build()
+        .await
+        .expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+
+    assert_eq!(totals.downloaded, 1);
+    assert_eq!(totals.filtered, 1);
+    assert_eq!(totals.skipped, 0);
+    assert!(
+        dir.path()
+            .join("media")
+            .join(expected_filename("BBB"))
+            .exists()
+    );
+}
+
+fn item_with_engagement(tweet_id: &str, url: &str, favorites: u64, retweets: u64) -> Value {
+    let mut item = photo_item(tweet_id, url);
+    let legacy = &mut item["item"]["itemContent"]["tweet_results"]["result"]["legacy"];
+    legacy["favorite_count"] = json!(favorites);
+    legacy["retweet_count"] = json!(retweets);
+    item
+}
+
+#[tokio::test]
+async fn minimum_engagement_filters_and_records_counts() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let popular = mount_media(&server, "AAA").await;
+    let few_likes = mount_media(&server, "BBB").await;
+    let few_retweets = mount_media(&server, "CCC").await;
+    let no_counts = mount_media(&server, "DDD").await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(
+            vec![
+                item_with_engagement("1", &popular, 1500, 200),
+                item_with_engagement("2", &few_likes, 999, 200),
+                item_with_engagement("3", &few_retweets, 1500, 10),
+                photo_item("4", &no_counts),
+            ],
+            None,
+        )))
+        .mount(&server)
+        .await;
+
+    let (builder, db) = task_builder(&server, dir.path()).await;
+    let task = builder
+        .min_favorites(1000)
+        .min_retweets(100)
+        .build()
+        .await
+        .expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+
+    assert_eq!(totals.downloaded, 2);
+    assert_eq!(totals.filtered, 2);
+    let row = sqlx::query("SELECT favorite_count, retweet_count FROM tweets WHERE tweet_id = '1'")
+        .fetch_one(&db)
+        .await
+        .expect("tweet row");
+    assert_eq!(row.get::<Option<i64>, _>("favorite_count"), Some(1500));
+    assert_eq!(row.get::<Option<i64>, _>("retweet_count"), Some(200));
+}
+
+#[tokio::test]
+async fn list_only_downloads_and_records_nothing() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    Mock::given(method("GET"))
+        .and(path_regex("^/media/"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&server)
+        .await;
+    let popular = format!("{}/media/AAA.jpg", server.uri());
+    let unpopular = format!("{}/media/BBB.jpg", server.uri());
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(
+            vec![
+                item_with_engagement("1", &popular, 1500, 200),
+                item_with_engagement("2", &unpopular, 10, 200),
+            ],
+            None,
+        )))
+        .mount(&server)
+        .await;
+
+    let format: ListFormat = "{tweet_id} {type} {url}".parse().expect("format");
+    let listed = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let (builder, db) = task_builder(&server, dir.path()).await;
+    let task = builder
+        .min_favorites(1000)
+        .list_only({
+            let listed = Arc::clone(&listed);
+            move |item| {
+                let line = format.line(item, "test_user", ImageSize::Large);
+                listed.lock().expect("lock").push(line);
+            }
+        })
+        .build()
+        .await
+        .expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+
+    assert_eq!(
+        *listed.lock().expect("lock"),
+        [format!("1 image {popular}?name=large")]
+    );
+    assert_eq!(totals.filtered, 1);
+    assert_eq!(totals.downloaded, 0);
+    assert!(!dir.path().join("media").exists());
+    let tweets: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM tweets")
+        .fetch_one(&db)
+        .await
+        .expect("count");
+    assert_eq!(tweets, 0);
+}
+
+#[tokio::test]
+async fn counts_failed_media_download() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let good = mount_media(&server, "AAA").await;
+
+    Mock::given(method("GET"))
+        .and(path("/media/BAD.jpg"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+    let bad = format!("{}/media/BAD.jpg", server.uri());
+
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(
+            vec![photo_item("1", &good), photo_item("2", &bad)],
+            None,
+        )))
+        .mount(&server)
+        .await;
+
+    let (totals, db) = run_task(&server, dir.path()).await;
+    assert_eq!(totals.downloaded, 1);
+    assert_eq!(totals.failed, 1);
+    assert!(
+        !dir.path()
+            .join("media")
+            .join(expected_filename("BAD"))
+            .exists()
+    );
+
+    let filename: Option<String> = sqlx::query("SELECT filename FROM media WHERE media_url = ?")
+        .bind(&bad)
+        .fetch_one(&db)
+        .await
+        .expect("media row")
+        .get("filename");
+    assert_eq!(filename, None);
+}
+
+/// A page with one photo, `/media/GONE.jpg`, answered with 404 until `restored`
+async fn mount_gone_media(server: &MockServer) {
+    mount_user(server).await;
+    Mock::given(method("GET"))
+        .and(path("/media/GONE.jpg"))
+        .respond_with(ResponseTemplate::new(404))
+        .with_priority(10)
+        .mount(server)
+        .await;
+    let url = format!("{}/media/GONE.jpg", server.uri());
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(media_page(vec![photo_item("1", &url)], None)),
+        )
+        .mount(server)
+        .await;
+}
+
+async fn restored(server: &MockServer) {
+    Mock::given(method("GET"))
+        .and(path("/media/GONE.jpg"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"back".to_vec()))
+        .with_priority(1)
+        .mount(server)
+        .await;
+}
+
+async fn media_requests(server: &MockServer) -> usize {
+    let requests = server.received_requests().await.expect("recorded requests");
+    requests
+        .iter()
+        .filter(|r| r.url.path() == "/media/GONE.jpg")
+        .count()
+}
+
+#[tokio::test]
+async fn media_found_gone_is_skipped_on_later_runs() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_gone_media(&server).await;
+
+    let (first, _db) = run_task(&server, dir.path()).await;
+    assert_eq!(first.failed, 1);
+    let requested = media_requests(&server).await;
+
+    restored(&server).await;
+    let (second, _db) = run_task(&server, dir.path()).await;
+    assert_eq!(second.failed, 0);
+    assert_eq!(second.downloaded, 0);
+    assert_eq!(second.gone, 1);
+    assert_eq!(media_requests(&server).await, requested);
+}
+
+#[tokio::test]
+async fn gone_media_is_tried_again_when_rechecked() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_gone_media(&server).await;
+    let (first, db) = run_task(&server, dir.path()).await;
+    assert_eq!(first.failed, 1);
+    let url = format!("{}/media/GONE.jpg", server.uri());
+    // Not found gone a day ago yet
+    let (builder, _db) = task_builder(&server, dir.path()).await;
+    let task = builder
+        .recheck_gone_after(Duration::from_secs(24 * 60 * 60))
+        .build()
+        .await
+        .expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+    assert_eq!(totals.gone, 1);
+
+    restored(&server).await;
+    let (builder, _db) = task_builder(&server, dir.path()).await;
+    let task = builder
+        .recheck_gone_after(Duration::ZERO)
+        .build()
+        .await
+        .expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+
+    assert_eq!(totals.downloaded, 1);
+    assert_eq!(totals.gone, 0);
+    assert!(!rxd::db::is_gone(&db, &url, None).await.expect("gone"));
+}
+
+/// A page with one photo whose first download is answered with `pushback`
+async fn mount_pushed_back_media(server: &MockServer, pushback: ResponseTemplate) {
+    mount_user(server).await;
+    Mock::given(method("GET"))
+        .and(path("/media/AAA.jpg"))
+        .respond_with(pushback)
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(server)
+        .await;
+    let url = mount_media(server, "AAA").await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(media_page(vec![photo_item("1", &url)], None)),
+        )
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn waits_for_retry_after_seconds_of_media_hosts() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_pushed_back_media(
+        &server,
+        ResponseTemplate::new(503).insert_header("retry-after", "1"),
+    )
+    .await;
+
+    let started = std::time::Instant::now();
+    let (totals, _db) = run_task(&server, dir.path()).await;
+
+    assert_eq!(totals.downloaded, 1);
+    assert_eq!(totals.failed, 0);
+    assert!(started.elapsed() >= Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn waits_until_the_retry_after_date_of_media_hosts() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    let date = chrono::Utc::now() + chrono::Duration::seconds(2);
+    mount_pushed_back_media(
+        &server,
+        ResponseTemplate::new(429).insert_header(
+            "retry-after",
+            date.format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+        ),
+    )
+    .await;
+
+    let started = std::time::Instant::now();
+    let (totals, _db) = run_task(&server, dir.path()).await;
+
+    assert_eq!(totals.downloaded, 1);
+    // The date has whole seconds, so it is at least a second away
+    assert!(started.elapsed() >= Duration::from_millis(900));
+}
+
+#[tokio::test]
+async fn retry_after_is_capped_by_the_maximum_wait() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_pushed_back_media(
+        &server,
+        ResponseTemplate::new(429).insert_header("retry-after", "3600"),
+    )
+    .await;
+
+    let (builder, _db) = task_builder(&server, dir.path()).await;
+    let task = builder
+        .pushback_policy(PushbackPolicy::default().max_wait(Duration::from_millis(100)))
+        .build()
+        .await
+        .expect("task");
+    let totals = tokio::time::timeout(Duration::from_secs(10), Arc::new(task).execute())
+        .await
+        .expect("capped wait")
+        .expect("execute");
+
+    assert_eq!(totals.downloaded, 1);
+}
+
+#[tokio::test]
+async fn media_pushed_back_every_time_fails_after_the_retries() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    Mock::given(method("GET"))
+        .and(path("/media/AAA.jpg"))
+        .respond_with(ResponseTemplate::new(503).insert_header("retry-after", "0"))
+        .expect(4)
+        .mount(&server)
+        .await;
+    let url = format!("{}/media/AAA.jpg", server.uri());
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(media_page(vec![photo_item("1", &url)], None)),
+        )
+        .mount(&server)
+        .await;
+
+    let (totals, _db) = run_task(&server, dir.path()).await;
+
+    assert_eq!(totals.downloaded, 0);
+    assert_eq!(totals.failed, 1);
+}
+
+/// Four pages: a new file, then two whose files are gone, then a new one
+/// on the last page, requested `last_page` times
+async fn mount_mostly_known_pages(server: &MockServer, last_page: u64) {
+    let first = mount_media(server, "AAA").await;
+    let last = mount_media(server, "DDD").await;
+    Mock::given(method("GET"))
+        .and(path_regex("^/media/GONE"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(server)
+        .await;
+    let gone = |id: &str| format!("{}/media/GONE{id}.jpg", server.uri());
+
+    let pages = [
+        (
+            "page-1",
+            media_page(vec![photo_item("2", &gone("B"))], Some("page-2")),
+        ),
+        (
+            "page-2",
+            media_page(vec![photo_item("3", &gone("C"))], Some("page-3")),
+        ),
+    ];
+    for (cursor, body) in pages {
+        Mock::given(method("GET"))
+            .and(path(USER_MEDIA))
+            .and(query_param_contains("variables", cursor))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .with_priority(1)
+            .mount(server)
+            .await;
+    }
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .and(query_param_contains("variables", "page-3"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(media_page(vec![photo_item("4", &last)], None)),
+        )
+        .with_priority(1)
+        .expect(last_page)
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(media_page(vec![photo_item("1", &first)], Some("page-1"))),
+        )
+        .with_priority(10)
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn stops_after_pages_without_new_downloads() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    // Failed downloads are not new either
+    mount_mostly_known_pages(&server, 0).await;
+
+    let (builder, _db) = task_builder(&server, dir.path()).await;
+    let pages = std::num::NonZeroU32::new(2).expect("nonzero");
+    let task = builder
+        .stop_after_known_pages(pages)
+        .build()
+        .await
+        .expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+
+    assert_eq!(totals.downloaded, 1);
+    assert_eq!(totals.failed, 2);
+}
+
+#[tokio::test]
+async fn full_scans_ignore_stop_after_known_pages() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    mount_mostly_known_pages(&server, 1).await;
+
+    let (builder, _db) = task_builder(&server, dir.path()).await;
+    let pages = std::num::NonZeroU32::new(2).expect("nonzero");
+    let task = builder
+        .stop_after_known_pages(pages)
+        .full_scan(true)
+        .build()
+        .await
+        .expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+
+    assert_eq!(totals.downloaded, 2);
+    assert_eq!(totals.failed, 2);
+}
+
+#[tokio::test]
+async fn files_over_max_file_size_are_skipped() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let small = mount_media(&server, "AAA").await;
+
+    Mock::given(method("GET"))
+        .and(path("/media/BIG.jpg"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0u8; 4096]))
+        .mount(&server)
+        .await;
+    let big = format!("{}/media/BIG.jpg", server.uri());
+
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(
+            vec![photo_item("1", &small), photo_item("2", &big)],
+            None,
+        )))
+        .mount(&server)
+        .await;
+
+    let (builder, db) = task_builder(&server, dir.path()).await;
+    let task = builder.max_file_size(1024).build().await.expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+
+    assert_eq!(totals.downloaded, 1);
+    assert_eq!(totals.too_large, 1);
+    let record = rxd::db::get_media_by_url(&db, &big).await.expect("lookup");
+    assert_eq!(record.and_then(|r| r.file_hash), None);
+    assert_eq!(totals.failed, 0);
+    let media = dir.path().join("media");
+    assert!(!media.join(expected_filename("BIG")).exists());
+    assert!(
+        !media
+            .join(format!("{}.part", expected_filename("BIG")))
+            .exists()
+    );
+}
+
+#[tokio::test]
+async fn media_imported_from_gallery_dl_is_skipped() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let first = mount_media(&server, "AAA").await;
+    let imported = format!("{}/media/BBB.jpg", server.uri());
+
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(
+            vec![photo_item("1", &first), photo_item("2", &imported)],
+            None,
+        )))
+        .mount(&server)
+        .await;
+
+    let (builder, db) = task_builder(&server, dir.path()).await;
+    let archive = dir.path().join("archive.sqlite3");
+    let options = sqlx::sqlite::SqliteConnectOptions::new()
+        .filename(&archive)
+        .create_if_missing(true);
+    let gallery_dl_db = sqlx::SqlitePool::connect_with(options)
+        .await
+        .expect("archive");
+    sqlx::query("CREATE TABLE archive (entry TEXT PRIMARY KEY)")
+        .execute(&gallery_dl_db)
+        .await
+        .expect("table");
+    sqlx::query("INSERT INTO archive (entry) VALUES ('twitter2_0_1')")
+        .execute(&gallery_dl_db)
+        .await
+        .expect("entry");
+    gallery_dl_db.close().await;
+    rxd::import::gallery_dl::import(&db, &archive, None, Some("test_user"))
+        .await
+        .expect("import");
+
+    let task = builder.build().await.expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+
+    assert_eq!(totals.downloaded, 1);
+    assert_eq!(totals.skipped, 1);
+    assert!(
+        !dir.path()
+            .join("media")
+            .join(expected_filename("BBB"))
+            .exists()
+    );
+}
+
+#[tokio::test]
+async fn hashes_large_files_while_writing_them() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    // Arrives in many chunks
+    let content: Vec<u8> = (0..3 << 20).map(|i: u32| (i % 251) as u8).collect();
+    Mock::given(method("GET"))
+        .and(path("/media/BIG.jpg"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(content.clone()))
+        .mount(&server)
+        .await;
+    let url = format!("{}/media/BIG.jpg", server.uri());
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(media_page(vec![photo_item("1", &url)], None)),
+        )
+        .mount(&server)
+        .await;
+
+    let (builder, db) = task_builder(&server, dir.path()).await;
+    let task = builder
+        .hash_algorithm(HashAlgorithm::Blake3)
+        .build()
+        .await
+        .expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+
+    assert_eq!(totals.downloaded, 1);
+    let saved = dir.path().join("media").join(expected_filename("BIG"));
+    assert_eq!(std::fs::read(&saved).expect("saved"), content);
+    let record = rxd::db::get_media_by_url(&db, &url)
+        .await
+        .expect("lookup")
+        .expect("recorded");
+    assert_eq!(
+        record.file_hash,
+        Some(HashAlgorithm::Blake3.hash_file(&saved).expect("hash"))
+    );
+}
+
+fn video_item(tweet_id: &str, thumb: &str, variant: &str) -> Value {
+    let mut item = photo_item(tweet_id, thumb);
+    item["item"]["itemContent"]["tweet_results"]["result"]["legacy"]["extended_entities"]["media"]
+        [0] = json!({
+        "type": "video",
+        "media_url_https": thumb,
+        "original_info": { "width": 1920, "height": 1080 },
+        "video_info": {
+            "duration_millis": 15015,
+            "variants": [
+                { "content_type": "video/mp4", "bitrate": 832000, "url": variant }
+            ]
+        }
+    });
+    item
+}
+
+/// Serve a video tweet whose only variant is `variant`, returning its URL
+async fn mount_video_page(server: &MockServer, thumb: &str, variant: &str) -> String {
+    let variant_path = format!("/video/{variant}.mp4");
+    Mock::given(method("GET"))
+        .and(path(variant_path.as_str()))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"video".as_slice()))
+        .mount(server)
+        .await;
+    let variant_url = format!("{}{}", server.uri(), variant_path);
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(media_page(vec![video_item("1", thumb, &variant_url)], None)),
+        )
+        .mount(server)
+        .await;
+    variant_url
+}
+
+#[tokio::test]
+async fn records_the_url_each_file_was_fetched_from() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let image = mount_media(&server, "AAA").await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(media_page(vec![photo_item("2", &image)], None)),
+        )
+        .mount(&server)
+        .await;
+
+    let (totals, db) = run_task(&server, dir.path()).await;
+
+    assert_eq!(totals.downloaded, 1);
+    let record = rxd::db::get_media_by_url(&db, &image)
+        .await
+        .expect("lookup")
+        .expect("recorded");
+    assert_eq!(record.download_url, Some(format!("{image}?name=orig")));
+}
+
+#[tokio::test]
+async fn new_video_variants_are_not_downloaded_again() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let thumb = format!("{}/thumb/VVV.jpg", server.uri());
+    let first = mount_video_page(&server, &thumb, "720p").await;
+    let (totals, db) = run_task(&server, dir.path()).await;
+    assert_eq!(totals.downloaded, 1);
+
+    server.reset().await;
+    mount_user(&server).await;
+    mount_video_page(&server, &thumb, "1080p").await;
+    let (totals, _db) = run_task(&server, dir.path()).await;
+
+    assert_eq!(totals.downloaded, 0);
+    assert_eq!(totals.skipped, 1);
+    let record = rxd::db::get_media_by_url(&db, &thumb)
+        .await
+        .expect("lookup")
+        .expect("recorded");
+    assert_eq!(record.download_url, Some(first));
+}
+
+#[tokio::test]
+async fn videos_recorded_under_their_variant_are_moved_to_the_canonical_url() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let thumb = format!("{}/thumb/VVV.jpg", server.uri());
+    let variant = mount_video_page(&server, &thumb, "720p").await;
+    let (totals, db) = run_task(&server, dir.path()).await;
+    assert_eq!(totals.downloaded, 1);
+    // As recorded before canonical URLs
+    sqlx::query("UPDATE media SET media_url = ?, download_url = NULL")
+        .bind(&variant)
+        .execute(&db)
+        .await
+        .expect("legacy key");
+
+    let (totals, _db) = run_task(&server, dir.path()).await;
+
+    assert_eq!(totals.downloaded, 0);
+    assert_eq!(totals.skipped, 1);
+    let record = rxd::db::get_media_by_url(&db, &thumb)
+        .await
+        .expect("lookup")
+        .expect("moved");
+    assert_eq!(record.download_url, Some(variant.clone()));
+    assert!(
+        rxd::db::get_media_by_url(&db, &variant)
+            .await
+            .expect("lookup")
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn video_posters_are_saved_next_to_recorded_videos() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let thumb = format!("{}/thumb/VVV.jpg", server.uri());
+    mount_video_page(&server, &thumb, "720p").await;
+    let (totals, db) = run_task(&server, dir.path()).await;
+    assert_eq!(totals.downloaded, 1);
+
+    Mock::given(method("GET"))
+        .and(path("/thumb/VVV.jpg"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"poster".as_slice()))
+        .expect(1)
+        .mount(&server)
+        .await;
+    for _ in 0..2 {
+        let (builder, _db) = task_builder(&server, dir.path()).await;
+        let task = builder.video_thumbnails(true).build().await.expect("task");
+        let totals = Arc::new(task).execute().await.expect("execute");
+        assert_eq!(totals.skipped, 1);
+    }
+
+    let record = rxd::db::get_media_by_url(&db, &thumb)
+        .await
+        .expect("lookup")
+        .expect("recorded");
+    let video = record.filename.expect("filename");
+    let poster = record.thumbnail.expect("thumbnail");
+    assert_eq!(poster, video.replace(".mp4", ".thumb.jpg"));
+    let saved = std::fs::read(dir.path().join("media").join(&poster)).expect("poster");
+    assert_eq!(saved, b"poster");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn video_frames_are_extracted_with_the_poster_as_fallback() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    let tools = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let thumb = format!("{}/thumb/VVV.jpg", server.uri());
+    mount_video_page(&server, &thumb, "720p").await;
+    Mock::given(method("GET"))
+        .and(path("/thumb/VVV.jpg"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"poster".as_slice()))
+        .mount(&server)
+        .await;
+
+    let mut saved = Vec::new();
+    for works in [true, false] {
+        let frames = rxd::thumbs::FrameExtractor::new(
+            fake_ffmpeg(tools.path(), works),
+            Duration::from_secs(10),
+            1,
+        );
+        let (builder, db) = task_builder(&server, dir.path()).await;
+        let task = builder
+            .video_thumbnails(true)
+            .frame_extractor(Arc::new(frames))
+            .overwrite(true)
+            .build()
+            .await
+            .expect("task");
+        let totals = Arc::new(task).execute().await.expect("execute");
+        // A failed extraction does not fail the download
+        assert_eq!(totals.downloaded, 1);
+        assert_eq!(totals.failed, 0);
+        let record = rxd::db::get_media_by_url(&db, &thumb)
+            .await
+            .expect("lookup")
+            .expect("recorded");
+        let poster = record.thumbnail.expect("thumbnail");
+        saved.push(std::fs::read(dir.path().join("media").join(&poster)).expect("thumbnail"));
+    }
+    // The fake ffmpeg writes its arguments as the frame
+    let frame = String::from_utf8(saved[0].clone()).expect("frame");
+    assert!(frame.contains(" -i "), "{frame}");
+    assert_eq!(saved[1], b"poster");
+}
+
+/// Stand-in for ffmpeg that converts anything but `bad` input to `webp`
+#[cfg(unix)]
+fn fake_converter(dir: &Path) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = dir.join("ffmpeg");
+    let script = "#!/bin/sh
+while [ $# -gt 0 ]; do
+    case \"$1\" in -i) input=\"$2\"; shift;; esac
+    output=\"$1\"; shift
+done
+if grep -q bad \"$input\"; then echo 'Invalid data found' >&2; exit 1; fi
+[ \"$output\" = - ] || echo webp > \"$output\"
+";
+    std::fs::write(&path, script).expect("script");
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).expect("chmod");
+    path
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn post_process_converts_images_and_keeps_undecodable_ones() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    let tools = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let mut urls = Vec::new();
+    for (media_id, body) in [("GOOD", "png"), ("BAD", "bad")] {
+        let media_path = format!("/media/{media_id}.png");
+        Mock::given(method("GET"))
+            .and(path(media_path.as_str()))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "image/png"))
+            .mount(&server)
+            .await;
+        urls.push(format!("{}{}", server.uri(), media_path));
+    }
+    // Not matched by `only`
+    urls.push(mount_media(&server, "JPEG").await);
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(
+            vec![
+                photo_item("1", &urls[0]),
+                photo_item("2", &urls[1]),
+                photo_item("3", &urls[2]),
+            ],
+            None,
+        )))
+        .mount(&server)
+        .await;
+
+    let post_process = PostProcess::new(ConvertFormat::Webp).ffmpeg(fake_converter(tools.path()));
+    let (builder, db) = task_builder(&server, dir.path()).await;
+    let task = builder
+        .post_process(post_process.clone())
+        .build()
+        .await
+        .expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+    assert_eq!(totals.downloaded, 3);
+    assert_eq!((totals.converted, totals.unconverted), (1, 1));
+
+    let media = dir.path().join("media");
+    let record = rxd::db::get_media_by_url(&db, &urls[0])
+        .await
+        .expect("lookup")
+        .expect("recorded");
+    assert_eq!(
+        record.filename.as_deref(),
+        Some(expected_filename_with_ext("GOOD", "webp").as_str())
+    );
+    assert_eq!(
+        record.file_hash.as_deref(),
+        Some(rxd::db::calculate_hash(b"webp\n", HashAlgorithm::Sha256).as_str())
+    );
+    assert!(
+        !media
+            .join(expected_filename_with_ext("GOOD", "png"))
+            .exists()
+    );
+    let bad = std::fs::read(media.join(expected_filename_with_ext("BAD", "png"))).expect("kept");
+    assert_eq!(bad, b"bad");
+    assert!(
+        !media
+            .join(expected_filename_with_ext("BAD", "webp"))
+            .exists()
+    );
+    assert!(
+        !media
+            .join(expected_filename_with_ext("BAD", "webp.part"))
+            .exists()
+    );
+
+    // The converted file verifies under its new name
+    let (builder, _db) = task_builder(&server, dir.path()).await;
+    let task = builder
+        .post_process(post_process)
+        .build()
+        .await
+        .expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+    assert_eq!(totals.skipped, 3);
+    assert_eq!(totals.downloaded, 0);
+}
+
+#[tokio::test]
+async fn files_hashed_before_switching_to_blake3_still_verify() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let first = mount_media(&server, "AAA").await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(media_page(vec![photo_item("1", &first)], None)),
+        )
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    let (totals, db) = run_task(&server, dir.path()).await;
+    assert_eq!(totals.downloaded, 1);
+
+    let second = mount_media(&server, "BBB").await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(
+            vec![photo_item("1", &first), photo_item("2", &second)],
+            None,
+        )))
+        .mount(&server)
+        .await;
+    let (builder, _db) = task_builder(&server, dir.path()).await;
+    let task = builder
+        .hash_algorithm(HashAlgorithm::Blake3)
+        .build()
+        .await
+        .expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+
+    // The sha256 row verifies as it was written, the new file gets blake3
+    assert_eq!(totals.downloaded, 1);
+    assert_eq!(totals.skipped, 1);
+    for (url, content, algorithm) in [
+        (&first, b"AAA", HashAlgorithm::Sha256),
+        (&second, b"BBB", HashAlgorithm::Blake3),
+    ] {
+        let record = rxd::db::get_media_by_url(&db, url)
+            .await
+            .expect("lookup")
+            .expect("recorded");
+        assert_eq!(record.hash_algorithm, Some(algorithm));
+        assert_eq!(record.file_hash, Some(algorithm.hash(content)));
+        assert!(
+            rxd::db::verify_file(&db, url, &dir.path().join("media"))
+                .await
+                .expect("verify")
+        );
+    }
+}
+
+#[tokio::test]
+async fn overwrite_replaces_verified_files() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    // Served in a lower size the first time
+    Mock::given(method("GET"))
+        .and(path("/media/AAA.jpg"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"small".to_vec()))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    let first = mount_media(&server, "AAA").await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(media_page(vec![photo_item("1", &first)], None)),
+        )
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    let (totals, db) = run_task(&server, dir.path()).await;
+    assert_eq!(totals.downloaded, 1);
+
+    let second = mount_media(&server, "BBB").await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(
+            vec![
+                photo_item("1", &first),
+                photo_item("2", &second),
+                retweet_item("3", &format!("{}/media/CCC.jpg", server.uri())),
+            ],
+            None,
+        )))
+        .mount(&server)
+        .await;
+    let (builder, _db) = task_builder(&server, dir.path()).await;
+    let task = builder.overwrite(true).build().await.expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+
+    // Filters still apply, only files on disk count as replaced
+    assert_eq!(totals.downloaded, 2);
+    assert_eq!(totals.replaced, 1);
+    assert_eq!(totals.filtered, 1);
+    let file = dir.path().join("media").join(expected_filename("AAA"));
+    assert_eq!(std::fs::read(&file).expect("read"), b"AAA");
+    let record = rxd::db::get_media_by_url(&db, &first)
+        .await
+        .expect("lookup")
+        .expect("recorded");
+    assert_eq!(record.file_hash, Some(HashAlgorithm::Sha256.hash(b"AAA")));
+}
+
+#[tokio::test]
+async fn identical_media_are_linked_to_the_recorded_file() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let mut urls = Vec::new();
+    for media_id in ["AAA", "BBB"] {
+        let media_path = format!("/media/{media_id}.jpg");
+        Mock::given(method("GET"))
+            .and(path(media_path.as_str()))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"same".to_vec()))
+            .mount(&server)
+            .await;
+        urls.push(format!("{}{}", server.uri(), media_path));
+    }
+    // One page per run, the second file is linked to the first
+    for (tweet_id, url) in [("1", &urls[0]), ("2", &urls[1])] {
+        Mock::given(method("GET"))
+            .and(path(USER_MEDIA))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(media_page(vec![photo_item(tweet_id, url)], None)),
+            )
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        let (builder, _db) = task_builder(&server, dir.path()).await;
+        let task = builder
+            .dedupe(Dedupe::Hardlink, |_: &str| unreachable!("same account"))
+            .build()
+            .await
+            .expect("task");
+        let totals = Arc::new(task).execute().await.expect("execute");
+        assert_eq!(totals.downloaded, 1);
+    }
+
+    let media = dir.path().join("media");
+    let linked = media.join(expected_filename("BBB"));
+    assert_eq!(std::fs::read(&linked).expect("read"), b"same");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        assert_eq!(std::fs::metadata(&linked).expect("metadata").nlink(), 2);
+    }
+
+    let (_builder, db) = task_builder(&server, dir.path()).await;
+    let report = DuplicateReport::new(rxd::db::duplicate_media(&db).await.expect("duplicates"));
+    assert_eq!(report.groups.len(), 1);
+    let group: Vec<_> = report.groups[0]
+        .iter()
+        .map(|m| (m.media_url.as_str(), m.link))
+        .collect();
+    assert_eq!(
+        group,
+        [
+            (urls[0].as_str(), None),
+            (urls[1].as_str(), Some(LinkKind::Hardlink))
+        ]
+    );
+    assert_eq!(report.hardlinks, 1);
+    assert_eq!(report.bytes_saved, 4);
+}
+
+#[tokio::test]
+async fn writes_decoded_tweet_text_next_to_each_file() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let captioned = mount_media(&server, "AAA").await;
+    let untitled = mount_media(&server, "BBB").await;
+    let mut with_text = photo_item("1", &captioned);
+    with_text["item"]["itemContent"]["tweet_results"]["result"]["legacy"]["full_text"] =
+        json!("Tom &amp; Jerry &lt;3 &#x1F600;");
+    let mut without_text = photo_item("2", &untitled);
+    without_text["item"]["itemContent"]["tweet_results"]["result"]["legacy"]["full_text"] =
+        json!("");
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(media_page(vec![with_text, without_text], None)),
+        )
+        .mount(&server)
+        .await;
+
+    let (builder, _db) = task_builder(&server, dir.path()).await;
+    let task = builder
+        .text_sidecars(TextSidecars {
+            per_tweet: false,
+            permalink: true,
+        })
+        .build()
+        .await
+        .expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+    assert_eq!(totals.downloaded, 2);
+
+    let media = dir.path().join("media");
+    let caption = media.join(expected_filename_with_ext("AAA", "txt"));
+    assert_eq!(
+        std::fs::read_to_string(caption).expect("caption"),
+        "Tom & Jerry <3 \u{1F600}\nhttps://x.com/test_user/status/1\n"
+    );
+    assert!(
+        !media
+            .join(expected_filename_with_ext("BBB", "txt"))
+            .exists()
+    );
+}
+
+#[tokio::test]
+async fn embedded_metadata_is_part_of_the_recorded_hash() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let jpeg = include_bytes!("fixtures/pixel.jpg");
+    Mock::given(method("GET"))
+        .and(path("/media/AAA.jpg"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(jpeg.to_vec()))
+        .mount(&server)
+        .await;
+    let url = format!("{}/media/AAA.jpg", server.uri());
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(media_page(vec![photo_item("1", &url)], None)),
+        )
+        .mount(&server)
+        .await;
+
+    let (builder, db) = task_builder(&server, dir.path()).await;
+    let task = builder.embed_metadata(true).build().await.expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+    assert_eq!(totals.downloaded, 1);
+
+    let media = dir.path().join("media");
+    let file = std::fs::read(media.join(expected_filename("AAA"))).expect("read");
+    assert_ne!(file, jpeg);
+    assert!(file.windows(7).any(|w| w == b"@test_u"));
+    assert_eq!(totals.bytes, file.len() as u64);
+    assert!(
+        rxd::db::verify_file(&db, &url, &media)
+            .await
+            .expect("verify")
+    );
+}
+
+const LAST_MODIFIED: &str = "Wed, 12 Mar 2025 12:00:00 GMT";
+
+/// Download `AAA` served with an ETag, then edit the file so that it no
+/// longer verifies
+async fn download_and_edit(server: &MockServer, dir: &Path) -> SqlitePool {
+    mount_user(server).await;
+    Mock::given(method("GET"))
+        .and(path("/media/AAA.jpg"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("etag", "\"v1\"")
+                .insert_header("last-modified", LAST_MODIFIED)
+                .set_body_bytes(b"AAA".as_slice()),
+        )
+        .mount(server)
+        .await;
+    let url = format!("{}/media/AAA.jpg", server.uri());
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(media_page(vec![photo_item("1", &url)], None)),
+        )
+        .mount(server)
+        .await;
+
+    let (totals, db) = run_task(server, dir).await;
+    assert_eq!(totals.downloaded, 1);
+    std::fs::write(dir.join("media").join(expected_filename("AAA")), b"edited").expect("edit");
+
+    server.reset().await;
+    mount_user(server).await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(media_page(vec![photo_item("1", &url)], None)),
+        )
+        .mount(server)
+        .await;
+    db
+}
+
+/// Run again under another name, so the recorded file is not adopted as is
+async fn run_renamed(server: &MockServer, dir: &Path) -> rxd::events::Totals {
+    let (builder, _db) = task_builder(server, dir).await;
+    let template = Template::parse("{media_id}").expect("template");
+    let task = builder
+        .filename_template(template)
+        .build()
+        .await
+        .expect("task");
+    Arc::new(task).execute().await.expect("execute")
+}
+
+#[tokio::test]
+async fn unchanged_media_is_not_downloaded_again() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    download_and_edit(&server, dir.path()).await;
+    Mock::given(method("GET"))
+        .and(path("/media/AAA.jpg"))
+        .and(header("if-none-match", "\"v1\""))
+        // Matchers split values on commas, which dates contain
+        .and(header_exists("if-modified-since"))
+        .respond_with(ResponseTemplate::new(304))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let totals = run_renamed(&server, dir.path()).await;
+
+    assert_eq!(totals.downloaded, 0);
+    assert_eq!(totals.skipped, 1);
+    assert_eq!(totals.failed, 0);
+    assert!(!dir.path().join("media").join("AAA.jpg").exists());
+}
+
+#[tokio::test]
+async fn changed_media_is_downloaded_with_its_new_etag() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    let db = download_and_edit(&server, dir.path()).await;
+    Mock::given(method("GET"))
+        .and(path("/media/AAA.jpg"))
+        .and(header("if-none-match", "\"v1\""))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("etag", "\"v2\"")
+                .set_body_bytes(b"AAA v2".as_slice()),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let totals = run_renamed(&server, dir.path()).await;
+
+    assert_eq!(totals.downloaded, 1);
+    let saved = std::fs::read(dir.path().join("media").join("AAA.jpg")).expect("saved");
+    assert_eq!(saved, b"AAA v2");
+    let url = format!("{}/media/AAA.jpg", server.uri());
+    let record = rxd::db::get_media_by_url(&db, &url)
+        .await
+        .expect("lookup")
+        .expect("recorded");
+    assert_eq!(record.etag.as_deref(), Some("\"v2\""));
+    assert_eq!(record.last_modified, None);
+}
+
+#[tokio::test]
+async fn sends_events_to_attached_channel() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let url = mount_media(&server, "AAA").await;
+
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(media_page(vec![photo_item("1", &url)], None)),
+        )
+        .mount(&server)
+        .await;
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let (builder, _db) = task_builder(&server, dir.path()).await;
+    let task = builder.events(tx).build().await.expect("task");
+    Arc::new(task).execute().await.expect("execute");
+
+    let mut kinds = Vec::new();
+    while let Ok(event) = rx.try_recv() {
+        match event {
+            Event::TaskStarted { .. } => kinds.push("task_started"),
+            Event::PageFetched { count, .. } => {
+                assert_eq!(count, 1);
+                kinds.push("page_fetched");
+            }
+            Event::DownloadStarted { .. } => kinds.push("download_started"),
+            Event::DownloadProgress { .. } => {}
+            Event::ItemDownloaded { bytes, .. } => {
+                assert_eq!(bytes, 3);
+                kinds.push("item_downloaded");
+            }
+            Event::TaskFinished { totals, .. } => {
+                assert_eq!(totals.downloaded, 1);
+                kinds.push("task_finished");
+            }
+            other => panic!("unexpected event {other:?}"),
+        }
+    }
+    assert_eq!(
+        kinds,
+        [
+            "task_started",
+            "page_fetched",
+            "download_started",
+            "item_downloaded",
+            "task_finished"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn cancellation_stops_further_requests() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let cancel = CancellationToken::new();
+
+    Mock::given(method("GET"))
+        .and(path("/media/AAA.jpg"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&server)
+        .await;
+    let url = format!("{}/media/AAA.jpg", server.uri());
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .and(query_param_contains("variables", "page-1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(vec![], None)))
+        .expect(0)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    // Cancel while the first page is being served
+    let page = media_page(vec![photo_item("1", &url)], Some("page-1"));
+    let token = cancel.clone();
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(move |_: &wiremock::Request| {
+            token.cancel();
+            ResponseTemplate::new(200).set_body_json(page.clone())
+        })
+        .expect(1)
+        .with_priority(10)
+        .mount(&server)
+        .await;
+
+    let (builder, _db) = task_builder(&server, dir.path()).await;
+    let task = builder.cancellation(cancel).build().await.expect("task");
+
+    match Arc::new(task).execute().await {
+        Err(rxd::Error::Cancelled { totals }) => assert_eq!(totals.downloaded, 0),
+        other => panic!("expected cancellation, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn fetching_runs_ahead_of_slow_downloads_by_a_bounded_amount() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let cancel = CancellationToken::new();
+
+    Mock::given(method("GET"))
+        .and(path_regex("^/media/"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(b"slow".to_vec())
+                .set_delay(Duration::from_secs(1)),
+        )
+        .mount(&server)
+        .await;
+    // Page n links to page n + 1, without end
+    let uri = server.uri();
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(move |request: &wiremock::Request| {
+            let variables = request
+                .url
+                .query_pairs()
+                .find(|(key, _)| key == "variables")
+                .map(|(_, value)| value.into_owned())
+                .unwrap_or_default();
+            let page: u32 = serde_json::from_str::<Value>(&variables)
+                .ok()
+                .and_then(|v| v["cursor"].as_str().map(str::to_string))
+                .and_then(|cursor| cursor.strip_prefix("page-")?.parse().ok())
+                .unwrap_or(0);
+            let url = format!("{uri}/media/M{page}.jpg");
+            let next = format!("page-{}", page + 1);
+            ResponseTemplate::new(200).set_body_json(media_page(
+                vec![photo_item(&page.to_string(), &url)],
+                Some(&next),
+            ))
+        })
+        .mount(&server)
+        .await;
+
+    let db = rxd::db::init_memory_db().await.expect("db");
+    let api = Api::with_base_url("token", "ct0", &server.uri())
+        .expect("api")
+        .with_page_size(rxd::api::PageSize::try_from(1).expect("page size"));
+    let task = Task::builder()
+        .api(api)
+        .screen_name("test_user")
+        .concurrency(1)
+        .save_path(dir.path().join("media"))
+        .db(db)
+        .cancellation(cancel.clone())
+        .build()
+        .await
+        .expect("task");
+
+    let pages_requested = async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let requests = server.received_requests().await.unwrap_or_default();
+        cancel.cancel();
+        requests
+            .iter()
+            .filter(|r| r.url.path() == USER_MEDIA)
+            .count()
+    };
+    let (result, pages) = tokio::join!(Arc::new(task).execute(), pages_requested);
+
+    assert!(
+        matches!(result, Err(rxd::Error::Cancelled { .. })),
+        "{result:?}"
+    );
+    // Later pages were fetched during the first download, but only a
+    // couple of pages ahead of it
+    assert!((2..=5).contains(&pages), "{pages} pages requested");
+}
+
+#[tokio::test]
+async fn referer_and_origin_follow_base_url() {
+    let server = MockServer::start().await;
+    let uri = server.uri();
+
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .and(header("origin", uri.as_str()))
+        .and(header("referer", format!("{uri}/test_user").as_str()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(user_response()))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    // A trailing slash on the configured base must not change the headers
+    for base in [uri.clone(), format!("{uri}/")] {
+        let api = Api::with_base_url("token", "ct0", &base).expect("api");
+        assert_eq!(api.base_url(), uri);
+        let user = api.user_by_screen_name("test_user").await.expect("user");
+        assert_eq!(user.rest_id, "42");
+    }
+}
+
+#[test]
+fn defaults_to_x_com() {
+    let api = Api::new("token", "ct0").expect("api");
+    assert_eq!(api.base_url(), "https://x.com");
+}
+
+#[tokio::test]
+async fn retries_with_missing_features() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let url = mount_media(&server, "AAA").await;
+
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .and(query_param_contains(
+            "features",
+            "\"rweb_video_screen_enabled\":false",
+        ))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(media_page(vec![photo_item("1", &url)], None)),
+        )
+        .expect(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+            "errors": [{
+                "message": "The following features cannot be null: rweb_video_screen_enabled",
+                "code": 336
+            }]
+        })))
+        .expect(1)
+        .with_priority(10)
+        .mount(&server)
+        .await;
+
+    let (totals, _db) = run_task(&server, dir.path()).await;
+    assert_eq!(totals.downloaded, 1);
+}
+
+#[tokio::test]
+async fn extension_follows_content_type() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+
+    // URL says jpg, the server sends a PNG
+    Mock::given(method("GET"))
+        .and(path("/media/PNG.jpg"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(b"png".to_vec(), "image/png"))
+        .mount(&server)
+        .await;
+    let png = format!("{}/media/PNG.jpg", server.uri());
+    // No Content-Type, the URL extension is kept
+    let plain = mount_media(&server, "PLAIN").await;
+
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(
+            vec![photo_item("1", &png), photo_item("2", &plain)],
+            None,
+        )))
+        .mount(&server)
+        .await;
+
+    let (totals, _db) = run_task(&server, dir.path()).await;
+    assert_eq!(totals.downloaded, 2);
+    let media = dir.path().join("media");
+    assert!(
+        media
+            .join(expected_filename_with_ext("PNG", "png"))
+            .exists()
+    );
+    assert!(!media.join(expected_filename("PNG")).exists());
+    assert!(media.join(expected_filename("PLAIN")).exists());
+}
+
+#[tokio::test]
+async fn falls_back_to_smaller_image_size() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+
+    for (size, status) in [("orig", 404), ("4096x4096", 404), ("large", 200)] {
+        Mock::given(method("GET"))
+            .and(path("/media/OLD.jpg"))
+            .and(query_param("name", size))
+            .respond_with(ResponseTemplate::new(status).set_body_bytes(size.as_bytes()))
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+    let url = format!("{}/media/OLD.jpg", server.uri());
+
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(media_page(vec![photo_item("1", &url)], None)),
+        )
+        .mount(&server)
+        .await;
+
+    let (totals, db) = run_task(&server, dir.path()).await;
+    assert_eq!(totals.downloaded, 1);
+    let content =
+        std::fs::read(dir.path().join("media").join(expected_filename("OLD"))).expect("file");
+    assert_eq!(content, b"large");
+
+    let record = rxd::db::get_media_by_url(&db, &url)
+        .await
+        .expect("query")
+        .expect("media row");
+    assert_eq!(record.image_size.as_deref(), Some("large"));
+}
+
+#[tokio::test]
+async fn server_errors_skip_size_fallback() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+
+    Mock::given(method("GET"))
+        .and(path("/media/ERR.jpg"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let url = format!("{}/media/ERR.jpg", server.uri());
+
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(media_page(vec![photo_item("1", &url)], None)),
+        )
+        .mount(&server)
+        .await;
+
+    let (totals, _db) = run_task(&server, dir.path()).await;
+    assert_eq!(totals.failed, 1);
+}
+
+#[tokio::test]
+async fn downloads_configured_image_size() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+
+    Mock::given(method("GET"))
+        .and(path("/media/AAA.jpg"))
+        .and(query_param("name", "large"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"large".to_vec()))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let url = format!("{}/media/AAA.jpg", server.uri());
+
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(media_page(vec![photo_item("1", &url)], None)),
+        )
+        .mount(&server)
+        .await;
+
+    let (builder, db) = task_builder(&server, dir.path()).await;
+    let task = builder
+        .image_size(ImageSize::Large)
+        .build()
+        .await
+        .expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+    assert_eq!(totals.downloaded, 1);
+
+    // The name does not depend on the size so existing files are still found
+    assert!(
+        dir.path()
+            .join("media")
+            .join(expected_filename("AAA"))
+            .exists()
+    );
+    let record = rxd::db::get_media_by_url(&db, &url)
+        .await
+        .expect("query")
+        .expect("media row");
+    assert_eq!(record.image_size.as_deref(), Some("large"));
+}
+
+#[tokio::test]
+async fn server_errors_retry_the_same_page() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let first = mount_media(&server, "AAA").await;
+    let second = mount_media(&server, "BBB").await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .and(query_param_contains("variables", "page-3"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(vec![], None)))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    // The second page fails once, then answers
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .and(query_param_contains("variables", "page-2"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .and(query_param_contains("variables", "page-2"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(media_page(vec![photo_item("2", &second)], Some("page-3"))),
+        )
+        .with_priority(2)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(media_page(vec![photo_item("1", &first)], Some("page-2"))),
+        )
+        .with_priority(10)
+        .mount(&server)
+        .await;
+
+    let (builder, _db) = task_builder(&server, dir.path()).await;
+    let task = builder
+        .page_retries(PageRetryPolicy::default().backoff(Duration::from_millis(10)))
+        .build()
+        .await
+        .expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+
+    assert_eq!(totals.fetched, 2);
+    assert_eq!(totals.downloaded, 2);
+    let requests = server.received_requests().await.expect("recorded");
+    let page_2 = requests
+        .iter()
+        .filter(|r| r.url.path() == USER_MEDIA && r.url.as_str().contains("page-2"))
+        .count();
+    assert_eq!(page_2, 2);
+}
+
+#[tokio::test]
+async fn client_errors_are_not_retried() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(404))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let (builder, _db) = task_builder(&server, dir.path()).await;
+    let task = builder
+        .page_retries(PageRetryPolicy::default().backoff(Duration::from_millis(10)))
+        .build()
+        .await
+        .expect("task");
+    let error = Arc::new(task).execute().await.expect_err("fetch failure");
+
+    match error {
+        rxd::Error::FetchFailed { page, totals, .. } => {
+            assert_eq!(page, 1);
+            assert_eq!(totals.fetched, 0);
+        }
+        e => panic!("{e:?}"),
+    }
+}
+
+#[tokio::test]
+async fn failed_page_keeps_the_totals_of_the_pages_before() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let first = mount_media(&server, "AAA").await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .and(query_param_contains("variables", "page-2"))
+        .respond_with(ResponseTemplate::new(404))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(media_page(vec![photo_item("1", &first)], Some("page-2"))),
+        )
+        .with_priority(10)
+        .mount(&server)
+        .await;
+
+    let (builder, _db) = task_builder(&server, dir.path()).await;
+    let task = builder.build().await.expect("task");
+    let error = Arc::new(task).execute().await.expect_err("fetch failure");
+
+    assert_eq!(error.totals().map(|t| t.downloaded), Some(1));
+    match error {
+        rxd::Error::FetchFailed { page, totals, .. } => {
+            assert_eq!(page, 2);
+            assert_eq!(totals.fetched, 1);
+            assert!(totals.bytes > 0);
+        }
+        e => panic!("{e:?}"),
+    }
+}
+
+async fn mount_user_without_media(server: &MockServer) {
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": { "user": { "result": {
+                "__typename": "User",
+                "rest_id": "42",
+                "legacy": { "name": "Test User", "media_count": 0 }
+            }}}
+        })))
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn accounts_without_media_are_not_paged() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user_without_media(&server).await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(vec![], None)))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let (totals, _db) = run_task(&server, dir.path()).await;
+
+    assert_eq!(totals.fetched, 0);
+    assert_eq!(totals.failed, 0);
+}
+
+#[tokio::test]
+async fn full_scans_page_accounts_without_media() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user_without_media(&server).await;
+    let body: Value = serde_json::from_str(
+        &std::fs::read_to_string(format!(
+            "{}/tests/fixtures/user_media_cursor_only.json",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .expect("fixture"),
+    )
+    .expect("json");
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(body))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let (builder, _db) = task_builder(&server, dir.path()).await;
+    let task = builder.full_scan(true).build().await.expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+
+    assert_eq!(totals.fetched, 0);
+    assert_eq!(totals.failed, 0);
+}
+
+#[tokio::test]
+async fn pinned_tweets_are_downloaded_once() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let pin = mount_media(&server, "PIN").await;
+    let other = mount_media(&server, "AAA").await;
+    // The pinned tweet is listed again on the page where it was posted
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .and(query_param_contains("variables", "page-2"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(media_page(vec![photo_item("9", &pin)], None)),
+        )
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    let mut first_page = media_page(vec![photo_item("1", &other)], Some("page-2"));
+    let instructions =
+        first_page["data"]["user"]["result"]["timeline_v2"]["timeline"]["instructions"]
+            .as_array_mut()
+            .expect("instructions");
+    instructions.push(json!({
+        "type": "TimelinePinEntry",
+        "entry": {
+            "entryId": "tweet-9",
+            "content": { "itemContent": photo_item("9", &pin)["item"]["itemContent"].clone() }
+        }
+    }));
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(first_page))
+        .with_priority(10)
+        .mount(&server)
+        .await;
+
+    let (totals, _db) = run_task(&server, dir.path()).await;
+
+    assert_eq!(totals.downloaded, 2);
+    assert_eq!(totals.skipped, 0);
+    assert_eq!(totals.failed, 0);
+    let requests = server.received_requests().await.expect("recorded");
+    let pin_downloads = requests
+        .iter()
+        .filter(|r| r.url.path() == "/media/PIN.jpg")
+        .count();
+    assert_eq!(pin_downloads, 1);
+}
+
+#[tokio::test]
+async fn video_details_are_recorded() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    mount_user(&server).await;
+    let photo = mount_media(&server, "AAA").await;
+    let thumb = format!("{}/thumb/video.jpg", server.uri());
+    let variant = format!("{}/video/clip.mp4", server.uri());
+    Mock::given(method("GET"))
+        .and(path("/video/clip.mp4"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"video".as_slice()))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(
+            vec![video_item("1", &thumb, &variant), photo_item("2", &photo)],
+            None,
+        )))
+        .mount(&server)
+        .await;
+
+    let (totals, db) = run_task(&server, dir.path()).await;
+    assert_eq!(totals.downloaded, 2);
+
+    let details = |url: String| {
+        let db = db.clone();
+        async move {
+            let row =
+                sqlx::query("SELECT duration_ms, width, height FROM media WHERE media_url = ?")
+                    .bind(url)
+                    .fetch_one(&db)
+                    .await
+                    .expect("media row");
+            (
+                row.get::<Option<i64>, _>("duration_ms"),
+                row.get::<Option<i64>, _>("width"),
+                row.get::<Option<i64>, _>("height"),
+            )
+        }
+    };
+    assert_eq!(
+        details(thumb.clone()).await,
+        (Some(15015), Some(1920), Some(1080))
+    );
+    assert_eq!(details(photo.clone()).await, (None, None, None));
+}
@@ -0,0 +1,77 @@
+//! Tweet metadata embedded in downloaded images.
+
+use chrono::DateTime;
+use rxd::embed::{self, ImageMetadata};
+
+const JPEG: &[u8] = include_bytes!("fixtures/pixel.jpg");
+const PNG: &[u8] = include_bytes!("fixtures/pixel.png");
+
+fn metadata() -> ImageMetadata<'static> {
+    ImageMetadata {
+        date: DateTime::parse_from_rfc3339("2025-03-12T21:00:00+09:00").expect("date"),
+        author: "alice",
+        text: Some("Tom &amp; Jerry <3"),
+        permalink: "https://x.com/alice/status/1",
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+#[test]
+fn jpeg_gets_exif_and_xmp_without_touching_the_image_data() {
+    let embedded = embed::embed(JPEG, &metadata()).expect("embedded");
+
+    // SOI and APP0 stay first, the scan is copied as it is
+    assert_eq!(embedded[..20], JPEG[..20]);
+    assert_eq!(&embedded[20..22], &[0xFF, 0xE1]);
+    let scan = JPEG
+        .windows(2)
+        .position(|w| w == [0xFF, 0xDA])
+        .expect("sos");
+    assert!(embedded.ends_with(&JPEG[scan..]));
+    assert!(contains(&embedded, b"Exif\0\0MM"));
+    assert!(contains(&embedded, b"Tom & Jerry <3\0"));
+    assert!(contains(&embedded, b"@alice\0"));
+    assert!(contains(&embedded, b"2025:03:12 21:00:00\0"));
+    assert!(contains(&embedded, b"+09:00\0"));
+    assert!(contains(
+        &embedded,
+        b"<dc:source>https://x.com/alice/status/1</dc:source>"
+    ));
+    assert!(contains(&embedded, b"Tom &amp; Jerry &lt;3"));
+
+    // Embedding again replaces the segments
+    let again = embed::embed(&embedded, &metadata()).expect("embedded again");
+    assert_eq!(again, embedded);
+}
+
+#[test]
+fn png_gets_an_xmp_chunk_after_its_header() {
+    let embedded = embed::embed(PNG, &metadata()).expect("embedded");
+
+    // Signature and IHDR, then the new chunk
+    assert_eq!(embedded[..33], PNG[..33]);
+    assert_eq!(&embedded[37..41], b"iTXt");
+    assert!(contains(&embedded, b"XML:com.adobe.xmp\0"));
+    assert!(embedded.ends_with(&PNG[33..]));
+
+    let again = embed::embed(&embedded, &metadata()).expect("embedded again");
+    assert_eq!(again, embedded);
+}
+
+#[test]
+fn damaged_and_unknown_files_are_left_alone() {
+    // Cut off inside a segment
+    assert_eq!(embed::embed(&JPEG[..30], &metadata()), None);
+    // Segment length running past the end
+    let mut jpeg = JPEG.to_vec();
+    jpeg[4] = 0xFF;
+    assert_eq!(embed::embed(&jpeg, &metadata()), None);
+    // Checksum mismatch
+    let mut png = PNG.to_vec();
+    png[20] ^= 1;
+    assert_eq!(embed::embed(&png, &metadata()), None);
+    assert_eq!(embed::embed(b"GIF89a", &metadata()), None);
+}
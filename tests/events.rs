@@ -0,0 +1,126 @@
+//! The NDJSON schema of `--events`, one line per event.
+
+use std::path::PathBuf;
+
+use rxd::events::{Event, SkipReason, Totals};
+
+fn line(event: &Event) -> String {
+    serde_json::to_string(event).expect("serialize")
+}
+
+#[test]
+fn task_events() {
+    assert_eq!(
+        line(&Event::TaskStarted {
+            screen_name: "alice".to_string(),
+        }),
+        r#"{"type":"task_started","screen_name":"alice"}"#
+    );
+    assert_eq!(
+        line(&Event::TaskFinished {
+            screen_name: "alice".to_string(),
+            totals: Totals {
+                fetched: 5,
+                downloaded: 3,
+                skipped: 1,
+                failed: 1,
+                bytes: 4096,
+                ..Default::default()
+            },
+        }),
+        concat!(
+            r#"{"type":"task_finished","screen_name":"alice","totals":{"#,
+            r#""fetched":5,"downloaded":3,"skipped":1,"failed":1,"filtered":0,"#,
+            r#""too_large":0,"bytes":4096,"hook_failures":0,"replaced":0,"gone":0,"#,
+            r#""converted":0,"unconverted":0}}"#
+        )
+    );
+}
+
+#[test]
+fn page_events() {
+    assert_eq!(
+        line(&Event::PageFetched {
+            screen_name: "alice".to_string(),
+            page: 2,
+            count: 20,
+            cursor: Some("DAAB".to_string()),
+        }),
+        r#"{"type":"page_fetched","screen_name":"alice","page":2,"count":20,"cursor":"DAAB"}"#
+    );
+    assert_eq!(
+        line(&Event::PageFetched {
+            screen_name: "alice".to_string(),
+            page: 3,
+            count: 0,
+            cursor: None,
+        }),
+        r#"{"type":"page_fetched","screen_name":"alice","page":3,"count":0,"cursor":null}"#
+    );
+}
+
+#[test]
+fn download_events() {
+    let url = "https://pbs.twimg.com/media/AAA.jpg";
+    assert_eq!(
+        line(&Event::DownloadStarted {
+            screen_name: "alice".to_string(),
+            tweet_id: "1".to_string(),
+            url: url.to_string(),
+        }),
+        r#"{"type":"download_started","screen_name":"alice","tweet_id":"1","url":"https://pbs.twimg.com/media/AAA.jpg"}"#
+    );
+    assert_eq!(
+        line(&Event::DownloadProgress {
+            screen_name: "alice".to_string(),
+            url: url.to_string(),
+            bytes: 512,
+            total: Some(1024),
+        }),
+        r#"{"type":"download_progress","screen_name":"alice","url":"https://pbs.twimg.com/media/AAA.jpg","bytes":512,"total":1024}"#
+    );
+    assert_eq!(
+        line(&Event::ItemDownloaded {
+            screen_name: "alice".to_string(),
+            tweet_id: "1".to_string(),
+            url: url.to_string(),
+            path: PathBuf::from("downloads/alice/AAA.jpg"),
+            bytes: 1024,
+        }),
+        r#"{"type":"item_downloaded","screen_name":"alice","tweet_id":"1","url":"https://pbs.twimg.com/media/AAA.jpg","path":"downloads/alice/AAA.jpg","bytes":1024}"#
+    );
+    assert_eq!(
+        line(&Event::ItemFailed {
+            screen_name: "alice".to_string(),
+            tweet_id: "1".to_string(),
+            url: url.to_string(),
+            error: "download error: 500".to_string(),
+        }),
+        r#"{"type":"item_failed","screen_name":"alice","tweet_id":"1","url":"https://pbs.twimg.com/media/AAA.jpg","error":"download error: 500"}"#
+    );
+}
+
+#[test]
+fn skip_reasons() {
+    for (reason, name) in [
+        (SkipReason::Verified, "verified"),
+        (SkipReason::Exists, "exists"),
+        (SkipReason::Filtered, "filtered"),
+        (SkipReason::TooLarge, "too_large"),
+        (SkipReason::Imported, "imported"),
+        (SkipReason::NotModified, "not_modified"),
+        (SkipReason::Gone, "gone"),
+    ] {
+        assert_eq!(
+            line(&Event::ItemSkipped {
+                screen_name: "alice".to_string(),
+                tweet_id: "1".to_string(),
+                url: "https://pbs.twimg.com/media/AAA.jpg".to_string(),
+                reason,
+            }),
+            format!(
+                r#"{{"type":"item_skipped","screen_name":"alice","tweet_id":"1","url":"https://pbs.twimg.com/media/AAA.jpg","reason":"{name}"}}"#
+            )
+        );
+    }
+}
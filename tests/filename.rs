@@ -0,0 +1,215 @@
+//! Fitting file names into filesystem limits.
+
+use std::path::Path;
+
+use chrono::{DateTime, FixedOffset};
+
+use rxd::filename::{self, Fields, MAX_FILENAME_BYTES, Segment, Template, Timezone};
+
+const SLUG_LEN: usize = 300;
+
+fn slug(word: &str) -> String {
+    let mut slug = String::new();
+    while slug.chars().count() < SLUG_LEN {
+        slug.push('-');
+        slug.push_str(word);
+    }
+    slug.chars().take(SLUG_LEN).collect()
+}
+
+#[test]
+fn short_names_are_unchanged() {
+    let segments = [
+        Segment::fixed("2024-05-01-1790000000000000000"),
+        Segment::variable("-first-few-words"),
+    ];
+
+    assert_eq!(
+        filename::fit(&segments, 200).as_deref(),
+        Some("2024-05-01-1790000000000000000-first-few-words")
+    );
+}
+
+#[test]
+fn long_slug_is_truncated_keeping_the_id() {
+    let segments = [
+        Segment::fixed("2024-05-01-1790000000000000000"),
+        Segment::variable(slug("word")),
+        Segment::fixed("-GmA1aaaaXAAbbb1"),
+    ];
+    let max = filename::max_stem_len(Path::new("downloads"));
+
+    let stem = filename::fit(&segments, max).expect("fits");
+    assert!(stem.len() <= max);
+    assert!(max < MAX_FILENAME_BYTES);
+    assert!(stem.starts_with("2024-05-01-1790000000000000000-word-word"));
+    // No separator is left dangling before the fixed suffix
+    assert!(stem.ends_with("word-GmA1aaaaXAAbbb1"));
+}
+
+#[test]
+fn multibyte_characters_are_not_split() {
+    for word in ["日本語のテキスト", "café", "🚀🌕"] {
+        let segments = [
+            Segment::fixed("1790000000000000000"),
+            Segment::variable(slug(word)),
+        ];
+
+        // Odd limits land in the middle of multibyte characters
+        for max in [101, 102, 103, 150] {
+            let stem = filename::fit(&segments, max).expect("fits");
+            assert!(stem.len() <= max, "{word}: {} > {max}", stem.len());
+            assert!(stem.len() > max - 4, "{word}: {} cut too much", stem.len());
+            assert!(stem.starts_with("1790000000000000000-"));
+        }
+    }
+}
+
+#[test]
+fn longest_variable_segment_is_shortened_first() {
+    let segments = [
+        Segment::variable("screen_name"),
+        Segment::fixed("-1"),
+        Segment::variable(slug("text")),
+    ];
+
+    let stem = filename::fit(&segments, 100).expect("fits");
+    assert_eq!(stem.len(), 100);
+    assert!(stem.starts_with("screen_name-1-text-"));
+}
+
+#[test]
+fn fixed_segments_that_do_not_fit_fail() {
+    let segments = [Segment::fixed("x".repeat(300)), Segment::variable("-text")];
+
+    assert_eq!(filename::fit(&segments, 255), None);
+}
+
+fn fields(text: Option<&str>, index: Option<usize>) -> Fields<'_> {
+    Fields {
+        date: "2024-05-01",
+        time: "203000",
+        tweet_id: "1790000000000000000",
+        media_id: "GmA1aaaaXAAbbb1",
+        index,
+        screen_name: "test_user",
+        text,
+    }
+}
+
+fn render(template: &str, fields: &Fields) -> String {
+    let segments = Template::parse(template)
+        .expect("valid template")
+        .render(fields);
+    filename::fit(&segments, 255).expect("fits")
+}
+
+#[test]
+fn text_placeholder_renders_a_slug() {
+    let text = "First few words of the tweet! @nasa https://t.co/abc";
+
+    assert_eq!(
+        render("{date}-{tweet_id}-{text}", &fields(Some(text), None)),
+        "2024-05-01-1790000000000000000-first-few-words-of-the-tweet"
+    );
+}
+
+#[test]
+fn empty_placeholders_leave_no_dangling_separators() {
+    let fields = fields(Some("https://t.co/abc"), None);
+
+    assert_eq!(
+        render("{date}-{tweet_id}-{text}", &fields),
+        "2024-05-01-1790000000000000000"
+    );
+    assert_eq!(
+        render("{text}_{index}_{media_id}", &fields),
+        "GmA1aaaaXAAbbb1"
+    );
+    assert_eq!(
+        render("{date} {index} {text} {media_id}", &fields),
+        "2024-05-01 GmA1aaaaXAAbbb1"
+    );
+}
+
+#[test]
+fn index_placeholder_is_zero_padded() {
+    assert_eq!(
+        render("{tweet_id}-{index}", &fields(None, Some(2))),
+        "1790000000000000000-02"
+    );
+}
+
+#[test]
+fn slug_is_truncated_to_the_text_length() {
+    let text = "one two three four five six seven";
+    let template = Template::parse("{text}").expect("valid").text_length(13);
+
+    let stem = filename::fit(&template.render(&fields(Some(text), None)), 255).expect("fits");
+    assert_eq!(stem, "one-two-three");
+}
+
+#[test]
+fn slug_of_emoji_and_cjk_text() {
+    // Windows rejects <>:"/\|?* and control characters in names
+    let slug = filename::slug("猫がかわいい 🐱🐱 <script>\"a|b\"? Ünïcödé\u{7}", 60);
+
+    assert_eq!(slug, "猫がかわいい-script-a-b-ünïcödé");
+    assert!(
+        !slug
+            .chars()
+            .any(|c| "<>:\"/\\|?*".contains(c) || c.is_control())
+    );
+    assert_eq!(filename::slug("🎉🎉🎉 @someone", 60), "");
+}
+
+#[test]
+fn long_text_slug_fits_the_limit() {
+    let text = "word ".repeat(SLUG_LEN / 5);
+    let template = Template::parse("{date}-{text}-{media_id}")
+        .expect("valid")
+        .text_length(SLUG_LEN);
+    let max = filename::max_stem_len(Path::new("downloads"));
+
+    let stem = filename::fit(&template.render(&fields(Some(&text), None)), max).expect("fits");
+    assert!(stem.len() <= max);
+    assert!(stem.starts_with("2024-05-01-word-word"));
+    assert!(stem.ends_with("word-GmA1aaaaXAAbbb1"));
+}
+
+#[test]
+fn invalid_templates_are_rejected() {
+    for template in ["{date}-{unknown}", "{date", "static-name"] {
+        assert!(
+            matches!(Template::parse(template), Err(rxd::Error::Config(_))),
+            "{template}"
+        );
+    }
+}
+
+#[test]
+fn timezones_from_config_values() {
+    assert_eq!("local".parse::<Timezone>().expect("local"), Timezone::Local);
+    assert_eq!("UTC".parse::<Timezone>().expect("utc"), Timezone::Utc);
+    assert_eq!(
+        "+09:00".parse::<Timezone>().expect("offset"),
+        Timezone::Fixed(FixedOffset::east_opt(9 * 3600).expect("offset"))
+    );
+    assert!(matches!(
+        "Asia/Tokyo".parse::<Timezone>(),
+        Err(rxd::Error::Config(_))
+    ));
+}
+
+#[test]
+fn timezone_moves_the_date() {
+    let posted = DateTime::parse_from_rfc3339("2024-05-01T20:30:00Z").expect("timestamp");
+
+    let date = |tz: &str| {
+        let tz: Timezone = tz.parse().expect("timezone");
+        tz.convert(posted).format("%Y-%m-%d %H%M%S").to_string()
+    };
+    assert_eq!(date("UTC"), "2024-05-01 203000");
+    assert_eq!(date("+09:00"), "2024-05-02 053000");
+    assert_eq!(date("-05:00"), "2024-05-01 153000");
+}
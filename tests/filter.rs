@@ -0,0 +1,70 @@
+//! Tweet filters and their config validation.
+
+use rxd::Config;
+use rxd::filter::{self, FileSize, TextPattern};
+
+fn patterns(patterns: &[&str]) -> Vec<TextPattern> {
+    patterns
+        .iter()
+        .map(|p| p.parse().expect("valid pattern"))
+        .collect()
+}
+
+#[test]
+fn patterns_match_case_insensitive_substrings() {
+    let include = patterns(&["gundam", r"\bzelda\b"]);
+
+    assert!(filter::matches_any(&include, "New GUNDAM model kit"));
+    assert!(filter::matches_any(&include, "Zelda fan art"));
+    assert!(!filter::matches_any(&include, "zeldas"));
+    assert!(!filter::matches_any(&include, ""));
+}
+
+#[test]
+fn unicode_text_and_emoji() {
+    let exclude = patterns(&["プレゼント", "giveaway.*🎁", "ÉVÉNEMENT"]);
+
+    assert!(filter::matches_any(&exclude, "フォロー&RTでプレゼント！"));
+    assert!(filter::matches_any(
+        &exclude,
+        "GIVEAWAY 🎉🎉 win a 🎁 today"
+    ));
+    assert!(filter::matches_any(&exclude, "Grand événement ce soir"));
+    assert!(!filter::matches_any(&exclude, "giveaway 🎉 results"));
+    assert!(!filter::matches_any(&exclude, "新しいイラスト 🎨"));
+}
+
+#[test]
+fn invalid_patterns_fail_config_loading() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("config.toml");
+    std::fs::write(
+        &path,
+        r#"
+auth_token = "token"
+ct0 = "ct0"
+
+[[tasks]]
+screen_name = "test_user"
+text_include = ["ok", "(unclosed"]
+"#,
+    )
+    .expect("write config");
+
+    let error = Config::load(&path).expect_err("invalid regex");
+    assert!(matches!(error, rxd::Error::Config(_)));
+    assert!(error.to_string().contains("(unclosed"), "{error}");
+}
+
+#[test]
+fn file_sizes_with_units() {
+    let size = |s: &str| s.parse::<FileSize>().map(|size| size.0);
+
+    assert_eq!(size("50MB").unwrap(), 50_000_000);
+    assert_eq!(size("50 mb").unwrap(), 50_000_000);
+    assert_eq!(size("1.5GiB").unwrap(), 3 << 29);
+    assert_eq!(size("512k").unwrap(), 512_000);
+    assert_eq!(size("1024").unwrap(), 1024);
+    assert!(size("50 furlongs").is_err());
+    assert!(size("MB").is_err());
+}
@@ -0,0 +1,12 @@
+window.YTD.account.part0 = [
+  {
+    "account" : {
+      "email" : "alice@example.com",
+      "createdVia" : "web",
+      "username" : "alice",
+      "accountId" : "100",
+      "createdAt" : "2012-03-01T10:00:00.000Z",
+      "accountDisplayName" : "Alice"
+    }
+  }
+]
@@ -0,0 +1,32 @@
+Grailbird.data.tweets_2012_03 = 
+ [ {
+  "source" : "web",
+  "entities" : {
+    "user_mentions" : [ ],
+    "media" : [ {
+      "expanded_url" : "http://twitter.com/alice/status/180000000000000001/photo/1",
+      "indices" : [ 11, 31 ],
+      "url" : "http://t.co/AbCdE",
+      "media_url" : "http://p.twimg.com/AoldPhoto1.jpg",
+      "id_str" : "180000000000000002",
+      "id" : 180000000000000002,
+      "media_url_https" : "https://p.twimg.com/AoldPhoto1.jpg",
+      "display_url" : "pic.twitter.com/AbCdE"
+    } ],
+    "hashtags" : [ ],
+    "urls" : [ ]
+  },
+  "geo" : { },
+  "id_str" : "180000000000000001",
+  "text" : "First post http://t.co/AbCdE",
+  "id" : 180000000000000001,
+  "created_at" : "2012-03-14 12:00:00 +0000",
+  "user" : {
+    "name" : "Alice",
+    "screen_name" : "alice",
+    "protected" : false,
+    "id_str" : "100",
+    "id" : 100,
+    "verified" : false
+  }
+} ]
@@ -0,0 +1,39 @@
+window.YTD.tweet.part0 = [ {
+  "retweeted" : false,
+  "source" : "<a href=\"http://twitter.com/download/android\" rel=\"nofollow\">Twitter for Android</a>",
+  "entities" : {
+    "media" : [ {
+      "media_url" : "http://pbs.twimg.com/media/D1abcdeXgAAf12G.jpg",
+      "id_str" : "1100000000000000010",
+      "id" : 1100000000000000010,
+      "type" : "photo"
+    } ]
+  },
+  "favorite_count" : 7,
+  "id_str" : "1100000000000000001",
+  "retweet_count" : 0,
+  "id" : 1100000000000000001,
+  "created_at" : "Mon Feb 25 18:47:51 +0000 2019",
+  "full_text" : "Old camera roll https://t.co/Abc",
+  "extended_entities" : {
+    "media" : [ {
+      "media_url" : "http://pbs.twimg.com/media/D1abcdeXgAAf12G.jpg",
+      "id_str" : "1100000000000000010",
+      "id" : 1100000000000000010,
+      "type" : "photo"
+    }, {
+      "media_url_https" : "https://pbs.twimg.com/tweet_video_thumb/D1gifffXgAAzz9.jpg",
+      "id_str" : "1100000000000000011",
+      "id" : 1100000000000000011,
+      "type" : "animated_gif",
+      "video_info" : {
+        "aspect_ratio" : [ 1, 1 ],
+        "variants" : [ {
+          "bitrate" : 0,
+          "content_type" : "video/mp4",
+          "url" : "https://video.twimg.com/tweet_video/D1gifffXgAAzz9.mp4"
+        } ]
+      }
+    } ]
+  }
+} ]
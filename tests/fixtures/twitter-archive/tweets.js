@@ -0,0 +1,185 @@
+window.YTD.tweets.part0 = [
+  {
+    "tweet" : {
+      "edit_info" : {
+        "initial" : {
+          "editTweetIds" : [
+            "1899000000000000001"
+          ],
+          "editableUntil" : "2025-03-12T11:00:00.000Z",
+          "editsRemaining" : "5",
+          "isEditEligible" : true
+        }
+      },
+      "retweeted" : false,
+      "source" : "<a href=\"https://mobile.twitter.com\" rel=\"nofollow\">Twitter Web App</a>",
+      "entities" : {
+        "hashtags" : [ ],
+        "symbols" : [ ],
+        "user_mentions" : [ ],
+        "urls" : [ ],
+        "media" : [
+          {
+            "expanded_url" : "https://x.com/alice/status/1899000000000000001/photo/1",
+            "indices" : [
+              "24",
+              "47"
+            ],
+            "url" : "https://t.co/AbCdEfGhIj",
+            "media_url" : "http://pbs.twimg.com/media/GmA1aaaaXAAbbb1.jpg",
+            "id_str" : "1898999999000000001",
+            "id" : "1898999999000000001",
+            "media_url_https" : "https://pbs.twimg.com/media/GmA1aaaaXAAbbb1.jpg",
+            "sizes" : {
+              "large" : {
+                "w" : "1536",
+                "h" : "2048",
+                "resize" : "fit"
+              }
+            },
+            "type" : "photo",
+            "display_url" : "pic.x.com/AbCdEfGhIj"
+          }
+        ]
+      },
+      "display_text_range" : [
+        "0",
+        "23"
+      ],
+      "favorite_count" : "12",
+      "id_str" : "1899000000000000001",
+      "truncated" : false,
+      "retweet_count" : "3",
+      "id" : "1899000000000000001",
+      "possibly_sensitive" : false,
+      "created_at" : "Wed Mar 12 10:00:00 +0000 2025",
+      "favorited" : false,
+      "full_text" : "Two sketches from today https://t.co/AbCdEfGhIj",
+      "lang" : "en",
+      "extended_entities" : {
+        "media" : [
+          {
+            "expanded_url" : "https://x.com/alice/status/1899000000000000001/photo/1",
+            "indices" : [
+              "24",
+              "47"
+            ],
+            "url" : "https://t.co/AbCdEfGhIj",
+            "media_url" : "http://pbs.twimg.com/media/GmA1aaaaXAAbbb1.jpg",
+            "id_str" : "1898999999000000001",
+            "id" : "1898999999000000001",
+            "media_url_https" : "https://pbs.twimg.com/media/GmA1aaaaXAAbbb1.jpg",
+            "type" : "photo",
+            "display_url" : "pic.x.com/AbCdEfGhIj"
+          },
+          {
+            "expanded_url" : "https://x.com/alice/status/1899000000000000001/photo/1",
+            "indices" : [
+              "24",
+              "47"
+            ],
+            "url" : "https://t.co/AbCdEfGhIj",
+            "media_url" : "http://pbs.twimg.com/media/GmA2bbbbXAAccc2.jpg",
+            "id_str" : "1898999999000000002",
+            "id" : "1898999999000000002",
+            "media_url_https" : "https://pbs.twimg.com/media/GmA2bbbbXAAccc2.jpg",
+            "type" : "photo",
+            "display_url" : "pic.x.com/AbCdEfGhIj"
+          }
+        ]
+      }
+    }
+  },
+  {
+    "tweet" : {
+      "retweeted" : false,
+      "entities" : {
+        "media" : [
+          {
+            "media_url_https" : "https://pbs.twimg.com/ext_tw_video_thumb/1899000000000000020/pu/img/VidThumb1.jpg",
+            "id_str" : "1899000000000000020",
+            "type" : "video"
+          }
+        ]
+      },
+      "favorite_count" : "40",
+      "id_str" : "1899000000000000002",
+      "retweet_count" : "0",
+      "id" : "1899000000000000002",
+      "created_at" : "Thu Mar 13 09:30:00 +0000 2025",
+      "full_text" : "Timelapse https://t.co/XyZ",
+      "extended_entities" : {
+        "media" : [
+          {
+            "media_url_https" : "https://pbs.twimg.com/ext_tw_video_thumb/1899000000000000020/pu/img/VidThumb1.jpg",
+            "id_str" : "1899000000000000020",
+            "type" : "video",
+            "video_info" : {
+              "aspect_ratio" : [
+                "16",
+                "9"
+              ],
+              "duration_millis" : "15015",
+              "variants" : [
+                {
+                  "bitrate" : "832000",
+                  "content_type" : "video/mp4",
+                  "url" : "https://video.twimg.com/ext_tw_video/1899000000000000020/pu/vid/640x360/LowRes.mp4?tag=12"
+                },
+                {
+                  "content_type" : "application/x-mpegURL",
+                  "url" : "https://video.twimg.com/ext_tw_video/1899000000000000020/pu/pl/Playlist.m3u8?tag=12"
+                },
+                {
+                  "bitrate" : "2176000",
+                  "content_type" : "video/mp4",
+                  "url" : "https://video.twimg.com/ext_tw_video/1899000000000000020/pu/vid/1280x720/HighRes.mp4?tag=12"
+                }
+              ]
+            }
+          }
+        ]
+      }
+    }
+  },
+  {
+    "tweet" : {
+      "retweeted" : false,
+      "entities" : {
+        "media" : [
+          {
+            "media_url_https" : "https://pbs.twimg.com/media/GmZ9zzzzXAAyyy9.jpg",
+            "id_str" : "1898000000000000009",
+            "source_status_id_str" : "1898000000000000008",
+            "type" : "photo"
+          }
+        ]
+      },
+      "favorite_count" : "0",
+      "id_str" : "1899000000000000003",
+      "retweet_count" : "5",
+      "id" : "1899000000000000003",
+      "created_at" : "Fri Mar 14 08:00:00 +0000 2025",
+      "full_text" : "RT @bob: Look at this https://t.co/Qwe"
+    }
+  },
+  {
+    "tweet" : {
+      "retweeted" : false,
+      "entities" : {
+        "media" : [ ]
+      },
+      "favorite_count" : "1",
+      "id_str" : "1899000000000000004",
+      "retweet_count" : "0",
+      "id" : "1899000000000000004",
+      "created_at" : "Sat Mar 15 07:00:00 +0000 2025",
+      "full_text" : "Just text"
+    }
+  },
+  {
+    "like" : {
+      "tweetId" : "1899000000000000005"
+    }
+  }
+]
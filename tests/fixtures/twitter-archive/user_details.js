@@ -0,0 +1,8 @@
+var user_details =  {
+  "screen_name" : "alice",
+  "location" : "",
+  "full_name" : "Alice",
+  "bio" : "",
+  "id" : "100",
+  "created_at" : "2012-03-01 10:00:00 +0000"
+}
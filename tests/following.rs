@@ -0,0 +1,145 @@
+//! Listing the accounts to download from a Following timeline.
+
+use rxd::Api;
+use rxd::following::{self, FollowingConfig};
+use serde_json::{Value, json};
+use wiremock::matchers::{method, path, query_param_contains};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const USER_BY_SCREEN_NAME: &str = "/i/api/graphql/xc8f1g7BYqr6VTzTbvNlGw/UserByScreenName";
+const FOLLOWING: &str = "/i/api/graphql/iSicc7LrzWGBgDPL0tM_TQ/Following";
+
+fn account(rest_id: &str, screen_name: &str) -> Value {
+    json!({
+        "entryId": format!("user-{rest_id}"),
+        "content": { "itemContent": { "user_results": { "result": {
+            "__typename": "User",
+            "rest_id": rest_id,
+            "legacy": { "name": screen_name, "screen_name": screen_name, "media_count": 1 }
+        }}}}
+    })
+}
+
+fn suspended(rest_id: &str) -> Value {
+    json!({
+        "entryId": format!("user-{rest_id}"),
+        "content": { "itemContent": { "user_results": { "result": {
+            "__typename": "UserUnavailable",
+            "reason": "Suspended"
+        }}}}
+    })
+}
+
+fn following_page(mut entries: Vec<Value>, cursor: &str) -> Value {
+    entries.push(json!({
+        "entryId": "cursor-bottom-0",
+        "content": { "value": cursor }
+    }));
+    json!({
+        "data": { "user": { "result": { "timeline": { "timeline": {
+            "instructions": [{ "type": "TimelineAddEntries", "entries": entries }]
+        }}}}}
+    })
+}
+
+async fn mount_following(server: &MockServer) {
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": { "user": { "result": {
+                "__typename": "User",
+                "rest_id": "42",
+                "legacy": { "name": "Me" }
+            }}}
+        })))
+        .mount(server)
+        .await;
+    // Later pages first, the first page matches every request
+    Mock::given(method("GET"))
+        .and(path(FOLLOWING))
+        .and(query_param_contains("variables", "\"cursor\":\"page-2\""))
+        .respond_with(ResponseTemplate::new(200).set_body_json(following_page(
+            vec![account("3", "news"), account("4", "dave")],
+            "page-3",
+        )))
+        .with_priority(1)
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(FOLLOWING))
+        .and(query_param_contains("variables", "\"cursor\":\"page-3\""))
+        .respond_with(ResponseTemplate::new(200).set_body_json(following_page(vec![], "page-3")))
+        .with_priority(1)
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(FOLLOWING))
+        .respond_with(ResponseTemplate::new(200).set_body_json(following_page(
+            vec![account("1", "alice"), suspended("2"), account("5", "bob")],
+            "page-2",
+        )))
+        .mount(server)
+        .await;
+}
+
+fn screen_names(accounts: &[rxd::User]) -> Vec<&str> {
+    accounts.iter().map(|a| a.screen_name.as_str()).collect()
+}
+
+#[tokio::test]
+async fn lists_followed_accounts_across_pages_without_excluded_ones() {
+    let server = MockServer::start().await;
+    mount_following(&server).await;
+    let api = Api::with_base_url("token", "ct0", &server.uri()).expect("api");
+    let config = FollowingConfig {
+        screen_name: Some("@me".into()),
+        exclude: vec!["@News".into()],
+        limit_accounts: None,
+    };
+
+    let accounts = following::followed_accounts(&api, &config)
+        .await
+        .expect("list");
+
+    assert_eq!(screen_names(&accounts), ["alice", "bob", "dave"]);
+    assert_eq!(accounts[0].rest_id, "1");
+}
+
+#[tokio::test]
+async fn stops_listing_at_the_account_limit() {
+    let server = MockServer::start().await;
+    mount_following(&server).await;
+    let api = Api::with_base_url("token", "ct0", &server.uri()).expect("api");
+    let config = FollowingConfig {
+        screen_name: Some("me".into()),
+        exclude: Vec::new(),
+        limit_accounts: Some(1),
+    };
+
+    let accounts = following::followed_accounts(&api, &config)
+        .await
+        .expect("list");
+
+    assert_eq!(screen_names(&accounts), ["alice"]);
+    let pages = server
+        .received_requests()
+        .await
+        .expect("recorded")
+        .iter()
+        .filter(|r| r.url.path() == FOLLOWING)
+        .count();
+    assert_eq!(pages, 1);
+}
+
+#[test]
+fn unavailable_accounts_are_counted() {
+    let page = following::parse_following_response(&following_page(
+        vec![account("1", "alice"), suspended("2")],
+        "next",
+    ))
+    .expect("parse");
+
+    assert_eq!(screen_names(&page.accounts), ["alice"]);
+    assert_eq!(page.unavailable, 1);
+    assert_eq!(page.next_cursor.as_deref(), Some("next"));
+}
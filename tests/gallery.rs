@@ -0,0 +1,131 @@
+//! Static HTML galleries of downloaded files.
+
+use std::fs;
+use std::path::Path;
+
+use common::media;
+use rxd::db;
+use rxd::gallery::{self, GalleryOptions};
+
+mod common;
+
+const CAPTION: &str = "cats & <dogs> at https://t.co/x";
+
+fn files(dir: &Path) -> Vec<(String, Vec<u8>)> {
+    let mut files: Vec<_> = fs::read_dir(dir)
+        .expect("out")
+        .map(|entry| {
+            let entry = entry.expect("entry");
+            let name = entry.file_name().into_string().expect("name");
+            (name, fs::read(entry.path()).expect("read"))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+#[tokio::test]
+async fn pages_link_files_and_tweets() {
+    let tmp = tempfile::tempdir().expect("tmp");
+    let folder = tmp.path().join("downloads").join("alice");
+    fs::create_dir_all(&folder).expect("folder");
+    let out = tmp.path().join("site");
+    let pool = db::init_memory_db().await.expect("db");
+    media("old cat.jpg")
+        .time("2025-02-27 09:00:00")
+        .text(CAPTION)
+        .file(&folder, b"data")
+        .record(&pool)
+        .await;
+    media("clip.mp4")
+        .tweet("2")
+        .time("2025-03-01 10:00:00")
+        .text(CAPTION)
+        .file(&folder, b"data")
+        .record(&pool)
+        .await;
+    media("new.jpg")
+        .tweet("3")
+        .time("2025-03-12 11:30:00")
+        .text(CAPTION)
+        .file(&folder, b"data")
+        .record(&pool)
+        .await;
+    fs::write(folder.join("clip.thumb.jpg"), b"poster").expect("poster");
+    media("lost.jpg")
+        .tweet("4")
+        .time("2025-03-13 08:00:00")
+        .record(&pool)
+        .await;
+
+    let options = GalleryOptions::default().page_size(2);
+    let summary = gallery::generate(&pool, "alice", &folder, &out, &options)
+        .await
+        .expect("gallery");
+
+    assert_eq!(summary.items, 3);
+    assert_eq!(summary.pages, 2);
+    assert_eq!(summary.posters, 1);
+    assert_eq!(summary.missing, 1);
+    assert_eq!(summary.written, 3);
+    let index = fs::read_to_string(out.join(gallery::INDEX)).expect("index");
+    let second = fs::read_to_string(out.join("page-2.html")).expect("page 2");
+    // Newest first, grouped by month
+    assert!(index.find("new.jpg") < index.find("clip.mp4"));
+    assert!(index.contains("<h2 id=\"2025-03\">March 2025</h2>"));
+    assert!(second.contains("<h2 id=\"2025-02\">February 2025</h2>"));
+    assert!(index.contains(
+        "<a class=\"media video\" href=\"../downloads/alice/clip.mp4\"><img src=\"../downloads/alice/clip.thumb.jpg\" loading=\"lazy\""
+    ));
+    assert!(second.contains("href=\"../downloads/alice/old%20cat.jpg\""));
+    assert!(index.contains("https://x.com/alice/status/3"));
+    assert!(index.contains("cats &amp; &lt;dogs&gt;"));
+    assert!(index.contains("<a href=\"page-2.html\">older</a>"));
+    assert!(second.contains("<a href=\"index.html\">newer</a>"));
+    assert!(!index.contains("lost.jpg"));
+    // Nothing but the tweets themselves from the network
+    for page in [&index, &second] {
+        assert!(!page.contains("src=\"http"));
+        assert!(!page.contains("<script"));
+    }
+    assert!(out.join(gallery::STYLESHEET).is_file());
+}
+
+#[tokio::test]
+async fn regenerating_is_idempotent() {
+    let tmp = tempfile::tempdir().expect("tmp");
+    let folder = tmp.path().join("alice");
+    fs::create_dir_all(&folder).expect("folder");
+    let out = tmp.path().join("site");
+    let pool = db::init_memory_db().await.expect("db");
+    for (id, day) in [("1", "01"), ("2", "02"), ("3", "03")] {
+        let time = format!("2025-03-{day} 10:00:00");
+        media(&format!("{id}.jpg"))
+            .tweet(id)
+            .time(&time)
+            .text(CAPTION)
+            .file(&folder, b"data")
+            .record(&pool)
+            .await;
+    }
+    let options = GalleryOptions::default().page_size(1);
+
+    gallery::generate(&pool, "alice", &folder, &out, &options)
+        .await
+        .expect("gallery");
+    let before = files(&out);
+    let again = gallery::generate(&pool, "alice", &folder, &out, &options)
+        .await
+        .expect("gallery");
+    assert_eq!(again.written, 0);
+    assert_eq!(files(&out), before);
+
+    // Fewer pages leave none of the old ones behind
+    let options = GalleryOptions::default().page_size(2);
+    let smaller = gallery::generate(&pool, "alice", &folder, &out, &options)
+        .await
+        .expect("gallery");
+    assert_eq!(smaller.pages, 2);
+    let names: Vec<String> = files(&out).into_iter().map(|(name, _)| name).collect();
+    assert_eq!(names, ["gallery.css", "index.html", "page-2.html"]);
+}
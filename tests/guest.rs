@@ -0,0 +1,115 @@
+//! Guest mode against a mock API server.
+
+use rxd::api::{GUEST_PAGE_SIZE, TlsOptions};
+use rxd::task::ExtractOptions;
+use rxd::{Api, Error};
+use serde_json::{Value, json};
+use wiremock::matchers::{header, method, path, query_param_contains};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const ACTIVATE: &str = "/i/api/1.1/guest/activate.json";
+const USER_BY_SCREEN_NAME: &str = "/i/api/graphql/xc8f1g7BYqr6VTzTbvNlGw/UserByScreenName";
+const USER_MEDIA: &str = "/i/api/graphql/Le6KlbilFmSu-5VltFND-Q/UserMedia";
+
+fn user_response(protected: bool) -> Value {
+    json!({
+        "data": { "user": { "result": {
+            "__typename": "User",
+            "rest_id": "42",
+            "legacy": { "name": "Test User", "media_count": 3, "protected": protected }
+        }}}
+    })
+}
+
+async fn guest_api(server: &MockServer) -> Api {
+    Mock::given(method("POST"))
+        .and(path(ACTIVATE))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "guest_token": "1234567890" })),
+        )
+        .expect(1)
+        .mount(server)
+        .await;
+    Api::guest(&server.uri(), &TlsOptions::default())
+        .await
+        .expect("guest api")
+}
+
+#[tokio::test]
+async fn guests_send_the_token_instead_of_cookies() {
+    let server = MockServer::start().await;
+    let api = guest_api(&server).await;
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .and(header("x-guest-token", "1234567890"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(user_response(false)))
+        .mount(&server)
+        .await;
+
+    let user = api.user_by_screen_name("test_user").await.expect("lookup");
+
+    assert!(api.is_guest());
+    assert_eq!(user.rest_id, "42");
+    let requests = server.received_requests().await.expect("recorded");
+    assert!(requests.iter().all(|r| !r.headers.contains_key("cookie")));
+}
+
+#[tokio::test]
+async fn protected_accounts_need_credentials() {
+    let server = MockServer::start().await;
+    let api = guest_api(&server).await;
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .respond_with(ResponseTemplate::new(200).set_body_json(user_response(true)))
+        .mount(&server)
+        .await;
+
+    let err = api
+        .user_by_screen_name("locked")
+        .await
+        .expect_err("protected");
+
+    assert!(matches!(err, Error::GuestUnsupported(_)), "{err:?}");
+    assert!(err.to_string().contains("set auth_token and ct0"), "{err}");
+}
+
+#[tokio::test]
+async fn refused_timelines_need_credentials() {
+    let server = MockServer::start().await;
+    let api = guest_api(&server).await;
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .respond_with(ResponseTemplate::new(200).set_body_json(user_response(false)))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .and(query_param_contains(
+            "variables",
+            format!("\"count\":{GUEST_PAGE_SIZE}"),
+        ))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "errors": [{ "code": 200, "message": "Forbidden." }]
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let user = api.user_by_screen_name("test_user").await.expect("lookup");
+
+    let err = api
+        .user_media(&user, None, &ExtractOptions::default())
+        .await
+        .expect_err("refused");
+
+    assert!(matches!(err, Error::GuestUnsupported(_)), "{err:?}");
+}
+
+#[tokio::test]
+async fn guests_have_no_account_of_their_own() {
+    let server = MockServer::start().await;
+    let api = guest_api(&server).await;
+
+    let err = api.verify_credentials().await.expect_err("no account");
+
+    assert!(matches!(err, Error::GuestUnsupported(_)), "{err:?}");
+}
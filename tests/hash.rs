@@ -0,0 +1,134 @@
+//! Hash algorithms against published test vectors, and backfilling hashes.
+
+use rxd::db;
+use rxd::hash::backfill::{self, BackfillSummary};
+use rxd::hash::{HashAlgorithm, Hasher};
+
+/// Input of the official BLAKE3 test vectors
+fn input(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn blake3_test_vectors() {
+    let blake3 = |data: &[u8]| HashAlgorithm::Blake3.hash(data);
+
+    assert_eq!(
+        blake3(b""),
+        "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+    );
+    assert_eq!(
+        blake3(&input(1)),
+        "2d3adedff11b61f14c886e35afa036736dcd87a74d27b5c1510225d0f592e213"
+    );
+    assert_eq!(
+        blake3(b"abc"),
+        "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+    );
+    // Around chunk boundaries and deeper trees
+    for (len, expected) in [
+        (
+            1024,
+            "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7",
+        ),
+        (
+            1025,
+            "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444",
+        ),
+        (
+            3073,
+            "7124b49501012f81cc7f11ca069ec9226cecb8a2c850cfe644e327d22d3e1cd3",
+        ),
+        (
+            31744,
+            "62b6960e1a44bcc1eb1a611a8d6235b6b4b78f32e7abc4fb4c6cdcce94895c47",
+        ),
+        (
+            102400,
+            "bc3e3d41a1146b069abffad3c0d44860cf664390afce4d9661f7902e7943e085",
+        ),
+    ] {
+        assert_eq!(blake3(&input(len)), expected, "{len} bytes");
+    }
+}
+
+#[test]
+fn incremental_hashing_matches() {
+    let data = input(10_000);
+    for algorithm in [HashAlgorithm::Sha256, HashAlgorithm::Blake3] {
+        let mut hasher = Hasher::new(algorithm);
+        for piece in data.chunks(333) {
+            hasher.update(piece);
+        }
+        assert_eq!(hasher.finalize(), algorithm.hash(&data), "{algorithm}");
+    }
+}
+
+#[test]
+fn sha256_is_unchanged() {
+    assert_eq!(
+        HashAlgorithm::Sha256.hash(b"abc"),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+}
+
+#[tokio::test]
+async fn backfill_hashes_recorded_files_once() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let pool = db::init_memory_db().await.expect("db");
+    db::upsert_tweet(&pool, "1", "Alice", "2025-03-12 10:00:00", None)
+        .await
+        .expect("tweet");
+    for (url, filename) in [
+        ("https://pbs.twimg.com/media/A.jpg", "a.jpg"),
+        ("https://pbs.twimg.com/media/B.jpg", "b.jpg"),
+        ("https://pbs.twimg.com/media/C.jpg", "gone.jpg"),
+    ] {
+        db::upsert_media(&pool, "1", url, None, Some(filename))
+            .await
+            .expect("media");
+    }
+    db::update_hash(
+        &pool,
+        "https://pbs.twimg.com/media/B.jpg",
+        "bb",
+        HashAlgorithm::Sha256,
+    )
+    .await
+    .expect("hash");
+    let folder = dir.path().join("alice");
+    std::fs::create_dir(&folder).expect("mkdir");
+    std::fs::write(folder.join("a.jpg"), b"a").expect("write");
+
+    let run = || {
+        backfill::backfill(
+            &pool,
+            Some("alice"),
+            |screen_name| dir.path().join(screen_name.to_lowercase()),
+            HashAlgorithm::Blake3,
+            2,
+            |_, _| {},
+        )
+    };
+    let summary = run().await.expect("backfill");
+    assert_eq!(
+        summary,
+        BackfillSummary {
+            hashed: 1,
+            missing: 1,
+            failed: 0,
+            already_hashed: 1,
+        }
+    );
+    let record = db::get_media_by_url(&pool, "https://pbs.twimg.com/media/A.jpg")
+        .await
+        .expect("lookup")
+        .expect("recorded");
+    assert_eq!(record.file_hash, Some(HashAlgorithm::Blake3.hash(b"a")));
+    assert_eq!(record.hash_algorithm, Some(HashAlgorithm::Blake3));
+
+    // Only the missing file is looked at again
+    let summary = run().await.expect("backfill");
+    assert_eq!((summary.hashed, summary.missing), (0, 1));
+    assert_eq!(summary.already_hashed, 2);
+}
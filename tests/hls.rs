@@ -0,0 +1,187 @@
+//! HLS playlist parsing and segment download.
+
+use std::sync::Arc;
+
+use rxd::Task;
+use rxd::hls::{self, MediaPlaylist, Variant};
+use serde_json::json;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const VIDEO_ID: &str = "/ext_tw_video/1700000000000000301/pu";
+
+fn fixture(name: &str) -> String {
+    let path = format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"));
+    std::fs::read_to_string(&path).expect("fixture exists")
+}
+
+#[test]
+fn parses_master_playlist() {
+    let variants = hls::parse_master(&fixture("hls_master.m3u8"));
+
+    assert_eq!(variants.len(), 3);
+    assert_eq!(
+        variants.iter().max_by_key(|v| v.bandwidth),
+        Some(&Variant {
+            uri: format!("{VIDEO_ID}/pl/1280x720/high.m3u8"),
+            bandwidth: 2_400_000,
+            audio_uri: None,
+        })
+    );
+}
+
+#[test]
+fn media_playlist_has_no_variants() {
+    let playlist = fixture("hls_media.m3u8");
+
+    assert!(hls::parse_master(&playlist).is_empty());
+    assert_eq!(
+        hls::parse_media(&playlist),
+        MediaPlaylist {
+            init: Some(format!("{VIDEO_ID}/vid/1280x720/init.mp4")),
+            segments: vec![
+                format!("{VIDEO_ID}/vid/1280x720/0/3000/seg0.m4s"),
+                format!("{VIDEO_ID}/vid/1280x720/3000/5500/seg1.m4s"),
+            ],
+        }
+    );
+}
+
+#[test]
+fn separate_audio_rendition() {
+    let playlist = r#"#EXTM3U
+#EXT-X-MEDIA:NAME="Audio",TYPE=AUDIO,GROUP-ID="audio-128000",AUTOSELECT=YES,URI="/audio/128000.m3u8"
+#EXT-X-STREAM-INF:BANDWIDTH=2400000,RESOLUTION=1280x720,AUDIO="audio-128000"
+/video/720.m3u8
+"#;
+
+    assert_eq!(
+        hls::parse_master(playlist),
+        vec![Variant {
+            uri: "/video/720.m3u8".to_string(),
+            bandwidth: 2_400_000,
+            audio_uri: Some("/audio/128000.m3u8".to_string()),
+        }]
+    );
+}
+
+#[test]
+fn detects_playlist_urls() {
+    assert!(hls::is_playlist(
+        "https://video.twimg.com/amplify_video/1/pl/abc.m3u8?tag=14"
+    ));
+    assert!(!hls::is_playlist(
+        "https://video.twimg.com/ext_tw_video/1/pu/vid/1280x720/high.mp4?tag=12"
+    ));
+}
+
+async fn mount(server: &MockServer, route: String, body: &[u8]) {
+    Mock::given(method("GET"))
+        .and(path(route))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(body.to_vec()))
+        .expect(1)
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn downloads_hls_only_video() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+
+    Mock::given(method("GET"))
+        .and(path(
+            "/i/api/graphql/xc8f1g7BYqr6VTzTbvNlGw/UserByScreenName",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": { "user": { "result": {
+                "__typename": "User",
+                "rest_id": "42",
+                "legacy": { "name": "Test User", "media_count": 1 }
+            }}}
+        })))
+        .mount(&server)
+        .await;
+
+    let master = format!("{}{VIDEO_ID}/pl/master.m3u8", server.uri());
+    let tweet = json!({
+        "item": { "itemContent": { "tweet_results": { "result": {
+            "__typename": "Tweet",
+            "rest_id": "1",
+            "legacy": {
+                "created_at": "Wed Mar 12 12:00:00 +0000 2025",
+                "extended_entities": { "media": [{
+                    "type": "video",
+                    "video_info": { "variants": [
+                        { "content_type": "application/x-mpegURL", "url": master }
+                    ]}
+                }]}
+            }
+        }}}}
+    });
+    Mock::given(method("GET"))
+        .and(path("/i/api/graphql/Le6KlbilFmSu-5VltFND-Q/UserMedia"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": { "user": { "result": { "timeline_v2": { "timeline": {
+                "instructions": [{ "entries": [
+                    { "entryId": "profile-grid-0", "content": { "items": [tweet] } }
+                ]}]
+            }}}}}
+        })))
+        .mount(&server)
+        .await;
+
+    mount(
+        &server,
+        format!("{VIDEO_ID}/pl/master.m3u8"),
+        fixture("hls_master.m3u8").as_bytes(),
+    )
+    .await;
+    mount(
+        &server,
+        format!("{VIDEO_ID}/pl/1280x720/high.m3u8"),
+        fixture("hls_media.m3u8").as_bytes(),
+    )
+    .await;
+    mount(
+        &server,
+        format!("{VIDEO_ID}/vid/1280x720/init.mp4"),
+        b"init|",
+    )
+    .await;
+    mount(
+        &server,
+        format!("{VIDEO_ID}/vid/1280x720/0/3000/seg0.m4s"),
+        b"seg0|",
+    )
+    .await;
+    mount(
+        &server,
+        format!("{VIDEO_ID}/vid/1280x720/3000/5500/seg1.m4s"),
+        b"seg1",
+    )
+    .await;
+
+    let db = rxd::db::init_db(&dir.path().join("rxd.db"))
+        .await
+        .expect("db");
+    let api = rxd::Api::with_base_url("token", "ct0", &server.uri()).expect("api");
+    let task = Task::builder()
+        .api(api)
+        .screen_name("test_user")
+        .save_path(dir.path().join("media"))
+        .db(db)
+        .build()
+        .await
+        .expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+    assert_eq!(totals.downloaded, 1);
+
+    let files: Vec<_> = std::fs::read_dir(dir.path().join("media"))
+        .expect("media dir")
+        .map(|e| e.expect("entry").path())
+        .collect();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].extension().and_then(|e| e.to_str()), Some("mp4"));
+    assert_eq!(std::fs::read(&files[0]).expect("file"), b"init|seg0|seg1");
+}
@@ -0,0 +1,46 @@
+//! Image URLs in the classic `.../ID.jpg` and the `?format=jpg` style.
+
+use rxd::task::{image_extension, sized_image_url};
+
+#[test]
+fn classic_urls_get_a_name_query() {
+    let url = "https://pbs.twimg.com/media/GabcXYZ.jpg";
+    assert_eq!(
+        sized_image_url(url, "orig"),
+        "https://pbs.twimg.com/media/GabcXYZ.jpg?name=orig"
+    );
+    assert_eq!(image_extension(url).as_deref(), Some("jpg"));
+}
+
+#[test]
+fn format_urls_have_their_name_replaced() {
+    let url = "https://pbs.twimg.com/media/GabcXYZ?format=png&name=small";
+    assert_eq!(
+        sized_image_url(url, "4096x4096"),
+        "https://pbs.twimg.com/media/GabcXYZ?format=png&name=4096x4096"
+    );
+    assert_eq!(image_extension(url).as_deref(), Some("png"));
+
+    let without_name = "https://pbs.twimg.com/media/GabcXYZ?format=webp";
+    assert_eq!(
+        sized_image_url(without_name, "large"),
+        "https://pbs.twimg.com/media/GabcXYZ?format=webp&name=large"
+    );
+    assert_eq!(image_extension(without_name).as_deref(), Some("webp"));
+}
+
+#[test]
+fn format_wins_over_the_path_and_is_lowercased() {
+    let url = "https://pbs.twimg.com/media/GabcXYZ.jpg?name=orig&format=PNG";
+    assert_eq!(image_extension(url).as_deref(), Some("png"));
+    assert_eq!(
+        sized_image_url(url, "small"),
+        "https://pbs.twimg.com/media/GabcXYZ.jpg?format=PNG&name=small"
+    );
+}
+
+#[test]
+fn urls_without_an_extension_have_none() {
+    assert_eq!(image_extension("https://pbs.twimg.com/media/GabcXYZ"), None);
+    assert_eq!(image_extension("not a url"), None);
+}
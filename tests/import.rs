@@ -0,0 +1,489 @@
+//! Importing folders downloaded before the database existed.
+
+use std::path::Path;
+
+use chrono::NaiveDate;
+use rxd::db;
+use rxd::hash::HashAlgorithm;
+use rxd::import::{self, ImportSummary, Inferred};
+
+#[test]
+fn infers_fields_from_default_names() {
+    let date = NaiveDate::from_ymd_opt(2025, 3, 12);
+
+    assert_eq!(
+        import::infer("2025-03-12-GmA1aaaaXAAbbb1"),
+        Inferred {
+            date,
+            tweet_id: None,
+            media_id: Some("GmA1aaaaXAAbbb1".to_string()),
+        }
+    );
+    assert_eq!(
+        import::infer("2025-03-12-1899000000000000001-02-GmA1aaaaXAAbbb1"),
+        Inferred {
+            date,
+            tweet_id: Some("1899000000000000001".to_string()),
+            media_id: Some("GmA1aaaaXAAbbb1".to_string()),
+        }
+    );
+    assert_eq!(import::infer("holiday photo"), Inferred::default());
+}
+
+fn write(dir: &Path, name: &str, content: &[u8]) {
+    let path = dir.join(name);
+    std::fs::create_dir_all(path.parent().expect("parent")).expect("mkdir");
+    std::fs::write(path, content).expect("write");
+}
+
+#[tokio::test]
+async fn imports_once_and_resumes() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    write(dir.path(), "2025-03-12-GmA1aaaaXAAbbb1.jpg", b"one");
+    write(
+        dir.path(),
+        "2025-03-13-1899000000000000001-01-video.mp4",
+        b"two",
+    );
+    write(dir.path(), "old/holiday.png", b"three");
+    write(dir.path(), "2025-03-14-GmA2.jpg.part", b"partial");
+    write(dir.path(), "notes.txt", b"not media");
+    let pool = db::init_memory_db().await.expect("db");
+
+    let summary = import::import_folder(
+        &pool,
+        dir.path(),
+        "alice",
+        HashAlgorithm::Sha256,
+        2,
+        |_, _| {},
+    )
+    .await
+    .expect("import");
+    assert_eq!(
+        summary,
+        ImportSummary {
+            found: 3,
+            known: 0,
+            indexed: 3,
+            failed: 0,
+        }
+    );
+
+    // Downloads recognize the image by its URL
+    let url = "https://pbs.twimg.com/media/GmA1aaaaXAAbbb1.jpg";
+    assert!(
+        db::verify_file(&pool, url, dir.path())
+            .await
+            .expect("verify")
+    );
+    let users = db::list_users(&pool, db::UserOrder::Name)
+        .await
+        .expect("users");
+    assert_eq!(users[0].media, 3);
+    assert_eq!(users[0].hashed, 3);
+
+    write(dir.path(), "2025-04-01-GmA3cccccXAAddd3.png", b"four");
+    let mut calls = Vec::new();
+    let summary = import::import_folder(
+        &pool,
+        dir.path(),
+        "alice",
+        HashAlgorithm::Sha256,
+        2,
+        |done, total| calls.push((done, total)),
+    )
+    .await
+    .expect("import");
+    assert_eq!((summary.known, summary.indexed), (3, 1));
+    assert_eq!(calls, [(0, 1), (1, 1)]);
+}
+
+mod gallery_dl {
+    use std::path::{Path, PathBuf};
+
+    use rxd::db;
+    use rxd::import::gallery_dl::{self, Entry, ImportSummary};
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/gallery-dl");
+
+    /// gallery-dl archive holding `keys`, one per line
+    async fn write_archive(dir: &Path, keys: &str) -> PathBuf {
+        let path = dir.join("archive.sqlite3");
+        let options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true);
+        let archive = SqlitePoolOptions::new()
+            .connect_with(options)
+            .await
+            .expect("archive");
+        sqlx::query("CREATE TABLE archive (entry TEXT PRIMARY KEY) WITHOUT ROWID")
+            .execute(&archive)
+            .await
+            .expect("table");
+        for key in keys.lines() {
+            sqlx::query("INSERT INTO archive (entry) VALUES (?)")
+                .bind(key)
+                .execute(&archive)
+                .await
+                .expect("entry");
+        }
+        archive.close().await;
+        path
+    }
+
+    fn fixture(name: &str) -> String {
+        std::fs::read_to_string(Path::new(FIXTURES).join(name)).expect("fixture")
+    }
+
+    #[test]
+    fn parses_archive_keys() {
+        let entry = |tweet_id: &str, num| Entry {
+            tweet_id: tweet_id.to_string(),
+            num,
+        };
+
+        assert_eq!(
+            gallery_dl::parse_entry("twitter1899000000000000001_0_2"),
+            Some(entry("1899000000000000001", 2))
+        );
+        assert_eq!(
+            gallery_dl::parse_entry("twitter1899000000000000004_1899000000000000005_1"),
+            Some(entry("1899000000000000004", 1))
+        );
+        for other in [
+            "pixiv12345678_p0",
+            "twitterabc_0_1",
+            "twitter1_0",
+            "twitter1_0_0",
+        ] {
+            assert_eq!(gallery_dl::parse_entry(other), None, "{other}");
+        }
+    }
+
+    #[test]
+    fn parses_metadata_files() {
+        let metadata =
+            gallery_dl::parse_metadata(&fixture("twitter/alice/1899000000000000001_2.jpg.json"))
+                .expect("twitter metadata");
+
+        assert_eq!(metadata.entry.tweet_id, "1899000000000000001");
+        assert_eq!(metadata.entry.num, 2);
+        assert_eq!(metadata.screen_name, "alice");
+        assert_eq!(metadata.content.as_deref(), Some("Two sketches from today"));
+        assert_eq!(metadata.filename.as_deref(), Some("GmA2bbbbXAAccc2"));
+        assert_eq!(
+            metadata.date.map(|d| d.to_string()).as_deref(),
+            Some("2025-03-12 10:00:00")
+        );
+        assert_eq!(
+            gallery_dl::parse_metadata(&fixture("pixiv/12345678_p0.png.json")),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn imports_archive_with_metadata() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let archive = write_archive(dir.path(), &fixture("archive.txt")).await;
+        let pool = db::init_memory_db().await.expect("db");
+
+        let summary = gallery_dl::import(&pool, &archive, Some(Path::new(FIXTURES)), None)
+            .await
+            .expect("import");
+        assert_eq!(
+            summary,
+            ImportSummary {
+                entries: 6,
+                imported: 3,
+                known: 0,
+                ignored: 2,
+                no_account: 1,
+            }
+        );
+
+        let image = "https://pbs.twimg.com/media/GmA2bbbbXAAccc2.jpg";
+        let video = gallery_dl::placeholder_url("1899000000000000002", 1);
+        for url in [image, &video] {
+            assert!(
+                db::is_imported_elsewhere(&pool, url, "-")
+                    .await
+                    .expect("lookup"),
+                "{url}"
+            );
+        }
+        let record = db::get_media_by_url(&pool, image)
+            .await
+            .expect("lookup")
+            .expect("recorded");
+        assert_eq!(
+            record.filename.as_deref(),
+            Some("1899000000000000001_2.jpg")
+        );
+        let found = db::search_text(&pool, "sketches", Some("alice"))
+            .await
+            .expect("search");
+        assert_eq!(found.len(), 1);
+
+        // Accounts of entries without metadata can be given, the rest is kept
+        let summary = gallery_dl::import(&pool, &archive, Some(Path::new(FIXTURES)), Some("bob"))
+            .await
+            .expect("import");
+        assert_eq!((summary.imported, summary.known), (1, 3));
+        let users = db::list_users(&pool, db::UserOrder::Name)
+            .await
+            .expect("users");
+        assert_eq!(users[1].screen_name, "bob");
+        // Time of the tweet from its ID
+        assert_eq!(
+            users[1].newest_tweet.as_deref().map(|t| &t[..4]),
+            Some("2025")
+        );
+    }
+}
+
+mod twitter_archive {
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    use rxd::db;
+    use rxd::filename::Timezone;
+    use rxd::hash::HashAlgorithm;
+    use rxd::import::twitter_archive::{self, ImportOptions, ImportSummary, TwitterArchive};
+    use rxd::{Api, ImageSize, MediaType};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const FIXTURES: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/twitter-archive"
+    );
+    const PIXEL: &[u8] = include_bytes!("fixtures/pixel.jpg");
+
+    fn fixture(name: &str) -> String {
+        std::fs::read_to_string(Path::new(FIXTURES).join(name)).expect("fixture")
+    }
+
+    fn write(path: &Path, content: &[u8]) {
+        std::fs::create_dir_all(path.parent().expect("parent")).expect("mkdir");
+        std::fs::write(path, content).expect("write");
+    }
+
+    /// Unpacked export of the `tweets.js` fixture with media served by
+    /// `media_host`, bundling the first photo and the smaller video
+    fn unpacked_export(dir: &Path, media_host: &str) -> PathBuf {
+        let root = dir.join("twitter-2025-03-20-abc123");
+        let tweets = fixture("tweets.js").replace("https://pbs.twimg.com", media_host);
+        write(&root.join("data/tweets.js"), tweets.as_bytes());
+        write(
+            &root.join("data/account.js"),
+            fixture("account.js").as_bytes(),
+        );
+        let media = root.join("data/tweets_media");
+        write(
+            &media.join("1899000000000000001-GmA1aaaaXAAbbb1.jpg"),
+            PIXEL,
+        );
+        write(&media.join("1899000000000000002-LowRes.mp4"), b"video");
+        root
+    }
+
+    fn options(folder: &Path) -> ImportOptions {
+        ImportOptions {
+            screen_name: "alice".to_string(),
+            folder: folder.to_path_buf(),
+            filename_template: None,
+            timezone: Timezone::Utc,
+            image_size: ImageSize::Orig,
+            hash_algorithm: HashAlgorithm::Sha256,
+            max_file_size: None,
+            concurrency: 2,
+        }
+    }
+
+    #[test]
+    fn parses_every_generation() {
+        let parsed = twitter_archive::parse_tweets(&fixture("tweets.js")).expect("tweets.js");
+        assert_eq!((parsed.tweets.len(), parsed.unreadable), (4, 1));
+        let photos = &parsed.tweets[0];
+        assert_eq!(photos.tweet_id, "1899000000000000001");
+        assert_eq!(photos.timestamp.to_rfc3339(), "2025-03-12T10:00:00+00:00");
+        assert_eq!(photos.engagement.favorite_count, Some(12));
+        assert_eq!(photos.engagement.retweet_count, Some(3));
+        // All media of the extended entities, not just the first
+        assert_eq!(
+            photos
+                .media
+                .iter()
+                .map(|m| m.url.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "https://pbs.twimg.com/media/GmA1aaaaXAAbbb1.jpg",
+                "https://pbs.twimg.com/media/GmA2bbbbXAAccc2.jpg"
+            ]
+        );
+        let video = &parsed.tweets[1].media[0];
+        assert!(matches!(video.media_type, MediaType::Video));
+        assert_eq!(video.duration_ms, Some(15015));
+        // mp4 variants only, the highest bitrate first
+        assert_eq!(video.variants.len(), 2);
+        assert!(video.variants[0].contains("HighRes.mp4"));
+        assert!(parsed.tweets[2].is_retweet);
+        assert!(parsed.tweets[3].media.is_empty());
+
+        // Unwrapped tweets with numeric IDs and counts
+        let parsed = twitter_archive::parse_tweets(&fixture("tweet.js")).expect("tweet.js");
+        let tweet = &parsed.tweets[0];
+        assert_eq!(tweet.tweet_id, "1100000000000000001");
+        assert_eq!(tweet.engagement.favorite_count, Some(7));
+        assert_eq!(
+            tweet.media[0].url,
+            "https://pbs.twimg.com/media/D1abcdeXgAAf12G.jpg"
+        );
+        assert!(matches!(tweet.media[1].media_type, MediaType::Video));
+
+        // A file per month in the oldest exports
+        let parsed = twitter_archive::parse_tweets(&fixture("grailbird.js")).expect("grailbird");
+        let tweet = &parsed.tweets[0];
+        assert_eq!(
+            tweet.full_text.as_deref(),
+            Some("First post http://t.co/AbCdE")
+        );
+        assert_eq!(tweet.timestamp.to_rfc3339(), "2012-03-14T12:00:00+00:00");
+        assert_eq!(tweet.media[0].url, "https://p.twimg.com/AoldPhoto1.jpg");
+
+        for file in ["account.js", "user_details.js"] {
+            assert_eq!(
+                twitter_archive::parse_account(&fixture(file)).as_deref(),
+                Some("alice"),
+                "{file}"
+            );
+        }
+        assert!(twitter_archive::parse_tweets("window.YTD.tweets.part0 = {}").is_err());
+    }
+
+    #[tokio::test]
+    async fn copies_bundled_media_and_downloads_the_rest() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/media/GmA2bbbbXAAccc2.jpg"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(PIXEL))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let dir = tempfile::tempdir().expect("tempdir");
+        let root = unpacked_export(dir.path(), &server.uri());
+        let folder = dir.path().join("downloads/alice");
+        let pool = db::init_memory_db().await.expect("db");
+        let api = Api::with_base_url("", "", &server.uri()).expect("api");
+
+        let archive = TwitterArchive::open(&root).await.expect("open");
+        assert_eq!(
+            archive.screen_name().await.expect("account").as_deref(),
+            Some("alice")
+        );
+        let summary =
+            twitter_archive::import(&pool, &archive, Some(&api), &options(&folder), |_, _| {})
+                .await
+                .expect("import");
+        assert_eq!(
+            summary,
+            ImportSummary {
+                tweets: 5,
+                imported: 2,
+                copied: 2,
+                queued: 1,
+                downloaded: 1,
+                failed: 0,
+                // The retweet's photo and the like
+                skipped: 2,
+            }
+        );
+
+        // Named like downloads, the video after the variant it is of
+        for (url, name) in [
+            (
+                format!("{}/media/GmA1aaaaXAAbbb1.jpg", server.uri()),
+                "2025-03-12-1899000000000000001-01-GmA1aaaaXAAbbb1.jpg",
+            ),
+            (
+                format!("{}/media/GmA2bbbbXAAccc2.jpg", server.uri()),
+                "2025-03-12-1899000000000000001-02-GmA2bbbbXAAccc2.jpg",
+            ),
+            (
+                format!(
+                    "{}/ext_tw_video_thumb/1899000000000000020/pu/img/VidThumb1.jpg",
+                    server.uri()
+                ),
+                "2025-03-13-LowRes.mp4",
+            ),
+        ] {
+            assert!(folder.join(name).exists(), "{name}");
+            assert!(
+                db::verify_file(&pool, &url, &folder).await.expect("verify"),
+                "{url}"
+            );
+        }
+        let found = db::search_text(&pool, "timelapse", Some("alice"))
+            .await
+            .expect("search");
+        assert_eq!(found.len(), 1);
+
+        // Everything is recorded with its file now
+        let summary =
+            twitter_archive::import(&pool, &archive, Some(&api), &options(&folder), |_, _| {})
+                .await
+                .expect("import");
+        assert_eq!(
+            (summary.imported, summary.copied, summary.skipped),
+            (0, 0, 5)
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_zip_files() {
+        if Command::new("zip").arg("-v").output().is_err()
+            || Command::new("unzip").arg("-v").output().is_err()
+        {
+            eprintln!("zip or unzip not installed, skipping");
+            return;
+        }
+        let dir = tempfile::tempdir().expect("tempdir");
+        unpacked_export(dir.path(), "https://pbs.twimg.com");
+        let zip = dir.path().join("export.zip");
+        let status = Command::new("zip")
+            .arg("-qr")
+            .arg(&zip)
+            .arg("twitter-2025-03-20-abc123")
+            .current_dir(dir.path())
+            .status()
+            .expect("zip");
+        assert!(status.success());
+        let folder = dir.path().join("downloads/alice");
+        let pool = db::init_memory_db().await.expect("db");
+
+        let archive = TwitterArchive::open(&zip).await.expect("open");
+        assert_eq!(
+            archive.screen_name().await.expect("account").as_deref(),
+            Some("alice")
+        );
+        // Without a client the missing photo is only recorded
+        let summary = twitter_archive::import(&pool, &archive, None, &options(&folder), |_, _| {})
+            .await
+            .expect("import");
+        assert_eq!(
+            (summary.copied, summary.queued, summary.downloaded),
+            (2, 1, 0)
+        );
+        assert_eq!(
+            std::fs::read(folder.join("2025-03-13-LowRes.mp4")).expect("video"),
+            b"video"
+        );
+        assert!(
+            TwitterArchive::open(&dir.path().join("downloads"))
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! Account details for `rxd info`.
+
+use rxd::{Api, Error, db};
+use serde_json::json;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const USER_BY_SCREEN_NAME: &str = "/i/api/graphql/xc8f1g7BYqr6VTzTbvNlGw/UserByScreenName";
+
+async fn answer(server: &MockServer, result: serde_json::Value) {
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": { "user": { "result": result } }
+        })))
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn reads_the_profile() {
+    let server = MockServer::start().await;
+    answer(
+        &server,
+        json!({
+            "__typename": "User",
+            "rest_id": "42",
+            "legacy": {
+                "screen_name": "Alice",
+                "name": "Alice A.",
+                "media_count": 120,
+                "statuses_count": 3400,
+                "followers_count": 56,
+                "protected": true,
+                "created_at": "Wed Mar 05 14:30:00 +0000 2014",
+                "profile_image_url_https": "https://pbs.twimg.com/profile_images/1/avatar_normal.jpg",
+                "profile_banner_url": "https://pbs.twimg.com/profile_banners/42/1500000000"
+            }
+        }),
+    )
+    .await;
+    let api = Api::with_base_url("token", "ct0", &server.uri()).expect("api");
+
+    let profile = api.user_profile("alice").await.expect("profile");
+
+    assert_eq!(profile.screen_name, "Alice");
+    assert_eq!(profile.name, "Alice A.");
+    assert_eq!(profile.rest_id, "42");
+    assert_eq!(profile.media_count, 120);
+    assert_eq!(profile.statuses_count, 3400);
+    assert_eq!(profile.followers_count, 56);
+    assert!(profile.protected);
+    assert_eq!(
+        profile.created_at.as_deref(),
+        Some("2014-03-05T14:30:00+00:00")
+    );
+    assert_eq!(
+        profile.avatar_url.as_deref(),
+        Some("https://pbs.twimg.com/profile_images/1/avatar.jpg")
+    );
+    assert_eq!(
+        profile.banner_url.as_deref(),
+        Some("https://pbs.twimg.com/profile_banners/42/1500000000")
+    );
+}
+
+#[tokio::test]
+async fn suspended_accounts_are_unavailable() {
+    let server = MockServer::start().await;
+    answer(
+        &server,
+        json!({ "__typename": "UserUnavailable", "reason": "Suspended" }),
+    )
+    .await;
+    let api = Api::with_base_url("token", "ct0", &server.uri()).expect("api");
+
+    match api.user_profile("spammer").await {
+        Err(Error::UserUnavailable {
+            screen_name,
+            reason,
+        }) => {
+            assert_eq!(screen_name, "spammer");
+            assert_eq!(reason, "Suspended");
+        }
+        other => panic!("expected an unavailable account, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn archived_counts_ignore_case() {
+    let pool = db::init_memory_db().await.expect("db");
+    db::upsert_tweet(&pool, "1", "Alice", "2025-03-12 10:00:00", None)
+        .await
+        .expect("tweet");
+    db::upsert_tweet(&pool, "2", "Alice", "2025-04-01 08:00:00", None)
+        .await
+        .expect("tweet");
+    db::upsert_media(&pool, "1", "https://pbs.twimg.com/media/a.jpg", None, None)
+        .await
+        .expect("media");
+
+    let stats = db::user_stats(&pool, "alice")
+        .await
+        .expect("stats")
+        .expect("archived");
+    assert_eq!(stats.screen_name, "Alice");
+    assert_eq!(stats.media, 1);
+    assert_eq!(stats.hashed, 0);
+    assert_eq!(stats.newest_tweet.as_deref(), Some("2025-04-01 08:00:00"));
+    assert_eq!(db::user_stats(&pool, "bob").await.expect("stats"), None);
+}
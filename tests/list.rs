@@ -0,0 +1,80 @@
+//! Members of a List, paged from the ListMembers endpoint.
+
+use rxd::Api;
+use rxd::list::{self, ListId};
+use serde_json::{Value, json};
+use wiremock::matchers::{method, path, query_param_contains};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const LIST_MEMBERS: &str = "/i/api/graphql/BQp2IEYkgxuSxqbTAr1e1g/ListMembers";
+
+fn member(rest_id: &str, screen_name: &str) -> Value {
+    json!({
+        "entryId": format!("user-{rest_id}"),
+        "content": { "itemContent": { "user_results": { "result": {
+            "__typename": "User",
+            "rest_id": rest_id,
+            "legacy": { "name": screen_name, "screen_name": screen_name }
+        }}}}
+    })
+}
+
+fn members_page(mut entries: Vec<Value>, cursor: &str) -> Value {
+    entries.push(json!({
+        "entryId": "cursor-bottom-0",
+        "content": { "value": cursor }
+    }));
+    json!({
+        "data": { "list": { "members_timeline": { "timeline": {
+            "instructions": [{ "type": "TimelineAddEntries", "entries": entries }]
+        }}}}
+    })
+}
+
+#[test]
+fn list_ids_are_parsed_from_ids_and_links() {
+    for input in [
+        "1234567890",
+        " 1234567890 ",
+        "https://x.com/i/lists/1234567890",
+        "https://twitter.com/i/lists/1234567890/members?s=20",
+    ] {
+        let list: ListId = input.parse().expect(input);
+        assert_eq!(list.as_str(), "1234567890", "{input}");
+    }
+    for input in ["", "nasa", "https://x.com/i/lists/", "https://x.com/nasa"] {
+        assert!(input.parse::<ListId>().is_err(), "{input}");
+    }
+}
+
+#[tokio::test]
+async fn members_are_paged_until_the_cursor_repeats() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(LIST_MEMBERS))
+        .and(query_param_contains("variables", "\"cursor\":\"page-2\""))
+        .respond_with(ResponseTemplate::new(200).set_body_json(members_page(
+            vec![member("2", "bob"), member("1", "alice")],
+            "page-2",
+        )))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(LIST_MEMBERS))
+        .and(query_param_contains("variables", "\"listId\":\"99\""))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(members_page(vec![member("1", "alice")], "page-2")),
+        )
+        .mount(&server)
+        .await;
+    let api = Api::with_base_url("token", "ct0", &server.uri()).expect("api");
+
+    let members = list::list_members(&api, &"99".parse().expect("list id"))
+        .await
+        .expect("members");
+
+    let names: Vec<_> = members.iter().map(|m| m.screen_name.as_str()).collect();
+    assert_eq!(names, ["alice", "bob"]);
+}
@@ -0,0 +1,50 @@
+//! Lines printed by `download --list-only`.
+
+use rxd::ImageSize;
+use rxd::listing::ListFormat;
+use rxd::task::{ExtractOptions, parse_user_media_response};
+use serde_json::Value;
+
+fn first_page_items() -> Vec<rxd::MediaItem> {
+    let path = format!(
+        "{}/tests/fixtures/user_media_first_page.json",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let content = std::fs::read_to_string(&path).expect("fixture exists");
+    let raw: Value = serde_json::from_str(&content).expect("fixture is valid JSON");
+    parse_user_media_response(&raw, &ExtractOptions::default())
+        .expect("parse")
+        .items
+}
+
+#[test]
+fn urls_are_listed_by_default() {
+    let items = first_page_items();
+    let line = ListFormat::default().line(&items[0], "someone", ImageSize::Orig);
+
+    assert_eq!(
+        line,
+        "https://pbs.twimg.com/media/GmA1aaaaXAAbbb1.jpg?name=orig"
+    );
+}
+
+#[test]
+fn formats_fill_in_placeholders() {
+    let items = first_page_items();
+    let format: ListFormat = "{screen_name}\t{tweet_id}\t{date}\t{type}"
+        .parse()
+        .expect("format");
+
+    assert_eq!(
+        format.line(&items[0], "someone", ImageSize::Orig),
+        "someone\t1900000000000000001\t2025-03-12T18:47:51+00:00\timage"
+    );
+}
+
+#[test]
+fn unknown_placeholders_are_rejected() {
+    for format in ["{media_id}", "{url"] {
+        let error = format.parse::<ListFormat>().expect_err(format);
+        assert!(matches!(error, rxd::Error::Config(_)), "{error:?}");
+    }
+}
@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use rxd::lock::{RunLock, lock_path};
+
+#[test]
+fn lock_file_sits_next_to_the_database() {
+    assert_eq!(
+        lock_path(std::path::Path::new("data/rxd.db")),
+        std::path::Path::new("data/rxd.db.lock")
+    );
+}
+
+#[test]
+fn a_second_run_is_told_who_holds_the_lock() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = lock_path(&dir.path().join("rxd.db"));
+    let lock = RunLock::try_acquire(&path).expect("lock");
+    assert!(lock.stale().is_none());
+
+    let error = RunLock::try_acquire(&path).expect_err("held");
+    match &error {
+        rxd::Error::Locked { holder, .. } => {
+            let holder = holder.as_ref().expect("holder");
+            assert_eq!(holder.pid, std::process::id());
+        }
+        other => panic!("expected Locked, got {other:?}"),
+    }
+    assert!(
+        error
+            .to_string()
+            .contains(&format!("pid {}", std::process::id()))
+    );
+    drop(lock);
+    let lock = RunLock::try_acquire(&path).expect("released");
+    assert!(lock.stale().is_none());
+}
+
+#[test]
+fn a_crashed_run_leaves_a_stale_lock_that_is_reclaimed() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = lock_path(&dir.path().join("nested").join("rxd.db"));
+    std::fs::create_dir_all(path.parent().expect("parent")).expect("mkdir");
+    // Written by a run that never released the lock
+    std::fs::write(&path, "4194305 2026-01-02T03:04:05+00:00\n").expect("write");
+
+    let lock = RunLock::try_acquire(&path).expect("reclaimed");
+    assert_eq!(lock.stale().map(|holder| holder.pid), Some(4194305));
+    let contents = std::fs::read_to_string(&path).expect("read");
+    assert!(contents.starts_with(&format!("{} ", std::process::id())));
+
+    drop(lock);
+    assert_eq!(std::fs::read_to_string(&path).expect("read"), "");
+}
+
+#[tokio::test]
+async fn waiting_takes_the_lock_once_it_is_released() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = lock_path(&dir.path().join("rxd.db"));
+    let lock = RunLock::try_acquire(&path).expect("lock");
+
+    let waiting = tokio::spawn({
+        let path = path.clone();
+        async move { RunLock::acquire(&path).await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!waiting.is_finished());
+
+    drop(lock);
+    let lock = tokio::time::timeout(Duration::from_secs(5), waiting)
+        .await
+        .expect("not released")
+        .expect("join")
+        .expect("lock");
+    assert!(lock.stale().is_none());
+}
+
+#[tokio::test]
+async fn writing_commands_refuse_a_locked_database() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let db = dir.path().join("rxd.db");
+    rxd::db::init_db(&db).await.expect("db");
+    let globals = rxd::cli::Globals {
+        config: Some(dir.path().join("config.toml")),
+        wait: false,
+    };
+
+    let (_pool, lock) = globals.lock_existing_db(Some(&db)).await.expect("lock");
+    assert!(lock.is_some());
+    let error = globals.lock_existing_db(Some(&db)).await.expect_err("held");
+    assert!(matches!(error, rxd::Error::Locked { .. }), "{error:?}");
+    // Reading needs no lock
+    globals.open_existing_db(Some(&db)).await.expect("open");
+
+    drop(lock);
+    globals.lock_db(Some(&db)).await.expect("released");
+}
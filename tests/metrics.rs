@@ -0,0 +1,149 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{Local, TimeZone};
+use rxd::Api;
+use rxd::events::{Event, SkipReason, Totals};
+use rxd::metrics::Metrics;
+use serde_json::json;
+use tokio_util::sync::CancellationToken;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const USER_BY_SCREEN_NAME: &str = "/i/api/graphql/xc8f1g7BYqr6VTzTbvNlGw/UserByScreenName";
+
+fn started(url: &str) -> Event {
+    Event::DownloadStarted {
+        screen_name: "nasa".to_string(),
+        tweet_id: "1".to_string(),
+        url: url.to_string(),
+    }
+}
+
+#[test]
+fn events_are_counted_per_account() {
+    let metrics = Metrics::new(Default::default());
+    for event in [
+        started("a"),
+        started("b"),
+        started("c"),
+        Event::ItemDownloaded {
+            screen_name: "nasa".to_string(),
+            tweet_id: "1".to_string(),
+            url: "a".to_string(),
+            path: PathBuf::from("a.jpg"),
+            bytes: 1500,
+        },
+        Event::ItemFailed {
+            screen_name: "nasa".to_string(),
+            tweet_id: "1".to_string(),
+            url: "b".to_string(),
+            error: "boom".to_string(),
+        },
+        Event::ItemSkipped {
+            screen_name: "esa".to_string(),
+            tweet_id: "2".to_string(),
+            url: "d".to_string(),
+            reason: SkipReason::Verified,
+        },
+    ] {
+        metrics.record(&event);
+    }
+    let text = metrics.render();
+    for line in [
+        "rxd_items_downloaded_total{screen_name=\"nasa\"} 1",
+        "rxd_items_failed_total{screen_name=\"nasa\"} 1",
+        "rxd_items_skipped_total{screen_name=\"esa\",reason=\"verified\"} 1",
+        "rxd_downloaded_bytes_total{screen_name=\"nasa\"} 1500",
+        "rxd_downloads_in_flight 1",
+        "rxd_api_requests_total 0",
+        "rxd_runs_total 0",
+        "# TYPE rxd_items_downloaded_total counter",
+    ] {
+        assert!(
+            text.lines().any(|l| l == line),
+            "{line} missing from\n{text}"
+        );
+    }
+    // Unknown until an API response says
+    assert!(!text.contains("rxd_rate_limit_remaining "));
+    assert!(!text.contains("rxd_last_run_timestamp_seconds "));
+
+    // A finished task has nothing in flight any more
+    metrics.record(&Event::TaskFinished {
+        screen_name: "nasa".to_string(),
+        totals: Totals::default(),
+    });
+    let started = Local.with_ymd_and_hms(2026, 1, 2, 3, 4, 5).unwrap();
+    metrics.record_run(started, Duration::from_millis(2500));
+    let text = metrics.render();
+    for line in [
+        "rxd_downloads_in_flight 0",
+        "rxd_runs_total 1",
+        &format!("rxd_last_run_timestamp_seconds {}", started.timestamp()),
+        "rxd_last_run_duration_seconds 2.500",
+    ] {
+        assert!(
+            text.lines().any(|l| l == line),
+            "{line} missing from\n{text}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn api_requests_and_rate_limit_are_served() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("x-rate-limit-remaining", "42")
+                .set_body_json(json!({
+                    "data": { "user": { "result": {
+                        "__typename": "User",
+                        "rest_id": "42",
+                        "legacy": { "name": "Test User", "media_count": 1 }
+                    }}}
+                })),
+        )
+        .mount(&server)
+        .await;
+    let api = Api::with_base_url("token", "ct0", &server.uri()).expect("api");
+    // Clones count towards the same total
+    api.clone().user_by_screen_name("a").await.expect("lookup");
+    api.user_by_screen_name("b").await.expect("lookup");
+
+    let metrics = Arc::new(Metrics::new(api.usage().clone()));
+    let cancel = CancellationToken::new();
+    let addr = rxd::metrics::serve(
+        "127.0.0.1:0".parse().expect("addr"),
+        metrics,
+        cancel.clone(),
+    )
+    .await
+    .expect("serve");
+
+    let response = reqwest::get(format!("http://{addr}/metrics"))
+        .await
+        .expect("scrape");
+    assert_eq!(response.status(), 200);
+    let text = response.text().await.expect("body");
+    assert!(
+        text.lines().any(|l| l == "rxd_api_requests_total 2"),
+        "{text}"
+    );
+    assert!(
+        text.lines().any(|l| l == "rxd_rate_limit_remaining 42"),
+        "{text}"
+    );
+
+    let response = reqwest::get(format!("http://{addr}/other"))
+        .await
+        .expect("request");
+    assert_eq!(response.status(), 404);
+
+    cancel.cancel();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+}
@@ -0,0 +1,156 @@
+//! Tasks losing the network connection, simulated by stopping the mock API
+//! server and starting it again on the same port.
+
+use std::net::{SocketAddr, TcpListener};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rxd::network::OutagePolicy;
+use rxd::{Api, Error, Task};
+use serde_json::{Value, json};
+use wiremock::matchers::{method, path, query_param_contains};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const USER_BY_SCREEN_NAME: &str = "/i/api/graphql/xc8f1g7BYqr6VTzTbvNlGw/UserByScreenName";
+const USER_MEDIA: &str = "/i/api/graphql/Le6KlbilFmSu-5VltFND-Q/UserMedia";
+
+fn media_page(items: Vec<Value>, cursor: Option<&str>) -> Value {
+    let mut entries = vec![json!({
+        "entryId": "profile-grid-0",
+        "content": { "items": items }
+    })];
+    if let Some(cursor) = cursor {
+        entries.push(json!({
+            "entryId": "cursor-bottom-0",
+            "content": { "value": cursor }
+        }));
+    }
+    json!({
+        "data": { "user": { "result": { "timeline_v2": { "timeline": {
+            "instructions": [{ "type": "TimelineAddEntries", "entries": entries }]
+        }}}}}
+    })
+}
+
+fn photo_item(tweet_id: &str, url: &str) -> Value {
+    json!({
+        "item": { "itemContent": { "tweet_results": { "result": {
+            "__typename": "Tweet",
+            "rest_id": tweet_id,
+            "legacy": {
+                "created_at": "Wed Mar 12 12:00:00 +0000 2025",
+                "extended_entities": { "media": [
+                    { "type": "photo", "media_url_https": url }
+                ]}
+            }
+        }}}}
+    })
+}
+
+/// Mock server on `addr`, waiting for a stopped one to free the port
+async fn start_server(addr: SocketAddr) -> MockServer {
+    for _ in 0..50 {
+        if let Ok(listener) = TcpListener::bind(addr) {
+            return MockServer::builder().listener(listener).start().await;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("port {addr} still in use");
+}
+
+/// Task for `test_user` built while the server is up, which is then stopped
+async fn task_while_online(dir: &Path, policy: OutagePolicy) -> (Task, SocketAddr) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+    let addr = listener.local_addr().expect("addr");
+    let server = MockServer::builder().listener(listener).start().await;
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": { "user": { "result": {
+                "__typename": "User",
+                "rest_id": "42",
+                "legacy": { "name": "Test User", "media_count": 1 }
+            }}}
+        })))
+        .mount(&server)
+        .await;
+    let db = rxd::db::init_db(&dir.join("rxd.db")).await.expect("db");
+    let task = Task::builder()
+        .api(Api::with_base_url("token", "ct0", &server.uri()).expect("api"))
+        .screen_name("test_user")
+        .save_path(dir.join("media"))
+        .outage_policy(policy)
+        .db(db)
+        .build()
+        .await
+        .expect("task");
+    drop(server);
+    (task, addr)
+}
+
+/// Timeline of one photo, as served once the network is back
+async fn mount_timeline(server: &MockServer) {
+    let media_url = format!("{}/media/AAA.jpg", server.uri());
+    Mock::given(method("GET"))
+        .and(path("/media/AAA.jpg"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"AAA".as_slice()))
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .and(query_param_contains("variables", "page-2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(vec![], None)))
+        .with_priority(1)
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(media_page(
+            vec![photo_item("1", &media_url)],
+            Some("page-2"),
+        )))
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn tasks_resume_once_the_network_is_back() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let policy = OutagePolicy::default()
+        .failures(1)
+        .probe_interval(Duration::from_millis(100));
+    let (task, addr) = task_while_online(dir.path(), policy).await;
+
+    let reconnect = async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let server = start_server(addr).await;
+        mount_timeline(&server).await;
+        server
+    };
+    let execute = tokio::time::timeout(Duration::from_secs(10), Arc::new(task).execute());
+    let (result, _server) = tokio::join!(execute, reconnect);
+
+    let totals = result.expect("resumed").expect("execute");
+    assert_eq!(totals.downloaded, 1);
+    assert_eq!(totals.failed, 0);
+}
+
+#[tokio::test]
+async fn tasks_give_up_after_the_longest_outage() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let policy = OutagePolicy::default()
+        .failures(1)
+        .max_outage(Duration::from_millis(300))
+        .probe_interval(Duration::from_millis(100));
+    let (task, _addr) = task_while_online(dir.path(), policy).await;
+
+    let result = tokio::time::timeout(Duration::from_secs(10), Arc::new(task).execute())
+        .await
+        .expect("gave up");
+
+    assert!(
+        matches!(result, Err(Error::NetworkDown { .. })),
+        "{result:?}"
+    );
+}
@@ -0,0 +1,325 @@
+//! Packing an account's files into a tar or zip archive.
+
+use std::path::Path;
+
+use chrono::NaiveDate;
+use common::media;
+use rxd::db;
+use rxd::hash::HashAlgorithm;
+use rxd::pack::{self, PackOptions};
+use serde_json::Value;
+
+mod common;
+
+/// Names and contents of the entries of the tar archive at `path`
+fn entries(path: &Path) -> Vec<(String, Vec<u8>)> {
+    let tar = std::fs::read(path).expect("read");
+    let mut entries = Vec::new();
+    let mut long_name = None;
+    let mut offset = 0;
+    while offset + 512 <= tar.len() && tar[offset..offset + 512].iter().any(|&b| b != 0) {
+        let header = &tar[offset..offset + 512];
+        let field = |range: std::ops::Range<usize>| {
+            let bytes = &header[range];
+            let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+            String::from_utf8(bytes[..end].to_vec()).expect("utf-8")
+        };
+        let size = u64::from_str_radix(field(124..136).trim(), 8).expect("size") as usize;
+        let stored: u32 = u32::from_str_radix(field(148..154).trim(), 8).expect("checksum");
+        let sum: u32 = header
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| {
+                if (148..156).contains(&i) {
+                    32
+                } else {
+                    u32::from(b)
+                }
+            })
+            .sum();
+        assert_eq!(stored, sum, "checksum of {}", field(0..100));
+        let data = tar[offset + 512..offset + 512 + size].to_vec();
+        offset += 512 + size.div_ceil(512) * 512;
+        if header[156] == b'L' {
+            long_name = Some(String::from_utf8(data[..size - 1].to_vec()).expect("utf-8"));
+            continue;
+        }
+        let name = long_name.take().unwrap_or_else(|| match field(345..500) {
+            prefix if prefix.is_empty() => field(0..100),
+            prefix => format!("{prefix}/{}", field(0..100)),
+        });
+        entries.push((name, data));
+    }
+    entries
+}
+
+/// Names and contents of the entries of the zip archive at `path`, read
+/// through its central directory and checked against their CRC-32
+fn zip_entries(path: &Path) -> Vec<(String, Vec<u8>)> {
+    let zip = std::fs::read(path).expect("read");
+    let u16_at = |at: usize| u16::from_le_bytes([zip[at], zip[at + 1]]) as usize;
+    let u32_at = |at: usize| u32::from_le_bytes(zip[at..at + 4].try_into().unwrap());
+    // Written without a comment
+    let end = zip.len() - 22;
+    assert_eq!(u32_at(end), 0x0605_4b50, "end of central directory");
+    let count = u16_at(end + 10);
+    let mut offset = u32_at(end + 16) as usize;
+
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        assert_eq!(u32_at(offset), 0x0201_4b50, "central header");
+        assert_eq!(u16_at(offset + 10), 0, "stored");
+        let crc = u32_at(offset + 16);
+        let size = u32_at(offset + 24) as usize;
+        let name_len = u16_at(offset + 28);
+        let next = offset + 46 + name_len + u16_at(offset + 30) + u16_at(offset + 32);
+        let name =
+            String::from_utf8(zip[offset + 46..offset + 46 + name_len].to_vec()).expect("utf-8");
+
+        let local = u32_at(offset + 42) as usize;
+        assert_eq!(u32_at(local), 0x0403_4b50, "local header of {name}");
+        assert_eq!(u32_at(local + 14), crc, "local CRC-32 of {name}");
+        let data_at = local + 30 + u16_at(local + 26) + u16_at(local + 28);
+        let data = zip[data_at..data_at + size].to_vec();
+        assert_eq!(crc32(&data), crc, "CRC-32 of {name}");
+        entries.push((name, data));
+        offset = next;
+    }
+    entries
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn names(entries: &[(String, Vec<u8>)]) -> Vec<&str> {
+    entries.iter().map(|(name, _)| name.as_str()).collect()
+}
+
+#[tokio::test]
+async fn packs_files_sidecars_and_a_manifest_like_the_folder() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let folder = dir.path().join("alice");
+    std::fs::create_dir(&folder).expect("mkdir");
+    let pool = db::init_memory_db().await.expect("db");
+    media("a.jpg")
+        .text("caption")
+        .hash_of(b"image a")
+        .file(&folder, b"image a")
+        .record(&pool)
+        .await;
+    std::fs::write(folder.join("a.txt"), b"caption").expect("write");
+    let long = format!("{}.mp4", "v".repeat(120));
+    media(&long)
+        .tweet("2")
+        .time("2025-03-13 10:00:00")
+        .text("caption")
+        .hash_of(b"video")
+        .file(&folder, b"video")
+        .record(&pool)
+        .await;
+    std::fs::write(folder.join(format!("{}.thumb.jpg", "v".repeat(120))), b"t").expect("write");
+    media("gone.jpg")
+        .tweet("3")
+        .time("2025-03-14 10:00:00")
+        .text("caption")
+        .hash_of(b"gone")
+        .record(&pool)
+        .await;
+
+    let out = dir.path().join("alice.tar");
+    let summary = pack::pack(&pool, "alice", &folder, &out, &PackOptions::default())
+        .await
+        .expect("pack");
+
+    assert_eq!(summary.files, 2);
+    assert_eq!(summary.sidecars, 2);
+    assert_eq!(summary.missing, 1);
+    assert_eq!(summary.mismatched, 0);
+    let entries = entries(&out);
+    let long_entry = format!("alice/{long}");
+    let long_thumb = format!("alice/{}.thumb.jpg", "v".repeat(120));
+    assert_eq!(
+        names(&entries),
+        [
+            "alice/a.jpg",
+            "alice/a.txt",
+            long_entry.as_str(),
+            long_thumb.as_str(),
+            "alice/manifest.json",
+        ]
+    );
+    assert_eq!(entries[0].1, b"image a");
+    assert_eq!(entries[2].1, b"video");
+    assert!(!dir.path().join("alice.tar.part").exists());
+
+    let manifest: Value = serde_json::from_slice(&entries[4].1).expect("manifest");
+    assert_eq!(manifest["screen_name"], "alice");
+    let tweets = manifest["tweets"].as_array().expect("tweets");
+    assert_eq!(tweets.len(), 2);
+    assert_eq!(tweets[0]["tweet_id"], "1");
+    assert_eq!(tweets[0]["full_text"], "caption");
+    assert_eq!(tweets[0]["files"][0]["filename"], "a.jpg");
+    assert_eq!(
+        tweets[0]["files"][0]["hash"],
+        HashAlgorithm::Sha256.hash(b"image a")
+    );
+}
+
+#[tokio::test]
+async fn mismatched_files_fail_the_pack_unless_allowed() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let folder = dir.path().join("alice");
+    std::fs::create_dir(&folder).expect("mkdir");
+    let pool = db::init_memory_db().await.expect("db");
+    media("a.jpg")
+        .text("caption")
+        .hash_of(b"image a")
+        .record(&pool)
+        .await;
+    std::fs::write(folder.join("a.jpg"), b"image b").expect("write");
+    let out = dir.path().join("alice.tar");
+
+    let error = pack::pack(&pool, "alice", &folder, &out, &PackOptions::default())
+        .await
+        .expect_err("mismatch");
+    assert!(matches!(error, rxd::Error::HashMismatch(_)), "{error:?}");
+    assert!(!out.exists());
+    assert!(!dir.path().join("alice.tar.part").exists());
+
+    let options = PackOptions::default().allow_mismatch(true);
+    let summary = pack::pack(&pool, "alice", &folder, &out, &options)
+        .await
+        .expect("pack");
+    assert_eq!(summary.files, 1);
+    assert_eq!(summary.mismatched, 1);
+    assert_eq!(entries(&out)[0].1, b"image b");
+}
+
+#[tokio::test]
+async fn since_and_until_include_whole_days() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let folder = dir.path().join("alice");
+    std::fs::create_dir(&folder).expect("mkdir");
+    let pool = db::init_memory_db().await.expect("db");
+    for (id, time) in [
+        ("1", "2025-03-11 23:59:59"),
+        ("2", "2025-03-12 00:00:00"),
+        ("3", "2025-03-13 23:59:59"),
+        ("4", "2025-03-14 00:00:00"),
+    ] {
+        let filename = format!("{id}.jpg");
+        media(&filename)
+            .tweet(id)
+            .time(time)
+            .text("caption")
+            .hash_of(id.as_bytes())
+            .file(&folder, id.as_bytes())
+            .record(&pool)
+            .await;
+    }
+    let out = dir.path().join("alice.tar");
+    let options = PackOptions::default()
+        .since(NaiveDate::from_ymd_opt(2025, 3, 12))
+        .until(NaiveDate::from_ymd_opt(2025, 3, 13));
+
+    pack::pack(&pool, "alice", &folder, &out, &options)
+        .await
+        .expect("pack");
+
+    assert_eq!(
+        names(&entries(&out)),
+        ["alice/2.jpg", "alice/3.jpg", "alice/manifest.json"]
+    );
+}
+
+#[tokio::test]
+async fn archive_type_follows_the_extension() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let pool = db::init_memory_db().await.expect("db");
+
+    let out = dir.path().join("alice.rar");
+    let error = pack::pack(&pool, "alice", dir.path(), &out, &PackOptions::default())
+        .await
+        .expect_err("rar");
+    assert!(matches!(error, rxd::Error::Config(_)), "{error:?}");
+
+    let out = dir.path().join("alice.zip");
+    pack::pack(&pool, "alice", dir.path(), &out, &PackOptions::default())
+        .await
+        .expect("zip");
+    let bytes = std::fs::read(&out).expect("read");
+    assert_eq!(bytes[..4], *b"PK\x03\x04");
+
+    let out = dir.path().join("alice.tar.gz");
+    match pack::pack(&pool, "alice", dir.path(), &out, &PackOptions::default()).await {
+        Ok(_) => {
+            let bytes = std::fs::read(&out).expect("read");
+            assert_eq!(bytes[..2], [0x1f, 0x8b]);
+        }
+        // Not every machine running the tests has gzip
+        Err(rxd::Error::Config(e)) if e.contains("not installed") => {}
+        Err(e) => panic!("{e}"),
+    }
+}
+
+#[tokio::test]
+async fn zip_archives_unpack_like_the_folder() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let folder = dir.path().join("alice");
+    std::fs::create_dir(&folder).expect("mkdir");
+    let pool = db::init_memory_db().await.expect("db");
+    media("a.jpg")
+        .text("caption")
+        .hash_of(b"image a")
+        .file(&folder, b"image a")
+        .record(&pool)
+        .await;
+    std::fs::write(folder.join("a.txt"), b"caption").expect("write");
+    let long = format!("{}.mp4", "v".repeat(120));
+    let video = vec![7; 200_000];
+    media(&long)
+        .tweet("2")
+        .time("2025-03-13 10:00:00")
+        .text("caption")
+        .hash_of(&video)
+        .file(&folder, &video)
+        .record(&pool)
+        .await;
+
+    let out = dir.path().join("alice.zip");
+    let summary = pack::pack(&pool, "alice", &folder, &out, &PackOptions::default())
+        .await
+        .expect("pack");
+
+    assert_eq!(summary.files, 2);
+    assert_eq!(summary.sidecars, 1);
+    let entries = zip_entries(&out);
+    let long_entry = format!("alice/{long}");
+    assert_eq!(
+        names(&entries),
+        [
+            "alice/a.jpg",
+            "alice/a.txt",
+            long_entry.as_str(),
+            "alice/manifest.json"
+        ]
+    );
+    assert_eq!(entries[0].1, b"image a");
+    assert_eq!(entries[1].1, b"caption");
+    assert_eq!(entries[2].1, video);
+    let manifest: Value = serde_json::from_slice(&entries[3].1).expect("manifest");
+    assert_eq!(manifest["tweets"][1]["files"][0]["filename"], long);
+    assert!(!dir.path().join("alice.zip.part").exists());
+}
@@ -0,0 +1,72 @@
+//! The `page_size` setting, validated and sent with every timeline request.
+
+use rxd::api::PageSize;
+use rxd::task::ExtractOptions;
+use rxd::{Api, Config};
+use serde_json::json;
+use wiremock::matchers::{method, path, query_param_contains};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const USER_BY_SCREEN_NAME: &str = "/i/api/graphql/xc8f1g7BYqr6VTzTbvNlGw/UserByScreenName";
+const USER_MEDIA: &str = "/i/api/graphql/Le6KlbilFmSu-5VltFND-Q/UserMedia";
+
+fn load(page_size: &str) -> rxd::Result<Config> {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("config.toml");
+    std::fs::write(
+        &path,
+        format!("auth_token = \"token\"\nct0 = \"ct0\"\npage_size = {page_size}\n"),
+    )
+    .expect("write config");
+    Config::load(&path)
+}
+
+#[test]
+fn page_sizes_are_limited_to_the_api_maximum() {
+    let config = load("20").expect("valid");
+    assert_eq!(config.page_size.map(PageSize::get), Some(20));
+
+    for invalid in ["0", "101"] {
+        let error = load(invalid).expect_err(invalid);
+        assert!(matches!(error, rxd::Error::Config(_)), "{error:?}");
+        assert!(error.to_string().contains("between 1 and 100"), "{error}");
+    }
+}
+
+#[tokio::test]
+async fn every_page_requests_the_configured_size() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .and(query_param_contains("variables", "\"count\":20"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": { "user": { "result": { "timeline_v2": { "timeline": {
+                "instructions": []
+            }}}}}
+        })))
+        .expect(2)
+        .mount(&server)
+        .await;
+    let api = Api::with_base_url("token", "ct0", &server.uri())
+        .expect("api")
+        .with_page_size(PageSize::try_from(20).expect("page size"));
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": { "user": { "result": {
+                "__typename": "User",
+                "rest_id": "42",
+                "legacy": { "name": "Test User", "media_count": 1 }
+            }}}
+        })))
+        .mount(&server)
+        .await;
+    let user = api.user_by_screen_name("test_user").await.expect("lookup");
+
+    assert_eq!(api.page_size(), 20);
+    for cursor in [None, Some("page-2")] {
+        api.user_media(&user, cursor, &ExtractOptions::default())
+            .await
+            .expect("page");
+    }
+}
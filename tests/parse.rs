@@ -0,0 +1,382 @@
+//! Parser tests against scrubbed UserMedia responses in `tests/fixtures`.
+
+use chrono::{DateTime, FixedOffset};
+use rxd::api::parse_missing_features;
+use rxd::task::{Engagement, ExtractOptions, MediaPage, Tombstone, parse_user_media_response};
+use rxd::{MediaItem, MediaType};
+use serde_json::Value;
+
+fn parse_fixture(name: &str) -> (Vec<MediaItem>, Option<String>) {
+    parse_fixture_with(name, &ExtractOptions::default())
+}
+
+fn parse_fixture_with(name: &str, options: &ExtractOptions) -> (Vec<MediaItem>, Option<String>) {
+    let page = parse_page(name, options);
+    (page.items, page.next_cursor)
+}
+
+fn parse_page(name: &str, options: &ExtractOptions) -> MediaPage {
+    let path = format!("{}/tests/fixtures/{name}.json", env!("CARGO_MANIFEST_DIR"));
+    let content = std::fs::read_to_string(&path).expect("fixture exists");
+    let raw: Value = serde_json::from_str(&content).expect("fixture is valid JSON");
+    parse_user_media_response(&raw, options).expect("fixture parses")
+}
+
+fn timestamp(s: &str) -> DateTime<FixedOffset> {
+    DateTime::parse_from_rfc3339(s).expect("valid timestamp")
+}
+
+fn summarize(items: &[MediaItem]) -> Vec<(&str, &str, bool)> {
+    items
+        .iter()
+        .map(|i| {
+            (
+                i.tweet_id.as_str(),
+                i.download_url.as_str(),
+                matches!(i.media_type, MediaType::Video),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn first_page_entries_and_cursor() {
+    let (items, cursor) = parse_fixture("user_media_first_page");
+
+    assert_eq!(
+        summarize(&items),
+        vec![
+            (
+                "1900000000000000001",
+                "https://pbs.twimg.com/media/GmA1aaaaXAAbbb1.jpg",
+                false
+            ),
+            (
+                "1900000000000000001",
+                "https://pbs.twimg.com/media/GmA1aaaaXAAbbb2.jpg",
+                false
+            ),
+            // TweetWithVisibilityResults wraps the actual tweet
+            (
+                "1900000000000000002",
+                "https://pbs.twimg.com/media/GmA1aaaaXAAbbb3.jpg",
+                false
+            ),
+        ]
+    );
+    assert_eq!(items[0].timestamp, timestamp("2025-03-12T18:47:51Z"));
+    assert_eq!(items[2].timestamp, timestamp("2025-03-11T08:00:00Z"));
+    assert_eq!(
+        items[0].engagement,
+        Engagement {
+            favorite_count: Some(12),
+            retweet_count: Some(3),
+            reply_count: Some(4),
+            quote_count: Some(1),
+            view_count: Some(1000),
+        }
+    );
+    // Counts the response leaves out stay unknown
+    assert_eq!(items[2].engagement.reply_count, None);
+    assert_eq!(items[2].engagement.quote_count, None);
+    assert_eq!(items[2].engagement.view_count, Some(1000));
+    // Only tweets with several media are numbered
+    assert_eq!(
+        items.iter().map(|i| i.index).collect::<Vec<_>>(),
+        vec![Some(1), Some(2), None]
+    );
+    assert_eq!(
+        items[0].full_text.as_deref(),
+        Some("two photos https://t.co/abc")
+    );
+    assert_eq!(cursor.as_deref(), Some("DAABCgABGa-bottom-1"));
+}
+
+#[test]
+fn tombstones_are_reported() {
+    let page = parse_page("user_media_first_page", &ExtractOptions::default());
+
+    assert_eq!(
+        page.tombstones,
+        [Tombstone {
+            entry_id: "profile-grid-0-tweet-1900000000000000003".to_string(),
+            tweet_id: Some("1900000000000000003".to_string()),
+            reason: "This Post is unavailable.".to_string(),
+        }]
+    );
+
+    let raw = serde_json::json!({
+        "data": { "user": { "result": { "timeline_v2": { "timeline": {
+            "instructions": [{ "moduleItems": [
+                {
+                    "entryId": "profile-grid-0-tweet-1800000000000000009",
+                    "item": { "itemContent": { "tweet_results": { "result": {
+                        "__typename": "TweetUnavailable", "reason": "Suspended"
+                    }}}}
+                },
+                {
+                    "entryId": "profile-grid-0-unknown",
+                    "item": { "itemContent": { "tweet_results": { "result": {
+                        "__typename": "TweetTombstone"
+                    }}}}
+                }
+            ]}]
+        }}}}}
+    });
+    let page = parse_user_media_response(&raw, &ExtractOptions::default()).expect("parses");
+    let reasons: Vec<_> = page
+        .tombstones
+        .iter()
+        .map(|t| (t.tweet_id.as_deref(), t.reason.as_str()))
+        .collect();
+    assert_eq!(
+        reasons,
+        [
+            (Some("1800000000000000009"), "Suspended"),
+            (None, "tombstone")
+        ]
+    );
+}
+
+#[test]
+fn module_items_on_later_pages() {
+    let (items, cursor) = parse_fixture("user_media_module_items");
+
+    assert_eq!(
+        summarize(&items),
+        vec![(
+            "1800000000000000001",
+            "https://pbs.twimg.com/media/FxOld1.jpg",
+            false
+        )]
+    );
+    assert_eq!(items[0].timestamp, timestamp("2024-01-01T00:00:00Z"));
+    assert_eq!(cursor.as_deref(), Some("DAABCgABGa-bottom-2"));
+}
+
+#[test]
+fn media_listed_twice_is_kept_once() {
+    let (items, _) = parse_fixture("user_media_duplicate");
+
+    assert_eq!(
+        summarize(&items),
+        vec![(
+            "1800000000000000001",
+            "https://pbs.twimg.com/media/FxOld1.jpg",
+            false
+        )]
+    );
+}
+
+#[test]
+fn pinned_tweet_comes_first_and_once() {
+    let path = format!(
+        "{}/tests/fixtures/user_media_pinned.json",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let raw: Value =
+        serde_json::from_str(&std::fs::read_to_string(path).expect("fixture")).expect("json");
+    let items = parse_user_media_response(&raw, &ExtractOptions::default())
+        .expect("parse")
+        .items;
+
+    let urls: Vec<_> = items.iter().map(|i| (i.url.as_str(), i.pinned)).collect();
+    assert_eq!(
+        urls,
+        vec![
+            ("https://pbs.twimg.com/media/FxPin1.jpg", true),
+            ("https://pbs.twimg.com/media/GmA1aaaaXAAbbb1.jpg", false),
+            ("https://pbs.twimg.com/media/GmA1aaaaXAAbbb2.jpg", false),
+        ]
+    );
+}
+
+#[test]
+fn empty_timeline() {
+    let (items, cursor) = parse_fixture("user_media_empty");
+
+    assert!(items.is_empty());
+    assert_eq!(cursor, None);
+}
+
+#[test]
+fn missing_timeline_is_empty() {
+    let (items, cursor) = parse_fixture("user_media_no_timeline");
+
+    assert!(items.is_empty());
+    assert_eq!(cursor, None);
+}
+
+#[test]
+fn cursor_only_page() {
+    let (items, cursor) = parse_fixture("user_media_cursor_only");
+
+    assert!(items.is_empty());
+    assert_eq!(cursor.as_deref(), Some("DAABCgABGa-bottom-end"));
+}
+
+#[test]
+fn video_duration_and_resolution() {
+    let (items, _) = parse_fixture("user_media_video");
+
+    let details: Vec<_> = items
+        .iter()
+        .map(|i| (i.duration_ms, i.resolution))
+        .collect();
+    assert_eq!(
+        details,
+        vec![
+            // From the chosen variant's URL
+            (Some(15015), Some((1280, 720))),
+            // GIFs have no duration, their size is the original's
+            (None, Some((480, 480))),
+        ]
+    );
+}
+
+#[test]
+fn video_picks_highest_bitrate_mp4() {
+    let (items, cursor) = parse_fixture("user_media_video");
+
+    assert_eq!(
+        summarize(&items),
+        vec![
+            (
+                "1700000000000000001",
+                "https://video.twimg.com/ext_tw_video/1700000000000000201/pu/vid/1280x720/high.mp4?tag=12",
+                true
+            ),
+            // animated_gif variants have no bitrate
+            (
+                "1700000000000000002",
+                "https://video.twimg.com/tweet_video/GifVideo.mp4",
+                true
+            ),
+        ]
+    );
+    // Recorded under the thumbnail, which does not change with the variants
+    assert_eq!(
+        items[0].url,
+        "https://pbs.twimg.com/ext_tw_video_thumb/1700000000000000201/pu/img/thumb.jpg"
+    );
+    assert_eq!(items[0].timestamp, timestamp("2023-09-01T12:30:00Z"));
+    assert_eq!(cursor.as_deref(), Some("DAABCgABGa-bottom-video"));
+}
+
+#[test]
+fn video_bitrate_is_capped() {
+    let video_url = |max| {
+        let options = ExtractOptions::default().max_video_bitrate(Some(max));
+        let (items, _) = parse_fixture_with("user_media_video", &options);
+        assert!(items[0].url.ends_with("/thumb.jpg"));
+        items[0].download_url.clone()
+    };
+
+    assert!(video_url(1_000_000).ends_with("/640x360/mid.mp4?tag=12"));
+    assert!(video_url(832_000).ends_with("/640x360/mid.mp4?tag=12"));
+    // Nothing fits, the smallest variant comes closest
+    assert!(video_url(100_000).ends_with("/480x270/low.mp4?tag=12"));
+}
+
+#[test]
+fn card_videos_without_extended_entities() {
+    let (items, cursor) = parse_fixture("user_media_card");
+
+    // Link preview and external player cards have nothing to download
+    assert_eq!(
+        summarize(&items),
+        vec![
+            (
+                "1700000000000000011",
+                "https://video.twimg.com/amplify_video/1700000000000000401/vid/avc1/1280x720/high.mp4?tag=14",
+                true
+            ),
+            (
+                "1700000000000000012",
+                "https://video.twimg.com/amplify_video/1700000000000000402/vid/1280x720/stream.mp4",
+                true
+            ),
+        ]
+    );
+    assert_eq!(items[1].timestamp, timestamp("2023-09-01T12:30:00Z"));
+    assert_eq!(cursor.as_deref(), Some("DAABCgABGa-bottom-card"));
+}
+
+#[test]
+fn note_inline_media_and_full_text() {
+    let (items, cursor) = parse_fixture("user_media_note");
+
+    // The third inline media ID is not in the payload
+    assert_eq!(
+        summarize(&items),
+        vec![
+            (
+                "1700000000000000021",
+                "https://pbs.twimg.com/media/NoteImage1.jpg",
+                false
+            ),
+            (
+                "1700000000000000021",
+                "https://pbs.twimg.com/media/NoteImage2.jpg",
+                false
+            ),
+        ]
+    );
+    let full_text = items[1].full_text.as_deref().expect("note text");
+    assert!(full_text.ends_with("The end."));
+    assert_eq!(items[0].full_text, items[1].full_text);
+    assert_eq!(items[1].timestamp, timestamp("2023-09-02T08:15:00Z"));
+    assert_eq!(cursor.as_deref(), Some("DAABCgABGa-bottom-note"));
+}
+
+#[test]
+fn retweets_are_marked() {
+    let (items, _) = parse_fixture("user_media_retweet");
+
+    assert_eq!(
+        items
+            .iter()
+            .map(|i| (i.tweet_id.as_str(), i.is_retweet))
+            .collect::<Vec<_>>(),
+        vec![
+            ("1700000000000000031", false),
+            ("1700000000000000032", true)
+        ]
+    );
+    assert!(items.iter().all(|i| i.index.is_none()));
+}
+
+#[test]
+fn missing_instructions_is_an_error() {
+    let raw: Value = serde_json::json!({ "data": { "user": { "result": {} } } });
+
+    assert!(parse_user_media_response(&raw, &ExtractOptions::default()).is_err());
+}
+
+#[test]
+fn missing_features_from_error_body() {
+    let path = format!(
+        "{}/tests/fixtures/features_cannot_be_null.json",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let body = std::fs::read_to_string(&path).expect("fixture exists");
+
+    assert_eq!(
+        parse_missing_features(&body),
+        vec![
+            ("rweb_video_screen_enabled".to_string(), false),
+            (
+                "responsive_web_grok_analysis_button_from_backend".to_string(),
+                false
+            ),
+        ]
+    );
+}
+
+#[test]
+fn unrelated_errors_have_no_missing_features() {
+    let body = r#"{"errors":[{"message":"Rate limit exceeded","code":88}]}"#;
+
+    assert!(parse_missing_features(body).is_empty());
+    assert!(parse_missing_features("not json").is_empty());
+}
@@ -0,0 +1,157 @@
+//! Perceptual hashes and near-duplicate clusters.
+
+use std::fs;
+
+#[cfg(unix)]
+use common::fake_ffmpeg;
+use common::media;
+use rxd::db::{self, PhashedMedia};
+use rxd::error::Error;
+use rxd::phash::{self, PhashOptions};
+
+mod common;
+
+fn phashed(media_url: &str, phash: u64) -> PhashedMedia {
+    PhashedMedia {
+        media_url: media_url.to_string(),
+        tweet_id: "1".to_string(),
+        screen_name: "alice".to_string(),
+        filename: format!("{media_url}.jpg"),
+        file_size: None,
+        phash,
+    }
+}
+
+fn urls(cluster: &phash::Cluster) -> Vec<&str> {
+    cluster.media.iter().map(|m| m.media_url.as_str()).collect()
+}
+
+#[test]
+fn hashes_brightness_gradients() {
+    // Brighter to the right everywhere, then darker to the right
+    let rising: [u8; 72] = std::array::from_fn(|i| (i % 9) as u8 * 10);
+    let falling: [u8; 72] = std::array::from_fn(|i| 100 - (i % 9) as u8 * 10);
+    assert_eq!(phash::dhash(&rising), 0);
+    assert_eq!(phash::dhash(&falling), u64::MAX);
+    assert_eq!(phash::distance(0b1011, 0b0110), 3);
+}
+
+#[test]
+fn clusters_chains_of_close_hashes() {
+    let media = vec![
+        phashed("a", 0),
+        phashed("lonely", u64::MAX),
+        phashed("b", 0b111),
+        phashed("c", 0b111_111),
+        phashed("d", 0xFF00_0000_0000_0000),
+        phashed("e", 0xFF00_0000_0000_0001),
+    ];
+
+    let clusters = phash::cluster(media, 3);
+
+    assert_eq!(clusters.len(), 2);
+    // b is 3 bits from a and c is 3 bits from b, so c joins 6 bits from a
+    assert_eq!(urls(&clusters[0]), ["a", "b", "c"]);
+    assert_eq!(clusters[0].max_distance, 6);
+    assert_eq!(urls(&clusters[1]), ["d", "e"]);
+    assert_eq!(clusters[1].max_distance, 1);
+    assert!(phash::cluster(vec![phashed("a", 0), phashed("b", 0b1111)], 3).is_empty());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn hashes_new_images_and_finds_near_duplicates() {
+    let tmp = tempfile::tempdir().expect("tmp");
+    let folder = tmp.path().join("alice");
+    fs::create_dir_all(&folder).expect("folder");
+    let pool = db::init_memory_db().await.expect("db");
+    let rising: [u8; 72] = std::array::from_fn(|i| (i % 9) as u8 * 10);
+    let mut reposted = rising;
+    reposted[1] = 50;
+    let falling: [u8; 72] = std::array::from_fn(|i| 100 - (i % 9) as u8 * 10);
+    for (tweet_id, filename, pixels) in [
+        ("1", "art.jpg", &rising),
+        ("2", "art-small.png", &reposted),
+        ("3", "other.jpg", &falling),
+        ("4", "corrupt.jpg", &rising),
+        ("5", "clip.mp4", &rising),
+    ] {
+        media(filename)
+            .tweet(tweet_id)
+            .file(&folder, b"image")
+            .record(&pool)
+            .await;
+        // Printed by the fake ffmpeg
+        fs::write(folder.join(format!("{filename}.gray")), pixels).expect("pixels");
+    }
+    let options = PhashOptions::default()
+        .concurrency(2)
+        .ffmpeg(fake_ffmpeg(tmp.path(), true));
+    let run = || {
+        phash::hash_images(
+            &pool,
+            Some("alice"),
+            |_| folder.clone(),
+            &options,
+            |_, _| {},
+        )
+    };
+
+    let summary = run().await.expect("hash");
+
+    assert_eq!(summary.hashed, 3);
+    assert_eq!(summary.failed.len(), 1);
+    assert_eq!(summary.failed[0].0, folder.join("corrupt.jpg"));
+    let clusters = phash::near_duplicates(&pool, None, 6)
+        .await
+        .expect("clusters");
+    assert_eq!(clusters.len(), 1);
+    assert_eq!(
+        urls(&clusters[0]),
+        [
+            "https://pbs.twimg.com/media/art.jpg",
+            "https://pbs.twimg.com/media/art-small.png"
+        ]
+    );
+    assert_eq!(clusters[0].max_distance, 1);
+
+    // Only the image that failed is tried again
+    let again = run().await.expect("hash");
+    assert_eq!(again.hashed, 0);
+    assert_eq!(again.failed.len(), 1);
+
+    // A new file for the same media needs a new hash
+    db::update_hash(
+        &pool,
+        "https://pbs.twimg.com/media/art.jpg",
+        "changed",
+        rxd::hash::HashAlgorithm::Sha256,
+    )
+    .await
+    .expect("update");
+    let changed = run().await.expect("hash");
+    assert_eq!(changed.hashed, 1);
+}
+
+#[tokio::test]
+async fn fails_without_ffmpeg() {
+    let tmp = tempfile::tempdir().expect("tmp");
+    let pool = db::init_memory_db().await.expect("db");
+    media("a.jpg")
+        .file(tmp.path(), b"image")
+        .record(&pool)
+        .await;
+    fs::write(tmp.path().join("a.jpg.gray"), [0; 72]).expect("pixels");
+    let options = PhashOptions::default().ffmpeg(tmp.path().join("no-such-ffmpeg"));
+
+    let result = phash::hash_images(
+        &pool,
+        None,
+        |_| tmp.path().to_path_buf(),
+        &options,
+        |_, _| {},
+    )
+    .await;
+
+    assert!(matches!(result, Err(Error::Config(_))), "{result:?}");
+}
@@ -0,0 +1,41 @@
+//! `Retry-After` headers of media hosts pushing back.
+
+use std::time::{Duration, SystemTime};
+
+use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+use rxd::pushback::retry_after;
+
+fn headers(value: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(RETRY_AFTER, HeaderValue::from_str(value).expect("header"));
+    headers
+}
+
+/// 2015-10-21 07:28:00 UTC
+fn now() -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(1_445_412_480)
+}
+
+#[test]
+fn delta_seconds() {
+    assert_eq!(
+        retry_after(&headers("120"), now()),
+        Some(Duration::from_secs(120))
+    );
+}
+
+#[test]
+fn http_dates_count_from_now() {
+    let later = headers("Wed, 21 Oct 2015 07:30:00 GMT");
+    assert_eq!(retry_after(&later, now()), Some(Duration::from_secs(120)));
+
+    let earlier = headers("Wed, 21 Oct 2015 07:00:00 GMT");
+    assert_eq!(retry_after(&earlier, now()), Some(Duration::ZERO));
+}
+
+#[test]
+fn missing_or_unreadable_headers_ask_for_nothing() {
+    assert_eq!(retry_after(&HeaderMap::new(), now()), None);
+    assert_eq!(retry_after(&headers("soon"), now()), None);
+    assert_eq!(retry_after(&headers("-5"), now()), None);
+}
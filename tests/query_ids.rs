@@ -0,0 +1,89 @@
+//! Query ID discovery against a mock web client.
+
+use std::collections::HashMap;
+
+use rxd::query_ids::{self, QueryIds};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const BUNDLE: &str = r#"e.exports={queryId:"NEWuserByName",operationName:"UserByScreenName",operationType:"query"}},
+function(e){e.exports={queryId:"NEWuserMedia",operationName:"UserMedia",operationType:"query"}}"#;
+
+async fn mount_web_client(server: &MockServer) {
+    let html = format!(
+        r#"<html><script src="{}/responsive-web/client-web/main.1a2b3c4d.js" nonce=""></script></html>"#,
+        server.uri()
+    );
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(html))
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/responsive-web/client-web/main.1a2b3c4d.js"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(BUNDLE))
+        .expect(1)
+        .mount(server)
+        .await;
+}
+
+fn discovered() -> QueryIds {
+    QueryIds::from_operations(&HashMap::from([
+        ("UserByScreenName".to_string(), "NEWuserByName".to_string()),
+        ("UserMedia".to_string(), "NEWuserMedia".to_string()),
+    ]))
+}
+
+#[test]
+fn parses_operations_from_bundle() {
+    let operations = query_ids::parse_bundle(BUNDLE);
+    assert_eq!(operations.len(), 2);
+    assert_eq!(operations["UserMedia"], "NEWuserMedia");
+    assert_eq!(QueryIds::from_operations(&operations), discovered());
+}
+
+#[test]
+fn missing_operations_keep_builtin_ids() {
+    let ids = QueryIds::from_operations(&HashMap::from([(
+        "UserMedia".to_string(),
+        "NEWuserMedia".to_string(),
+    )]));
+    assert_eq!(ids.user_media, "NEWuserMedia");
+    assert_eq!(
+        ids.user_by_screen_name,
+        QueryIds::default().user_by_screen_name
+    );
+}
+
+#[tokio::test]
+async fn discovers_and_caches() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    let cache = dir.path().join("query_ids.json");
+    mount_web_client(&server).await;
+    let client = reqwest::Client::new();
+
+    let ids = query_ids::resolve(&client, &server.uri(), &cache, false).await;
+    assert_eq!(ids, discovered());
+    assert!(cache.exists());
+
+    // Served from the cache, the bundle mock expects a single request
+    let ids = query_ids::resolve(&client, &server.uri(), &cache, false).await;
+    assert_eq!(ids, discovered());
+}
+
+#[tokio::test]
+async fn falls_back_to_builtin_ids() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    let client = reqwest::Client::new();
+
+    let ids = query_ids::resolve(
+        &client,
+        &server.uri(),
+        &dir.path().join("query_ids.json"),
+        true,
+    )
+    .await;
+    assert_eq!(ids, QueryIds::default());
+}
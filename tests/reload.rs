@@ -0,0 +1,73 @@
+use std::path::Path;
+
+use rxd::reload::LoadedConfig;
+
+fn load(dir: &Path, contents: &str) -> rxd::Result<LoadedConfig> {
+    let path = dir.join("config.toml");
+    std::fs::write(&path, contents).expect("write config");
+    LoadedConfig::load(&path)
+}
+
+#[test]
+fn changes_list_tasks_and_settings() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let old = load(
+        dir.path(),
+        r#"
+auth_token = "old-token"
+ct0 = "ct0"
+concurrent_downloads = 4
+
+[[tasks]]
+screen_name = "bar"
+
+[[tasks]]
+screen_name = "Baz"
+"#,
+    )
+    .expect("old config");
+    let new = load(
+        dir.path(),
+        r#"
+auth_token = "new-token"
+ct0 = "ct0"
+concurrent_downloads = 8
+post_download_hook = "echo {path}"
+
+[[tasks]]
+screen_name = "baz"
+min_favorites = 10
+
+[[tasks]]
+screen_name = "foo"
+"#,
+    )
+    .expect("new config");
+
+    assert_eq!(new.config.tasks.len(), 2);
+    assert_eq!(
+        old.changes(&new),
+        [
+            "added @foo",
+            "removed @bar",
+            "@Baz min_favorites unset→10",
+            "auth_token changed, restart rxd to apply it",
+            "concurrent_downloads 4→8",
+            "post_download_hook unset→\"echo {path}\", restart rxd to apply it",
+        ]
+    );
+    assert!(new.changes(&new).is_empty());
+}
+
+#[test]
+fn invalid_edits_are_rejected() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    for contents in [
+        "concurrent_downloads = \"many\"",
+        "[[tasks]]\nscreen_name = \"foo\"\nfilename_template = \"{date\"",
+        "[[tasks]\n",
+    ] {
+        let error = load(dir.path(), contents).expect_err(contents);
+        assert!(matches!(error, rxd::Error::Config(_)), "{error:?}");
+    }
+}
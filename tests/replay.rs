@@ -0,0 +1,142 @@
+//! Replays of archived responses, which download media without any API
+//! requests.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use rxd::archive::{ArchivedResponses, RAW_DIR, ResponseArchive};
+use rxd::{Api, Task};
+use serde_json::{Value, json};
+use wiremock::matchers::{method, path, path_regex};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn lookup() -> Value {
+    json!({
+        "data": { "user": { "result": {
+            "__typename": "User",
+            "rest_id": "42",
+            "legacy": { "name": "Test User", "screen_name": "test_user", "media_count": 2 }
+        }}}
+    })
+}
+
+fn media_page(tweet_id: &str, url: &str) -> Value {
+    json!({
+        "data": { "user": { "result": { "timeline_v2": { "timeline": {
+            "instructions": [{ "type": "TimelineAddEntries", "entries": [{
+                "entryId": "profile-grid-0",
+                "content": { "items": [{
+                    "item": { "itemContent": { "tweet_results": { "result": {
+                        "__typename": "Tweet",
+                        "rest_id": tweet_id,
+                        "legacy": {
+                            "created_at": "Wed Mar 12 12:00:00 +0000 2025",
+                            "extended_entities": { "media": [
+                                { "type": "photo", "media_url_https": url }
+                            ]}
+                        }
+                    }}}}
+                }]}
+            }]}]
+        }}}}}
+    })
+}
+
+async fn mount_media(server: &MockServer, media_id: &str) -> String {
+    let media_path = format!("/media/{media_id}.jpg");
+    Mock::given(method("GET"))
+        .and(path(media_path.as_str()))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(media_id.as_bytes()))
+        .mount(server)
+        .await;
+    format!("{}{}", server.uri(), media_path)
+}
+
+/// Archive of two runs in `dir/_raw`, the second listing the first's media
+/// again, and an unreadable page
+async fn archive_runs(server: &MockServer, dir: &Path, archive: ResponseArchive) {
+    let first = mount_media(server, "AAA").await;
+    let second = mount_media(server, "BBB").await;
+    let pages = [
+        media_page("1", &first),
+        media_page("1", &first),
+        media_page("2", &second),
+    ];
+    archive
+        .write(dir, "UserByScreenName", None, &lookup().to_string())
+        .await
+        .expect("lookup");
+    for (i, page) in pages.iter().enumerate() {
+        archive
+            .write(dir, "UserMedia", Some(i as u32 + 1), &page.to_string())
+            .await
+            .expect("page");
+    }
+    archive
+        .write(dir, "UserMedia", Some(4), "{\"data\": ")
+        .await
+        .expect("broken page");
+}
+
+async fn replay(server: &MockServer, dir: &Path) -> rxd::events::Totals {
+    // Every GraphQL request would fail, the mock server only serves media
+    Mock::given(path_regex("^/i/api/"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(server)
+        .await;
+    let responses = ArchivedResponses::scan(&dir.join(RAW_DIR))
+        .await
+        .expect("scan");
+    assert_eq!(
+        responses.screen_name().await.expect("lookup").as_deref(),
+        Some("test_user")
+    );
+    let db = rxd::db::init_memory_db().await.expect("db");
+    let task = Task::builder()
+        .api(Api::with_base_url("token", "ct0", &server.uri()).expect("api"))
+        .screen_name("test_user")
+        .save_path(dir.join("media"))
+        .replay(responses)
+        .db(db)
+        .build()
+        .await
+        .expect("task");
+    Arc::new(task).execute().await.expect("execute")
+}
+
+#[tokio::test]
+async fn archived_pages_are_downloaded_without_api_requests() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    archive_runs(&server, dir.path(), ResponseArchive::default()).await;
+
+    let totals = replay(&server, dir.path()).await;
+
+    assert_eq!(totals.fetched, 2);
+    assert_eq!(totals.downloaded, 2);
+    assert_eq!(totals.failed, 0);
+}
+
+#[tokio::test]
+async fn gzipped_archives_are_replayed() {
+    if std::process::Command::new("gzip")
+        .arg("--version")
+        .output()
+        .is_err()
+    {
+        return;
+    }
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    archive_runs(
+        &server,
+        dir.path(),
+        ResponseArchive::default().compress(true),
+    )
+    .await;
+
+    let totals = replay(&server, dir.path()).await;
+
+    assert_eq!(totals.downloaded, 2);
+}
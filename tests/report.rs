@@ -0,0 +1,129 @@
+//! JSON report of a download run.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{Local, TimeZone};
+use rxd::events::{Event, SkipReason};
+use rxd::report::{REPORT_VERSION, Report};
+use rxd::summary::{ItemLog, RunSummary, TaskSummary};
+
+fn events() -> Vec<Event> {
+    vec![
+        Event::TaskStarted {
+            screen_name: "alice".into(),
+        },
+        Event::ItemDownloaded {
+            screen_name: "alice".into(),
+            tweet_id: "1".into(),
+            url: "https://pbs.twimg.com/media/A.jpg".into(),
+            path: PathBuf::from("alice/A.jpg"),
+            bytes: 1234,
+        },
+        Event::ItemSkipped {
+            screen_name: "alice".into(),
+            tweet_id: "2".into(),
+            url: "https://pbs.twimg.com/media/B.jpg".into(),
+            reason: SkipReason::Exists,
+        },
+        Event::ItemFailed {
+            screen_name: "alice".into(),
+            tweet_id: "3".into(),
+            url: "https://pbs.twimg.com/media/C.jpg".into(),
+            error: "download failed: 500 Internal Server Error".into(),
+        },
+    ]
+}
+
+fn summary(items: ItemLog) -> RunSummary {
+    RunSummary {
+        tasks: vec![TaskSummary {
+            screen_name: "alice".into(),
+            totals: Default::default(),
+            elapsed: Duration::from_secs(3),
+            error: None,
+            items,
+        }],
+        elapsed: Duration::from_secs(3),
+        overwrite: false,
+        full_scan: false,
+    }
+}
+
+#[tokio::test]
+async fn collected_items_are_forwarded_and_reported() {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let (forward, mut forwarded) = tokio::sync::mpsc::unbounded_channel();
+    let log = tokio::spawn(ItemLog::collect(rx, Some(forward)));
+    for event in events() {
+        tx.send(event).expect("send");
+    }
+    drop(tx);
+    let items = log.await.expect("collect");
+    let mut count = 0;
+    while forwarded.recv().await.is_some() {
+        count += 1;
+    }
+    assert_eq!(count, 4);
+
+    let started = Local
+        .with_ymd_and_hms(2025, 3, 12, 10, 0, 0)
+        .single()
+        .expect("time");
+    let report = Report::new(
+        &summary(items),
+        started,
+        started + chrono::Duration::seconds(3),
+        "digest".into(),
+    );
+    let json = serde_json::to_value(&report).expect("json");
+    assert_eq!(json["version"], REPORT_VERSION);
+    assert_eq!(json["config_digest"], "digest");
+    let task = &json["tasks"][0];
+    assert_eq!(task["screen_name"], "alice");
+    assert_eq!(
+        task["downloaded"],
+        serde_json::json!([{
+            "path": "alice/A.jpg",
+            "tweet_id": "1",
+            "url": "https://pbs.twimg.com/media/A.jpg",
+            "bytes": 1234,
+        }])
+    );
+    assert_eq!(task["skipped"][0]["reason"], "exists");
+    assert_eq!(
+        task["failed"][0]["error"],
+        "download failed: 500 Internal Server Error"
+    );
+}
+
+#[test]
+fn existing_reports_are_overwritten_or_kept() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("reports").join("run.json");
+    let now = Local::now();
+    let report = Report::new(&summary(ItemLog::default()), now, now, "digest".into());
+
+    assert_eq!(report.write(&path, false).expect("write"), path);
+    assert_eq!(report.write(&path, false).expect("overwrite"), path);
+    let kept = report.write(&path, true).expect("keep");
+    assert_ne!(kept, path);
+    assert_eq!(kept.parent(), path.parent());
+    let name = kept
+        .file_name()
+        .expect("name")
+        .to_string_lossy()
+        .into_owned();
+    assert!(
+        name.starts_with("run-") && name.ends_with(".json"),
+        "{name}"
+    );
+
+    let files: Vec<_> = std::fs::read_dir(dir.path().join("reports"))
+        .expect("read dir")
+        .map(|e| e.expect("entry").file_name())
+        .collect();
+    assert_eq!(files.len(), 2, "{files:?}");
+    let read: Report = serde_json::from_slice(&std::fs::read(&kept).expect("read")).expect("parse");
+    assert_eq!(read.tasks[0].screen_name, "alice");
+}
@@ -0,0 +1,108 @@
+//! Rotation of GraphQL requests between accounts.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rxd::Api;
+use rxd::api::TlsOptions;
+use rxd::rotation::Credentials;
+use serde_json::{Value, json};
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const USER_BY_SCREEN_NAME: &str = "/i/api/graphql/xc8f1g7BYqr6VTzTbvNlGw/UserByScreenName";
+const MAIN_COOKIE: &str = "auth_token=main; ct0=main;";
+const ALT_COOKIE: &str = "auth_token=alt; ct0=alt;";
+
+fn user_response() -> Value {
+    json!({
+        "data": { "user": { "result": {
+            "__typename": "User",
+            "rest_id": "42",
+            "legacy": { "name": "Test User" }
+        }}}
+    })
+}
+
+/// Reset of a window an hour from now
+fn reset_later() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time")
+        .as_secs();
+    (now + 3600).to_string()
+}
+
+fn rotating_api(server: &MockServer) -> Api {
+    let alt = Credentials {
+        name: Some("alt".into()),
+        auth_token: "alt".into(),
+        ct0: "alt".into(),
+    };
+    Api::with_base_url("main", "main", &server.uri())
+        .expect("api")
+        .with_rotation(&[alt], &TlsOptions::default())
+        .expect("rotation")
+}
+
+#[tokio::test]
+async fn rate_limited_accounts_are_swapped_without_waiting() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .and(header("cookie", MAIN_COOKIE))
+        .respond_with(ResponseTemplate::new(429).insert_header("x-rate-limit-reset", reset_later()))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .and(header("cookie", ALT_COOKIE))
+        .respond_with(ResponseTemplate::new(200).set_body_json(user_response()))
+        .expect(2)
+        .mount(&server)
+        .await;
+    let api = rotating_api(&server);
+
+    let lookups = async {
+        api.user_by_screen_name("test_user").await.expect("first");
+        // The limited account is not tried again within its window
+        api.user_by_screen_name("test_user").await.expect("second");
+    };
+    tokio::time::timeout(Duration::from_secs(5), lookups)
+        .await
+        .expect("no rate limit wait");
+}
+
+#[tokio::test]
+async fn requests_go_to_the_account_with_the_most_left() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .and(header("cookie", MAIN_COOKIE))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(user_response())
+                .insert_header("x-rate-limit-remaining", "0")
+                .insert_header("x-rate-limit-reset", reset_later()),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .and(header("cookie", ALT_COOKIE))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(user_response())
+                .insert_header("x-rate-limit-remaining", "5")
+                .insert_header("x-rate-limit-reset", reset_later()),
+        )
+        .expect(2)
+        .mount(&server)
+        .await;
+    let api = rotating_api(&server);
+
+    for _ in 0..3 {
+        api.user_by_screen_name("test_user").await.expect("lookup");
+    }
+}
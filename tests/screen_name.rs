@@ -0,0 +1,74 @@
+//! Screen names given as handles or profile URLs.
+
+use rxd::Config;
+use rxd::screen_name::normalize;
+
+#[test]
+fn messy_inputs_become_handles() {
+    let cases = [
+        ("nasa", "nasa"),
+        ("  NASA  ", "NASA"),
+        ("@nasa", "nasa"),
+        ("https://x.com/nasa", "nasa"),
+        ("https://x.com/nasa/", "nasa"),
+        ("http://twitter.com/nasa?s=21", "nasa"),
+        ("twitter.com/nasa?s=21&t=AbCdEf", "nasa"),
+        ("HTTPS://X.COM/Nasa_Moon", "Nasa_Moon"),
+        ("https://mobile.twitter.com/nasa", "nasa"),
+        ("https://www.twitter.com/nasa#top", "nasa"),
+        ("x.com/nasa/media", "nasa"),
+        ("https://x.com/nasa/status/1899882021231431884?s=46", "nasa"),
+        ("https://x.com/@nasa", "nasa"),
+        ("a", "a"),
+        ("fifteen_chars_1", "fifteen_chars_1"),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(normalize(input).expect(input), expected, "{input}");
+    }
+}
+
+#[test]
+fn invalid_inputs_are_refused_as_given() {
+    for input in [
+        "",
+        "@",
+        "https://x.com/",
+        "https://x.com/home",
+        "https://x.com/i/lists/123",
+        "https://example.com/nasa",
+        "nasa/media",
+        "sixteen_chars_12",
+        "nasa-moon",
+        "ナサ",
+    ] {
+        let error = normalize(input).expect_err(input);
+        assert!(matches!(error, rxd::Error::InvalidScreenName(ref given) if given == input));
+    }
+}
+
+#[test]
+fn task_screen_names_are_normalized_when_loading() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("config.toml");
+    let write = |screen_name: &str| {
+        std::fs::write(
+            &path,
+            format!(
+                "auth_token = \"token\"\nct0 = \"ct0\"\n\n[[tasks]]\nscreen_name = \"{screen_name}\"\n"
+            ),
+        )
+        .expect("write config");
+    };
+
+    write("https://x.com/NASA?s=21");
+    let config = Config::load(&path).expect("config");
+    assert_eq!(config.tasks[0].screen_name, "NASA");
+
+    write("https://x.com/not a handle");
+    let error = Config::load(&path).expect_err("invalid screen name");
+    assert!(matches!(error, rxd::Error::Config(_)));
+    assert!(
+        error.to_string().contains("https://x.com/not a handle"),
+        "{error}"
+    );
+}
@@ -0,0 +1,36 @@
+//! Caption files written next to downloaded media.
+
+use std::path::Path;
+
+use rxd::sidecar::{TextSidecars, decode_entities};
+
+#[test]
+fn decodes_entities_and_keeps_unknown_ones() {
+    assert_eq!(
+        decode_entities("a &amp; b &lt;&gt; &quot;c&#39; &#233;"),
+        "a & b <> \"c' é"
+    );
+    assert_eq!(
+        decode_entities("AT&T &unknown; & &amp"),
+        "AT&T &unknown; & &amp"
+    );
+}
+
+#[test]
+fn per_tweet_captions_are_shared_by_its_files() {
+    let sidecars = TextSidecars {
+        per_tweet: true,
+        permalink: false,
+    };
+    let media = Path::new("media/2025-03-12-1-01-AAA.jpg");
+    assert_eq!(
+        sidecars.path(media, "1"),
+        Path::new("media/1.txt").to_path_buf()
+    );
+    assert_eq!(
+        sidecars.caption(Some("  hi  "), "alice", "1").as_deref(),
+        Some("hi\n")
+    );
+    assert_eq!(sidecars.caption(Some(" \n"), "alice", "1"), None);
+    assert_eq!(sidecars.caption(None, "alice", "1"), None);
+}
@@ -0,0 +1,98 @@
+//! Statistics of the archive, with sizes from the database or the disk.
+
+use common::{folder, media};
+use rxd::db;
+use rxd::stats::{self, StatsOptions};
+
+mod common;
+
+#[tokio::test]
+async fn detailed_stats_by_month_type_and_size() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let pool = db::init_memory_db().await.expect("db");
+    media("a.jpg")
+        .time("2025-01-05 10:00:00")
+        .size(100)
+        .record(&pool)
+        .await;
+    media("b.jpg")
+        .time("2025-01-05 10:00:00")
+        .size(200)
+        .record(&pool)
+        .await;
+    media("c.mp4")
+        .tweet("2")
+        .time("2025-03-20 10:00:00")
+        .size(5000)
+        .record(&pool)
+        .await;
+    media("d.jpg")
+        .user("bob")
+        .tweet("3")
+        .time("2025-03-21 10:00:00")
+        .size(50)
+        .record(&pool)
+        .await;
+
+    let options = StatsOptions::default().detailed(true).top(2);
+    let stats = stats::collect(&pool, Some("alice"), folder(dir.path()), &options)
+        .await
+        .expect("stats");
+
+    assert_eq!(stats.accounts.len(), 1);
+    assert_eq!(stats.accounts[0].screen_name, "alice");
+    assert_eq!(stats.accounts[0].tweets, 2);
+    assert_eq!(stats.accounts[0].files, 3);
+    assert_eq!(stats.accounts[0].bytes, 5300);
+    let detail = stats.detail.expect("detail");
+    // February had nothing and is left out
+    let months: Vec<_> = detail
+        .months
+        .iter()
+        .map(|m| (m.key.as_str(), m.tweets, m.files, m.bytes))
+        .collect();
+    assert_eq!(months, [("2025-01", 1, 2, 300), ("2025-03", 1, 1, 5000)]);
+    let types: Vec<_> = detail
+        .media_types
+        .iter()
+        .map(|t| (t.key.as_str(), t.files, t.bytes))
+        .collect();
+    assert_eq!(types, [("image", 2, 300), ("video", 1, 5000)]);
+    let largest: Vec<_> = detail
+        .largest
+        .iter()
+        .map(|f| (f.tweet_id.as_str(), f.filename.as_str(), f.bytes))
+        .collect();
+    assert_eq!(largest, [("2", "c.mp4", 5000), ("1", "b.jpg", 200)]);
+}
+
+#[tokio::test]
+async fn missing_sizes_are_read_from_disk_once() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let pool = db::init_memory_db().await.expect("db");
+    std::fs::create_dir(dir.path().join("alice")).expect("mkdir");
+    media("a.jpg")
+        .time("2025-01-05 10:00:00")
+        .record(&pool)
+        .await;
+    std::fs::write(dir.path().join("alice/a.jpg"), [0; 42]).expect("write");
+    media("gone.jpg")
+        .tweet("2")
+        .time("2025-01-06 10:00:00")
+        .record(&pool)
+        .await;
+
+    let first = stats::collect(&pool, None, folder(dir.path()), &StatsOptions::default())
+        .await
+        .expect("stats");
+    assert_eq!(first.sized_from_disk, 1);
+    assert_eq!(first.unknown_size, 1);
+    assert_eq!(first.accounts[0].bytes, 42);
+    assert!(first.detail.is_none());
+
+    let second = stats::collect(&pool, None, folder(dir.path()), &StatsOptions::default())
+        .await
+        .expect("stats");
+    assert_eq!(second.sized_from_disk, 0);
+    assert_eq!(second.accounts[0].bytes, 42);
+}
@@ -0,0 +1,189 @@
+//! End-of-run report formatting and notifications.
+
+use std::time::{Duration, Instant};
+
+use rxd::events::Totals;
+use rxd::notify::{self, NotificationConfig, NotifyOn, WebhookFormat};
+use rxd::summary::{self, RunSummary, TaskSummary};
+use wiremock::matchers::method;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn task(screen_name: &str, downloaded: usize, skipped: usize, bytes: u64) -> TaskSummary {
+    TaskSummary {
+        screen_name: screen_name.to_string(),
+        totals: Totals {
+            downloaded,
+            skipped,
+            bytes,
+            ..Default::default()
+        },
+        elapsed: Duration::from_secs(60),
+        error: None,
+        items: Default::default(),
+    }
+}
+
+#[test]
+fn transfer_line_uses_binary_units() {
+    let summary = RunSummary {
+        tasks: vec![task("alice", 10, 3, 3 << 30), task("bob", 2, 40, 512 << 20)],
+        elapsed: Duration::from_secs(42 * 60 + 10),
+        overwrite: false,
+        full_scan: false,
+    };
+
+    assert_eq!(
+        summary.transfer(),
+        "3.50 GiB downloaded in 42m 10s (avg 1.42 MiB/s)"
+    );
+    assert!(
+        summary
+            .to_table()
+            .ends_with(&format!("\n{}\n", summary.transfer()))
+    );
+}
+
+#[test]
+fn table_shows_overwrite_mode() {
+    let mut summary = RunSummary {
+        tasks: vec![task("alice", 3, 0, 0)],
+        elapsed: Duration::from_secs(1),
+        overwrite: false,
+        full_scan: false,
+    };
+    summary.tasks[0].totals.replaced = 2;
+    assert!(!summary.to_table().contains("overwrite mode"));
+
+    summary.overwrite = true;
+    assert!(
+        summary
+            .to_table()
+            .ends_with("overwrite mode: 2 files on disk replaced\n")
+    );
+}
+
+#[test]
+fn table_notes_a_full_scan() {
+    let mut summary = RunSummary {
+        tasks: vec![task("alice", 3, 0, 0)],
+        elapsed: Duration::from_secs(1),
+        overwrite: false,
+        full_scan: false,
+    };
+    assert!(!summary.to_table().contains("full scan"));
+
+    summary.full_scan = true;
+    assert!(
+        summary
+            .to_table()
+            .ends_with("full scan: every timeline paged to the end\n")
+    );
+}
+
+#[test]
+fn byte_totals_saturate() {
+    let summary = RunSummary {
+        tasks: vec![task("alice", 1, 0, u64::MAX), task("bob", 1, 0, 1 << 40)],
+        elapsed: Duration::from_secs(1),
+        overwrite: false,
+        full_scan: false,
+    };
+
+    assert_eq!(summary.totals().bytes, u64::MAX);
+}
+
+#[test]
+fn compact_durations() {
+    assert_eq!(
+        summary::compact_duration(Duration::from_millis(8_900)),
+        "8s"
+    );
+    assert_eq!(summary::compact_duration(Duration::from_secs(61)), "1m 01s");
+    assert_eq!(
+        summary::compact_duration(Duration::from_secs(3720)),
+        "1h 02m"
+    );
+    assert_eq!(summary::speed(1 << 20, Duration::ZERO), "0 B/s");
+}
+
+#[cfg(feature = "desktop-notify")]
+#[test]
+fn desktop_notification_stands_out_on_failures() {
+    use rxd::notify::desktop::DesktopNotification;
+
+    let mut summary = RunSummary {
+        tasks: vec![
+            task("alice", 400, 3, 0),
+            task("bob", 12, 0, 0),
+            task("carol", 0, 0, 0),
+        ],
+        elapsed: Duration::from_secs(60),
+        overwrite: false,
+        full_scan: false,
+    };
+    let notification = DesktopNotification::for_run(&summary);
+    assert_eq!(notification.body, "3 tasks finished, 412 new files");
+    assert!(!notification.urgent);
+
+    summary.tasks[0].totals.failed = 1;
+    summary.tasks[2].error = Some("cancelled after 0 downloads".to_string());
+    let notification = DesktopNotification::for_run(&summary);
+    assert_eq!(notification.title, "rxd: run finished with failures");
+    assert_eq!(
+        notification.body,
+        "3 tasks finished, 412 new files, 2 failures"
+    );
+    assert!(notification.urgent);
+}
+
+#[cfg(feature = "desktop-notify")]
+#[test]
+fn notifier_is_searched_for_in_path() {
+    use rxd::notify::desktop::{self, NOTIFIER};
+
+    let empty = tempfile::tempdir().expect("tempdir");
+    let bin = tempfile::tempdir().expect("tempdir");
+    let file_name = match cfg!(windows) {
+        true => format!("{NOTIFIER}.exe"),
+        false => NOTIFIER.to_string(),
+    };
+    std::fs::write(bin.path().join(&file_name), "").expect("write notifier");
+
+    let path = std::env::join_paths([empty.path(), bin.path()]).expect("join paths");
+    assert_eq!(
+        desktop::find_notifier_in(&path),
+        Some(bin.path().join(&file_name))
+    );
+    let path = std::env::join_paths([empty.path()]).expect("join paths");
+    assert_eq!(desktop::find_notifier_in(&path), None);
+}
+
+#[tokio::test]
+async fn webhook_that_never_answers_times_out() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(30)))
+        .mount(&server)
+        .await;
+    let config = NotificationConfig {
+        webhook_url: server.uri(),
+        format: WebhookFormat::GenericJson,
+        notify_on: NotifyOn::Always,
+        telegram_chat_id: None,
+        timeout_secs: 1,
+    };
+    let summary = RunSummary {
+        tasks: vec![task("alice", 1, 0, 10)],
+        elapsed: Duration::from_secs(1),
+        overwrite: false,
+        full_scan: false,
+    };
+
+    let started = Instant::now();
+    let error = notify::send(&reqwest::Client::new(), &config, &summary)
+        .await
+        .expect_err("timeout");
+
+    assert!(matches!(error, rxd::Error::Timeout(_)), "{error:?}");
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
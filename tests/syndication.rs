@@ -0,0 +1,288 @@
+//! Public tweets from the syndication API.
+
+use rxd::api::TlsOptions;
+use rxd::deleted::TweetStatus;
+use rxd::syndication::{self, Syndication};
+use std::sync::Arc;
+
+use rxd::task::ExtractOptions;
+use rxd::{Api, Error, MediaType, Task};
+use serde_json::{Value, json};
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const TWEET_ID: &str = "1899882021231431884";
+const USER_BY_SCREEN_NAME: &str = "/i/api/graphql/xc8f1g7BYqr6VTzTbvNlGw/UserByScreenName";
+const USER_MEDIA: &str = "/i/api/graphql/Le6KlbilFmSu-5VltFND-Q/UserMedia";
+const TWEET_RESULT_BY_REST_ID: &str = "/i/api/graphql/Xl5pC_lBk_gcO2ItU39DQw/TweetResultByRestId";
+
+fn fixture(name: &str) -> Value {
+    let path = format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"));
+    serde_json::from_str(&std::fs::read_to_string(path).expect("fixture")).expect("json")
+}
+
+async fn answer(server: &MockServer, tweet_id: &str, response: ResponseTemplate) {
+    Mock::given(method("GET"))
+        .and(path("/tweet-result"))
+        .and(query_param("id", tweet_id))
+        .and(query_param("token", syndication::token(tweet_id)))
+        .respond_with(response)
+        .mount(server)
+        .await;
+}
+
+fn client(server: &MockServer) -> Syndication {
+    Syndication::with_base_url(&server.uri(), &TlsOptions::default()).expect("client")
+}
+
+#[test]
+fn maps_photos_and_videos() {
+    let tweet = syndication::parse_tweet_result(
+        TWEET_ID,
+        &fixture("syndication_tweet.json"),
+        &ExtractOptions::default(),
+    )
+    .expect("parse");
+
+    assert_eq!(tweet.tweet_id, TWEET_ID);
+    assert_eq!(tweet.screen_name.as_deref(), Some("NASA"));
+    assert_eq!(tweet.media.len(), 2);
+
+    let photo = &tweet.media[0];
+    assert!(matches!(photo.media_type, MediaType::Image));
+    assert_eq!(photo.url, "https://pbs.twimg.com/media/GL1ftOFXYAAbB2c.jpg");
+    assert_eq!(photo.download_url, photo.url);
+    assert_eq!(photo.index, Some(1));
+    assert_eq!(photo.timestamp.to_rfc3339(), "2025-03-12T18:47:51+00:00");
+    assert_eq!(photo.engagement.favorite_count, Some(4182));
+    assert_eq!(photo.engagement.reply_count, Some(317));
+    assert!(
+        photo
+            .full_text
+            .as_deref()
+            .is_some_and(|t| t.starts_with("Liftoff!"))
+    );
+
+    // Keyed by the poster like timeline videos, fetched at the best bitrate
+    let video = &tweet.media[1];
+    assert!(matches!(video.media_type, MediaType::Video));
+    assert_eq!(
+        video.url,
+        "https://pbs.twimg.com/ext_tw_video_thumb/1899881877681311744/pu/img/qW8dNcRkLxPz1y7T.jpg"
+    );
+    assert_eq!(video.poster_url.as_deref(), Some(video.url.as_str()));
+    assert!(video.download_url.contains("/1280x720/"));
+    assert_eq!(video.resolution, Some((1280, 720)));
+    assert_eq!(video.duration_ms, Some(30030));
+    assert_eq!(video.index, Some(2));
+
+    let capped = syndication::parse_tweet_result(
+        TWEET_ID,
+        &fixture("syndication_tweet.json"),
+        &ExtractOptions::default().max_video_bitrate(Some(1_000_000)),
+    )
+    .expect("parse");
+    assert!(capped.media[1].download_url.contains("/640x360/"));
+}
+
+#[test]
+fn refuses_what_only_accounts_see() {
+    let parse =
+        |raw: Value| syndication::parse_tweet_result(TWEET_ID, &raw, &ExtractOptions::default());
+
+    match parse(fixture("syndication_age_restricted.json")) {
+        Err(Error::SyndicationUnavailable { tweet_id, reason }) => {
+            assert_eq!(tweet_id, TWEET_ID);
+            assert!(reason.starts_with("Age-restricted adult content."));
+        }
+        other => panic!("expected a tombstone, got {other:?}"),
+    }
+
+    // Media listed in the entities but left out of the details
+    let mut sensitive = fixture("syndication_tweet.json");
+    sensitive["possibly_sensitive"] = json!(true);
+    sensitive["mediaDetails"] = json!([]);
+    assert!(matches!(
+        parse(sensitive),
+        Err(Error::SyndicationUnavailable { .. })
+    ));
+
+    // A sensitive tweet whose media are all there is served
+    let mut shown = fixture("syndication_tweet.json");
+    shown["possibly_sensitive"] = json!(true);
+    assert_eq!(parse(shown).expect("parse").media.len(), 2);
+
+    assert!(matches!(
+        parse(json!({})),
+        Err(Error::SyndicationUnavailable { .. })
+    ));
+}
+
+#[test]
+fn token_has_no_zeros_or_point() {
+    let token = syndication::token(TWEET_ID);
+    assert!(!token.is_empty());
+    assert!(token.chars().all(|c| c.is_ascii_alphanumeric() && c != '0'));
+    assert_eq!(token, syndication::token(TWEET_ID));
+    assert_ne!(token, syndication::token("20"));
+}
+
+#[tokio::test]
+async fn fetches_tweets_and_reports_missing_ones() {
+    let server = MockServer::start().await;
+    answer(
+        &server,
+        TWEET_ID,
+        ResponseTemplate::new(200).set_body_json(fixture("syndication_tweet.json")),
+    )
+    .await;
+    answer(&server, "2", ResponseTemplate::new(404)).await;
+    answer(&server, "3", ResponseTemplate::new(200)).await;
+    let client = client(&server);
+
+    let tweet = client
+        .tweet(TWEET_ID, &ExtractOptions::default())
+        .await
+        .expect("tweet");
+    assert_eq!(tweet.media.len(), 2);
+
+    for tweet_id in ["2", "3"] {
+        match client.tweet(tweet_id, &ExtractOptions::default()).await {
+            Err(Error::SyndicationUnavailable { reason, .. }) => {
+                assert!(reason.contains("protected"), "{reason}");
+            }
+            other => panic!("expected tweet {tweet_id} to be missing, got {other:?}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn tweet_status_falls_back_when_graphql_refuses() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(TWEET_RESULT_BY_REST_ID))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+    answer(
+        &server,
+        TWEET_ID,
+        ResponseTemplate::new(200).set_body_json(fixture("syndication_tweet.json")),
+    )
+    .await;
+    answer(&server, "2", ResponseTemplate::new(404)).await;
+    let api = Api::with_base_url("token", "ct0", &server.uri())
+        .expect("api")
+        .with_syndication(client(&server));
+
+    assert_eq!(
+        api.tweet_status(TWEET_ID).await.expect("status"),
+        TweetStatus::Live
+    );
+    // Deleted and protected look the same, so the GraphQL error stands
+    match api.tweet_status("2").await {
+        Err(Error::Api { status, .. }) => assert_eq!(status, 404),
+        other => panic!("expected the GraphQL error, got {other:?}"),
+    }
+}
+
+/// A UserMedia page with one video of [`TWEET_ID`] offered at `variant`
+fn video_page(poster: &str, variant: &str) -> Value {
+    json!({ "data": { "user": { "result": { "timeline_v2": { "timeline": {
+        "instructions": [{ "type": "TimelineAddEntries", "entries": [{
+            "entryId": "profile-grid-0",
+            "content": { "items": [{ "item": { "itemContent": { "tweet_results": { "result": {
+                "__typename": "Tweet",
+                "rest_id": TWEET_ID,
+                "legacy": {
+                    "created_at": "Wed Mar 12 18:47:51 +0000 2025",
+                    "full_text": "Liftoff!",
+                    "extended_entities": { "media": [{
+                        "type": "video",
+                        "media_url_https": poster,
+                        "video_info": { "variants": [
+                            { "bitrate": 2176000, "content_type": "video/mp4", "url": variant }
+                        ]}
+                    }]}
+                }
+            }}}}}]}
+        }]}]
+    }}}}}})
+}
+
+#[tokio::test]
+async fn rescues_videos_whose_variant_is_gone() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    let poster = format!("{}/ext_tw_video_thumb/1/pu/img/poster.jpg", server.uri());
+    let stale = format!("{}/ext_tw_video/1/pu/vid/1280x720/stale.mp4", server.uri());
+    let fresh = format!("{}/ext_tw_video/1/pu/vid/1280x720/fresh.mp4", server.uri());
+    Mock::given(method("GET"))
+        .and(path(USER_BY_SCREEN_NAME))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": { "user": { "result": {
+                "__typename": "User",
+                "rest_id": "11348282",
+                "legacy": { "name": "NASA", "media_count": 1 }
+            }}}
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(USER_MEDIA))
+        .respond_with(ResponseTemplate::new(200).set_body_json(video_page(&poster, &stale)))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/ext_tw_video/1/pu/vid/1280x720/stale.mp4"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/ext_tw_video/1/pu/vid/1280x720/fresh.mp4"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"video".to_vec()))
+        .mount(&server)
+        .await;
+    let mut tweet = fixture("syndication_tweet.json");
+    tweet["mediaDetails"] = json!([{
+        "type": "video",
+        "media_url_https": poster,
+        "video_info": { "variants": [
+            { "bitrate": 2176000, "content_type": "video/mp4", "url": fresh }
+        ]}
+    }]);
+    answer(
+        &server,
+        TWEET_ID,
+        ResponseTemplate::new(200).set_body_json(tweet),
+    )
+    .await;
+
+    let db = rxd::db::init_memory_db().await.expect("db");
+    let api = Api::with_base_url("token", "ct0", &server.uri())
+        .expect("api")
+        .with_syndication(client(&server));
+    let task = Task::builder()
+        .api(api)
+        .screen_name("nasa")
+        .save_path(dir.path().join("media"))
+        .syndication_rescue(true)
+        .db(db.clone())
+        .build()
+        .await
+        .expect("task");
+    let totals = Arc::new(task).execute().await.expect("execute");
+
+    assert_eq!(totals.downloaded, 1);
+    assert_eq!(totals.failed, 0);
+    // Recorded under its usual key, fetched from the rescued variant
+    let record = rxd::db::get_media_by_url(&db, &poster)
+        .await
+        .expect("query")
+        .expect("record");
+    let filename = record.filename.expect("filename");
+    assert_eq!(
+        std::fs::read(dir.path().join("media").join(filename)).expect("file"),
+        b"video"
+    );
+}
@@ -0,0 +1,164 @@
+//! Thumbnails of downloaded images.
+
+use std::fs;
+use std::time::{Duration, SystemTime};
+
+#[cfg(unix)]
+use common::fake_ffmpeg;
+use common::media;
+use rxd::db;
+use rxd::error::Error;
+use rxd::gallery::{self, GalleryOptions};
+use rxd::thumbs::{self, FrameExtractor, ThumbFormat, ThumbOptions};
+
+mod common;
+
+#[cfg(unix)]
+#[tokio::test]
+async fn makes_missing_and_outdated_thumbnails() {
+    let tmp = tempfile::tempdir().expect("tmp");
+    let folder = tmp.path().join("alice");
+    fs::create_dir_all(&folder).expect("folder");
+    let pool = db::init_memory_db().await.expect("db");
+    media("a.jpg").file(&folder, b"image").record(&pool).await;
+    media("b.png")
+        .tweet("2")
+        .file(&folder, b"image")
+        .record(&pool)
+        .await;
+    media("corrupt.jpg")
+        .tweet("3")
+        .file(&folder, b"image")
+        .record(&pool)
+        .await;
+    media("clip.mp4")
+        .tweet("4")
+        .file(&folder, b"image")
+        .record(&pool)
+        .await;
+    media("lost.jpg").tweet("5").record(&pool).await;
+    let options = ThumbOptions::default()
+        .size(200)
+        .concurrency(2)
+        .ffmpeg(fake_ffmpeg(tmp.path(), true));
+    let run = || {
+        thumbs::make_thumbs(
+            &pool,
+            Some("alice"),
+            |_| folder.clone(),
+            &options,
+            |_, _| {},
+        )
+    };
+
+    let summary = run().await.expect("thumbs");
+
+    assert_eq!(summary.made, 2);
+    assert_eq!(summary.missing, 1);
+    assert_eq!(summary.failed.len(), 1);
+    assert_eq!(summary.failed[0].path, folder.join("corrupt.jpg"));
+    assert!(summary.failed[0].reason.contains("Invalid data"));
+    let thumb = thumbs::thumb_path(&folder, "b.png", ThumbFormat::Jpeg);
+    assert_eq!(thumb, folder.join(".thumbs").join("b.jpg"));
+    let args = fs::read_to_string(&thumb).expect("thumb");
+    assert!(args.contains("min(iw,200)"), "{args}");
+    assert!(!folder.join(".thumbs").join("clip.jpg").exists());
+    assert!(!folder.join(".thumbs").join("corrupt.jpg").exists());
+
+    // Only images newer than their thumbnail are done again
+    let again = run().await.expect("thumbs");
+    assert_eq!(again.made, 0);
+    assert_eq!(again.up_to_date, 2);
+    let image = fs::File::options()
+        .write(true)
+        .open(folder.join("a.jpg"))
+        .expect("image");
+    image
+        .set_modified(SystemTime::now() + Duration::from_secs(60))
+        .expect("touch");
+    let touched = run().await.expect("thumbs");
+    assert_eq!(touched.made, 1);
+    assert_eq!(touched.up_to_date, 1);
+
+    // The gallery shows the thumbnails instead of the images
+    let out = tmp.path().join("site");
+    gallery::generate(&pool, "alice", &folder, &out, &GalleryOptions::default())
+        .await
+        .expect("gallery");
+    let index = fs::read_to_string(out.join(gallery::INDEX)).expect("index");
+    assert!(index.contains(
+        "<a class=\"media\" href=\"../alice/b.png\"><img src=\"../alice/.thumbs/b.jpg\""
+    ));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn backfills_video_frames() {
+    let tmp = tempfile::tempdir().expect("tmp");
+    let folder = tmp.path().join("alice");
+    fs::create_dir_all(&folder).expect("folder");
+    let pool = db::init_memory_db().await.expect("db");
+    media("clip.mp4")
+        .file(&folder, b"image")
+        .record(&pool)
+        .await;
+    media("short.mp4")
+        .tweet("2")
+        .file(&folder, b"image")
+        .record(&pool)
+        .await;
+    media("posted.mp4")
+        .tweet("3")
+        .file(&folder, b"image")
+        .record(&pool)
+        .await;
+    fs::write(folder.join("posted.thumb.jpg"), b"poster").expect("poster");
+    let options = ThumbOptions::default()
+        .videos(true)
+        .ffmpeg(fake_ffmpeg(tmp.path(), true));
+
+    let summary = thumbs::make_thumbs(&pool, None, |_| folder.clone(), &options, |_, _| {})
+        .await
+        .expect("thumbs");
+
+    assert_eq!(summary.made, 2);
+    assert_eq!(summary.up_to_date, 1);
+    let frame = fs::read_to_string(folder.join("clip.thumb.jpg")).expect("frame");
+    assert!(frame.starts_with("-nostdin"), "{frame}");
+    assert!(frame.contains("-ss 1 -i"), "{frame}");
+    let first = fs::read_to_string(folder.join("short.thumb.jpg")).expect("frame");
+    assert!(first.contains("-ss 0 -i"), "{first}");
+    assert_eq!(
+        fs::read(folder.join("posted.thumb.jpg")).expect("poster"),
+        b"poster"
+    );
+    let record = db::get_media_by_url(&pool, "https://pbs.twimg.com/media/clip.mp4")
+        .await
+        .expect("lookup")
+        .expect("recorded");
+    assert_eq!(record.thumbnail.as_deref(), Some("clip.thumb.jpg"));
+}
+
+#[tokio::test]
+async fn fails_without_ffmpeg() {
+    let tmp = tempfile::tempdir().expect("tmp");
+    let pool = db::init_memory_db().await.expect("db");
+    media("a.jpg")
+        .file(tmp.path(), b"image")
+        .record(&pool)
+        .await;
+    let options = ThumbOptions::default().ffmpeg(tmp.path().join("no-such-ffmpeg"));
+
+    let result = thumbs::make_thumbs(
+        &pool,
+        None,
+        |_| tmp.path().to_path_buf(),
+        &options,
+        |_, _| {},
+    )
+    .await;
+
+    assert!(matches!(result, Err(Error::Config(_))), "{result:?}");
+    let frames = FrameExtractor::new(tmp.path().join("no-such-ffmpeg"), Duration::from_secs(5), 1);
+    assert!(!frames.is_available().await);
+}
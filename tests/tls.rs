@@ -0,0 +1,58 @@
+//! Loading of custom root certificates.
+
+use std::path::PathBuf;
+
+use rxd::Api;
+use rxd::api::TlsOptions;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn api_error(tls: &TlsOptions) -> String {
+    Api::with_tls("token", "ct0", "https://x.com", tls)
+        .expect_err("invalid TLS options")
+        .to_string()
+}
+
+#[test]
+fn loads_pem_certificate() {
+    for os_roots in [true, false] {
+        let tls = TlsOptions {
+            ca_cert: Some(fixture("ca.pem")),
+            os_roots,
+        };
+        assert!(Api::with_tls("token", "ct0", "https://x.com", &tls).is_ok());
+    }
+}
+
+#[test]
+fn missing_certificate_names_path() {
+    let path = fixture("does-not-exist.pem");
+    let error = api_error(&TlsOptions {
+        ca_cert: Some(path.clone()),
+        os_roots: true,
+    });
+    assert!(error.contains(&path.display().to_string()), "{error}");
+}
+
+#[test]
+fn invalid_certificate_names_path() {
+    let path = fixture("user_media_empty.json");
+    let error = api_error(&TlsOptions {
+        ca_cert: Some(path.clone()),
+        os_roots: true,
+    });
+    assert!(error.contains(&path.display().to_string()), "{error}");
+}
+
+#[test]
+fn no_roots_is_an_error() {
+    let error = api_error(&TlsOptions {
+        ca_cert: None,
+        os_roots: false,
+    });
+    assert!(error.contains("ca_cert"), "{error}");
+}
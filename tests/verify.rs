@@ -0,0 +1,166 @@
+//! Verifying downloaded files and repairing damaged ones.
+
+use common::{folder, media};
+use rxd::Api;
+use rxd::db;
+use rxd::hash::HashAlgorithm;
+use rxd::task::ImageSize;
+use rxd::verify::{self, Damage, RepairOptions, RepairSummary};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+mod common;
+
+async fn serve(server: &MockServer, at: &str, status: u16, body: &[u8]) {
+    Mock::given(method("GET"))
+        .and(path(at))
+        .respond_with(ResponseTemplate::new(status).set_body_bytes(body.to_vec()))
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn repairs_damaged_files_and_reports_gone_ones() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    let files = dir.path().join("alice");
+    std::fs::create_dir(&files).expect("mkdir");
+    let pool = db::init_memory_db().await.expect("db");
+    let uri = server.uri();
+
+    let intact = format!("{uri}/media/INTACT.jpg");
+    media("intact.jpg")
+        .media_url(&intact)
+        .hash_of(b"intact")
+        .record(&pool)
+        .await;
+    std::fs::write(files.join("intact.jpg"), b"intact").expect("write");
+    // Corrupt image, served again
+    let corrupt = format!("{uri}/media/CORRUPT.jpg");
+    media("corrupt.jpg")
+        .media_url(&corrupt)
+        .hash_of(b"image")
+        .record(&pool)
+        .await;
+    std::fs::write(files.join("corrupt.jpg"), b"imag").expect("write");
+    serve(&server, "/media/CORRUPT.jpg", 200, b"image").await;
+    // Missing video, fetched from its variant rather than the thumbnail
+    let thumb = format!("{uri}/thumb/VIDEO.jpg");
+    let variant = format!("{uri}/video/720p.mp4");
+    media("video.mp4")
+        .media_url(&thumb)
+        .download_url(&variant)
+        .hash_of(b"video")
+        .record(&pool)
+        .await;
+    serve(&server, "/video/720p.mp4", 200, b"video").await;
+    // Corrupt image whose media was deleted
+    let gone = format!("{uri}/media/GONE.jpg");
+    media("gone.jpg")
+        .media_url(&gone)
+        .hash_of(b"gone")
+        .record(&pool)
+        .await;
+    std::fs::write(files.join("gone.jpg"), b"gon").expect("write");
+    serve(&server, "/media/GONE.jpg", 404, b"").await;
+
+    let report = verify::verify(&pool, None, folder(dir.path()), 2, |_, _| {})
+        .await
+        .expect("verify");
+    assert_eq!(report.intact, 1);
+    let mut damaged: Vec<_> = report
+        .damaged
+        .iter()
+        .map(|d| (d.media.filename.as_str(), d.damage))
+        .collect();
+    damaged.sort_by_key(|(filename, _)| *filename);
+    assert_eq!(
+        damaged,
+        [
+            ("corrupt.jpg", Damage::Mismatch),
+            ("gone.jpg", Damage::Mismatch),
+            ("video.mp4", Damage::Missing),
+        ]
+    );
+
+    let api = Api::with_base_url("token", "ct0", &uri).expect("api");
+    let options = RepairOptions {
+        concurrency: 2,
+        image_size: ImageSize::Orig,
+        hash_algorithm: HashAlgorithm::Blake3,
+        max_file_size: None,
+    };
+    let summary = verify::repair(&pool, &api, report.damaged, &options, |_, _| {})
+        .await
+        .expect("repair");
+    assert_eq!(
+        summary,
+        RepairSummary {
+            repaired: 2,
+            unrepairable: 1,
+            failed: 0,
+        }
+    );
+
+    assert_eq!(
+        std::fs::read(files.join("corrupt.jpg")).expect("read"),
+        b"image"
+    );
+    assert_eq!(
+        std::fs::read(files.join("video.mp4")).expect("read"),
+        b"video"
+    );
+    // Left as it was
+    assert_eq!(std::fs::read(files.join("gone.jpg")).expect("read"), b"gon");
+    assert!(db::is_gone(&pool, &gone, None).await.expect("gone"));
+    let record = db::get_media_by_url(&pool, &corrupt)
+        .await
+        .expect("lookup")
+        .expect("recorded");
+    assert_eq!(record.hash_algorithm, Some(HashAlgorithm::Blake3));
+    assert_eq!(record.download_url, Some(format!("{corrupt}?name=orig")));
+
+    let report = verify::verify(&pool, None, folder(dir.path()), 2, |_, _| {})
+        .await
+        .expect("verify");
+    assert_eq!(report.intact, 3);
+    assert_eq!(report.damaged.len(), 1);
+}
+
+#[tokio::test]
+async fn media_recorded_as_gone_is_unrepairable_without_a_request() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().expect("tempdir");
+    let pool = db::init_memory_db().await.expect("db");
+    let gone = format!("{}/media/GONE.jpg", server.uri());
+    media("gone.jpg")
+        .media_url(&gone)
+        .hash_of(b"gone")
+        .record(&pool)
+        .await;
+    db::mark_gone(&pool, &gone).await.expect("mark gone");
+    Mock::given(method("GET"))
+        .and(path("/media/GONE.jpg"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"gone".to_vec()))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let report = verify::verify(&pool, None, folder(dir.path()), 1, |_, _| {})
+        .await
+        .expect("verify");
+    assert!(report.damaged[0].media.gone);
+    let api = Api::with_base_url("token", "ct0", &server.uri()).expect("api");
+    let options = RepairOptions {
+        concurrency: 1,
+        image_size: ImageSize::Orig,
+        hash_algorithm: HashAlgorithm::Sha256,
+        max_file_size: None,
+    };
+    let summary = verify::repair(&pool, &api, report.damaged, &options, |_, _| {})
+        .await
+        .expect("repair");
+
+    assert_eq!(summary.unrepairable, 1);
+    assert_eq!(summary.failed, 0);
+}